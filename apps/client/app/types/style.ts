@@ -2,6 +2,7 @@ export interface Style {
   id: string;
   name: string;
   url: string;
+  thumbnail?: string;
   version: number;
 }
 
@@ -0,0 +1,64 @@
+//! Cost of `verify_checksums` on PMTiles reads
+//!
+//! Reads every z14 tile of the Protomaps test archive with and without
+//! checksum verification, and prints the time per tile of each.
+//!
+//! ```sh
+//! cargo bench --bench pmtiles_checksums
+//! ```
+
+use std::time::{Duration, Instant};
+
+use tileserver_rs::config::SourceConfig;
+use tileserver_rs::inspect;
+use tileserver_rs::sources::{SourceManager, TileSource};
+
+const PATH: &str = "data/tiles/protomaps-sample.pmtiles";
+const ZOOM: u8 = 14;
+/// Passes over all tiles per mode
+const ROUNDS: usize = 20;
+
+async fn source(options: &str) -> SourceManager {
+    let config: SourceConfig = toml::from_str(&format!(
+        "id = \"protomaps\"\ntype = \"pmtiles\"\npath = \"{}\"\n{}",
+        PATH, options
+    ))
+    .unwrap();
+    SourceManager::from_configs(&[config]).await.unwrap()
+}
+
+/// Read all `tiles` `ROUNDS` times, returning the time per read
+async fn measure(source: &dyn TileSource, tiles: &[(u32, u32)]) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for &(x, y) in tiles {
+            let tile = source.get_tile(ZOOM, x, y).await.unwrap();
+            std::hint::black_box(tile);
+        }
+    }
+    start.elapsed() / (tiles.len() * ROUNDS) as u32
+}
+
+#[tokio::main]
+async fn main() {
+    let plain = source("").await;
+    let verified = source("[options]\nverify_checksums = true").await;
+    let plain = plain.get("protomaps").unwrap();
+    let verified = verified.get("protomaps").unwrap();
+
+    let tiles = inspect::list(plain.as_ref(), ZOOM).await.unwrap().tiles;
+    // Warm the directory caches
+    measure(plain.as_ref(), &tiles).await;
+    measure(verified.as_ref(), &tiles).await;
+
+    let unverified = measure(plain.as_ref(), &tiles).await;
+    let checked = measure(verified.as_ref(), &tiles).await;
+
+    println!("{} tiles at z{}, {} rounds", tiles.len(), ZOOM, ROUNDS);
+    println!("unverified  {:>10.1?} per tile", unverified);
+    println!("verified    {:>10.1?} per tile", checked);
+    println!(
+        "overhead    {:>10.1?} per tile",
+        checked.saturating_sub(unverified)
+    );
+}
@@ -0,0 +1,146 @@
+//! Tile loading of the native renderer: in-process loader vs HTTP
+//!
+//! Loads every z14 tile of the Zurich test source the way a render worker
+//! does, once through [`ResourceLoader`] from `pmtiles://` URLs and once over
+//! HTTP from a minimal tile server on a loopback port, and prints the time
+//! per tile of each.
+//!
+//! ```sh
+//! cargo bench --bench resource_loader
+//! ```
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use maplibre_native_sys::resource_kind;
+use tileserver_rs::config::Config;
+use tileserver_rs::inspect;
+use tileserver_rs::render::loader::{Resource, ResourceLoader, LOCAL_TILES};
+use tileserver_rs::sources::{SharedSources, SourceManager};
+use tileserver_rs::styles::{SharedStyles, StyleManager};
+
+const SOURCE: &str = "zurich";
+const ZOOM: u8 = 14;
+/// Passes over all tiles per mode
+const ROUNDS: usize = 5;
+
+/// `/data/{source}/{z}/{x}/{y}.pbf` as the HTTP handler serves it to
+/// MapLibre, decompressed
+async fn tile(
+    State(sources): State<SharedSources>,
+    Path((source, z, x, y)): Path<(String, u8, u32, String)>,
+) -> impl IntoResponse {
+    let y: u32 = match y.trim_end_matches(".pbf").parse() {
+        Ok(y) => y,
+        Err(_) => return StatusCode::BAD_REQUEST.into_response(),
+    };
+    match sources.load().get_tile(&source, z, x, y).await {
+        Ok(Some(tile)) => match tile.decompress() {
+            Ok(data) => data.into_response(),
+            Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        },
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// Load all `urls` `ROUNDS` times, returning the time per load
+fn measure(urls: &[String], mut load: impl FnMut(&str) -> Resource) -> Duration {
+    // Warm caches and connections
+    for url in urls {
+        assert!(matches!(load(url), Resource::Data(_)), "{} not loaded", url);
+    }
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for url in urls {
+            std::hint::black_box(load(url));
+        }
+    }
+    start.elapsed() / (urls.len() * ROUNDS) as u32
+}
+
+fn main() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let config = Config::load(Some("tests/config.test.toml".into())).unwrap();
+    let sources = runtime
+        .block_on(SourceManager::from_configs(&config.sources))
+        .unwrap();
+    let styles = StyleManager::from_configs(&config.styles).unwrap();
+    let sources = SharedSources::new(sources);
+
+    let manager = sources.load();
+    let source = manager.get(SOURCE).expect("zurich test source");
+    let scheme = source.metadata().scheme;
+    let tiles = runtime
+        .block_on(inspect::list(source.as_ref(), ZOOM))
+        .unwrap()
+        .tiles;
+    drop(manager);
+
+    let listener = runtime
+        .block_on(tokio::net::TcpListener::bind("127.0.0.1:0"))
+        .unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let app = Router::new()
+        .route("/data/{source}/{z}/{x}/{y}", get(tile))
+        .with_state(sources.clone());
+    runtime.spawn(async move { axum::serve(listener, app).await });
+
+    let tiles: Vec<(u32, u32)> = tiles
+        .iter()
+        .filter_map(|&(x, y)| Some((x, scheme.xyz_row(ZOOM, y)?)))
+        .collect();
+    // The URLs styles are rewritten to with each loader
+    let local_urls: Vec<String> = tiles
+        .iter()
+        .map(|(x, y)| format!("{}{}/{}/{}/{}", LOCAL_TILES, SOURCE, ZOOM, x, y))
+        .collect();
+    let urls: Vec<String> = tiles
+        .iter()
+        .map(|(x, y)| format!("{}/data/{}/{}/{}/{}.pbf", base_url, SOURCE, ZOOM, x, y))
+        .collect();
+
+    let loader = Arc::new(
+        ResourceLoader::new(
+            runtime.handle().clone(),
+            &base_url,
+            sources,
+            SharedStyles::new(styles),
+        )
+        .unwrap(),
+    );
+    let in_process = measure(&local_urls, |url| loader.load(url, resource_kind::TILE));
+
+    let client = reqwest::Client::new();
+    let http = measure(&urls, |url| {
+        runtime.block_on(async {
+            match client.get(url).send().await {
+                Ok(response) if response.status().is_success() => {
+                    Resource::Data(response.bytes().await.unwrap())
+                }
+                Ok(response) if response.status() == StatusCode::NOT_FOUND => Resource::NotFound,
+                Ok(response) => Resource::Error(response.status().to_string()),
+                Err(e) => Resource::Error(e.to_string()),
+            }
+        })
+    });
+
+    println!(
+        "{} tiles of {} at z{}, {} rounds",
+        urls.len(),
+        SOURCE,
+        ZOOM,
+        ROUNDS
+    );
+    println!("in_process  {:>10.1?} per tile", in_process);
+    println!("http        {:>10.1?} per tile", http);
+    println!(
+        "speedup     {:>10.1}x",
+        http.as_secs_f64() / in_process.as_secs_f64()
+    );
+}
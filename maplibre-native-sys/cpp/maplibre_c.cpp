@@ -10,7 +10,13 @@
 #include <mbgl/gfx/headless_frontend.hpp>
 #include <mbgl/map/map.hpp>
 #include <mbgl/map/map_options.hpp>
+#include <mbgl/storage/file_source.hpp>
+#include <mbgl/storage/file_source_manager.hpp>
+#include <mbgl/storage/resource.hpp>
 #include <mbgl/storage/resource_options.hpp>
+#include <mbgl/storage/response.hpp>
+#include <mbgl/util/async_request.hpp>
+#include <mbgl/util/client_options.hpp>
 #include <mbgl/style/style.hpp>
 #include <mbgl/util/image.hpp>
 #include <mbgl/util/run_loop.hpp>
@@ -58,14 +64,127 @@ struct MLNHeadlessFrontend {
     mbgl::Size size;
 };
 
+/* Resource callback of a map, handed to its file source as platform context */
+struct MLNResourceLoader {
+    MLNResourceCallback callback;
+    void* userData;
+};
+
 struct MLNMap {
     MLNHeadlessFrontend* frontend;
+    /* Declared before `map` so it outlives the map's file source */
+    std::unique_ptr<MLNResourceLoader> loader;
     std::unique_ptr<mbgl::Map> map;
     float pixelRatio;
     MLNMapMode mode;
     bool styleLoaded;
 };
 
+/* Pending request of a CallbackFileSource; cancelled when destroyed */
+class CallbackRequest : public mbgl::AsyncRequest {
+public:
+    explicit CallbackRequest(std::shared_ptr<bool> active_) : active(std::move(active_)) {}
+    ~CallbackRequest() override { *active = false; }
+
+private:
+    std::shared_ptr<bool> active;
+};
+
+/* File source answering every request through a map's MLNResourceCallback */
+class CallbackFileSource : public mbgl::FileSource {
+public:
+    CallbackFileSource(MLNResourceLoader loader_,
+                       const mbgl::ResourceOptions& resourceOptions_,
+                       const mbgl::ClientOptions& clientOptions_)
+        : loader(loader_),
+          resourceOptions(resourceOptions_.clone()),
+          clientOptions(clientOptions_.clone()) {}
+
+    std::unique_ptr<mbgl::AsyncRequest> request(const mbgl::Resource& resource, Callback callback) override {
+        MLNResourceRequest request{resource.url.c_str(), static_cast<uint8_t>(resource.kind)};
+        MLNResourceResponse result{nullptr, 0, nullptr, false};
+        loader.callback(&request, &result, loader.userData);
+
+        // Copied now: the callback only keeps them until its next call
+        mbgl::Response response;
+        if (result.error) {
+            response.error = std::make_unique<mbgl::Response::Error>(
+                mbgl::Response::Error::Reason::Other, result.error);
+        } else if (result.not_found) {
+            // Like the HTTP file source: missing tiles are empty, anything
+            // else missing is an error
+            if (resource.kind == mbgl::Resource::Kind::Tile) {
+                response.noContent = true;
+            } else {
+                response.error = std::make_unique<mbgl::Response::Error>(
+                    mbgl::Response::Error::Reason::NotFound, "Not found");
+            }
+        } else {
+            response.data = std::make_shared<const std::string>(
+                reinterpret_cast<const char*>(result.data), result.data_len);
+        }
+
+        // Responses are expected after `request` returns, on this thread's
+        // RunLoop, and not at all once the request is cancelled
+        auto active = std::make_shared<bool>(true);
+        mbgl::util::RunLoop::Get()->invoke([active, callback = std::move(callback), response]() {
+            if (*active) {
+                callback(response);
+            }
+        });
+        return std::make_unique<CallbackRequest>(active);
+    }
+
+    bool canRequest(const mbgl::Resource&) const override { return true; }
+
+    /* Options are only kept for the map to read back; requests don't use them */
+    void setResourceOptions(mbgl::ResourceOptions options) override {
+        std::lock_guard<std::mutex> lock(optionsMutex);
+        resourceOptions = std::move(options);
+    }
+
+    mbgl::ResourceOptions getResourceOptions() override {
+        std::lock_guard<std::mutex> lock(optionsMutex);
+        return resourceOptions.clone();
+    }
+
+    void setClientOptions(mbgl::ClientOptions options) override {
+        std::lock_guard<std::mutex> lock(optionsMutex);
+        clientOptions = std::move(options);
+    }
+
+    mbgl::ClientOptions getClientOptions() override {
+        std::lock_guard<std::mutex> lock(optionsMutex);
+        return clientOptions.clone();
+    }
+
+private:
+    MLNResourceLoader loader;
+    std::mutex optionsMutex;
+    mbgl::ResourceOptions resourceOptions;
+    mbgl::ClientOptions clientOptions;
+};
+
+/*
+ * Route the resource loaders of maps with a callback to a CallbackFileSource.
+ * File sources are shared by maps with equal resource options, which include
+ * the platform context, so each such map gets its own; maps without one keep
+ * the default loader.
+ */
+static void registerCallbackFileSource() {
+    auto manager = mbgl::FileSourceManager::get();
+    auto fallback = manager->unRegisterFileSourceFactory(mbgl::FileSourceType::ResourceLoader);
+    manager->registerFileSourceFactory(
+        mbgl::FileSourceType::ResourceLoader,
+        [fallback](const mbgl::ResourceOptions& resourceOptions,
+                   const mbgl::ClientOptions& clientOptions) -> std::unique_ptr<mbgl::FileSource> {
+            if (auto* loader = static_cast<MLNResourceLoader*>(resourceOptions.platformContext())) {
+                return std::make_unique<CallbackFileSource>(*loader, resourceOptions, clientOptions);
+            }
+            return fallback ? fallback(resourceOptions, clientOptions) : nullptr;
+        });
+}
+
 extern "C" {
 
 MLNErrorCode mln_init(void) {
@@ -84,6 +203,8 @@ MLNErrorCode mln_init(void) {
         
         // Ensure the calling thread has a RunLoop
         ensureRunLoop();
+        static std::once_flag fileSourceRegistered;
+        std::call_once(fileSourceRegistered, registerCallbackFileSource);
         initialized = true;
         return MLN_OK;
     } catch (const std::exception& e) {
@@ -181,12 +302,14 @@ MLNMap* mln_map_create_with_loader(
                   .withPixelRatio(pixel_ratio)
                   .withMapMode(mapMode);
         
-        // Resource options (using default file sources)
+        // Resource options: default file sources, unless the map has a
+        // callback for CallbackFileSource to answer requests with
         mbgl::ResourceOptions resourceOptions;
-        
-        // TODO: Handle custom resource callback if provided
-        (void)request_callback;
-        (void)user_data;
+        if (request_callback) {
+            map->loader = std::make_unique<MLNResourceLoader>(
+                MLNResourceLoader{request_callback, user_data});
+            resourceOptions.withPlatformContext(map->loader.get());
+        }
         
         // Create the map
         map->map = std::make_unique<mbgl::Map>(
@@ -43,15 +43,16 @@ typedef enum {
     MLN_MAP_MODE_TILE = 1,
 } MLNMapMode;
 
-/* Debug options (bitflags) */
-typedef enum {
+/* Debug options (bitflags): any combination of MLN_DEBUG_* values */
+enum {
     MLN_DEBUG_NONE = 0,
     MLN_DEBUG_TILE_BORDERS = 1 << 0,
     MLN_DEBUG_PARSE_STATUS = 1 << 1,
     MLN_DEBUG_TIMESTAMPS = 1 << 2,
     MLN_DEBUG_COLLISION = 1 << 3,
     MLN_DEBUG_OVERDRAW = 1 << 4,
-} MLNDebugOptions;
+};
+typedef uint32_t MLNDebugOptions;
 
 /* Size structure */
 typedef struct {
@@ -101,6 +102,15 @@ typedef struct {
 
 /* Callback types */
 typedef void (*MLNRenderCallback)(MLNErrorCode error, MLNImageData* image, void* user_data);
+
+/*
+ * Resource request callback of a map created with mln_map_create_with_loader.
+ * Called on the map's thread, once per resource, and answers synchronously by
+ * filling `response`: `data` for the resource, `not_found` for a missing one
+ * (an empty tile, for tiles), or `error`. `data` and `error` must stay valid
+ * until the callback is called again for the same map or the map is
+ * destroyed; they are copied before either happens.
+ */
 typedef void (*MLNResourceCallback)(const MLNResourceRequest* request,
                                     MLNResourceResponse* response,
                                     void* user_data);
@@ -152,11 +162,14 @@ MLNMap* mln_map_create(MLNHeadlessFrontend* frontend, float pixel_ratio, MLNMapM
 
 /**
  * Create a new map instance with custom resource loader.
+ * Every resource the map needs (tiles, glyphs, sprites, TileJSON) is requested
+ * through `request_callback` instead of MapLibre's own file sources.
  * @param frontend Headless frontend to use for rendering
  * @param pixel_ratio Pixel ratio
  * @param mode Map mode
- * @param request_callback Callback for resource requests
- * @param user_data User data passed to callbacks
+ * @param request_callback Callback for resource requests, or NULL for the
+ *        default file sources
+ * @param user_data User data passed to callbacks, until the map is destroyed
  * @return Pointer to map or NULL on error
  */
 MLNMap* mln_map_create_with_loader(
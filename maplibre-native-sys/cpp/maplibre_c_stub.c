@@ -214,14 +214,16 @@ MLNErrorCode mln_map_render_still(MLNMap* map, const MLNRenderOptions* options,
         return MLN_ERROR_NOT_LOADED;
     }
 
-    /* Get size from options or frontend */
+    /* Get size from options or frontend, in physical pixels: truncated,
+       like the framebuffer of MapLibre's headless frontend */
     uint32_t width, height;
     if (options) {
-        width = options->size.width;
-        height = options->size.height;
+        float ratio = options->pixel_ratio > 0 ? options->pixel_ratio : 1.0f;
+        width = (uint32_t)(options->size.width * ratio);
+        height = (uint32_t)(options->size.height * ratio);
     } else if (map->frontend) {
-        width = map->frontend->size.width;
-        height = map->frontend->size.height;
+        width = (uint32_t)(map->frontend->size.width * map->frontend->pixel_ratio);
+        height = (uint32_t)(map->frontend->size.height * map->frontend->pixel_ratio);
     } else {
         width = 512;
         height = 512;
@@ -235,6 +237,12 @@ MLNErrorCode mln_map_render_still(MLNMap* map, const MLNRenderOptions* options,
         return MLN_ERROR_UNKNOWN;
     }
 
+    /* Tint blue with the camera's bearing and pitch, so rotated and tilted
+       renders differ from north-up ones */
+    const MLNCameraOptions* camera = options ? &options->camera : &map->camera;
+    int tint = 128 + (int)(camera->bearing * 64.0 / 360.0) + (int)(camera->pitch * 63.0 / 60.0);
+    uint8_t blue = (uint8_t)(tint < 0 ? 0 : (tint > 255 ? 255 : tint));
+
     /* Fill with a gradient pattern to show it's a stub */
     for (uint32_t y = 0; y < height; y++) {
         for (uint32_t x = 0; x < width; x++) {
@@ -242,7 +250,7 @@ MLNErrorCode mln_map_render_still(MLNMap* map, const MLNRenderOptions* options,
             /* Create a simple gradient pattern */
             data[idx + 0] = (uint8_t)((x * 255) / width);      /* R */
             data[idx + 1] = (uint8_t)((y * 255) / height);     /* G */
-            data[idx + 2] = 128;                                /* B */
+            data[idx + 2] = blue;                               /* B */
             data[idx + 3] = 255;                                /* A */
         }
     }
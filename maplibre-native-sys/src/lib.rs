@@ -51,16 +51,17 @@ pub enum MLNMapMode {
     MLN_MAP_MODE_TILE = 1,
 }
 
-/// Debug options (bitflags)
-#[repr(C)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum MLNDebugOptions {
-    MLN_DEBUG_NONE = 0,
-    MLN_DEBUG_TILE_BORDERS = 1,
-    MLN_DEBUG_PARSE_STATUS = 2,
-    MLN_DEBUG_TIMESTAMPS = 4,
-    MLN_DEBUG_COLLISION = 8,
-    MLN_DEBUG_OVERDRAW = 16,
+bitflags::bitflags! {
+    /// Debug options (bitflags), combined with `|`
+    #[repr(transparent)]
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+    pub struct MLNDebugOptions: u32 {
+        const MLN_DEBUG_TILE_BORDERS = 1 << 0;
+        const MLN_DEBUG_PARSE_STATUS = 1 << 1;
+        const MLN_DEBUG_TIMESTAMPS = 1 << 2;
+        const MLN_DEBUG_COLLISION = 1 << 3;
+        const MLN_DEBUG_OVERDRAW = 1 << 4;
+    }
 }
 
 /// Size structure
@@ -128,7 +129,7 @@ impl Default for MLNRenderOptions {
             pixel_ratio: 1.0,
             camera: MLNCameraOptions::default(),
             mode: MLNMapMode::MLN_MAP_MODE_TILE,
-            debug: MLNDebugOptions::MLN_DEBUG_NONE,
+            debug: MLNDebugOptions::empty(),
         }
     }
 }
@@ -196,6 +197,10 @@ pub type MLNRenderCallback = Option<
 >;
 
 /// Callback type for resource requests
+///
+/// Called on the map's thread and answered synchronously through the
+/// response. Its `data` and `error` must stay valid until the next call for
+/// the same map or until the map is destroyed.
 pub type MLNResourceCallback = Option<
     unsafe extern "C" fn(
         request: *const MLNResourceRequest,
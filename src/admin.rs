@@ -0,0 +1,330 @@
+//! Admin API for runtime inspection
+//!
+//! Served on its own listener (`server.port + 1000` unless configured) and
+//! only when `[admin] token` is set. Every request must carry the token in
+//! the `X-Admin-Token` header. All responses, including errors, are JSON.
+
+use axum::{
+    extract::{Path, Request, State},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+    Json, Router,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use crate::config::SourceConfig;
+use crate::error::{error_body, ErrorCode, TileServerError};
+use crate::events::{Event, EventBus};
+use crate::health::HealthStatus;
+use crate::sources::manager::CacheStats;
+use crate::sources::{self, SharedSources, SourceManager};
+use crate::styles::SharedStyles;
+
+/// Header carrying the admin token
+pub const ADMIN_TOKEN_HEADER: &str = "x-admin-token";
+
+#[derive(Clone)]
+pub struct AdminState {
+    pub sources: SharedSources,
+    pub styles: SharedStyles,
+    /// Configs of file and proxy sources, used to reload them individually
+    pub source_configs: Arc<Vec<SourceConfig>>,
+    pub token: Arc<str>,
+    /// Reloads and invalidated tiles are announced on `GET /events`
+    pub events: EventBus,
+}
+
+/// Detailed status of a single source
+#[derive(Debug, Clone, Serialize)]
+pub struct AdminSource {
+    pub id: String,
+    pub status: HealthStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub optional: bool,
+    /// Whether `POST /admin/sources/{id}/reload` applies to this source
+    pub reloadable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backend: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_size_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tile_count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub minzoom: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub maxzoom: Option<u8>,
+}
+
+/// A loaded style
+#[derive(Debug, Clone, Serialize)]
+pub struct AdminStyle {
+    pub id: String,
+    pub name: String,
+    pub path: String,
+    /// Unix timestamp (seconds) of the last time the style was read from disk
+    pub loaded_at: u64,
+}
+
+/// Tile cache state; `tile_cache` is null when no cache is configured
+#[derive(Debug, Clone, Serialize)]
+pub struct AdminCache {
+    pub tile_cache: Option<CacheStats>,
+}
+
+/// JSON error body for admin responses
+#[derive(Debug)]
+pub struct AdminError {
+    code: ErrorCode,
+    message: String,
+    details: Option<serde_json::Value>,
+}
+
+impl AdminError {
+    fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            details: None,
+        }
+    }
+}
+
+impl From<TileServerError> for AdminError {
+    fn from(error: TileServerError) -> Self {
+        // Admin callers are trusted, so pass the underlying error through
+        Self {
+            code: error.code(),
+            message: error.to_string(),
+            details: error.details(),
+        }
+    }
+}
+
+impl IntoResponse for AdminError {
+    fn into_response(self) -> Response {
+        (
+            self.code.status(),
+            Json(error_body(self.code, &self.message, self.details)),
+        )
+            .into_response()
+    }
+}
+
+/// Build the admin router
+pub fn router(state: AdminState) -> Router {
+    Router::new()
+        .route("/admin/sources", get(list_sources))
+        .route("/admin/sources/{id}/reload", post(reload_source))
+        .route(
+            "/admin/sources/{id}/tiles/{z}/{x}/{y}",
+            delete(invalidate_tile),
+        )
+        .route("/admin/styles", get(list_styles))
+        .route("/admin/cache", get(get_cache).delete(clear_cache))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .with_state(state)
+}
+
+/// Reject requests without the configured `X-Admin-Token`
+async fn require_token(
+    State(state): State<AdminState>,
+    request: Request,
+    next: Next,
+) -> std::result::Result<Response, AdminError> {
+    let Some(token) = request.headers().get(ADMIN_TOKEN_HEADER) else {
+        return Err(AdminError::new(
+            ErrorCode::Unauthorized,
+            "Missing X-Admin-Token header",
+        ));
+    };
+    if !constant_time_eq(token.as_bytes(), state.token.as_bytes()) {
+        return Err(AdminError::new(ErrorCode::Forbidden, "Invalid admin token"));
+    }
+    Ok(next.run(request).await)
+}
+
+/// Compare without short-circuiting on the first differing byte
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+async fn list_sources(State(state): State<AdminState>) -> Json<Vec<AdminSource>> {
+    let sources = state.sources.load();
+    let configs: HashMap<&str, &SourceConfig> = state
+        .source_configs
+        .iter()
+        .map(|config| (config.id.as_str(), config))
+        .collect();
+
+    let mut statuses = Vec::new();
+    for id in sources.ids() {
+        statuses.push(source_status(&sources, id, configs.get(id.as_str()).copied()).await);
+    }
+    statuses.extend(sources.failed().iter().map(|failed| {
+        let config = configs.get(failed.id.as_str()).copied();
+        AdminSource {
+            id: failed.id.clone(),
+            status: HealthStatus::Error,
+            error: Some(failed.error.clone()),
+            optional: sources.is_optional(&failed.id),
+            reloadable: config.is_some(),
+            backend: None,
+            format: None,
+            path: config.map(|c| c.path.clone()),
+            file_size_bytes: config.and_then(file_size),
+            tile_count: None,
+            minzoom: None,
+            maxzoom: None,
+        }
+    }));
+    statuses.sort_by(|a, b| a.id.cmp(&b.id));
+
+    Json(statuses)
+}
+
+/// Status of a loaded source
+async fn source_status(
+    sources: &SourceManager,
+    id: &str,
+    config: Option<&SourceConfig>,
+) -> AdminSource {
+    let source = sources.get(id).expect("id comes from the manager");
+    let metadata = source.metadata();
+    let tile_count = source.tile_count().await.unwrap_or_else(|e| {
+        tracing::debug!("Failed to count tiles for {}: {}", id, e);
+        None
+    });
+
+    AdminSource {
+        id: id.to_string(),
+        status: HealthStatus::Ok,
+        error: None,
+        optional: sources.is_optional(id),
+        reloadable: config.is_some(),
+        backend: Some(source.backend()),
+        format: Some(metadata.format.name()),
+        path: config.map(|c| c.path.clone()),
+        file_size_bytes: config.and_then(file_size),
+        tile_count,
+        minzoom: Some(metadata.minzoom),
+        maxzoom: Some(metadata.maxzoom),
+    }
+}
+
+/// Size of a local source file; remote sources have none
+fn file_size(config: &SourceConfig) -> Option<u64> {
+    std::fs::metadata(&config.path)
+        .ok()
+        .filter(|m| m.is_file())
+        .map(|m| m.len())
+}
+
+async fn reload_source(
+    State(state): State<AdminState>,
+    Path(id): Path<String>,
+) -> std::result::Result<Json<AdminSource>, AdminError> {
+    let Some(config) = state.source_configs.iter().find(|c| c.id == id) else {
+        let sources = state.sources.load();
+        return Err(if sources.exists(&id) {
+            AdminError::new(
+                ErrorCode::BadRequest,
+                format!("Source '{}' is not a file or proxy source", id),
+            )
+        } else {
+            TileServerError::SourceNotFound(id).into()
+        });
+    };
+
+    state.sources.reload_source(config).await?;
+    tracing::info!("Reloaded source: {} ({})", config.id, config.path);
+    state
+        .events
+        .send(Event::SourceReloaded { source: id.clone() });
+
+    let sources = state.sources.load();
+    Ok(Json(source_status(&sources, &id, Some(config)).await))
+}
+
+/// Drop a cached tile and tell `/events` subscribers to fetch it again
+///
+/// For sources without a tile cache only the event is sent, e.g. after the
+/// tile was rewritten in place.
+async fn invalidate_tile(
+    State(state): State<AdminState>,
+    Path((id, z, x, y)): Path<(String, u8, u32, u32)>,
+) -> std::result::Result<Json<Event>, AdminError> {
+    let sources = state.sources.load();
+    if !sources.exists(&id) {
+        return Err(TileServerError::SourceNotFound(id).into());
+    }
+    sources::validate_tile_coords(z, x, y)?;
+
+    sources.invalidate_tile(&id, z, x, y).await;
+    let event = Event::TileInvalidated {
+        source: id,
+        z,
+        x,
+        y,
+    };
+    state.events.send(event.clone());
+    Ok(Json(event))
+}
+
+async fn list_styles(State(state): State<AdminState>) -> Json<Vec<AdminStyle>> {
+    let manager = state.styles.load();
+    let mut styles: Vec<AdminStyle> = manager
+        .all()
+        .into_iter()
+        .map(|style| AdminStyle {
+            id: style.id.clone(),
+            name: style.name.clone(),
+            path: style.path.display().to_string(),
+            loaded_at: manager
+                .loaded_at(&style.id)
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        })
+        .collect();
+    styles.sort_by(|a, b| a.id.cmp(&b.id));
+
+    Json(styles)
+}
+
+async fn get_cache(State(state): State<AdminState>) -> Json<AdminCache> {
+    Json(AdminCache {
+        tile_cache: state.sources.load().cache_stats().await,
+    })
+}
+
+async fn clear_cache(State(state): State<AdminState>) -> Json<AdminCache> {
+    let sources = state.sources.load();
+    if sources.clear_cache().await {
+        tracing::info!("Tile cache flushed via admin API");
+    }
+    Json(AdminCache {
+        tile_cache: sources.cache_stats().await,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+        assert!(!constant_time_eq(b"", b"secret"));
+    }
+}
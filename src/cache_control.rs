@@ -1,6 +1,228 @@
-use axum::http::HeaderValue;
+use axum::http::{
+    header::{
+        HeaderName, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, ETAG,
+        IF_NONE_MATCH, LAST_MODIFIED, VARY,
+    },
+    HeaderMap, HeaderValue,
+};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::sources::{SourceManager, TileData, TileInfo};
+use crate::styles::Style;
+
+/// Backend that produced a tile (`mbtiles`, `pmtiles`, `postgres`, `render`, ...)
+pub const X_TILE_SOURCE: HeaderName = HeaderName::from_static("x-tile-source");
+/// Format of the tile (`pbf`, `png`, `jpeg`, `webp`, `geojson`, ...)
+pub const X_TILE_FORMAT: HeaderName = HeaderName::from_static("x-tile-format");
+/// Time spent rendering a raster tile, in milliseconds
+pub const X_RENDER_TIME_MS: HeaderName = HeaderName::from_static("x-render-time-ms");
+/// Fingerprint of the style a raster tile was rendered with
+pub const X_STYLE_VERSION: HeaderName = HeaderName::from_static("x-style-version");
 
 /// Set cache headers for tile responses
 pub fn tile_cache_headers() -> HeaderValue {
     HeaderValue::from_static("public, max-age=86400, stale-while-revalidate=604800")
 }
+
+/// Set cache headers for style thumbnails, which rarely change
+pub fn thumbnail_cache_headers() -> HeaderValue {
+    HeaderValue::from_static("public, max-age=604800")
+}
+
+/// Build response headers for a tile served from a source
+///
+/// Tile bodies depend on the request's `Accept-Encoding` (gzip tiles are
+/// decompressed for clients that do not accept gzip), so shared caches must
+/// key on it via `Vary`.
+pub fn tile_response_headers(tile: &TileData) -> HeaderMap {
+    tile_info_headers(&tile.info())
+}
+
+/// Build the headers of a tile response from its size and encoding alone,
+/// so `HEAD` requests match `GET` without reading the tile
+pub fn tile_info_headers(info: &TileInfo) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static(info.format.content_type()),
+    );
+    headers.insert(CONTENT_LENGTH, HeaderValue::from(info.size));
+    headers.insert(CACHE_CONTROL, tile_cache_headers());
+    headers.insert(VARY, HeaderValue::from_static("Accept-Encoding"));
+
+    if let Some(encoding) = info.compression.content_encoding() {
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static(encoding));
+    }
+
+    headers
+}
+
+/// Add `Last-Modified` and a weak `ETag` for tiles from a file-backed source
+///
+/// The ETag combines the file's modification time with the tile size, so it
+/// changes whenever the file is replaced. Sources without a modification
+/// time get neither header.
+pub fn insert_validators(
+    headers: &mut HeaderMap,
+    info: &TileInfo,
+    last_modified: Option<SystemTime>,
+) {
+    let Some(modified) = last_modified else {
+        return;
+    };
+
+    if let Ok(value) = HeaderValue::from_str(&httpdate::fmt_http_date(modified)) {
+        headers.insert(LAST_MODIFIED, value);
+    }
+    let secs = modified
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    if let Ok(value) = HeaderValue::from_str(&format!("W/\"{:x}-{:x}\"", secs, info.size)) {
+        headers.insert(ETAG, value);
+    }
+}
+
+/// 64-bit FNV-1a hash, stable across processes and builds so that every
+/// server behind a CDN derives the same ETags
+pub fn fingerprint(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Validators of a rendered raster tile, known before rendering it
+///
+/// The ETag combines a fingerprint of the rewritten style JSON with the data
+/// versions of the sources the style uses, so it changes when either does:
+/// a reloaded style or a replaced tile file makes CDNs fetch new tiles,
+/// while unchanged tiles revalidate with `304 Not Modified` without being
+/// rendered again. There is no ETag when a source has no data version, as
+/// nothing would tell clients that its data changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderValidators {
+    /// Fingerprint of the rewritten style, sent as `X-Style-Version`
+    pub style_version: String,
+    /// Weak ETag of the tile, `None` when a source has no data version
+    pub etag: Option<String>,
+}
+
+impl RenderValidators {
+    pub fn new(rewritten_style: &str, style: &Style, sources: &SourceManager) -> Self {
+        let data_versions: Option<Vec<(String, String)>> = style
+            .data_source_ids()
+            .into_iter()
+            .map(|id| {
+                let version = sources.data_version(&id)?;
+                Some((id, version))
+            })
+            .collect();
+
+        let style_version = format!("{:016x}", fingerprint(rewritten_style.as_bytes()));
+        let etag = data_versions.map(|mut data_versions| {
+            data_versions.sort();
+            data_versions.dedup();
+            let data: String = data_versions
+                .iter()
+                .map(|(id, version)| format!("{}={};", id, version))
+                .collect();
+            format!(
+                "W/\"{}-{:016x}\"",
+                style_version,
+                fingerprint(data.as_bytes())
+            )
+        });
+        Self {
+            style_version,
+            etag,
+        }
+    }
+
+    /// Add `ETag`, `X-Style-Version` and `Cache-Control` to a response
+    pub fn insert(&self, headers: &mut HeaderMap) {
+        if let Some(Ok(value)) = self.etag.as_deref().map(HeaderValue::from_str) {
+            headers.insert(ETAG, value);
+        }
+        if let Ok(value) = HeaderValue::from_str(&self.style_version) {
+            headers.insert(X_STYLE_VERSION, value);
+        }
+        headers.insert(CACHE_CONTROL, tile_cache_headers());
+    }
+
+    /// Whether the request's `If-None-Match` names the current tile
+    pub fn matches(&self, request: &HeaderMap) -> bool {
+        self.etag
+            .as_deref()
+            .is_some_and(|etag| if_none_match(request, etag))
+    }
+}
+
+/// Whether an `If-None-Match` header matches `etag`, by weak comparison
+pub fn if_none_match(request: &HeaderMap, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let etag = opaque(etag);
+    request
+        .get_all(IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|tag| tag.trim() == "*" || opaque(tag) == etag)
+}
+
+/// Add diagnostic headers to a tile response when `enabled`
+///
+/// Off by default (`[server] debug_headers`) since they expose which
+/// backends the server runs.
+pub fn insert_debug_headers(
+    headers: &mut HeaderMap,
+    enabled: bool,
+    source: &'static str,
+    format: &'static str,
+    render_time: Option<Duration>,
+) {
+    if !enabled {
+        return;
+    }
+
+    headers.insert(X_TILE_SOURCE, HeaderValue::from_static(source));
+    headers.insert(X_TILE_FORMAT, HeaderValue::from_static(format));
+    if let Some(elapsed) = render_time {
+        headers.insert(
+            X_RENDER_TIME_MS,
+            HeaderValue::from(elapsed.as_millis() as u64),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint_is_stable() {
+        assert_eq!(fingerprint(b""), 0xcbf29ce484222325);
+        assert_eq!(fingerprint(b"a"), 0xaf63dc4c8601ec8c);
+        assert_ne!(
+            fingerprint(b"{\"version\":8}"),
+            fingerprint(b"{\"version\":9}")
+        );
+    }
+
+    #[test]
+    fn test_if_none_match() {
+        let request = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(IF_NONE_MATCH, HeaderValue::from_str(value).unwrap());
+            headers
+        };
+        let etag = "W/\"abc-123\"";
+
+        assert!(if_none_match(&request("W/\"abc-123\""), etag));
+        // Weak comparison ignores the W/ prefix
+        assert!(if_none_match(&request("\"abc-123\""), etag));
+        assert!(if_none_match(&request("\"old\", W/\"abc-123\""), etag));
+        assert!(if_none_match(&request("*"), etag));
+        assert!(!if_none_match(&request("W/\"abc-124\""), etag));
+        assert!(!if_none_match(&HeaderMap::new(), etag));
+    }
+}
@@ -1,12 +1,26 @@
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
+use std::str::FromStr;
 
 #[derive(Parser, Debug)]
 #[command(name = "tileserver-rs")]
 #[command(author, version, about = "A high-performance tile server for PMTiles and MBTiles", long_about = None)]
 pub struct Cli {
+    /// Directory or .pmtiles/.mbtiles file to serve without a config file
+    ///
+    /// Without this or --config, the default config locations are tried, then
+    /// the current directory is scanned.
+    #[arg(value_name = "PATH")]
+    pub path: Option<PathBuf>,
+
     /// Path to configuration file
-    #[arg(short, long, value_name = "FILE", env = "TILESERVER_CONFIG")]
+    #[arg(
+        short,
+        long,
+        value_name = "FILE",
+        env = "TILESERVER_CONFIG",
+        global = true
+    )]
     pub config: Option<PathBuf>,
 
     /// Host to bind to
@@ -21,6 +35,14 @@ pub struct Cli {
     #[arg(long, env = "TILESERVER_PUBLIC_URL")]
     pub public_url: Option<String>,
 
+    /// Add X-Tile-Source/X-Tile-Format/X-Render-Time-Ms headers to tile responses
+    #[arg(long, env = "TILESERVER_DEBUG_HEADERS")]
+    pub debug_headers: bool,
+
+    /// Reload styles and source files when they change on disk
+    #[arg(long, env = "TILESERVER_WATCH")]
+    pub watch: bool,
+
     /// Enable the web UI (enabled by default)
     #[arg(long, env = "TILESERVER_UI", default_value = "true")]
     pub ui: bool,
@@ -32,6 +54,85 @@ pub struct Cli {
     /// Enable verbose logging
     #[arg(short, long)]
     pub verbose: bool,
+
+    /// Log line format: human-readable text, newline-delimited JSON, or
+    /// multi-line pretty output
+    #[arg(
+        long,
+        value_enum,
+        env = "TILESERVER_LOG_FORMAT",
+        default_value_t = LogFormat::Text
+    )]
+    pub log_format: LogFormat,
+
+    /// Where logs go: stdout, stderr or file:/path/to/log (appended to)
+    #[arg(long, env = "TILESERVER_LOG_OUTPUT", default_value = "stdout")]
+    pub log_output: LogOutput,
+
+    #[command(subcommand)]
+    pub command: Option<Commands>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Commands {
+    /// Validate the configuration without starting the server
+    ///
+    /// Exits with 0 when valid, 1 with warnings and 2 with errors.
+    #[command(visible_alias = "check")]
+    Validate {
+        /// Report format written to stderr
+        #[arg(long, value_enum, default_value_t = ReportFormat::Table)]
+        format: ReportFormat,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    Table,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+    Pretty,
+}
+
+/// Destination of log output
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LogOutput {
+    Stdout,
+    Stderr,
+    File(PathBuf),
+}
+
+impl FromStr for LogOutput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stdout" => Ok(LogOutput::Stdout),
+            "stderr" => Ok(LogOutput::Stderr),
+            _ => match s.strip_prefix("file:") {
+                Some(path) if !path.is_empty() => Ok(LogOutput::File(PathBuf::from(path))),
+                _ => Err(format!(
+                    "invalid log output '{}', expected stdout, stderr or file:/path/to/log",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for LogOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogOutput::Stdout => write!(f, "stdout"),
+            LogOutput::Stderr => write!(f, "stderr"),
+            LogOutput::File(path) => write!(f, "file:{}", path.display()),
+        }
+    }
 }
 
 impl Cli {
@@ -44,3 +145,71 @@ impl Cli {
         !self.no_ui && self.ui
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_validate() {
+        let cli = Cli::parse_from([
+            "tileserver-rs",
+            "validate",
+            "--config",
+            "config.toml",
+            "--format",
+            "json",
+        ]);
+
+        assert_eq!(cli.config, Some(PathBuf::from("config.toml")));
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Validate {
+                format: ReportFormat::Json
+            })
+        ));
+    }
+
+    #[test]
+    fn test_parse_path() {
+        let cli = Cli::parse_from(["tileserver-rs", "/data/tiles", "--port", "3000", "--watch"]);
+        assert_eq!(cli.path, Some(PathBuf::from("/data/tiles")));
+        assert_eq!(cli.port, Some(3000));
+        assert!(cli.watch);
+        assert!(cli.command.is_none());
+    }
+
+    #[test]
+    fn test_parse_log_options() {
+        let cli = Cli::parse_from(["tileserver-rs"]);
+        assert_eq!(cli.log_format, LogFormat::Text);
+        assert_eq!(cli.log_output, LogOutput::Stdout);
+
+        let cli = Cli::parse_from([
+            "tileserver-rs",
+            "--log-format",
+            "json",
+            "--log-output",
+            "file:/var/log/tileserver.log",
+        ]);
+        assert_eq!(cli.log_format, LogFormat::Json);
+        assert_eq!(
+            cli.log_output,
+            LogOutput::File(PathBuf::from("/var/log/tileserver.log"))
+        );
+
+        assert!(Cli::try_parse_from(["tileserver-rs", "--log-output", "file:"]).is_err());
+        assert!(Cli::try_parse_from(["tileserver-rs", "--log-output", "syslog"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_check_alias() {
+        let cli = Cli::parse_from(["tileserver-rs", "check"]);
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Validate {
+                format: ReportFormat::Table
+            })
+        ));
+    }
+}
@@ -0,0 +1,455 @@
+//! Keep-alive and connection limits for TCP listeners
+//!
+//! Long-lived connections hold a file descriptor each, so idle keep-alive
+//! connections are closed after `[server] keepalive_timeout_secs` and busy
+//! ones after `keepalive_max_requests` requests. HTTP/1.1 responses
+//! advertise both limits in a `Keep-Alive: timeout=75, max=1000` header.
+//! Connections beyond `max_connections` are accepted only to answer their
+//! requests with a 503 `TOO_MANY_CONNECTIONS` error and `Connection: close`.
+//!
+//! Plain HTTP listeners apply the limits with [`LimitedListener`], HTTPS
+//! listeners with [`LimitedAcceptor`] under the TLS acceptor.
+
+use std::{
+    convert::Infallible,
+    future::{Future, Ready},
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use axum::{
+    body::{Bytes, HttpBody},
+    extract::ConnectInfo,
+    http::{header, HeaderName, HeaderValue, Request, Version},
+    response::{IntoResponse, Response},
+    serve::IncomingStream,
+    BoxError, Router,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{TcpListener, TcpStream},
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::{Instant, Sleep},
+};
+use tower::Service;
+
+use crate::config::ServerConfig;
+use crate::error::TileServerError;
+
+/// `Keep-Alive` response header
+pub const KEEP_ALIVE: HeaderName = HeaderName::from_static("keep-alive");
+
+/// Keep-alive and connection limits, 0 to disable one
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConnectionLimits {
+    pub keepalive_timeout_secs: u64,
+    pub keepalive_max_requests: u64,
+    pub max_connections: usize,
+}
+
+impl From<&ServerConfig> for ConnectionLimits {
+    fn from(config: &ServerConfig) -> Self {
+        Self {
+            keepalive_timeout_secs: config.keepalive_timeout_secs,
+            keepalive_max_requests: config.keepalive_max_requests,
+            max_connections: config.max_connections,
+        }
+    }
+}
+
+impl ConnectionLimits {
+    /// `Keep-Alive` header for a response after `served` earlier requests
+    /// on the same connection
+    fn keep_alive_header(&self, served: u64) -> Option<HeaderValue> {
+        let mut params = Vec::new();
+        if self.keepalive_timeout_secs > 0 {
+            params.push(format!("timeout={}", self.keepalive_timeout_secs));
+        }
+        if self.keepalive_max_requests > 0 {
+            let remaining = self.keepalive_max_requests.saturating_sub(served);
+            params.push(format!("max={}", remaining));
+        }
+        if params.is_empty() {
+            return None;
+        }
+        HeaderValue::from_str(&params.join(", ")).ok()
+    }
+
+    /// Whether the request after `served` earlier ones is the last one
+    /// allowed on its connection
+    fn is_last_request(&self, served: u64) -> bool {
+        self.keepalive_max_requests > 0 && served + 1 >= self.keepalive_max_requests
+    }
+}
+
+/// State shared by a connection's stream and the service answering it
+#[derive(Debug)]
+struct ConnectionState {
+    /// False for connections over `max_connections`
+    admitted: bool,
+    /// Requests received so far
+    requests: AtomicU64,
+    /// Requests still waiting for their response
+    in_flight: AtomicUsize,
+}
+
+/// Places under `max_connections` of one listener, handed to the
+/// connections it accepts
+#[derive(Debug, Clone)]
+struct Admission {
+    limits: ConnectionLimits,
+    permits: Option<Arc<Semaphore>>,
+}
+
+impl Admission {
+    fn new(limits: ConnectionLimits) -> Self {
+        let permits = (limits.max_connections > 0).then(|| {
+            Arc::new(Semaphore::new(
+                limits.max_connections.min(Semaphore::MAX_PERMITS),
+            ))
+        });
+        Self { limits, permits }
+    }
+
+    /// Wrap an accepted connection, taking a place if one is free
+    fn admit(&self, stream: TcpStream, addr: SocketAddr) -> LimitedStream {
+        let permit = self
+            .permits
+            .as_ref()
+            .map(|permits| permits.clone().try_acquire_owned());
+        let admitted = !matches!(permit, Some(Err(_)));
+        if !admitted {
+            tracing::debug!(
+                "Refusing connection from {}: {} connections are open",
+                addr,
+                self.limits.max_connections
+            );
+        }
+
+        let idle = (self.limits.keepalive_timeout_secs > 0).then(|| {
+            let timeout = Duration::from_secs(self.limits.keepalive_timeout_secs);
+            (timeout, Box::pin(tokio::time::sleep(timeout)))
+        });
+        LimitedStream {
+            stream,
+            state: Arc::new(ConnectionState {
+                admitted,
+                requests: AtomicU64::new(0),
+                in_flight: AtomicUsize::new(0),
+            }),
+            idle,
+            _permit: permit.and_then(Result::ok),
+        }
+    }
+}
+
+/// TCP listener applying [`ConnectionLimits`] to accepted connections
+///
+/// Pair it with [`make_service`] in `axum::serve`.
+#[derive(Debug)]
+pub struct LimitedListener {
+    listener: TcpListener,
+    admission: Admission,
+}
+
+impl LimitedListener {
+    pub fn new(listener: TcpListener, limits: ConnectionLimits) -> Self {
+        Self {
+            listener,
+            admission: Admission::new(limits),
+        }
+    }
+}
+
+impl axum::serve::Listener for LimitedListener {
+    type Io = LimitedStream;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        let (stream, addr) = axum::serve::Listener::accept(&mut self.listener).await;
+        (self.admission.admit(stream, addr), addr)
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        self.listener.local_addr()
+    }
+}
+
+/// `axum_server` acceptor applying [`ConnectionLimits`] to the TCP
+/// connections of an HTTPS listener, before the TLS handshake
+///
+/// Serve it with `router.into_make_service()`: the connection services it
+/// makes add the peer address as [`ConnectInfo`].
+#[derive(Debug, Clone)]
+pub struct LimitedAcceptor {
+    admission: Admission,
+}
+
+impl LimitedAcceptor {
+    pub fn new(limits: ConnectionLimits) -> Self {
+        Self {
+            admission: Admission::new(limits),
+        }
+    }
+}
+
+impl axum_server::accept::Accept<TcpStream, Router> for LimitedAcceptor {
+    type Stream = LimitedStream;
+    type Service = ConnectionService;
+    type Future = Ready<io::Result<(LimitedStream, ConnectionService)>>;
+
+    fn accept(&self, stream: TcpStream, router: Router) -> Self::Future {
+        std::future::ready(stream.peer_addr().map(|addr| {
+            let stream = self.admission.admit(stream, addr);
+            let service = ConnectionService {
+                router,
+                limits: self.admission.limits,
+                peer: ConnectInfo(addr),
+                state: stream.state.clone(),
+            };
+            (stream, service)
+        }))
+    }
+}
+
+/// Accepted connection that ends once idle for the keep-alive timeout
+///
+/// Dropping it frees its place under `max_connections`.
+#[derive(Debug)]
+pub struct LimitedStream {
+    stream: TcpStream,
+    state: Arc<ConnectionState>,
+    idle: Option<(Duration, Pin<Box<Sleep>>)>,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl LimitedStream {
+    /// Restart the idle timer after traffic
+    fn touch(&mut self) {
+        if let Some((timeout, sleep)) = &mut self.idle {
+            sleep.as_mut().reset(Instant::now() + *timeout);
+        }
+    }
+
+    /// Whether the connection has been idle for longer than the timeout;
+    /// connections waiting for a response are never idle
+    fn poll_idle(&mut self, cx: &mut Context<'_>) -> bool {
+        let Some((timeout, sleep)) = &mut self.idle else {
+            return false;
+        };
+        if self.state.in_flight.load(Ordering::Acquire) > 0 {
+            sleep.as_mut().reset(Instant::now() + *timeout);
+            return false;
+        }
+        sleep.as_mut().poll(cx).is_ready()
+    }
+}
+
+impl AsyncRead for LimitedStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        match Pin::new(&mut this.stream).poll_read(cx, buf) {
+            // Reads as the end of the stream, so the connection closes cleanly
+            Poll::Pending if this.poll_idle(cx) => Poll::Ready(Ok(())),
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(result) => {
+                if buf.filled().len() > filled {
+                    this.touch();
+                }
+                Poll::Ready(result)
+            }
+        }
+    }
+}
+
+impl AsyncWrite for LimitedStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.stream).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = result {
+            if written > 0 {
+                this.touch();
+            }
+        }
+        result
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.stream).poll_write_vectored(cx, bufs);
+        if let Poll::Ready(Ok(written)) = result {
+            if written > 0 {
+                this.touch();
+            }
+        }
+        result
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+    }
+}
+
+/// Make service answering each [`LimitedListener`] connection with `router`
+///
+/// Like `into_make_service_with_connect_info::<SocketAddr>`, requests carry
+/// the peer address as [`ConnectInfo`].
+pub fn make_service(router: Router, limits: ConnectionLimits) -> LimitedMakeService {
+    LimitedMakeService { router, limits }
+}
+
+/// See [`make_service`]
+#[derive(Debug, Clone)]
+pub struct LimitedMakeService {
+    router: Router,
+    limits: ConnectionLimits,
+}
+
+impl Service<IncomingStream<'_, LimitedListener>> for LimitedMakeService {
+    type Response = ConnectionService;
+    type Error = Infallible;
+    type Future = Ready<Result<ConnectionService, Infallible>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, stream: IncomingStream<'_, LimitedListener>) -> Self::Future {
+        std::future::ready(Ok(ConnectionService {
+            router: self.router.clone(),
+            limits: self.limits,
+            peer: ConnectInfo(*stream.remote_addr()),
+            state: stream.io().state.clone(),
+        }))
+    }
+}
+
+/// Service for one connection, counting its requests
+#[derive(Debug, Clone)]
+pub struct ConnectionService {
+    router: Router,
+    limits: ConnectionLimits,
+    peer: ConnectInfo<SocketAddr>,
+    state: Arc<ConnectionState>,
+}
+
+impl<B> Service<Request<B>> for ConnectionService
+where
+    B: HttpBody<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> Self::Future {
+        if !self.state.admitted {
+            let mut response = TileServerError::TooManyConnections.into_response();
+            response
+                .headers_mut()
+                .insert(header::CONNECTION, HeaderValue::from_static("close"));
+            return Box::pin(std::future::ready(Ok(response)));
+        }
+
+        let served = self.state.requests.fetch_add(1, Ordering::Relaxed);
+        // Connection headers only exist in HTTP/1; HTTP/1.0 closes by default
+        let keep_alive = request.version() == Version::HTTP_11
+            && !request
+                .headers()
+                .get(header::CONNECTION)
+                .is_some_and(|value| value.as_bytes().eq_ignore_ascii_case(b"close"));
+        let close = keep_alive && self.limits.is_last_request(served);
+        let keep_alive_header = self.limits.keep_alive_header(served);
+
+        request.extensions_mut().insert(self.peer);
+        let in_flight = InFlight::new(self.state.clone());
+        let response = self.router.call(request);
+        Box::pin(async move {
+            let mut response = response.await?;
+            drop(in_flight);
+            let headers = response.headers_mut();
+            if close {
+                // hyper closes the connection once this response is sent
+                headers.insert(header::CONNECTION, HeaderValue::from_static("close"));
+            } else if let Some(value) = keep_alive_header.filter(|_| keep_alive) {
+                headers.insert(KEEP_ALIVE, value);
+            }
+            Ok(response)
+        })
+    }
+}
+
+/// Marks a request as waiting for its response until dropped
+struct InFlight(Arc<ConnectionState>);
+
+impl InFlight {
+    fn new(state: Arc<ConnectionState>) -> Self {
+        state.in_flight.fetch_add(1, Ordering::AcqRel);
+        Self(state)
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keep_alive_header() {
+        let limits = ConnectionLimits {
+            keepalive_timeout_secs: 75,
+            keepalive_max_requests: 1000,
+            max_connections: 10_000,
+        };
+        assert_eq!(limits.keep_alive_header(0).unwrap(), "timeout=75, max=1000");
+        assert_eq!(limits.keep_alive_header(998).unwrap(), "timeout=75, max=2");
+        assert!(!limits.is_last_request(998));
+        assert!(limits.is_last_request(999));
+
+        let unlimited = ConnectionLimits {
+            keepalive_max_requests: 0,
+            ..limits
+        };
+        assert_eq!(unlimited.keep_alive_header(5000).unwrap(), "timeout=75");
+        assert!(!unlimited.is_last_request(5000));
+
+        assert!(ConnectionLimits::default().keep_alive_header(0).is_none());
+    }
+}
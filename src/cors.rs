@@ -0,0 +1,354 @@
+//! CORS policies
+//!
+//! `[server] cors_origins`, `cors_allow_credentials` and `cors_expose_headers`
+//! set the default policy. Route groups can override it in
+//! `[server.cors_routes.<group>]`, or turn CORS off with `enabled = false`.
+//! The admin API sends no CORS headers unless its group enables them.
+//!
+//! Preflight responses may be cached by browsers for a day, and policies
+//! without credentials say so with `Access-Control-Allow-Credentials: false`.
+
+use axum::http::{
+    header::{
+        ACCEPT, ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_ORIGIN, AUTHORIZATION,
+        CONTENT_TYPE, IF_RANGE, RANGE,
+    },
+    HeaderName, HeaderValue, Method,
+};
+use axum::response::Response;
+use axum::Router;
+use std::collections::HashMap;
+use std::time::Duration;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+use crate::admin::ADMIN_TOKEN_HEADER;
+use crate::config::{ServerConfig, CORS_ROUTE_GROUPS};
+use crate::error::{Result, TileServerError};
+use crate::request_id::REQUEST_ID_HEADER;
+
+/// Route group of the admin API
+pub const ADMIN_GROUP: &str = "admin";
+
+/// How long browsers may cache a preflight response
+const MAX_AGE: Duration = Duration::from_secs(86400);
+
+/// Matches request origins against the configured patterns
+///
+/// Patterns are exact origins (`https://app.example.com`), subdomain
+/// wildcards (`https://*.example.com`, or `*.example.com` for any scheme)
+/// or `*` for every origin. Wildcards don't match the bare domain itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OriginMatcher {
+    any: bool,
+    exact: Vec<String>,
+    /// Scheme prefix (e.g. `https://`, empty for any) and domain suffix
+    /// (e.g. `.example.com`)
+    wildcards: Vec<(String, String)>,
+}
+
+impl OriginMatcher {
+    /// Parse origin patterns; an empty list allows every origin
+    pub fn new(patterns: &[String]) -> Result<Self> {
+        let mut matcher = Self {
+            any: patterns.is_empty(),
+            ..Self::default()
+        };
+
+        for pattern in patterns {
+            let pattern = pattern.trim().trim_end_matches('/').to_ascii_lowercase();
+            if pattern == "*" {
+                matcher.any = true;
+                continue;
+            }
+
+            let (scheme, host) = match pattern.split_once("://") {
+                Some((scheme, host)) => (format!("{}://", scheme), host),
+                None => (String::new(), pattern.as_str()),
+            };
+            if host.contains('*') {
+                match host.strip_prefix('*') {
+                    Some(suffix) if suffix.starts_with('.') && !suffix.contains('*') => {
+                        matcher.wildcards.push((scheme, suffix.to_string()));
+                    }
+                    _ => return Err(invalid_origin(&pattern)),
+                }
+            } else if scheme.is_empty()
+                || host.is_empty()
+                || HeaderValue::from_str(&pattern).is_err()
+            {
+                return Err(invalid_origin(&pattern));
+            } else {
+                matcher.exact.push(pattern);
+            }
+        }
+
+        Ok(matcher)
+    }
+
+    /// Whether every origin is allowed
+    pub fn is_any(&self) -> bool {
+        self.any
+    }
+
+    pub fn matches(&self, origin: &str) -> bool {
+        if self.any {
+            return true;
+        }
+        let origin = origin.to_ascii_lowercase();
+        if self.exact.contains(&origin) {
+            return true;
+        }
+
+        let Some((scheme, host)) = origin.split_once("://") else {
+            return false;
+        };
+        self.wildcards.iter().any(|(allowed_scheme, suffix)| {
+            let scheme_matches =
+                allowed_scheme.is_empty() || allowed_scheme.strip_suffix("://") == Some(scheme);
+            scheme_matches
+                && host.len() > suffix.len()
+                && host.ends_with(suffix.as_str())
+                && host[..host.len() - suffix.len()]
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+        })
+    }
+}
+
+fn invalid_origin(pattern: &str) -> TileServerError {
+    TileServerError::ConfigError(format!(
+        "Invalid CORS origin '{}', expected e.g. 'https://app.example.com', \
+         'https://*.example.com' or '*'",
+        pattern
+    ))
+}
+
+/// A resolved CORS policy
+#[derive(Debug, Clone)]
+struct Policy {
+    origins: OriginMatcher,
+    allow_credentials: bool,
+    expose_headers: Vec<HeaderName>,
+}
+
+impl Policy {
+    /// Refuse credentials for every origin, which would let any site make
+    /// authenticated requests and read the responses
+    fn check(&self, section: &str) -> Result<()> {
+        if self.allow_credentials && self.origins.is_any() {
+            return Err(TileServerError::ConfigError(format!(
+                "CORS credentials are allowed for every origin in {}; \
+                 list the allowed origins instead of '*'",
+                section
+            )));
+        }
+        Ok(())
+    }
+
+    fn layer(&self, admin: bool) -> CorsLayer {
+        // Credentials are only allowed with a list of origins, which are
+        // echoed since `Access-Control-Allow-Origin: *` can't carry them
+        let allow_origin = if self.origins.is_any() {
+            AllowOrigin::any()
+        } else {
+            let origins = self.origins.clone();
+            AllowOrigin::predicate(move |origin: &HeaderValue, _| {
+                origin.to_str().is_ok_and(|origin| origins.matches(origin))
+            })
+        };
+
+        let mut methods = vec![Method::GET, Method::POST, Method::OPTIONS, Method::HEAD];
+        let mut headers = vec![
+            ACCEPT,
+            AUTHORIZATION,
+            CONTENT_TYPE,
+            IF_RANGE,
+            RANGE,
+            REQUEST_ID_HEADER.clone(),
+        ];
+        if admin {
+            methods.push(Method::DELETE);
+            headers.push(HeaderName::from_static(ADMIN_TOKEN_HEADER));
+        }
+
+        CorsLayer::new()
+            .allow_headers(headers)
+            .max_age(MAX_AGE)
+            .allow_origin(allow_origin)
+            .allow_methods(methods)
+            .allow_credentials(self.allow_credentials)
+            .expose_headers(self.expose_headers.clone())
+    }
+}
+
+/// CORS policies for every route group
+#[derive(Debug, Clone)]
+pub struct Cors {
+    default: Policy,
+    /// Overridden groups; `None` disables CORS for the group
+    routes: HashMap<String, Option<Policy>>,
+}
+
+impl Cors {
+    pub fn from_config(server: &ServerConfig) -> Result<Self> {
+        let default = Policy {
+            origins: OriginMatcher::new(&server.cors_origins)?,
+            allow_credentials: server.cors_allow_credentials,
+            expose_headers: header_names(&server.cors_expose_headers)?,
+        };
+        default.check("[server]")?;
+
+        let mut routes = HashMap::new();
+        // The admin API is only reachable cross-origin when configured
+        routes.insert(ADMIN_GROUP.to_string(), None);
+
+        for (group, route) in &server.cors_routes {
+            if !CORS_ROUTE_GROUPS.contains(&group.as_str()) {
+                return Err(TileServerError::ConfigError(format!(
+                    "Unknown CORS route group '{}', expected one of: {}",
+                    group,
+                    CORS_ROUTE_GROUPS.join(", ")
+                )));
+            }
+
+            let enabled = route.enabled.unwrap_or(true);
+            let policy = if enabled {
+                Some(Policy {
+                    origins: match &route.origins {
+                        Some(origins) => OriginMatcher::new(origins)?,
+                        None => default.origins.clone(),
+                    },
+                    allow_credentials: route.allow_credentials.unwrap_or(default.allow_credentials),
+                    expose_headers: match &route.expose_headers {
+                        Some(headers) => header_names(headers)?,
+                        None => default.expose_headers.clone(),
+                    },
+                })
+            } else {
+                None
+            };
+            if let Some(policy) = &policy {
+                policy.check(&format!("[server.cors_routes.{}]", group))?;
+            }
+            routes.insert(group.clone(), policy);
+        }
+
+        Ok(Self { default, routes })
+    }
+
+    /// Expose additional headers in every policy
+    pub fn expose_headers(mut self, headers: impl IntoIterator<Item = HeaderName>) -> Self {
+        let headers: Vec<HeaderName> = headers.into_iter().collect();
+        let policies = std::iter::once(&mut self.default).chain(self.routes.values_mut().flatten());
+        for policy in policies {
+            for header in &headers {
+                if !policy.expose_headers.contains(header) {
+                    policy.expose_headers.push(header.clone());
+                }
+            }
+        }
+        self
+    }
+
+    /// Whether the default policy allows every origin
+    pub fn allows_any_origin(&self) -> bool {
+        self.default.origins.is_any()
+    }
+
+    /// Apply the policy of `group` (or the default policy) to `router`
+    pub fn apply<S>(&self, group: Option<&str>, router: Router<S>) -> Router<S>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        let policy = match group.and_then(|group| self.routes.get(group)) {
+            Some(policy) => policy.as_ref(),
+            None => Some(&self.default),
+        };
+        match policy {
+            Some(policy) if policy.allow_credentials => {
+                router.layer(policy.layer(group == Some(ADMIN_GROUP)))
+            }
+            Some(policy) => router
+                .layer(policy.layer(group == Some(ADMIN_GROUP)))
+                .layer(axum::middleware::map_response(deny_credentials)),
+            None => router,
+        }
+    }
+}
+
+/// Spell out `Access-Control-Allow-Credentials: false` on CORS responses,
+/// which `CorsLayer` leaves out when credentials aren't allowed
+async fn deny_credentials(mut response: Response) -> Response {
+    let headers = response.headers_mut();
+    if headers.contains_key(ACCESS_CONTROL_ALLOW_ORIGIN) {
+        headers
+            .entry(ACCESS_CONTROL_ALLOW_CREDENTIALS)
+            .or_insert(HeaderValue::from_static("false"));
+    }
+    response
+}
+
+fn header_names(headers: &[String]) -> Result<Vec<HeaderName>> {
+    headers
+        .iter()
+        .map(|header| {
+            HeaderName::from_bytes(header.trim().as_bytes()).map_err(|_| {
+                TileServerError::ConfigError(format!("Invalid CORS expose header '{}'", header))
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(patterns: &[&str]) -> OriginMatcher {
+        OriginMatcher::new(&patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>()).unwrap()
+    }
+
+    #[test]
+    fn test_exact_origins() {
+        let m = matcher(&["https://app.example.com", "https://staging.example.com/"]);
+        assert!(m.matches("https://app.example.com"));
+        assert!(m.matches("https://STAGING.example.com"));
+        assert!(!m.matches("http://app.example.com"));
+        assert!(!m.matches("https://evil.com"));
+        assert!(!m.is_any());
+    }
+
+    #[test]
+    fn test_wildcard_origins() {
+        let m = matcher(&["https://*.example.com", "*.example.org"]);
+        assert!(m.matches("https://app.example.com"));
+        assert!(m.matches("https://a.b.example.com"));
+        assert!(m.matches("http://tiles.example.org"));
+        assert!(!m.matches("http://app.example.com"));
+        assert!(!m.matches("https://example.com"));
+        assert!(!m.matches("https://evilexample.com"));
+        assert!(!m.matches("https://app.example.com.evil.com"));
+        assert!(!m.matches("https://app.example.com:8443"));
+    }
+
+    #[test]
+    fn test_any_origin() {
+        assert!(matcher(&[]).is_any());
+        assert!(matcher(&["https://app.example.com", "*"]).matches("https://x.y"));
+    }
+
+    #[test]
+    fn test_invalid_origins() {
+        for pattern in [
+            "app.example.com",
+            "https://app.*.com",
+            "*example.com",
+            "https://*",
+        ] {
+            assert!(
+                OriginMatcher::new(&[pattern.to_string()]).is_err(),
+                "{}",
+                pattern
+            );
+        }
+    }
+}
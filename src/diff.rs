@@ -0,0 +1,556 @@
+//! Feature-level comparison of one tile in two sources
+//! (`GET /diff/{source_a}/{source_b}/{z}/{x}/{y}`)
+//!
+//! Useful when migrating to a new export of the same data. Features of a
+//! layer are matched by their MVT id when both have one, and otherwise by
+//! their encoded geometry; matched features are compared property by
+//! property. A tile missing from one source compares as an empty tile.
+
+use geozero::mvt::tile::{Feature, Layer, Value};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::error::{Result, TileServerError};
+use crate::sources::manager::SourceManager;
+use crate::sources::{mvt_layer_features, TileData, TileFormat};
+
+/// Differences between the two versions of a tile
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TileDiff {
+    /// Whether both versions have the same layers and features
+    pub identical: bool,
+    pub layers_only_in_a: Vec<String>,
+    pub layers_only_in_b: Vec<String>,
+    /// Every layer of either version, sorted by name
+    pub layers: Vec<LayerDiff>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LayerDiff {
+    pub name: String,
+    /// Features only in B
+    pub added: usize,
+    /// Features only in A
+    pub removed: usize,
+    pub unchanged: usize,
+    /// Features in both whose geometry or properties differ
+    pub changed: Vec<FeatureChange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FeatureChange {
+    /// MVT feature id, when the feature has one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    pub geometry_changed: bool,
+    /// Changed properties; `null` on a side that lacks the property
+    pub properties: BTreeMap<String, PropertyChange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PropertyChange {
+    pub a: Option<serde_json::Value>,
+    pub b: Option<serde_json::Value>,
+}
+
+/// What happened to a feature going from A to B, as `_diff_action` in GeoJSON
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Added,
+    Removed,
+    Unchanged,
+}
+
+impl Action {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Action::Added => "added",
+            Action::Removed => "removed",
+            Action::Unchanged => "unchanged",
+        }
+    }
+}
+
+/// Matched features of one layer
+struct LayerComparison {
+    name: String,
+    a: Option<Layer>,
+    b: Option<Layer>,
+    /// `(a, b)` feature indices of features found in both versions
+    matched: Vec<(usize, usize)>,
+    removed: Vec<usize>,
+    added: Vec<usize>,
+}
+
+/// Two versions of a tile with their features matched up
+pub struct Comparison {
+    layers: Vec<LayerComparison>,
+}
+
+/// Fetch tile `z/x/y` (XYZ rows) from both sources and compare them
+pub async fn diff_sources(
+    sources: &SourceManager,
+    source_a: &str,
+    source_b: &str,
+    z: u8,
+    x: u32,
+    y: u32,
+) -> Result<Comparison> {
+    for id in [source_a, source_b] {
+        let source = sources
+            .get(id)
+            .ok_or_else(|| TileServerError::SourceNotFound(id.to_string()))?;
+        if source.metadata().format != TileFormat::Pbf {
+            return Err(TileServerError::BadRequest(format!(
+                "Source '{}' is not a vector source; only vector tiles can be compared",
+                id
+            )));
+        }
+    }
+    crate::sources::validate_tile_coords(z, x, y)?;
+
+    let (a, b) = tokio::try_join!(
+        sources.get_tile(source_a, z, x, y),
+        sources.get_tile(source_b, z, x, y)
+    )?;
+    if a.is_none() && b.is_none() {
+        return Err(TileServerError::TileNotFound { z, x, y });
+    }
+    compare(a.as_ref(), b.as_ref())
+}
+
+/// Compare two versions of a vector tile, either of which may be missing
+pub fn compare(a: Option<&TileData>, b: Option<&TileData>) -> Result<Comparison> {
+    let decode = |tile: Option<&TileData>| -> Result<BTreeMap<String, Layer>> {
+        let Some(tile) = tile else {
+            return Ok(BTreeMap::new());
+        };
+        Ok(tile
+            .decode_mvt()?
+            .layers
+            .into_iter()
+            .map(|layer| (layer.name.clone(), layer))
+            .collect())
+    };
+    let mut a = decode(a)?;
+    let mut b = decode(b)?;
+
+    let names: BTreeSet<String> = a.keys().chain(b.keys()).cloned().collect();
+    let layers = names
+        .into_iter()
+        .map(|name| {
+            let a = a.remove(&name);
+            let b = b.remove(&name);
+            let (matched, removed, added) = match_features(a.as_ref(), b.as_ref());
+            LayerComparison {
+                name,
+                a,
+                b,
+                matched,
+                removed,
+                added,
+            }
+        })
+        .collect();
+    Ok(Comparison { layers })
+}
+
+/// Pair up the features of two versions of a layer, by id and then by
+/// geometry, returning the pairs and the unpaired features of A and B
+fn match_features(
+    a: Option<&Layer>,
+    b: Option<&Layer>,
+) -> (Vec<(usize, usize)>, Vec<usize>, Vec<usize>) {
+    let a_features = a.map(|layer| layer.features.as_slice()).unwrap_or_default();
+    let b_features = b.map(|layer| layer.features.as_slice()).unwrap_or_default();
+
+    let mut by_id: HashMap<u64, Vec<usize>> = HashMap::new();
+    let mut by_geometry: HashMap<(i32, &[u32]), Vec<usize>> = HashMap::new();
+    // Reversed so that `pop` hands out duplicates in tile order
+    for (i, feature) in b_features.iter().enumerate().rev() {
+        match feature.id {
+            Some(id) => by_id.entry(id).or_default().push(i),
+            None => by_geometry
+                .entry(geometry_key(feature))
+                .or_default()
+                .push(i),
+        }
+    }
+
+    let mut matched = Vec::new();
+    let mut removed = Vec::new();
+    let mut b_matched = vec![false; b_features.len()];
+    for (i, feature) in a_features.iter().enumerate() {
+        let candidate = match feature.id {
+            Some(id) => by_id.get_mut(&id).and_then(Vec::pop),
+            None => by_geometry
+                .get_mut(&geometry_key(feature))
+                .and_then(Vec::pop),
+        };
+        match candidate {
+            Some(j) => {
+                b_matched[j] = true;
+                matched.push((i, j));
+            }
+            None => removed.push(i),
+        }
+    }
+    let added = (0..b_features.len()).filter(|&j| !b_matched[j]).collect();
+
+    (matched, removed, added)
+}
+
+fn geometry_key(feature: &Feature) -> (i32, &[u32]) {
+    (feature.r#type.unwrap_or_default(), &feature.geometry)
+}
+
+/// Properties of a feature, resolved against its layer's keys and values
+pub(crate) fn properties(layer: &Layer, feature: &Feature) -> BTreeMap<String, serde_json::Value> {
+    feature
+        .tags
+        .chunks_exact(2)
+        .filter_map(|tag| {
+            let key = layer.keys.get(tag[0] as usize)?;
+            let value = layer.values.get(tag[1] as usize)?;
+            Some((key.clone(), json_value(value)?))
+        })
+        .collect()
+}
+
+fn json_value(value: &Value) -> Option<serde_json::Value> {
+    if let Some(s) = &value.string_value {
+        return Some(s.clone().into());
+    }
+    if let Some(f) = value.float_value {
+        return serde_json::Number::from_f64(f64::from(f)).map(Into::into);
+    }
+    if let Some(f) = value.double_value {
+        return serde_json::Number::from_f64(f).map(Into::into);
+    }
+    if let Some(i) = value.int_value.or(value.sint_value) {
+        return Some(i.into());
+    }
+    if let Some(u) = value.uint_value {
+        return Some(u.into());
+    }
+    value.bool_value.map(Into::into)
+}
+
+impl LayerComparison {
+    fn feature_change(&self, (i, j): (usize, usize)) -> Option<FeatureChange> {
+        let (a, b) = (self.a.as_ref()?, self.b.as_ref()?);
+        let (fa, fb) = (&a.features[i], &b.features[j]);
+
+        let geometry_changed = geometry_key(fa) != geometry_key(fb);
+        let mut pa = properties(a, fa);
+        let mut pb = properties(b, fb);
+        let keys: BTreeSet<String> = pa.keys().chain(pb.keys()).cloned().collect();
+        let properties: BTreeMap<String, PropertyChange> = keys
+            .into_iter()
+            .filter_map(|key| {
+                let change = PropertyChange {
+                    a: pa.remove(&key),
+                    b: pb.remove(&key),
+                };
+                (change.a != change.b).then_some((key, change))
+            })
+            .collect();
+
+        (geometry_changed || !properties.is_empty()).then_some(FeatureChange {
+            id: fb.id,
+            geometry_changed,
+            properties,
+        })
+    }
+
+    fn summary(&self) -> LayerDiff {
+        let changed: Vec<FeatureChange> = self
+            .matched
+            .iter()
+            .filter_map(|&pair| self.feature_change(pair))
+            .collect();
+        LayerDiff {
+            name: self.name.clone(),
+            added: self.added.len(),
+            removed: self.removed.len(),
+            unchanged: self.matched.len() - changed.len(),
+            changed,
+        }
+    }
+}
+
+impl Comparison {
+    /// Summary of the differences
+    pub fn summary(&self) -> TileDiff {
+        let layers: Vec<LayerDiff> = self.layers.iter().map(LayerComparison::summary).collect();
+        let only_in = |present: fn(&LayerComparison) -> bool| {
+            self.layers
+                .iter()
+                .filter(|layer| present(layer))
+                .map(|layer| layer.name.clone())
+                .collect()
+        };
+
+        TileDiff {
+            identical: layers
+                .iter()
+                .all(|layer| layer.added == 0 && layer.removed == 0 && layer.changed.is_empty())
+                && self
+                    .layers
+                    .iter()
+                    .all(|layer| layer.a.is_some() == layer.b.is_some()),
+            layers_only_in_a: only_in(|layer| layer.b.is_none()),
+            layers_only_in_b: only_in(|layer| layer.a.is_none()),
+            layers,
+        }
+    }
+
+    /// Every feature of both versions as a GeoJSON FeatureCollection, each
+    /// with `_layer` and `_diff_action` properties
+    ///
+    /// Features whose geometry or properties changed appear twice: the A
+    /// version as `removed` and the B version as `added`.
+    pub fn to_geojson(&self) -> serde_json::Value {
+        let mut features = Vec::new();
+        for layer in &self.layers {
+            let changed: BTreeSet<(usize, usize)> = layer
+                .matched
+                .iter()
+                .copied()
+                .filter(|&pair| layer.feature_change(pair).is_some())
+                .collect();
+
+            let removed = layer
+                .removed
+                .iter()
+                .copied()
+                .chain(changed.iter().map(|&(i, _)| i));
+            let added = layer
+                .added
+                .iter()
+                .copied()
+                .chain(changed.iter().map(|&(_, j)| j));
+            let unchanged = layer
+                .matched
+                .iter()
+                .filter(|pair| !changed.contains(pair))
+                .map(|&(_, j)| j);
+
+            if let Some(a) = &layer.a {
+                features.extend(annotated(a, removed, Action::Removed));
+            }
+            if let Some(b) = &layer.b {
+                features.extend(annotated(b, added, Action::Added));
+                features.extend(annotated(b, unchanged, Action::Unchanged));
+            }
+        }
+
+        serde_json::json!({
+            "type": "FeatureCollection",
+            "features": features
+        })
+    }
+}
+
+/// GeoJSON features of `layer` at `indices`, tagged with `action`
+fn annotated(
+    layer: &Layer,
+    indices: impl Iterator<Item = usize>,
+    action: Action,
+) -> Vec<serde_json::Value> {
+    let mut subset = Layer {
+        features: indices.map(|i| layer.features[i].clone()).collect(),
+        ..layer.clone()
+    };
+    if subset.features.is_empty() {
+        return Vec::new();
+    }
+
+    let mut features = mvt_layer_features(&mut subset);
+    for feature in &mut features {
+        if let Some(properties) = feature
+            .get_mut("properties")
+            .and_then(|properties| properties.as_object_mut())
+        {
+            properties.insert("_diff_action".to_string(), action.as_str().into());
+        }
+    }
+    features
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sources::TileCompression;
+    use bytes::Bytes;
+    use geozero::mvt::tile::GeomType;
+    use geozero::mvt::{Message, Tile};
+
+    fn encode(layers: Vec<Layer>) -> TileData {
+        TileData {
+            data: Bytes::from(Tile { layers }.encode_to_vec()),
+            format: TileFormat::Pbf,
+            compression: TileCompression::None,
+        }
+    }
+
+    /// Point feature at `(x, y)` with the given id and `name`
+    fn point(id: Option<u64>, x: u32, y: u32, name: u32) -> Feature {
+        Feature {
+            id,
+            tags: vec![0, name],
+            r#type: Some(GeomType::Point as i32),
+            // MoveTo(1), then zigzag-encoded coordinates
+            geometry: vec![9, x << 1, y << 1],
+        }
+    }
+
+    fn layer(name: &str, features: Vec<Feature>) -> Layer {
+        Layer {
+            version: 2,
+            name: name.to_string(),
+            features,
+            keys: vec!["name".to_string()],
+            values: ["Zurich", "Bern", "Basel"]
+                .into_iter()
+                .map(|s| Value {
+                    string_value: Some(s.to_string()),
+                    ..Default::default()
+                })
+                .collect(),
+            extent: Some(4096),
+        }
+    }
+
+    fn sample() -> (TileData, TileData) {
+        let a = encode(vec![
+            layer(
+                "places",
+                vec![
+                    point(Some(1), 10, 10, 0),
+                    point(Some(2), 20, 20, 1),
+                    point(None, 30, 30, 2),
+                    point(None, 40, 40, 2),
+                ],
+            ),
+            layer("water", vec![point(None, 5, 5, 0)]),
+        ]);
+        let b = encode(vec![
+            layer(
+                "places",
+                vec![
+                    // Renamed
+                    point(Some(2), 20, 20, 2),
+                    // Moved
+                    point(Some(1), 11, 10, 0),
+                    point(None, 30, 30, 2),
+                    point(None, 50, 50, 0),
+                ],
+            ),
+            layer("roads", vec![point(None, 5, 5, 1)]),
+        ]);
+        (a, b)
+    }
+
+    #[test]
+    fn test_summary() {
+        let (a, b) = sample();
+        let diff = compare(Some(&a), Some(&b)).unwrap().summary();
+
+        assert!(!diff.identical);
+        assert_eq!(diff.layers_only_in_a, vec!["water"]);
+        assert_eq!(diff.layers_only_in_b, vec!["roads"]);
+        let names: Vec<&str> = diff.layers.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, ["places", "roads", "water"]);
+
+        let places = &diff.layers[0];
+        assert_eq!((places.added, places.removed, places.unchanged), (1, 1, 1));
+        assert_eq!(
+            places.changed,
+            vec![
+                FeatureChange {
+                    id: Some(1),
+                    geometry_changed: true,
+                    properties: BTreeMap::new(),
+                },
+                FeatureChange {
+                    id: Some(2),
+                    geometry_changed: false,
+                    properties: BTreeMap::from([(
+                        "name".to_string(),
+                        PropertyChange {
+                            a: Some("Bern".into()),
+                            b: Some("Basel".into()),
+                        }
+                    )]),
+                },
+            ]
+        );
+
+        let roads = &diff.layers[1];
+        assert_eq!((roads.added, roads.removed, roads.unchanged), (1, 0, 0));
+        let water = &diff.layers[2];
+        assert_eq!((water.added, water.removed, water.unchanged), (0, 1, 0));
+    }
+
+    #[test]
+    fn test_identical_tiles() {
+        let (a, _) = sample();
+        let diff = compare(Some(&a), Some(&a)).unwrap().summary();
+        assert!(diff.identical);
+        assert!(diff.layers_only_in_a.is_empty() && diff.layers_only_in_b.is_empty());
+        assert_eq!(diff.layers[0].unchanged, 4);
+        assert!(diff.layers[0].changed.is_empty());
+    }
+
+    #[test]
+    fn test_missing_tile_compares_as_empty() {
+        let (a, _) = sample();
+        let diff = compare(Some(&a), None).unwrap().summary();
+        assert!(!diff.identical);
+        assert_eq!(diff.layers_only_in_a, vec!["places", "water"]);
+        assert_eq!(diff.layers[0].removed, 4);
+    }
+
+    #[test]
+    fn test_property_change_serialization() {
+        let change = FeatureChange {
+            id: None,
+            geometry_changed: false,
+            properties: BTreeMap::from([(
+                "name".to_string(),
+                PropertyChange {
+                    a: None,
+                    b: Some("Bern".into()),
+                },
+            )]),
+        };
+        assert_eq!(
+            serde_json::to_string(&change).unwrap(),
+            r#"{"geometry_changed":false,"properties":{"name":{"a":null,"b":"Bern"}}}"#
+        );
+    }
+
+    #[test]
+    fn test_geojson() {
+        let (a, b) = sample();
+        let geojson = compare(Some(&a), Some(&b)).unwrap().to_geojson();
+        let features = geojson["features"].as_array().unwrap();
+
+        let count = |layer: &str, action: &str| {
+            features
+                .iter()
+                .filter(|f| {
+                    f["properties"]["_layer"] == layer && f["properties"]["_diff_action"] == action
+                })
+                .count()
+        };
+        // Both changed features count as removed from A and added in B
+        assert_eq!(count("places", "removed"), 3);
+        assert_eq!(count("places", "added"), 3);
+        assert_eq!(count("places", "unchanged"), 1);
+        assert_eq!(count("water", "removed"), 1);
+        assert_eq!(count("roads", "added"), 1);
+        assert_eq!(features.len(), 9);
+    }
+}
@@ -1,9 +1,14 @@
 use axum::{
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
+    Json,
 };
+use serde::Serialize;
+use serde_json::{json, Value};
 use thiserror::Error;
 
+use crate::request_id::RequestId;
+
 #[derive(Error, Debug)]
 pub enum TileServerError {
     #[error("Source not found: {0}")]
@@ -18,6 +23,21 @@ pub enum TileServerError {
     #[error("Invalid tile request format")]
     InvalidTileRequest,
 
+    #[error("Bad request: {0}")]
+    BadRequest(String),
+
+    #[error("Not acceptable: {0}")]
+    NotAcceptable(String),
+
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
+    #[error("Payload too large: {0}")]
+    PayloadTooLarge(String),
+
+    #[error("URI too long: {0}")]
+    UriTooLong(String),
+
     #[error("Style not found: {0}")]
     StyleNotFound(String),
 
@@ -42,9 +62,47 @@ pub enum TileServerError {
     #[error("Render error: {0}")]
     RenderError(String),
 
+    #[error("Rendering not available")]
+    RenderUnavailable,
+
+    #[error("Render queue is full, try again later")]
+    RenderQueueFull,
+
+    #[error("Timed out: {0}")]
+    Timeout(String),
+
+    #[error("Too many open connections, try again later")]
+    TooManyConnections,
+
+    #[error(
+        "Source '{source_id}' is unavailable after repeated failures, retry in {retry_after_secs}s"
+    )]
+    SourceUnavailable {
+        source_id: String,
+        retry_after_secs: u64,
+    },
+
+    #[error(
+        "Tile {z}/{x}/{y} of source '{source_id}' is {bytes} bytes, over the {limit} byte limit"
+    )]
+    TileTooLarge {
+        source_id: String,
+        z: u8,
+        x: u32,
+        y: u32,
+        bytes: u64,
+        limit: u64,
+    },
+
+    #[error("Upstream error: {0}")]
+    UpstreamError(String),
+
     #[error("MBTiles error: {0}")]
     MbTilesError(String),
 
+    #[error("GeoPackage error: {0}")]
+    GeoPackageError(String),
+
     #[cfg(feature = "raster")]
     #[error("Raster error: {0}")]
     RasterError(String),
@@ -65,59 +123,336 @@ pub enum TileServerError {
     Internal(#[from] anyhow::Error),
 }
 
-impl IntoResponse for TileServerError {
-    fn into_response(self) -> Response {
-        let (status, message) = match &self {
-            TileServerError::SourceNotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
-            TileServerError::TileNotFound { .. } => (StatusCode::NOT_FOUND, self.to_string()),
-            TileServerError::InvalidCoordinates { .. } => {
-                (StatusCode::BAD_REQUEST, self.to_string())
-            }
-            TileServerError::InvalidTileRequest => (StatusCode::BAD_REQUEST, self.to_string()),
-            TileServerError::StyleNotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
-            TileServerError::SpriteNotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
-            TileServerError::FontNotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
-            TileServerError::NotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
-            TileServerError::FileError(_) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "File read error".to_string(),
-            ),
-            TileServerError::MetadataError(_) => {
-                (StatusCode::INTERNAL_SERVER_ERROR, self.to_string())
-            }
-            TileServerError::ConfigError(_) => {
-                (StatusCode::INTERNAL_SERVER_ERROR, self.to_string())
-            }
-            TileServerError::RenderError(_) => {
-                (StatusCode::INTERNAL_SERVER_ERROR, self.to_string())
-            }
-            TileServerError::MbTilesError(_) => {
-                (StatusCode::INTERNAL_SERVER_ERROR, self.to_string())
-            }
+/// Stable error codes sent in the `error.code` field of error responses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    /// No source with the requested id (404)
+    SourceNotFound,
+    /// The source has no tile at these coordinates (404)
+    TileNotFound,
+    /// No style with the requested id (404)
+    StyleNotFound,
+    /// No sprite for the style (404)
+    SpriteNotFound,
+    /// None of the fonts of the fontstack exist (404)
+    FontNotFound,
+    /// Any other missing resource (404)
+    NotFound,
+    /// Tile coordinates outside the tile grid (400)
+    InvalidCoordinates,
+    /// Malformed tile path or format (400)
+    InvalidTileRequest,
+    /// Invalid parameters (400)
+    BadRequest,
+    /// None of the media types in the `Accept` header can be served (406)
+    NotAcceptable,
+    /// Missing credentials (401)
+    Unauthorized,
+    /// Wrong credentials, or a feature disabled in the configuration (403)
+    Forbidden,
+    /// The request body is over `max_request_body_bytes` (413)
+    PayloadTooLarge,
+    /// The query string is over `max_query_string_bytes` (414)
+    UriTooLong,
+    /// The server runs without a renderer (503)
+    RenderUnavailable,
+    /// Every renderer is busy and the render queue is full (503)
+    RenderQueueFull,
+    /// The request or render took too long (503)
+    Timeout,
+    /// The server is at `max_connections` (503)
+    TooManyConnections,
+    /// The source's circuit breaker is open after repeated failures (503)
+    SourceUnavailable,
+    /// An upstream tile server failed (502)
+    UpstreamError,
+    /// Rendering or encoding an image or tile failed (500)
+    RenderFailed,
+    /// A tile is over the configured size limit (500)
+    TileTooLarge,
+    /// A source file could not be read (500)
+    FileError,
+    /// Source metadata could not be parsed (500)
+    MetadataError,
+    /// The server configuration is invalid (500)
+    ConfigError,
+    /// An MBTiles database query failed (500)
+    MbtilesError,
+    /// Reading a GeoPackage failed (500)
+    GeopackageError,
+    /// Reading a raster source failed (500)
+    RasterError,
+    /// A PostgreSQL query or connection failed (500)
+    PostgresError,
+    /// Any other server error (500)
+    InternalError,
+}
+
+impl ErrorCode {
+    /// HTTP status sent with this code
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::SourceNotFound
+            | ErrorCode::TileNotFound
+            | ErrorCode::StyleNotFound
+            | ErrorCode::SpriteNotFound
+            | ErrorCode::FontNotFound
+            | ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::InvalidCoordinates
+            | ErrorCode::InvalidTileRequest
+            | ErrorCode::BadRequest => StatusCode::BAD_REQUEST,
+            ErrorCode::NotAcceptable => StatusCode::NOT_ACCEPTABLE,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::Forbidden => StatusCode::FORBIDDEN,
+            ErrorCode::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorCode::UriTooLong => StatusCode::URI_TOO_LONG,
+            ErrorCode::RenderUnavailable
+            | ErrorCode::RenderQueueFull
+            | ErrorCode::Timeout
+            | ErrorCode::TooManyConnections
+            | ErrorCode::SourceUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::UpstreamError => StatusCode::BAD_GATEWAY,
+            ErrorCode::RenderFailed
+            | ErrorCode::TileTooLarge
+            | ErrorCode::FileError
+            | ErrorCode::MetadataError
+            | ErrorCode::ConfigError
+            | ErrorCode::MbtilesError
+            | ErrorCode::GeopackageError
+            | ErrorCode::RasterError
+            | ErrorCode::PostgresError
+            | ErrorCode::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl TileServerError {
+    /// Error code sent to clients
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            TileServerError::SourceNotFound(_) => ErrorCode::SourceNotFound,
+            TileServerError::TileNotFound { .. } => ErrorCode::TileNotFound,
+            TileServerError::InvalidCoordinates { .. } => ErrorCode::InvalidCoordinates,
+            TileServerError::InvalidTileRequest => ErrorCode::InvalidTileRequest,
+            TileServerError::BadRequest(_) => ErrorCode::BadRequest,
+            TileServerError::NotAcceptable(_) => ErrorCode::NotAcceptable,
+            TileServerError::Forbidden(_) => ErrorCode::Forbidden,
+            TileServerError::PayloadTooLarge(_) => ErrorCode::PayloadTooLarge,
+            TileServerError::UriTooLong(_) => ErrorCode::UriTooLong,
+            TileServerError::StyleNotFound(_) => ErrorCode::StyleNotFound,
+            TileServerError::SpriteNotFound(_) => ErrorCode::SpriteNotFound,
+            TileServerError::FontNotFound(_) => ErrorCode::FontNotFound,
+            TileServerError::NotFound(_) => ErrorCode::NotFound,
+            TileServerError::FileError(_) => ErrorCode::FileError,
+            TileServerError::MetadataError(_) => ErrorCode::MetadataError,
+            TileServerError::ConfigError(_) => ErrorCode::ConfigError,
+            TileServerError::RenderError(_) => ErrorCode::RenderFailed,
+            TileServerError::RenderUnavailable => ErrorCode::RenderUnavailable,
+            TileServerError::RenderQueueFull => ErrorCode::RenderQueueFull,
+            TileServerError::Timeout(_) => ErrorCode::Timeout,
+            TileServerError::TooManyConnections => ErrorCode::TooManyConnections,
+            TileServerError::SourceUnavailable { .. } => ErrorCode::SourceUnavailable,
+            TileServerError::TileTooLarge { .. } => ErrorCode::TileTooLarge,
+            TileServerError::UpstreamError(_) => ErrorCode::UpstreamError,
+            TileServerError::MbTilesError(_) => ErrorCode::MbtilesError,
+            TileServerError::GeoPackageError(_) => ErrorCode::GeopackageError,
             #[cfg(feature = "raster")]
-            TileServerError::RasterError(_) => {
-                (StatusCode::INTERNAL_SERVER_ERROR, self.to_string())
-            }
-            #[cfg(feature = "postgres")]
-            TileServerError::PostgresError(_) => {
-                (StatusCode::INTERNAL_SERVER_ERROR, self.to_string())
-            }
+            TileServerError::RasterError(_) => ErrorCode::RasterError,
             #[cfg(feature = "postgres")]
-            TileServerError::PostgresPoolError(_) => {
-                (StatusCode::INTERNAL_SERVER_ERROR, self.to_string())
-            }
-            #[cfg(feature = "postgres")]
-            TileServerError::PostgresVersionError(_) => {
-                (StatusCode::INTERNAL_SERVER_ERROR, self.to_string())
+            TileServerError::PostgresError(_)
+            | TileServerError::PostgresPoolError(_)
+            | TileServerError::PostgresVersionError(_) => ErrorCode::PostgresError,
+            TileServerError::Internal(_) => ErrorCode::InternalError,
+        }
+    }
+
+    /// HTTP status of the error response
+    pub fn status(&self) -> StatusCode {
+        self.code().status()
+    }
+
+    /// Message sent to clients, without filesystem paths in release builds
+    fn public_message(&self) -> String {
+        let message = match self {
+            TileServerError::FileError(_) => "File read error".to_string(),
+            TileServerError::Internal(_) => "Internal server error".to_string(),
+            _ => self.to_string(),
+        };
+        if cfg!(debug_assertions) {
+            message
+        } else {
+            redact_paths(&message)
+        }
+    }
+
+    /// Fields identifying what the error is about
+    pub(crate) fn details(&self) -> Option<Value> {
+        match self {
+            TileServerError::SourceNotFound(source) => Some(json!({ "source": source })),
+            TileServerError::StyleNotFound(style) => Some(json!({ "style": style })),
+            TileServerError::TileNotFound { z, x, y } => Some(json!({ "z": z, "x": x, "y": y })),
+            TileServerError::InvalidCoordinates { z, x, y } => {
+                let mut details = json!({ "z": z, "x": x, "y": y });
+                if let Some((coordinate, value, max)) =
+                    crate::sources::out_of_range_coordinate(*z, *x, *y)
+                {
+                    details["coordinate"] = coordinate.into();
+                    details["value"] = value.into();
+                    details["max"] = max.into();
+                }
+                Some(details)
             }
-            TileServerError::Internal(_) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Internal server error".to_string(),
-            ),
+            TileServerError::SourceUnavailable {
+                source_id,
+                retry_after_secs,
+            } => Some(json!({ "source": source_id, "retry_after": retry_after_secs })),
+            TileServerError::TileTooLarge {
+                source_id,
+                z,
+                x,
+                y,
+                bytes,
+                limit,
+            } => Some(json!({
+                "source": source_id,
+                "z": z,
+                "x": x,
+                "y": y,
+                "bytes": bytes,
+                "limit": limit,
+            })),
+            _ => None,
+        }
+    }
+}
+
+impl IntoResponse for TileServerError {
+    fn into_response(self) -> Response {
+        let code = self.code();
+        let body = error_body(code, &self.public_message(), self.details());
+        let mut response = (code.status(), Json(body)).into_response();
+
+        // These clear up on their own; tell clients when to come back
+        let retry_after = match self {
+            TileServerError::SourceUnavailable {
+                retry_after_secs, ..
+            } => Some(retry_after_secs),
+            TileServerError::Timeout(_)
+            | TileServerError::RenderQueueFull
+            | TileServerError::TooManyConnections => Some(1),
+            _ => None,
         };
+        if let Some(secs) = retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, header::HeaderValue::from(secs));
+        }
+        response
+    }
+}
 
-        (status, message).into_response()
+/// Error response body: `{"error": {"code", "message", "details", "request_id"}}`
+///
+/// `details` is left out when there are none, and `request_id` outside of a
+/// request.
+pub fn error_body(code: ErrorCode, message: &str, details: Option<Value>) -> Value {
+    let mut error = json!({ "code": code, "message": message });
+    if let Some(details) = details {
+        error["details"] = details;
     }
+    if let Some(id) = RequestId::current() {
+        error["request_id"] = id.0.into();
+    }
+    json!({ "error": error })
+}
+
+/// Replace filesystem paths in an error message with `<path>`
+///
+/// Absolute, home and relative (`./`, `../`) paths are replaced, along with
+/// Windows drive paths; URLs are kept.
+pub fn redact_paths(message: &str) -> String {
+    message
+        .split(' ')
+        .map(|word| {
+            let core = word.trim_start_matches(['"', '\'', '(', '[']);
+            let core = core.trim_end_matches(['"', '\'', ')', ']', ',', ':', ';', '.']);
+            if core.is_empty() || !is_path(core) {
+                return word.to_string();
+            }
+            word.replacen(core, "<path>", 1)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn is_path(word: &str) -> bool {
+    let bytes = word.as_bytes();
+    let absolute = word.starts_with('/') && word.len() > 1 && !word.starts_with("//");
+    let relative = ["./", "../", "~/"].iter().any(|p| word.starts_with(p));
+    let windows = bytes.len() > 2
+        && bytes[0].is_ascii_alphabetic()
+        && bytes[1] == b':'
+        && (bytes[2] == b'\\' || bytes[2] == b'/');
+    absolute || relative || windows
 }
 
 pub type Result<T> = std::result::Result<T, TileServerError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_paths() {
+        assert_eq!(
+            redact_paths("Failed to open COG file: /data/dem.tif: No such file"),
+            "Failed to open COG file: <path>: No such file"
+        );
+        assert_eq!(
+            redact_paths("MBTiles file not found: '../tiles/world.mbtiles'"),
+            "MBTiles file not found: '<path>'"
+        );
+        assert_eq!(
+            redact_paths("Cannot read C:\\tiles\\a.pmtiles"),
+            "Cannot read <path>"
+        );
+        // URLs and plain messages are kept
+        assert_eq!(
+            redact_paths("Upstream error: https://tiles.example.com/1/2/3.pbf returned 500"),
+            "Upstream error: https://tiles.example.com/1/2/3.pbf returned 500"
+        );
+        assert_eq!(
+            redact_paths("Invalid tile size: 300. Only 256 and 512 are supported."),
+            "Invalid tile size: 300. Only 256 and 512 are supported."
+        );
+    }
+
+    #[test]
+    fn test_status_mapping() {
+        assert_eq!(
+            TileServerError::StyleNotFound("x".into()).status(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            TileServerError::InvalidTileRequest.status(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            TileServerError::RenderUnavailable.status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(
+            TileServerError::UriTooLong("x".into()).status(),
+            StatusCode::URI_TOO_LONG
+        );
+        assert_eq!(
+            TileServerError::RenderError("boom".into()).status(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+        assert_eq!(
+            serde_json::to_value(ErrorCode::MbtilesError).unwrap(),
+            "MBTILES_ERROR"
+        );
+    }
+}
@@ -0,0 +1,186 @@
+//! Server-sent events (`GET /events`)
+//!
+//! Clients such as map editors subscribe to learn when data changes under
+//! them: a source or style reloaded by the admin API or `--watch`, or a
+//! cached tile dropped through the admin API. A `heartbeat` is sent every 30
+//! seconds so proxies don't close idle connections.
+
+use axum::{
+    extract::State,
+    response::sse::{self, Sse},
+    routing::get,
+    Router,
+};
+use futures::Stream;
+use serde::Serialize;
+use std::convert::Infallible;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+
+/// Interval between `heartbeat` events
+pub const HEARTBEAT: Duration = Duration::from_secs(30);
+
+/// Events buffered per subscriber before slow ones start missing events
+const CAPACITY: usize = 256;
+
+/// A change clients may want to react to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// A tile must be fetched again (`y` in XYZ, as in tile URLs)
+    TileInvalidated {
+        source: String,
+        z: u8,
+        x: u32,
+        y: u32,
+    },
+    /// Every tile of a source may have changed
+    SourceReloaded {
+        source: String,
+    },
+    StyleReloaded {
+        style: String,
+    },
+    /// Unix timestamp (seconds) of when the heartbeat was sent
+    Heartbeat {
+        time: u64,
+    },
+}
+
+impl Event {
+    /// SSE event name, matching the `type` field of the payload
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::TileInvalidated { .. } => "tile_invalidated",
+            Event::SourceReloaded { .. } => "source_reloaded",
+            Event::StyleReloaded { .. } => "style_reloaded",
+            Event::Heartbeat { .. } => "heartbeat",
+        }
+    }
+
+    fn heartbeat() -> Self {
+        Event::Heartbeat {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        }
+    }
+}
+
+/// Broadcasts events to every `/events` subscriber
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CAPACITY);
+        Self { sender }
+    }
+
+    /// Publish `event`; it is dropped when nobody is subscribed
+    pub fn send(&self, event: Event) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+
+    /// Events published from now on, with a heartbeat every `heartbeat`
+    pub fn stream(&self, heartbeat: Duration) -> impl Stream<Item = Event> {
+        let interval = tokio::time::interval_at(tokio::time::Instant::now() + heartbeat, heartbeat);
+
+        futures::stream::unfold(
+            (self.subscribe(), interval),
+            |(mut receiver, mut interval)| async move {
+                loop {
+                    tokio::select! {
+                        _ = interval.tick() => {
+                            return Some((Event::heartbeat(), (receiver, interval)));
+                        }
+                        event = receiver.recv() => match event {
+                            Ok(event) => return Some((event, (receiver, interval))),
+                            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                                tracing::warn!("Event subscriber missed {} event(s)", missed);
+                            }
+                            Err(broadcast::error::RecvError::Closed) => return None,
+                        },
+                    }
+                }
+            },
+        )
+    }
+}
+
+/// Router serving `GET /events`
+pub fn router<S>(events: EventBus) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .route("/events", get(subscribe))
+        .with_state(events)
+}
+
+async fn subscribe(
+    State(events): State<EventBus>,
+) -> Sse<impl Stream<Item = Result<sse::Event, Infallible>>> {
+    use futures::StreamExt;
+
+    Sse::new(events.stream(HEARTBEAT).map(|event| {
+        Ok(sse::Event::default()
+            .event(event.name())
+            .data(serde_json::to_string(&event).unwrap_or_default()))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    #[test]
+    fn test_event_payload() {
+        let event = Event::TileInvalidated {
+            source: "osm".to_string(),
+            z: 3,
+            x: 4,
+            y: 2,
+        };
+        assert_eq!(event.name(), "tile_invalidated");
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"type":"tile_invalidated","source":"osm","z":3,"x":4,"y":2}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_receives_events_and_heartbeats() {
+        let bus = EventBus::new();
+        let mut stream = Box::pin(bus.stream(Duration::from_millis(100)));
+
+        bus.send(Event::StyleReloaded {
+            style: "basic".to_string(),
+        });
+        assert_eq!(
+            stream.next().await,
+            Some(Event::StyleReloaded {
+                style: "basic".to_string()
+            })
+        );
+
+        let next = tokio::time::timeout(Duration::from_secs(1), stream.next())
+            .await
+            .unwrap();
+        assert_eq!(next.map(|e| e.name()), Some("heartbeat"));
+    }
+}
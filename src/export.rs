@@ -0,0 +1,370 @@
+//! MBTiles downloads of a source for `GET /data/{source}/tiles.mbtiles`
+//!
+//! The requested zoom levels and bounds are written to a temporary SQLite
+//! file, which is then streamed to the client. Tiles are copied as stored,
+//! compression included, so nothing is decoded or re-encoded. Exports are
+//! capped by `[export] max_tiles`, estimated from the tile grid before any
+//! tile is read.
+
+use futures::{stream, StreamExt};
+use rusqlite::Connection;
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::config::ExportConfig;
+use crate::error::{Result, TileServerError};
+use crate::inspect::tile_range;
+use crate::sources::{TileMetadata, TileRange, TileSource};
+
+/// Tiles read at once
+const CONCURRENCY: usize = 16;
+
+/// Tiles inserted per transaction
+const BATCH_SIZE: usize = 512;
+
+/// Query parameters of an export
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ExportQuery {
+    pub min_zoom: Option<u8>,
+    pub max_zoom: Option<u8>,
+    /// `west,south,east,north` in degrees
+    pub bounds: Option<String>,
+}
+
+/// Tiles to export: the grid cells covering the bounds at each zoom level
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportPlan {
+    pub min_zoom: u8,
+    pub max_zoom: u8,
+    pub bounds: [f64; 4],
+    /// Ranges with XYZ rows, one per zoom level
+    pub ranges: Vec<TileRange>,
+}
+
+impl ExportPlan {
+    /// Number of grid cells covered, an upper bound on the tiles exported
+    pub fn estimated_tiles(&self) -> u64 {
+        self.ranges.iter().map(range_size).sum()
+    }
+}
+
+fn range_size(range: &TileRange) -> u64 {
+    u64::from(range.max_x - range.min_x + 1) * u64::from(range.max_y - range.min_y + 1)
+}
+
+/// Resolve an export request against the source's zoom levels and bounds
+///
+/// Fails with a bad request if the parameters are invalid or the export
+/// would cover more than `config.max_tiles` tiles.
+pub fn plan(
+    metadata: &TileMetadata,
+    query: &ExportQuery,
+    config: &ExportConfig,
+) -> Result<ExportPlan> {
+    let min_zoom = query
+        .min_zoom
+        .unwrap_or(metadata.minzoom)
+        .max(metadata.minzoom);
+    let max_zoom = query
+        .max_zoom
+        .unwrap_or(metadata.maxzoom)
+        .min(metadata.maxzoom);
+    if min_zoom > max_zoom {
+        return Err(TileServerError::BadRequest(format!(
+            "No zoom levels to export between {} and {}; the source has {} to {}",
+            query.min_zoom.unwrap_or(metadata.minzoom),
+            query.max_zoom.unwrap_or(metadata.maxzoom),
+            metadata.minzoom,
+            metadata.maxzoom
+        )));
+    }
+
+    let source_bounds = metadata
+        .bounds
+        .unwrap_or([-180.0, -85.051129, 180.0, 85.051129]);
+    let bounds = match query.bounds.as_deref() {
+        Some(bounds) => intersect(parse_bounds(bounds)?, source_bounds).ok_or_else(|| {
+            TileServerError::BadRequest("Bounds are outside of the source".to_string())
+        })?,
+        None => source_bounds,
+    };
+
+    let plan = ExportPlan {
+        min_zoom,
+        max_zoom,
+        bounds,
+        ranges: (min_zoom..=max_zoom)
+            .map(|z| tile_range(z, Some(bounds)))
+            .collect(),
+    };
+    let estimated = plan.estimated_tiles();
+    if estimated > config.max_tiles {
+        return Err(TileServerError::BadRequest(format!(
+            "Export would contain up to {} tiles, more than the limit of {}; \
+             narrow the zoom levels or bounds",
+            estimated, config.max_tiles
+        )));
+    }
+    Ok(plan)
+}
+
+fn parse_bounds(bounds: &str) -> Result<[f64; 4]> {
+    let invalid = || {
+        TileServerError::BadRequest(format!(
+            "Invalid bounds '{}', expected west,south,east,north",
+            bounds
+        ))
+    };
+    let values: Vec<f64> = bounds
+        .split(',')
+        .map(|v| v.trim().parse::<f64>().map_err(|_| invalid()))
+        .collect::<Result<_>>()?;
+    let [west, south, east, north] = values[..] else {
+        return Err(invalid());
+    };
+    if !(-180.0..=180.0).contains(&west)
+        || !(-180.0..=180.0).contains(&east)
+        || !(-90.0..=90.0).contains(&south)
+        || !(-90.0..=90.0).contains(&north)
+        || west >= east
+        || south >= north
+    {
+        return Err(invalid());
+    }
+    Ok([west, south, east, north])
+}
+
+fn intersect(a: [f64; 4], b: [f64; 4]) -> Option<[f64; 4]> {
+    let bounds = [
+        a[0].max(b[0]),
+        a[1].max(b[1]),
+        a[2].min(b[2]),
+        a[3].min(b[3]),
+    ];
+    (bounds[0] < bounds[2] && bounds[1] < bounds[3]).then_some(bounds)
+}
+
+/// Write the tiles of `plan` to a new MBTiles file at `path`
+///
+/// Returns the number of tiles written. The file is built in WAL mode and
+/// checkpointed at the end, so it can be copied on its own.
+pub async fn write_mbtiles(source: &dyn TileSource, plan: &ExportPlan, path: &Path) -> Result<u64> {
+    let metadata = source.metadata().clone();
+    let conn = {
+        let path = path.to_path_buf();
+        let plan = plan.clone();
+        blocking(move || create(&path, &metadata, &plan)).await?
+    };
+
+    let coords = plan.ranges.clone().into_iter().flat_map(|range| {
+        (range.min_x..=range.max_x)
+            .flat_map(move |x| (range.min_y..=range.max_y).map(move |y| (range.z, x, y)))
+    });
+    let mut tiles = stream::iter(coords)
+        .map(|(z, x, y)| async move {
+            let tile = source.get_xyz_tile(z, x, y).await?;
+            Ok::<_, TileServerError>(tile.map(|tile| (z, x, y, tile.data)))
+        })
+        .buffered(CONCURRENCY)
+        .ready_chunks(BATCH_SIZE);
+
+    let mut conn = conn;
+    let mut written = 0;
+    while let Some(batch) = tiles.next().await {
+        let batch: Vec<_> = batch
+            .into_iter()
+            .filter_map(|tile| tile.transpose())
+            .collect::<Result<_>>()?;
+        written += batch.len() as u64;
+        conn = blocking(move || {
+            let tx = conn.unchecked_transaction()?;
+            {
+                let mut insert = tx.prepare_cached(
+                    "INSERT INTO tiles (zoom_level, tile_column, tile_row, tile_data) \
+                     VALUES (?1, ?2, ?3, ?4)",
+                )?;
+                for (z, x, y, data) in &batch {
+                    // MBTiles stores rows bottom-up (TMS)
+                    let tms_y = (1u32 << z) - 1 - y;
+                    insert.execute(rusqlite::params![z, x, tms_y, data.as_ref()])?;
+                }
+            }
+            tx.commit()?;
+            Ok(conn)
+        })
+        .await?;
+    }
+
+    blocking(move || {
+        conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE); PRAGMA journal_mode = DELETE;")?;
+        conn.close().map_err(|(_, e)| e)
+    })
+    .await?;
+    Ok(written)
+}
+
+/// Create the MBTiles schema and fill in the metadata table
+fn create(path: &Path, metadata: &TileMetadata, plan: &ExportPlan) -> rusqlite::Result<Connection> {
+    let conn = Connection::open(path)?;
+    conn.execute_batch(
+        "PRAGMA journal_mode = WAL;
+         PRAGMA synchronous = OFF;
+         CREATE TABLE metadata (name TEXT NOT NULL, value TEXT);
+         CREATE TABLE tiles (
+             zoom_level INTEGER NOT NULL,
+             tile_column INTEGER NOT NULL,
+             tile_row INTEGER NOT NULL,
+             tile_data BLOB
+         );
+         CREATE UNIQUE INDEX tile_index ON tiles (zoom_level, tile_column, tile_row);",
+    )?;
+
+    let [west, south, east, north] = plan.bounds;
+    let center = metadata
+        .center
+        .filter(|[lon, lat, _]| (west..=east).contains(lon) && (south..=north).contains(lat))
+        .map(|[lon, lat, zoom]| {
+            [
+                lon,
+                lat,
+                zoom.clamp(plan.min_zoom as f64, plan.max_zoom as f64),
+            ]
+        })
+        .unwrap_or([
+            (west + east) / 2.0,
+            (south + north) / 2.0,
+            plan.min_zoom as f64,
+        ]);
+    let mut entries = vec![
+        ("name", metadata.name.clone()),
+        ("format", metadata.format.extension().to_string()),
+        ("minzoom", plan.min_zoom.to_string()),
+        ("maxzoom", plan.max_zoom.to_string()),
+        ("bounds", format!("{},{},{},{}", west, south, east, north)),
+        (
+            "center",
+            format!("{},{},{}", center[0], center[1], center[2]),
+        ),
+    ];
+    if let Some(description) = &metadata.description {
+        entries.push(("description", description.clone()));
+    }
+    if let Some(attribution) = &metadata.attribution {
+        entries.push(("attribution", attribution.clone()));
+    }
+    if let Some(vector_layers) = &metadata.vector_layers {
+        entries.push((
+            "json",
+            serde_json::json!({ "vector_layers": vector_layers }).to_string(),
+        ));
+    }
+
+    let mut insert = conn.prepare("INSERT INTO metadata (name, value) VALUES (?1, ?2)")?;
+    for (name, value) in entries {
+        insert.execute([name, value.as_str()])?;
+    }
+    drop(insert);
+    Ok(conn)
+}
+
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> rusqlite::Result<T> + Send + 'static,
+) -> Result<T> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| TileServerError::MbTilesError(format!("Task join error: {}", e)))?
+        .map_err(|e| TileServerError::MbTilesError(format!("Export failed: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sources::{TileFormat, TileScheme};
+
+    fn metadata() -> TileMetadata {
+        TileMetadata {
+            id: "test".to_string(),
+            name: "Test".to_string(),
+            description: None,
+            attribution: None,
+            format: TileFormat::Pbf,
+            minzoom: 2,
+            maxzoom: 10,
+            bounds: Some([8.0, 47.0, 9.0, 48.0]),
+            center: None,
+            vector_layers: None,
+            scheme: TileScheme::Xyz,
+            overzoom: false,
+            encoding: None,
+        }
+    }
+
+    #[test]
+    fn test_plan_clamps_to_source() {
+        let config = ExportConfig::default();
+        let query = ExportQuery {
+            min_zoom: Some(0),
+            max_zoom: Some(4),
+            bounds: Some("0,40,8.5,50".to_string()),
+        };
+        let plan = plan(&metadata(), &query, &config).unwrap();
+
+        assert_eq!((plan.min_zoom, plan.max_zoom), (2, 4));
+        assert_eq!(plan.bounds, [8.0, 47.0, 8.5, 48.0]);
+        assert_eq!(plan.ranges.len(), 3);
+        assert_eq!(plan.ranges[0].z, 2);
+    }
+
+    #[test]
+    fn test_plan_rejects_invalid_requests() {
+        let config = ExportConfig::default();
+        for (min_zoom, max_zoom, bounds) in [
+            (Some(11), None, None),
+            (Some(5), Some(4), None),
+            (None, None, Some("1,2,3")),
+            (None, None, Some("9,47,8,48")),
+            (None, None, Some("a,b,c,d")),
+            (None, None, Some("-10,0,-5,5")),
+        ] {
+            let query = ExportQuery {
+                min_zoom,
+                max_zoom,
+                bounds: bounds.map(str::to_string),
+            };
+            assert!(
+                matches!(
+                    plan(&metadata(), &query, &config),
+                    Err(TileServerError::BadRequest(_))
+                ),
+                "{:?}",
+                query
+            );
+        }
+    }
+
+    #[test]
+    fn test_plan_enforces_limit() {
+        let world = TileMetadata {
+            bounds: None,
+            ..metadata()
+        };
+        let query = ExportQuery::default();
+        let config = ExportConfig { max_tiles: 1000 };
+        let Err(TileServerError::BadRequest(message)) = plan(&world, &query, &config) else {
+            panic!("Export over the limit should be rejected");
+        };
+        assert!(
+            message.contains("more than the limit of 1000"),
+            "{}",
+            message
+        );
+
+        // Zoom 2 to 4 of the world: 16 + 64 + 256 tiles
+        let query = ExportQuery {
+            max_zoom: Some(4),
+            ..query
+        };
+        let plan = plan(&world, &query, &config).unwrap();
+        assert_eq!(plan.estimated_tiles(), 336);
+    }
+}
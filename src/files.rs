@@ -0,0 +1,140 @@
+//! Static files from `files_dir` (`GET /files/{*filepath}`)
+//!
+//! Files are streamed from disk rather than read into memory, so large
+//! GeoJSON or MBTiles downloads don't exhaust memory. A single byte range
+//! (`Range: bytes=start-end`) is answered with `206 Partial Content`, which
+//! lets clients resume downloads and seek in media files. `If-Range` is
+//! checked against the file's `ETag` and `Last-Modified`, so a resumed
+//! download of a replaced file starts over.
+
+use axum::{
+    body::Body,
+    http::{
+        header::{
+            ACCEPT_RANGES, CACHE_CONTROL, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG,
+            IF_RANGE, LAST_MODIFIED, RANGE,
+        },
+        HeaderMap, HeaderValue, StatusCode,
+    },
+    response::{IntoResponse, Response},
+};
+use std::io::SeekFrom;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+use crate::error::{Result, TileServerError};
+use crate::range::{if_range_matches, ByteRange};
+
+/// Read size when streaming a file
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Serve `filepath` from `files_dir`, honouring a `Range` request header
+pub async fn serve(
+    files_dir: &Path,
+    filepath: &str,
+    request_headers: &HeaderMap,
+) -> Result<Response> {
+    // Sanitize the filepath to prevent directory traversal attacks
+    let filepath = filepath.trim_start_matches('/');
+    if filepath.contains("..") || filepath.starts_with('/') {
+        return Err(TileServerError::NotFound("Invalid file path".to_string()));
+    }
+
+    let file_path = files_dir.join(filepath);
+
+    // Ensure the resolved path is still within the files directory
+    let canonical_files_dir = files_dir
+        .canonicalize()
+        .map_err(|_| TileServerError::NotFound("Files directory not accessible".to_string()))?;
+    let canonical_file_path = file_path
+        .canonicalize()
+        .map_err(|_| TileServerError::NotFound(format!("File not found: {}", filepath)))?;
+
+    if !canonical_file_path.starts_with(&canonical_files_dir) {
+        return Err(TileServerError::NotFound("Invalid file path".to_string()));
+    }
+
+    let not_found = || TileServerError::NotFound(format!("File not found: {}", filepath));
+    let mut file = tokio::fs::File::open(&canonical_file_path)
+        .await
+        .map_err(|_| not_found())?;
+    let metadata = file.metadata().await.map_err(|_| not_found())?;
+    if !metadata.is_file() {
+        return Err(not_found());
+    }
+    let len = metadata.len();
+
+    // Determine content type from extension
+    let content_type = mime_guess::from_path(&canonical_file_path)
+        .first_or_octet_stream()
+        .to_string();
+
+    let mut headers = HeaderMap::new();
+    headers.insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+
+    // Validators for If-Range, from the modification time and size
+    let modified = metadata.modified().ok();
+    let etag = modified.map(|modified| {
+        let secs = modified
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        format!("\"{:x}-{:x}\"", secs, len)
+    });
+    if let Some(value) = etag.as_deref().and_then(|e| HeaderValue::from_str(e).ok()) {
+        headers.insert(ETAG, value);
+    }
+    if let Some(modified) = modified {
+        if let Ok(value) = HeaderValue::from_str(&httpdate::fmt_http_date(modified)) {
+            headers.insert(LAST_MODIFIED, value);
+        }
+    }
+
+    let range = if_range_matches(request_headers.get(IF_RANGE), etag.as_deref(), modified)
+        .then(|| request_headers.get(RANGE).and_then(|h| h.to_str().ok()))
+        .flatten();
+    let range = ByteRange::parse(range, len);
+    let (status, start, end) = match range {
+        ByteRange::Full => (StatusCode::OK, 0, len.saturating_sub(1)),
+        ByteRange::Partial { start, end } => {
+            headers.insert(
+                CONTENT_RANGE,
+                HeaderValue::from_str(&format!("bytes {}-{}/{}", start, end, len))
+                    .expect("Content-Range is ASCII"),
+            );
+            (StatusCode::PARTIAL_CONTENT, start, end)
+        }
+        ByteRange::Unsatisfiable => {
+            headers.insert(
+                CONTENT_RANGE,
+                HeaderValue::from_str(&format!("bytes */{}", len)).expect("Content-Range is ASCII"),
+            );
+            return Ok((StatusCode::RANGE_NOT_SATISFIABLE, headers).into_response());
+        }
+    };
+    let body_len = if len == 0 { 0 } else { end - start + 1 };
+
+    if start > 0 {
+        file.seek(SeekFrom::Start(start)).await?;
+    }
+    let body = Body::from_stream(ReaderStream::with_capacity(
+        file.take(body_len),
+        BUFFER_SIZE,
+    ));
+
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_str(&content_type)
+            .unwrap_or(HeaderValue::from_static("application/octet-stream")),
+    );
+    headers.insert(CONTENT_LENGTH, HeaderValue::from(body_len));
+    // Cache static files for 1 hour
+    headers.insert(
+        CACHE_CONTROL,
+        HeaderValue::from_static("public, max-age=3600"),
+    );
+
+    Ok((status, headers, body).into_response())
+}
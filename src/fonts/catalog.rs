@@ -0,0 +1,193 @@
+//! Fonts found as `.ttf`, `.otf` and `.ttc` files in the fonts directory
+//!
+//! Each face is named "Family Style" (e.g. `Noto Sans Regular`), the form
+//! `text-font` uses, and its glyph ranges are generated on first request.
+
+use bytes::Bytes;
+use prost::Message;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use ttf_parser::{name_id, Face};
+
+use super::sdf::render_glyph;
+use super::{Fontstack, GlyphCache, Glyphs};
+
+/// A face within a font file
+struct FontFace {
+    path: PathBuf,
+    /// Shared by the faces of a collection
+    data: Arc<Vec<u8>>,
+    index: u32,
+}
+
+/// Font files available for glyph generation
+#[derive(Default)]
+pub struct FontCatalog {
+    faces: BTreeMap<String, FontFace>,
+    /// Generated ranges, keyed by font name and range
+    generated: GlyphCache,
+}
+
+impl std::fmt::Debug for FontCatalog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FontCatalog")
+            .field("fonts", &self.faces.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl FontCatalog {
+    /// Find the font files in `dir` and its subdirectories
+    ///
+    /// Files that can't be parsed are logged and skipped. When two faces have
+    /// the same name, the first found wins.
+    pub fn scan(dir: &Path) -> Self {
+        let mut files = Vec::new();
+        find_font_files(dir, &mut files);
+        files.sort();
+
+        let mut catalog = Self::default();
+        for path in files {
+            if let Err(e) = catalog.add_file(&path) {
+                tracing::warn!("Skipping font {}: {}", path.display(), e);
+            }
+        }
+        catalog
+    }
+
+    fn add_file(&mut self, path: &Path) -> std::result::Result<(), String> {
+        let data = Arc::new(std::fs::read(path).map_err(|e| e.to_string())?);
+        let count = ttf_parser::fonts_in_collection(&data).unwrap_or(1);
+
+        for index in 0..count {
+            let face = Face::parse(&data, index).map_err(|e| e.to_string())?;
+            let Some(name) = font_name(&face) else {
+                return Err("font has no family name".to_string());
+            };
+            if let Some(existing) = self.faces.get(&name) {
+                tracing::warn!(
+                    "Font '{}' in {} is already provided by {}",
+                    name,
+                    path.display(),
+                    existing.path.display()
+                );
+                continue;
+            }
+            tracing::debug!("Found font: {} ({})", name, path.display());
+            self.faces.insert(
+                name,
+                FontFace {
+                    path: path.to_path_buf(),
+                    data: data.clone(),
+                    index,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// Names of the fonts found, sorted
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.faces.keys().map(String::as_str)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.faces.contains_key(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.faces.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.faces.is_empty()
+    }
+
+    /// Glyph PBF of `font` for `range` (e.g. `0-255`), generated on first use
+    ///
+    /// `None` if the font is unknown or the range isn't a 256-codepoint block.
+    pub fn glyphs(&self, font: &str, range: &str) -> Option<Bytes> {
+        let face = self.faces.get(font)?;
+        let start = range_start(range)?;
+
+        let key = (font.to_string(), range.to_string());
+        if let Some(data) = self.generated.get(&key) {
+            return Some(data);
+        }
+
+        let parsed = Face::parse(&face.data, face.index).ok()?;
+        let glyphs = Glyphs {
+            stacks: vec![Fontstack {
+                name: font.to_string(),
+                range: range.to_string(),
+                glyphs: (start..start + 256)
+                    .filter_map(|codepoint| render_glyph(&parsed, codepoint))
+                    .collect(),
+            }],
+        };
+        let data = Bytes::from(glyphs.encode_to_vec());
+        self.generated.insert(key, data.clone());
+        Some(data)
+    }
+}
+
+/// "Family Style", preferring the typographic names that group more than the
+/// four classic styles into one family
+fn font_name(face: &Face) -> Option<String> {
+    let name = |ids: [u16; 2]| {
+        ids.iter().find_map(|&id| {
+            face.names()
+                .into_iter()
+                .filter(|name| name.name_id == id && name.is_unicode())
+                .find_map(|name| name.to_string())
+                .filter(|name| !name.trim().is_empty())
+        })
+    };
+
+    let family = name([name_id::TYPOGRAPHIC_FAMILY, name_id::FAMILY])?;
+    match name([name_id::TYPOGRAPHIC_SUBFAMILY, name_id::SUBFAMILY]) {
+        Some(style) => Some(format!("{} {}", family.trim(), style.trim())),
+        None => Some(family.trim().to_string()),
+    }
+}
+
+/// First codepoint of a range such as `256-511`
+fn range_start(range: &str) -> Option<u32> {
+    let (start, end) = range.split_once('-')?;
+    let (start, end): (u32, u32) = (start.parse().ok()?, end.parse().ok()?);
+    (start % 256 == 0 && end == start + 255 && end <= 0xFFFF).then_some(start)
+}
+
+fn find_font_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            find_font_files(&path, files);
+        } else if path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ["ttf", "otf", "ttc"].contains(&ext.to_ascii_lowercase().as_str()))
+        {
+            files.push(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_range_start() {
+        assert_eq!(range_start("0-255"), Some(0));
+        assert_eq!(range_start("65280-65535"), Some(65280));
+        assert_eq!(range_start("1-256"), None);
+        assert_eq!(range_start("0-511"), None);
+        assert_eq!(range_start("65536-65791"), None);
+        assert_eq!(range_start("a-b"), None);
+    }
+}
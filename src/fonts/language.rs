@@ -0,0 +1,154 @@
+//! Font selection by `Accept-Language`
+//!
+//! `[font_languages]` maps language codes to fonts, e.g. `ja` to
+//! `Noto Sans CJK JP Regular`. A glyph request whose `Accept-Language`
+//! prefers a mapped language gets that font moved to the front of its
+//! fontstack, or added there when it is in the fonts directory, so shared
+//! codepoints such as Han characters are drawn in the reader's own script.
+
+use std::collections::HashMap;
+
+use crate::styles::localize::sanitize_language;
+
+/// Most languages read from an `Accept-Language` header
+const MAX_LANGUAGES: usize = 16;
+
+/// Languages of an `Accept-Language` header, most preferred first
+///
+/// Tags are normalized with [`sanitize_language`]; `*` and tags with
+/// `q=0` are left out, and equal weights keep their header order.
+pub fn preferred_languages(header: &str) -> Vec<String> {
+    let mut languages: Vec<(String, f32)> = header
+        .split(',')
+        .take(MAX_LANGUAGES)
+        .filter_map(|entry| {
+            let mut parts = entry.split(';');
+            let tag = parts.next()?.trim();
+            if tag == "*" {
+                return None;
+            }
+            let weight = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if weight <= 0.0 {
+                return None;
+            }
+            Some((sanitize_language(tag)?, weight))
+        })
+        .collect();
+    languages.sort_by(|a, b| b.1.total_cmp(&a.1));
+    languages.into_iter().map(|(tag, _)| tag).collect()
+}
+
+/// Font mapped to the most preferred language that has one
+///
+/// A tag matches its own entry first, then ever shorter prefixes of it, so
+/// `ja-JP` uses the `ja` font. Fonts for which `available` is false are
+/// skipped.
+pub fn language_font<'a>(
+    languages: &[String],
+    map: &'a HashMap<String, String>,
+    available: impl Fn(&str) -> bool,
+) -> Option<&'a str> {
+    let lookup = |tag: &str| {
+        map.iter()
+            .find(|(language, _)| sanitize_language(language).as_deref() == Some(tag))
+            .map(|(_, font)| font.as_str())
+    };
+    languages.iter().find_map(|tag| {
+        let prefixes = std::iter::successors(Some(tag.as_str()), |prefix| {
+            prefix.rfind('-').map(|end| &prefix[..end])
+        });
+        prefixes.filter_map(lookup).find(|font| available(font))
+    })
+}
+
+/// `fontstack` with `font` moved to the front, or `None` if it is already
+/// first
+pub fn prioritize(fontstack: &str, font: &str) -> Option<String> {
+    let fonts: Vec<&str> = fontstack.split(',').map(|s| s.trim()).collect();
+    if fonts.first() == Some(&font) {
+        return None;
+    }
+    let stack: Vec<&str> = std::iter::once(font)
+        .chain(fonts.into_iter().filter(|f| *f != font))
+        .collect();
+    Some(stack.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map() -> HashMap<String, String> {
+        HashMap::from([
+            ("ja".to_string(), "Noto Sans CJK JP Regular".to_string()),
+            (
+                "zh-Hant".to_string(),
+                "Noto Sans CJK TC Regular".to_string(),
+            ),
+            ("zh".to_string(), "Noto Sans CJK SC Regular".to_string()),
+            ("ko".to_string(), "Noto Sans CJK KR Regular".to_string()),
+        ])
+    }
+
+    #[test]
+    fn test_preferred_languages() {
+        assert_eq!(
+            preferred_languages("fr-CH, fr;q=0.9, en;q=0.8, de;q=0.7, *;q=0.5"),
+            vec!["fr-ch", "fr", "en", "de"]
+        );
+        // Sorted by weight, ties in header order, q=0 dropped
+        assert_eq!(
+            preferred_languages("en;q=0.5, ja, ko;q=0, zh_TW;q=0.5"),
+            vec!["ja", "en", "zh-tw"]
+        );
+        assert!(preferred_languages("").is_empty());
+        assert!(preferred_languages("*").is_empty());
+    }
+
+    #[test]
+    fn test_language_font() {
+        let map = map();
+        let all = |_: &str| true;
+        let font = |header: &str| language_font(&preferred_languages(header), &map, all);
+
+        assert_eq!(font("ja-JP,en;q=0.8"), Some("Noto Sans CJK JP Regular"));
+        assert_eq!(font("en, ko;q=0.9"), Some("Noto Sans CJK KR Regular"));
+        // Longer prefixes win over the primary subtag
+        assert_eq!(font("zh-Hant-TW"), Some("Noto Sans CJK TC Regular"));
+        assert_eq!(font("zh-Hant"), Some("Noto Sans CJK TC Regular"));
+        assert_eq!(font("zh-CN"), Some("Noto Sans CJK SC Regular"));
+        assert_eq!(font("en-US, de"), None);
+
+        // Unavailable fonts fall through to the next language
+        let no_jp = |f: &str| f != "Noto Sans CJK JP Regular";
+        assert_eq!(
+            language_font(&preferred_languages("ja, ko;q=0.5"), &map, no_jp),
+            Some("Noto Sans CJK KR Regular")
+        );
+    }
+
+    #[test]
+    fn test_prioritize() {
+        assert_eq!(
+            prioritize(
+                "Noto Sans Regular, Noto Sans CJK JP Regular",
+                "Noto Sans CJK JP Regular"
+            ),
+            Some("Noto Sans CJK JP Regular,Noto Sans Regular".to_string())
+        );
+        assert_eq!(
+            prioritize("Noto Sans Regular", "Noto Sans CJK KR Regular"),
+            Some("Noto Sans CJK KR Regular,Noto Sans Regular".to_string())
+        );
+        assert_eq!(
+            prioritize(
+                "Noto Sans CJK JP Regular,Noto Sans Regular",
+                "Noto Sans CJK JP Regular"
+            ),
+            None
+        );
+    }
+}
@@ -0,0 +1,242 @@
+//! Font glyphs (`GET /fonts/{fontstack}/{range}.pbf`)
+//!
+//! A fontstack names several fonts, e.g. `Open Sans Bold,Arial Unicode MS
+//! Regular`. Like tileserver-gl and martin, glyphs are combined across the
+//! stack: each codepoint comes from the first font that has it, so later
+//! fonts fill in the scripts earlier ones lack. Merged ranges are cached, and
+//! single-font requests are served straight from disk.
+//!
+//! Fonts are read from pre-built PBF directories (`<fonts>/<font>/0-255.pbf`)
+//! or, failing that, generated from TTF/OTF files in the fonts directory.
+
+mod catalog;
+pub mod language;
+mod sdf;
+pub mod subset;
+
+pub use catalog::FontCatalog;
+
+use bytes::Bytes;
+use prost::Message;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::error::{Result, TileServerError};
+
+/// Most merged ranges kept in memory (256 ranges cover a whole fontstack)
+const CACHE_CAPACITY: usize = 1024;
+
+/// A glyph PBF (`glyphs.proto` from the Mapbox GL glyph spec)
+#[derive(Clone, PartialEq, Message)]
+pub struct Glyphs {
+    #[prost(message, repeated, tag = "1")]
+    pub stacks: Vec<Fontstack>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Fontstack {
+    #[prost(string, required, tag = "1")]
+    pub name: String,
+    #[prost(string, required, tag = "2")]
+    pub range: String,
+    #[prost(message, repeated, tag = "3")]
+    pub glyphs: Vec<Glyph>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Glyph {
+    /// Unicode codepoint
+    #[prost(uint32, required, tag = "1")]
+    pub id: u32,
+    /// SDF bitmap, absent for glyphs without pixels such as spaces
+    #[prost(bytes = "vec", optional, tag = "2")]
+    pub bitmap: Option<Vec<u8>>,
+    #[prost(uint32, required, tag = "3")]
+    pub width: u32,
+    #[prost(uint32, required, tag = "4")]
+    pub height: u32,
+    #[prost(sint32, required, tag = "5")]
+    pub left: i32,
+    #[prost(sint32, required, tag = "6")]
+    pub top: i32,
+    #[prost(uint32, required, tag = "7")]
+    pub advance: u32,
+}
+
+/// Merged glyph ranges, keyed by fontstack and range
+#[derive(Debug, Default)]
+pub struct GlyphCache {
+    ranges: Mutex<HashMap<(String, String), Bytes>>,
+}
+
+impl GlyphCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn get(&self, key: &(String, String)) -> Option<Bytes> {
+        self.ranges.lock().ok()?.get(key).cloned()
+    }
+
+    fn insert(&self, key: (String, String), data: Bytes) {
+        let Ok(mut ranges) = self.ranges.lock() else {
+            return;
+        };
+        if ranges.len() >= CACHE_CAPACITY {
+            ranges.clear();
+        }
+        ranges.insert(key, data);
+    }
+}
+
+/// Merge glyph PBFs, each codepoint taken from the first font that has it
+///
+/// The result is one fontstack named after all fonts, as martin does.
+pub fn merge(name: &str, range: &str, pbfs: &[Bytes]) -> Result<Bytes> {
+    let mut glyphs: BTreeMap<u32, Glyph> = BTreeMap::new();
+    for pbf in pbfs {
+        let decoded = Glyphs::decode(pbf.as_ref()).map_err(|e| {
+            TileServerError::MetadataError(format!("Failed to decode glyphs: {}", e))
+        })?;
+        for glyph in decoded.stacks.into_iter().flat_map(|stack| stack.glyphs) {
+            glyphs.entry(glyph.id).or_insert(glyph);
+        }
+    }
+
+    let merged = Glyphs {
+        stacks: vec![Fontstack {
+            name: name.to_string(),
+            range: range.to_string(),
+            glyphs: glyphs.into_values().collect(),
+        }],
+    };
+    Ok(Bytes::from(merged.encode_to_vec()))
+}
+
+/// Glyphs for `range` (e.g. `0-255.pbf`) of a comma-separated fontstack
+///
+/// Each font comes from its PBF directory in `fonts_dir`, or is generated
+/// from `font_files`. Fonts found in neither are skipped; the request fails
+/// only when none of them has the range.
+pub async fn load_glyphs(
+    fonts_dir: &Path,
+    font_files: &FontCatalog,
+    fontstack: &str,
+    range: &str,
+    cache: &GlyphCache,
+) -> Result<Bytes> {
+    // Must match pattern like "0-255.pbf", "256-511.pbf", etc.
+    if !range.ends_with(".pbf") {
+        return Err(TileServerError::InvalidTileRequest);
+    }
+
+    // Security: Validate range format to prevent path traversal
+    let range_name = range.trim_end_matches(".pbf");
+    if range_name.contains("..") || range_name.contains('/') || range_name.contains('\\') {
+        return Err(TileServerError::InvalidTileRequest);
+    }
+
+    let fonts: Vec<&str> = fontstack.split(',').map(|s| s.trim()).collect();
+    let key = (fonts.join(","), range.to_string());
+    if fonts.len() > 1 {
+        if let Some(data) = cache.get(&key) {
+            return Ok(data);
+        }
+    }
+
+    // Security: Canonicalize fonts directory for path validation
+    let canonical_fonts_dir = fonts_dir
+        .canonicalize()
+        .map_err(|_| TileServerError::FontNotFound("Fonts directory not accessible".to_string()))?;
+
+    let mut found = Vec::new();
+    for font_name in &fonts {
+        // Security: Reject font names with path traversal sequences
+        if font_name.contains("..") || font_name.contains('/') || font_name.contains('\\') {
+            continue;
+        }
+
+        let font_path = fonts_dir.join(font_name).join(range);
+
+        // Security: Verify the resolved path is within fonts directory
+        if let Ok(canonical_path) = font_path.canonicalize() {
+            if !canonical_path.starts_with(&canonical_fonts_dir) {
+                continue; // Path escapes fonts directory
+            }
+        }
+
+        if let Ok(data) = tokio::fs::read(&font_path).await {
+            tracing::debug!("Reading font: {}/{}", font_name, range);
+            found.push(Bytes::from(data));
+        } else if let Some(data) = font_files.glyphs(font_name, range_name) {
+            tracing::debug!("Generated font: {}/{}", font_name, range);
+            found.push(data);
+        }
+    }
+
+    match found.len() {
+        0 => {
+            tracing::debug!("Font not found: {} (tried: {:?})", range, fonts);
+            Err(TileServerError::FontNotFound(fontstack.to_string()))
+        }
+        // Nothing to merge
+        1 => Ok(found.remove(0)),
+        _ => {
+            let merged = merge(&fonts.join(", "), range_name, &found)?;
+            cache.insert(key, merged.clone());
+            Ok(merged)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glyph(id: u32, advance: u32) -> Glyph {
+        Glyph {
+            id,
+            bitmap: Some(vec![0; 4]),
+            width: 1,
+            height: 1,
+            left: -1,
+            top: -5,
+            advance,
+        }
+    }
+
+    fn pbf(name: &str, glyphs: Vec<Glyph>) -> Bytes {
+        let glyphs = Glyphs {
+            stacks: vec![Fontstack {
+                name: name.to_string(),
+                range: "0-255".to_string(),
+                glyphs,
+            }],
+        };
+        Bytes::from(glyphs.encode_to_vec())
+    }
+
+    #[test]
+    fn test_merge_prefers_earlier_fonts() {
+        let a = pbf("A", vec![glyph(65, 10), glyph(66, 10)]);
+        let b = pbf("B", vec![glyph(66, 20), glyph(67, 20)]);
+
+        let merged = Glyphs::decode(merge("A, B", "0-255", &[a, b]).unwrap()).unwrap();
+        assert_eq!(merged.stacks.len(), 1);
+        assert_eq!(merged.stacks[0].name, "A, B");
+        assert_eq!(merged.stacks[0].range, "0-255");
+
+        let advances: Vec<(u32, u32)> = merged.stacks[0]
+            .glyphs
+            .iter()
+            .map(|g| (g.id, g.advance))
+            .collect();
+        assert_eq!(advances, vec![(65, 10), (66, 10), (67, 20)]);
+    }
+
+    #[test]
+    fn test_merge_rejects_invalid_pbf() {
+        assert!(merge("A", "0-255", &[Bytes::from_static(b"\xff\xff")]).is_err());
+    }
+}
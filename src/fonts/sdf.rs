@@ -0,0 +1,268 @@
+//! Signed distance field glyphs rendered from font outlines
+//!
+//! Uses the parameters of node-fontnik, which built most published glyph
+//! sets: glyphs are drawn at 24px inside a 3px buffer, and distances up to
+//! 8px from the outline are encoded around a cutoff of 0.25. The distance
+//! transform is the one MapLibre's TinySDF uses for local glyphs.
+
+use ab_glyph_rasterizer::{point, Point, Rasterizer};
+use ttf_parser::{Face, OutlineBuilder};
+
+use super::Glyph;
+
+/// Size glyphs are rendered at, in pixels
+pub const FONT_SIZE: f32 = 24.0;
+
+/// Padding around each bitmap, in pixels
+pub const BUFFER: u32 = 3;
+
+/// Distance covered by the field, in pixels
+const RADIUS: f64 = 8.0;
+
+/// Position of the outline between 0 (far outside) and 1 (far inside)
+const CUTOFF: f64 = 0.25;
+
+/// Stands in for infinity without producing NaN in the transform
+const INF: f64 = 1e20;
+
+/// Render the glyph for `codepoint`, or `None` if the font lacks it
+///
+/// Glyphs without an outline, such as spaces, carry metrics only.
+pub fn render_glyph(face: &Face, codepoint: u32) -> Option<Glyph> {
+    let id = face.glyph_index(char::from_u32(codepoint)?)?;
+    let scale = FONT_SIZE / f32::from(face.units_per_em());
+    let advance = (f32::from(face.glyph_hor_advance(id).unwrap_or(0)) * scale).round() as u32;
+    // `top` is measured from the ascender, as in node-fontnik
+    let ascender = (f32::from(face.ascender()) * scale).floor() as i32;
+
+    let bbox = face
+        .glyph_bounding_box(id)
+        .filter(|bbox| bbox.width() > 0 && bbox.height() > 0);
+    let Some(bbox) = bbox else {
+        return Some(Glyph {
+            id: codepoint,
+            bitmap: None,
+            width: 0,
+            height: 0,
+            left: 0,
+            top: -ascender,
+            advance,
+        });
+    };
+
+    let left = (f32::from(bbox.x_min) * scale).round() as i32;
+    let right = (f32::from(bbox.x_max) * scale).round() as i32;
+    let bottom = (f32::from(bbox.y_min) * scale).round() as i32;
+    let top = (f32::from(bbox.y_max) * scale).round() as i32;
+    let width = (right - left).max(1) as u32;
+    let height = (top - bottom).max(1) as u32;
+
+    let padded_width = (width + 2 * BUFFER) as usize;
+    let padded_height = (height + 2 * BUFFER) as usize;
+    let mut outline = Outline {
+        rasterizer: Rasterizer::new(padded_width, padded_height),
+        scale,
+        offset: (BUFFER as f32 - left as f32, BUFFER as f32 + top as f32),
+        start: point(0.0, 0.0),
+        last: point(0.0, 0.0),
+    };
+    face.outline_glyph(id, &mut outline)?;
+
+    let mut coverage = vec![0.0; padded_width * padded_height];
+    outline
+        .rasterizer
+        .for_each_pixel(|i, alpha| coverage[i] = alpha.min(1.0));
+
+    Some(Glyph {
+        id: codepoint,
+        bitmap: Some(distance_field(&coverage, padded_width, padded_height)),
+        width,
+        height,
+        left,
+        top: top - ascender,
+        advance,
+    })
+}
+
+/// Draws an outline in font units onto the bitmap, flipping it upright
+struct Outline {
+    rasterizer: Rasterizer,
+    scale: f32,
+    offset: (f32, f32),
+    start: Point,
+    last: Point,
+}
+
+impl Outline {
+    fn point(&self, x: f32, y: f32) -> Point {
+        point(
+            self.offset.0 + x * self.scale,
+            self.offset.1 - y * self.scale,
+        )
+    }
+}
+
+impl OutlineBuilder for Outline {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.start = self.point(x, y);
+        self.last = self.start;
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let to = self.point(x, y);
+        self.rasterizer.draw_line(self.last, to);
+        self.last = to;
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let to = self.point(x, y);
+        self.rasterizer.draw_quad(self.last, self.point(x1, y1), to);
+        self.last = to;
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let to = self.point(x, y);
+        self.rasterizer
+            .draw_cubic(self.last, self.point(x1, y1), self.point(x2, y2), to);
+        self.last = to;
+    }
+
+    fn close(&mut self) {
+        if self.last != self.start {
+            self.rasterizer.draw_line(self.last, self.start);
+        }
+        self.last = self.start;
+    }
+}
+
+/// Encode the distance of each pixel to the outline, from coverage in 0..=1
+fn distance_field(coverage: &[f32], width: usize, height: usize) -> Vec<u8> {
+    // Squared distances to the nearest pixel inside and outside the glyph,
+    // seeded with sub-pixel distances for partly covered pixels
+    let mut outer = Vec::with_capacity(coverage.len());
+    let mut inner = Vec::with_capacity(coverage.len());
+    for &alpha in coverage {
+        let alpha = f64::from(alpha);
+        if alpha >= 1.0 {
+            outer.push(0.0);
+            inner.push(INF);
+        } else if alpha <= 0.0 {
+            outer.push(INF);
+            inner.push(0.0);
+        } else {
+            outer.push((0.5 - alpha).max(0.0).powi(2));
+            inner.push((alpha - 0.5).max(0.0).powi(2));
+        }
+    }
+
+    transform(&mut outer, width, height);
+    transform(&mut inner, width, height);
+
+    outer
+        .iter()
+        .zip(&inner)
+        .map(|(outer, inner)| {
+            let distance = outer.sqrt() - inner.sqrt();
+            (255.0 - 255.0 * (distance / RADIUS + CUTOFF))
+                .round()
+                .clamp(0.0, 255.0) as u8
+        })
+        .collect()
+}
+
+/// 2D squared Euclidean distance transform, columns then rows
+fn transform(grid: &mut [f64], width: usize, height: usize) {
+    let len = width.max(height);
+    let mut f = vec![0.0; len];
+    let mut v = vec![0; len];
+    let mut z = vec![0.0; len + 1];
+
+    for x in 0..width {
+        transform_1d(grid, x, width, height, &mut f, &mut v, &mut z);
+    }
+    for y in 0..height {
+        transform_1d(grid, y * width, 1, width, &mut f, &mut v, &mut z);
+    }
+}
+
+/// 1D transform of `length` cells from `offset`, `stride` apart
+/// (Felzenszwalb & Huttenlocher)
+fn transform_1d(
+    grid: &mut [f64],
+    offset: usize,
+    stride: usize,
+    length: usize,
+    f: &mut [f64],
+    v: &mut [usize],
+    z: &mut [f64],
+) {
+    if length == 0 {
+        return;
+    }
+    v[0] = 0;
+    z[0] = -INF;
+    z[1] = INF;
+    f[0] = grid[offset];
+
+    let mut k = 0;
+    for q in 1..length {
+        f[q] = grid[offset + q * stride];
+        let q2 = (q * q) as f64;
+        let mut s;
+        loop {
+            let r = v[k];
+            s = (f[q] - f[r] + q2 - (r * r) as f64) / (q - r) as f64 / 2.0;
+            if s <= z[k] && k > 0 {
+                k -= 1;
+            } else {
+                break;
+            }
+        }
+        if s > z[k] {
+            k += 1;
+        }
+        v[k] = q;
+        z[k] = s;
+        z[k + 1] = INF;
+    }
+
+    let mut k = 0;
+    for q in 0..length {
+        while z[k + 1] < q as f64 {
+            k += 1;
+        }
+        let r = v[k];
+        let qr = q.abs_diff(r) as f64;
+        grid[offset + q * stride] = f[r] + qr * qr;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distance_field() {
+        // A 3x3 filled square in the middle of a 9x9 bitmap
+        let (width, height) = (9, 9);
+        let mut coverage = vec![0.0; width * height];
+        for y in 3..6 {
+            for x in 3..6 {
+                coverage[y * width + x] = 1.0;
+            }
+        }
+
+        let field = distance_field(&coverage, width, height);
+        let at = |x: usize, y: usize| field[y * width + x];
+
+        // Inside is above the cutoff, outside below, falling off with distance
+        let cutoff = (255.0 * (1.0 - CUTOFF)) as u8;
+        assert!(at(4, 4) > cutoff);
+        assert!(at(2, 4) < cutoff);
+        assert!(at(0, 4) < at(2, 4));
+        assert!(at(4, 4) > at(3, 4));
+        // Symmetric
+        assert_eq!(at(0, 4), at(8, 4));
+        assert_eq!(at(4, 0), at(0, 4));
+    }
+}
@@ -0,0 +1,514 @@
+//! Glyph subsets of a style (`POST /fonts/subset`)
+//!
+//! Instead of fetching 256-glyph ranges one by one as labels need them, a
+//! client can post its style and get every range its labels can use in one
+//! PBF. The ranges are worked out from each symbol layer's `text-field`:
+//! literal text needs only the ranges of its characters, while text read
+//! from feature properties (`["get", "name"]`, `{name}` tokens) could be
+//! anything, so its fontstack gets all ranges.
+
+use bytes::Bytes;
+use prost::Message;
+use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::sync::Mutex;
+
+use super::{load_glyphs, FontCatalog, GlyphCache, Glyphs};
+use crate::error::{Result, TileServerError};
+
+/// Fontstack MapLibre uses for layers without a `text-font`
+const DEFAULT_FONTSTACK: &str = "Open Sans Regular,Arial Unicode MS Regular";
+
+/// Glyph PBFs hold the Basic Multilingual Plane, in 256 ranges
+const RANGE_SIZE: u32 = 256;
+const RANGE_COUNT: u32 = 256;
+
+/// Subsets kept before the cache is cleared
+const CACHE_CAPACITY: usize = 64;
+
+/// Characters a `text-field` can produce
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextChars {
+    /// Text from feature data, which could hold any character
+    Any,
+    /// Only these characters, from literal text
+    Only(BTreeSet<char>),
+}
+
+impl TextChars {
+    fn none() -> Self {
+        Self::Only(BTreeSet::new())
+    }
+
+    fn of(text: &str) -> Self {
+        Self::Only(text.chars().collect())
+    }
+
+    fn union(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Only(mut a), Self::Only(b)) => {
+                a.extend(b);
+                Self::Only(a)
+            }
+            _ => Self::Any,
+        }
+    }
+
+    fn map(self, f: impl Fn(char) -> String) -> Self {
+        match self {
+            Self::Any => Self::Any,
+            Self::Only(chars) => Self::Only(
+                chars
+                    .into_iter()
+                    .flat_map(|c| f(c).chars().collect::<Vec<_>>())
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Start codepoints of the glyph ranges holding these characters
+    fn ranges(&self) -> BTreeSet<u32> {
+        match self {
+            Self::Any => (0..RANGE_COUNT).map(|i| i * RANGE_SIZE).collect(),
+            Self::Only(chars) => chars
+                .iter()
+                .map(|&c| c as u32 / RANGE_SIZE * RANGE_SIZE)
+                .filter(|&start| start < RANGE_COUNT * RANGE_SIZE)
+                .collect(),
+        }
+    }
+}
+
+/// Start codepoints of the glyph ranges each fontstack of `style` needs,
+/// keyed by fontstack (comma-separated, as in glyph URLs)
+///
+/// Fontstacks whose labels hold no characters are left out.
+pub fn style_ranges(style: &Value) -> BTreeMap<String, BTreeSet<u32>> {
+    let mut ranges: BTreeMap<String, BTreeSet<u32>> = BTreeMap::new();
+    let layouts = style
+        .get("layers")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|layer| layer.get("layout"));
+    for layout in layouts {
+        let Some(text_field) = layout.get("text-field") else {
+            continue;
+        };
+        let mut chars = text_chars(text_field);
+        // Labels may be transformed to either case, depending on the zoom
+        // or feature, unless the transform is a plain "none"
+        match layout.get("text-transform") {
+            None => {}
+            Some(Value::String(transform)) if transform == "none" => {}
+            Some(Value::String(transform)) if transform == "uppercase" => {
+                chars = chars.map(|c| c.to_uppercase().collect());
+            }
+            Some(Value::String(transform)) if transform == "lowercase" => {
+                chars = chars.map(|c| c.to_lowercase().collect());
+            }
+            Some(_) => {
+                chars = chars.map(|c| c.to_uppercase().chain(c.to_lowercase()).collect());
+            }
+        }
+        let needed = chars.ranges();
+        if needed.is_empty() {
+            continue;
+        }
+        for fontstack in fontstacks(layout.get("text-font")) {
+            ranges.entry(fontstack).or_default().extend(&needed);
+        }
+    }
+    ranges
+}
+
+/// Characters of a `text-field`: a string with `{token}`s, a legacy
+/// function, or an expression
+pub fn text_chars(value: &Value) -> TextChars {
+    match value {
+        Value::Null => TextChars::none(),
+        Value::Bool(_) | Value::Number(_) => TextChars::of(&value.to_string()),
+        Value::String(text) => token_chars(text),
+        Value::Array(items) => expression_chars(items),
+        Value::Object(function) => function_chars(function),
+    }
+}
+
+/// A legacy token string: `{name}` tokens read feature properties
+fn token_chars(text: &str) -> TextChars {
+    let has_token = text
+        .find('{')
+        .is_some_and(|start| text[start..].contains('}'));
+    if has_token {
+        TextChars::Any
+    } else {
+        TextChars::of(text)
+    }
+}
+
+/// A legacy function: each stop's output, unless the property's value is
+/// shown as it is
+fn function_chars(function: &serde_json::Map<String, Value>) -> TextChars {
+    if function.get("type").and_then(Value::as_str) == Some("identity") {
+        return TextChars::Any;
+    }
+    let stops = function
+        .get("stops")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|stop| stop.get(1));
+    stops
+        .chain(function.get("default"))
+        .map(text_chars)
+        .fold(TextChars::none(), TextChars::union)
+}
+
+/// An expression: the outputs of expressions that choose or join text are
+/// followed, and anything else is treated as reading feature data
+fn expression_chars(items: &[Value]) -> TextChars {
+    let Some(op) = items.first().and_then(Value::as_str) else {
+        return TextChars::Any;
+    };
+    let args = &items[1..];
+    let union = |outputs: &mut dyn Iterator<Item = &Value>| {
+        outputs
+            .map(text_chars)
+            .fold(TextChars::none(), TextChars::union)
+    };
+    match op {
+        "literal" => match args.first() {
+            Some(Value::String(text)) => TextChars::of(text),
+            Some(value @ (Value::Bool(_) | Value::Number(_))) => TextChars::of(&value.to_string()),
+            _ => TextChars::none(),
+        },
+        // Images are drawn from the sprite, not glyphs
+        "image" => TextChars::none(),
+        "concat" | "coalesce" => union(&mut args.iter()),
+        // Sections alternate with their options objects
+        "format" => union(&mut args.iter().filter(|arg| !arg.is_object())),
+        "upcase" => union(&mut args.iter()).map(|c| c.to_uppercase().collect()),
+        "downcase" => union(&mut args.iter()).map(|c| c.to_lowercase().collect()),
+        // ["case", cond, output, ..., fallback]
+        "case" => union(&mut args.iter().skip(1).step_by(2).chain(args.last())),
+        // ["match", input, labels, output, ..., fallback]
+        "match" => union(&mut args.iter().skip(2).step_by(2).chain(args.last())),
+        // ["step", input, output, stop, output, ...]
+        "step" => union(&mut args.iter().skip(1).step_by(2)),
+        // ["let", name, value, ..., body]: the body may use any variable
+        "let" => union(&mut args.last().into_iter()),
+        _ => TextChars::Any,
+    }
+}
+
+/// Fontstacks a `text-font` can use: the array itself, or every `literal`
+/// array inside an expression or function choosing between them
+fn fontstacks(value: Option<&Value>) -> Vec<String> {
+    fn fonts(value: &Value) -> Option<String> {
+        let fonts: Vec<&str> = value
+            .as_array()?
+            .iter()
+            .map(Value::as_str)
+            .collect::<Option<_>>()?;
+        (!fonts.is_empty()).then(|| fonts.join(","))
+    }
+
+    fn collect(value: &Value, stacks: &mut Vec<String>) {
+        match value {
+            Value::Array(items) if items.first().and_then(Value::as_str) == Some("literal") => {
+                stacks.extend(items.get(1).and_then(fonts));
+            }
+            Value::Array(items) => {
+                for item in items {
+                    collect(item, stacks);
+                }
+            }
+            Value::Object(function) => {
+                let stops = function
+                    .get("stops")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|stop| stop.get(1));
+                for output in stops {
+                    stacks.extend(fonts(output));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut stacks = Vec::new();
+    if let Some(value) = value {
+        match fonts(value) {
+            // A plain array of font names, not an expression of strings
+            Some(stack) if !value[0].as_str().is_some_and(is_operator) => stacks.push(stack),
+            _ => collect(value, &mut stacks),
+        }
+    }
+    if stacks.is_empty() {
+        stacks.push(DEFAULT_FONTSTACK.to_string());
+    }
+    stacks.sort();
+    stacks.dedup();
+    stacks
+}
+
+/// Whether an array of strings is an expression, e.g. `["get", "font"]`,
+/// rather than a list of fonts
+fn is_operator(first: &str) -> bool {
+    matches!(first, "get" | "to-string" | "var" | "literal")
+}
+
+/// Subset PBFs, keyed by a hash of the style they were made for
+#[derive(Debug, Default)]
+pub struct SubsetCache {
+    subsets: Mutex<HashMap<u64, Bytes>>,
+}
+
+impl SubsetCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Hash of a style's JSON, as the subset cache keys it
+pub fn style_hash(style: &Value) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    style.to_string().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Every glyph range `style` uses, combined into one PBF with a fontstack
+/// entry per fontstack and range
+///
+/// Ranges that none of a fontstack's fonts have are left out, as they
+/// would be a 404 from `/fonts/{fontstack}/{range}.pbf`.
+pub async fn load_subset(
+    fonts_dir: &Path,
+    font_files: &FontCatalog,
+    style: &Value,
+    glyph_cache: &GlyphCache,
+    cache: &SubsetCache,
+) -> Result<Bytes> {
+    if !style.get("layers").is_some_and(Value::is_array) {
+        return Err(TileServerError::BadRequest(
+            "Style has no layers array".to_string(),
+        ));
+    }
+
+    let hash = style_hash(style);
+    if let Some(data) = cache
+        .subsets
+        .lock()
+        .ok()
+        .and_then(|s| s.get(&hash).cloned())
+    {
+        return Ok(data);
+    }
+
+    let mut combined = Glyphs { stacks: Vec::new() };
+    for (fontstack, starts) in style_ranges(style) {
+        for start in starts {
+            let range = format!("{}-{}", start, start + RANGE_SIZE - 1);
+            let file = format!("{}.pbf", range);
+            let data =
+                match load_glyphs(fonts_dir, font_files, &fontstack, &file, glyph_cache).await {
+                    Ok(data) => data,
+                    Err(TileServerError::FontNotFound(_)) => continue,
+                    Err(e) => return Err(e),
+                };
+            let glyphs = Glyphs::decode(data.as_ref()).map_err(|e| {
+                TileServerError::MetadataError(format!("Failed to decode glyphs: {}", e))
+            })?;
+            combined
+                .stacks
+                .extend(glyphs.stacks.into_iter().map(|mut stack| {
+                    stack.name = fontstack.clone();
+                    stack.range = range.clone();
+                    stack
+                }));
+        }
+    }
+
+    let data = Bytes::from(combined.encode_to_vec());
+    if let Ok(mut subsets) = cache.subsets.lock() {
+        if subsets.len() >= CACHE_CAPACITY {
+            subsets.clear();
+        }
+        subsets.insert(hash, data.clone());
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn chars(text: &str) -> TextChars {
+        TextChars::of(text)
+    }
+
+    #[test]
+    fn test_literal_text_chars() {
+        assert_eq!(text_chars(&json!("Zürich")), chars("Zürich"));
+        assert_eq!(text_chars(&json!(["literal", "Bern"])), chars("Bern"));
+        assert_eq!(
+            text_chars(&json!(["concat", "A", ["literal", "B"]])),
+            chars("AB")
+        );
+        assert_eq!(
+            text_chars(&json!(["format", "Peak", {"font-scale": 1.2}, "▲", {}])),
+            chars("Peak▲")
+        );
+        assert_eq!(
+            text_chars(&json!(["format", ["image", "marker"], {}])),
+            chars("")
+        );
+        assert_eq!(text_chars(&json!(["upcase", "ab"])), chars("AB"));
+        assert_eq!(
+            text_chars(&json!(["step", ["zoom"], "a", 10, "b"])),
+            chars("ab")
+        );
+        assert_eq!(
+            text_chars(&json!([
+                "match",
+                ["get", "class"],
+                "city",
+                "•",
+                ["town", "village"],
+                "·",
+                "?"
+            ])),
+            chars("•·?")
+        );
+        assert_eq!(
+            text_chars(&json!(["case", ["has", "ref"], "R", "-"])),
+            chars("R-")
+        );
+        assert_eq!(
+            text_chars(&json!({ "stops": [[4, "x"], [10, "y"]] })),
+            chars("xy")
+        );
+    }
+
+    #[test]
+    fn test_data_text_is_any() {
+        for text_field in [
+            json!(["get", "name"]),
+            json!("{name}"),
+            json!("{ref} km"),
+            json!(["concat", "#", ["get", "ref"]]),
+            json!(["to-string", ["get", "ele"]]),
+            json!(["case", ["has", "name"], ["get", "name"], "?"]),
+            json!(["let", "n", ["get", "name"], ["var", "n"]]),
+            json!({ "type": "identity", "property": "name" }),
+            json!({ "stops": [[4, "x"], [10, "{name}"]] }),
+        ] {
+            assert_eq!(text_chars(&text_field), TextChars::Any, "{}", text_field);
+        }
+        // A lone brace isn't a token
+        assert_eq!(text_chars(&json!("{")), chars("{"));
+    }
+
+    #[test]
+    fn test_style_ranges() {
+        let style = json!({
+            "version": 8,
+            "layers": [
+                { "id": "bg", "type": "background" },
+                {
+                    "id": "labels",
+                    "type": "symbol",
+                    "layout": { "text-field": "Zürich Ελλάδα", "text-font": ["Noto Sans Regular"] }
+                },
+                {
+                    "id": "icons",
+                    "type": "symbol",
+                    "layout": { "text-field": "", "text-font": ["Noto Sans Medium"] }
+                },
+                {
+                    "id": "shouting",
+                    "type": "symbol",
+                    "layout": {
+                        "text-field": "é",
+                        "text-transform": "uppercase",
+                        "text-font": ["literal", ["Noto Sans Regular"]]
+                    }
+                },
+                {
+                    "id": "names",
+                    "type": "symbol",
+                    "layout": {
+                        "text-field": ["get", "name"],
+                        "text-font": ["step", ["zoom"], ["literal", ["Noto Sans Medium"]], 10, ["literal", ["Noto Sans Bold", "Noto Sans Regular"]]]
+                    }
+                },
+                { "id": "default-font", "type": "symbol", "layout": { "text-field": "Ж" } }
+            ]
+        });
+        let ranges = style_ranges(&style);
+        let all: BTreeSet<u32> = (0..256).map(|i| i * 256).collect();
+
+        assert_eq!(
+            ranges.keys().collect::<Vec<_>>(),
+            vec![
+                "Noto Sans Bold,Noto Sans Regular",
+                "Noto Sans Medium",
+                "Noto Sans Regular",
+                DEFAULT_FONTSTACK
+            ]
+        );
+        // "Zürich" is Latin-1, Greek is 880-1023, and É is Latin-1 again
+        assert_eq!(ranges["Noto Sans Regular"], BTreeSet::from([0, 768]));
+        assert_eq!(ranges["Noto Sans Medium"], all);
+        assert_eq!(ranges["Noto Sans Bold,Noto Sans Regular"], all);
+        // Cyrillic is 1024-1279
+        assert_eq!(ranges[DEFAULT_FONTSTACK], BTreeSet::from([1024]));
+    }
+
+    #[test]
+    fn test_text_transform() {
+        let style = |transform: Value| {
+            json!({
+                "layers": [{
+                    "id": "a",
+                    "type": "symbol",
+                    "layout": { "text-field": "ß", "text-transform": transform, "text-font": ["A"] }
+                }]
+            })
+        };
+        // "ß".to_uppercase() is "SS", also in 0-255
+        assert_eq!(
+            style_ranges(&style(json!("uppercase")))["A"],
+            BTreeSet::from([0])
+        );
+        let text = json!({
+            "layers": [{
+                "id": "a",
+                "type": "symbol",
+                "layout": {
+                    "text-field": "ǅ",
+                    "text-transform": ["step", ["zoom"], "none", 10, "lowercase"],
+                    "text-font": ["A"]
+                }
+            }]
+        });
+        // ǅ (U+01C5) has Ǆ and ǆ as its cases, all in 256-511
+        assert_eq!(style_ranges(&text)["A"], BTreeSet::from([256]));
+    }
+
+    #[test]
+    fn test_style_hash() {
+        let a = json!({ "layers": [], "name": "a" });
+        assert_eq!(style_hash(&a), style_hash(&a.clone()));
+        assert_ne!(
+            style_hash(&a),
+            style_hash(&json!({ "layers": [], "name": "b" }))
+        );
+    }
+}
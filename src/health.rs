@@ -0,0 +1,209 @@
+//! Liveness and readiness checks
+//!
+//! `/health/live` only reports that the process is up. `/health/ready` probes
+//! every source and returns 503 while a required source is failing. Probe
+//! results are cached for a few seconds so load balancers polling every
+//! second don't hammer the backends.
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use crate::sources::resilient::BreakerState;
+use crate::sources::SourceManager;
+
+/// How long probe results are reused
+pub const READINESS_CACHE_TTL: Duration = Duration::from_secs(5);
+
+/// Upper bound for a single source probe
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Ok,
+    Error,
+}
+
+/// Probe result for a single source
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceHealth {
+    pub id: String,
+    pub status: HealthStatus,
+    /// Optional sources are reported but do not gate readiness
+    pub optional: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Circuit breaker state of sources with one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub breaker: Option<BreakerState>,
+}
+
+/// Readiness response body
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadinessReport {
+    pub status: HealthStatus,
+    pub sources: Vec<SourceHealth>,
+}
+
+impl ReadinessReport {
+    /// Build a report, failing if any required source is failing
+    pub fn from_sources(mut sources: Vec<SourceHealth>) -> Self {
+        sources.sort_by(|a, b| a.id.cmp(&b.id));
+        let ready = sources
+            .iter()
+            .all(|s| s.optional || s.status == HealthStatus::Ok);
+
+        Self {
+            status: if ready {
+                HealthStatus::Ok
+            } else {
+                HealthStatus::Error
+            },
+            sources,
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.status == HealthStatus::Ok
+    }
+}
+
+impl IntoResponse for ReadinessReport {
+    fn into_response(self) -> Response {
+        let status = if self.is_ready() {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+
+        (status, Json(self)).into_response()
+    }
+}
+
+/// Probe every source, including those that failed to load
+pub async fn probe_sources(sources: &SourceManager) -> ReadinessReport {
+    let probes = sources.iter().map(|(id, source)| async move {
+        let result = match tokio::time::timeout(PROBE_TIMEOUT, source.health_check()).await {
+            Ok(result) => result.map_err(|e| e.to_string()),
+            Err(_) => Err(format!("Probe timed out after {:?}", PROBE_TIMEOUT)),
+        };
+
+        SourceHealth {
+            id: id.clone(),
+            status: if result.is_ok() {
+                HealthStatus::Ok
+            } else {
+                HealthStatus::Error
+            },
+            optional: sources.is_optional(id),
+            error: result.err(),
+            breaker: sources.breaker_state(id),
+        }
+    });
+
+    let mut results = futures::future::join_all(probes).await;
+    results.extend(sources.failed().iter().map(|failed| SourceHealth {
+        id: failed.id.clone(),
+        status: HealthStatus::Error,
+        optional: sources.is_optional(&failed.id),
+        error: Some(failed.error.clone()),
+        breaker: None,
+    }));
+
+    ReadinessReport::from_sources(results)
+}
+
+/// Readiness prober with a short-lived result cache
+pub struct ReadinessChecker {
+    ttl: Duration,
+    cached: Mutex<Option<(Instant, ReadinessReport)>>,
+}
+
+impl ReadinessChecker {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// Get the readiness report, probing sources if the cached one expired
+    pub async fn check(&self, sources: &SourceManager) -> ReadinessReport {
+        // Holding the lock while probing lets concurrent callers share one probe
+        let mut cached = self.cached.lock().await;
+        if let Some((checked_at, ref report)) = *cached {
+            if checked_at.elapsed() < self.ttl {
+                return report.clone();
+            }
+        }
+
+        let report = probe_sources(sources).await;
+        *cached = Some((Instant::now(), report.clone()));
+        report
+    }
+}
+
+impl Default for ReadinessChecker {
+    fn default() -> Self {
+        Self::new(READINESS_CACHE_TTL)
+    }
+}
+
+/// Liveness check: the process is up and serving requests
+pub async fn liveness() -> (StatusCode, &'static str) {
+    (StatusCode::OK, "OK")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn health(id: &str, status: HealthStatus, optional: bool) -> SourceHealth {
+        SourceHealth {
+            id: id.to_string(),
+            status,
+            optional,
+            error: None,
+            breaker: None,
+        }
+    }
+
+    #[test]
+    fn test_optional_sources_do_not_gate_readiness() {
+        let report = ReadinessReport::from_sources(vec![
+            health("b", HealthStatus::Ok, false),
+            health("a", HealthStatus::Error, true),
+        ]);
+        assert!(report.is_ready());
+        assert_eq!(report.sources[0].id, "a");
+
+        let report = ReadinessReport::from_sources(vec![
+            health("a", HealthStatus::Ok, true),
+            health("b", HealthStatus::Error, false),
+        ]);
+        assert!(!report.is_ready());
+        assert_eq!(
+            report.into_response().status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+
+    #[tokio::test]
+    async fn test_checker_caches_results() {
+        let checker = ReadinessChecker::new(Duration::from_secs(60));
+        let sources = SourceManager::new();
+
+        let first = checker.check(&sources).await;
+        assert!(first.is_ready());
+        let checked_at = checker.cached.lock().await.as_ref().unwrap().0;
+
+        checker.check(&sources).await;
+        assert_eq!(checker.cached.lock().await.as_ref().unwrap().0, checked_at);
+    }
+}
@@ -0,0 +1,248 @@
+//! Per-tile statistics for `GET /data/{source}/inspect`
+//!
+//! Lists the stored tiles of a source at one zoom level, within its bounds,
+//! and reports the size of each along with the layers and features of vector
+//! tiles. Useful for tracking down oversized tiles. Each tile is written as
+//! one line of newline-delimited JSON (`{"x_y": {...}}`), so responses are
+//! streamed rather than built up as one document.
+//!
+//! [`tile`] checks a single tile in depth for
+//! `GET /data/{source}/{z}/{x}/{y}/inspect`, and [`query`] looks at one tile
+//! across all sources for `GET /tiles/{z}/{x}/{y}.json`.
+
+pub mod query;
+pub mod tile;
+
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::error::{Result, TileServerError};
+use crate::ogcapi::tile_matrix_limits;
+use crate::sources::{TileData, TileFormat, TileRange, TileSource};
+
+/// Most tiles reported for one zoom level
+pub const MAX_TILES: usize = 10_000;
+
+/// Response header set when tiles beyond `MAX_TILES` were left out
+pub const TRUNCATED_HEADER: &str = "x-inspect-truncated";
+
+/// Tiles read at once while streaming
+const CONCURRENCY: usize = 16;
+
+/// Statistics for one tile
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TileStats {
+    /// Stored size, before decompression
+    pub bytes: usize,
+    /// Layers of a vector tile, limited to the requested ones
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layers: Option<Vec<LayerStats>>,
+    /// Features across `layers`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub feature_count: Option<usize>,
+    /// Why the tile could not be decoded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LayerStats {
+    pub name: String,
+    pub feature_count: usize,
+}
+
+/// Stored tiles to report at one zoom level
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Inspection {
+    pub z: u8,
+    /// `(x, y)` with rows in the source's scheme, as used in tile URLs
+    pub tiles: Vec<(u32, u32)>,
+    /// Whether tiles beyond `MAX_TILES` were left out
+    pub truncated: bool,
+}
+
+/// Tiles covering `bounds` at zoom `z`, or the whole zoom level without bounds
+pub fn tile_range(z: u8, bounds: Option<[f64; 4]>) -> TileRange {
+    let limits = tile_matrix_limits(z, &bounds.unwrap_or([-180.0, -90.0, 180.0, 90.0]));
+    TileRange {
+        z,
+        min_x: limits.min_tile_col,
+        max_x: limits.max_tile_col,
+        min_y: limits.min_tile_row,
+        max_y: limits.max_tile_row,
+    }
+}
+
+/// List the stored tiles of `source` at zoom `z` within its bounds
+pub async fn list(source: &dyn TileSource, z: u8) -> Result<Inspection> {
+    let metadata = source.metadata();
+    if z > 30 {
+        return Err(TileServerError::BadRequest(format!(
+            "Zoom level {} is out of range",
+            z
+        )));
+    }
+    if z < metadata.minzoom || z > metadata.maxzoom {
+        return Ok(Inspection {
+            z,
+            tiles: Vec::new(),
+            truncated: false,
+        });
+    }
+
+    let range = tile_range(z, metadata.bounds);
+    let Some(mut tiles) = source.list_tiles(range, MAX_TILES + 1).await? else {
+        return Err(TileServerError::BadRequest(format!(
+            "Source '{}' ({}) does not support tile inspection",
+            metadata.id,
+            source.backend()
+        )));
+    };
+
+    let truncated = tiles.len() > MAX_TILES;
+    tiles.truncate(MAX_TILES);
+    let tiles = tiles
+        .into_iter()
+        .filter_map(|(x, y)| Some((x, metadata.scheme.xyz_row(z, y)?)))
+        .collect();
+
+    Ok(Inspection {
+        z,
+        tiles,
+        truncated,
+    })
+}
+
+/// Statistics for a tile, or `None` if it has none of the requested `layers`
+///
+/// An empty `layers` filter keeps every tile.
+pub fn tile_stats(tile: &TileData, layers: &[String]) -> Option<TileStats> {
+    let mut stats = TileStats {
+        bytes: tile.data.len(),
+        layers: None,
+        feature_count: None,
+        error: None,
+    };
+    if tile.format != TileFormat::Pbf {
+        return layers.is_empty().then_some(stats);
+    }
+
+    match tile.decode_mvt() {
+        Ok(decoded) => {
+            let found: Vec<LayerStats> = decoded
+                .layers
+                .into_iter()
+                .filter(|layer| layers.is_empty() || layers.contains(&layer.name))
+                .map(|layer| LayerStats {
+                    name: layer.name,
+                    feature_count: layer.features.len(),
+                })
+                .collect();
+            if found.is_empty() && !layers.is_empty() {
+                return None;
+            }
+            stats.feature_count = Some(found.iter().map(|layer| layer.feature_count).sum());
+            stats.layers = Some(found);
+        }
+        Err(e) => stats.error = Some(e.to_string()),
+    }
+    Some(stats)
+}
+
+/// Stream one NDJSON line per tile of `inspection`
+///
+/// Tiles that disappeared since they were listed, or that are filtered out
+/// by `layers`, are skipped.
+pub fn stream(
+    source: Arc<dyn TileSource>,
+    inspection: Inspection,
+    layers: Vec<String>,
+) -> impl Stream<Item = Bytes> {
+    let z = inspection.z;
+    let layers = Arc::new(layers);
+
+    futures::stream::iter(inspection.tiles)
+        .map(move |(x, y)| {
+            let source = source.clone();
+            let layers = layers.clone();
+            async move {
+                let stats = match source.get_tile(z, x, y).await {
+                    Ok(Some(tile)) => tile_stats(&tile, &layers)?,
+                    Ok(None) => return None,
+                    Err(e) => TileStats {
+                        bytes: 0,
+                        layers: None,
+                        feature_count: None,
+                        error: Some(e.to_string()),
+                    },
+                };
+                Some(line(x, y, &stats))
+            }
+        })
+        .buffered(CONCURRENCY)
+        .filter_map(std::future::ready)
+}
+
+/// `{"x_y": {...}}` followed by a newline
+fn line(x: u32, y: u32, stats: &TileStats) -> Bytes {
+    let entry = HashMap::from([(format!("{}_{}", x, y), stats)]);
+    let mut line = serde_json::to_vec(&entry).unwrap_or_default();
+    line.push(b'\n');
+    Bytes::from(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sources::TileCompression;
+
+    #[test]
+    fn test_tile_range() {
+        let world = tile_range(2, None);
+        assert_eq!(
+            (world.min_x, world.max_x, world.min_y, world.max_y),
+            (0, 3, 0, 3)
+        );
+
+        // Zurich
+        let range = tile_range(10, Some([8.45, 47.32, 8.62, 47.43]));
+        assert!(range.contains(536, 358));
+        assert!(!range.contains(0, 0));
+    }
+
+    #[test]
+    fn test_raster_tile_stats() {
+        let tile = TileData {
+            data: Bytes::from_static(b"\x89PNG"),
+            format: TileFormat::Png,
+            compression: TileCompression::None,
+        };
+        let stats = tile_stats(&tile, &[]).unwrap();
+        assert_eq!(stats.bytes, 4);
+        assert_eq!(stats.layers, None);
+        assert_eq!(serde_json::to_string(&stats).unwrap(), r#"{"bytes":4}"#);
+        assert!(tile_stats(&tile, &["roads".to_string()]).is_none());
+    }
+
+    #[test]
+    fn test_line() {
+        let stats = TileStats {
+            bytes: 4096,
+            layers: Some(vec![LayerStats {
+                name: "roads".to_string(),
+                feature_count: 12,
+            }]),
+            feature_count: Some(12),
+            error: None,
+        };
+        assert_eq!(
+            line(3, 5, &stats),
+            Bytes::from_static(
+                b"{\"3_5\":{\"bytes\":4096,\"layers\":[{\"name\":\"roads\",\"feature_count\":12}],\"feature_count\":12}}\n"
+            )
+        );
+    }
+}
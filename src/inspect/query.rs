@@ -0,0 +1,106 @@
+//! Contents of one tile across sources for `GET /tiles/{z}/{x}/{y}.json`
+//!
+//! Reports, for every source with data at the tile, its stored size and the
+//! layers it holds with their feature counts and the first few features as
+//! GeoJSON. Handy for finding out why a style layer draws nothing.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::error::{Result, TileServerError};
+use crate::sources::{self, SourceManager, TileFormat, TileScheme};
+
+/// Features converted to GeoJSON per layer
+pub const SAMPLE_FEATURES: usize = 5;
+
+/// Sources with data at one tile, by ID
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TileQuery {
+    pub sources: BTreeMap<String, SourceTile>,
+}
+
+/// One source's tile
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceTile {
+    /// Stored size, before decompression
+    pub bytes: usize,
+    /// Layers of a vector tile, by name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layers: Option<BTreeMap<String, LayerSample>>,
+    /// Why the tile could not be decoded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Feature count and leading features of one layer
+#[derive(Debug, Clone, Serialize)]
+pub struct LayerSample {
+    pub features: usize,
+    /// Up to `SAMPLE_FEATURES` features as GeoJSON
+    pub sample: Vec<serde_json::Value>,
+}
+
+/// Query tile `z/x/y` (XYZ rows) in every source, or only in `filter`
+///
+/// Sources without a tile there are left out. A filter naming a source that
+/// doesn't exist is an error.
+pub async fn query_tile(
+    sources: &SourceManager,
+    z: u8,
+    x: u32,
+    y: u32,
+    filter: &[String],
+) -> Result<TileQuery> {
+    sources::validate_tile_coords(z, x, y)?;
+
+    let ids: Vec<&str> = if filter.is_empty() {
+        sources.ids().into_iter().map(String::as_str).collect()
+    } else {
+        if let Some(missing) = filter.iter().find(|id| !sources.exists(id)) {
+            return Err(TileServerError::SourceNotFound(missing.clone()));
+        }
+        filter.iter().map(String::as_str).collect()
+    };
+
+    let tiles = futures::future::join_all(ids.into_iter().map(|id| async move {
+        let source = sources.get(id)?;
+        let row = source.metadata().request_row(z, y, Some(TileScheme::Xyz))?;
+        match source.get_tile_with_overzoom(z, x, row).await {
+            Ok(tile) => tile.map(|tile| (id, tile)),
+            Err(e) => {
+                tracing::debug!("Failed to read tile {}/{}/{} of {}: {}", z, x, y, id, e);
+                None
+            }
+        }
+    }))
+    .await;
+
+    let mut query = TileQuery::default();
+    for (id, tile) in tiles.into_iter().flatten() {
+        let mut entry = SourceTile {
+            bytes: tile.data.len(),
+            layers: None,
+            error: None,
+        };
+        if tile.format == TileFormat::Pbf {
+            match tile.decode_mvt() {
+                Ok(decoded) => {
+                    let layers = decoded
+                        .layers
+                        .into_iter()
+                        .map(|mut layer| {
+                            let features = layer.features.len();
+                            layer.features.truncate(SAMPLE_FEATURES);
+                            let sample = sources::mvt_layer_features(&mut layer);
+                            (layer.name, LayerSample { features, sample })
+                        })
+                        .collect();
+                    entry.layers = Some(layers);
+                }
+                Err(e) => entry.error = Some(e.to_string()),
+            }
+        }
+        query.sources.insert(id.to_string(), entry);
+    }
+    Ok(query)
+}
@@ -0,0 +1,602 @@
+//! Validation report for one tile (`GET /data/{source}/{z}/{x}/{y}/inspect`)
+//!
+//! Decodes a vector tile the way clients do and reports its layers, geometry
+//! types, property keys and sizes along with every problem found, much like
+//! `vt-validate`. Corrupt tiles produce a report of the decode error instead
+//! of failing the request. Strict mode also checks coordinates against the
+//! buffered extent and ring winding order (MVT 2.1, section 4.3.4).
+
+use geozero::mvt::tile::{GeomType, Layer};
+use geozero::mvt::Message;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+
+use crate::sources::overzoom::{ring_area, Point};
+use crate::sources::{buffer_units, TileCompression, TileData, TileFormat};
+
+/// Most problems listed in a report; `error_count` has the total
+pub const MAX_ERRORS: usize = 100;
+
+/// Pixels allowed outside the tile in strict mode, MapLibre's default buffer
+pub const DEFAULT_BUFFER: u32 = 128;
+
+const CMD_MOVE_TO: u32 = 1;
+const CMD_LINE_TO: u32 = 2;
+const CMD_CLOSE_PATH: u32 = 7;
+
+/// Checks beyond decoding
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Checks {
+    /// Check coordinates against the buffered extent and ring winding order
+    pub strict: bool,
+    /// Pixels allowed outside the tile, measured like `?buffer=` on tiles
+    pub buffer: Option<u32>,
+}
+
+/// Everything found in one tile
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TileReport {
+    pub format: TileFormat,
+    /// `none`, `gzip`, `zstd` or `br`
+    pub compression: &'static str,
+    /// Stored size, before decompression
+    pub bytes: usize,
+    /// Size after decompression, for vector tiles that could be decompressed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decompressed_bytes: Option<usize>,
+    /// Whether no problems were found
+    pub valid: bool,
+    pub strict: bool,
+    pub layers: Vec<LayerReport>,
+    pub feature_count: usize,
+    /// Problems found, including those beyond `MAX_ERRORS`
+    pub error_count: usize,
+    pub errors: Vec<TileError>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LayerReport {
+    pub name: String,
+    pub version: u32,
+    pub extent: u32,
+    pub feature_count: usize,
+    pub geometry_types: GeometryTypes,
+    /// Distinct values of each property key
+    pub keys: BTreeMap<String, usize>,
+}
+
+/// Features of each geometry type
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct GeometryTypes {
+    pub point: usize,
+    pub linestring: usize,
+    pub polygon: usize,
+    pub unknown: usize,
+}
+
+/// A problem with the tile, one of its layers or one of their features
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TileError {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layer: Option<String>,
+    /// Index of the feature within the layer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub feature: Option<usize>,
+    pub message: String,
+}
+
+impl TileReport {
+    fn error(&mut self, layer: Option<&str>, feature: Option<usize>, message: String) {
+        self.error_count += 1;
+        if self.errors.len() < MAX_ERRORS {
+            self.errors.push(TileError {
+                layer: layer.map(str::to_string),
+                feature,
+                message,
+            });
+        }
+    }
+}
+
+/// Check a tile; decode failures are reported rather than returned
+pub fn report(tile: &TileData, checks: Checks) -> TileReport {
+    let mut report = TileReport {
+        format: tile.format,
+        compression: tile.compression.content_encoding().unwrap_or("none"),
+        bytes: tile.data.len(),
+        decompressed_bytes: None,
+        valid: true,
+        strict: checks.strict,
+        layers: Vec::new(),
+        feature_count: 0,
+        error_count: 0,
+        errors: Vec::new(),
+    };
+    if tile.format != TileFormat::Pbf {
+        return report;
+    }
+
+    let data = match tile.decompress() {
+        Ok(data) => data,
+        Err(e) => {
+            report.error(None, None, e.to_string());
+            report.valid = false;
+            return report;
+        }
+    };
+    report.decompressed_bytes = Some(data.len());
+    if tile.compression == TileCompression::None && data.starts_with(&[0x1f, 0x8b]) {
+        report.error(
+            None,
+            None,
+            "Tile data is gzip-compressed but stored as uncompressed".to_string(),
+        );
+    }
+
+    match geozero::mvt::Tile::decode(data.as_slice()) {
+        Ok(decoded) => {
+            let mut names = HashSet::new();
+            for layer in &decoded.layers {
+                if !names.insert(layer.name.as_str()) {
+                    report.error(Some(&layer.name), None, "Duplicate layer name".to_string());
+                }
+                let layer_report = check_layer(layer, checks, &mut report);
+                report.feature_count += layer_report.feature_count;
+                report.layers.push(layer_report);
+            }
+        }
+        Err(e) => report.error(None, None, format!("Failed to decode MVT tile: {}", e)),
+    }
+    report.valid = report.error_count == 0;
+    report
+}
+
+fn check_layer(layer: &Layer, checks: Checks, report: &mut TileReport) -> LayerReport {
+    let name = Some(layer.name.as_str());
+    let extent = layer.extent.unwrap_or(4096);
+    if layer.name.is_empty() {
+        report.error(name, None, "Layer has no name".to_string());
+    }
+    if layer.version != 1 && layer.version != 2 {
+        report.error(
+            name,
+            None,
+            format!("Unsupported layer version {}", layer.version),
+        );
+    }
+    if extent == 0 {
+        report.error(name, None, "Layer extent is 0".to_string());
+    }
+
+    let values: Vec<String> = layer.values.iter().map(|v| format!("{:?}", v)).collect();
+    let mut keys: BTreeMap<&str, HashSet<&str>> = BTreeMap::new();
+    let mut geometry_types = GeometryTypes::default();
+
+    for (index, feature) in layer.features.iter().enumerate() {
+        let mut error = |message: String| report.error(name, Some(index), message);
+
+        if feature.tags.len() % 2 != 0 {
+            error("Tags have an odd length".to_string());
+        }
+        for tag in feature.tags.chunks_exact(2) {
+            match (layer.keys.get(tag[0] as usize), values.get(tag[1] as usize)) {
+                (Some(key), Some(value)) => {
+                    keys.entry(key.as_str()).or_default().insert(value.as_str());
+                }
+                (None, _) => error(format!("Key index {} is out of range", tag[0])),
+                (_, None) => error(format!("Value index {} is out of range", tag[1])),
+            }
+        }
+
+        // Missing and unrecognized types both read as `Unknown`
+        let geom_type = feature.r#type();
+        match geom_type {
+            GeomType::Point => geometry_types.point += 1,
+            GeomType::Linestring => geometry_types.linestring += 1,
+            GeomType::Polygon => geometry_types.polygon += 1,
+            GeomType::Unknown => {
+                geometry_types.unknown += 1;
+                error("Unknown geometry type".to_string());
+                continue;
+            }
+        }
+        match parse_geometry(geom_type, &feature.geometry) {
+            Ok(parts) if checks.strict => {
+                let buffer = buffer_units(checks.buffer.unwrap_or(DEFAULT_BUFFER), extent);
+                for message in strict_problems(geom_type, &parts, extent, buffer) {
+                    error(message);
+                }
+            }
+            Ok(_) => {}
+            Err(message) => error(message),
+        }
+    }
+
+    LayerReport {
+        name: layer.name.clone(),
+        version: layer.version,
+        extent,
+        feature_count: layer.features.len(),
+        geometry_types,
+        keys: keys
+            .into_iter()
+            .map(|(key, values)| (key.to_string(), values.len()))
+            .collect(),
+    }
+}
+
+/// Decode a geometry, checking its commands against its type (MVT 2.1, 4.3)
+///
+/// Points come back as one part; lines and rings as one part each.
+fn parse_geometry(geom_type: GeomType, geometry: &[u32]) -> Result<Vec<Vec<Point>>, String> {
+    let zigzag = |v: u32| ((v >> 1) as i64) ^ -((v & 1) as i64);
+
+    let mut parts: Vec<Vec<Point>> = Vec::new();
+    let (mut cx, mut cy) = (0i64, 0i64);
+    let mut previous = None;
+    let mut idx = 0;
+
+    while idx < geometry.len() {
+        let command = geometry[idx] & 0x7;
+        let count = (geometry[idx] >> 3) as usize;
+        idx += 1;
+
+        match command {
+            CMD_CLOSE_PATH => {
+                if geom_type != GeomType::Polygon {
+                    return Err("ClosePath outside a polygon".to_string());
+                }
+                if count != 1 {
+                    return Err(format!("ClosePath with count {}, expected 1", count));
+                }
+                if previous != Some(CMD_LINE_TO) {
+                    return Err("ClosePath without a preceding LineTo".to_string());
+                }
+                if parts.last().is_some_and(|ring| ring.len() < 3) {
+                    return Err("Ring has fewer than 3 points".to_string());
+                }
+            }
+            CMD_MOVE_TO | CMD_LINE_TO => {
+                let name = if command == CMD_MOVE_TO {
+                    "MoveTo"
+                } else {
+                    "LineTo"
+                };
+                if count == 0 {
+                    return Err(format!("{} with count 0", name));
+                }
+                let left = geometry.len() - idx;
+                if count.saturating_mul(2) > left {
+                    return Err(format!(
+                        "{} needs {} parameters but only {} remain",
+                        name,
+                        count.saturating_mul(2),
+                        left
+                    ));
+                }
+                let points: Vec<Point> = geometry[idx..idx + 2 * count]
+                    .chunks_exact(2)
+                    .map(|pair| {
+                        cx += zigzag(pair[0]);
+                        cy += zigzag(pair[1]);
+                        [cx, cy]
+                    })
+                    .collect();
+                idx += 2 * count;
+
+                match (command, geom_type) {
+                    (CMD_MOVE_TO, GeomType::Point) => {
+                        if !parts.is_empty() {
+                            return Err("Point geometry has more than one MoveTo".to_string());
+                        }
+                        parts.push(points);
+                    }
+                    (CMD_MOVE_TO, _) => {
+                        if count != 1 {
+                            return Err(format!("MoveTo with count {}, expected 1", count));
+                        }
+                        if geom_type == GeomType::Linestring && previous == Some(CMD_MOVE_TO) {
+                            return Err("MoveTo without a following LineTo".to_string());
+                        }
+                        if geom_type == GeomType::Polygon && previous == Some(CMD_LINE_TO) {
+                            return Err("Ring is not closed with ClosePath".to_string());
+                        }
+                        parts.push(points);
+                    }
+                    (_, GeomType::Point) => {
+                        return Err("LineTo in a point geometry".to_string());
+                    }
+                    _ => match parts.last_mut() {
+                        Some(part) if previous != Some(CMD_CLOSE_PATH) => part.extend(points),
+                        _ => return Err("LineTo without a preceding MoveTo".to_string()),
+                    },
+                }
+            }
+            other => return Err(format!("Unknown command {}", other)),
+        }
+        previous = Some(command);
+    }
+
+    match (geom_type, previous) {
+        (_, None) => Err("Empty geometry".to_string()),
+        (GeomType::Linestring, Some(CMD_MOVE_TO)) => {
+            Err("MoveTo without a following LineTo".to_string())
+        }
+        (GeomType::Polygon, Some(command)) if command != CMD_CLOSE_PATH => {
+            Err("Ring is not closed with ClosePath".to_string())
+        }
+        _ => Ok(parts),
+    }
+}
+
+/// Coordinates outside the buffered extent, and polygon winding problems
+///
+/// Exterior rings have positive area in tile coordinates (clockwise with y
+/// down) and interior rings negative area, so a polygon must start with a
+/// positive ring and no ring may have zero area.
+fn strict_problems(
+    geom_type: GeomType,
+    parts: &[Vec<Point>],
+    extent: u32,
+    buffer: u32,
+) -> Vec<String> {
+    let mut problems = Vec::new();
+    let (min, max) = (-(buffer as i64), extent as i64 + buffer as i64);
+    if let Some(point) = parts
+        .iter()
+        .flatten()
+        .find(|p| p.iter().any(|&c| c < min || c > max))
+    {
+        problems.push(format!(
+            "Coordinate ({}, {}) is outside the extent buffered by {} units",
+            point[0], point[1], buffer
+        ));
+    }
+
+    if geom_type == GeomType::Polygon {
+        let areas: Vec<i64> = parts.iter().map(|ring| ring_area(ring)).collect();
+        if areas.first().is_some_and(|&area| area < 0) {
+            problems.push("Polygon starts with an interior ring".to_string());
+        }
+        if areas.contains(&0) {
+            problems.push("Ring has zero area".to_string());
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use geozero::mvt::tile::{Feature, Value};
+
+    /// Command integer followed by zigzag-encoded deltas
+    fn command(id: u32, count: u32, deltas: &[i32]) -> Vec<u32> {
+        let mut encoded = vec![(count << 3) | id];
+        encoded.extend(deltas.iter().map(|&d| ((d << 1) ^ (d >> 31)) as u32));
+        encoded
+    }
+
+    fn tile(features: Vec<Feature>) -> TileData {
+        let layer = Layer {
+            version: 2,
+            name: "test".to_string(),
+            features,
+            keys: vec!["kind".to_string()],
+            values: vec![
+                Value {
+                    string_value: Some("a".to_string()),
+                    ..Default::default()
+                },
+                Value {
+                    string_value: Some("b".to_string()),
+                    ..Default::default()
+                },
+            ],
+            extent: Some(4096),
+        };
+        TileData {
+            data: Bytes::from(
+                geozero::mvt::Tile {
+                    layers: vec![layer],
+                }
+                .encode_to_vec(),
+            ),
+            format: TileFormat::Pbf,
+            compression: TileCompression::None,
+        }
+    }
+
+    fn feature(geom_type: GeomType, geometry: Vec<u32>, tags: Vec<u32>) -> Feature {
+        Feature {
+            id: None,
+            tags,
+            r#type: Some(geom_type as i32),
+            geometry,
+        }
+    }
+
+    /// A square ring from (x, y), clockwise in tile coordinates (an exterior ring)
+    /// or counter-clockwise (an interior ring)
+    fn square(x: i32, y: i32, size: i32, clockwise: bool) -> Vec<u32> {
+        let deltas = if clockwise {
+            [size, 0, 0, size, -size, 0]
+        } else {
+            [0, size, size, 0, 0, -size]
+        };
+        [
+            command(CMD_MOVE_TO, 1, &[x, y]),
+            command(CMD_LINE_TO, 3, &deltas),
+            command(CMD_CLOSE_PATH, 1, &[]),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn test_valid_tile_report() {
+        let tile = tile(vec![
+            feature(
+                GeomType::Point,
+                command(CMD_MOVE_TO, 1, &[10, 10]),
+                vec![0, 0],
+            ),
+            feature(
+                GeomType::Linestring,
+                [
+                    command(CMD_MOVE_TO, 1, &[0, 0]),
+                    command(CMD_LINE_TO, 1, &[100, 100]),
+                ]
+                .concat(),
+                vec![0, 1],
+            ),
+            feature(GeomType::Polygon, square(10, 10, 100, true), vec![0, 0]),
+        ]);
+        let report = report(&tile, Checks::default());
+        assert!(report.valid, "{:?}", report.errors);
+        assert_eq!(report.compression, "none");
+        assert_eq!(report.decompressed_bytes, Some(report.bytes));
+        assert_eq!(report.feature_count, 3);
+
+        let layer = &report.layers[0];
+        assert_eq!((layer.name.as_str(), layer.extent), ("test", 4096));
+        assert_eq!(
+            layer.geometry_types,
+            GeometryTypes {
+                point: 1,
+                linestring: 1,
+                polygon: 1,
+                unknown: 0
+            }
+        );
+        assert_eq!(layer.keys, BTreeMap::from([("kind".to_string(), 2)]));
+    }
+
+    #[test]
+    fn test_geometry_errors() {
+        let tile = tile(vec![
+            // Truncated parameters
+            feature(GeomType::Point, vec![(2 << 3) | CMD_MOVE_TO, 2, 2], vec![]),
+            feature(GeomType::Point, command(5, 1, &[0, 0]), vec![]),
+            feature(
+                GeomType::Linestring,
+                command(CMD_MOVE_TO, 1, &[0, 0]),
+                vec![],
+            ),
+            // Ring without ClosePath
+            feature(
+                GeomType::Polygon,
+                [
+                    command(CMD_MOVE_TO, 1, &[0, 0]),
+                    command(CMD_LINE_TO, 2, &[10, 0, 0, 10]),
+                ]
+                .concat(),
+                vec![],
+            ),
+            feature(GeomType::Unknown, vec![], vec![]),
+            feature(
+                GeomType::Point,
+                command(CMD_MOVE_TO, 1, &[1, 1]),
+                vec![0, 7],
+            ),
+        ]);
+        let report = report(&tile, Checks::default());
+        assert!(!report.valid);
+        let messages: Vec<(Option<usize>, &str)> = report
+            .errors
+            .iter()
+            .map(|e| (e.feature, e.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (Some(0), "MoveTo needs 4 parameters but only 2 remain"),
+                (Some(1), "Unknown command 5"),
+                (Some(2), "MoveTo without a following LineTo"),
+                (Some(3), "Ring is not closed with ClosePath"),
+                (Some(4), "Unknown geometry type"),
+                (Some(5), "Value index 7 is out of range"),
+            ]
+        );
+        assert_eq!(report.layers[0].geometry_types.unknown, 1);
+    }
+
+    #[test]
+    fn test_strict_checks() {
+        let tile = tile(vec![
+            feature(GeomType::Polygon, square(10, 10, 100, true), vec![]),
+            // Counter-clockwise exterior ring
+            feature(GeomType::Polygon, square(10, 10, 100, false), vec![]),
+            feature(
+                GeomType::Point,
+                command(CMD_MOVE_TO, 1, &[5200, 10]),
+                vec![],
+            ),
+            feature(
+                GeomType::Point,
+                command(CMD_MOVE_TO, 1, &[4200, -200]),
+                vec![],
+            ),
+        ]);
+        assert!(report(&tile, Checks::default()).valid);
+
+        let strict = report(
+            &tile,
+            Checks {
+                strict: true,
+                buffer: None,
+            },
+        );
+        assert!(strict.strict);
+        let messages: Vec<(Option<usize>, &str)> = strict
+            .errors
+            .iter()
+            .map(|e| (e.feature, e.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (Some(1), "Polygon starts with an interior ring"),
+                (
+                    Some(2),
+                    "Coordinate (5200, 10) is outside the extent buffered by 1024 units"
+                ),
+            ]
+        );
+
+        let tight = report(
+            &tile,
+            Checks {
+                strict: true,
+                buffer: Some(8),
+            },
+        );
+        assert_eq!(tight.error_count, 3);
+    }
+
+    #[test]
+    fn test_corrupt_tile_is_reported() {
+        let mut tile = tile(vec![feature(
+            GeomType::Point,
+            command(CMD_MOVE_TO, 1, &[10, 10]),
+            vec![],
+        )]);
+        tile.data = tile.data.slice(..tile.data.len() - 3);
+        let report = report(&tile, Checks::default());
+        assert!(!report.valid);
+        assert!(report.layers.is_empty());
+        assert_eq!(report.errors.len(), 1);
+        assert!(report.errors[0]
+            .message
+            .starts_with("Failed to decode MVT tile"));
+
+        let raster = TileData {
+            data: Bytes::from_static(b"\x89PNG"),
+            format: TileFormat::Png,
+            compression: TileCompression::None,
+        };
+        let report = super::report(&raster, Checks::default());
+        assert!(report.valid);
+        assert_eq!(report.decompressed_bytes, None);
+    }
+}
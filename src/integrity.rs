@@ -0,0 +1,262 @@
+//! Source integrity checks for `POST /data/{source}/validate`
+//!
+//! Runs the backend's own checks ([`TileSource::check_integrity`]: SQLite
+//! `PRAGMA integrity_check` for MBTiles, header and directory consistency for
+//! PMTiles, an `EXPLAIN` of the tile query for PostgreSQL tables), then reads
+//! and decodes a sample of stored tiles. Checks stop once the configured
+//! duration runs out, and the report says so. Stopping drops the running
+//! check, and MBTiles interrupts its SQLite query then, returning the
+//! connection to the pool.
+//!
+//! With `?stream=true` a progress event is written as newline-delimited JSON
+//! before each check, followed by the report.
+
+use bytes::Bytes;
+use futures::Stream;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+use crate::inspect::tile::{self, Checks};
+use crate::inspect::tile_range;
+use crate::sources::{TileScheme, TileSource};
+
+/// Zoom levels tiles are sampled from, spread between minzoom and maxzoom
+const SAMPLE_ZOOMS: u8 = 4;
+
+/// Tiles read and decoded at each sampled zoom level
+const SAMPLE_TILES: usize = 8;
+
+/// A problem found by a check
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct IntegrityIssue {
+    /// Check that found it, e.g. `integrity_check` or `tiles`
+    pub check: &'static str,
+    pub message: String,
+}
+
+impl IntegrityIssue {
+    pub fn new(check: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            check,
+            message: message.into(),
+        }
+    }
+}
+
+/// How long one check took
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CheckTiming {
+    pub check: &'static str,
+    pub duration_ms: u64,
+}
+
+/// Outcome of validating a source
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct IntegrityReport {
+    pub source: String,
+    pub backend: &'static str,
+    /// Whether every check ran and none found a problem
+    pub valid: bool,
+    /// Whether every check ran before the time ran out
+    pub complete: bool,
+    pub issues: Vec<IntegrityIssue>,
+    /// Checks that finished, in the order they ran
+    pub checks: Vec<CheckTiming>,
+    /// Tiles read and decoded
+    pub sampled_tiles: usize,
+    pub duration_ms: u64,
+}
+
+/// Line of a streamed validation
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ValidateEvent {
+    /// A check is starting
+    Progress { check: &'static str },
+    /// All checks are done, or the time ran out
+    Report(IntegrityReport),
+}
+
+/// Results gathered so far, kept when the checks are cut short
+struct Progress {
+    issues: Vec<IntegrityIssue>,
+    checks: Vec<CheckTiming>,
+    sampled_tiles: usize,
+    events: Option<mpsc::Sender<ValidateEvent>>,
+}
+
+impl Progress {
+    async fn start(&self, check: &'static str) -> Instant {
+        if let Some(events) = &self.events {
+            let _ = events.send(ValidateEvent::Progress { check }).await;
+        }
+        Instant::now()
+    }
+
+    fn finish(&mut self, check: &'static str, started: Instant) {
+        self.checks.push(CheckTiming {
+            check,
+            duration_ms: started.elapsed().as_millis() as u64,
+        });
+    }
+}
+
+/// Validate `source`, stopping after `max_duration`
+pub async fn validate(
+    id: &str,
+    source: &dyn TileSource,
+    max_duration: Duration,
+) -> IntegrityReport {
+    validate_with_events(id, source, max_duration, None).await
+}
+
+/// Validate `source` in a background task, streaming progress as NDJSON
+pub fn validate_stream(
+    id: String,
+    source: Arc<dyn TileSource>,
+    max_duration: Duration,
+) -> impl Stream<Item = Bytes> {
+    let (tx, rx) = mpsc::channel(16);
+    tokio::spawn(async move {
+        let report =
+            validate_with_events(&id, source.as_ref(), max_duration, Some(tx.clone())).await;
+        let _ = tx.send(ValidateEvent::Report(report)).await;
+    });
+
+    futures::stream::unfold(rx, |mut rx| async move {
+        let event = rx.recv().await?;
+        let mut line = serde_json::to_vec(&event).unwrap_or_default();
+        line.push(b'\n');
+        Some((Bytes::from(line), rx))
+    })
+}
+
+async fn validate_with_events(
+    id: &str,
+    source: &dyn TileSource,
+    max_duration: Duration,
+    events: Option<mpsc::Sender<ValidateEvent>>,
+) -> IntegrityReport {
+    let started = Instant::now();
+    let mut progress = Progress {
+        issues: Vec::new(),
+        checks: Vec::new(),
+        sampled_tiles: 0,
+        events,
+    };
+
+    let complete = tokio::time::timeout(max_duration, run_checks(source, &mut progress))
+        .await
+        .is_ok();
+    if !complete {
+        progress.issues.push(IntegrityIssue::new(
+            "timeout",
+            format!("Validation stopped after {:?}", max_duration),
+        ));
+    }
+
+    IntegrityReport {
+        source: id.to_string(),
+        backend: source.backend(),
+        valid: complete && progress.issues.is_empty(),
+        complete,
+        issues: progress.issues,
+        checks: progress.checks,
+        sampled_tiles: progress.sampled_tiles,
+        duration_ms: started.elapsed().as_millis() as u64,
+    }
+}
+
+async fn run_checks(source: &dyn TileSource, progress: &mut Progress) {
+    let started = progress.start("backend").await;
+    match source.check_integrity().await {
+        Ok(issues) => progress.issues.extend(issues),
+        Err(e) => progress
+            .issues
+            .push(IntegrityIssue::new("backend", e.to_string())),
+    }
+    progress.finish("backend", started);
+
+    let started = progress.start("tiles").await;
+    sample_tiles(source, progress).await;
+    progress.finish("tiles", started);
+}
+
+/// Zoom levels to sample: minzoom, maxzoom and up to two in between
+pub fn sample_zooms(minzoom: u8, maxzoom: u8) -> Vec<u8> {
+    if maxzoom <= minzoom {
+        return vec![minzoom];
+    }
+    let span = u32::from(maxzoom - minzoom);
+    let steps = u32::from(SAMPLE_ZOOMS - 1).min(span);
+    let mut zooms: Vec<u8> = (0..=steps)
+        .map(|i| minzoom + (span * i / steps) as u8)
+        .collect();
+    zooms.dedup();
+    zooms
+}
+
+/// Read and decode the first stored tiles of a few zoom levels
+async fn sample_tiles(source: &dyn TileSource, progress: &mut Progress) {
+    let metadata = source.metadata();
+    for z in sample_zooms(
+        metadata.minzoom,
+        metadata.maxzoom.min(crate::sources::MAX_TILE_ZOOM),
+    ) {
+        let range = tile_range(z, metadata.bounds);
+        let tiles = match source.list_tiles(range, SAMPLE_TILES).await {
+            Ok(Some(tiles)) => tiles,
+            // Sources that cannot list their tiles are not sampled
+            Ok(None) => return,
+            Err(e) => {
+                progress.issues.push(IntegrityIssue::new(
+                    "tiles",
+                    format!("Failed to list tiles at zoom {}: {}", z, e),
+                ));
+                continue;
+            }
+        };
+
+        for (x, y) in tiles {
+            // Rows in the source's scheme, as used by `get_tile` and tile URLs
+            let Some(row) = metadata.request_row(z, y, Some(TileScheme::Xyz)) else {
+                continue;
+            };
+            progress.sampled_tiles += 1;
+            match source.get_tile(z, x, row).await {
+                Ok(Some(data)) => {
+                    let report = tile::report(&data, Checks::default());
+                    if let Some(error) = report.errors.first().filter(|_| !report.valid) {
+                        progress.issues.push(IntegrityIssue::new(
+                            "tiles",
+                            format!("Tile {}/{}/{}: {}", z, x, row, error.message),
+                        ));
+                    }
+                }
+                Ok(None) => progress.issues.push(IntegrityIssue::new(
+                    "tiles",
+                    format!("Tile {}/{}/{} is listed but cannot be read", z, x, row),
+                )),
+                Err(e) => progress.issues.push(IntegrityIssue::new(
+                    "tiles",
+                    format!("Tile {}/{}/{}: {}", z, x, row, e),
+                )),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_zooms() {
+        assert_eq!(sample_zooms(0, 14), vec![0, 4, 9, 14]);
+        assert_eq!(sample_zooms(5, 7), vec![5, 6, 7]);
+        assert_eq!(sample_zooms(3, 3), vec![3]);
+        assert_eq!(sample_zooms(0, 1), vec![0, 1]);
+    }
+}
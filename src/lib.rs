@@ -2,19 +2,51 @@
 //!
 //! This module exposes the core functionality for testing and embedding.
 
+pub mod admin;
 pub mod cache_control;
 pub mod config;
+pub mod connections;
+pub mod cors;
+pub mod diff;
 pub mod error;
+pub mod events;
+pub mod export;
+pub mod files;
+pub mod fonts;
+pub mod health;
+pub mod inspect;
+pub mod integrity;
+pub mod listener;
+pub mod mapbox_static;
+pub mod middleware;
+pub mod ogcapi;
 pub mod openapi;
+pub mod preview;
+pub mod quality;
+pub mod query;
+pub mod range;
 pub mod render;
+pub mod request_id;
 pub mod sources;
+pub mod startup;
 pub mod styles;
+pub mod telemetry;
+pub mod thumbnail;
+pub mod timeout;
+pub mod tls;
+pub mod validate;
+pub mod watch;
 pub mod wmts;
 
 pub use config::Config;
 pub use error::{Result, TileServerError};
-pub use sources::{SourceManager, TileCompression, TileData, TileFormat, TileJson, TileSource};
-pub use styles::{rewrite_style_for_api, Style, StyleInfo, StyleManager, UrlQueryParams};
+pub use sources::{
+    SharedSources, SourceManager, TileCompression, TileData, TileFormat, TileInfo, TileJson,
+    TileJsonVersion, TileSource,
+};
+pub use styles::{
+    rewrite_style_for_api, SharedStyles, Style, StyleInfo, StyleManager, UrlQueryParams,
+};
 
 #[cfg(feature = "postgres")]
 pub use config::{PostgresConfig, PostgresFunctionConfig};
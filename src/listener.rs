@@ -0,0 +1,274 @@
+//! Listening sockets for the main server
+//!
+//! Besides `host:port`, the server can listen on a Unix domain socket
+//! (`[server] listen = "unix:/run/tileserver.sock"`) for sidecar deployments
+//! behind a local reverse proxy, or take over the socket passed by systemd
+//! socket activation (`LISTEN_FDS`).
+
+use anyhow::Context;
+use axum::Router;
+use std::net::SocketAddr;
+
+#[cfg(unix)]
+use std::os::unix::{
+    fs::{FileTypeExt, PermissionsExt},
+    io::{FromRawFd, IntoRawFd, RawFd},
+    net::{UnixListener, UnixStream},
+};
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+
+use crate::config::ServerConfig;
+
+/// First file descriptor passed by systemd (`SD_LISTEN_FDS_START`)
+#[cfg(unix)]
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+/// A bound listening socket
+#[derive(Debug)]
+pub enum Listener {
+    Tcp(std::net::TcpListener),
+    #[cfg(unix)]
+    Unix(UnixSocket),
+}
+
+impl Listener {
+    /// Human-readable address for logs
+    pub fn describe(&self) -> String {
+        match self {
+            Listener::Tcp(listener) => listener
+                .local_addr()
+                .map(|addr| addr.to_string())
+                .unwrap_or_else(|_| "unknown address".to_string()),
+            #[cfg(unix)]
+            Listener::Unix(socket) => match socket.listener.local_addr() {
+                Ok(addr) => match addr.as_pathname() {
+                    Some(path) => format!("unix:{}", path.display()),
+                    None => "unnamed unix socket".to_string(),
+                },
+                Err(_) => "unix socket".to_string(),
+            },
+        }
+    }
+}
+
+/// A Unix domain socket listener
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct UnixSocket {
+    pub listener: UnixListener,
+    /// Removed once the server stops; `None` when systemd owns the socket
+    file: Option<SocketFile>,
+}
+
+/// Deletes the socket file when dropped
+#[cfg(unix)]
+#[derive(Debug)]
+struct SocketFile(PathBuf);
+
+#[cfg(unix)]
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.0) {
+            tracing::warn!("Failed to remove socket {}: {}", self.0.display(), e);
+        }
+    }
+}
+
+/// Open the listener configured in `[server]`: a Unix socket if `listen` is
+/// set, otherwise `host:port`
+pub fn bind(server: &ServerConfig) -> anyhow::Result<Listener> {
+    if let Some(path) = server.unix_socket_path()? {
+        #[cfg(unix)]
+        {
+            let mode = server.socket_mode()?;
+            let socket = bind_unix(&path, mode)
+                .with_context(|| format!("Failed to bind {}", path.display()))?;
+            return Ok(Listener::Unix(socket));
+        }
+        #[cfg(not(unix))]
+        anyhow::bail!(
+            "Unix sockets are not supported on this platform ({})",
+            path.display()
+        );
+    }
+
+    let addr: SocketAddr = format!("{}:{}", server.host, server.port).parse()?;
+    let listener =
+        std::net::TcpListener::bind(addr).with_context(|| format!("Failed to bind {}", addr))?;
+    Ok(Listener::Tcp(listener))
+}
+
+/// Bind a Unix socket at `path`, replacing a stale socket left by a crash
+#[cfg(unix)]
+pub fn bind_unix(path: &Path, mode: Option<u32>) -> std::io::Result<UnixSocket> {
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", path.display()),
+            ));
+        }
+        if UnixStream::connect(path).is_ok() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AddrInUse,
+                format!("{} is in use by another process", path.display()),
+            ));
+        }
+        std::fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+    let file = SocketFile(path.to_path_buf());
+    if let Some(mode) = mode {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    }
+
+    Ok(UnixSocket {
+        listener,
+        file: Some(file),
+    })
+}
+
+/// Take the socket passed by systemd socket activation, if any
+///
+/// The environment is left as-is: `LISTEN_PID` already keeps child processes
+/// from claiming the socket.
+#[cfg(unix)]
+pub fn from_systemd() -> anyhow::Result<Option<Listener>> {
+    let pid = std::env::var("LISTEN_PID").ok();
+    let fds = std::env::var("LISTEN_FDS").ok();
+
+    match listen_fds(pid.as_deref(), fds.as_deref(), std::process::id()) {
+        0 => Ok(None),
+        // SAFETY: systemd hands this process ownership of the descriptor
+        1 => Ok(Some(
+            unsafe { from_fd(SD_LISTEN_FDS_START) }
+                .context("Failed to use the socket passed by systemd")?,
+        )),
+        n => anyhow::bail!(
+            "systemd passed {} sockets, but only one listener is supported",
+            n
+        ),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn from_systemd() -> anyhow::Result<Option<Listener>> {
+    Ok(None)
+}
+
+/// Number of sockets passed to this process per `LISTEN_PID`/`LISTEN_FDS`
+#[cfg(unix)]
+fn listen_fds(pid: Option<&str>, fds: Option<&str>, own_pid: u32) -> usize {
+    if pid.and_then(|pid| pid.parse::<u32>().ok()) != Some(own_pid) {
+        return 0;
+    }
+    fds.and_then(|fds| fds.parse().ok()).unwrap_or(0)
+}
+
+/// Wrap an inherited listening socket, which may be TCP or Unix
+///
+/// # Safety
+///
+/// `fd` must be an open socket that nothing else owns.
+#[cfg(unix)]
+pub unsafe fn from_fd(fd: RawFd) -> std::io::Result<Listener> {
+    // Both types only wrap the descriptor; getsockname() tells the family apart
+    let tcp = std::net::TcpListener::from_raw_fd(fd);
+    if tcp.local_addr().is_ok() {
+        return Ok(Listener::Tcp(tcp));
+    }
+
+    let listener = UnixListener::from_raw_fd(tcp.into_raw_fd());
+    listener.local_addr()?;
+    Ok(Listener::Unix(UnixSocket {
+        listener,
+        file: None,
+    }))
+}
+
+/// Serve `router` on a Unix socket until `shutdown` completes, then remove
+/// the socket file
+#[cfg(unix)]
+pub async fn serve_unix<F>(socket: UnixSocket, router: Router, shutdown: F) -> std::io::Result<()>
+where
+    F: std::future::Future<Output = ()> + Send + 'static,
+{
+    let UnixSocket { listener, file } = socket;
+    listener.set_nonblocking(true)?;
+    let listener = tokio::net::UnixListener::from_std(listener)?;
+
+    let result = axum::serve(listener, router)
+        .with_graceful_shutdown(shutdown)
+        .await;
+    drop(file);
+    result
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listen_fds() {
+        assert_eq!(listen_fds(Some("42"), Some("1"), 42), 1);
+        assert_eq!(listen_fds(Some("42"), Some("2"), 42), 2);
+        // Meant for another process, e.g. our parent
+        assert_eq!(listen_fds(Some("41"), Some("1"), 42), 0);
+        assert_eq!(listen_fds(None, Some("1"), 42), 0);
+        assert_eq!(listen_fds(Some("42"), None, 42), 0);
+        assert_eq!(listen_fds(Some("42"), Some("x"), 42), 0);
+    }
+
+    #[test]
+    fn test_from_fd_detects_family() {
+        let tcp = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = tcp.local_addr().unwrap();
+        let listener = unsafe { from_fd(tcp.into_raw_fd()) }.unwrap();
+        assert!(matches!(&listener, Listener::Tcp(l) if l.local_addr().unwrap() == addr));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("inherited.sock");
+        let unix = UnixListener::bind(&path).unwrap();
+        let listener = unsafe { from_fd(unix.into_raw_fd()) }.unwrap();
+        assert_eq!(listener.describe(), format!("unix:{}", path.display()));
+
+        // An inherited socket belongs to systemd and is not removed
+        drop(listener);
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn test_bind_unix_replaces_stale_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tileserver.sock");
+
+        // Left behind without cleanup, nothing listening
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        let socket = bind_unix(&path, Some(0o660)).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o660);
+
+        // A live socket is not taken over
+        assert_eq!(
+            bind_unix(&path, None).unwrap_err().kind(),
+            std::io::ErrorKind::AddrInUse
+        );
+
+        drop(socket);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_bind_unix_keeps_regular_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "").unwrap();
+
+        assert!(bind_unix(&path, None).is_err());
+        assert!(path.exists());
+    }
+}
@@ -1,6 +1,10 @@
-//! HTTP request logging middleware
+//! Log output setup and HTTP request logging middleware
 //!
-//! Provides Martin/actix-web style request logging with the format:
+//! Logs are written as compact text, newline-delimited JSON or pretty
+//! multi-line text (`--log-format`), to stdout, stderr or a file
+//! (`--log-output`).
+//!
+//! Requests are logged Martin/actix-web style, with the format:
 //! `IP "METHOD PATH HTTP/VERSION" STATUS SIZE "REFERRER" "USER_AGENT" DURATION`
 //!
 //! Example output:
@@ -15,7 +19,64 @@ use axum::{
 };
 use opentelemetry::metrics::{Counter, Histogram};
 use opentelemetry::KeyValue;
-use std::{net::SocketAddr, sync::OnceLock, time::Instant};
+use std::{
+    sync::{Mutex, OnceLock},
+    time::Instant,
+};
+use tracing::Subscriber;
+use tracing_subscriber::{
+    fmt::{self, writer::BoxMakeWriter, MakeWriter},
+    registry::LookupSpan,
+    Layer,
+};
+
+use crate::cli::{LogFormat, LogOutput};
+use crate::middleware::real_ip::ClientIp;
+
+/// Layer writing log lines in `format` to `output`
+///
+/// Files are created if needed and appended to, without ANSI colors.
+pub fn fmt_layer<S>(
+    format: LogFormat,
+    output: &LogOutput,
+) -> std::io::Result<Box<dyn Layer<S> + Send + Sync>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let (writer, ansi) = match output {
+        LogOutput::Stdout => (BoxMakeWriter::new(std::io::stdout), true),
+        LogOutput::Stderr => (BoxMakeWriter::new(std::io::stderr), true),
+        LogOutput::File(path) => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?;
+            (BoxMakeWriter::new(Mutex::new(file)), false)
+        }
+    };
+    Ok(format_layer(format, writer, ansi))
+}
+
+fn format_layer<S, W>(format: LogFormat, writer: W, ansi: bool) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = fmt::layer().with_writer(writer);
+    let layer = if ansi { layer } else { layer.with_ansi(false) };
+    match format {
+        LogFormat::Text => layer.compact().boxed(),
+        LogFormat::Pretty => layer.pretty().boxed(),
+        // One object per line, with the event's fields at the top level and
+        // the fields of the current span under `span`
+        LogFormat::Json => layer
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .boxed(),
+    }
+}
 
 struct HttpMetrics {
     request_count: Counter<u64>,
@@ -61,26 +122,11 @@ pub async fn request_logger(request: Request<Body>, next: Next) -> Response<Body
         .unwrap_or_else(|| request.uri().path().to_string());
     let version = format!("{:?}", request.version());
 
-    // Get client IP from x-forwarded-for header or connection info
+    // Client IP, from the socket or the proxy headers when they are trusted
     let client_ip = request
-        .headers()
-        .get("x-forwarded-for")
-        .and_then(|v| v.to_str().ok())
-        .and_then(|s| s.split(',').next())
-        .map(|s| s.trim().to_string())
-        .or_else(|| {
-            request
-                .headers()
-                .get("x-real-ip")
-                .and_then(|v| v.to_str().ok())
-                .map(|s| s.to_string())
-        })
-        .or_else(|| {
-            request
-                .extensions()
-                .get::<axum::extract::ConnectInfo<SocketAddr>>()
-                .map(|ci| ci.0.ip().to_string())
-        })
+        .extensions()
+        .get::<ClientIp>()
+        .map(|ip| ip.0.to_string())
         .unwrap_or_else(|| "-".to_string());
 
     // Get referrer
@@ -143,3 +189,80 @@ pub async fn request_logger(request: Request<Body>, next: Next) -> Response<Body
 
     response
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    /// Log lines written by the layer under test
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_json_log_lines() {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::registry().with(format_layer(
+            LogFormat::Json,
+            move || writer.clone(),
+            true,
+        ));
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("tile", source = "zurich", z = 3);
+            let _entered = span.enter();
+            tracing::info!(bytes = 512, "served tile");
+            tracing::warn!("slow");
+        });
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2, "{}", output);
+
+        let line = &lines[0];
+        assert!(line["timestamp"].as_str().is_some_and(|t| t.contains('T')));
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["target"], module_path!());
+        assert_eq!(line["message"], "served tile");
+        assert_eq!(line["bytes"], 512);
+        assert_eq!(line["span"]["source"], "zurich");
+        assert_eq!(line["span"]["z"], 3);
+        assert!(line.get("spans").is_none());
+        assert_eq!(lines[1]["level"], "WARN");
+    }
+
+    #[test]
+    fn test_file_output_appends() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tileserver.log");
+        std::fs::write(&path, "earlier\n").unwrap();
+
+        let layer = fmt_layer(LogFormat::Text, &LogOutput::File(path.clone())).unwrap();
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || tracing::info!("started"));
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.starts_with("earlier\n"));
+        assert!(contents.contains("INFO"));
+        assert!(contents.contains("started"));
+        assert!(!contents.contains('\x1b'), "no ANSI colors in files");
+
+        let missing = LogOutput::File(dir.path().join("missing/dir/log"));
+        assert!(fmt_layer::<tracing_subscriber::Registry>(LogFormat::Json, &missing).is_err());
+    }
+}
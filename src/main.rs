@@ -1,42 +1,87 @@
 use axum::{
     extract::{Path, Query, State},
     http::{
-        header::{ACCEPT, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_TYPE},
-        HeaderMap, HeaderValue, Method, StatusCode, Uri,
+        header::{
+            ACCEPT, ACCEPT_ENCODING, ACCEPT_LANGUAGE, CACHE_CONTROL, CONTENT_DISPOSITION,
+            CONTENT_TYPE, VARY,
+        },
+        HeaderMap, HeaderValue, StatusCode, Uri,
     },
     response::{Html, IntoResponse, Response},
-    routing::get,
+    routing::{get, post},
     Json, Router,
 };
+use futures::StreamExt;
 use rust_embed::Embed;
-use std::{net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
+use std::{
+    net::SocketAddr,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use tokio::net::TcpListener;
-use tower_http::{
-    compression::CompressionLayer,
-    cors::{AllowOrigin, CorsLayer},
+use tower_http::compression::{
+    predicate::{NotForContentType, Predicate},
+    CompressionLayer, DefaultPredicate,
 };
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
+mod admin;
 mod cache_control;
 mod cli;
 mod config;
+mod connections;
+mod cors;
+mod diff;
 mod error;
+mod events;
+mod export;
+mod files;
+mod fonts;
+mod health;
+mod inspect;
+mod integrity;
+mod listener;
 mod logging;
+mod mapbox_static;
+mod middleware;
+mod ogcapi;
 mod openapi;
+mod preview;
+mod quality;
+mod query;
+mod range;
 mod render;
+mod request_id;
 mod sources;
+mod startup;
 mod styles;
 mod telemetry;
+mod thumbnail;
+mod timeout;
+mod tls;
+mod validate;
+mod watch;
 mod wmts;
 
-use cli::Cli;
-use config::Config;
+use cli::{Cli, Commands, ReportFormat};
+use config::{CompatConfig, Config, RenderLoader, StyleRewriteConfig};
+use connections::{ConnectionLimits, LimitedListener};
 use error::TileServerError;
-use render::{ImageFormat, RenderOptions, Renderer, StaticQueryParams, StaticType};
-use sources::{SourceManager, TileJson};
-use styles::{StyleInfo, StyleManager, UrlQueryParams};
+use middleware::limits::{self, RequestLimits};
+use middleware::real_ip::{self, RealIpSettings};
+use render::loader::ResourceLoader;
+use render::{
+    DebugOptions, ImageFormat, PoolConfig, RenderOptions, Renderer, SolidTileCache,
+    StaticQueryParams, StaticRenderRequest, StaticType, TileView,
+};
+use sources::{SharedSources, SourceManager, TileJson, TileJsonVersion, TileScheme};
+use styles::raster::RasterTileJson;
+use styles::sprite::SpriteFile;
+use styles::validate::{AvailableFonts, Diagnostic, StyleChecks};
+use styles::{SharedStyles, Style, StyleInfo, StyleManager, UrlQueryParams};
 
 /// Embedded SPA assets (built from apps/client)
 #[derive(Embed)]
@@ -46,26 +91,89 @@ struct Assets;
 /// Application state shared across handlers
 #[derive(Clone)]
 pub struct AppState {
-    pub sources: Arc<SourceManager>,
-    pub styles: Arc<StyleManager>,
+    pub sources: SharedSources,
+    pub styles: SharedStyles,
     pub renderer: Option<Arc<Renderer>>,
+    /// Shared encodings of solid-color tiles, also used by the renderer
+    pub solid_tiles: Arc<SolidTileCache>,
+    pub readiness: Arc<health::ReadinessChecker>,
     pub base_url: String,
     pub ui_enabled: bool,
     pub fonts_dir: Option<PathBuf>,
+    /// Glyph ranges merged across multi-font stacks
+    pub glyph_cache: Arc<fonts::GlyphCache>,
+    /// Glyph subsets of posted styles
+    pub glyph_subsets: Arc<fonts::subset::SubsetCache>,
+    /// Renderer settings, for the tile sizes it may render
+    pub render: Arc<config::RenderConfig>,
+    /// Styles with labels in a requested `?language=`
+    pub localized_styles: Arc<styles::localize::LocalizedStyleCache>,
+    /// TTF/OTF fonts in the fonts directory, for generating glyphs
+    pub font_files: Arc<fonts::FontCatalog>,
+    /// Fonts preferred for `Accept-Language` languages
+    pub font_languages: Arc<std::collections::HashMap<String, String>>,
     pub files_dir: Option<PathBuf>,
+    /// Keys for expanding `mapbox://`, `maptiler://` and `stadia://` style URLs
+    pub style_rewrite: Arc<StyleRewriteConfig>,
+    /// Add diagnostic `X-Tile-*` headers to tile responses
+    pub debug_headers: bool,
+    /// Serve debugging endpoints such as per-tile inspection
+    pub debug_endpoints: bool,
+    /// Serve HTML map previews of sources and styles
+    pub preview_pages: bool,
+    /// Longest a source integrity check may run
+    pub max_check_duration: Duration,
+    pub export: config::ExportConfig,
+    pub thumbnail: config::ThumbnailConfig,
+    /// Notifications for `GET /events` subscribers
+    pub events: events::EventBus,
+    /// Endpoints mimicking other tile services
+    pub compat: Arc<CompatConfig>,
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
+fn main() -> anyhow::Result<()> {
     dotenvy::dotenv().ok();
 
     // Parse CLI arguments
     let cli = Cli::parse_args();
+
+    // Subcommands run without starting the server runtime
+    if let Some(Commands::Validate { format }) = cli.command {
+        let report = validate::validate_config(cli.config.as_deref());
+        match format {
+            ReportFormat::Table => eprint!("{}", report.to_table()),
+            ReportFormat::Json => eprintln!("{}", report.to_json()),
+        }
+        std::process::exit(report.exit_code());
+    }
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(serve(cli))
+}
+
+/// Run the tile server
+async fn serve(cli: Cli) -> anyhow::Result<()> {
     let ui_enabled = cli.ui_enabled();
     let verbose = cli.verbose;
 
-    // Load configuration early to get telemetry settings
-    let mut config = Config::load(cli.config)?;
+    // Load configuration early to get telemetry settings. An explicit config
+    // wins; otherwise serve the given path or, without any config file, the
+    // current directory.
+    let auto_detect_root = match (&cli.config, &cli.path) {
+        (Some(_), _) => None,
+        (None, Some(path)) => Some(path.clone()),
+        (None, None) if Config::default_path().is_none() => Some(PathBuf::from(".")),
+        (None, None) => None,
+    };
+    if let Some(root) = auto_detect_root.as_ref().filter(|root| !root.exists()) {
+        return Err(startup::StartupError::MissingDataDir(root.clone()).into());
+    }
+    let mut config = match &auto_detect_root {
+        Some(root) => Config::auto_detect(root)?.with_env_overrides()?,
+        None => Config::load(cli.config)?,
+    };
 
     // Initialize tracing with OpenTelemetry
     // Filter out verbose MapLibre Native logs unless explicitly requested
@@ -75,7 +183,8 @@ async fn main() -> anyhow::Result<()> {
         EnvFilter::from_default_env().add_directive("tileserver_rs=info".parse()?)
     };
 
-    let fmt_layer = tracing_subscriber::fmt::layer().compact();
+    let fmt_layer = logging::fmt_layer(cli.log_format, &cli.log_output)
+        .map_err(|e| anyhow::anyhow!("Failed to open log output {}: {}", cli.log_output, e))?;
 
     let registry = tracing_subscriber::registry().with(filter).with(fmt_layer);
 
@@ -86,6 +195,20 @@ async fn main() -> anyhow::Result<()> {
         registry.init();
     }
 
+    if let Some(root) = &auto_detect_root {
+        tracing::info!("No config file, auto-detected from {}", root.display());
+        for source in &config.sources {
+            tracing::info!("  source '{}': {}", source.id, source.path);
+        }
+        for style in &config.styles {
+            tracing::info!("  style '{}': {}", style.id, style.path.display());
+        }
+        if let Some(fonts) = &config.fonts {
+            tracing::info!("  fonts: {}", fonts.display());
+        }
+    }
+    config.register_style_mbtiles();
+
     // Override with CLI arguments
     if let Some(host) = cli.host {
         config.server.host = host;
@@ -96,23 +219,120 @@ async fn main() -> anyhow::Result<()> {
     if let Some(public_url) = cli.public_url {
         config.server.public_url = Some(public_url);
     }
+    if cli.debug_headers {
+        config.server.debug_headers = true;
+    }
+
+    // Fail fast on taken ports rather than after loading every source
+    if config.server.unix_socket_path()?.is_none() && std::env::var_os("LISTEN_FDS").is_none() {
+        startup::check_port(&config.server.host, config.server.port)?;
+    }
+    for listener in &config.server.listeners {
+        startup::check_port(&listener.host, listener.port)?;
+    }
+
+    // Stop queries that the request timeout would abandon anyway
+    #[cfg(feature = "postgres")]
+    if let Some(pg_config) = config.postgres.as_mut() {
+        pg_config
+            .statement_timeout_ms
+            .get_or_insert(config.server.request_timeout_ms);
+    }
 
     // Load tile sources
     #[cfg(feature = "postgres")]
-    let sources =
-        SourceManager::from_configs_with_postgres(&config.sources, config.postgres.as_ref())
-            .await?;
+    let mut sources = SourceManager::from_configs_with_postgres(
+        &config.sources,
+        config.server.source_load_concurrency,
+        config.postgres.as_ref(),
+    )
+    .await?;
     #[cfg(not(feature = "postgres"))]
-    let sources = SourceManager::from_configs(&config.sources).await?;
+    let mut sources = SourceManager::from_configs_with_concurrency(
+        &config.sources,
+        config.server.source_load_concurrency,
+    )
+    .await?;
+    sources.set_tile_limits(config.server.tile_limits);
     tracing::info!("Loaded {} tile source(s)", sources.len());
 
+    // Scan fonts before loading styles, which are checked against them
+    let mut font_files = fonts::FontCatalog::default();
+    if let Some(ref fonts_path) = config.fonts {
+        if fonts_path.exists() {
+            tracing::info!("Fonts directory: {}", fonts_path.display());
+            font_files = fonts::FontCatalog::scan(fonts_path);
+            if !font_files.is_empty() {
+                tracing::info!(
+                    "Generating glyphs for {} font file face(s)",
+                    font_files.len()
+                );
+            }
+        } else {
+            tracing::warn!("Fonts directory not found: {}", fonts_path.display());
+        }
+    }
+
+    let font_files = Arc::new(font_files);
+
     // Load styles
-    let styles = StyleManager::from_configs(&config.styles)?;
+    let style_checks = StyleChecks {
+        fonts: config
+            .fonts
+            .clone()
+            .map(|dir| AvailableFonts::new(dir, font_files.clone())),
+        strict: config.strict_styles,
+    };
+    let styles = StyleManager::from_configs_with_checks(&config.styles, style_checks)?;
     tracing::info!("Loaded {} style(s)", styles.len());
+    startup::check_not_empty(&sources, &styles)?;
+
+    // Build base URL - use public_url if configured, otherwise auto-generate
+    let base_url = config.server.base_url();
+
+    // Log files directory if configured
+    if let Some(ref files_path) = config.files {
+        if files_path.exists() {
+            tracing::info!("Files directory: {}", files_path.display());
+        } else {
+            tracing::warn!("Files directory not found: {}", files_path.display());
+        }
+    }
+
+    let sources = SharedSources::new(sources);
+    let styles = SharedStyles::new(styles);
+    let glyph_cache = Arc::new(fonts::GlyphCache::new());
 
     // Initialize native renderer for rendering (if styles are configured)
-    let renderer = if !styles.is_empty() {
-        match Renderer::new() {
+    let renderer = if !styles.load().is_empty() {
+        let loader = match config.render.loader {
+            RenderLoader::InProcess => {
+                let loader = ResourceLoader::new(
+                    tokio::runtime::Handle::current(),
+                    &base_url,
+                    sources.clone(),
+                    styles.clone(),
+                )?;
+                Some(Arc::new(match &config.fonts {
+                    Some(dir) => {
+                        loader.with_fonts(dir.clone(), font_files.clone(), glyph_cache.clone())
+                    }
+                    None => loader,
+                }))
+            }
+            RenderLoader::Http => None,
+        };
+        let pool_config = PoolConfig {
+            workers: config.render.workers,
+            queue_size: config.render.queue_size,
+            render_timeout: Some(config.render.tile_render_timeout_ms)
+                .filter(|&ms| ms > 0)
+                .map(Duration::from_millis),
+            priority: config.render.priority_formula,
+            loader,
+            ..PoolConfig::default()
+        };
+        match Renderer::with_config(pool_config, 3) {
             Ok(r) => {
                 tracing::info!("Native MapLibre renderer initialized");
                 Some(Arc::new(r))
@@ -126,44 +346,39 @@ async fn main() -> anyhow::Result<()> {
         None
     };
 
-    // Build base URL - use public_url if configured, otherwise auto-generate
-    let base_url = if let Some(ref public_url) = config.server.public_url {
-        public_url.trim_end_matches('/').to_string()
-    } else {
-        let host_for_url = if config.server.host == "0.0.0.0" {
-            "localhost"
-        } else {
-            &config.server.host
-        };
-        format!("http://{}:{}", host_for_url, config.server.port)
-    };
-
-    // Log fonts directory if configured
-    if let Some(ref fonts_path) = config.fonts {
-        if fonts_path.exists() {
-            tracing::info!("Fonts directory: {}", fonts_path.display());
-        } else {
-            tracing::warn!("Fonts directory not found: {}", fonts_path.display());
-        }
-    }
-
-    // Log files directory if configured
-    if let Some(ref files_path) = config.files {
-        if files_path.exists() {
-            tracing::info!("Files directory: {}", files_path.display());
-        } else {
-            tracing::warn!("Files directory not found: {}", files_path.display());
-        }
-    }
+    let solid_tiles = renderer
+        .as_ref()
+        .map(|renderer| renderer.solid_tiles())
+        .unwrap_or_default();
 
     let state = AppState {
-        sources: Arc::new(sources),
-        styles: Arc::new(styles),
+        sources,
+        styles,
         renderer,
+        solid_tiles,
+        readiness: Arc::new(health::ReadinessChecker::default()),
         base_url,
         ui_enabled,
-        fonts_dir: config.fonts,
-        files_dir: config.files,
+        fonts_dir: config.fonts.clone(),
+        glyph_cache,
+        glyph_subsets: Arc::new(fonts::subset::SubsetCache::new()),
+        render: Arc::new(config.render.clone()),
+        localized_styles: Arc::new(styles::localize::LocalizedStyleCache::new()),
+        font_files,
+        files_dir: config.files.clone(),
+        style_rewrite: Arc::new(StyleRewriteConfig {
+            local_tiles: config.render.loader == RenderLoader::InProcess,
+            ..config.style_rewrite.clone()
+        }),
+        debug_headers: config.server.debug_headers,
+        debug_endpoints: config.server.debug_endpoints,
+        preview_pages: config.server.preview_pages,
+        max_check_duration: Duration::from_secs(config.server.max_check_duration_secs),
+        export: config.export,
+        thumbnail: config.thumbnail,
+        events: events::EventBus::new(),
+        compat: Arc::new(config.compat.clone()),
+        font_languages: Arc::new(config.font_languages.clone()),
     };
 
     if ui_enabled {
@@ -172,71 +387,222 @@ async fn main() -> anyhow::Result<()> {
         tracing::info!("Web UI disabled (use --ui to enable)");
     }
 
-    // Build CORS layer with proper multi-origin support
-    let allow_origin = if config.server.cors_origins.is_empty()
-        || config.server.cors_origins.iter().any(|o| o == "*")
-    {
-        // Wildcard: allow any origin
-        if !config.server.cors_origins.is_empty() {
-            tracing::warn!(
-                "CORS configured with wildcard (*). Consider restricting origins in production."
-            );
-        }
-        AllowOrigin::any()
-    } else {
-        // Specific origins: parse and validate each one
-        let origins: Vec<HeaderValue> = config
-            .server
-            .cors_origins
-            .iter()
-            .filter_map(|o| {
-                o.parse::<HeaderValue>().ok().or_else(|| {
-                    tracing::warn!("Invalid CORS origin '{}', skipping", o);
-                    None
-                })
-            })
-            .collect();
+    // CORS policies: the `[server]` default plus per route group overrides
+    let mut cors = cors::Cors::from_config(&config.server)?;
+    if cors.allows_any_origin() && !config.server.cors_origins.is_empty() {
+        tracing::warn!(
+            "CORS configured with wildcard (*). Consider restricting origins in production."
+        );
+    }
+    if state.debug_headers {
+        // Let browser devtools and map debuggers read the diagnostic headers
+        cors = cors.expose_headers([
+            cache_control::X_TILE_SOURCE,
+            cache_control::X_TILE_FORMAT,
+            cache_control::X_RENDER_TIME_MS,
+        ]);
+        tracing::info!("Debug headers enabled on tile responses");
+    }
 
-        if origins.is_empty() {
-            tracing::warn!("No valid CORS origins configured, defaulting to wildcard");
-            AllowOrigin::any()
-        } else {
-            AllowOrigin::list(origins)
-        }
-    };
+    if cli.watch {
+        watch::spawn(
+            &config.sources,
+            &config.styles,
+            state.sources.clone(),
+            state.styles.clone(),
+            state.events.clone(),
+        )?;
+    }
 
-    let cors = CorsLayer::new()
-        .allow_headers([ACCEPT, CONTENT_TYPE])
-        .max_age(Duration::from_secs(86400))
-        .allow_origin(allow_origin)
-        .allow_methods([Method::GET, Method::OPTIONS, Method::HEAD]);
+    for listener in &config.server.listeners {
+        listener.validate()?;
+    }
+    config.render.validate()?;
 
     // Build router
-    let mut router = Router::new().merge(api_router(state.clone()));
+    let real_ip = RealIpSettings::from(&config.server);
+    let connection_limits = ConnectionLimits::from(&config.server);
+    let router = app_router(state.clone(), &cors, &config.server, &|_| true);
+    // Servers besides the main one, awaited once it shuts down
+    let mut extra_servers = Vec::new();
+
+    // Serve the admin API on its own listener when a token is configured
+    if let Some(token) = config.admin.token.as_deref().filter(|t| !t.is_empty()) {
+        let (host, port) = config.admin.listen_addr(&config.server).ok_or_else(|| {
+            anyhow::anyhow!(
+                "No free admin port above {}, set [admin] port",
+                config.server.port
+            )
+        })?;
+        let admin_state = admin::AdminState {
+            sources: state.sources.clone(),
+            styles: state.styles.clone(),
+            source_configs: Arc::new(config.sources.clone()),
+            token: token.into(),
+            events: state.events.clone(),
+        };
+        let admin_router = cors
+            .apply(Some(cors::ADMIN_GROUP), admin::router(admin_state))
+            .layer(axum::middleware::from_fn(logging::request_logger));
+        let admin_router = real_ip::apply(admin_router, real_ip);
+        let admin_router = request_id::apply(admin_router, config.server.trust_request_id);
+
+        let admin_addr: SocketAddr = format!("{}:{}", host, port).parse()?;
+        let admin_listener = TcpListener::bind(admin_addr)
+            .await
+            .map_err(|e| startup::explain_bind_error(e.into(), admin_addr))?;
+        tracing::info!("Admin API listening on http://{}", admin_addr);
+        extra_servers.push(tokio::spawn(async move {
+            let listener = LimitedListener::new(admin_listener, connection_limits);
+            let service = connections::make_service(admin_router, connection_limits);
+            if let Err(e) = axum::serve(listener, service)
+                .with_graceful_shutdown(shutdown_signal())
+                .await
+            {
+                tracing::error!("Admin API stopped: {}", e);
+            }
+        }));
+    }
 
-    // Add Swagger UI at /_openapi with bundled assets (works in air-gapped environments)
-    router =
-        router.merge(SwaggerUi::new("/_openapi").url("/openapi.json", openapi::ApiDoc::openapi()));
+    // Additional listeners share the state, with their own base URL
+    for listener_config in &config.server.listeners {
+        let listener_state = AppState {
+            base_url: listener_config.base_url(),
+            ..state.clone()
+        };
+        let router = app_router(listener_state, &cors, &config.server, &|group| {
+            listener_config.serves(group)
+        });
+        let addr: SocketAddr =
+            format!("{}:{}", listener_config.host, listener_config.port).parse()?;
+        let listener = TcpListener::bind(addr)
+            .await
+            .map_err(|e| startup::explain_bind_error(e.into(), addr))?;
+        match &listener_config.routes {
+            Some(routes) => {
+                tracing::info!("Also listening on http://{} ({})", addr, routes.join(", "))
+            }
+            None => tracing::info!("Also listening on http://{}", addr),
+        }
+        extra_servers.push(tokio::spawn(async move {
+            let listener = LimitedListener::new(listener, connection_limits);
+            let service = connections::make_service(router, connection_limits);
+            if let Err(e) = axum::serve(listener, service)
+                .with_graceful_shutdown(shutdown_signal())
+                .await
+            {
+                tracing::error!("Listener on {} stopped: {}", addr, e);
+            }
+        }));
+    }
 
-    // Add embedded SPA if UI is enabled
-    if ui_enabled {
-        router = router.fallback(serve_spa);
+    // Prefer a socket passed by systemd over the configured address
+    let listener = match listener::from_systemd()? {
+        Some(listener) => {
+            tracing::info!("Using socket from systemd: {}", listener.describe());
+            listener
+        }
+        None => listener::bind(&config.server).map_err(|e| {
+            startup::explain_bind_error(e, format!("{}:{}", config.server.host, config.server.port))
+        })?,
+    };
+    let address = listener.describe();
+    let listen_url = match &listener {
+        listener::Listener::Tcp(_) if config.server.tls.is_some() => {
+            format!("https://{}", address)
+        }
+        listener::Listener::Tcp(_) => format!("http://{}", address),
+        #[cfg(unix)]
+        listener::Listener::Unix(_) => address.clone(),
+    };
+    eprint!(
+        "{}",
+        startup::summary(
+            &config,
+            &state.sources.load(),
+            &state.styles.load(),
+            state.renderer.is_some(),
+            &listen_url,
+        )
+    );
+    if config.server.public_url.is_none() && !matches!(listener, listener::Listener::Tcp(_)) {
+        tracing::warn!(
+            "server.public_url is not set; URLs in responses will use {}",
+            state.base_url
+        );
     }
 
-    let router = router
-        .layer(cors)
-        .layer(CompressionLayer::new())
-        .layer(axum::middleware::from_fn(logging::request_logger));
+    match listener {
+        listener::Listener::Tcp(listener) => {
+            if let Some(tls_config) = config.server.tls {
+                let rustls = tls::load(&tls_config).await?;
+                tls::spawn_reloader(rustls.clone(), tls_config.clone());
+
+                // Optional plain HTTP listener that only redirects to HTTPS
+                if let Some(http_port) = tls_config.redirect_http_port {
+                    let redirect_addr: SocketAddr =
+                        format!("{}:{}", config.server.host, http_port).parse()?;
+                    let redirect_listener = TcpListener::bind(redirect_addr)
+                        .await
+                        .map_err(|e| startup::explain_bind_error(e.into(), redirect_addr))?;
+                    let https_port = listener.local_addr()?.port();
+                    let redirect_router = tls::redirect_router(https_port);
+                    tracing::info!("Redirecting http://{} to HTTPS", redirect_addr);
+                    extra_servers.push(tokio::spawn(async move {
+                        let listener = LimitedListener::new(redirect_listener, connection_limits);
+                        let service = connections::make_service(redirect_router, connection_limits);
+                        if let Err(e) = axum::serve(listener, service)
+                            .with_graceful_shutdown(shutdown_signal())
+                            .await
+                        {
+                            tracing::error!("HTTP redirect listener stopped: {}", e);
+                        }
+                    }));
+                }
 
-    let addr: SocketAddr = format!("{}:{}", config.server.host, config.server.port).parse()?;
-    tracing::info!("Starting tileserver on http://{}", addr);
+                tracing::info!("Starting tileserver on https://{}", address);
 
-    let listener = TcpListener::bind(addr).await?;
+                // Run the server with graceful shutdown
+                let handle = tls::Handle::new();
+                let shutdown = handle.clone();
+                tokio::spawn(async move {
+                    shutdown_signal().await;
+                    shutdown.graceful_shutdown(None);
+                });
+                tls::serve(listener, router, rustls, connection_limits, handle).await?;
+            } else {
+                tracing::info!("Starting tileserver on http://{}", address);
+                listener.set_nonblocking(true)?;
+                let listener = TcpListener::from_std(listener)?;
+
+                // Close idle and long-lived connections, and refuse
+                // connections over the limit
+                let listener = LimitedListener::new(listener, connection_limits);
+                let service = connections::make_service(router, connection_limits);
+
+                // Run the server with graceful shutdown
+                axum::serve(listener, service)
+                    .with_graceful_shutdown(shutdown_signal())
+                    .await?;
+            }
+        }
+        #[cfg(unix)]
+        listener::Listener::Unix(socket) => {
+            if config.server.tls.is_some() {
+                anyhow::bail!("[server.tls] is not supported on Unix sockets");
+            }
+            tracing::info!("Starting tileserver on {}", address);
+            // Graceful shutdown removes the socket file
+            listener::serve_unix(socket, router, shutdown_signal()).await?;
+        }
+    }
 
-    // Run the server with graceful shutdown
-    axum::serve(listener, router)
-        .with_graceful_shutdown(shutdown_signal())
-        .await?;
+    // Let the additional listeners finish their in-flight requests too
+    futures::future::join_all(extra_servers).await;
+
+    // Renderer threads may still hold the styles, so delete decoded sprites
+    // explicitly
+    state.styles.inline_sprites().clear();
 
     // Shutdown OpenTelemetry
     telemetry::shutdown_telemetry();
@@ -300,48 +666,214 @@ async fn serve_spa(uri: Uri) -> impl IntoResponse {
     (StatusCode::NOT_FOUND, "Not Found").into_response()
 }
 
-fn api_router(state: AppState) -> Router {
-    Router::new()
-        .route("/health", get(health_check))
+/// The API with the UI or landing page and the shared middleware, serving
+/// only the route groups accepted by `serves`
+fn app_router(
+    state: AppState,
+    cors: &cors::Cors,
+    server: &config::ServerConfig,
+    serves: &dyn Fn(&str) -> bool,
+) -> Router {
+    let mut router = api_router(state.clone(), cors, serves);
+
+    if serves("ui") {
+        // Add Swagger UI at /_openapi with bundled assets (works in air-gapped environments)
+        let mut other = Router::new()
+            .merge(SwaggerUi::new("/_openapi").url("/openapi.json", openapi::ApiDoc::openapi()));
+
+        // Add embedded SPA if UI is enabled, otherwise serve the OGC API landing page
+        if state.ui_enabled {
+            other = other.fallback(serve_spa);
+        } else {
+            other = other.merge(
+                Router::new()
+                    .route("/", get(get_ogc_landing_page))
+                    .with_state(state.clone()),
+            );
+        }
+        router = router.merge(cors.apply(None, other));
+    }
+
+    let mut router = timeout::apply(router, server.request_timeout_ms);
+
+    // An export is sent once the whole file is written, which can take
+    // longer than the request timeout; `[export] max_tiles` bounds it instead.
+    // Validations stop after `max_check_duration_secs` with their own report.
+    if serves("data") {
+        let export = Router::new()
+            .route("/data/{source}/tiles.mbtiles", get(get_source_mbtiles))
+            .route("/data/{source}/validate", post(post_source_validate))
+            .with_state(state);
+        router = router.merge(cors.apply(Some("data"), export));
+    }
+
+    // JSON API responses go out uncompressed, so only tiles and other
+    // compressed bodies carry `Vary: Accept-Encoding`
+    let compress = DefaultPredicate::new().and(NotForContentType::const_new("application/json"));
+    let router = limits::apply(router, RequestLimits::from(server))
+        .layer(CompressionLayer::new().compress_when(compress))
+        .layer(axum::middleware::from_fn(logging::request_logger));
+    let router = real_ip::apply(router, RealIpSettings::from(server));
+    request_id::apply(router, server.trust_request_id)
+}
+
+fn api_router(state: AppState, cors: &cors::Cors, serves: &dyn Fn(&str) -> bool) -> Router {
+    // Tiles and images answer byte range requests once fully rendered
+    let ranges = axum::middleware::from_fn(range::byte_ranges);
+
+    let health = Router::new()
+        .route("/health", get(health::liveness))
+        .route("/health/live", get(health::liveness))
+        .route("/health/ready", get(get_readiness))
         // Note: /openapi.json and /_openapi/* are handled by SwaggerUi merge
-        .route("/index.json", get(get_index_json))
-        // Style endpoints
+        .route("/index.json", get(get_index_json));
+
+    // Style endpoints
+    let styles = Router::new()
         .route("/styles.json", get(get_all_styles))
         .route("/styles/{style_json}", get(get_style_tilejson))
         .route("/styles/{style}/style.json", get(get_style_json))
         .route("/styles/{style}/wmts.xml", get(get_wmts_capabilities))
+        .route("/styles/{style}/validate", get(get_style_validation))
+        .route("/styles/{style}/thumbnail.png", get(get_style_thumbnail))
+        .route("/wmts", get(get_wmts_kvp))
         .route("/styles/{style}/{sprite_file}", get(get_sprite))
-        .route("/styles/{style}/{z}/{x}/{y_fmt}", get(get_raster_tile))
+        .route(
+            "/styles/{style}/{sprite_id}/{sprite_file}",
+            get(get_sprite_with_id),
+        )
+        .route(
+            "/styles/{style}/{z}/{x}/{y_fmt}",
+            get(get_raster_tile).layer(ranges.clone()),
+        )
         .route(
             "/styles/{style}/{tile_size}/{z}/{x}/{y_fmt}",
-            get(get_raster_tile_with_size),
+            get(get_raster_tile_with_size).layer(ranges.clone()),
+        )
+        .route(
+            "/styles/{style}/{z}/{x}/{y}/query",
+            get(get_raster_tile_query),
         )
         .route(
             "/styles/{style}/static/{static_type}/{size_fmt}",
-            get(get_static_image),
+            get(get_static_image).layer(ranges.clone()),
         )
-        // Font endpoints
+        .route("/styles/{style}/render", post(post_static_render));
+
+    // Font endpoints
+    let fonts = Router::new()
         .route("/fonts.json", get(get_fonts_list))
-        .route("/fonts/{fontstack}/{range}", get(get_font_glyphs))
-        // Data endpoints
+        .route("/fonts/subset", post(post_font_subset))
+        .route("/fonts/{fontstack}/{range}", get(get_font_glyphs));
+
+    // Data endpoints
+    let data = Router::new()
         .route("/data.json", get(get_all_sources))
         .route("/data/{source}", get(get_source_tilejson))
-        .route("/data/{source}/{z}/{x}/{y_fmt}", get(get_tile))
-        // Static files endpoint
-        .route("/files/{*filepath}", get(get_static_file))
-        .with_state(state)
+        .route("/data/{source}/inspect", get(get_source_inspect))
+        .route("/data/{source}/quality", get(get_source_quality))
+        .route(
+            "/data/{source}/signed-url/{z}/{x}/{y}",
+            get(get_signed_tile_url),
+        )
+        .route(
+            "/data/{source}/{z}/{x}/{y_fmt}",
+            get(get_tile).head(head_tile).layer(ranges.clone()),
+        )
+        .route(
+            "/diff/{source_a}/{source_b}/{z}/{x}/{y}",
+            get(get_tile_diff),
+        );
+    #[cfg(feature = "raster")]
+    let data = data.route(
+        "/data/{source}/terrain-rgb/{z}/{x}/{y_fmt}",
+        get(get_terrain_rgb_tile).layer(ranges.clone()),
+    );
+
+    // OGC API - Tiles endpoints
+    let tiles = Router::new()
+        .route("/conformance", get(get_ogc_conformance))
+        .route("/tiles", get(get_ogc_tilesets))
+        .route("/tiles/{tileset}", get(get_ogc_tileset))
+        .route(
+            "/tiles/{tileset}/{tile_matrix}/{tile_row}/{tile_col}",
+            get(get_ogc_tile).layer(ranges.clone()),
+        )
+        .route("/tileMatrixSets", get(get_ogc_tile_matrix_sets))
+        .route(
+            "/tileMatrixSets/{tile_matrix_set}",
+            get(get_ogc_tile_matrix_set),
+        );
+
+    // HTML map previews, independent of the web UI
+    let (styles, data) = if state.preview_pages {
+        let preview = preview::PreviewState {
+            sources: state.sources.clone(),
+            styles: state.styles.clone(),
+            base_url: state.base_url.clone(),
+        };
+        (
+            styles.merge(preview::styles_router(preview.clone())),
+            data.merge(preview::data_router(preview)),
+        )
+    } else {
+        (styles, data)
+    };
+
+    // Static files endpoint
+    let files = Router::new().route("/files/{*filepath}", get(get_static_file));
+
+    let mut router = Router::new();
+    let groups = [
+        ("health", None, health),
+        ("events", None, events::router(state.events.clone())),
+        ("styles", Some("styles"), styles),
+        ("fonts", Some("fonts"), fonts),
+        ("data", Some("data"), data),
+        ("tiles", Some("tiles"), tiles),
+        ("files", Some("files"), files),
+    ];
+    for (group, cors_group, routes) in groups {
+        if serves(group) {
+            router = router.merge(cors.apply(cors_group, routes));
+        }
+    }
+
+    // Mapbox Static Images API compatibility
+    if state.compat.mapbox_static && serves("styles") {
+        let mapbox_static = Router::new()
+            .route(
+                "/styles/v1/{username}/{style_id}/static/{position}/{size}",
+                get(get_mapbox_static_image).layer(ranges.clone()),
+            )
+            .route(
+                "/styles/v1/{username}/{style_id}/static/{overlay}/{position}/{size}",
+                get(get_mapbox_static_image).layer(ranges),
+            );
+        router = router.merge(cors.apply(Some("styles"), mapbox_static));
+    }
+
+    if state.debug_endpoints && serves("data") {
+        let debug = Router::new()
+            .route("/data/{source}/{z}/{x}/{y}/inspect", get(get_tile_inspect))
+            .route("/tiles/{z}/{x}/{y_fmt}", get(get_tile_query));
+        router = router.merge(cors.apply(Some("data"), debug));
+    }
+
+    router.with_state(state)
 }
 
-/// Health check endpoint
-async fn health_check() -> (StatusCode, &'static str) {
-    (StatusCode::OK, "OK")
+/// Readiness check endpoint
+async fn get_readiness(State(state): State<AppState>) -> health::ReadinessReport {
+    let sources = state.sources.load();
+    state.readiness.check(&sources).await
 }
 
 /// Combined index entry for /index.json
 #[derive(serde::Serialize)]
 #[serde(untagged)]
 enum IndexEntry {
-    Data(TileJson),
+    Data(Box<TileJson>),
     Style(RasterTileJson),
 }
 
@@ -360,36 +892,35 @@ async fn get_index_json(
     State(state): State<AppState>,
     Query(query): Query<IndexQueryParams>,
 ) -> Json<Vec<IndexEntry>> {
+    let sources = state.sources.load();
+    let styles = state.styles.load();
     let mut entries = Vec::new();
 
-    // Build key query string
-    let key_query = query
-        .key
-        .as_ref()
-        .map(|k| format!("?key={}", urlencoding::encode(k)))
-        .unwrap_or_default();
-
     // Add all data sources
-    for metadata in state.sources.all_metadata() {
-        entries.push(IndexEntry::Data(
-            metadata.to_tilejson_with_key(&state.base_url, query.key.as_deref()),
-        ));
+    for metadata in sources.all_metadata() {
+        let mut tilejson = metadata.to_tilejson_with_key(
+            &state.base_url,
+            query.key.as_deref(),
+            TileJsonVersion::default(),
+        );
+        let stats = sources.stats(&metadata.id);
+        tilejson.tile_count = stats.tile_count;
+        tilejson.size_bytes = stats.size_bytes;
+        if state.preview_pages {
+            tilejson.preview = Some(preview::data_url(&state.base_url, &metadata.id));
+        }
+        entries.push(IndexEntry::Data(Box::new(tilejson)));
     }
 
     // Add all styles as raster tile sources
-    for style in state.styles.all() {
-        let tile_url = format!(
-            "{}/styles/{}/{{z}}/{{x}}/{{y}}.png{}",
-            state.base_url, style.id, key_query
-        );
-        entries.push(IndexEntry::Style(RasterTileJson {
-            tilejson: "3.0.0",
-            name: style.name.clone(),
-            tiles: vec![tile_url],
-            minzoom: 0,
-            maxzoom: 22,
-            attribution: None,
-        }));
+    for style in styles.all() {
+        let mut tilejson = style.raster_tilejson(&state.base_url, query.key.as_deref(), &sources);
+        tilejson.thumbnail =
+            Some(tilejson.thumbnail_url(&state.base_url, &style.id, query.key.as_deref()));
+        if state.preview_pages {
+            tilejson.preview = Some(preview::style_url(&state.base_url, &style.id));
+        }
+        entries.push(IndexEntry::Style(tilejson));
     }
 
     Json(entries)
@@ -410,11 +941,8 @@ async fn get_all_styles(
     State(state): State<AppState>,
     Query(query): Query<StylesQueryParams>,
 ) -> Json<Vec<StyleInfo>> {
-    Json(
-        state
-            .styles
-            .all_infos_with_key(&state.base_url, query.key.as_deref()),
-    )
+    let styles = state.styles.load();
+    Json(styles.all_infos_with_key(&state.base_url, query.key.as_deref()))
 }
 
 /// Query parameters for style.json endpoint
@@ -432,8 +960,8 @@ async fn get_style_json(
     Path(style_id): Path<String>,
     Query(query): Query<StyleQueryParams>,
 ) -> Result<Json<serde_json::Value>, TileServerError> {
-    let style = state
-        .styles
+    let styles = state.styles.load();
+    let style = styles
         .get(&style_id)
         .ok_or_else(|| TileServerError::StyleNotFound(style_id))?;
 
@@ -441,22 +969,46 @@ async fn get_style_json(
     let url_params = UrlQueryParams::with_key(query.key);
 
     // Rewrite relative URLs to absolute URLs for external clients
-    let rewritten_style =
-        styles::rewrite_style_for_api(&style.style_json, &state.base_url, &url_params);
+    let rewritten_style = styles::rewrite_style_for_api_with_config(
+        &style.style_json,
+        &state.base_url,
+        &url_params,
+        &state.style_rewrite,
+    );
 
     Ok(Json(rewritten_style))
 }
 
-/// TileJSON response for raster style tiles
+/// Problems found when linting a style
 #[derive(serde::Serialize)]
-struct RasterTileJson {
-    tilejson: &'static str,
-    name: String,
-    tiles: Vec<String>,
-    minzoom: u8,
-    maxzoom: u8,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    attribution: Option<String>,
+struct StyleValidation<'a> {
+    style: String,
+    /// No errors; warnings may remain
+    valid: bool,
+    diagnostics: &'a [Diagnostic],
+}
+
+/// Get the problems found in a style when it was loaded
+/// Route: GET /styles/{style}/validate
+async fn get_style_validation(
+    State(state): State<AppState>,
+    Path(style_id): Path<String>,
+) -> Result<Response, TileServerError> {
+    let styles = state.styles.load();
+    if styles.get(&style_id).is_none() {
+        return Err(TileServerError::StyleNotFound(style_id));
+    }
+
+    let diagnostics = styles.diagnostics(&style_id);
+    let valid = diagnostics
+        .iter()
+        .all(|d| d.severity != styles::validate::Severity::Error);
+    Ok(Json(StyleValidation {
+        style: style_id.clone(),
+        valid,
+        diagnostics,
+    })
+    .into_response())
 }
 
 /// Get TileJSON for raster tiles of a style
@@ -476,36 +1028,21 @@ async fn get_style_tilejson(
     Path(style_json): Path<String>,
     Query(query): Query<StyleTileJsonQueryParams>,
 ) -> Result<Json<RasterTileJson>, TileServerError> {
+    let styles = state.styles.load();
     // Only handle requests ending with .json
     let style_id = style_json
         .strip_suffix(".json")
         .ok_or_else(|| TileServerError::StyleNotFound(style_json.clone()))?;
 
-    let style = state
-        .styles
+    let style = styles
         .get(style_id)
         .ok_or_else(|| TileServerError::StyleNotFound(style_id.to_string()))?;
 
-    // Build raster tile URL template with optional key
-    let key_query = query
-        .key
-        .as_ref()
-        .map(|k| format!("?key={}", urlencoding::encode(k)))
-        .unwrap_or_default();
-
-    let tile_url = format!(
-        "{}/styles/{}/{{z}}/{{x}}/{{y}}.png{}",
-        state.base_url, style_id, key_query
-    );
-
-    Ok(Json(RasterTileJson {
-        tilejson: "3.0.0",
-        name: style.name.clone(),
-        tiles: vec![tile_url],
-        minzoom: 0,
-        maxzoom: 22,
-        attribution: None,
-    }))
+    Ok(Json(style.raster_tilejson(
+        &state.base_url,
+        query.key.as_deref(),
+        &state.sources.load(),
+    )))
 }
 
 /// Query parameters for data source endpoints
@@ -513,21 +1050,30 @@ async fn get_style_tilejson(
 struct DataSourceQueryParams {
     /// API key to append to tile URLs
     key: Option<String>,
+    /// TileJSON version of the response (`3.0.0` or `2.2.0`)
+    #[serde(default)]
+    tilejson: TileJsonVersion,
+    /// Add the `x-tile-count`, `x-storage-bytes` and `x-last-modified`
+    /// extension fields (source TileJSON only)
+    #[serde(default)]
+    extended: bool,
 }
 
 /// Get all available tile sources
 /// Route: GET /data.json
 /// Query parameters:
 /// - `key`: Optional API key to append to tile URLs
+/// - `tilejson`: TileJSON version, `3.0.0` (default) or `2.2.0`
 async fn get_all_sources(
     State(state): State<AppState>,
     Query(query): Query<DataSourceQueryParams>,
 ) -> Json<Vec<TileJson>> {
     let sources: Vec<TileJson> = state
         .sources
+        .load()
         .all_metadata()
         .iter()
-        .map(|m| m.to_tilejson_with_key(&state.base_url, query.key.as_deref()))
+        .map(|m| m.to_tilejson_with_key(&state.base_url, query.key.as_deref(), query.tilejson))
         .collect();
 
     Json(sources)
@@ -537,82 +1083,527 @@ async fn get_all_sources(
 /// Route: GET /data/{source}
 /// Query parameters:
 /// - `key`: Optional API key to append to tile URLs
+/// - `tilejson`: TileJSON version, `3.0.0` (default) or `2.2.0`
 async fn get_source_tilejson(
     State(state): State<AppState>,
     Path(source): Path<String>,
     Query(query): Query<DataSourceQueryParams>,
 ) -> Result<Json<TileJson>, TileServerError> {
+    let sources = state.sources.load();
     // Strip .json extension if present
     let source_id = source.strip_suffix(".json").unwrap_or(&source);
 
-    let source_ref = state
-        .sources
-        .get(source_id)
+    let metadata = sources
+        .metadata(source_id)
         .ok_or_else(|| TileServerError::SourceNotFound(source_id.to_string()))?;
 
-    let tilejson = source_ref
-        .metadata()
-        .to_tilejson_with_key(&state.base_url, query.key.as_deref());
+    let mut tilejson =
+        metadata.to_tilejson_with_key(&state.base_url, query.key.as_deref(), query.tilejson);
+    if query.extended {
+        tilejson.extensions = Some(sources.tilejson_extensions(source_id));
+    }
     Ok(Json(tilejson))
 }
 
-/// Tile request parameters (raw from URL)
+/// Query parameters for the inspect endpoint
 #[derive(serde::Deserialize)]
-struct TileParams {
-    source: String,
+struct InspectQueryParams {
+    /// Zoom level to inspect
     z: u8,
-    x: u32,
-    y_fmt: String, // e.g., "123.pbf" or "123.mvt"
+    /// Comma-separated layer names to report
+    layer: Option<String>,
 }
 
-impl TileParams {
-    fn parse_y_and_format(&self) -> Option<(u32, &str)> {
-        let (y_str, format) = self.y_fmt.rsplit_once('.')?;
-        let y = y_str.parse().ok()?;
-        Some((y, format))
+/// Stream per-tile statistics for one zoom level as NDJSON
+/// Route: GET /data/{source}/inspect?z={z}
+async fn get_source_inspect(
+    State(state): State<AppState>,
+    Path(source): Path<String>,
+    Query(query): Query<InspectQueryParams>,
+) -> Result<Response, TileServerError> {
+    let sources = state.sources.load();
+    let source = sources
+        .get(&source)
+        .ok_or_else(|| TileServerError::SourceNotFound(source.clone()))?
+        .clone();
+
+    let layers: Vec<String> = query
+        .layer
+        .iter()
+        .flat_map(|layers| layers.split(','))
+        .map(|layer| layer.trim().to_string())
+        .filter(|layer| !layer.is_empty())
+        .collect();
+
+    let inspection = inspect::list(source.as_ref(), query.z).await?;
+    let truncated = inspection.truncated;
+    let lines = inspect::stream(source, inspection, layers).map(Ok::<_, std::convert::Infallible>);
+
+    let mut response = axum::body::Body::from_stream(lines).into_response();
+    let headers = response.headers_mut();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("application/x-ndjson"),
+    );
+    if truncated {
+        headers.insert(inspect::TRUNCATED_HEADER, HeaderValue::from_static("true"));
     }
+    Ok(response)
 }
 
-async fn get_tile(
+/// Sample tiles of one zoom level and report problems with their data
+/// Route: GET /data/{source}/quality?z={z}[&sample={n}][&seed={seed}]
+async fn get_source_quality(
     State(state): State<AppState>,
-    Path(params): Path<TileParams>,
-    Query(query): Query<std::collections::HashMap<String, String>>,
+    Path(source): Path<String>,
+    Query(query): Query<quality::QualityQuery>,
+) -> Result<Json<quality::QualityReport>, TileServerError> {
+    let sources = state.sources.load();
+    let tile_source = sources
+        .get(&source)
+        .ok_or_else(|| TileServerError::SourceNotFound(source.clone()))?
+        .clone();
+
+    let report = quality::analyze(&source, tile_source.as_ref(), &query).await?;
+    Ok(Json(report))
+}
+
+/// Download tiles of a source as an MBTiles file
+async fn get_source_mbtiles(
+    State(state): State<AppState>,
+    Path(source_id): Path<String>,
+    Query(query): Query<export::ExportQuery>,
 ) -> Result<Response, TileServerError> {
-    let (y, format) = params
-        .parse_y_and_format()
-        .ok_or(TileServerError::InvalidTileRequest)?;
+    let sources = state.sources.load();
+    let source = sources
+        .get(&source_id)
+        .ok_or_else(|| TileServerError::SourceNotFound(source_id.clone()))?
+        .clone();
+
+    let plan = export::plan(source.metadata(), &query, &state.export)?;
+    let path = tempfile::Builder::new()
+        .suffix(".mbtiles")
+        .tempfile()?
+        .into_temp_path();
+    // SQLite treats the empty file as a new database
+
+    let tiles = export::write_mbtiles(source.as_ref(), &plan, &path).await?;
+    tracing::debug!("Exported {} tiles of '{}'", tiles, source_id);
+
+    let file = tokio::fs::File::open(&path).await?;
+    // The file is deleted once the response body is dropped
+    let body = tokio_util::io::ReaderStream::new(file).map(move |chunk| {
+        let _ = &path;
+        chunk
+    });
 
-    if format == "geojson" {
-        return get_tile_as_geojson(&state, &params.source, params.z, params.x, y).await;
-    }
+    let mut response = axum::body::Body::from_stream(body).into_response();
+    let headers = response.headers_mut();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("application/vnd.sqlite3"),
+    );
+    headers.insert(
+        CONTENT_DISPOSITION,
+        HeaderValue::from_str(&format!("attachment; filename=\"{}.mbtiles\"", source_id))
+            .map_err(|e| TileServerError::Internal(e.into()))?,
+    );
+    Ok(response)
+}
 
-    #[cfg(feature = "raster")]
-    let tile = {
-        #[cfg(feature = "postgres")]
-        if state.sources.is_postgres_function_source(&params.source) {
-            let query_params = serde_json::to_value(&query).unwrap_or_default();
-            state
-                .sources
-                .get_vector_tile_with_query_params(
-                    &params.source,
-                    params.z,
-                    params.x,
-                    y,
-                    &query_params,
-                )
-                .await?
-                .ok_or(TileServerError::TileNotFound {
-                    z: params.z,
-                    x: params.x,
-                    y,
-                })?
-        } else {
-            let resampling = query
-                .get("resampling")
-                .and_then(|s| s.parse::<config::ResamplingMethod>().ok());
+/// Source validation query parameters
+#[derive(serde::Deserialize, Default)]
+struct ValidateQueryParams {
+    /// Stream progress events before the report as NDJSON
+    #[serde(default)]
+    stream: bool,
+}
 
-            #[cfg(all(feature = "postgres", feature = "raster"))]
-            let query_params = if state.sources.is_outdb_raster_source(&params.source) {
+/// Check the integrity of a source's data
+/// Route: POST /data/{source}/validate[?stream=true]
+async fn post_source_validate(
+    State(state): State<AppState>,
+    Path(source): Path<String>,
+    Query(query): Query<ValidateQueryParams>,
+) -> Result<Response, TileServerError> {
+    let sources = state.sources.load();
+    let tile_source = sources
+        .get(&source)
+        .ok_or_else(|| TileServerError::SourceNotFound(source.clone()))?
+        .clone();
+
+    if !query.stream {
+        let report =
+            integrity::validate(&source, tile_source.as_ref(), state.max_check_duration).await;
+        return Ok(Json(report).into_response());
+    }
+
+    let lines = integrity::validate_stream(source, tile_source, state.max_check_duration)
+        .map(Ok::<_, std::convert::Infallible>);
+    let mut response = axum::body::Body::from_stream(lines).into_response();
+    response.headers_mut().insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("application/x-ndjson"),
+    );
+    Ok(response)
+}
+
+/// Tile inspection request parameters
+#[derive(serde::Deserialize)]
+struct TileInspectParams {
+    source: String,
+    z: u8,
+    x: u32,
+    y: u32,
+}
+
+/// Tile inspection query parameters
+#[derive(serde::Deserialize, Default)]
+struct TileInspectQueryParams {
+    /// Also check coordinates against the buffered extent and winding order
+    #[serde(default)]
+    strict: bool,
+    /// Pixels allowed outside the tile in strict mode
+    buffer: Option<String>,
+}
+
+/// Validate one tile and report its contents as JSON
+/// Route: GET /data/{source}/{z}/{x}/{y}/inspect[?strict=true]
+async fn get_tile_inspect(
+    State(state): State<AppState>,
+    Path(params): Path<TileInspectParams>,
+    Query(query): Query<TileInspectQueryParams>,
+) -> Result<Json<inspect::tile::TileReport>, TileServerError> {
+    let (z, x, y) = (params.z, params.x, params.y);
+    let sources = state.sources.load();
+    let source = sources
+        .get(&params.source)
+        .ok_or_else(|| TileServerError::SourceNotFound(params.source.clone()))?;
+    sources::validate_tile_coords(z, x, y)?;
+
+    let tile = source
+        .get_tile_with_overzoom(z, x, y)
+        .await?
+        .ok_or(TileServerError::TileNotFound { z, x, y })?;
+    let checks = inspect::tile::Checks {
+        strict: query.strict,
+        buffer: query
+            .buffer
+            .as_deref()
+            .map(sources::parse_tile_buffer)
+            .transpose()?,
+    };
+    Ok(Json(inspect::tile::report(&tile, checks)))
+}
+
+/// Tile query request parameters
+#[derive(serde::Deserialize)]
+struct TileQueryParams {
+    z: u8,
+    x: u32,
+    y_fmt: String, // e.g., "123.json"
+}
+
+/// Tile query query parameters
+#[derive(serde::Deserialize, Default)]
+struct TileQueryQueryParams {
+    /// Comma-separated source IDs to limit the query to
+    source: Option<String>,
+}
+
+/// Report the layers and features every source has at one tile
+/// Route: GET /tiles/{z}/{x}/{y}.json[?source=a,b]
+async fn get_tile_query(
+    State(state): State<AppState>,
+    Path(params): Path<TileQueryParams>,
+    Query(query): Query<TileQueryQueryParams>,
+) -> Result<Json<inspect::query::TileQuery>, TileServerError> {
+    let y = params
+        .y_fmt
+        .strip_suffix(".json")
+        .and_then(|y| y.parse().ok())
+        .ok_or(TileServerError::InvalidTileRequest)?;
+    let filter: Vec<String> = query
+        .source
+        .iter()
+        .flat_map(|s| s.split(','))
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect();
+
+    let sources = state.sources.load();
+    let report = inspect::query::query_tile(&sources, params.z, params.x, y, &filter).await?;
+    Ok(Json(report))
+}
+
+/// Signed URL request parameters
+#[derive(serde::Deserialize)]
+struct SignedUrlParams {
+    source: String,
+    z: u8,
+    x: u32,
+    y: u32,
+}
+
+/// Signed URL query parameters
+#[derive(serde::Deserialize)]
+struct SignedUrlQueryParams {
+    /// Seconds the URL stays valid
+    #[serde(default = "default_signed_url_ttl")]
+    ttl: u64,
+}
+
+fn default_signed_url_ttl() -> u64 {
+    900
+}
+
+/// Signed GCS URL and byte range for a tile of a PMTiles archive
+/// Route: GET /data/{source}/signed-url/{z}/{x}/{y}?ttl={seconds}
+async fn get_signed_tile_url(
+    State(state): State<AppState>,
+    Path(params): Path<SignedUrlParams>,
+    Query(query): Query<SignedUrlQueryParams>,
+) -> Result<Json<sources::pmtiles::http::SignedTileUrl>, TileServerError> {
+    let sources = state.sources.load();
+    let y = match sources.get(&params.source) {
+        Some(source) => source
+            .metadata()
+            .request_row(params.z, params.y, None)
+            .ok_or(TileServerError::InvalidCoordinates {
+                z: params.z,
+                x: params.x,
+                y: params.y,
+            })?,
+        None => params.y,
+    };
+    let signed = sources
+        .signed_tile_url(
+            &params.source,
+            params.z,
+            params.x,
+            y,
+            std::time::Duration::from_secs(query.ttl),
+        )
+        .await?;
+    Ok(Json(signed))
+}
+
+/// Tile diff request parameters
+#[derive(serde::Deserialize)]
+struct DiffParams {
+    source_a: String,
+    source_b: String,
+    z: u8,
+    x: u32,
+    y: u32,
+}
+
+/// Tile diff query parameters
+#[derive(serde::Deserialize)]
+struct DiffQueryParams {
+    /// `geojson` for annotated features instead of the summary
+    format: Option<String>,
+}
+
+/// Compare the same vector tile in two sources
+/// Route: GET /diff/{source_a}/{source_b}/{z}/{x}/{y}
+async fn get_tile_diff(
+    State(state): State<AppState>,
+    Path(params): Path<DiffParams>,
+    Query(query): Query<DiffQueryParams>,
+) -> Result<Response, TileServerError> {
+    let sources = state.sources.load();
+    let comparison = diff::diff_sources(
+        &sources,
+        &params.source_a,
+        &params.source_b,
+        params.z,
+        params.x,
+        params.y,
+    )
+    .await?;
+
+    match query.format.as_deref() {
+        None | Some("json") => Ok(Json(comparison.summary()).into_response()),
+        Some("geojson") => Ok((
+            [(
+                CONTENT_TYPE,
+                HeaderValue::from_static("application/geo+json"),
+            )],
+            comparison.to_geojson().to_string(),
+        )
+            .into_response()),
+        Some(format) => Err(TileServerError::BadRequest(format!(
+            "Unknown diff format '{}', expected json or geojson",
+            format
+        ))),
+    }
+}
+
+/// Tile request parameters (raw from URL)
+#[derive(serde::Deserialize)]
+struct TileParams {
+    source: String,
+    z: u8,
+    x: u32,
+    y_fmt: String, // e.g., "123.pbf", "123@2x.png", or "123" to negotiate
+}
+
+impl TileParams {
+    fn parse_y_and_format(&self) -> Option<(u32, &str)> {
+        match self.parse_y_scale_and_format()? {
+            (y, 1, format) => Some((y, format)),
+            _ => None,
+        }
+    }
+
+    /// Parse y, scale, and format from "123.png" or "123@2x.png"
+    fn parse_y_scale_and_format(&self) -> Option<(u32, u8, &str)> {
+        let (y_and_scale, format) = self.y_fmt.rsplit_once('.')?;
+        let (y_str, scale) = match y_and_scale.split_once('@') {
+            Some((y_str, scale_str)) => {
+                let scale = scale_str.strip_suffix('x')?.parse().ok()?;
+                (y_str, Some(scale).filter(|s| (1..=9).contains(s))?)
+            }
+            None => (y_and_scale, 1),
+        };
+        Some((y_str.parse().ok()?, scale, format))
+    }
+}
+
+async fn get_tile(
+    State(state): State<AppState>,
+    Path(params): Path<TileParams>,
+    Query(query): Query<std::collections::HashMap<String, String>>,
+    request_headers: HeaderMap,
+) -> Result<Response, TileServerError> {
+    if params.y_fmt.contains('.') {
+        return serve_tile(state, params, query, request_headers).await;
+    }
+
+    // Without an extension the format comes from the Accept header, so
+    // caches have to key on it, errors included
+    let mut response = serve_tile(state, params, query, request_headers)
+        .await
+        .into_response();
+    response
+        .headers_mut()
+        .append(VARY, HeaderValue::from_static("Accept"));
+    Ok(response)
+}
+
+async fn serve_tile(
+    state: AppState,
+    params: TileParams,
+    query: std::collections::HashMap<String, String>,
+    request_headers: HeaderMap,
+) -> Result<Response, TileServerError> {
+    let sources = state.sources.load();
+    let (y, scale, format) = if params.y_fmt.contains('.') {
+        params
+            .parse_y_scale_and_format()
+            .ok_or(TileServerError::InvalidTileRequest)?
+    } else {
+        let y = params
+            .y_fmt
+            .parse()
+            .map_err(|_| TileServerError::InvalidTileRequest)?;
+        (
+            y,
+            1,
+            negotiate_tile_format(&sources, &params.source, &request_headers)?,
+        )
+    };
+    sources::validate_tile_coords(params.z, params.x, y)?;
+
+    // Only sources rendered per request have tiles at other sizes
+    #[cfg(feature = "raster")]
+    let scaled = sources.renders_raster_tiles(&params.source);
+    #[cfg(not(feature = "raster"))]
+    let scaled = false;
+    if scale != 1 && !(scaled && format != "geojson") {
+        return Err(TileServerError::InvalidTileRequest);
+    }
+
+    // Clients may address a source in the other scheme via `?scheme=`
+    let requested_scheme = query
+        .get("scheme")
+        .map(|s| s.parse::<TileScheme>())
+        .transpose()
+        .map_err(|_| TileServerError::InvalidTileRequest)?;
+    let y = match sources.get(&params.source) {
+        Some(source) => source
+            .metadata()
+            .request_row(params.z, y, requested_scheme)
+            .ok_or(TileServerError::InvalidCoordinates {
+                z: params.z,
+                x: params.x,
+                y,
+            })?,
+        None => y,
+    };
+
+    if format == "geojson" {
+        return get_tile_as_geojson(&state, &params.source, params.z, params.x, y).await;
+    }
+
+    // Vector tiles built per request take a buffer; PostgreSQL functions get
+    // it with the rest of the query parameters instead
+    let buffer = query
+        .get("buffer")
+        .map(|b| sources::parse_tile_buffer(b))
+        .transpose()?;
+    #[cfg(feature = "postgres")]
+    let buffer = buffer.filter(|_| !sources.is_postgres_function_source(&params.source));
+    let buffered = match buffer {
+        Some(buffer)
+            if sources
+                .get(&params.source)
+                .is_some_and(|s| s.format() == sources::TileFormat::Pbf) =>
+        {
+            Some(
+                sources
+                    .get_tile_with_buffer(&params.source, params.z, params.x, y, Some(buffer))
+                    .await?
+                    .ok_or(TileServerError::TileNotFound {
+                        z: params.z,
+                        x: params.x,
+                        y,
+                    })?,
+            )
+        }
+        _ => None,
+    };
+
+    #[cfg(feature = "raster")]
+    let tile = if let Some(tile) = buffered {
+        tile
+    } else {
+        #[cfg(feature = "postgres")]
+        if sources.is_postgres_function_source(&params.source) {
+            let query_params = serde_json::to_value(&query).unwrap_or_default();
+            sources
+                .get_vector_tile_with_query_params(
+                    &params.source,
+                    params.z,
+                    params.x,
+                    y,
+                    &query_params,
+                )
+                .await?
+                .ok_or(TileServerError::TileNotFound {
+                    z: params.z,
+                    x: params.x,
+                    y,
+                })?
+        } else {
+            let resampling = query
+                .get("resampling")
+                .and_then(|s| s.parse::<config::ResamplingMethod>().ok());
+
+            #[cfg(all(feature = "postgres", feature = "raster"))]
+            let query_params = if sources.is_outdb_raster_source(&params.source) {
                 Some(serde_json::to_value(&query).unwrap_or_default())
             } else {
                 None
@@ -621,14 +1612,13 @@ async fn get_tile(
             #[cfg(not(all(feature = "postgres", feature = "raster")))]
             let query_params: Option<serde_json::Value> = None;
 
-            state
-                .sources
+            sources
                 .get_raster_tile_with_params(
                     &params.source,
                     params.z,
                     params.x,
                     y,
-                    256,
+                    256 * u32::from(scale),
                     resampling,
                     query_params,
                 )
@@ -646,14 +1636,13 @@ async fn get_tile(
                 .get("resampling")
                 .and_then(|s| s.parse::<config::ResamplingMethod>().ok());
 
-            state
-                .sources
+            sources
                 .get_raster_tile_with_params(
                     &params.source,
                     params.z,
                     params.x,
                     y,
-                    256,
+                    256 * u32::from(scale),
                     resampling,
                     None,
                 )
@@ -667,12 +1656,13 @@ async fn get_tile(
     };
 
     #[cfg(not(feature = "raster"))]
-    let tile = {
+    let tile = if let Some(tile) = buffered {
+        tile
+    } else {
         #[cfg(feature = "postgres")]
-        let tile = if state.sources.is_postgres_function_source(&params.source) {
+        let tile = if sources.is_postgres_function_source(&params.source) {
             let query_params: serde_json::Value = serde_json::to_value(&query).unwrap_or_default();
-            state
-                .sources
+            sources
                 .get_vector_tile_with_query_params(
                     &params.source,
                     params.z,
@@ -687,12 +1677,8 @@ async fn get_tile(
                     y,
                 })?
         } else {
-            let source = state
-                .sources
-                .get(&params.source)
-                .ok_or_else(|| TileServerError::SourceNotFound(params.source.clone()))?;
-            source
-                .get_tile(params.z, params.x, y)
+            sources
+                .get_tile(&params.source, params.z, params.x, y)
                 .await?
                 .ok_or(TileServerError::TileNotFound {
                     z: params.z,
@@ -704,13 +1690,8 @@ async fn get_tile(
         #[cfg(not(feature = "postgres"))]
         let tile = {
             let _ = query;
-            let source = state
-                .sources
-                .get(&params.source)
-                .ok_or_else(|| TileServerError::SourceNotFound(params.source.clone()))?;
-
-            source
-                .get_tile(params.z, params.x, y)
+            sources
+                .get_tile(&params.source, params.z, params.x, y)
                 .await?
                 .ok_or(TileServerError::TileNotFound {
                     z: params.z,
@@ -722,20 +1703,163 @@ async fn get_tile(
         tile
     };
 
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        CONTENT_TYPE,
-        HeaderValue::from_static(tile.format.content_type()),
-    );
-    headers.insert(CACHE_CONTROL, cache_control::tile_cache_headers());
+    let tile = tile.negotiate_encoding(accept_encoding(&request_headers))?;
+    let headers = tile_headers(&state, &params.source, &tile.info());
 
-    if let Some(encoding) = tile.compression.content_encoding() {
-        headers.insert(CONTENT_ENCODING, HeaderValue::from_static(encoding));
+    Ok((headers, tile.data).into_response())
+}
+
+/// Get an elevation tile encoded as Terrain-RGB
+/// Route: GET /data/{source}/terrain-rgb/{z}/{x}/{y}.png
+#[cfg(feature = "raster")]
+async fn get_terrain_rgb_tile(
+    State(state): State<AppState>,
+    Path(params): Path<TileParams>,
+) -> Result<Response, TileServerError> {
+    let (y, format) = params
+        .parse_y_and_format()
+        .ok_or(TileServerError::InvalidTileRequest)?;
+    if format != "png" {
+        return Err(TileServerError::BadRequest(format!(
+            "Terrain-RGB tiles are PNG, not {}",
+            format
+        )));
     }
+    sources::validate_tile_coords(params.z, params.x, y)?;
+
+    let sources = state.sources.load();
+    let tile = sources
+        .get_terrain_rgb_tile(&params.source, params.z, params.x, y, 256)
+        .await?
+        .ok_or(TileServerError::TileNotFound {
+            z: params.z,
+            x: params.x,
+            y,
+        })?;
+    let headers = tile_headers(&state, &params.source, &tile.info());
 
     Ok((headers, tile.data).into_response())
 }
 
+/// Extension of the format an extensionless tile request is served in,
+/// picked from its `Accept` header
+fn negotiate_tile_format(
+    sources: &SourceManager,
+    source_id: &str,
+    request_headers: &HeaderMap,
+) -> Result<&'static str, TileServerError> {
+    let format = sources
+        .get(source_id)
+        .ok_or_else(|| TileServerError::SourceNotFound(source_id.to_string()))?
+        .format();
+    let accept = request_headers
+        .get(ACCEPT)
+        .and_then(|value| value.to_str().ok());
+    match format.negotiate(accept) {
+        Some(sources::TileRepresentation::Native) => Ok(format.extension()),
+        Some(sources::TileRepresentation::GeoJson) => Ok("geojson"),
+        None => {
+            let offered: Vec<&str> = format.media_types().iter().map(|(t, _)| *t).collect();
+            Err(TileServerError::NotAcceptable(format!(
+                "source '{}' serves {}",
+                source_id,
+                offered.join(", ")
+            )))
+        }
+    }
+}
+
+/// Answer `HEAD` for a tile from its size and encoding, without reading it
+/// where the source can tell
+///
+/// Tiles built from query parameters, GeoJSON tiles, tiles negotiated from
+/// `Accept` and gzip tiles the client cannot accept take the `GET` path,
+/// whose body axum drops.
+async fn head_tile(
+    State(state): State<AppState>,
+    Path(params): Path<TileParams>,
+    Query(query): Query<std::collections::HashMap<String, String>>,
+    request_headers: HeaderMap,
+) -> Result<Response, TileServerError> {
+    if !params.y_fmt.contains('.') {
+        return get_tile(State(state), Path(params), Query(query), request_headers).await;
+    }
+    let (y, format) = params
+        .parse_y_and_format()
+        .ok_or(TileServerError::InvalidTileRequest)?;
+    sources::validate_tile_coords(params.z, params.x, y)?;
+    if !query.is_empty() || format == "geojson" {
+        return get_tile(State(state), Path(params), Query(query), request_headers).await;
+    }
+
+    let sources = state.sources.load();
+    let y = match sources.get(&params.source) {
+        Some(source) => source.metadata().request_row(params.z, y, None).ok_or(
+            TileServerError::InvalidCoordinates {
+                z: params.z,
+                x: params.x,
+                y,
+            },
+        )?,
+        None => y,
+    };
+    let info = sources
+        .tile_info(&params.source, params.z, params.x, y)
+        .await?
+        .ok_or(TileServerError::TileNotFound {
+            z: params.z,
+            x: params.x,
+            y,
+        })?;
+    if info.compression == sources::TileCompression::Gzip
+        && !info
+            .compression
+            .is_accepted_by(accept_encoding(&request_headers))
+    {
+        // The decompressed size is only known after decompressing
+        return get_tile(State(state), Path(params), Query(query), request_headers).await;
+    }
+
+    Ok(tile_headers(&state, &params.source, &info).into_response())
+}
+
+/// Response headers for a tile from `source_id`, shared by `GET` and `HEAD`
+fn tile_headers(state: &AppState, source_id: &str, info: &sources::TileInfo) -> HeaderMap {
+    let sources = state.sources.load();
+    let mut headers = cache_control::tile_info_headers(info);
+    cache_control::insert_validators(
+        &mut headers,
+        info,
+        sources
+            .get(source_id)
+            .and_then(|source| source.last_modified()),
+    );
+    cache_control::insert_debug_headers(
+        &mut headers,
+        state.debug_headers,
+        source_backend(state, source_id),
+        info.format.name(),
+        None,
+    );
+    headers
+}
+
+/// Backend name of a source for the `X-Tile-Source` debug header
+fn source_backend(state: &AppState, source_id: &str) -> &'static str {
+    let sources = state.sources.load();
+    sources
+        .get(source_id)
+        .map(|source| source.backend())
+        .unwrap_or("unknown")
+}
+
+/// Get the request's `Accept-Encoding` header value
+fn accept_encoding(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(ACCEPT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+}
+
 /// Get a tile as GeoJSON (helper function)
 async fn get_tile_as_geojson(
     state: &AppState,
@@ -744,78 +1868,31 @@ async fn get_tile_as_geojson(
     x: u32,
     y: u32,
 ) -> Result<Response, TileServerError> {
-    use flate2::read::GzDecoder;
-    use geozero::mvt::{Message, Tile};
-    use geozero::ProcessToJson;
-    use sources::TileCompression;
-    use std::io::Read;
-
-    let source = state
-        .sources
+    let sources = state.sources.load();
+    let source = sources
         .get(source_id)
         .ok_or_else(|| TileServerError::SourceNotFound(source_id.to_string()))?;
 
     // Check if source is vector format
     if source.metadata().format != sources::TileFormat::Pbf {
-        return Err(TileServerError::RenderError(
+        return Err(TileServerError::BadRequest(
             "GeoJSON conversion only supported for vector tiles (PBF)".to_string(),
         ));
     }
 
     let tile = source
-        .get_tile(z, x, y)
+        .get_tile_with_overzoom(z, x, y)
         .await?
         .ok_or(TileServerError::TileNotFound { z, x, y })?;
 
-    // Decompress if needed
-    let raw_data = match tile.compression {
-        TileCompression::Gzip => {
-            let mut decoder = GzDecoder::new(&tile.data[..]);
-            let mut decompressed = Vec::new();
-            decoder.read_to_end(&mut decompressed).map_err(|e| {
-                TileServerError::RenderError(format!("Failed to decompress tile: {}", e))
-            })?;
-            decompressed
-        }
-        TileCompression::None => tile.data.to_vec(),
-        _ => {
-            return Err(TileServerError::RenderError(format!(
-                "Unsupported compression: {:?}",
-                tile.compression
-            )));
-        }
-    };
-
-    // Parse MVT tile using prost
-    let mvt_tile = Tile::decode(raw_data.as_slice())
-        .map_err(|e| TileServerError::RenderError(format!("Failed to decode MVT tile: {}", e)))?;
+    let mut mvt_tile = tile.decode_mvt()?;
 
     // Convert each layer to GeoJSON and combine into a FeatureCollection
-    let mut all_features: Vec<serde_json::Value> = Vec::new();
-
-    for mut layer in mvt_tile.layers {
-        // Each layer implements GeozeroDatasource which can convert to JSON
-        if let Ok(layer_json) = layer.to_json() {
-            // Parse the layer GeoJSON (it's a FeatureCollection)
-            if let Ok(fc) = serde_json::from_str::<serde_json::Value>(&layer_json) {
-                if let Some(features) = fc.get("features").and_then(|f| f.as_array()) {
-                    // Add layer name to each feature's properties
-                    for feature in features {
-                        let mut feature = feature.clone();
-                        if let Some(props) = feature.get_mut("properties") {
-                            if let Some(props_obj) = props.as_object_mut() {
-                                props_obj.insert(
-                                    "_layer".to_string(),
-                                    serde_json::Value::String(layer.name.clone()),
-                                );
-                            }
-                        }
-                        all_features.push(feature);
-                    }
-                }
-            }
-        }
-    }
+    let all_features: Vec<serde_json::Value> = mvt_tile
+        .layers
+        .iter_mut()
+        .flat_map(sources::mvt_layer_features)
+        .collect();
 
     // Build final FeatureCollection
     let geojson = serde_json::json!({
@@ -829,6 +1906,13 @@ async fn get_tile_as_geojson(
         HeaderValue::from_static("application/geo+json"),
     );
     headers.insert(CACHE_CONTROL, cache_control::tile_cache_headers());
+    cache_control::insert_debug_headers(
+        &mut headers,
+        state.debug_headers,
+        source.backend(),
+        "geojson",
+        None,
+    );
 
     Ok((headers, geojson.to_string()).into_response())
 }
@@ -874,35 +1958,106 @@ impl RasterTileParams {
 async fn get_raster_tile(
     State(state): State<AppState>,
     Path(params): Path<RasterTileParams>,
+    Query(query): Query<LanguageQuery>,
+    Query(debug): Query<DebugQuery>,
+    Query(view): Query<ViewQuery>,
+    request_headers: HeaderMap,
 ) -> Result<Response, TileServerError> {
-    // Check if rendering is available
-    let renderer = state
-        .renderer
-        .as_ref()
-        .ok_or_else(|| TileServerError::RenderError("Rendering not available".to_string()))?;
+    let debug = DebugOptions::from_query(debug.debug.as_deref(), state.debug_endpoints)?;
+    let view = TileView::from_query(view.bearing, view.pitch)?;
+    let sources = state.sources.load();
+    let styles = state.styles.load();
 
     // Parse parameters
     let (y, scale, format) = params.parse().ok_or(TileServerError::InvalidTileRequest)?;
 
     // Get style
-    let style = state
-        .styles
+    let style = styles
         .get(&params.style)
         .ok_or_else(|| TileServerError::StyleNotFound(params.style.clone()))?;
 
+    render_style_tile(
+        &state,
+        style,
+        &sources,
+        (params.z, params.x, y),
+        (default_tile_size(&state), scale),
+        format,
+        &query,
+        debug,
+        view,
+        &request_headers,
+    )
+    .await
+}
+
+/// Render tile `(z, x, y)` of a style, `tile_size` pixels times `scale`
+///
+/// Shared by the REST, WMTS and OGC API tile endpoints. Tiles outside the
+/// style's extent are answered without rendering, and so are revalidations
+/// of unchanged tiles.
+#[allow(clippy::too_many_arguments)]
+async fn render_style_tile(
+    state: &AppState,
+    style: &Style,
+    sources: &SourceManager,
+    (z, x, y): (u8, u32, u32),
+    (tile_size, scale): (u32, u8),
+    format: ImageFormat,
+    query: &LanguageQuery,
+    debug: DebugOptions,
+    view: TileView,
+    request_headers: &HeaderMap,
+) -> Result<Response, TileServerError> {
+    sources::validate_tile_coords(z, x, y)?;
+    state
+        .render
+        .check_image_size(tile_size, tile_size, f32::from(scale))?;
+    if let Some(response) =
+        tile_outside_style(state, style, sources, (z, x, y), (tile_size, scale), format)?
+    {
+        return Ok(response);
+    }
+
+    // Check if rendering is available
+    let renderer = state
+        .renderer
+        .as_ref()
+        .ok_or(TileServerError::RenderUnavailable)?;
+
     // Rewrite style to inline tile URLs for native rendering
-    let rewritten_style =
-        styles::rewrite_style_for_native(&style.style_json, &state.base_url, &state.sources);
+    let rewritten_style = styles::rewrite_style_for_native(
+        &style.style_json,
+        &style.id,
+        &state.base_url,
+        sources,
+        &state.style_rewrite,
+        state.styles.inline_sprites(),
+    );
+    let rewritten_style = localized_style(state, &style.id, &rewritten_style, query);
+
+    // Tiles only change with the style or its data, so revalidation skips
+    // rendering; debug renders are never revalidated
+    let validators = cache_control::RenderValidators::new(&rewritten_style, style, sources);
+    if debug.is_empty() && validators.matches(request_headers) {
+        let mut headers = HeaderMap::new();
+        validators.insert(&mut headers);
+        return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+    }
 
     // Render the tile
+    let started = Instant::now();
     let image_data = renderer
-        .render_tile(
-            &rewritten_style.to_string(),
-            params.z,
-            params.x,
+        .render_tile_with_size(
+            &rewritten_style,
+            z,
+            x,
             y,
+            tile_size,
             scale,
             format,
+            debug,
+            view,
         )
         .await?;
 
@@ -912,11 +2067,73 @@ async fn get_raster_tile(
         CONTENT_TYPE,
         HeaderValue::from_static(format.content_type()),
     );
-    headers.insert(CACHE_CONTROL, cache_control::tile_cache_headers());
+    if debug.is_empty() {
+        validators.insert(&mut headers);
+    } else {
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    }
+    cache_control::insert_debug_headers(
+        &mut headers,
+        state.debug_headers,
+        "render",
+        format.name(),
+        Some(started.elapsed()),
+    );
 
     Ok((headers, image_data).into_response())
 }
 
+/// Size of `/styles/{style}/{z}/{x}/{y}` tiles, the renderer pool's
+fn default_tile_size(state: &AppState) -> u32 {
+    state
+        .renderer
+        .as_ref()
+        .map_or(PoolConfig::default().tile_size, |renderer| {
+            renderer.pool().tile_size()
+        })
+}
+
+/// `?language=` of rendered images
+#[derive(Debug, Default, serde::Deserialize)]
+struct LanguageQuery {
+    /// Language to prefer for labels, e.g. `de`
+    language: Option<String>,
+}
+
+/// `?debug=` of rendered tiles
+#[derive(Debug, Default, serde::Deserialize)]
+struct DebugQuery {
+    /// Debug overlays to draw, e.g. `tile-borders,collision`
+    debug: Option<String>,
+}
+
+/// `?bearing=` and `?pitch=` of rendered tiles
+#[derive(Debug, Default, serde::Deserialize)]
+struct ViewQuery {
+    /// Degrees clockwise from north, 0 to 360
+    bearing: Option<f64>,
+    /// Degrees of tilt, 0 to 60
+    pitch: Option<f64>,
+}
+
+/// Serialized style for the renderer, with labels in the requested language
+/// when there is one
+fn localized_style(
+    state: &AppState,
+    style_id: &str,
+    style_json: &serde_json::Value,
+    query: &LanguageQuery,
+) -> String {
+    match query
+        .language
+        .as_deref()
+        .and_then(styles::localize::sanitize_language)
+    {
+        Some(language) => state.localized_styles.get(style_id, &language, style_json),
+        None => style_json.to_string(),
+    }
+}
+
 /// Raster tile request parameters with variable tile size
 #[derive(serde::Deserialize)]
 struct RasterTileWithSizeParams {
@@ -959,66 +2176,133 @@ impl RasterTileWithSizeParams {
 async fn get_raster_tile_with_size(
     State(state): State<AppState>,
     Path(params): Path<RasterTileWithSizeParams>,
+    Query(query): Query<LanguageQuery>,
+    Query(debug): Query<DebugQuery>,
+    Query(view): Query<ViewQuery>,
+    request_headers: HeaderMap,
 ) -> Result<Response, TileServerError> {
-    // Validate tile size (only 256 and 512 are supported)
-    if params.tile_size != 256 && params.tile_size != 512 {
-        return Err(TileServerError::RenderError(format!(
-            "Invalid tile size: {}. Only 256 and 512 are supported.",
-            params.tile_size
-        )));
-    }
-
-    // Check if rendering is available
-    let renderer = state
-        .renderer
-        .as_ref()
-        .ok_or_else(|| TileServerError::RenderError("Rendering not available".to_string()))?;
+    let debug = DebugOptions::from_query(debug.debug.as_deref(), state.debug_endpoints)?;
+    let view = TileView::from_query(view.bearing, view.pitch)?;
+    let sources = state.sources.load();
+    let styles = state.styles.load();
+    state.render.check_tile_size(params.tile_size)?;
 
     // Parse parameters
-    let (y, additional_scale, format) =
-        params.parse().ok_or(TileServerError::InvalidTileRequest)?;
+    let (y, scale, format) = params.parse().ok_or(TileServerError::InvalidTileRequest)?;
 
-    // Calculate effective scale
-    // For 512px tiles, we use scale=2 (renders at 512px)
-    // For 256px tiles, we use scale=1 (renders at 256px)
-    // Additional scale from URL (e.g., @2x) multiplies on top
-    let base_scale: u8 = if params.tile_size == 512 { 2 } else { 1 };
-    let effective_scale = base_scale * additional_scale;
+    // Get style
+    let style = styles
+        .get(&params.style)
+        .ok_or_else(|| TileServerError::StyleNotFound(params.style.clone()))?;
 
-    // Clamp to valid range
-    let scale = effective_scale.min(9);
+    // The tile is rendered at its size, times the scale from the URL (`@2x`)
+    render_style_tile(
+        &state,
+        style,
+        &sources,
+        (params.z, params.x, y),
+        (u32::from(params.tile_size), scale),
+        format,
+        &query,
+        debug,
+        view,
+        &request_headers,
+    )
+    .await
+}
 
-    // Get style
-    let style = state
-        .styles
+/// Pixel query request parameters
+#[derive(serde::Deserialize)]
+struct PixelQueryParams {
+    style: String,
+    z: u8,
+    x: u32,
+    y: u32,
+}
+
+/// Pixel query parameters
+#[derive(serde::Deserialize)]
+struct PixelQueryQuery {
+    px: f64,
+    py: f64,
+    radius: Option<f64>,
+    tile_size: Option<u32>,
+}
+
+/// Get the features drawn at a pixel of a raster tile
+/// Route: GET /styles/{style}/{z}/{x}/{y}/query?px={px}&py={py}[&radius={radius}][&tile_size={tile_size}]
+async fn get_raster_tile_query(
+    State(state): State<AppState>,
+    Path(params): Path<PixelQueryParams>,
+    Query(query): Query<PixelQueryQuery>,
+) -> Result<Response, TileServerError> {
+    let sources = state.sources.load();
+    let styles = state.styles.load();
+    let style = styles
         .get(&params.style)
         .ok_or_else(|| TileServerError::StyleNotFound(params.style.clone()))?;
 
-    // Rewrite style to inline tile URLs for native rendering
-    let rewritten_style =
-        styles::rewrite_style_for_native(&style.style_json, &state.base_url, &state.sources);
+    let pixel = query::PixelQuery::new(
+        params.z,
+        params.x,
+        params.y,
+        (query.px, query.py),
+        query.radius,
+        query.tile_size,
+    )?;
+    let features = query::query_pixel(style, &sources, &pixel).await?;
 
-    // Render the tile
-    let image_data = renderer
-        .render_tile(
-            &rewritten_style.to_string(),
-            params.z,
-            params.x,
-            y,
-            scale,
-            format,
-        )
-        .await?;
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("application/geo+json"),
+    );
+    headers.insert(CACHE_CONTROL, cache_control::tile_cache_headers());
+
+    Ok((headers, features.to_string()).into_response())
+}
+
+/// Response for a raster tile outside the style's zoom range and bounds
+///
+/// `None` when the tile is inside. Otherwise a tile of the style's background
+/// color if the style sets `blank_outside`, else `TileNotFound`.
+fn tile_outside_style(
+    state: &AppState,
+    style: &Style,
+    sources: &SourceManager,
+    (z, x, y): (u8, u32, u32),
+    (tile_size, scale): (u32, u8),
+    format: ImageFormat,
+) -> Result<Option<Response>, TileServerError> {
+    if style.raster_extent(sources).contains(z, x, y) {
+        return Ok(None);
+    }
+    if !style.raster.blank_outside {
+        return Err(TileServerError::TileNotFound { z, x, y });
+    }
 
-    // Build response
     let mut headers = HeaderMap::new();
     headers.insert(
         CONTENT_TYPE,
         HeaderValue::from_static(format.content_type()),
     );
     headers.insert(CACHE_CONTROL, cache_control::tile_cache_headers());
+    cache_control::insert_debug_headers(
+        &mut headers,
+        state.debug_headers,
+        "blank",
+        format.name(),
+        None,
+    );
 
-    Ok((headers, image_data).into_response())
+    // Nothing to render outside the data, so fill the tile with the background
+    let color = style
+        .background_color()
+        .unwrap_or(render::solid::TRANSPARENT);
+    // Same size as rendered tiles
+    let size = tile_size * scale as u32;
+    let image_data = state.solid_tiles.tile(color, size, format, "outside")?;
+    Ok(Some((headers, image_data).into_response()))
 }
 
 /// Static image request parameters
@@ -1031,29 +2315,8 @@ struct StaticImageParams {
 
 impl StaticImageParams {
     /// Parse size, scale, and format from "800x600@2x.png" style string
-    fn parse(&self) -> Option<(u32, u32, u8, ImageFormat)> {
-        // Split extension: "800x600@2x" and "png"
-        let (size_and_scale, format_str) = self.size_fmt.rsplit_once('.')?;
-
-        let format = format_str.parse::<ImageFormat>().ok()?;
-
-        // Check for scale: "800x600@2x" or just "800x600"
-        let (size_str, scale) = if let Some((size, scale_str)) = size_and_scale.split_once('@') {
-            let scale = scale_str.strip_suffix('x')?.parse().ok()?;
-            if !(1..=9).contains(&scale) {
-                return None;
-            }
-            (size, scale)
-        } else {
-            (size_and_scale, 1)
-        };
-
-        // Parse width and height: "800x600"
-        let (width_str, height_str) = size_str.split_once('x')?;
-        let width = width_str.parse().ok()?;
-        let height = height_str.parse().ok()?;
-
-        Some((width, height, scale, format))
+    fn parse(&self) -> Option<(u32, u32, f32, ImageFormat)> {
+        render::parse_static_size(&self.size_fmt)
     }
 }
 
@@ -1064,16 +2327,21 @@ async fn get_static_image(
     Path(params): Path<StaticImageParams>,
     Query(query): Query<StaticQueryParams>,
 ) -> Result<Response, TileServerError> {
+    let debug = DebugOptions::from_query(query.debug.as_deref(), state.debug_endpoints)?;
+    let sources = state.sources.load();
+    let styles = state.styles.load();
     // Check if rendering is available
     let renderer = state
         .renderer
         .as_ref()
-        .ok_or_else(|| TileServerError::RenderError("Rendering not available".to_string()))?;
+        .ok_or(TileServerError::RenderUnavailable)?;
 
     // Parse parameters
     let (width, height, scale, format) = params.parse().ok_or_else(|| {
-        TileServerError::RenderError(format!("Invalid size format: {}", params.size_fmt))
+        TileServerError::BadRequest(format!("Invalid size format: {}", params.size_fmt))
     })?;
+    // `?pixelRatio=` takes the place of `@{scale}x`
+    let scale = query.pixel_ratio.unwrap_or(scale);
 
     // Parse static type
     let static_type = params
@@ -1082,19 +2350,28 @@ async fn get_static_image(
         .map_err(TileServerError::RenderError)?;
 
     // Get style
-    let style = state
-        .styles
+    let style = styles
         .get(&params.style)
         .ok_or_else(|| TileServerError::StyleNotFound(params.style.clone()))?;
 
     // Rewrite style to inline tile URLs for native rendering
-    let rewritten_style =
-        styles::rewrite_style_for_native(&style.style_json, &state.base_url, &state.sources);
+    let rewritten_style = styles::rewrite_style_for_native(
+        &style.style_json,
+        &style.id,
+        &state.base_url,
+        &sources,
+        &state.style_rewrite,
+        state.styles.inline_sprites(),
+    );
+    let language = LanguageQuery {
+        language: query.language.clone(),
+    };
+    let rewritten_style = localized_style(&state, &style.id, &rewritten_style, &language);
 
     // Create render options
-    let options = RenderOptions::for_static(
+    let mut options = RenderOptions::for_static(
         params.style.clone(),
-        rewritten_style.to_string(),
+        rewritten_style,
         static_type,
         width,
         height,
@@ -1102,12 +2379,226 @@ async fn get_static_image(
         format,
         query,
     )
-    .map_err(TileServerError::RenderError)?;
+    .map_err(TileServerError::BadRequest)?;
+    options.check_camera()?;
+    state
+        .render
+        .check_image_size(options.width, options.height, options.scale)?;
+    options.debug = debug;
 
     // Render static image
     let image_data = renderer.render_static(options).await?;
 
-    // Build response
+    Ok(static_image_response(format, image_data))
+}
+
+/// Render a static image from a JSON body
+/// Route: POST /styles/{style}/render
+async fn post_static_render(
+    State(state): State<AppState>,
+    Path(style_id): Path<String>,
+    Json(request): Json<StaticRenderRequest>,
+) -> Result<Response, TileServerError> {
+    let sources = state.sources.load();
+    let styles = state.styles.load();
+    // Check if rendering is available
+    let renderer = state
+        .renderer
+        .as_ref()
+        .ok_or(TileServerError::RenderUnavailable)?;
+
+    // Get style
+    let style = styles
+        .get(&style_id)
+        .ok_or_else(|| TileServerError::StyleNotFound(style_id.clone()))?;
+
+    // Rewrite style to inline tile URLs for native rendering
+    let rewritten_style = styles::rewrite_style_for_native(
+        &style.style_json,
+        &style.id,
+        &state.base_url,
+        &sources,
+        &state.style_rewrite,
+        state.styles.inline_sprites(),
+    );
+
+    let format = request.format;
+    let options = RenderOptions::for_render_request(style_id, rewritten_style.to_string(), request)
+        .map_err(TileServerError::BadRequest)?;
+    options.check_camera()?;
+    state
+        .render
+        .check_image_size(options.width, options.height, options.scale)?;
+
+    let image_data = renderer.render_static(options).await?;
+
+    Ok(static_image_response(format, image_data))
+}
+
+/// Get a thumbnail of a style at its default view
+/// Route: GET /styles/{style}/thumbnail.png?width=&height=
+///
+/// Without a renderer, a placeholder with the style's name is returned.
+async fn get_style_thumbnail(
+    State(state): State<AppState>,
+    Path(style_id): Path<String>,
+    Query(query): Query<thumbnail::ThumbnailQuery>,
+    request_headers: HeaderMap,
+) -> Result<Response, TileServerError> {
+    let (width, height) = query.size(state.thumbnail.max_size)?;
+    let sources = state.sources.load();
+    let styles = state.styles.load();
+    let style = styles
+        .get(&style_id)
+        .ok_or_else(|| TileServerError::StyleNotFound(style_id.clone()))?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("image/png"));
+
+    let Some(renderer) = state.renderer.as_ref() else {
+        let glyphs = match &state.fonts_dir {
+            Some(fonts_dir) => {
+                let font = thumbnail::label_font(&style.style_json)
+                    .unwrap_or_else(|| thumbnail::DEFAULT_FONT.to_string());
+                let fontstack = format!("{},{}", font, thumbnail::DEFAULT_FONT);
+                fonts::load_glyphs(
+                    fonts_dir,
+                    &state.font_files,
+                    &fontstack,
+                    "0-255.pbf",
+                    &state.glyph_cache,
+                )
+                .await
+                .ok()
+            }
+            None => None,
+        };
+        let image = thumbnail::placeholder(width, height, &style.name, glyphs.as_deref())?;
+        // Replaced by the real thumbnail once a renderer is available
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        return Ok((headers, image).into_response());
+    };
+
+    let rewritten_style = styles::rewrite_style_for_native(
+        &style.style_json,
+        &style.id,
+        &state.base_url,
+        &sources,
+        &state.style_rewrite,
+        state.styles.inline_sprites(),
+    )
+    .to_string();
+    let validators = cache_control::RenderValidators::new(&rewritten_style, style, &sources);
+    if validators.matches(&request_headers) {
+        let mut headers = HeaderMap::new();
+        validators.insert(&mut headers);
+        headers.insert(CACHE_CONTROL, cache_control::thumbnail_cache_headers());
+        return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+    }
+
+    let (center, bearing, pitch) = thumbnail::default_view(&style.style_json);
+    let request = StaticRenderRequest {
+        center,
+        size: [width, height],
+        scale: 1.0,
+        format: ImageFormat::Png,
+        markers: Vec::new(),
+        paths: Vec::new(),
+        bearing,
+        pitch,
+    };
+    let options = RenderOptions::for_render_request(style_id, rewritten_style, request)
+        .map_err(TileServerError::BadRequest)?;
+    let image = renderer.render_static(options).await?;
+
+    validators.insert(&mut headers);
+    headers.insert(CACHE_CONTROL, cache_control::thumbnail_cache_headers());
+    Ok((headers, image).into_response())
+}
+
+/// Mapbox Static Images request parameters
+#[derive(serde::Deserialize)]
+struct MapboxStaticParams {
+    username: String,
+    style_id: String,
+    #[serde(default)]
+    overlay: Option<String>,
+    position: String, // e.g., "-122.4,37.8,12,0,60", "[-77.04,38.89,-77.02,38.91]" or "auto"
+    size: String,     // e.g., "600x400" or "300x200@2x"
+}
+
+/// Mapbox Static Images query parameters
+#[derive(serde::Deserialize)]
+struct MapboxStaticQuery {
+    /// Pixels kept clear around the overlays of an `auto` image
+    padding: Option<String>,
+}
+
+/// Get a static image for a Mapbox Static Images API URL
+/// Route: GET /styles/v1/{username}/{style_id}/static/[{overlay}/]{position}/{size}
+async fn get_mapbox_static_image(
+    State(state): State<AppState>,
+    Path(params): Path<MapboxStaticParams>,
+    Query(query): Query<MapboxStaticQuery>,
+) -> Result<Response, TileServerError> {
+    if state
+        .compat
+        .mapbox_username
+        .as_deref()
+        .is_some_and(|username| username != params.username)
+    {
+        return Err(TileServerError::StyleNotFound(format!(
+            "{}/{}",
+            params.username, params.style_id
+        )));
+    }
+
+    let sources = state.sources.load();
+    let styles = state.styles.load();
+    let renderer = state
+        .renderer
+        .as_ref()
+        .ok_or(TileServerError::RenderUnavailable)?;
+
+    let request = mapbox_static::MapboxStaticRequest::parse(
+        params.overlay.as_deref(),
+        &params.position,
+        &params.size,
+    )
+    .map_err(TileServerError::BadRequest)?;
+
+    let style = styles
+        .get(&params.style_id)
+        .ok_or_else(|| TileServerError::StyleNotFound(params.style_id.clone()))?;
+
+    let rewritten_style = styles::rewrite_style_for_native(
+        &style.style_json,
+        &style.id,
+        &state.base_url,
+        &sources,
+        &state.style_rewrite,
+        state.styles.inline_sprites(),
+    );
+
+    let options = request
+        .render_options(
+            params.style_id.clone(),
+            rewritten_style.to_string(),
+            query.padding.as_deref(),
+        )
+        .map_err(TileServerError::BadRequest)?;
+    options.check_camera()?;
+    state
+        .render
+        .check_image_size(options.width, options.height, options.scale)?;
+
+    let image_data = renderer.render_static(options).await?;
+
+    Ok(static_image_response(ImageFormat::Png, image_data))
+}
+
+/// Build the response for a rendered static image
+fn static_image_response(format: ImageFormat, image_data: Vec<u8>) -> Response {
     let mut headers = HeaderMap::new();
     headers.insert(
         CONTENT_TYPE,
@@ -1119,7 +2610,7 @@ async fn get_static_image(
         HeaderValue::from_static("public, max-age=3600"),
     );
 
-    Ok((headers, image_data).into_response())
+    (headers, image_data).into_response()
 }
 
 /// Sprite request parameters
@@ -1129,68 +2620,60 @@ struct SpriteParams {
     sprite_file: String, // e.g., "sprite.png", "sprite@2x.json", "sprite.json"
 }
 
+/// Sprite request parameters for one sprite of a style's sprite array
+#[derive(serde::Deserialize)]
+struct SpriteIdParams {
+    style: String,
+    sprite_id: String,
+    sprite_file: String,
+}
+
 /// Get sprite image or metadata for a style
 /// Route: GET /styles/{style}/sprite[@{scale}x].{format}
 async fn get_sprite(
     State(state): State<AppState>,
     Path(params): Path<SpriteParams>,
 ) -> Result<Response, TileServerError> {
-    // Security: Strict validation of sprite file name
-    // Only allow: sprite.png, sprite.json, sprite@2x.png, sprite@2x.json, sprite@3x.png, etc.
-    if !params.sprite_file.starts_with("sprite") {
-        return Err(TileServerError::InvalidTileRequest);
-    }
-
-    // Security: Reject any path traversal attempts
-    if params.sprite_file.contains("..")
-        || params.sprite_file.contains('/')
-        || params.sprite_file.contains('\\')
-    {
-        return Err(TileServerError::InvalidTileRequest);
-    }
-
-    // Security: Validate sprite file matches expected pattern
-    // Valid patterns: sprite.png, sprite.json, sprite@2x.png, sprite@2x.json, sprite@3x.png, etc.
-    let valid_extensions = [".png", ".json"];
-    let has_valid_extension = valid_extensions
-        .iter()
-        .any(|ext| params.sprite_file.ends_with(ext));
-    if !has_valid_extension {
-        return Err(TileServerError::InvalidTileRequest);
-    }
-
-    // Get style to find its directory
-    let style = state
-        .styles
-        .get(&params.style)
-        .ok_or_else(|| TileServerError::StyleNotFound(params.style.clone()))?;
-
-    // Get the style directory (parent of style.json)
-    let style_dir = style
-        .path
-        .parent()
-        .ok_or_else(|| TileServerError::StyleNotFound(params.style.clone()))?;
+    sprite_response(&state, &params.style, None, &params.sprite_file).await
+}
 
-    // Build path to sprite file
-    let sprite_path = style_dir.join(&params.sprite_file);
+/// Get one sprite of a style with several sprites
+/// Route: GET /styles/{style}/{sprite_id}/sprite[@{scale}x].{format}
+async fn get_sprite_with_id(
+    State(state): State<AppState>,
+    Path(params): Path<SpriteIdParams>,
+) -> Result<Response, TileServerError> {
+    sprite_response(
+        &state,
+        &params.style,
+        Some(&params.sprite_id),
+        &params.sprite_file,
+    )
+    .await
+}
 
-    // Read the sprite file
-    let data = tokio::fs::read(&sprite_path).await.map_err(|e| {
-        tracing::debug!("Sprite file not found: {} ({})", sprite_path.display(), e);
-        TileServerError::SpriteNotFound(params.sprite_file.clone())
-    })?;
+async fn sprite_response(
+    state: &AppState,
+    style: &str,
+    sprite_id: Option<&str>,
+    sprite_file: &str,
+) -> Result<Response, TileServerError> {
+    // Security: only sprite[@{n}x].{png,webp,json} names are accepted, so user
+    // input never reaches the path as-is
+    let file = SpriteFile::parse(sprite_file).ok_or(TileServerError::InvalidTileRequest)?;
 
-    // Determine content type
-    let content_type = if params.sprite_file.ends_with(".json") {
-        "application/json"
-    } else if params.sprite_file.ends_with(".png") {
-        "image/png"
-    } else {
-        "application/octet-stream"
-    };
+    let styles = state.styles.load();
+    let style = styles
+        .get(style)
+        .ok_or_else(|| TileServerError::StyleNotFound(style.to_string()))?;
+    let dir = style.sprite_dir(sprite_id)?;
+    let data = styles::sprite::read_sprite(&dir, file).await?;
 
     let mut headers = HeaderMap::new();
-    headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static(file.format.content_type()),
+    );
     headers.insert(CACHE_CONTROL, cache_control::tile_cache_headers());
 
     Ok((headers, data).into_response())
@@ -1212,9 +2695,9 @@ async fn get_wmts_capabilities(
     Path(style_id): Path<String>,
     Query(query): Query<WmtsQueryParams>,
 ) -> Result<Response, TileServerError> {
+    let styles = state.styles.load();
     // Get style
-    let style = state
-        .styles
+    let style = styles
         .get(&style_id)
         .ok_or_else(|| TileServerError::StyleNotFound(style_id.clone()))?;
 
@@ -1223,8 +2706,7 @@ async fn get_wmts_capabilities(
         &state.base_url,
         &style_id,
         &style.name,
-        0,  // minzoom
-        22, // maxzoom
+        &style.raster_extent(&state.sources.load()),
         query.key.as_deref(),
     );
 
@@ -1238,6 +2720,235 @@ async fn get_wmts_capabilities(
     Ok((headers, xml).into_response())
 }
 
+/// WMTS KVP endpoint (GetCapabilities, GetTile, GetFeatureInfo)
+/// Route: GET /wmts?SERVICE=WMTS&REQUEST=...
+async fn get_wmts_kvp(
+    State(state): State<AppState>,
+    Query(params): Query<std::collections::HashMap<String, String>>,
+    request_headers: HeaderMap,
+) -> Result<Response, wmts::WmtsException> {
+    let sources = state.sources.load();
+    let styles = state.styles.load();
+    let key = params
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("key"))
+        .map(|(_, v)| v.as_str());
+
+    match wmts::WmtsRequest::from_kvp(&params)? {
+        wmts::WmtsRequest::GetCapabilities => {
+            let styles = styles.all();
+            let layers: Vec<_> = styles
+                .iter()
+                .map(|style| {
+                    let extent = style.raster_extent(&sources);
+                    (style.id.as_str(), style.name.as_str(), extent)
+                })
+                .collect();
+            let xml = wmts::generate_wmts_service_capabilities(&state.base_url, &layers, key);
+
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/xml"));
+            headers.insert(
+                CACHE_CONTROL,
+                HeaderValue::from_static("public, max-age=86400"),
+            );
+
+            Ok((headers, xml).into_response())
+        }
+        wmts::WmtsRequest::GetTile(tile) => {
+            let (style, tile_size) =
+                wmts::resolve_layer(&styles, &tile.layer, tile.tile_matrix_set.as_deref())?;
+            // 512px layers are 256px tiles at @2x, matching the RESTful
+            // ResourceURL template
+            let scale = (tile_size / 256) as u8;
+            let query = LanguageQuery {
+                language: params
+                    .iter()
+                    .find(|(k, _)| k.eq_ignore_ascii_case("language"))
+                    .map(|(_, v)| v.clone()),
+            };
+
+            Ok(render_style_tile(
+                &state,
+                style,
+                &sources,
+                (tile.z, tile.x, tile.y),
+                (256, scale),
+                tile.format,
+                &query,
+                DebugOptions::empty(),
+                TileView::default(),
+                &request_headers,
+            )
+            .await?)
+        }
+        wmts::WmtsRequest::GetFeatureInfo { tile, i, j } => {
+            let (style, tile_size) =
+                wmts::resolve_layer(&styles, &tile.layer, tile.tile_matrix_set.as_deref())?;
+            wmts::check_pixel(i, j, tile_size)?;
+
+            let mut features = Vec::new();
+            for source_id in style.data_source_ids() {
+                if let Some(source) = sources.get(&source_id) {
+                    features
+                        .extend(wmts::query_point(source.as_ref(), &tile, i, j, tile_size).await?);
+                }
+            }
+
+            let geojson = serde_json::json!({
+                "type": "FeatureCollection",
+                "features": features
+            });
+
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+
+            Ok((headers, geojson.to_string()).into_response())
+        }
+    }
+}
+
+/// OGC API landing page
+/// Route: GET / (only when the UI is disabled)
+async fn get_ogc_landing_page(State(state): State<AppState>) -> Json<ogcapi::LandingPage> {
+    Json(ogcapi::landing_page(&state.base_url))
+}
+
+/// OGC API conformance declaration
+/// Route: GET /conformance
+async fn get_ogc_conformance() -> Json<ogcapi::Conformance> {
+    Json(ogcapi::conformance())
+}
+
+/// OGC API tileset list for all sources and styles
+/// Route: GET /tiles
+async fn get_ogc_tilesets(State(state): State<AppState>) -> Json<ogcapi::TileSetList> {
+    let sources = state.sources.load();
+    let styles = state.styles.load();
+    Json(ogcapi::tileset_list(&state.base_url, &sources, &styles))
+}
+
+/// OGC API tileset metadata
+/// Route: GET /tiles/{tileset}
+async fn get_ogc_tileset(
+    State(state): State<AppState>,
+    Path(tileset_id): Path<String>,
+) -> Result<Json<ogcapi::TileSet>, TileServerError> {
+    let sources = state.sources.load();
+    let styles = state.styles.load();
+    let tileset = ogcapi::find_tileset(&tileset_id, &sources, &styles)
+        .ok_or_else(|| TileServerError::NotFound(format!("Tileset {}", tileset_id)))?;
+
+    Ok(Json(tileset.to_tileset(&state.base_url)))
+}
+
+/// OGC API tile request parameters
+#[derive(serde::Deserialize)]
+struct OgcTileParams {
+    tileset: String,
+    tile_matrix: u8,
+    tile_row: u32,
+    tile_col: u32,
+}
+
+/// Get a tile from an OGC API tileset
+/// Route: GET /tiles/{tileset}/{tileMatrix}/{tileRow}/{tileCol}
+async fn get_ogc_tile(
+    State(state): State<AppState>,
+    Path(params): Path<OgcTileParams>,
+    Query(query): Query<LanguageQuery>,
+    request_headers: HeaderMap,
+) -> Result<Response, TileServerError> {
+    let sources = state.sources.load();
+    let styles = state.styles.load();
+    let OgcTileParams {
+        tileset: tileset_id,
+        tile_matrix: z,
+        tile_row: y,
+        tile_col: x,
+    } = params;
+
+    let tileset = ogcapi::find_tileset(&tileset_id, &sources, &styles)
+        .ok_or_else(|| TileServerError::NotFound(format!("Tileset {}", tileset_id)))?;
+
+    if !tileset.contains_tile(z, y, x) {
+        return Err(TileServerError::TileNotFound { z, x, y });
+    }
+
+    match tileset.kind {
+        ogcapi::TilesetKind::Source => {
+            let source = sources
+                .get(&tileset_id)
+                .ok_or_else(|| TileServerError::SourceNotFound(tileset_id.clone()))?;
+
+            // OGC rows count from the top, as XYZ rows do
+            let source_y = source
+                .metadata()
+                .request_row(z, y, Some(TileScheme::Xyz))
+                .ok_or(TileServerError::InvalidCoordinates { z, x, y })?;
+            let tile = sources
+                .get_tile(&tileset_id, z, x, source_y)
+                .await?
+                .ok_or(TileServerError::TileNotFound { z, x, y })?
+                .negotiate_encoding(accept_encoding(&request_headers))?;
+
+            let mut headers = cache_control::tile_response_headers(&tile);
+            cache_control::insert_validators(&mut headers, &tile.info(), source.last_modified());
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static(tileset.media_type));
+            cache_control::insert_debug_headers(
+                &mut headers,
+                state.debug_headers,
+                source.backend(),
+                tile.format.name(),
+                None,
+            );
+
+            Ok((headers, tile.data).into_response())
+        }
+        ogcapi::TilesetKind::Style => {
+            let style = styles
+                .get(&tileset_id)
+                .ok_or_else(|| TileServerError::StyleNotFound(tileset_id.clone()))?;
+
+            // WebMercatorQuad tiles are 256 pixels
+            render_style_tile(
+                &state,
+                style,
+                &sources,
+                (z, x, y),
+                (256, 1),
+                ImageFormat::Png,
+                &query,
+                DebugOptions::empty(),
+                TileView::default(),
+                &request_headers,
+            )
+            .await
+        }
+    }
+}
+
+/// OGC API tile matrix set list
+/// Route: GET /tileMatrixSets
+async fn get_ogc_tile_matrix_sets(State(state): State<AppState>) -> Json<serde_json::Value> {
+    Json(ogcapi::tile_matrix_set_list(&state.base_url))
+}
+
+/// OGC API tile matrix set definition
+/// Route: GET /tileMatrixSets/{tileMatrixSet}
+async fn get_ogc_tile_matrix_set(
+    Path(tile_matrix_set): Path<String>,
+) -> Result<Json<ogcapi::TileMatrixSet>, TileServerError> {
+    if tile_matrix_set != ogcapi::WEB_MERCATOR_QUAD {
+        return Err(TileServerError::NotFound(format!(
+            "Tile matrix set {}",
+            tile_matrix_set
+        )));
+    }
+
+    Ok(Json(ogcapi::web_mercator_quad()))
+}
+
 /// Get list of available fonts
 /// Route: GET /fonts.json
 async fn get_fonts_list(
@@ -1268,8 +2979,12 @@ async fn get_fonts_list(
         }
     }
 
+    // Fonts generated from TTF/OTF files
+    fonts.extend(state.font_files.names().map(str::to_string));
+
     // Sort alphabetically for consistent output
     fonts.sort();
+    fonts.dedup();
 
     Ok(Json(fonts))
 }
@@ -1295,68 +3010,81 @@ struct FontParams {
     range: String,     // e.g., "0-255.pbf"
 }
 
-/// Get font glyphs (PBF format)
+/// Get font glyphs (PBF format), merged across the fontstack
 /// Route: GET /fonts/{fontstack}/{start}-{end}.pbf
 async fn get_font_glyphs(
     State(state): State<AppState>,
     Path(params): Path<FontParams>,
+    request_headers: HeaderMap,
 ) -> Result<Response, TileServerError> {
     // Check if fonts directory is configured
     let fonts_dir = state.fonts_dir.as_ref().ok_or_else(|| {
         TileServerError::FontNotFound("Fonts directory not configured".to_string())
     })?;
 
-    // Parse the range to ensure it's valid (e.g., "0-255.pbf")
-    // Must match pattern like "0-255.pbf", "256-511.pbf", etc.
-    if !params.range.ends_with(".pbf") {
-        return Err(TileServerError::InvalidTileRequest);
-    }
+    // Put the font for the reader's language first, if one is configured
+    let languages = request_headers
+        .get(ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+        .map(fonts::language::preferred_languages)
+        .unwrap_or_default();
+    let fontstack = fonts::language::language_font(&languages, &state.font_languages, |font| {
+        params.fontstack.split(',').any(|f| f.trim() == font)
+            || state.font_files.contains(font)
+            || fonts_dir.join(font).is_dir()
+    })
+    .and_then(|font| fonts::language::prioritize(&params.fontstack, font))
+    .unwrap_or_else(|| params.fontstack.clone());
+
+    let data = fonts::load_glyphs(
+        fonts_dir,
+        &state.font_files,
+        &fontstack,
+        &params.range,
+        &state.glyph_cache,
+    )
+    .await?;
 
-    // Security: Validate range format to prevent path traversal
-    let range_name = params.range.trim_end_matches(".pbf");
-    if range_name.contains("..") || range_name.contains('/') || range_name.contains('\\') {
-        return Err(TileServerError::InvalidTileRequest);
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("application/x-protobuf"),
+    );
+    headers.insert(CACHE_CONTROL, cache_control::tile_cache_headers());
+    if !state.font_languages.is_empty() {
+        headers.insert(VARY, HeaderValue::from_static("Accept-Language"));
     }
 
-    // Font stacks are comma-separated, try each font in order
-    let fonts: Vec<&str> = params.fontstack.split(',').map(|s| s.trim()).collect();
-
-    // Security: Canonicalize fonts directory for path validation
-    let canonical_fonts_dir = fonts_dir
-        .canonicalize()
-        .map_err(|_| TileServerError::FontNotFound("Fonts directory not accessible".to_string()))?;
-
-    for font_name in &fonts {
-        // Security: Reject font names with path traversal sequences
-        if font_name.contains("..") || font_name.contains('/') || font_name.contains('\\') {
-            continue;
-        }
-
-        let font_path = fonts_dir.join(font_name).join(&params.range);
-
-        // Security: Verify the resolved path is within fonts directory
-        if let Ok(canonical_path) = font_path.canonicalize() {
-            if !canonical_path.starts_with(&canonical_fonts_dir) {
-                continue; // Path escapes fonts directory
-            }
-        }
+    tracing::debug!("Serving font: {}/{}", params.fontstack, params.range);
+    Ok((headers, data).into_response())
+}
 
-        if let Ok(data) = tokio::fs::read(&font_path).await {
-            let mut headers = HeaderMap::new();
-            headers.insert(
-                CONTENT_TYPE,
-                HeaderValue::from_static("application/x-protobuf"),
-            );
-            headers.insert(CACHE_CONTROL, cache_control::tile_cache_headers());
+/// Get every glyph range a style's labels use, in one PBF
+/// Route: POST /fonts/subset
+async fn post_font_subset(
+    State(state): State<AppState>,
+    Json(style): Json<serde_json::Value>,
+) -> Result<Response, TileServerError> {
+    let fonts_dir = state.fonts_dir.as_ref().ok_or_else(|| {
+        TileServerError::FontNotFound("Fonts directory not configured".to_string())
+    })?;
 
-            tracing::debug!("Serving font: {}/{}", font_name, params.range);
-            return Ok((headers, data).into_response());
-        }
-    }
+    let data = fonts::subset::load_subset(
+        fonts_dir,
+        &state.font_files,
+        &style,
+        &state.glyph_cache,
+        &state.glyph_subsets,
+    )
+    .await?;
 
-    // No font found in the stack
-    tracing::debug!("Font not found: {} (tried: {:?})", params.range, fonts);
-    Err(TileServerError::FontNotFound(params.fontstack))
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("application/x-protobuf"),
+    );
+    headers.insert(CACHE_CONTROL, cache_control::tile_cache_headers());
+    Ok((headers, data).into_response())
 }
 
 /// Get a static file from the files directory
@@ -1364,6 +3092,7 @@ async fn get_font_glyphs(
 async fn get_static_file(
     State(state): State<AppState>,
     Path(filepath): Path<String>,
+    request_headers: HeaderMap,
 ) -> Result<Response, TileServerError> {
     // Check if files directory is configured
     let files_dir = state
@@ -1371,47 +3100,5 @@ async fn get_static_file(
         .as_ref()
         .ok_or_else(|| TileServerError::NotFound("Files directory not configured".to_string()))?;
 
-    // Sanitize the filepath to prevent directory traversal attacks
-    let filepath = filepath.trim_start_matches('/');
-    if filepath.contains("..") || filepath.starts_with('/') {
-        return Err(TileServerError::NotFound("Invalid file path".to_string()));
-    }
-
-    let file_path = files_dir.join(filepath);
-
-    // Ensure the resolved path is still within the files directory
-    let canonical_files_dir = files_dir
-        .canonicalize()
-        .map_err(|_| TileServerError::NotFound("Files directory not accessible".to_string()))?;
-    let canonical_file_path = file_path
-        .canonicalize()
-        .map_err(|_| TileServerError::NotFound(format!("File not found: {}", filepath)))?;
-
-    if !canonical_file_path.starts_with(&canonical_files_dir) {
-        return Err(TileServerError::NotFound("Invalid file path".to_string()));
-    }
-
-    // Read the file
-    let data = tokio::fs::read(&canonical_file_path)
-        .await
-        .map_err(|_| TileServerError::NotFound(format!("File not found: {}", filepath)))?;
-
-    // Determine content type from extension
-    let content_type = mime_guess::from_path(&canonical_file_path)
-        .first_or_octet_stream()
-        .to_string();
-
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        CONTENT_TYPE,
-        HeaderValue::from_str(&content_type)
-            .unwrap_or(HeaderValue::from_static("application/octet-stream")),
-    );
-    // Cache static files for 1 hour
-    headers.insert(
-        CACHE_CONTROL,
-        HeaderValue::from_static("public, max-age=3600"),
-    );
-
-    Ok((headers, data).into_response())
+    files::serve(files_dir, &filepath, &request_headers).await
 }
@@ -0,0 +1,566 @@
+//! Mapbox Static Images API compatibility
+//!
+//! Maps `/styles/v1/{username}/{style_id}/static/[{overlay}/]{position}/{size}`
+//! onto the static image renderer, so tools set up for Mapbox can point at
+//! this server. Enabled with `[compat] mapbox_static = true`.
+//!
+//! Overlays are a comma-separated list of markers (`pin-s-a+f00(lon,lat)`),
+//! paths (`path-5+f44-0.5+f4440-0.5({polyline})`) and GeoJSON
+//! (`geojson({geojson})`) styled with simplestyle-spec properties. Custom
+//! marker images (`url-...`) are drawn as default pins.
+//!
+//! See <https://docs.mapbox.com/api/maps/static-images/>
+
+use image::Rgba;
+use serde_json::Value;
+
+use crate::render::overlay::{decode_polyline, parse_hex_color, parse_marker};
+use crate::render::overlay::{GeoPoint, MarkerOverlay, PathOverlay};
+use crate::render::{ImageFormat, RenderOptions, StaticQueryParams, StaticType};
+
+/// Mapbox's default overlay color
+const DEFAULT_COLOR: Rgba<u8> = Rgba([0x55, 0x55, 0x55, 255]);
+
+/// A parsed Mapbox Static Images request
+#[derive(Debug, Clone)]
+pub struct MapboxStaticRequest {
+    pub static_type: StaticType,
+    pub width: u32,
+    pub height: u32,
+    pub scale: f32,
+    pub paths: Vec<PathOverlay>,
+    pub markers: Vec<MarkerOverlay>,
+}
+
+impl MapboxStaticRequest {
+    /// Parse the `{overlay}`, `{position}` and `{size}` path segments
+    ///
+    /// `position` is `lon,lat,zoom[,bearing[,pitch]]`, `[west,south,east,north]`
+    /// or `auto`; `size` is `{width}x{height}[@2x]`.
+    pub fn parse(overlay: Option<&str>, position: &str, size: &str) -> Result<Self, String> {
+        let static_type = parse_position(position)?;
+        let (width, height, scale) = parse_size(size)?;
+        let (paths, markers) = match overlay {
+            Some(overlay) => parse_overlays(overlay)?,
+            None => (Vec::new(), Vec::new()),
+        };
+
+        if matches!(static_type, StaticType::Auto) && paths.is_empty() && markers.is_empty() {
+            return Err("auto position requires at least one overlay".to_string());
+        }
+
+        Ok(Self {
+            static_type,
+            width,
+            height,
+            scale,
+            paths,
+            markers,
+        })
+    }
+
+    /// Options for rendering the image as PNG
+    ///
+    /// `padding` is Mapbox's `padding` query parameter: pixels kept clear
+    /// around the overlays of an `auto` image, as `all` or
+    /// `top,right,bottom,left`.
+    pub fn render_options(
+        self,
+        style_id: String,
+        style_json: String,
+        padding: Option<&str>,
+    ) -> Result<RenderOptions, String> {
+        let auto = matches!(self.static_type, StaticType::Auto);
+        let padding = padding
+            .map(|padding| padding_fraction(padding, self.width, self.height))
+            .transpose()?;
+
+        let mut options = RenderOptions::for_static(
+            style_id,
+            style_json,
+            self.static_type,
+            self.width,
+            self.height,
+            self.scale,
+            ImageFormat::Png,
+            StaticQueryParams::default(),
+        )?
+        .with_overlays(self.paths, self.markers);
+        if auto {
+            options.fit_overlays(padding, None);
+        }
+        Ok(options)
+    }
+}
+
+fn parse_position(position: &str) -> Result<StaticType, String> {
+    if position == "auto" {
+        return Ok(StaticType::Auto);
+    }
+
+    if let Some(bbox) = position.strip_prefix('[').and_then(|p| p.strip_suffix(']')) {
+        let values = parse_numbers(bbox)?;
+        let [min_lon, min_lat, max_lon, max_lat] = values[..] else {
+            return Err(format!("Invalid bounding box: {}", position));
+        };
+        return Ok(StaticType::BoundingBox {
+            min_lon,
+            min_lat,
+            max_lon,
+            max_lat,
+        });
+    }
+
+    match parse_numbers(position)?[..] {
+        [lon, lat, zoom] => Ok(StaticType::Center {
+            lon,
+            lat,
+            zoom,
+            bearing: None,
+            pitch: None,
+        }),
+        [lon, lat, zoom, bearing] => Ok(StaticType::Center {
+            lon,
+            lat,
+            zoom,
+            bearing: Some(bearing),
+            pitch: None,
+        }),
+        [lon, lat, zoom, bearing, pitch] => Ok(StaticType::Center {
+            lon,
+            lat,
+            zoom,
+            bearing: Some(bearing),
+            pitch: Some(pitch),
+        }),
+        _ => Err(format!("Invalid position: {}", position)),
+    }
+}
+
+fn parse_numbers(list: &str) -> Result<Vec<f64>, String> {
+    list.split(',')
+        .map(|n| {
+            n.trim()
+                .parse()
+                .map_err(|_| format!("Invalid number: {}", n))
+        })
+        .collect()
+}
+
+fn parse_size(size: &str) -> Result<(u32, u32, f32), String> {
+    let invalid = || format!("Invalid size: {}", size);
+    let (dimensions, scale) = match size.split_once('@') {
+        Some((dimensions, scale)) => {
+            let scale = scale
+                .strip_suffix('x')
+                .and_then(|s| s.parse().ok())
+                .ok_or_else(invalid)?;
+            (dimensions, scale)
+        }
+        None => (size, 1.0),
+    };
+    let (width, height) = dimensions.split_once('x').ok_or_else(invalid)?;
+    Ok((
+        width.parse().map_err(|_| invalid())?,
+        height.parse().map_err(|_| invalid())?,
+        scale,
+    ))
+}
+
+/// Padding in pixels as a fraction of the overlays' extent, for
+/// [`RenderOptions::fit_overlays`]
+fn padding_fraction(padding: &str, width: u32, height: u32) -> Result<f64, String> {
+    let pixels = parse_numbers(padding)?
+        .into_iter()
+        .fold(0.0, f64::max)
+        .max(0.0);
+    let clear = 1.0 - 2.0 * pixels / width.min(height) as f64;
+    if clear <= 0.0 {
+        return Err(format!("Padding {} leaves no room for overlays", padding));
+    }
+    Ok(1.0 / clear - 1.0)
+}
+
+/// Split overlays at commas that are not inside parentheses or strings
+fn split_overlays(overlay: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut in_string, mut escaped) = (0usize, false, false);
+    let mut start = 0;
+
+    for (i, c) in overlay.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                parts.push(&overlay[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&overlay[start..]);
+    parts
+}
+
+/// Parse a comma-separated list of Mapbox overlays
+pub fn parse_overlays(overlay: &str) -> Result<(Vec<PathOverlay>, Vec<MarkerOverlay>), String> {
+    let mut paths = Vec::new();
+    let mut markers = Vec::new();
+
+    for part in split_overlays(overlay) {
+        let part = part.trim();
+        if let Some(geojson) = part
+            .strip_prefix("geojson(")
+            .and_then(|p| p.strip_suffix(')'))
+        {
+            let geojson: Value = serde_json::from_str(geojson)
+                .map_err(|e| format!("Invalid GeoJSON overlay: {}", e))?;
+            add_geojson(&geojson, &Value::Null, &mut paths, &mut markers)?;
+        } else if part.starts_with("path") {
+            paths.push(parse_path(part)?);
+        } else if part.starts_with("pin-") {
+            markers.push(parse_marker(part).ok_or_else(|| format!("Invalid marker: {}", part))?);
+        } else if let Some(custom) = part.strip_prefix("url-") {
+            // Custom marker images are not fetched; draw a pin in their place
+            let position = custom
+                .strip_suffix(')')
+                .and_then(|c| c.rsplit_once('('))
+                .and_then(|(_, coordinates)| parse_marker(coordinates))
+                .ok_or_else(|| format!("Invalid marker: {}", part))?
+                .position;
+            markers.push(marker(position, DEFAULT_COLOR, "medium"));
+        } else {
+            return Err(format!("Unknown overlay: {}", part));
+        }
+    }
+
+    Ok((paths, markers))
+}
+
+/// Parse `path-{strokeWidth}+{strokeColor}-{strokeOpacity}+{fillColor}-{fillOpacity}({polyline})`
+fn parse_path(part: &str) -> Result<PathOverlay, String> {
+    let invalid = || format!("Invalid path: {}", part);
+    let (style, polyline) = part
+        .strip_suffix(')')
+        .and_then(|p| p.split_once('('))
+        .ok_or_else(invalid)?;
+
+    let mut sections = style.split('+');
+    let stroke_width = match sections.next().and_then(|s| s.strip_prefix("path")) {
+        Some("") => 5.0,
+        Some(width) => width
+            .strip_prefix('-')
+            .and_then(|w| w.parse().ok())
+            .ok_or_else(invalid)?,
+        None => return Err(invalid()),
+    };
+    let stroke_color = match sections.next() {
+        Some(stroke) => color_with_opacity(stroke).ok_or_else(invalid)?,
+        None => DEFAULT_COLOR,
+    };
+    let fill_color = sections
+        .next()
+        .map(|fill| color_with_opacity(fill).ok_or_else(invalid))
+        .transpose()?;
+
+    let points = decode_polyline(polyline);
+    if points.len() < 2 {
+        return Err(format!("Path needs at least 2 points: {}", part));
+    }
+
+    Ok(PathOverlay {
+        points,
+        stroke_color,
+        stroke_width,
+        fill_color,
+    })
+}
+
+/// Parse `{color}[-{opacity}]`
+fn color_with_opacity(spec: &str) -> Option<Rgba<u8>> {
+    let (color, opacity) = match spec.split_once('-') {
+        Some((color, opacity)) => (color, Some(opacity.parse().ok()?)),
+        None => (spec, None),
+    };
+    Some(with_opacity(parse_hex_color(color)?, opacity))
+}
+
+fn with_opacity(mut color: Rgba<u8>, opacity: Option<f64>) -> Rgba<u8> {
+    if let Some(opacity) = opacity {
+        color.0[3] = (opacity.clamp(0.0, 1.0) * 255.0).round() as u8;
+    }
+    color
+}
+
+fn marker(position: GeoPoint, color: Rgba<u8>, size: &str) -> MarkerOverlay {
+    MarkerOverlay {
+        position,
+        color,
+        label: None,
+        size: match size {
+            "small" => 20.0,
+            "large" => 36.0,
+            _ => 28.0,
+        },
+    }
+}
+
+/// Add the overlays of a GeoJSON object, styled by simplestyle-spec
+/// properties of its feature
+fn add_geojson(
+    geojson: &Value,
+    properties: &Value,
+    paths: &mut Vec<PathOverlay>,
+    markers: &mut Vec<MarkerOverlay>,
+) -> Result<(), String> {
+    let prop = |name: &str| properties.get(name);
+    let color = |name: &str, opacity: &str, default_opacity: f64| {
+        let color = prop(name)
+            .and_then(|c| c.as_str())
+            .and_then(parse_hex_color)
+            .unwrap_or(DEFAULT_COLOR);
+        let opacity = prop(opacity).and_then(|o| o.as_f64());
+        with_opacity(color, Some(opacity.unwrap_or(default_opacity)))
+    };
+    let coordinates = geojson.get("coordinates");
+
+    match geojson.get("type").and_then(|t| t.as_str()) {
+        Some("FeatureCollection") => {
+            for feature in array(geojson.get("features"))? {
+                add_geojson(feature, &Value::Null, paths, markers)?;
+            }
+        }
+        Some("Feature") => {
+            let properties = geojson.get("properties").unwrap_or(&Value::Null);
+            if let Some(geometry) = geojson.get("geometry").filter(|g| !g.is_null()) {
+                add_geojson(geometry, properties, paths, markers)?;
+            }
+        }
+        Some("GeometryCollection") => {
+            for geometry in array(geojson.get("geometries"))? {
+                add_geojson(geometry, properties, paths, markers)?;
+            }
+        }
+        Some("Point") => {
+            let size = prop("marker-size").and_then(|s| s.as_str()).unwrap_or("");
+            let position = point(coordinates)?;
+            markers.push(marker(position, color("marker-color", "", 1.0), size));
+        }
+        Some("MultiPoint") => {
+            let size = prop("marker-size").and_then(|s| s.as_str()).unwrap_or("");
+            for position in array(coordinates)? {
+                markers.push(marker(
+                    point(Some(position))?,
+                    color("marker-color", "", 1.0),
+                    size,
+                ));
+            }
+        }
+        Some(kind @ ("LineString" | "MultiLineString" | "Polygon" | "MultiPolygon")) => {
+            let lines: Vec<&Value> = match kind {
+                "LineString" => vec![coordinates.unwrap_or(&Value::Null)],
+                "MultiLineString" | "Polygon" => array(coordinates)?.iter().collect(),
+                _ => array(coordinates)?
+                    .iter()
+                    .map(|polygon| array(Some(polygon)))
+                    .collect::<Result<Vec<_>, _>>()?
+                    .into_iter()
+                    .flatten()
+                    .collect(),
+            };
+            let fill_color = kind
+                .ends_with("Polygon")
+                .then(|| color("fill", "fill-opacity", 0.6));
+
+            for line in lines {
+                let points = array(Some(line))?
+                    .iter()
+                    .map(|p| point(Some(p)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if points.len() < 2 {
+                    continue;
+                }
+                paths.push(PathOverlay {
+                    points,
+                    stroke_color: color("stroke", "stroke-opacity", 1.0),
+                    stroke_width: prop("stroke-width").and_then(|w| w.as_f64()).unwrap_or(2.0)
+                        as f32,
+                    fill_color,
+                });
+            }
+        }
+        other => return Err(format!("Unsupported GeoJSON type: {:?}", other)),
+    }
+    Ok(())
+}
+
+fn array(value: Option<&Value>) -> Result<&Vec<Value>, String> {
+    value
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| "Invalid GeoJSON: expected an array".to_string())
+}
+
+fn point(value: Option<&Value>) -> Result<GeoPoint, String> {
+    match array(value)?.as_slice() {
+        [lon, lat, ..] => match (lon.as_f64(), lat.as_f64()) {
+            (Some(lon), Some(lat)) => Ok(GeoPoint { lon, lat }),
+            _ => Err("Invalid GeoJSON position".to_string()),
+        },
+        _ => Err("Invalid GeoJSON position".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_position() {
+        assert!(matches!(
+            parse_position("-122.4241,37.78,14.25,30,60").unwrap(),
+            StaticType::Center {
+                zoom,
+                bearing: Some(bearing),
+                pitch: Some(pitch),
+                ..
+            } if zoom == 14.25 && bearing == 30.0 && pitch == 60.0
+        ));
+        assert!(matches!(
+            parse_position("-122.4241,37.78,14").unwrap(),
+            StaticType::Center { bearing: None, .. }
+        ));
+        assert!(matches!(
+            parse_position("[-77.04,38.89,-77.02,38.91]").unwrap(),
+            StaticType::BoundingBox { min_lon, max_lat, .. } if min_lon == -77.04 && max_lat == 38.91
+        ));
+        assert!(matches!(parse_position("auto").unwrap(), StaticType::Auto));
+        assert!(parse_position("-122.4,37.8").is_err());
+        assert!(parse_position("[1,2,3]").is_err());
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("600x400").unwrap(), (600, 400, 1.0));
+        assert_eq!(parse_size("300x200@2x").unwrap(), (300, 200, 2.0));
+        assert_eq!(parse_size("300x200@1.5x").unwrap(), (300, 200, 1.5));
+        assert!(parse_size("300").is_err());
+        assert!(parse_size("300x200@x").is_err());
+    }
+
+    #[test]
+    fn test_split_overlays() {
+        assert_eq!(
+            split_overlays(
+                r#"pin-s+f00(1,2),geojson({"type":"Point","coordinates":[1,2]}),pin-l(3,4)"#
+            ),
+            vec![
+                "pin-s+f00(1,2)",
+                r#"geojson({"type":"Point","coordinates":[1,2]})"#,
+                "pin-l(3,4)"
+            ]
+        );
+        assert_eq!(
+            split_overlays(r#"geojson({"title":"a),b"})"#),
+            vec![r#"geojson({"title":"a),b"})"#]
+        );
+    }
+
+    #[test]
+    fn test_parse_marker_and_path_overlays() {
+        // Example from the Mapbox documentation
+        let (paths, markers) = parse_overlays(
+            "pin-s-a+9ed4bd(-122.46589,37.77343),pin-s-b+000(-122.42816,37.75965),\
+             path-5+f44-0.5(_p~iF~ps|U_ulLnnqC_mqNvxq`@)",
+        )
+        .unwrap();
+
+        assert_eq!(markers.len(), 2);
+        assert_eq!(markers[0].color, Rgba([0x9e, 0xd4, 0xbd, 255]));
+        assert_eq!(markers[1].position.lat, 37.75965);
+        assert_eq!(paths.len(), 1);
+        assert_eq!(paths[0].stroke_width, 5.0);
+        assert_eq!(paths[0].stroke_color, Rgba([0xff, 0x44, 0x44, 128]));
+        assert_eq!(paths[0].fill_color, None);
+        assert_eq!(paths[0].points.len(), 3);
+        assert_eq!(paths[0].points[0].lat, 38.5);
+
+        let (paths, _) = parse_overlays("path+00f+f00-0.25(_p~iF~ps|U_ulLnnqC)").unwrap();
+        assert_eq!(paths[0].stroke_width, 5.0);
+        assert_eq!(paths[0].stroke_color, Rgba([0, 0, 255, 255]));
+        assert_eq!(paths[0].fill_color, Some(Rgba([255, 0, 0, 64])));
+
+        let (_, markers) =
+            parse_overlays("url-https%3A%2F%2Fexample.com%2Fpin.png(-76.9,38.9)").unwrap();
+        assert_eq!(markers[0].position.lon, -76.9);
+
+        assert!(parse_overlays("path-x+f44(_p~iF~ps|U_ulLnnqC)").is_err());
+        assert!(parse_overlays("circle(1,2)").is_err());
+    }
+
+    #[test]
+    fn test_parse_geojson_overlay() {
+        let (paths, markers) = parse_overlays(
+            r##"geojson({"type":"FeatureCollection","features":[
+                {"type":"Feature","properties":{"marker-color":"#0f0","marker-size":"large"},
+                 "geometry":{"type":"Point","coordinates":[-76.9,38.9]}},
+                {"type":"Feature","properties":{"stroke":"#00f","stroke-width":4,"stroke-opacity":0.5},
+                 "geometry":{"type":"LineString","coordinates":[[-76.9,38.9],[-77.0,38.8]]}},
+                {"type":"Feature","properties":{"fill":"#f00","fill-opacity":0},
+                 "geometry":{"type":"Polygon","coordinates":[[[0,0],[1,0],[1,1],[0,0]]]}}
+            ]})"##,
+        )
+        .unwrap();
+
+        assert_eq!(markers.len(), 1);
+        assert_eq!(markers[0].color, Rgba([0, 255, 0, 255]));
+        assert_eq!(markers[0].size, 36.0);
+        assert_eq!(paths.len(), 2);
+        assert_eq!(paths[0].stroke_color, Rgba([0, 0, 255, 128]));
+        assert_eq!(paths[0].stroke_width, 4.0);
+        assert_eq!(paths[1].points.len(), 4);
+        assert_eq!(paths[1].stroke_color, DEFAULT_COLOR);
+        assert_eq!(paths[1].fill_color, Some(Rgba([255, 0, 0, 0])));
+
+        assert!(parse_overlays(r#"geojson({"type":"Point"})"#).is_err());
+        assert!(parse_overlays("geojson({nope})").is_err());
+    }
+
+    #[test]
+    fn test_auto_fits_overlays() {
+        let request = MapboxStaticRequest::parse(
+            Some("pin-s(-122.46589,37.77343),pin-s(-122.42816,37.75965)"),
+            "auto",
+            "500x300@2x",
+        )
+        .unwrap();
+        let options = request
+            .render_options("basic".to_string(), "{}".to_string(), Some("50"))
+            .unwrap();
+
+        assert_eq!(
+            (options.width, options.height, options.scale),
+            (500, 300, 2.0)
+        );
+        assert_eq!(options.format, ImageFormat::Png);
+        assert!((options.lon - -122.447025).abs() < 1e-9);
+        assert!((options.lat - 37.76654).abs() < 1e-9);
+        assert!(options.zoom > 10.0 && options.zoom <= 18.0);
+        assert_eq!(options.markers.len(), 2);
+
+        assert!(MapboxStaticRequest::parse(None, "auto", "500x300").is_err());
+        let request = MapboxStaticRequest::parse(Some("pin-s(1,2)"), "auto", "500x300").unwrap();
+        assert!(request
+            .render_options("basic".to_string(), "{}".to_string(), Some("150"))
+            .is_err());
+    }
+}
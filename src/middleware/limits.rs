@@ -0,0 +1,85 @@
+//! Request size limits
+//!
+//! Caps the request body at `[server] max_request_body_bytes` and the query
+//! string at `max_query_string_bytes`, so oversized render requests or
+//! static image paths cannot exhaust memory. Bodies declaring a larger
+//! `Content-Length` are refused before they are read; bodies without one
+//! are cut off by [`DefaultBodyLimit`] once the limit is reached.
+
+use axum::{
+    body::Body,
+    extract::{DefaultBodyLimit, Request, State},
+    http::header,
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Router,
+};
+
+use crate::config::ServerConfig;
+use crate::error::TileServerError;
+
+/// Request size limits in bytes, 0 to disable one
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RequestLimits {
+    pub max_body_bytes: u64,
+    pub max_query_bytes: u64,
+}
+
+impl From<&ServerConfig> for RequestLimits {
+    fn from(config: &ServerConfig) -> Self {
+        Self {
+            max_body_bytes: config.max_request_body_bytes,
+            max_query_bytes: config.max_query_string_bytes,
+        }
+    }
+}
+
+/// Wrap `router` so requests over the limits are refused
+pub fn apply(router: Router, limits: RequestLimits) -> Router {
+    let body_limit = match usize::try_from(limits.max_body_bytes) {
+        Ok(0) | Err(_) => DefaultBodyLimit::disable(),
+        Ok(max) => DefaultBodyLimit::max(max),
+    };
+    router
+        .layer(body_limit)
+        .layer(middleware::from_fn_with_state(limits, request_limits))
+}
+
+/// Middleware answering 414 for long query strings and 413 for bodies
+/// declared larger than the limit
+pub async fn request_limits(
+    State(limits): State<RequestLimits>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    if let Err(e) = check_request(&request, limits) {
+        tracing::debug!("Refused request to {}: {}", request.uri().path(), e);
+        return e.into_response();
+    }
+    next.run(request).await
+}
+
+fn check_request(request: &Request<Body>, limits: RequestLimits) -> Result<(), TileServerError> {
+    let query_bytes = request.uri().query().map_or(0, str::len) as u64;
+    if limits.max_query_bytes > 0 && query_bytes > limits.max_query_bytes {
+        return Err(TileServerError::UriTooLong(format!(
+            "query string of {} bytes is over the {} byte limit",
+            query_bytes, limits.max_query_bytes
+        )));
+    }
+
+    let body_bytes = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    if let Some(body_bytes) = body_bytes {
+        if limits.max_body_bytes > 0 && body_bytes > limits.max_body_bytes {
+            return Err(TileServerError::PayloadTooLarge(format!(
+                "request body of {} bytes is over the {} byte limit",
+                body_bytes, limits.max_body_bytes
+            )));
+        }
+    }
+    Ok(())
+}
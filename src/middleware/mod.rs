@@ -0,0 +1,4 @@
+//! Request middleware shared by the public and admin routers
+
+pub mod limits;
+pub mod real_ip;
@@ -0,0 +1,245 @@
+//! Client IP address of each request
+//!
+//! By default the client is the socket peer. Behind a reverse proxy that is
+//! the proxy itself, so with `[server] trust_proxy_headers` the address is
+//! read from `X-Forwarded-For`, or from the RFC 7239 `Forwarded` header when
+//! there is no `X-Forwarded-For`.
+//!
+//! Both headers list the client first and each proxy appends the address it
+//! received the request from, so anything left of the entry added by the
+//! first trusted proxy may have been made up by the client. Setting
+//! `trusted_proxy_depth` to the number of proxies picks that entry instead
+//! of the leftmost one.
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap},
+    middleware::{self, Next},
+    response::Response,
+    Router,
+};
+use std::net::{IpAddr, SocketAddr};
+
+use crate::config::ServerConfig;
+
+/// Client IP address, available from the request extensions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+/// Where the client IP is taken from
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RealIpSettings {
+    pub trust_proxy_headers: bool,
+    pub trusted_proxy_depth: Option<usize>,
+}
+
+impl From<&ServerConfig> for RealIpSettings {
+    fn from(config: &ServerConfig) -> Self {
+        Self {
+            trust_proxy_headers: config.trust_proxy_headers,
+            trusted_proxy_depth: config.trusted_proxy_depth,
+        }
+    }
+}
+
+/// Wrap `router` so every request carries its [`ClientIp`] when it is known
+pub fn apply(router: Router, settings: RealIpSettings) -> Router {
+    router.layer(middleware::from_fn_with_state(settings, real_ip))
+}
+
+/// Middleware that stores the client IP in the request extensions
+pub async fn real_ip(
+    State(settings): State<RealIpSettings>,
+    mut request: Request<Body>,
+    next: Next,
+) -> Response {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0.ip());
+    if let Some(ip) = client_ip(request.headers(), peer, settings) {
+        request.extensions_mut().insert(ClientIp(ip));
+    }
+    next.run(request).await
+}
+
+/// Client IP for a request from `peer` (unknown on Unix sockets)
+pub fn client_ip(
+    headers: &HeaderMap,
+    peer: Option<IpAddr>,
+    settings: RealIpSettings,
+) -> Option<IpAddr> {
+    let forwarded = settings
+        .trust_proxy_headers
+        .then(|| forwarded_client(headers, settings.trusted_proxy_depth))
+        .flatten();
+    forwarded.or(peer).map(|ip| ip.to_canonical())
+}
+
+/// Client address from the forwarding headers
+///
+/// Falls back to the socket peer, by returning `None`, when the chosen entry
+/// is not an IP address (such as `unknown` or an obfuscated identifier).
+fn forwarded_client(headers: &HeaderMap, depth: Option<usize>) -> Option<IpAddr> {
+    let mut hops = header_values(headers, "x-forwarded-for")
+        .map(str::trim)
+        .collect::<Vec<_>>();
+    if hops.is_empty() {
+        hops = header_values(headers, header::FORWARDED.as_str())
+            .filter_map(forwarded_for)
+            .collect();
+    }
+
+    let index = match depth {
+        Some(depth) => hops.len().saturating_sub(depth.max(1)),
+        None => 0,
+    };
+    parse_ip(hops.get(index)?)
+}
+
+/// Comma-separated entries of every `name` header, in order
+fn header_values<'a>(headers: &'a HeaderMap, name: &str) -> impl Iterator<Item = &'a str> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter(|entry| !entry.trim().is_empty())
+}
+
+/// `for=` parameter of one `Forwarded` element, with its quotes removed
+fn forwarded_for(element: &str) -> Option<&str> {
+    element.split(';').find_map(|pair| {
+        let (key, value) = pair.trim().split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case("for")
+            .then(|| value.trim().trim_matches('"'))
+    })
+}
+
+/// IP address of an entry, which may carry a port (`192.0.2.1:4711`,
+/// `[2001:db8::1]:4711`) or brackets (`[2001:db8::1]`)
+fn parse_ip(entry: &str) -> Option<IpAddr> {
+    entry
+        .parse::<IpAddr>()
+        .ok()
+        .or_else(|| entry.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+        .or_else(|| {
+            entry
+                .strip_prefix('[')?
+                .strip_suffix(']')?
+                .parse::<IpAddr>()
+                .ok()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    const PEER: &str = "10.0.0.2";
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn resolve(
+        headers: &[(&str, &str)],
+        trust_proxy_headers: bool,
+        trusted_proxy_depth: Option<usize>,
+    ) -> Option<IpAddr> {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.append(
+                header::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                HeaderValue::from_str(value).unwrap(),
+            );
+        }
+        let settings = RealIpSettings {
+            trust_proxy_headers,
+            trusted_proxy_depth,
+        };
+        client_ip(&map, Some(ip(PEER)), settings)
+    }
+
+    #[test]
+    fn test_socket_address_by_default() {
+        let headers = [("x-forwarded-for", "203.0.113.7")];
+        assert_eq!(resolve(&headers, false, None), Some(ip(PEER)));
+        assert_eq!(resolve(&[], true, None), Some(ip(PEER)));
+    }
+
+    #[test]
+    fn test_x_forwarded_for() {
+        let single = [("x-forwarded-for", "203.0.113.7")];
+        assert_eq!(resolve(&single, true, None), Some(ip("203.0.113.7")));
+
+        // Several hops, split across headers
+        let chain = [
+            ("x-forwarded-for", "203.0.113.7, 198.51.100.1"),
+            ("x-forwarded-for", "192.0.2.9"),
+        ];
+        assert_eq!(resolve(&chain, true, None), Some(ip("203.0.113.7")));
+
+        // Ports and IPv6 entries
+        let ported = [("x-forwarded-for", "203.0.113.7:51234")];
+        assert_eq!(resolve(&ported, true, None), Some(ip("203.0.113.7")));
+        let v6 = [("x-forwarded-for", "[2001:db8::1]:4711, 10.0.0.1")];
+        assert_eq!(resolve(&v6, true, None), Some(ip("2001:db8::1")));
+
+        // Garbage falls back to the socket address
+        let garbage = [("x-forwarded-for", "not-an-ip")];
+        assert_eq!(resolve(&garbage, true, None), Some(ip(PEER)));
+    }
+
+    #[test]
+    fn test_ipv4_mapped_addresses() {
+        let mapped = [("x-forwarded-for", "::ffff:203.0.113.7")];
+        assert_eq!(resolve(&mapped, true, None), Some(ip("203.0.113.7")));
+
+        let settings = RealIpSettings::default();
+        let peer = Some(ip("::ffff:10.0.0.2"));
+        assert_eq!(client_ip(&HeaderMap::new(), peer, settings), Some(ip(PEER)));
+    }
+
+    #[test]
+    fn test_trusted_proxy_depth() {
+        // The client made up the first entry; two proxies added the rest
+        let spoofed = [("x-forwarded-for", "1.2.3.4, 203.0.113.7, 198.51.100.1")];
+        assert_eq!(resolve(&spoofed, true, None), Some(ip("1.2.3.4")));
+        assert_eq!(resolve(&spoofed, true, Some(2)), Some(ip("203.0.113.7")));
+        assert_eq!(resolve(&spoofed, true, Some(1)), Some(ip("198.51.100.1")));
+
+        // Fewer entries than proxies: the leftmost one is the closest guess
+        let short = [("x-forwarded-for", "203.0.113.7")];
+        assert_eq!(resolve(&short, true, Some(3)), Some(ip("203.0.113.7")));
+    }
+
+    #[test]
+    fn test_forwarded_header() {
+        let forwarded = [(
+            "forwarded",
+            r#"for="[2001:db8:cafe::17]:4711";proto=https, for=198.51.100.1;by=10.0.0.1"#,
+        )];
+        assert_eq!(
+            resolve(&forwarded, true, None),
+            Some(ip("2001:db8:cafe::17"))
+        );
+        assert_eq!(resolve(&forwarded, true, Some(1)), Some(ip("198.51.100.1")));
+
+        let case = [("forwarded", "proto=http;For=192.0.2.60")];
+        assert_eq!(resolve(&case, true, None), Some(ip("192.0.2.60")));
+
+        let hidden = [("forwarded", "for=_hidden, for=unknown")];
+        assert_eq!(resolve(&hidden, true, None), Some(ip(PEER)));
+
+        // X-Forwarded-For wins when both are present
+        let both = [
+            ("forwarded", "for=192.0.2.60"),
+            ("x-forwarded-for", "203.0.113.7"),
+        ];
+        assert_eq!(resolve(&both, true, None), Some(ip("203.0.113.7")));
+    }
+}
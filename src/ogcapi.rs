@@ -0,0 +1,607 @@
+//! OGC API - Tiles support
+//!
+//! Implements the core, tileset and tilesets-list conformance classes of
+//! OGC API - Tiles 1.0 on top of the configured sources and styles. All
+//! tilesets use the WebMercatorQuad tile matrix set, whose definition is
+//! derived from the same scale denominators as the WMTS capabilities.
+
+use serde::Serialize;
+
+use crate::sources::{SourceManager, TileFormat, TileMetadata};
+use crate::styles::{Style, StyleManager};
+use crate::wmts;
+
+/// Identifier of the only tile matrix set served
+pub const WEB_MERCATOR_QUAD: &str = "WebMercatorQuad";
+
+const WEB_MERCATOR_QUAD_URI: &str =
+    "http://www.opengis.net/def/tilematrixset/OGC/1.0/WebMercatorQuad";
+const CRS_EPSG_3857: &str = "http://www.opengis.net/def/crs/EPSG/0/3857";
+const CRS_CRS84: &str = "http://www.opengis.net/def/crs/OGC/1.3/CRS84";
+const REL_TILING_SCHEME: &str = "http://www.opengis.net/def/rel/ogc/1.0/tiling-scheme";
+const REL_CONFORMANCE: &str = "http://www.opengis.net/def/rel/ogc/1.0/conformance";
+const REL_TILESETS_VECTOR: &str = "http://www.opengis.net/def/rel/ogc/1.0/tilesets-vector";
+const REL_TILESETS_MAP: &str = "http://www.opengis.net/def/rel/ogc/1.0/tilesets-map";
+const REL_TILING_SCHEMES: &str = "http://www.opengis.net/def/rel/ogc/1.0/tiling-schemes";
+
+/// Highest tile matrix in the WebMercatorQuad definition
+const MAX_TILE_MATRIX: u8 = 24;
+
+/// Web Mercator latitude limit in degrees
+pub const MAX_LATITUDE: f64 = 85.051128779807;
+
+/// Conformance classes implemented by this server
+pub const CONFORMANCE_CLASSES: &[&str] = &[
+    "http://www.opengis.net/spec/ogcapi-common-1/1.0/conf/core",
+    "http://www.opengis.net/spec/ogcapi-common-1/1.0/conf/json",
+    "http://www.opengis.net/spec/ogcapi-tiles-1/1.0/conf/core",
+    "http://www.opengis.net/spec/ogcapi-tiles-1/1.0/conf/tileset",
+    "http://www.opengis.net/spec/ogcapi-tiles-1/1.0/conf/tilesets-list",
+    "http://www.opengis.net/spec/ogcapi-tiles-1/1.0/conf/mvt",
+    "http://www.opengis.net/spec/ogcapi-tiles-1/1.0/conf/png",
+    "http://www.opengis.net/spec/tms/2.0/conf/json-tilematrixset",
+];
+
+/// A hypermedia link
+#[derive(Debug, Clone, Serialize)]
+pub struct Link {
+    pub href: String,
+    pub rel: String,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub templated: Option<bool>,
+}
+
+impl Link {
+    fn new(href: String, rel: &str, media_type: &str) -> Self {
+        Self {
+            href,
+            rel: rel.to_string(),
+            media_type: Some(media_type.to_string()),
+            title: None,
+            templated: None,
+        }
+    }
+
+    fn titled(mut self, title: &str) -> Self {
+        self.title = Some(title.to_string());
+        self
+    }
+}
+
+/// Landing page (`/`)
+#[derive(Debug, Serialize)]
+pub struct LandingPage {
+    pub title: String,
+    pub description: String,
+    pub links: Vec<Link>,
+}
+
+/// Conformance declaration (`/conformance`)
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Conformance {
+    pub conforms_to: Vec<String>,
+}
+
+/// Kind of data a tileset contains
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DataType {
+    Map,
+    Vector,
+}
+
+/// Entry of the tileset list (`/tiles`)
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TileSetItem {
+    pub title: String,
+    pub data_type: DataType,
+    pub crs: String,
+    #[serde(rename = "tileMatrixSetURI")]
+    pub tile_matrix_set_uri: String,
+    pub links: Vec<Link>,
+}
+
+/// Tileset list (`/tiles`)
+#[derive(Debug, Serialize)]
+pub struct TileSetList {
+    pub tilesets: Vec<TileSetItem>,
+}
+
+/// Tileset metadata (`/tiles/{tilesetId}`)
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TileSet {
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attribution: Option<String>,
+    pub data_type: DataType,
+    pub crs: String,
+    #[serde(rename = "tileMatrixSetURI")]
+    pub tile_matrix_set_uri: String,
+    pub tile_matrix_set_limits: Vec<TileMatrixLimits>,
+    pub bounding_box: BoundingBox,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub center_point: Option<CenterPoint>,
+    pub links: Vec<Link>,
+}
+
+/// Range of tiles available at one tile matrix
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TileMatrixLimits {
+    pub tile_matrix: String,
+    pub min_tile_row: u32,
+    pub max_tile_row: u32,
+    pub min_tile_col: u32,
+    pub max_tile_col: u32,
+}
+
+/// Bounding box in CRS84
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BoundingBox {
+    pub lower_left: [f64; 2],
+    pub upper_right: [f64; 2],
+    pub crs: String,
+}
+
+/// Suggested center of a tileset
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CenterPoint {
+    pub coordinates: [f64; 2],
+    pub crs: String,
+    pub tile_matrix: String,
+}
+
+/// Tile matrix set definition (`/tileMatrixSets/WebMercatorQuad`)
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TileMatrixSet {
+    pub id: String,
+    pub title: String,
+    pub uri: String,
+    pub crs: String,
+    pub ordered_axes: [String; 2],
+    pub well_known_scale_set: String,
+    pub tile_matrices: Vec<TileMatrix>,
+}
+
+/// One zoom level of a tile matrix set
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TileMatrix {
+    pub id: String,
+    pub scale_denominator: f64,
+    pub cell_size: f64,
+    pub corner_of_origin: String,
+    pub point_of_origin: [f64; 2],
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub matrix_width: u32,
+    pub matrix_height: u32,
+}
+
+/// What backs a tileset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TilesetKind {
+    /// A data source, served as stored
+    Source,
+    /// A style, rendered to PNG
+    Style,
+}
+
+/// A source or style exposed as an OGC API tileset
+#[derive(Debug, Clone)]
+pub struct Tileset {
+    pub id: String,
+    pub kind: TilesetKind,
+    pub title: String,
+    pub description: Option<String>,
+    pub attribution: Option<String>,
+    pub data_type: DataType,
+    pub media_type: &'static str,
+    pub minzoom: u8,
+    pub maxzoom: u8,
+    /// Bounds as [west, south, east, north]
+    pub bounds: [f64; 4],
+    pub center: Option<[f64; 3]>,
+}
+
+impl Tileset {
+    /// Describe a data source
+    pub fn from_source(metadata: &TileMetadata) -> Self {
+        let (data_type, media_type) = match metadata.format {
+            TileFormat::Pbf => (DataType::Vector, "application/vnd.mapbox-vector-tile"),
+            format => (DataType::Map, format.content_type()),
+        };
+
+        Self {
+            id: metadata.id.clone(),
+            kind: TilesetKind::Source,
+            title: metadata.name.clone(),
+            description: metadata.description.clone(),
+            attribution: metadata.attribution.clone(),
+            data_type,
+            media_type,
+            minzoom: metadata.minzoom,
+            // Overzoomed sources can serve every tile matrix of the set
+            maxzoom: if metadata.overzoom {
+                MAX_TILE_MATRIX
+            } else {
+                metadata.maxzoom.min(MAX_TILE_MATRIX)
+            },
+            bounds: metadata
+                .bounds
+                .unwrap_or([-180.0, -MAX_LATITUDE, 180.0, MAX_LATITUDE]),
+            center: metadata.center,
+        }
+    }
+
+    /// Describe a style rendered as raster tiles
+    pub fn from_style(style: &Style, sources: &SourceManager) -> Self {
+        let extent = style.raster_extent(sources);
+        Self {
+            id: style.id.clone(),
+            kind: TilesetKind::Style,
+            title: style.name.clone(),
+            description: None,
+            attribution: None,
+            data_type: DataType::Map,
+            media_type: "image/png",
+            minzoom: extent.minzoom,
+            maxzoom: extent.maxzoom,
+            bounds: extent.bounds,
+            center: None,
+        }
+    }
+
+    /// Whether a tile lies within the tile matrix set limits of this tileset
+    pub fn contains_tile(&self, z: u8, row: u32, col: u32) -> bool {
+        if z < self.minzoom || z > self.maxzoom {
+            return false;
+        }
+        let limits = tile_matrix_limits(z, &self.bounds);
+        (limits.min_tile_row..=limits.max_tile_row).contains(&row)
+            && (limits.min_tile_col..=limits.max_tile_col).contains(&col)
+    }
+
+    fn url(&self, base_url: &str) -> String {
+        format!("{}/tiles/{}", base_url, self.id)
+    }
+
+    fn tiling_scheme_link(base_url: &str) -> Link {
+        Link::new(
+            format!("{}/tileMatrixSets/{}", base_url, WEB_MERCATOR_QUAD),
+            REL_TILING_SCHEME,
+            "application/json",
+        )
+        .titled("WebMercatorQuad tile matrix set definition")
+    }
+
+    /// Entry for the tileset list
+    pub fn to_item(&self, base_url: &str) -> TileSetItem {
+        TileSetItem {
+            title: self.title.clone(),
+            data_type: self.data_type,
+            crs: CRS_EPSG_3857.to_string(),
+            tile_matrix_set_uri: WEB_MERCATOR_QUAD_URI.to_string(),
+            links: vec![
+                Link::new(self.url(base_url), "self", "application/json"),
+                Self::tiling_scheme_link(base_url),
+            ],
+        }
+    }
+
+    /// Full tileset metadata
+    pub fn to_tileset(&self, base_url: &str) -> TileSet {
+        let [west, south, east, north] = self.bounds;
+
+        TileSet {
+            title: self.title.clone(),
+            description: self.description.clone(),
+            attribution: self.attribution.clone(),
+            data_type: self.data_type,
+            crs: CRS_EPSG_3857.to_string(),
+            tile_matrix_set_uri: WEB_MERCATOR_QUAD_URI.to_string(),
+            tile_matrix_set_limits: (self.minzoom..=self.maxzoom)
+                .map(|z| tile_matrix_limits(z, &self.bounds))
+                .collect(),
+            bounding_box: BoundingBox {
+                lower_left: [west, south],
+                upper_right: [east, north],
+                crs: CRS_CRS84.to_string(),
+            },
+            center_point: self.center.map(|[lon, lat, zoom]| CenterPoint {
+                coordinates: [lon, lat],
+                crs: CRS_CRS84.to_string(),
+                tile_matrix: (zoom.round() as u8)
+                    .clamp(self.minzoom, self.maxzoom)
+                    .to_string(),
+            }),
+            links: vec![
+                Link::new(self.url(base_url), "self", "application/json"),
+                Self::tiling_scheme_link(base_url),
+                Link {
+                    href: format!(
+                        "{}/{{tileMatrix}}/{{tileRow}}/{{tileCol}}",
+                        self.url(base_url)
+                    ),
+                    rel: "item".to_string(),
+                    media_type: Some(self.media_type.to_string()),
+                    title: Some("Tiles".to_string()),
+                    templated: Some(true),
+                },
+            ],
+        }
+    }
+}
+
+/// Look up a tileset by ID; data sources take precedence over styles
+pub fn find_tileset(id: &str, sources: &SourceManager, styles: &StyleManager) -> Option<Tileset> {
+    sources
+        .get(id)
+        .map(|source| Tileset::from_source(source.metadata()))
+        .or_else(|| {
+            styles
+                .get(id)
+                .map(|style| Tileset::from_style(style, sources))
+        })
+}
+
+/// All tilesets: data sources first, then styles, each sorted by ID
+pub fn all_tilesets(sources: &SourceManager, styles: &StyleManager) -> Vec<Tileset> {
+    let mut source_tilesets: Vec<Tileset> = sources
+        .all_metadata()
+        .into_iter()
+        .map(Tileset::from_source)
+        .collect();
+    source_tilesets.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let mut style_tilesets: Vec<Tileset> = styles
+        .all()
+        .into_iter()
+        .filter(|style| sources.get(&style.id).is_none())
+        .map(|style| Tileset::from_style(style, sources))
+        .collect();
+    style_tilesets.sort_by(|a, b| a.id.cmp(&b.id));
+
+    source_tilesets.extend(style_tilesets);
+    source_tilesets
+}
+
+/// Landing page document
+pub fn landing_page(base_url: &str) -> LandingPage {
+    LandingPage {
+        title: "TileServer RS".to_string(),
+        description: "Vector and raster tiles via OGC API - Tiles".to_string(),
+        links: vec![
+            Link::new(format!("{}/", base_url), "self", "application/json").titled("This document"),
+            Link::new(
+                format!("{}/openapi.json", base_url),
+                "service-desc",
+                "application/vnd.oai.openapi+json;version=3.0",
+            )
+            .titled("API definition"),
+            Link::new(
+                format!("{}/conformance", base_url),
+                REL_CONFORMANCE,
+                "application/json",
+            )
+            .titled("Conformance declaration"),
+            Link::new(
+                format!("{}/tiles", base_url),
+                REL_TILESETS_VECTOR,
+                "application/json",
+            )
+            .titled("Vector tilesets"),
+            Link::new(
+                format!("{}/tiles", base_url),
+                REL_TILESETS_MAP,
+                "application/json",
+            )
+            .titled("Map tilesets"),
+            Link::new(
+                format!("{}/tileMatrixSets", base_url),
+                REL_TILING_SCHEMES,
+                "application/json",
+            )
+            .titled("Tile matrix sets"),
+        ],
+    }
+}
+
+/// Conformance declaration document
+pub fn conformance() -> Conformance {
+    Conformance {
+        conforms_to: CONFORMANCE_CLASSES.iter().map(|c| c.to_string()).collect(),
+    }
+}
+
+/// Tileset list document
+pub fn tileset_list(base_url: &str, sources: &SourceManager, styles: &StyleManager) -> TileSetList {
+    TileSetList {
+        tilesets: all_tilesets(sources, styles)
+            .iter()
+            .map(|tileset| tileset.to_item(base_url))
+            .collect(),
+    }
+}
+
+/// Tile matrix set list document (`/tileMatrixSets`)
+pub fn tile_matrix_set_list(base_url: &str) -> serde_json::Value {
+    serde_json::json!({
+        "tileMatrixSets": [{
+            "id": WEB_MERCATOR_QUAD,
+            "title": "Google Maps Compatible for the World",
+            "uri": WEB_MERCATOR_QUAD_URI,
+            "links": [Link::new(
+                format!("{}/tileMatrixSets/{}", base_url, WEB_MERCATOR_QUAD),
+                "self",
+                "application/json",
+            )]
+        }]
+    })
+}
+
+/// WebMercatorQuad tile matrix set definition
+pub fn web_mercator_quad() -> TileMatrixSet {
+    TileMatrixSet {
+        id: WEB_MERCATOR_QUAD.to_string(),
+        title: "Google Maps Compatible for the World".to_string(),
+        uri: WEB_MERCATOR_QUAD_URI.to_string(),
+        crs: CRS_EPSG_3857.to_string(),
+        ordered_axes: ["X".to_string(), "Y".to_string()],
+        well_known_scale_set: "http://www.opengis.net/def/wkss/OGC/1.0/GoogleMapsCompatible"
+            .to_string(),
+        tile_matrices: (0..=MAX_TILE_MATRIX)
+            .map(|z| {
+                let scale_denominator = wmts::scale_denominator(z);
+                let matrix_size = 1u32 << z;
+                TileMatrix {
+                    id: z.to_string(),
+                    scale_denominator,
+                    cell_size: scale_denominator * wmts::PIXEL_SIZE_METERS,
+                    corner_of_origin: "topLeft".to_string(),
+                    point_of_origin: [-wmts::WEB_MERCATOR_EXTENT, wmts::WEB_MERCATOR_EXTENT],
+                    tile_width: 256,
+                    tile_height: 256,
+                    matrix_width: matrix_size,
+                    matrix_height: matrix_size,
+                }
+            })
+            .collect(),
+    }
+}
+
+/// Tiles covering `bounds` ([west, south, east, north]) at zoom `z`
+pub fn tile_matrix_limits(z: u8, bounds: &[f64; 4]) -> TileMatrixLimits {
+    let [west, south, east, north] = *bounds;
+    let (min_col, min_row) = lon_lat_to_tile(west, north, z);
+    let (max_col, max_row) = lon_lat_to_tile(east, south, z);
+
+    TileMatrixLimits {
+        tile_matrix: z.to_string(),
+        min_tile_row: min_row,
+        max_tile_row: max_row,
+        min_tile_col: min_col,
+        max_tile_col: max_col,
+    }
+}
+
+/// Tile column and row (top-left origin) containing a coordinate
+pub(crate) fn lon_lat_to_tile(lon: f64, lat: f64, z: u8) -> (u32, u32) {
+    let n = 2f64.powi(z as i32);
+    let max_index = (n - 1.0).max(0.0);
+
+    let lat_rad = lat.clamp(-MAX_LATITUDE, MAX_LATITUDE).to_radians();
+    let x = ((lon.clamp(-180.0, 180.0) + 180.0) / 360.0 * n).floor();
+    let y = ((1.0 - lat_rad.tan().asinh() / std::f64::consts::PI) / 2.0 * n).floor();
+
+    (
+        x.clamp(0.0, max_index) as u32,
+        y.clamp(0.0, max_index) as u32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world_tileset() -> Tileset {
+        Tileset {
+            id: "world".to_string(),
+            kind: TilesetKind::Source,
+            title: "World".to_string(),
+            description: None,
+            attribution: None,
+            data_type: DataType::Vector,
+            media_type: "application/vnd.mapbox-vector-tile",
+            minzoom: 0,
+            maxzoom: 4,
+            bounds: [-180.0, -MAX_LATITUDE, 180.0, MAX_LATITUDE],
+            center: Some([0.0, 0.0, 2.0]),
+        }
+    }
+
+    #[test]
+    fn test_lon_lat_to_tile() {
+        assert_eq!(lon_lat_to_tile(0.0, 0.0, 0), (0, 0));
+        assert_eq!(lon_lat_to_tile(-180.0, 90.0, 2), (0, 0));
+        assert_eq!(lon_lat_to_tile(180.0, -90.0, 2), (3, 3));
+        // Florence
+        assert_eq!(lon_lat_to_tile(11.25, 43.77, 10), (544, 373));
+    }
+
+    #[test]
+    fn test_tile_matrix_limits_world() {
+        let limits = tile_matrix_limits(3, &[-180.0, -MAX_LATITUDE, 180.0, MAX_LATITUDE]);
+        assert_eq!(
+            limits,
+            TileMatrixLimits {
+                tile_matrix: "3".to_string(),
+                min_tile_row: 0,
+                max_tile_row: 7,
+                min_tile_col: 0,
+                max_tile_col: 7,
+            }
+        );
+    }
+
+    #[test]
+    fn test_contains_tile() {
+        let mut tileset = world_tileset();
+        assert!(tileset.contains_tile(4, 15, 15));
+        assert!(!tileset.contains_tile(5, 0, 0));
+
+        tileset.bounds = [11.22, 43.74, 11.29, 43.79];
+        tileset.maxzoom = 14;
+        assert!(tileset.contains_tile(10, 373, 543));
+        assert!(!tileset.contains_tile(10, 0, 0));
+    }
+
+    #[test]
+    fn test_web_mercator_quad_matches_wmts() {
+        let tms = web_mercator_quad();
+        assert_eq!(tms.tile_matrices.len(), 25);
+        assert_eq!(
+            tms.tile_matrices[12].scale_denominator,
+            wmts::scale_denominator(12)
+        );
+        assert_eq!(tms.tile_matrices[24].matrix_width, 1 << 24);
+        assert!((tms.tile_matrices[0].cell_size - 156543.033928).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_tileset_json_required_properties() {
+        let json =
+            serde_json::to_value(world_tileset().to_tileset("http://localhost:8080")).unwrap();
+
+        // tileSet.yaml requires dataType, crs and links
+        assert_eq!(json["dataType"], "vector");
+        assert_eq!(json["crs"], CRS_EPSG_3857);
+        assert_eq!(json["tileMatrixSetURI"], WEB_MERCATOR_QUAD_URI);
+        assert_eq!(json["tileMatrixSetLimits"].as_array().unwrap().len(), 5);
+        assert_eq!(json["centerPoint"]["tileMatrix"], "2");
+
+        let links = json["links"].as_array().unwrap();
+        assert!(links
+            .iter()
+            .all(|l| l["href"].is_string() && l["rel"].is_string()));
+        let item = links.iter().find(|l| l["rel"] == "item").unwrap();
+        assert_eq!(
+            item["href"],
+            "http://localhost:8080/tiles/world/{tileMatrix}/{tileRow}/{tileCol}"
+        );
+        assert_eq!(item["templated"], true);
+        assert!(links.iter().any(|l| l["rel"] == REL_TILING_SCHEME));
+    }
+}
@@ -8,6 +8,7 @@
 
 #![allow(dead_code)]
 
+use crate::error::ErrorCode;
 use utoipa::OpenApi;
 
 /// OpenAPI documentation for tileserver-rs
@@ -25,32 +26,78 @@ use utoipa::OpenApi;
         (name = "Data", description = "Tile data sources: vector (PMTiles, MBTiles, PostgreSQL), raster (COG), and OutDB raster (PostGIS)"),
         (name = "Styles", description = "Map styles and raster tile rendering"),
         (name = "Fonts", description = "Font glyphs for map labels"),
-        (name = "Files", description = "Static file serving")
+        (name = "Files", description = "Static file serving"),
+        (name = "Events", description = "Server-sent notifications of reloads and invalidated tiles"),
+        (name = "OGC API", description = "OGC API - Tiles: landing page, conformance, tilesets and tile matrix sets")
     ),
     paths(
         health_check,
+        health_live,
+        health_ready,
         get_index,
         list_data_sources,
         get_data_source,
         get_tile,
+        get_negotiated_tile,
+        inspect_data_source,
+        quality_data_source,
+        export_data_source,
+        inspect_tile,
+        query_tile,
+        validate_data_source,
+        get_signed_tile_url,
+        get_terrain_rgb_tile,
+        get_tile_diff,
         list_styles,
         get_style_tilejson,
         get_style_json,
+        get_style_thumbnail,
+        get_style_validation,
         get_raster_tile,
         get_raster_tile_with_size,
+        query_raster_tile,
         get_static_image,
+        post_static_render,
+        get_mapbox_static_image,
         get_sprite,
+        get_sprite_with_id,
         get_wmts_capabilities,
+        get_wmts_kvp,
         list_fonts,
         get_font_glyphs,
+        post_font_subset,
         get_static_file,
+        subscribe_events,
+        get_ogc_landing_page,
+        get_ogc_conformance,
+        get_ogc_tilesets,
+        get_ogc_tileset,
+        get_ogc_tile,
+        get_ogc_tile_matrix_sets,
+        get_ogc_tile_matrix_set,
     ),
     components(schemas(
         TileJSON,
         VectorLayer,
         StyleInfo,
         GeoJSON,
+        StaticRenderRequest,
+        MarkerOverlay,
+        PathOverlay,
+        ReadinessReport,
+        SourceHealth,
+        ServerEvent,
+        TileDiff,
+        LayerDiff,
+        SignedTileUrl,
+        StyleValidation,
+        StyleDiagnostic,
+        IntegrityReport,
+        IntegrityIssue,
+        CheckTiming,
         ApiError,
+        ApiErrorBody,
+        ErrorCode,
     ))
 )]
 pub struct ApiDoc;
@@ -100,6 +147,40 @@ pub struct TileJSON {
     /// Vector layer definitions
     #[schema(nullable)]
     pub vector_layers: Option<Vec<VectorLayer>>,
+    /// Tile row scheme ("xyz" or "tms"), omitted for the default "xyz"
+    #[schema(nullable)]
+    pub scheme: Option<String>,
+    /// Elevation encoding of raster-dem tiles ("mapbox" for Terrain-RGB or
+    /// "terrarium"), for elevation sources only
+    #[schema(nullable)]
+    pub encoding: Option<String>,
+    /// Number of stored tiles (`/index.json` data entries, MBTiles and
+    /// PMTiles only)
+    #[schema(nullable)]
+    pub tile_count: Option<u64>,
+    /// Size of the source file in bytes (`/index.json` data entries, local
+    /// files only)
+    #[schema(nullable)]
+    pub size_bytes: Option<u64>,
+    /// URL of a small static render (`/index.json` style entries only)
+    #[schema(nullable)]
+    pub thumbnail: Option<String>,
+    /// URL of the HTML preview page (`/index.json` entries with
+    /// `preview_pages = true` only)
+    #[schema(nullable)]
+    pub preview: Option<String>,
+    /// Number of stored tiles (`/data/{source}?extended=true`, MBTiles and
+    /// PMTiles only)
+    #[schema(rename = "x-tile-count", nullable)]
+    pub x_tile_count: Option<u64>,
+    /// Size of the source file in bytes (`/data/{source}?extended=true`,
+    /// local files only)
+    #[schema(rename = "x-storage-bytes", nullable)]
+    pub x_storage_bytes: Option<u64>,
+    /// Modification time of the source file, RFC 3339
+    /// (`/data/{source}?extended=true`, local files only)
+    #[schema(rename = "x-last-modified", nullable)]
+    pub x_last_modified: Option<String>,
 }
 
 /// Vector layer metadata
@@ -126,7 +207,8 @@ pub struct VectorLayer {
 #[schema(example = json!({
     "id": "osm-bright",
     "name": "OSM Bright",
-    "url": "http://localhost:8080/styles/osm-bright/style.json"
+    "url": "http://localhost:8080/styles/osm-bright/style.json",
+    "thumbnail": "http://localhost:8080/styles/osm-bright/thumbnail.png"
 }))]
 pub struct StyleInfo {
     /// Style identifier
@@ -135,6 +217,8 @@ pub struct StyleInfo {
     pub name: String,
     /// URL to style.json
     pub url: String,
+    /// URL of a preview image of the style
+    pub thumbnail: Option<String>,
 }
 
 /// GeoJSON FeatureCollection
@@ -147,12 +231,286 @@ pub struct GeoJSON {
     pub features: Vec<serde_json::Value>,
 }
 
+/// JSON body for rendering a static map image
+#[derive(utoipa::ToSchema)]
+#[schema(example = json!({
+    "center": [-122.4194, 37.7749, 12],
+    "size": [800, 600],
+    "scale": 2,
+    "format": "png",
+    "markers": [{"position": [-122.4194, 37.7749], "color": "#f00", "size": 28}],
+    "paths": [{"points": [[-122.42, 37.77], [-122.41, 37.78]], "stroke_color": "#00f", "stroke_width": 4}],
+    "bearing": 0,
+    "pitch": 0
+}))]
+pub struct StaticRenderRequest {
+    /// Map center as [longitude, latitude, zoom]
+    #[schema(min_items = 3, max_items = 3)]
+    pub center: Vec<f64>,
+    /// Image size as [width, height] in pixels (each at most 4096)
+    #[schema(min_items = 2, max_items = 2)]
+    pub size: Vec<u32>,
+    /// Pixel ratio, fractional allowed (0.5-8, default 1)
+    #[schema(minimum = 0.5, maximum = 8, default = 1)]
+    pub scale: Option<f32>,
+    /// Image format: png, jpg, jpeg or webp (default png)
+    #[schema(default = "png")]
+    pub format: Option<String>,
+    /// Markers to draw on the image
+    pub markers: Option<Vec<MarkerOverlay>>,
+    /// Paths to draw on the image
+    pub paths: Option<Vec<PathOverlay>>,
+    /// Map bearing in degrees (default 0)
+    pub bearing: Option<f64>,
+    /// Map pitch in degrees (default 0)
+    pub pitch: Option<f64>,
+}
+
+/// Marker overlay for static images
+#[derive(utoipa::ToSchema)]
+pub struct MarkerOverlay {
+    /// Marker position as [longitude, latitude]
+    #[schema(min_items = 2, max_items = 2)]
+    pub position: Vec<f64>,
+    /// Hex color (3, 4, 6 or 8 digits, default "#f00")
+    pub color: Option<String>,
+    /// Label text
+    #[schema(nullable)]
+    pub label: Option<String>,
+    /// Marker size in pixels (default 24)
+    pub size: Option<f32>,
+}
+
+/// Path overlay for static images
+#[derive(utoipa::ToSchema)]
+pub struct PathOverlay {
+    /// Path vertices as [longitude, latitude] pairs (at least 2)
+    pub points: Vec<Vec<f64>>,
+    /// Hex stroke color (default "#00f")
+    pub stroke_color: Option<String>,
+    /// Stroke width in pixels (default 3)
+    pub stroke_width: Option<f32>,
+    /// Hex fill color for closed polygons
+    #[schema(nullable)]
+    pub fill_color: Option<String>,
+}
+
+/// Readiness check result
+#[derive(utoipa::ToSchema)]
+#[schema(example = json!({
+    "status": "error",
+    "sources": [
+        {"id": "osm", "status": "ok", "optional": false},
+        {"id": "terrain", "status": "error", "optional": false, "error": "MBTiles file not found: /data/terrain.mbtiles"}
+    ]
+}))]
+pub struct ReadinessReport {
+    /// "ok" when every required source is healthy, otherwise "error"
+    pub status: String,
+    /// Per-source probe results
+    pub sources: Vec<SourceHealth>,
+}
+
+/// Probe result for a single source
+#[derive(utoipa::ToSchema)]
+pub struct SourceHealth {
+    /// Source ID
+    pub id: String,
+    /// "ok" or "error"
+    pub status: String,
+    /// Optional sources do not affect readiness
+    pub optional: bool,
+    /// Probe failure message
+    pub error: Option<String>,
+    /// Circuit breaker state: "closed", "open" or "half_open"; only for
+    /// sources with a `resilience` table
+    pub breaker: Option<String>,
+}
+
+/// Payload of a server-sent event, also named by the SSE `event` field
+#[derive(utoipa::ToSchema)]
+#[schema(example = json!({"type": "tile_invalidated", "source": "osm", "z": 14, "x": 8580, "y": 5737}))]
+pub struct ServerEvent {
+    /// "tile_invalidated", "source_reloaded", "style_reloaded" or "heartbeat"
+    #[schema(rename = "type")]
+    pub event_type: String,
+    /// Source ID (tile_invalidated, source_reloaded)
+    pub source: Option<String>,
+    /// Style ID (style_reloaded)
+    pub style: Option<String>,
+    /// Tile coordinates in XYZ (tile_invalidated)
+    pub z: Option<u8>,
+    pub x: Option<u32>,
+    pub y: Option<u32>,
+    /// Unix timestamp in seconds (heartbeat)
+    pub time: Option<u64>,
+}
+
+/// Differences between the same tile in two sources
+#[derive(utoipa::ToSchema)]
+#[schema(example = json!({
+    "identical": false,
+    "layers_only_in_a": [],
+    "layers_only_in_b": ["buildings"],
+    "layers": [{
+        "name": "places",
+        "added": 1,
+        "removed": 0,
+        "unchanged": 12,
+        "changed": [{"id": 42, "geometry_changed": false, "properties": {"name": {"a": "Zurich", "b": "Zürich"}}}]
+    }]
+}))]
+pub struct TileDiff {
+    /// Whether both tiles have the same layers and features
+    pub identical: bool,
+    /// Layers only in the first source
+    pub layers_only_in_a: Vec<String>,
+    /// Layers only in the second source
+    pub layers_only_in_b: Vec<String>,
+    /// Every layer of either tile, sorted by name
+    pub layers: Vec<LayerDiff>,
+}
+
+/// Time-limited direct access to a tile in a PMTiles archive on GCS
+#[derive(utoipa::ToSchema)]
+#[schema(example = json!({
+    "url": "https://storage.googleapis.com/tiles/planet.pmtiles?X-Goog-Algorithm=GOOG4-RSA-SHA256&X-Goog-Signature=...",
+    "expires_at": "2024-02-29T12:49:56Z",
+    "range": "bytes=1048576-1062143"
+}))]
+pub struct SignedTileUrl {
+    /// Signed URL of the archive
+    pub url: String,
+    /// When the URL stops working (RFC 3339, UTC)
+    pub expires_at: String,
+    /// `Range` header value selecting the tile within the archive
+    pub range: String,
+}
+
+/// Problems found when linting a style
+#[derive(utoipa::ToSchema)]
+#[schema(example = json!({
+    "style": "basic",
+    "valid": false,
+    "diagnostics": [
+        {"severity": "error", "layer": "roads", "message": "Source 'osm' is not defined in the style"},
+        {"severity": "warning", "layer": "places", "message": "Font 'Noto Sans Bold' not found in the fonts directory"}
+    ]
+}))]
+pub struct StyleValidation {
+    /// Style ID
+    pub style: String,
+    /// Whether the style has no errors; warnings may remain
+    pub valid: bool,
+    /// Problems found, in layer order
+    pub diagnostics: Vec<StyleDiagnostic>,
+}
+
+/// A problem found in a style
+#[derive(utoipa::ToSchema)]
+pub struct StyleDiagnostic {
+    /// `error` (the style or layer can't render) or `warning`
+    pub severity: String,
+    /// Layer at fault, if the problem is with a layer
+    #[schema(nullable)]
+    pub layer: Option<String>,
+    /// What is wrong
+    pub message: String,
+}
+
+/// Outcome of checking a source's data
+#[derive(utoipa::ToSchema)]
+#[schema(example = json!({
+    "source": "openmaptiles",
+    "backend": "mbtiles",
+    "valid": false,
+    "complete": true,
+    "issues": [
+        {"check": "integrity_check", "message": "*** in database main ***\nPage 42: btreeInitPage() returns error code 11"}
+    ],
+    "checks": [
+        {"check": "backend", "duration_ms": 1840},
+        {"check": "tiles", "duration_ms": 35}
+    ],
+    "sampled_tiles": 32,
+    "duration_ms": 1875
+}))]
+pub struct IntegrityReport {
+    /// Source ID
+    pub source: String,
+    /// `mbtiles`, `pmtiles`, `postgres`, ...
+    pub backend: String,
+    /// Whether every check ran and none found a problem
+    pub valid: bool,
+    /// Whether every check ran before `max_check_duration_secs` ran out
+    pub complete: bool,
+    /// Problems found
+    pub issues: Vec<IntegrityIssue>,
+    /// Checks that finished, in the order they ran
+    pub checks: Vec<CheckTiming>,
+    /// Stored tiles read and decoded
+    pub sampled_tiles: usize,
+    /// Time taken by the whole validation
+    pub duration_ms: u64,
+}
+
+/// A problem found while checking a source
+#[derive(utoipa::ToSchema)]
+pub struct IntegrityIssue {
+    /// `integrity_check`, `metadata`, `header`, `directory`, `spatial_index`,
+    /// `tiles`, `backend` or `timeout`
+    pub check: String,
+    /// What is wrong
+    pub message: String,
+}
+
+/// Time taken by one check
+#[derive(utoipa::ToSchema)]
+pub struct CheckTiming {
+    /// `backend` or `tiles`
+    pub check: String,
+    pub duration_ms: u64,
+}
+
+/// Differences within one layer
+#[derive(utoipa::ToSchema)]
+pub struct LayerDiff {
+    /// Layer name
+    pub name: String,
+    /// Features only in the second source
+    pub added: usize,
+    /// Features only in the first source
+    pub removed: usize,
+    /// Features identical in both
+    pub unchanged: usize,
+    /// Features in both with a different geometry or properties; each lists
+    /// its id (if any), `geometry_changed`, and changed properties as `{"a", "b"}`
+    pub changed: Vec<serde_json::Value>,
+}
+
 /// API error response
 #[derive(utoipa::ToSchema)]
-#[schema(example = json!({"error": "Source not found: invalid-source"}))]
+#[schema(example = json!({
+    "error": {
+        "code": "SOURCE_NOT_FOUND",
+        "message": "Source not found: invalid-source",
+        "details": {"source": "invalid-source"}
+    }
+}))]
 pub struct ApiError {
-    /// Error message
-    pub error: String,
+    pub error: ApiErrorBody,
+}
+
+/// Error code, message and details
+#[derive(utoipa::ToSchema)]
+pub struct ApiErrorBody {
+    /// Stable, machine-readable error code
+    pub code: ErrorCode,
+    /// Human-readable message; wording may change between releases
+    pub message: String,
+    /// What the error is about, e.g. the source id or tile coordinates
+    #[schema(value_type = Option<Object>)]
+    pub details: Option<serde_json::Value>,
 }
 
 // ============================================================
@@ -161,7 +519,7 @@ pub struct ApiError {
 
 /// Health check
 ///
-/// Returns OK if the server is running
+/// Returns OK if the server is running. Alias of `/health/live`.
 #[utoipa::path(
     get,
     path = "/health",
@@ -172,9 +530,39 @@ pub struct ApiError {
 )]
 pub async fn health_check() {}
 
+/// Liveness check
+///
+/// Returns OK while the process is up, regardless of source health
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    tag = "Health",
+    responses(
+        (status = 200, description = "Server is alive", body = String, example = "OK")
+    )
+)]
+pub async fn health_live() {}
+
+/// Readiness check
+///
+/// Probes every source (PMTiles header read, MBTiles `SELECT 1`, PostgreSQL
+/// pool checkout). Results are cached for 5 seconds.
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    tag = "Health",
+    responses(
+        (status = 200, description = "All required sources are healthy", body = ReadinessReport),
+        (status = 503, description = "A required source is failing", body = ReadinessReport)
+    )
+)]
+pub async fn health_ready() {}
+
 /// Get all sources and styles
 ///
 /// Returns a combined list of all data sources and styles as TileJSON.
+/// Data entries carry `tile_count` and `size_bytes` where the backend can
+/// tell cheaply; style entries carry a `thumbnail` static image URL.
 /// The optional `key` parameter is appended to all tile URLs in the response.
 #[utoipa::path(
     get,
@@ -198,7 +586,8 @@ pub async fn get_index() {}
     path = "/data.json",
     tag = "Data",
     params(
-        ("key" = Option<String>, Query, description = "API key to include in tile URLs")
+        ("key" = Option<String>, Query, description = "API key to include in tile URLs"),
+        ("tilejson" = Option<String>, Query, description = "TileJSON version of the response: 3.0.0 (default) or 2.2.0 for older clients")
     ),
     responses(
         (status = 200, description = "List of data sources", body = Vec<TileJSON>)
@@ -210,13 +599,17 @@ pub async fn list_data_sources() {}
 ///
 /// Returns TileJSON metadata for a specific tile source.
 /// The optional `key` parameter is appended to all tile URLs in the response.
+/// With `tilejson=2.2.0` the response follows TileJSON 2.2: it always includes
+/// `scheme` and omits `vector_layers`.
 #[utoipa::path(
     get,
     path = "/data/{source}",
     tag = "Data",
     params(
         ("source" = String, Path, description = "Source ID (with or without .json extension)"),
-        ("key" = Option<String>, Query, description = "API key to include in tile URLs")
+        ("key" = Option<String>, Query, description = "API key to include in tile URLs"),
+        ("tilejson" = Option<String>, Query, description = "TileJSON version of the response: 3.0.0 (default) or 2.2.0 for older clients"),
+        ("extended" = Option<bool>, Query, description = "Add the x-tile-count, x-storage-bytes and x-last-modified extension fields")
     ),
     responses(
         (status = 200, description = "TileJSON metadata", body = TileJSON),
@@ -225,6 +618,240 @@ pub async fn list_data_sources() {}
 )]
 pub async fn get_data_source() {}
 
+/// Inspect the tiles of a data source
+///
+/// Streams newline-delimited JSON with one `{"x_y": {...}}` object per stored tile
+/// at zoom `z`, reporting its size in bytes and, for vector tiles, its layers and
+/// feature counts. Limited to the source's bounds and to 10,000 tiles; the
+/// `X-Inspect-Truncated` header is set when tiles were left out.
+/// Supported for local PMTiles and MBTiles sources.
+#[utoipa::path(
+    get,
+    path = "/data/{source}/inspect",
+    tag = "Data",
+    params(
+        ("source" = String, Path, description = "Source ID"),
+        ("z" = u8, Query, description = "Zoom level to inspect"),
+        ("layer" = Option<String>, Query, description = "Comma-separated layer names to report")
+    ),
+    responses(
+        (status = 200, description = "Per-tile statistics", content_type = "application/x-ndjson"),
+        (status = 400, description = "Invalid zoom level or source cannot be inspected", body = ApiError),
+        (status = 404, description = "Source not found", body = ApiError)
+    )
+)]
+pub async fn inspect_data_source() {}
+
+/// Analyze the data quality of a source
+///
+/// Reads a random sample of the stored tiles at zoom `z` and reports tiles
+/// without features, tiles over 1 MB, polygon features with self-intersecting
+/// rings and features without geometry, along with a histogram of tile sizes.
+/// `quality_score` is the percentage of sampled tiles without problems.
+/// Supported for local PMTiles and MBTiles sources.
+#[utoipa::path(
+    get,
+    path = "/data/{source}/quality",
+    tag = "Data",
+    params(
+        ("source" = String, Path, description = "Source ID"),
+        ("z" = u8, Query, description = "Zoom level to sample"),
+        ("sample" = Option<usize>, Query, description = "Tiles to sample, 1-1000 (default 100)"),
+        ("seed" = Option<u64>, Query, description = "Seed of the random sample, to repeat an analysis")
+    ),
+    responses(
+        (status = 200, description = "Quality report", content_type = "application/json"),
+        (status = 400, description = "Invalid zoom level or sample size, or source cannot list its tiles", body = ApiError),
+        (status = 404, description = "Source not found", body = ApiError)
+    )
+)]
+pub async fn quality_data_source() {}
+
+/// Download tiles as MBTiles
+///
+/// Writes the source's tiles within the requested zoom levels and bounds to
+/// an MBTiles file and streams it as an attachment. Tiles are copied as
+/// stored. Requests covering more than `[export] max_tiles` tiles of the
+/// tile grid are rejected.
+#[utoipa::path(
+    get,
+    path = "/data/{source}/tiles.mbtiles",
+    tag = "Data",
+    params(
+        ("source" = String, Path, description = "Source ID"),
+        ("min_zoom" = Option<u8>, Query, description = "Lowest zoom level (default: the source's minzoom)"),
+        ("max_zoom" = Option<u8>, Query, description = "Highest zoom level (default: the source's maxzoom)"),
+        ("bounds" = Option<String>, Query, description = "west,south,east,north in degrees (default: the source's bounds)", example = "8.4,47.3,8.6,47.4")
+    ),
+    responses(
+        (status = 200, description = "MBTiles file", content_type = "application/vnd.sqlite3"),
+        (status = 400, description = "Invalid parameters or too many tiles", body = ApiError),
+        (status = 404, description = "Source not found", body = ApiError)
+    )
+)]
+pub async fn export_data_source() {}
+
+/// Validate one tile
+///
+/// Reports the tile's format, compression, stored and decompressed sizes,
+/// and for vector tiles its layers with their extent, feature counts,
+/// geometry types and the number of distinct values of each property key.
+/// Problems such as truncated geometry commands, unknown geometry types and
+/// out-of-range tags are listed in `errors`; corrupt tiles are reported rather
+/// than failing. `strict=true` also checks coordinates against the buffered
+/// extent and ring winding order (MVT 2.1). Only served with
+/// `debug_endpoints = true`.
+#[utoipa::path(
+    get,
+    path = "/data/{source}/{z}/{x}/{y}/inspect",
+    tag = "Data",
+    params(
+        ("source" = String, Path, description = "Source ID"),
+        ("z" = u8, Path, description = "Zoom level"),
+        ("x" = u32, Path, description = "Tile X coordinate"),
+        ("y" = u32, Path, description = "Tile Y coordinate"),
+        ("strict" = Option<bool>, Query, description = "Check coordinates against the buffered extent and winding order (default false)"),
+        ("buffer" = Option<u32>, Query, description = "Pixels of a 512-pixel tile allowed outside the tile in strict mode, 0-512 (default 128)")
+    ),
+    responses(
+        (status = 200, description = "Validation report", content_type = "application/json"),
+        (status = 400, description = "Invalid coordinates or buffer", body = ApiError),
+        (status = 404, description = "Source or tile not found", body = ApiError)
+    )
+)]
+pub async fn inspect_tile() {}
+
+/// Query one tile across sources
+///
+/// Reports, for each source with data at the tile, its stored size and for
+/// vector tiles every layer with its feature count and the first 5 features
+/// as GeoJSON: `{"sources": {"id": {"bytes": 1234, "layers": {"water":
+/// {"features": 42, "sample": [...]}}}}}`. Sources without a tile there are
+/// left out. Only served with `debug_endpoints = true`.
+#[utoipa::path(
+    get,
+    path = "/tiles/{z}/{x}/{y}.json",
+    tag = "Data",
+    params(
+        ("z" = u8, Path, description = "Zoom level"),
+        ("x" = u32, Path, description = "Tile X coordinate"),
+        ("y" = u32, Path, description = "Tile Y coordinate"),
+        ("source" = Option<String>, Query, description = "Comma-separated source IDs to limit the query to")
+    ),
+    responses(
+        (status = 200, description = "Contents of the tile by source", content_type = "application/json"),
+        (status = 400, description = "Invalid coordinates", body = ApiError),
+        (status = 404, description = "A requested source not found", body = ApiError)
+    )
+)]
+pub async fn query_tile() {}
+
+/// Check the integrity of a data source
+///
+/// Runs the backend's checks (SQLite `PRAGMA integrity_check` and required
+/// metadata for MBTiles; header and directory consistency for local PMTiles;
+/// spatial index use by the tile query for PostgreSQL tables), then reads and
+/// decodes a sample of stored tiles. Stops after `max_check_duration_secs`,
+/// with `complete: false`. `stream=true` writes a `{"type": "progress"}` line
+/// as each check starts, then the report with `"type": "report"`, as
+/// newline-delimited JSON. `request_timeout_ms` does not apply.
+#[utoipa::path(
+    post,
+    path = "/data/{source}/validate",
+    tag = "Data",
+    params(
+        ("source" = String, Path, description = "Source ID"),
+        ("stream" = Option<bool>, Query, description = "Stream progress events before the report as NDJSON (default false)")
+    ),
+    responses(
+        (status = 200, description = "Integrity report", body = IntegrityReport),
+        (status = 404, description = "Source not found", body = ApiError)
+    )
+)]
+pub async fn validate_data_source() {}
+
+/// Get a signed URL for a tile in Google Cloud Storage
+///
+/// For a PMTiles archive in a GCS bucket, returns a V4 signed URL of the
+/// archive and the byte range holding the tile. Fetch the tile with a
+/// `GET` of the URL and the `Range` header; it is stored with the archive's
+/// tile compression. Only available for sources with
+/// `options.allow_signed_urls = true`.
+#[utoipa::path(
+    get,
+    path = "/data/{source}/signed-url/{z}/{x}/{y}",
+    tag = "Data",
+    params(
+        ("source" = String, Path, description = "Source ID"),
+        ("z" = u8, Path, description = "Zoom level"),
+        ("x" = u32, Path, description = "Tile column"),
+        ("y" = u32, Path, description = "Tile row (XYZ)"),
+        ("ttl" = Option<u64>, Query, description = "Seconds the URL stays valid, up to 604800 (default 900)")
+    ),
+    responses(
+        (status = 200, description = "Signed URL and byte range", body = SignedTileUrl),
+        (status = 400, description = "Invalid coordinates or ttl", body = ApiError),
+        (status = 404, description = "Source or tile not found, or signed URLs not enabled for the source", body = ApiError)
+    )
+)]
+pub async fn get_signed_tile_url() {}
+
+/// Get an elevation tile as Terrain-RGB
+///
+/// Reads the first band of a COG or VRT elevation source and encodes it as
+/// a Mapbox Terrain-RGB PNG, for MapLibre `raster-dem` sources:
+/// `elevation = (R * 256 * 256 + G * 256 + B) * 0.1 - 10000`.
+/// No-data pixels are encoded as the source's `nodata_elevation` (default 0).
+/// Requires the `raster` feature.
+#[utoipa::path(
+    get,
+    path = "/data/{source}/terrain-rgb/{z}/{x}/{y}.png",
+    tag = "Data",
+    params(
+        ("source" = String, Path, description = "COG or VRT elevation source ID"),
+        ("z" = u8, Path, description = "Zoom level"),
+        ("x" = u32, Path, description = "Tile column"),
+        ("y" = u32, Path, description = "Tile row")
+    ),
+    responses(
+        (status = 200, description = "Terrain-RGB tile", content_type = "image/png"),
+        (status = 400, description = "Invalid coordinates or format, or not an elevation source", body = ApiError),
+        (status = 404, description = "Source not found", body = ApiError)
+    )
+)]
+pub async fn get_terrain_rgb_tile() {}
+
+/// Compare a vector tile in two sources
+///
+/// Fetches tile `z/x/y` from both sources and reports layers found in only one
+/// of them, and per layer the features added, removed, unchanged and changed.
+/// Features are matched by MVT id when both have one, otherwise by geometry.
+/// A tile missing from one source compares as an empty tile.
+///
+/// With `format=geojson`, returns every feature of both tiles with `_layer` and
+/// `_diff_action` ("added", "removed" or "unchanged") properties; changed
+/// features appear as removed from the first source and added in the second.
+#[utoipa::path(
+    get,
+    path = "/diff/{source_a}/{source_b}/{z}/{x}/{y}",
+    tag = "Data",
+    params(
+        ("source_a" = String, Path, description = "Source to compare from"),
+        ("source_b" = String, Path, description = "Source to compare to"),
+        ("z" = u8, Path, description = "Zoom level"),
+        ("x" = u32, Path, description = "Tile column"),
+        ("y" = u32, Path, description = "Tile row (XYZ)"),
+        ("format" = Option<String>, Query, description = "json (default) or geojson")
+    ),
+    responses(
+        (status = 200, description = "Differences between the two tiles", body = TileDiff),
+        (status = 200, description = "Annotated features (format=geojson)", content_type = "application/geo+json", body = GeoJSON),
+        (status = 400, description = "Invalid coordinates or format, or a source is not a vector source", body = ApiError),
+        (status = 404, description = "Source not found, or tile missing from both sources", body = ApiError)
+    )
+)]
+pub async fn get_tile_diff() {}
+
 /// Get a tile from any data source
 ///
 /// Returns a tile from the specified source. The response format depends on the source type:
@@ -236,6 +863,7 @@ pub async fn get_data_source() {}
 /// **Raster/COG sources** (Cloud Optimized GeoTIFF):
 /// - Formats: `png`, `jpg`, `jpeg`, `webp`
 /// - Query param `resampling`: nearest, bilinear, cubic, lanczos, average, etc.
+/// - `{y}@2x.png` (up to `@9x`) renders a 512px tile from a finer overview
 ///
 /// **PostgreSQL Out-of-Database raster sources** (VRT/COG via PostGIS):
 /// - Formats: `png`, `jpg`, `jpeg`, `webp`
@@ -251,7 +879,9 @@ pub async fn get_data_source() {}
         ("x" = u32, Path, description = "Tile X coordinate"),
         ("y" = u32, Path, description = "Tile Y coordinate"),
         ("format" = String, Path, description = "Tile format: pbf, mvt, geojson (vector) or png, jpg, webp (raster)"),
-        ("resampling" = Option<String>, Query, description = "Resampling method for COG sources: nearest, bilinear, cubic, cubicspline, lanczos, average, mode, max, min, med, q1, q3")
+        ("resampling" = Option<String>, Query, description = "Resampling method for COG sources: nearest, bilinear, cubic, cubicspline, lanczos, average or mode"),
+        ("scheme" = Option<String>, Query, description = "Row scheme of the requested y coordinate: xyz (default) or tms"),
+        ("buffer" = Option<u32>, Query, description = "Pixels (0-512, of a 512px tile) of features to include outside the tile for PostgreSQL table sources and overzoomed tiles; passed on to PostgreSQL functions. MapLibre's default is 128. Stored tiles are returned as they are")
     ),
     responses(
         (status = 200, description = "Vector tile data", content_type = "application/x-protobuf"),
@@ -262,6 +892,35 @@ pub async fn get_data_source() {}
 )]
 pub async fn get_tile() {}
 
+/// Get a tile in the format named by the `Accept` header
+///
+/// The extensionless form of the tile endpoint. Vector sources answer
+/// `application/vnd.mapbox-vector-tile` or `application/x-protobuf` with the
+/// tile and `application/geo+json` with its GeoJSON conversion; image media
+/// types, `image/*` included, match only the format of a raster source.
+/// `q` values and wildcards are honoured. Without a usable `Accept` header the
+/// source's own format is returned. Responses carry `Vary: Accept`.
+#[utoipa::path(
+    get,
+    path = "/data/{source}/{z}/{x}/{y}",
+    tag = "Data",
+    params(
+        ("source" = String, Path, description = "Source ID"),
+        ("z" = u8, Path, description = "Zoom level (0-22)"),
+        ("x" = u32, Path, description = "Tile X coordinate"),
+        ("y" = u32, Path, description = "Tile Y coordinate"),
+        ("Accept" = Option<String>, Header, description = "Media types the client accepts, e.g. `application/geo+json` or `image/*`")
+    ),
+    responses(
+        (status = 200, description = "Vector tile data", content_type = "application/x-protobuf"),
+        (status = 200, description = "GeoJSON tile data", body = GeoJSON, content_type = "application/geo+json"),
+        (status = 200, description = "Raster tile image", content_type = "image/png"),
+        (status = 404, description = "Tile or source not found"),
+        (status = 406, description = "The source has no format matching the Accept header", body = ApiError)
+    )
+)]
+pub async fn get_negotiated_tile() {}
+
 /// List all styles
 ///
 /// Returns metadata for all available map styles.
@@ -317,9 +976,53 @@ pub async fn get_style_tilejson() {}
 )]
 pub async fn get_style_json() {}
 
+/// Get a style thumbnail
+///
+/// Renders a small PNG of the style at the `center`, `zoom`, `bearing` and `pitch` of its
+/// style.json, cached for a week. Without a renderer, a gray placeholder showing the
+/// style's name is returned instead.
+#[utoipa::path(
+    get,
+    path = "/styles/{style}/thumbnail.png",
+    tag = "Styles",
+    params(
+        ("style" = String, Path, description = "Style ID"),
+        ("width" = Option<u32>, Query, description = "Width in pixels, up to [thumbnail] max_size (default 300)"),
+        ("height" = Option<u32>, Query, description = "Height in pixels, up to [thumbnail] max_size (default 200)")
+    ),
+    responses(
+        (status = 200, description = "Thumbnail image", content_type = "image/png"),
+        (status = 304, description = "Thumbnail unchanged since the `If-None-Match` ETag"),
+        (status = 400, description = "Width or height out of range", body = ApiError),
+        (status = 404, description = "Style not found", body = ApiError)
+    )
+)]
+pub async fn get_style_thumbnail() {}
+
+/// Validate a style
+///
+/// Returns the problems found when the style was loaded: a spec version other than 8,
+/// unknown layer types, layers referencing undefined sources or missing `source-layer`,
+/// and fonts or icons that can't be found. Styles with errors are refused at load
+/// time when `strict_styles` is enabled.
+#[utoipa::path(
+    get,
+    path = "/styles/{style}/validate",
+    tag = "Styles",
+    params(
+        ("style" = String, Path, description = "Style ID")
+    ),
+    responses(
+        (status = 200, description = "Problems found in the style", body = StyleValidation),
+        (status = 404, description = "Style not found", body = ApiError)
+    )
+)]
+pub async fn get_style_validation() {}
+
 /// Get a raster tile
 ///
 /// Returns a raster tile rendered from the style. Supports retina with @2x suffix.
+/// Tiles outside the style's zoom range and bounds are 404, or transparent with `blank_outside`.
 #[utoipa::path(
     get,
     path = "/styles/{style}/{z}/{x}/{y}.{format}",
@@ -329,36 +1032,76 @@ pub async fn get_style_json() {}
         ("z" = u8, Path, description = "Zoom level (0-22)"),
         ("x" = u32, Path, description = "Tile X coordinate"),
         ("y" = String, Path, description = "Tile Y coordinate (optionally with @2x for retina)", example = "123"),
-        ("format" = String, Path, description = "Image format (png, jpg, jpeg, webp)")
+        ("format" = String, Path, description = "Image format (png, jpg, jpeg, webp)"),
+        ("language" = Option<String>, Query, description = "Language to prefer for labels: name:{language} over name, e.g. de"),
+        ("debug" = Option<String>, Query, description = "Debug overlays, comma-separated: tile-borders, parse-status, timestamps, collision, overdraw. Requires debug_endpoints = true", example = "tile-borders,collision"),
+        ("bearing" = Option<f64>, Query, description = "Rotation in degrees clockwise from north, 0 to 360 (default 0)", example = 90),
+        ("pitch" = Option<f64>, Query, description = "Tilt in degrees, 0 to 60 (default 0)", example = 30)
     ),
     responses(
         (status = 200, description = "Raster tile image", content_type = "image/png"),
-        (status = 404, description = "Style not found", body = ApiError)
+        (status = 400, description = "Unknown debug overlay, or bearing or pitch out of range", body = ApiError),
+        (status = 403, description = "Debug overlays without debug_endpoints = true", body = ApiError),
+        (status = 404, description = "Style not found, or tile outside the style's zoom range or bounds", body = ApiError)
     )
 )]
 pub async fn get_raster_tile() {}
 
 /// Get a raster tile with custom size
 ///
-/// Returns a raster tile with specified tile size (256 or 512 pixels)
+/// Returns a raster tile with specified tile size in pixels, times the
+/// `@{scale}x` of the Y coordinate
 #[utoipa::path(
     get,
     path = "/styles/{style}/{tileSize}/{z}/{x}/{y}.{format}",
     tag = "Styles",
     params(
         ("style" = String, Path, description = "Style ID"),
-        ("tileSize" = u16, Path, description = "Tile size in pixels (256 or 512)"),
+        ("tileSize" = u16, Path, description = "Tile size in pixels, one of [render] allowed_tile_sizes (default 256 or 512)"),
         ("z" = u8, Path, description = "Zoom level"),
         ("x" = u32, Path, description = "Tile X coordinate"),
-        ("y" = String, Path, description = "Tile Y coordinate"),
-        ("format" = String, Path, description = "Image format (png, jpg, jpeg, webp)")
+        ("y" = String, Path, description = "Tile Y coordinate (optionally with @2x for retina)"),
+        ("format" = String, Path, description = "Image format (png, jpg, jpeg, webp)"),
+        ("language" = Option<String>, Query, description = "Language to prefer for labels: name:{language} over name, e.g. de"),
+        ("debug" = Option<String>, Query, description = "Debug overlays, comma-separated: tile-borders, parse-status, timestamps, collision, overdraw. Requires debug_endpoints = true", example = "tile-borders,collision"),
+        ("bearing" = Option<f64>, Query, description = "Rotation in degrees clockwise from north, 0 to 360 (default 0)", example = 90),
+        ("pitch" = Option<f64>, Query, description = "Tilt in degrees, 0 to 60 (default 0)", example = 30)
     ),
     responses(
-        (status = 200, description = "Raster tile image", content_type = "image/png")
+        (status = 200, description = "Raster tile image", content_type = "image/png"),
+        (status = 400, description = "Tile size not in allowed_tile_sizes, unknown debug overlay, or bearing or pitch out of range", body = ApiError),
+        (status = 403, description = "Debug overlays without debug_endpoints = true", body = ApiError),
+        (status = 404, description = "Style not found, or tile outside the style's zoom range or bounds", body = ApiError)
     )
 )]
 pub async fn get_raster_tile_with_size() {}
 
+/// Query the features at a pixel of a raster tile
+///
+/// Returns the vector features drawn within `radius` pixels of the pixel,
+/// topmost first, like WMS GetFeatureInfo
+#[utoipa::path(
+    get,
+    path = "/styles/{style}/{z}/{x}/{y}/query",
+    tag = "Styles",
+    params(
+        ("style" = String, Path, description = "Style ID"),
+        ("z" = u8, Path, description = "Zoom level"),
+        ("x" = u32, Path, description = "Tile X coordinate"),
+        ("y" = u32, Path, description = "Tile Y coordinate"),
+        ("px" = f64, Query, description = "Pixel column, from 0 to tile_size", example = 264),
+        ("py" = f64, Query, description = "Pixel row, from 0 to tile_size", example = 174),
+        ("radius" = Option<f64>, Query, description = "Search radius in pixels (default 4, max 64)"),
+        ("tile_size" = Option<u32>, Query, description = "Size of the queried tile in pixels: 256 or 512 (default 512)")
+    ),
+    responses(
+        (status = 200, description = "GeoJSON FeatureCollection in longitude and latitude", content_type = "application/geo+json"),
+        (status = 400, description = "Pixel outside the tile, invalid tile size or coordinates", body = ApiError),
+        (status = 404, description = "Style not found", body = ApiError)
+    )
+)]
+pub async fn query_raster_tile() {}
+
 /// Get a static map image
 ///
 /// Renders a static map image centered at the specified location
@@ -369,38 +1112,117 @@ pub async fn get_raster_tile_with_size() {}
     params(
         ("style" = String, Path, description = "Style ID"),
         ("center" = String, Path, description = "Center point as lon,lat,zoom or 'auto'", example = "-122.4194,37.7749,12"),
-        ("size" = String, Path, description = "Image size as WIDTHxHEIGHT, optionally with @2x", example = "800x600"),
+        ("size" = String, Path, description = "Image size as WIDTHxHEIGHT, optionally with a pixel ratio from @0.5x to @8x, e.g. @2x or @3.5x", example = "800x600"),
         ("format" = String, Path, description = "Image format (png, jpg, jpeg, webp)"),
+        ("pixelRatio" = Option<f32>, Query, description = "Pixel ratio from 0.5 to 8, in place of the @{scale}x of the size", example = 3.5),
         ("bearing" = Option<f64>, Query, description = "Map bearing in degrees"),
         ("pitch" = Option<f64>, Query, description = "Map pitch in degrees"),
         ("markers" = Option<String>, Query, description = "Markers to add (format: pin-s+color(lon,lat))"),
-        ("path" = Option<String>, Query, description = "Path to draw (format: path-width+color(lon,lat|lon,lat))")
+        ("path" = Option<String>, Query, description = "Path to draw (format: path-width+color(lon,lat|lon,lat))"),
+        ("language" = Option<String>, Query, description = "Language to prefer for labels: name:{language} over name, e.g. de"),
+        ("debug" = Option<String>, Query, description = "Debug overlays, comma-separated: tile-borders, parse-status, timestamps, collision, overdraw. Requires debug_endpoints = true", example = "tile-borders,collision")
     ),
     responses(
-        (status = 200, description = "Static map image", content_type = "image/png")
+        (status = 200, description = "Static map image", content_type = "image/png"),
+        (status = 400, description = "Invalid size or pixel ratio, image larger than [render] max_image_pixels, or unknown debug overlay", body = ApiError),
+        (status = 403, description = "Debug overlays without debug_endpoints = true", body = ApiError)
     )
 )]
 pub async fn get_static_image() {}
 
+/// Render a static map image from a JSON body
+///
+/// Accepts the same options as the static image endpoint with structured
+/// marker and path overlays
+#[utoipa::path(
+    post,
+    path = "/styles/{style}/render",
+    tag = "Styles",
+    params(
+        ("style" = String, Path, description = "Style ID")
+    ),
+    request_body(content = StaticRenderRequest, content_type = "application/json"),
+    responses(
+        (status = 200, description = "Static map image", content_type = "image/png"),
+        (status = 400, description = "Malformed JSON body"),
+        (status = 404, description = "Style not found", body = ApiError),
+        (status = 413, description = "Body over max_request_body_bytes", body = ApiError),
+        (status = 422, description = "Missing or invalid fields")
+    )
+)]
+pub async fn post_static_render() {}
+
+/// Get a static map image for a Mapbox Static Images API URL
+///
+/// Only served with `[compat] mapbox_static = true`. The overlay segment is
+/// optional. Images are rendered as PNG.
+#[utoipa::path(
+    get,
+    path = "/styles/v1/{username}/{style_id}/static/{overlay}/{position}/{size}",
+    tag = "Styles",
+    params(
+        ("username" = String, Path, description = "Mapbox username, ignored unless `mapbox_username` is configured"),
+        ("style_id" = String, Path, description = "Style ID"),
+        ("overlay" = String, Path, description = "Comma-separated markers, paths and geojson() overlays", example = "pin-s-a+9ed4bd(-122.46589,37.77343)"),
+        ("position" = String, Path, description = "lon,lat,zoom[,bearing[,pitch]], [west,south,east,north] or 'auto'", example = "-122.4241,37.78,14.25,0,60"),
+        ("size" = String, Path, description = "Image size as WIDTHxHEIGHT, optionally with @2x", example = "600x400@2x"),
+        ("padding" = Option<String>, Query, description = "Pixels kept clear around overlays of 'auto' images")
+    ),
+    responses(
+        (status = 200, description = "Static map image", content_type = "image/png"),
+        (status = 400, description = "Invalid position, size or overlay", body = ApiError),
+        (status = 404, description = "Style not found, or username not accepted", body = ApiError)
+    )
+)]
+pub async fn get_mapbox_static_image() {}
+
 /// Get sprite image or JSON
 ///
-/// Returns sprite image (PNG) or metadata (JSON) for the style
+/// Returns sprite image (PNG or lossless WebP) or metadata (JSON) for the
+/// style. Scales up to @4x that aren't stored are scaled from the nearest
+/// stored one.
 #[utoipa::path(
     get,
     path = "/styles/{style}/sprite.{ext}",
     tag = "Styles",
     params(
         ("style" = String, Path, description = "Style ID"),
-        ("ext" = String, Path, description = "File extension (png or json, optionally with @2x)", example = "png")
+        ("ext" = String, Path, description = "File extension (png, webp or json, optionally with @2x, @3x or @4x)", example = "png")
     ),
     responses(
         (status = 200, description = "Sprite image", content_type = "image/png"),
+        (status = 200, description = "Sprite image, lossless", content_type = "image/webp"),
         (status = 200, description = "Sprite metadata", content_type = "application/json"),
+        (status = 400, description = "Invalid sprite file name", body = ApiError),
         (status = 404, description = "Sprite not found", body = ApiError)
     )
 )]
 pub async fn get_sprite() {}
 
+/// Get one sprite of a style with several sprites
+///
+/// For styles whose `sprite` is an array of `{id, url}`, serves the sprite
+/// with the given id and the URL `/styles/{style}/{spriteId}/sprite`. Missing
+/// scales are synthesized as for single sprites.
+#[utoipa::path(
+    get,
+    path = "/styles/{style}/{spriteId}/sprite.{ext}",
+    tag = "Styles",
+    params(
+        ("style" = String, Path, description = "Style ID"),
+        ("spriteId" = String, Path, description = "ID of the sprite in the style's sprite array"),
+        ("ext" = String, Path, description = "File extension (png, webp or json, optionally with @2x, @3x or @4x)", example = "json")
+    ),
+    responses(
+        (status = 200, description = "Sprite image", content_type = "image/png"),
+        (status = 200, description = "Sprite image, lossless", content_type = "image/webp"),
+        (status = 200, description = "Sprite metadata", content_type = "application/json"),
+        (status = 400, description = "Invalid sprite file name", body = ApiError),
+        (status = 404, description = "Style or sprite not found", body = ApiError)
+    )
+)]
+pub async fn get_sprite_with_id() {}
+
 /// Get WMTS capabilities
 ///
 /// Returns OGC WMTS GetCapabilities document for the style
@@ -418,9 +1240,44 @@ pub async fn get_sprite() {}
 )]
 pub async fn get_wmts_capabilities() {}
 
+/// WMTS KVP endpoint
+///
+/// Handles OGC WMTS key-value-pair GetCapabilities, GetTile and GetFeatureInfo
+/// requests for all styles. Errors are returned as `ows:ExceptionReport` XML.
+#[utoipa::path(
+    get,
+    path = "/wmts",
+    tag = "Styles",
+    params(
+        ("SERVICE" = String, Query, description = "Must be WMTS"),
+        ("REQUEST" = String, Query, description = "GetCapabilities, GetTile or GetFeatureInfo"),
+        ("LAYER" = Option<String>, Query, description = "Layer identifier ({style}-256 or {style}-512)", example = "osm-bright-256"),
+        ("TILEMATRIXSET" = Option<String>, Query, description = "GoogleMapsCompatible_256 or GoogleMapsCompatible_512"),
+        ("TILEMATRIX" = Option<u8>, Query, description = "Zoom level"),
+        ("TILEROW" = Option<u32>, Query, description = "Tile row"),
+        ("TILECOL" = Option<u32>, Query, description = "Tile column"),
+        ("FORMAT" = Option<String>, Query, description = "Image format (image/png, image/jpeg, image/webp)"),
+        ("I" = Option<u32>, Query, description = "Pixel column for GetFeatureInfo"),
+        ("J" = Option<u32>, Query, description = "Pixel row for GetFeatureInfo"),
+        ("INFOFORMAT" = Option<String>, Query, description = "GetFeatureInfo format (application/json)"),
+        ("LANGUAGE" = Option<String>, Query, description = "Language to prefer for GetTile labels: name:{language} over name, e.g. de"),
+        ("key" = Option<String>, Query, description = "API key to include in capabilities URLs")
+    ),
+    responses(
+        (status = 200, description = "WMTS capabilities XML", content_type = "application/xml"),
+        (status = 200, description = "Rendered tile", content_type = "image/png"),
+        (status = 304, description = "Tile unchanged since the `If-None-Match` ETag"),
+        (status = 200, description = "Features under the pixel", content_type = "application/json", body = GeoJSON),
+        (status = 400, description = "OGC exception report", content_type = "application/xml"),
+        (status = 501, description = "Unsupported operation", content_type = "application/xml")
+    )
+)]
+pub async fn get_wmts_kvp() {}
+
 /// List available fonts
 ///
-/// Returns a list of available font families
+/// Returns a list of available fonts: pre-built PBF glyph directories and
+/// fonts found as TTF/OTF files, named "Family Style"
 #[utoipa::path(
     get,
     path = "/fonts.json",
@@ -434,14 +1291,18 @@ pub async fn list_fonts() {}
 
 /// Get font glyphs
 ///
-/// Returns PBF-encoded font glyphs for a character range
+/// Returns PBF-encoded font glyphs for a character range.
+/// Glyphs of a multi-font stack are merged, each taken from the first font that has it.
+/// With `[font_languages]` configured, the font of the preferred `Accept-Language`
+/// is put first in the stack.
 #[utoipa::path(
     get,
     path = "/fonts/{fontstack}/{range}",
     tag = "Fonts",
     params(
         ("fontstack" = String, Path, description = "Font stack (comma-separated font names)", example = "Noto Sans Regular"),
-        ("range" = String, Path, description = "Character range (e.g., 0-255.pbf)", example = "0-255.pbf")
+        ("range" = String, Path, description = "Character range (e.g., 0-255.pbf)", example = "0-255.pbf"),
+        ("Accept-Language" = Option<String>, Header, description = "Preferred languages, e.g. `ja-JP,en;q=0.8`, choosing a font from `[font_languages]`")
     ),
     responses(
         (status = 200, description = "Font glyph data", content_type = "application/x-protobuf"),
@@ -450,23 +1311,167 @@ pub async fn list_fonts() {}
 )]
 pub async fn get_font_glyphs() {}
 
+/// Get the glyphs a style uses
+///
+/// Returns every glyph range the labels of the posted style can use, combined
+/// into one PBF with an entry per fontstack and range. Literal label text
+/// needs only the ranges of its characters; text read from feature properties
+/// (`["get", "name"]` or `{name}`) gets all ranges of its fontstack.
+#[utoipa::path(
+    post,
+    path = "/fonts/subset",
+    tag = "Fonts",
+    request_body(content = Object, description = "MapLibre style JSON", content_type = "application/json"),
+    responses(
+        (status = 200, description = "Combined glyph data", content_type = "application/x-protobuf"),
+        (status = 400, description = "Style has no layers array", body = ApiError),
+        (status = 404, description = "Fonts directory not configured", body = ApiError),
+        (status = 413, description = "Body over max_request_body_bytes", body = ApiError)
+    )
+)]
+pub async fn post_font_subset() {}
+
 /// Get static file
 ///
-/// Serves static files from the configured files directory
+/// Serves static files from the configured files directory.
+/// Files are streamed, and a single byte range can be requested with the `Range` header.
 #[utoipa::path(
     get,
     path = "/files/{filepath}",
     tag = "Files",
     params(
-        ("filepath" = String, Path, description = "Path to the file")
+        ("filepath" = String, Path, description = "Path to the file"),
+        ("Range" = Option<String>, Header, description = "Single byte range, e.g. `bytes=0-1023`")
     ),
     responses(
         (status = 200, description = "File content"),
-        (status = 404, description = "File not found", body = ApiError)
+        (status = 206, description = "Requested byte range of the file"),
+        (status = 404, description = "File not found", body = ApiError),
+        (status = 416, description = "Range lies beyond the end of the file")
     )
 )]
 pub async fn get_static_file() {}
 
+/// Subscribe to server events
+///
+/// A `text/event-stream` of `tile_invalidated`, `source_reloaded` and
+/// `style_reloaded` events, each carrying a JSON `ServerEvent`. Reloads come from
+/// the admin API or `--watch`; tiles are invalidated through the admin API.
+/// A `heartbeat` event is sent every 30 seconds.
+#[utoipa::path(
+    get,
+    path = "/events",
+    tag = "Events",
+    responses(
+        (status = 200, description = "Event stream", content_type = "text/event-stream", body = ServerEvent)
+    )
+)]
+pub async fn subscribe_events() {}
+
+/// OGC API landing page
+///
+/// Links to the API definition, conformance declaration and tilesets.
+/// Served only when the web UI is disabled.
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "OGC API",
+    responses(
+        (status = 200, description = "Landing page", content_type = "application/json")
+    )
+)]
+pub async fn get_ogc_landing_page() {}
+
+/// OGC API conformance declaration
+#[utoipa::path(
+    get,
+    path = "/conformance",
+    tag = "OGC API",
+    responses(
+        (status = 200, description = "Implemented conformance classes", content_type = "application/json")
+    )
+)]
+pub async fn get_ogc_conformance() {}
+
+/// List OGC API tilesets
+///
+/// Returns one tileset per data source and per style
+#[utoipa::path(
+    get,
+    path = "/tiles",
+    tag = "OGC API",
+    responses(
+        (status = 200, description = "Tileset list", content_type = "application/json")
+    )
+)]
+pub async fn get_ogc_tilesets() {}
+
+/// Get OGC API tileset metadata
+///
+/// Returns the tile matrix set link, bounds and tile matrix limits of a tileset
+#[utoipa::path(
+    get,
+    path = "/tiles/{tilesetId}",
+    tag = "OGC API",
+    params(
+        ("tilesetId" = String, Path, description = "Source or style ID", example = "openmaptiles")
+    ),
+    responses(
+        (status = 200, description = "Tileset metadata", content_type = "application/json"),
+        (status = 404, description = "Tileset not found", body = ApiError)
+    )
+)]
+pub async fn get_ogc_tileset() {}
+
+/// Get an OGC API tile
+///
+/// Serves source tiles as stored and renders style tiles as 256px PNGs
+#[utoipa::path(
+    get,
+    path = "/tiles/{tilesetId}/{tileMatrix}/{tileRow}/{tileCol}",
+    tag = "OGC API",
+    params(
+        ("tilesetId" = String, Path, description = "Source or style ID"),
+        ("tileMatrix" = u8, Path, description = "WebMercatorQuad tile matrix (zoom level)"),
+        ("tileRow" = u32, Path, description = "Tile row, counted from the top"),
+        ("tileCol" = u32, Path, description = "Tile column"),
+        ("language" = Option<String>, Query, description = "Language to prefer for labels of style tiles: name:{language} over name, e.g. de")
+    ),
+    responses(
+        (status = 200, description = "Vector tile", content_type = "application/vnd.mapbox-vector-tile"),
+        (status = 200, description = "Raster tile", content_type = "image/png"),
+        (status = 304, description = "Style tile unchanged since the `If-None-Match` ETag"),
+        (status = 404, description = "Tileset not found or tile outside limits", body = ApiError)
+    )
+)]
+pub async fn get_ogc_tile() {}
+
+/// List tile matrix sets
+#[utoipa::path(
+    get,
+    path = "/tileMatrixSets",
+    tag = "OGC API",
+    responses(
+        (status = 200, description = "Tile matrix set list", content_type = "application/json")
+    )
+)]
+pub async fn get_ogc_tile_matrix_sets() {}
+
+/// Get a tile matrix set definition
+#[utoipa::path(
+    get,
+    path = "/tileMatrixSets/{tileMatrixSetId}",
+    tag = "OGC API",
+    params(
+        ("tileMatrixSetId" = String, Path, description = "Tile matrix set ID", example = "WebMercatorQuad")
+    ),
+    responses(
+        (status = 200, description = "OGC Two Dimensional Tile Matrix Set (JSON)", content_type = "application/json"),
+        (status = 404, description = "Tile matrix set not found", body = ApiError)
+    )
+)]
+pub async fn get_ogc_tile_matrix_set() {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -496,21 +1501,43 @@ mod tests {
         // All expected endpoints
         let expected_paths = [
             "/health",
+            "/health/live",
+            "/health/ready",
             "/index.json",
             "/data.json",
             "/data/{source}",
             "/data/{source}/{z}/{x}/{y}.{format}",
+            "/data/{source}/{z}/{x}/{y}",
+            "/data/{source}/{z}/{x}/{y}/inspect",
+            "/data/{source}/validate",
+            "/data/{source}/quality",
+            "/data/{source}/terrain-rgb/{z}/{x}/{y}.png",
             "/styles.json",
             "/styles/{style}.json",
             "/styles/{style}/style.json",
+            "/styles/{style}/thumbnail.png",
             "/styles/{style}/{z}/{x}/{y}.{format}",
             "/styles/{style}/{tileSize}/{z}/{x}/{y}.{format}",
+            "/styles/{style}/{z}/{x}/{y}/query",
             "/styles/{style}/static/{center}/{size}.{format}",
+            "/styles/{style}/render",
+            "/styles/v1/{username}/{style_id}/static/{overlay}/{position}/{size}",
             "/styles/{style}/sprite.{ext}",
+            "/styles/{style}/{spriteId}/sprite.{ext}",
             "/styles/{style}/wmts.xml",
+            "/styles/{style}/validate",
+            "/wmts",
             "/fonts.json",
             "/fonts/{fontstack}/{range}",
+            "/fonts/subset",
             "/files/{filepath}",
+            "/events",
+            "/diff/{source_a}/{source_b}/{z}/{x}/{y}",
+            "/conformance",
+            "/tiles",
+            "/tiles/{tilesetId}",
+            "/tiles/{tilesetId}/{tileMatrix}/{tileRow}/{tileCol}",
+            "/tileMatrixSets/{tileMatrixSetId}",
         ];
 
         for path in expected_paths {
@@ -528,8 +1555,8 @@ mod tests {
         assert!(spec.tags.is_some(), "Tags should be defined");
         assert_eq!(
             spec.tags.as_ref().unwrap().len(),
-            5,
-            "Should have 5 tags defined"
+            7,
+            "Should have 7 tags defined"
         );
     }
 
@@ -542,6 +1569,11 @@ mod tests {
         assert!(schemas.contains_key("StyleInfo"));
         assert!(schemas.contains_key("VectorLayer"));
         assert!(schemas.contains_key("GeoJSON"));
+        assert!(schemas.contains_key("StaticRenderRequest"));
+        assert!(schemas.contains_key("MarkerOverlay"));
+        assert!(schemas.contains_key("PathOverlay"));
         assert!(schemas.contains_key("ApiError"));
+        assert!(schemas.contains_key("ApiErrorBody"));
+        assert!(schemas.contains_key("ErrorCode"));
     }
 }
@@ -0,0 +1,237 @@
+//! Built-in preview pages for `GET /data/{source}/` and `GET /styles/{style}/`
+//!
+//! Small server-rendered HTML pages with a MapLibre GL JS map loaded from a
+//! CDN, for checking a source or style without the web UI. The source page
+//! draws every vector layer in its own color and shows the properties of the
+//! features under a click; raster sources are drawn as they are. Served with
+//! `preview_pages = true` in `[server]`, whether or not the UI is enabled.
+
+use axum::{
+    extract::{Path, Query, State},
+    response::Html,
+    routing::get,
+    Router,
+};
+
+use crate::error::TileServerError;
+use crate::sources::{SharedSources, TileFormat, TileMetadata};
+use crate::styles::{SharedStyles, Style};
+
+/// MapLibre GL JS release the pages load
+const MAPLIBRE_URL: &str = "https://unpkg.com/maplibre-gl@4/dist";
+
+/// What the preview handlers read from
+#[derive(Clone)]
+pub struct PreviewState {
+    pub sources: SharedSources,
+    pub styles: SharedStyles,
+    pub base_url: String,
+}
+
+#[derive(Debug, serde::Deserialize, Default)]
+struct PreviewQuery {
+    /// API key to forward to the TileJSON or style URL
+    key: Option<String>,
+}
+
+/// Router serving `GET /data/{source}/`
+pub fn data_router<S>(state: PreviewState) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .route("/data/{source}/", get(get_data_preview))
+        .with_state(state)
+}
+
+/// Router serving `GET /styles/{style}/`
+pub fn styles_router<S>(state: PreviewState) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .route("/styles/{style}/", get(get_style_preview))
+        .with_state(state)
+}
+
+/// URL of the preview page of source `id`
+pub fn data_url(base_url: &str, id: &str) -> String {
+    format!("{}/data/{}/", base_url, id)
+}
+
+/// URL of the preview page of style `id`
+pub fn style_url(base_url: &str, id: &str) -> String {
+    format!("{}/styles/{}/", base_url, id)
+}
+
+async fn get_data_preview(
+    State(state): State<PreviewState>,
+    Path(source_id): Path<String>,
+    Query(query): Query<PreviewQuery>,
+) -> Result<Html<String>, TileServerError> {
+    let sources = state.sources.load();
+    let metadata = sources
+        .metadata(&source_id)
+        .ok_or_else(|| TileServerError::SourceNotFound(source_id.clone()))?;
+    Ok(Html(data_page(
+        metadata,
+        &state.base_url,
+        query.key.as_deref(),
+    )))
+}
+
+async fn get_style_preview(
+    State(state): State<PreviewState>,
+    Path(style_id): Path<String>,
+    Query(query): Query<PreviewQuery>,
+) -> Result<Html<String>, TileServerError> {
+    let styles = state.styles.load();
+    let style = styles
+        .get(&style_id)
+        .ok_or_else(|| TileServerError::StyleNotFound(style_id.clone()))?;
+    Ok(Html(style_page(
+        style,
+        &state.base_url,
+        query.key.as_deref(),
+    )))
+}
+
+/// Preview page of a data source, loading its TileJSON
+pub fn data_page(metadata: &TileMetadata, base_url: &str, key: Option<&str>) -> String {
+    let tilejson_url = format!("{}/data/{}.json{}", base_url, metadata.id, key_query(key));
+    let vector = metadata.format == TileFormat::Pbf;
+    let script = format!(
+        r##"const tilejsonUrl = {tilejson_url};
+const vector = {vector};
+const map = new maplibregl.Map({{
+  container: "map",
+  hash: true,
+  style: {{
+    version: 8,
+    sources: {{}},
+    layers: [{{ id: "background", type: "background", paint: {{ "background-color": "#f8f8f8" }} }}]
+  }}
+}});
+map.addControl(new maplibregl.NavigationControl());
+map.on("load", async () => {{
+  const tilejson = await (await fetch(tilejsonUrl)).json();
+  map.addSource("preview", {{ type: vector ? "vector" : "raster", url: tilejsonUrl }});
+  if (tilejson.bounds && !location.hash) {{
+    const [w, s, e, n] = tilejson.bounds;
+    map.fitBounds([[w, s], [e, n]], {{ animate: false }});
+  }}
+  if (!vector) {{
+    map.addLayer({{ id: "preview", type: "raster", source: "preview" }});
+    return;
+  }}
+  (tilejson.vector_layers || []).forEach((layer, i) => {{
+    const color = `hsl(${{(i * 137) % 360}}, 70%, 45%)`;
+    const common = {{ source: "preview", "source-layer": layer.id }};
+    map.addLayer({{ ...common, id: `${{layer.id}}-fill`, type: "fill",
+      filter: ["==", ["geometry-type"], "Polygon"],
+      paint: {{ "fill-color": color, "fill-opacity": 0.2 }} }});
+    map.addLayer({{ ...common, id: `${{layer.id}}-line`, type: "line",
+      filter: ["!=", ["geometry-type"], "Point"],
+      paint: {{ "line-color": color, "line-width": 1 }} }});
+    map.addLayer({{ ...common, id: `${{layer.id}}-circle`, type: "circle",
+      filter: ["==", ["geometry-type"], "Point"],
+      paint: {{ "circle-color": color, "circle-radius": 3 }} }});
+  }});
+  map.on("click", (e) => {{
+    const features = map.queryRenderedFeatures(e.point)
+      .filter((f) => f.source === "preview")
+      .map((f) => ({{ layer: f.sourceLayer, properties: f.properties }}));
+    if (!features.length) return;
+    const pre = document.createElement("pre");
+    pre.textContent = JSON.stringify(features, null, 2);
+    new maplibregl.Popup({{ maxWidth: "400px" }}).setLngLat(e.lngLat).setDOMContent(pre).addTo(map);
+  }});
+}});"##,
+        tilejson_url = js_string(&tilejson_url),
+        vector = vector,
+    );
+    page(&metadata.name, &script)
+}
+
+/// Preview page of a style, loading its `style.json`
+pub fn style_page(style: &Style, base_url: &str, key: Option<&str>) -> String {
+    let style_url = format!(
+        "{}/styles/{}/style.json{}",
+        base_url,
+        style.id,
+        key_query(key)
+    );
+    let script = format!(
+        r#"const map = new maplibregl.Map({{ container: "map", hash: true, style: {style_url} }});
+map.addControl(new maplibregl.NavigationControl());"#,
+        style_url = js_string(&style_url),
+    );
+    page(&style.name, &script)
+}
+
+/// `?key=...` for a URL, or nothing without a key
+fn key_query(key: Option<&str>) -> String {
+    key.map(|k| format!("?key={}", urlencoding::encode(k)))
+        .unwrap_or_default()
+}
+
+/// HTML page around a full-window map set up by `script`
+fn page(title: &str, script: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title} - tileserver-rs</title>
+<link rel="stylesheet" href="{maplibre}/maplibre-gl.css">
+<script src="{maplibre}/maplibre-gl.js"></script>
+<style>
+html, body, #map {{ margin: 0; height: 100%; }}
+.maplibregl-popup-content pre {{ margin: 0; max-height: 300px; overflow: auto; font-size: 11px; }}
+</style>
+</head>
+<body>
+<div id="map"></div>
+<script>
+{script}
+</script>
+</body>
+</html>
+"#,
+        title = html_escape(title),
+        maplibre = MAPLIBRE_URL,
+        script = script,
+    )
+}
+
+/// JavaScript string literal that is also safe inside a `<script>` element
+fn js_string(value: &str) -> String {
+    serde_json::to_string(value)
+        .unwrap_or_default()
+        .replace('<', "\\u003c")
+}
+
+fn html_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_js_string_cannot_close_script() {
+        assert_eq!(js_string("a</script>"), r#""a\u003c/script>""#);
+    }
+
+    #[test]
+    fn test_page_escapes_title() {
+        let html = page("<b>&", "");
+        assert!(html.contains("<title>&lt;b&gt;&amp; - tileserver-rs</title>"));
+    }
+}
@@ -0,0 +1,568 @@
+//! Data quality analysis for `GET /data/{source}/quality`
+//!
+//! Reads a random sample of the stored tiles at one zoom level and looks for
+//! signs of bad data: tiles without features, tiles over 1 MB, polygons whose
+//! rings cross themselves and features without geometry. The share of
+//! sampled tiles free of these problems is the `quality_score`.
+//!
+//! Candidates are the tiles [`inspect::list`] finds, so at most
+//! [`inspect::MAX_TILES`] of them. The sample is read in Hilbert tile ID
+//! order, the order PMTiles archives cluster their tile data and directories
+//! in, so reads move forward through the archive instead of jumping around.
+
+use geozero::mvt::tile::{GeomType, Layer};
+use geozero::mvt::Message;
+use pmtiles::{TileCoord, TileId};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{Result, TileServerError};
+use crate::inspect;
+use crate::sources::overzoom::{decode_geometry, Point};
+use crate::sources::{TileData, TileFormat, TileSource};
+
+/// Tiles sampled when `sample` is not given
+pub const DEFAULT_SAMPLE: usize = 100;
+
+/// Most tiles sampled in one request
+pub const MAX_SAMPLE: usize = 1_000;
+
+/// Stored size above which a tile is reported as oversized
+pub const OVERSIZED_BYTES: usize = 1024 * 1024;
+
+/// Most issues listed in a report; `issue_count` has the total
+pub const MAX_ISSUES: usize = 100;
+
+/// Rings with more points are not checked for self-intersections, as the
+/// check compares every pair of segments
+const MAX_RING_POINTS: usize = 2_048;
+
+/// Upper bounds of the size histogram buckets, in bytes
+const SIZE_BUCKETS: [usize; 6] = [
+    1024,
+    10 * 1024,
+    50 * 1024,
+    100 * 1024,
+    500 * 1024,
+    OVERSIZED_BYTES,
+];
+
+fn default_sample() -> usize {
+    DEFAULT_SAMPLE
+}
+
+/// Query parameters of a quality analysis
+#[derive(Debug, Clone, Deserialize)]
+pub struct QualityQuery {
+    /// Zoom level to sample
+    pub z: u8,
+    /// Tiles to sample
+    #[serde(default = "default_sample")]
+    pub sample: usize,
+    /// Seed of the random sample, to repeat an earlier analysis
+    pub seed: Option<u64>,
+}
+
+/// Kinds of problems found in sampled tiles
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    /// A tile without features, likely missing data
+    EmptyTile,
+    /// A tile over [`OVERSIZED_BYTES`]
+    OversizedTile,
+    /// Polygon features with self-intersecting or degenerate rings
+    InvalidGeometry,
+    /// Features without geometry
+    NullGeometry,
+    /// A tile that could not be decompressed or decoded
+    DecodeError,
+}
+
+/// A problem found in one tile, or in one layer of it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct QualityIssue {
+    pub kind: IssueKind,
+    /// `z/x/y`, with the row as used in tile URLs
+    pub tile: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layer: Option<String>,
+    /// Features of `layer` with the problem
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub features: Option<usize>,
+    pub message: String,
+}
+
+/// Sampled tiles or features with each kind of problem
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct IssueCounts {
+    pub empty_tiles: usize,
+    pub oversized_tiles: usize,
+    /// Features with invalid geometry
+    pub invalid_geometries: usize,
+    /// Features without geometry
+    pub null_geometries: usize,
+    pub decode_errors: usize,
+}
+
+/// Sampled tiles of at most `max_bytes`, and over the previous bucket's
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SizeBucket {
+    /// Upper bound in bytes, `null` for the bucket of oversized tiles
+    pub max_bytes: Option<usize>,
+    pub tiles: usize,
+}
+
+/// Outcome of a quality analysis
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct QualityReport {
+    pub source: String,
+    pub z: u8,
+    /// Seed of the random sample
+    pub seed: u64,
+    /// Stored tiles the sample was drawn from
+    pub candidates: usize,
+    pub sampled_tiles: usize,
+    /// Percentage of sampled tiles without problems, 100 when none were sampled
+    pub quality_score: u8,
+    pub counts: IssueCounts,
+    /// Problems found, including those beyond `MAX_ISSUES`
+    pub issue_count: usize,
+    pub issues: Vec<QualityIssue>,
+    pub size_histogram: Vec<SizeBucket>,
+}
+
+/// Problem found in a tile, before it is labelled with the tile's coordinates
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TileProblem {
+    pub kind: IssueKind,
+    pub layer: Option<String>,
+    pub features: Option<usize>,
+    pub message: String,
+}
+
+impl TileProblem {
+    fn tile(kind: IssueKind, message: String) -> Self {
+        Self {
+            kind,
+            layer: None,
+            features: None,
+            message,
+        }
+    }
+}
+
+/// Sample tiles of `source` at `query.z` and analyze them
+pub async fn analyze(
+    id: &str,
+    source: &dyn TileSource,
+    query: &QualityQuery,
+) -> Result<QualityReport> {
+    if query.sample == 0 || query.sample > MAX_SAMPLE {
+        return Err(TileServerError::BadRequest(format!(
+            "sample must be between 1 and {}",
+            MAX_SAMPLE
+        )));
+    }
+    let seed = query.seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+    });
+
+    let z = query.z;
+    let mut tiles = inspect::list(source, z).await?.tiles;
+    let candidates = tiles.len();
+    choose(&mut tiles, query.sample, seed);
+    let scheme = source.metadata().scheme;
+    tiles.sort_by_key(|&(x, y)| hilbert_id(z, x, scheme.xyz_row(z, y).unwrap_or(y)));
+
+    let mut report = QualityReport {
+        source: id.to_string(),
+        z,
+        seed,
+        candidates,
+        sampled_tiles: 0,
+        quality_score: 100,
+        counts: IssueCounts::default(),
+        issue_count: 0,
+        issues: Vec::new(),
+        size_histogram: SIZE_BUCKETS
+            .iter()
+            .map(|&max_bytes| Some(max_bytes))
+            .chain([None])
+            .map(|max_bytes| SizeBucket {
+                max_bytes,
+                tiles: 0,
+            })
+            .collect(),
+    };
+
+    let mut clean_tiles = 0usize;
+    for (x, y) in tiles {
+        // Listed tiles can be gone by the time they are read
+        let Some(tile) = source.get_tile(z, x, y).await? else {
+            continue;
+        };
+        report.sampled_tiles += 1;
+        let bucket = SIZE_BUCKETS
+            .iter()
+            .position(|&max_bytes| tile.data.len() <= max_bytes)
+            .unwrap_or(SIZE_BUCKETS.len());
+        report.size_histogram[bucket].tiles += 1;
+
+        let problems = analyze_tile(&tile);
+        if problems.is_empty() {
+            clean_tiles += 1;
+        }
+        for problem in problems {
+            let counts = &mut report.counts;
+            match problem.kind {
+                IssueKind::EmptyTile => counts.empty_tiles += 1,
+                IssueKind::OversizedTile => counts.oversized_tiles += 1,
+                IssueKind::InvalidGeometry => {
+                    counts.invalid_geometries += problem.features.unwrap_or(1)
+                }
+                IssueKind::NullGeometry => counts.null_geometries += problem.features.unwrap_or(1),
+                IssueKind::DecodeError => counts.decode_errors += 1,
+            }
+            report.issue_count += 1;
+            if report.issues.len() < MAX_ISSUES {
+                report.issues.push(QualityIssue {
+                    kind: problem.kind,
+                    tile: format!("{}/{}/{}", z, x, y),
+                    layer: problem.layer,
+                    features: problem.features,
+                    message: problem.message,
+                });
+            }
+        }
+    }
+
+    if let Some(score) = (clean_tiles * 100).checked_div(report.sampled_tiles) {
+        report.quality_score = score as u8;
+    }
+    Ok(report)
+}
+
+/// Problems with one tile; raster tiles are only checked for their size
+pub fn analyze_tile(tile: &TileData) -> Vec<TileProblem> {
+    let mut problems = Vec::new();
+    let bytes = tile.data.len();
+    if bytes > OVERSIZED_BYTES {
+        problems.push(TileProblem::tile(
+            IssueKind::OversizedTile,
+            format!("Tile is {} bytes, over the 1 MB limit", bytes),
+        ));
+    }
+    if bytes == 0 {
+        problems.push(TileProblem::tile(
+            IssueKind::EmptyTile,
+            "Tile is empty".to_string(),
+        ));
+        return problems;
+    }
+    if tile.format != TileFormat::Pbf {
+        return problems;
+    }
+
+    let decoded = match tile
+        .decompress()
+        .map_err(|e| e.to_string())
+        .and_then(|data| {
+            geozero::mvt::Tile::decode(data.as_slice())
+                .map_err(|e| format!("Failed to decode MVT tile: {}", e))
+        }) {
+        Ok(decoded) => decoded,
+        Err(message) => {
+            problems.push(TileProblem::tile(IssueKind::DecodeError, message));
+            return problems;
+        }
+    };
+
+    if decoded.layers.iter().all(|layer| layer.features.is_empty()) {
+        problems.push(TileProblem::tile(
+            IssueKind::EmptyTile,
+            "Tile has no features".to_string(),
+        ));
+    }
+    for layer in &decoded.layers {
+        problems.extend(layer_problems(layer));
+    }
+    problems
+}
+
+/// Features of a layer without geometry or with invalid polygons
+fn layer_problems(layer: &Layer) -> Vec<TileProblem> {
+    let (mut null, mut invalid) = (0, 0);
+    for feature in &layer.features {
+        let parts = decode_geometry(&feature.geometry);
+        if parts.iter().all(|part| part.is_empty()) {
+            null += 1;
+        } else if feature.r#type() == GeomType::Polygon
+            && parts.iter().any(|ring| !valid_ring(ring))
+        {
+            invalid += 1;
+        }
+    }
+
+    let problem = |kind, features: usize, message: &str| TileProblem {
+        kind,
+        layer: Some(layer.name.clone()),
+        features: Some(features),
+        message: format!("{} features {}", features, message),
+    };
+    let mut problems = Vec::new();
+    if null > 0 {
+        problems.push(problem(IssueKind::NullGeometry, null, "have no geometry"));
+    }
+    if invalid > 0 {
+        problems.push(problem(
+            IssueKind::InvalidGeometry,
+            invalid,
+            "have self-intersecting or degenerate rings",
+        ));
+    }
+    problems
+}
+
+/// Whether a ring, given without its closing point, has at least three
+/// distinct points and no segments that cross, touch or overlap other than
+/// neighbours meeting at their shared point
+fn valid_ring(ring: &[Point]) -> bool {
+    let mut points = ring.to_vec();
+    points.dedup();
+    if points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+    let n = points.len();
+    if n < 3 {
+        return false;
+    }
+    if n > MAX_RING_POINTS {
+        return true;
+    }
+
+    let segment = |i: usize| (points[i], points[(i + 1) % n]);
+    for i in 0..n {
+        let (a, b) = segment(i);
+        // Neighbours only share a point, unless the ring doubles back on itself
+        let c = points[(i + 2) % n];
+        if orientation(a, b, c) == 0 && (on_segment(a, b, c) || on_segment(b, c, a)) {
+            return false;
+        }
+        for j in i + 2..n {
+            if i == 0 && j == n - 1 {
+                continue;
+            }
+            let (c, d) = segment(j);
+            if segments_touch(a, b, c, d) {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Sign of the cross product of `b - a` and `c - a`
+fn orientation(a: Point, b: Point, c: Point) -> i8 {
+    let cross = (b[0] - a[0]) as i128 * (c[1] - a[1]) as i128
+        - (b[1] - a[1]) as i128 * (c[0] - a[0]) as i128;
+    cross.signum() as i8
+}
+
+/// Whether `p`, collinear with `a` and `b`, lies within the segment between them
+fn on_segment(a: Point, b: Point, p: Point) -> bool {
+    (a[0].min(b[0])..=a[0].max(b[0])).contains(&p[0])
+        && (a[1].min(b[1])..=a[1].max(b[1])).contains(&p[1])
+}
+
+fn segments_touch(a: Point, b: Point, c: Point, d: Point) -> bool {
+    let (o1, o2) = (orientation(c, d, a), orientation(c, d, b));
+    let (o3, o4) = (orientation(a, b, c), orientation(a, b, d));
+    if o1 * o2 < 0 && o3 * o4 < 0 {
+        return true;
+    }
+    (o1 == 0 && on_segment(c, d, a))
+        || (o2 == 0 && on_segment(c, d, b))
+        || (o3 == 0 && on_segment(a, b, c))
+        || (o4 == 0 && on_segment(a, b, d))
+}
+
+/// Position of a tile in a PMTiles archive, `u64::MAX` for invalid coordinates
+fn hilbert_id(z: u8, x: u32, y: u32) -> u64 {
+    TileCoord::new(z, x, y).map_or(u64::MAX, |coord| TileId::from(coord).value())
+}
+
+/// Keep a random `n` of `tiles`, the same ones for the same seed
+fn choose(tiles: &mut Vec<(u32, u32)>, n: usize, seed: u64) {
+    // SplitMix64, plenty for picking tiles
+    let mut state = seed;
+    let mut next = move || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+
+    // Partial Fisher-Yates shuffle
+    let n = n.min(tiles.len());
+    for i in 0..n {
+        let j = i + (next() % (tiles.len() - i) as u64) as usize;
+        tiles.swap(i, j);
+    }
+    tiles.truncate(n);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sources::overzoom::encode_geometry;
+    use crate::sources::TileCompression;
+    use bytes::Bytes;
+    use geozero::mvt::tile::Feature;
+
+    fn feature(geom_type: GeomType, parts: &[Vec<Point>]) -> Feature {
+        Feature {
+            id: None,
+            tags: vec![],
+            r#type: Some(geom_type as i32),
+            geometry: encode_geometry(geom_type, parts),
+        }
+    }
+
+    fn tile(features: Vec<Feature>) -> TileData {
+        let layer = Layer {
+            version: 2,
+            name: "test".to_string(),
+            features,
+            keys: vec![],
+            values: vec![],
+            extent: Some(4096),
+        };
+        TileData {
+            data: Bytes::from(
+                geozero::mvt::Tile {
+                    layers: vec![layer],
+                }
+                .encode_to_vec(),
+            ),
+            format: TileFormat::Pbf,
+            compression: TileCompression::None,
+        }
+    }
+
+    fn kinds(problems: &[TileProblem]) -> Vec<IssueKind> {
+        problems.iter().map(|problem| problem.kind).collect()
+    }
+
+    #[test]
+    fn test_valid_ring() {
+        let square = vec![[0, 0], [10, 0], [10, 10], [0, 10]];
+        assert!(valid_ring(&square));
+        // Closing point repeated and a duplicated vertex
+        assert!(valid_ring(&[[0, 0], [10, 0], [10, 0], [10, 10], [0, 0]]));
+
+        // Bowtie
+        assert!(!valid_ring(&[[0, 0], [10, 10], [10, 0], [0, 10]]));
+        // Touches itself at a vertex
+        assert!(!valid_ring(&[
+            [0, 0],
+            [10, 0],
+            [5, 5],
+            [10, 10],
+            [0, 10],
+            [5, 5]
+        ]));
+        // Spike doubling back along its own segment
+        assert!(!valid_ring(&[[0, 0], [10, 0], [5, 0], [5, 5]]));
+        // Degenerate
+        assert!(!valid_ring(&[[0, 0], [10, 0]]));
+        assert!(!valid_ring(&[[0, 0], [10, 0], [20, 0]]));
+    }
+
+    #[test]
+    fn test_analyze_tile() {
+        let good = tile(vec![
+            feature(GeomType::Point, &[vec![[5, 5]]]),
+            feature(
+                GeomType::Polygon,
+                &[vec![[0, 0], [10, 0], [10, 10], [0, 10]]],
+            ),
+        ]);
+        assert!(analyze_tile(&good).is_empty());
+
+        let bad = tile(vec![
+            feature(
+                GeomType::Polygon,
+                &[vec![[0, 0], [10, 10], [10, 0], [0, 10]]],
+            ),
+            feature(
+                GeomType::Polygon,
+                &[vec![[0, 0], [10, 10], [10, 0], [0, 10]]],
+            ),
+            Feature {
+                r#type: Some(GeomType::Point as i32),
+                ..Default::default()
+            },
+        ]);
+        let problems = analyze_tile(&bad);
+        assert_eq!(
+            kinds(&problems),
+            [IssueKind::NullGeometry, IssueKind::InvalidGeometry]
+        );
+        assert_eq!(problems[1].features, Some(2));
+        assert_eq!(problems[1].layer.as_deref(), Some("test"));
+
+        assert_eq!(kinds(&analyze_tile(&tile(vec![]))), [IssueKind::EmptyTile]);
+        let corrupt = TileData {
+            data: Bytes::from_static(&[0x1a, 0xff]),
+            format: TileFormat::Pbf,
+            compression: TileCompression::None,
+        };
+        assert_eq!(kinds(&analyze_tile(&corrupt)), [IssueKind::DecodeError]);
+    }
+
+    #[test]
+    fn test_analyze_tile_size() {
+        let large = TileData {
+            data: Bytes::from(vec![0u8; OVERSIZED_BYTES + 1]),
+            format: TileFormat::Png,
+            compression: TileCompression::None,
+        };
+        assert_eq!(kinds(&analyze_tile(&large)), [IssueKind::OversizedTile]);
+        let empty = TileData {
+            data: Bytes::new(),
+            format: TileFormat::Png,
+            compression: TileCompression::None,
+        };
+        assert_eq!(kinds(&analyze_tile(&empty)), [IssueKind::EmptyTile]);
+    }
+
+    #[test]
+    fn test_choose_is_seeded() {
+        let all: Vec<(u32, u32)> = (0..50).map(|i| (i, i)).collect();
+        let mut first = all.clone();
+        choose(&mut first, 10, 7);
+        let mut second = all.clone();
+        choose(&mut second, 10, 7);
+        assert_eq!(first, second);
+        assert_eq!(first.len(), 10);
+        assert!(first.iter().all(|tile| all.contains(tile)));
+
+        let mut few = all[..3].to_vec();
+        choose(&mut few, 10, 7);
+        assert_eq!(few.len(), 3);
+    }
+
+    #[test]
+    fn test_hilbert_id_orders_like_pmtiles() {
+        assert_eq!(hilbert_id(0, 0, 0), 0);
+        // Zoom 1 follows the Hilbert curve: (0,0), (0,1), (1,1), (1,0)
+        let mut tiles = vec![(1, 0), (1, 1), (0, 1), (0, 0)];
+        tiles.sort_by_key(|&(x, y)| hilbert_id(1, x, y));
+        assert_eq!(tiles, [(0, 0), (0, 1), (1, 1), (1, 0)]);
+        assert_eq!(hilbert_id(1, 2, 0), u64::MAX);
+    }
+}
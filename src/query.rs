@@ -0,0 +1,489 @@
+//! Feature queries on rendered raster tiles
+//! (`GET /styles/{style}/{z}/{x}/{y}/query?px=&py=`)
+//!
+//! Maps a pixel of a raster tile back to the vector features drawn there,
+//! like WMS GetFeatureInfo. The vector tiles of the style's sources under
+//! the pixel are searched for features within `radius` pixels of it.
+//! Only source layers drawn by a visible style layer at that zoom are
+//! searched; layer filters are not evaluated.
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+use geozero::mvt::tile::{Feature, GeomType, Layer};
+use serde_json::{json, Value};
+
+use crate::diff::properties;
+use crate::error::{Result, TileServerError};
+use crate::sources::overzoom::{decode_geometry, ring_area, Point};
+use crate::sources::{SourceManager, TileFormat};
+use crate::styles::{data_source_id, Style};
+
+/// Size in pixels of the raster tiles at `/styles/{style}/{z}/{x}/{y}.png`
+pub const DEFAULT_TILE_SIZE: u32 = 512;
+
+/// Search radius in pixels when none is given
+pub const DEFAULT_RADIUS: f64 = 4.0;
+
+/// Largest search radius in pixels
+pub const MAX_RADIUS: f64 = 64.0;
+
+/// A pixel of a raster tile at `z/x/y` (XYZ row)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PixelQuery {
+    pub z: u8,
+    pub x: u32,
+    pub y: u32,
+    /// Pixel column, from 0 to `tile_size`
+    pub px: f64,
+    /// Pixel row, from 0 to `tile_size`
+    pub py: f64,
+    /// Search radius in pixels
+    pub radius: f64,
+    /// Size of the queried tile in pixels, 256 or 512
+    pub tile_size: f64,
+}
+
+impl PixelQuery {
+    /// Validate a query; pixels outside the tile are a bad request
+    pub fn new(
+        z: u8,
+        x: u32,
+        y: u32,
+        (px, py): (f64, f64),
+        radius: Option<f64>,
+        tile_size: Option<u32>,
+    ) -> Result<Self> {
+        crate::sources::validate_tile_coords(z, x, y)?;
+        let tile_size = tile_size.unwrap_or(DEFAULT_TILE_SIZE);
+        if tile_size != 256 && tile_size != 512 {
+            return Err(TileServerError::BadRequest(format!(
+                "Invalid tile size: {}. Must be 256 or 512",
+                tile_size
+            )));
+        }
+        let tile_size = tile_size as f64;
+        let in_tile = |p: f64| (0.0..tile_size).contains(&p);
+        if !in_tile(px) || !in_tile(py) {
+            return Err(TileServerError::BadRequest(format!(
+                "Pixel ({}, {}) is outside the {}x{} tile",
+                px, py, tile_size, tile_size
+            )));
+        }
+        Ok(Self {
+            z,
+            x,
+            y,
+            px,
+            py,
+            radius: radius.unwrap_or(DEFAULT_RADIUS).clamp(0.0, MAX_RADIUS),
+            tile_size,
+        })
+    }
+
+    /// Position of the pixel in the world, from 0 to 1 on both axes
+    fn world(&self) -> (f64, f64) {
+        let n = (1u64 << self.z) as f64;
+        (
+            (self.x as f64 + self.px / self.tile_size) / n,
+            (self.y as f64 + self.py / self.tile_size) / n,
+        )
+    }
+
+    /// Longitude and latitude of the pixel
+    pub fn lon_lat(&self) -> (f64, f64) {
+        let (wx, wy) = self.world();
+        to_lon_lat(wx, wy)
+    }
+}
+
+fn to_lon_lat(wx: f64, wy: f64) -> (f64, f64) {
+    let lat = (PI * (1.0 - 2.0 * wy)).sinh().atan().to_degrees();
+    (wx * 360.0 - 180.0, lat)
+}
+
+/// The topmost visible style layer drawing a source layer
+#[derive(Debug, Clone)]
+struct StyleLayer {
+    /// Position in the style's layers; higher is drawn on top
+    index: usize,
+    id: String,
+}
+
+/// Visible style layers at zoom `z` by style source and source layer
+fn style_layers(style: &Style, z: u8) -> HashMap<(String, String), StyleLayer> {
+    let mut drawn = HashMap::new();
+    let layers = style.style_json.get("layers").and_then(|l| l.as_array());
+
+    for (index, layer) in layers.into_iter().flatten().enumerate() {
+        let str_field = |name: &str| layer.get(name).and_then(|v| v.as_str());
+        let zoom_field = |name: &str| layer.get(name).and_then(|v| v.as_f64());
+        let (Some(source), Some(source_layer), Some(id)) = (
+            str_field("source"),
+            str_field("source-layer"),
+            str_field("id"),
+        ) else {
+            continue;
+        };
+        let hidden = layer.pointer("/layout/visibility").and_then(|v| v.as_str()) == Some("none");
+        let zoom = z as f64;
+        if hidden
+            || zoom_field("minzoom").is_some_and(|min| zoom < min)
+            || zoom_field("maxzoom").is_some_and(|max| zoom >= max)
+        {
+            continue;
+        }
+        // Later layers are drawn on top
+        drawn.insert(
+            (source.to_string(), source_layer.to_string()),
+            StyleLayer {
+                index,
+                id: id.to_string(),
+            },
+        );
+    }
+
+    drawn
+}
+
+/// A feature found under the pixel
+struct Hit {
+    /// Drawing order: style layer, then position in the tile layer
+    order: (usize, usize),
+    feature: Value,
+}
+
+/// Features drawn within `radius` pixels of the queried pixel, as a GeoJSON
+/// FeatureCollection with the topmost feature first
+///
+/// Each feature's properties get `_source`, `_layer` (the source layer) and
+/// `_style_layer`. Geometries are clipped to the searched vector tile.
+/// Styles without vector sources served from `/data` return no features.
+pub async fn query_pixel(
+    style: &Style,
+    sources: &SourceManager,
+    query: &PixelQuery,
+) -> Result<Value> {
+    let drawn = style_layers(style, query.z);
+    let style_sources = style.style_json.get("sources").and_then(|s| s.as_object());
+    let (lon, lat) = query.lon_lat();
+    let mut hits = Vec::new();
+
+    for (style_source, definition) in style_sources.into_iter().flatten() {
+        let Some(source_id) = definition
+            .get("url")
+            .and_then(|u| u.as_str())
+            .and_then(data_source_id)
+        else {
+            continue;
+        };
+        let Some(source) = sources.get(source_id) else {
+            continue;
+        };
+        let metadata = source.metadata();
+        let outside = metadata.bounds.is_some_and(|[west, south, east, north]| {
+            lon < west || lon > east || lat < south || lat > north
+        });
+        if metadata.format != TileFormat::Pbf || query.z < metadata.minzoom || outside {
+            continue;
+        }
+
+        // Search the stored tile under the pixel, beyond maxzoom its ancestor
+        let z = query.z.min(metadata.maxzoom);
+        let n = (1u64 << z) as f64;
+        let (wx, wy) = query.world();
+        let (tx, ty) = ((wx * n).floor() as u32, (wy * n).floor() as u32);
+        let Some(tile) = source.get_tile(z, tx, ty).await? else {
+            continue;
+        };
+
+        let tile_position = TilePosition {
+            n,
+            tx,
+            ty,
+            wx,
+            wy,
+            radius: query.radius / query.tile_size / (1u64 << (query.z - z)) as f64,
+        };
+        for layer in tile.decode_mvt()?.layers {
+            let Some(style_layer) = drawn.get(&(style_source.clone(), layer.name.clone())) else {
+                continue;
+            };
+            for (i, feature) in layer.features.iter().enumerate() {
+                if !tile_position.hits(&layer, feature) {
+                    continue;
+                }
+                let mut geojson = tile_position.to_geojson(&layer, feature);
+                geojson["properties"]["_source"] = json!(source_id);
+                geojson["properties"]["_layer"] = json!(layer.name);
+                geojson["properties"]["_style_layer"] = json!(style_layer.id);
+                hits.push(Hit {
+                    order: (style_layer.index, i),
+                    feature: geojson,
+                });
+            }
+        }
+    }
+
+    hits.sort_by_key(|hit| std::cmp::Reverse(hit.order));
+    Ok(json!({
+        "type": "FeatureCollection",
+        "features": hits.into_iter().map(|hit| hit.feature).collect::<Vec<_>>(),
+    }))
+}
+
+/// The queried pixel within the searched vector tile
+struct TilePosition {
+    /// Tiles per axis at the tile's zoom
+    n: f64,
+    tx: u32,
+    ty: u32,
+    /// Pixel position in the world, from 0 to 1
+    wx: f64,
+    wy: f64,
+    /// Search radius as a fraction of the searched tile's width
+    radius: f64,
+}
+
+impl TilePosition {
+    /// Whether the feature is within the search radius of the pixel
+    fn hits(&self, layer: &Layer, feature: &Feature) -> bool {
+        let extent = layer.extent.unwrap_or(4096) as f64;
+        let point = [
+            (self.wx * self.n - self.tx as f64) * extent,
+            (self.wy * self.n - self.ty as f64) * extent,
+        ];
+        let tolerance = self.radius * extent;
+        let parts = decode_geometry(&feature.geometry);
+
+        match feature.r#type() {
+            GeomType::Point => parts
+                .iter()
+                .flatten()
+                .any(|p| distance(point, as_f64(*p)) <= tolerance),
+            GeomType::Linestring => parts
+                .iter()
+                .any(|line| segments(line, false).any(|s| segment_distance(point, s) <= tolerance)),
+            GeomType::Polygon => {
+                // Even-odd over all rings handles holes and multipolygons
+                let crossings = parts
+                    .iter()
+                    .flat_map(|ring| segments(ring, true))
+                    .filter(|&(a, b)| {
+                        (a[1] > point[1]) != (b[1] > point[1])
+                            && point[0] < a[0] + (point[1] - a[1]) / (b[1] - a[1]) * (b[0] - a[0])
+                    })
+                    .count();
+                crossings % 2 == 1
+                    || parts.iter().any(|ring| {
+                        segments(ring, true).any(|s| segment_distance(point, s) <= tolerance)
+                    })
+            }
+            GeomType::Unknown => false,
+        }
+    }
+
+    /// The feature as GeoJSON in longitude and latitude
+    fn to_geojson(&self, layer: &Layer, feature: &Feature) -> Value {
+        let extent = layer.extent.unwrap_or(4096) as f64;
+        let position = |p: &Point| {
+            let (lon, lat) = to_lon_lat(
+                (self.tx as f64 + p[0] as f64 / extent) / self.n,
+                (self.ty as f64 + p[1] as f64 / extent) / self.n,
+            );
+            json!([lon, lat])
+        };
+        let line = |part: &[Point]| part.iter().map(position).collect::<Vec<_>>();
+        let parts = decode_geometry(&feature.geometry);
+
+        let geometry = match feature.r#type() {
+            GeomType::Point => {
+                let points: Vec<Value> = parts.iter().flatten().map(position).collect();
+                match &points[..] {
+                    [point] => json!({"type": "Point", "coordinates": point}),
+                    _ => json!({"type": "MultiPoint", "coordinates": points}),
+                }
+            }
+            GeomType::Linestring => match &parts[..] {
+                [part] => json!({"type": "LineString", "coordinates": line(part)}),
+                _ => json!({
+                    "type": "MultiLineString",
+                    "coordinates": parts.iter().map(|p| line(p)).collect::<Vec<_>>(),
+                }),
+            },
+            GeomType::Polygon | GeomType::Unknown => {
+                // Exterior rings start polygons; the holes that follow belong to them
+                let mut polygons: Vec<Vec<Vec<Value>>> = Vec::new();
+                for ring in &parts {
+                    let mut closed = line(ring);
+                    closed.extend(ring.first().map(position));
+                    match polygons.last_mut() {
+                        Some(polygon) if ring_area(ring) < 0 => polygon.push(closed),
+                        _ => polygons.push(vec![closed]),
+                    }
+                }
+                match &polygons[..] {
+                    [polygon] => json!({"type": "Polygon", "coordinates": polygon}),
+                    _ => json!({"type": "MultiPolygon", "coordinates": polygons}),
+                }
+            }
+        };
+
+        let mut geojson = json!({
+            "type": "Feature",
+            "geometry": geometry,
+            "properties": properties(layer, feature),
+        });
+        if let Some(id) = feature.id {
+            geojson["id"] = json!(id);
+        }
+        geojson
+    }
+}
+
+fn as_f64(p: Point) -> [f64; 2] {
+    [p[0] as f64, p[1] as f64]
+}
+
+/// Consecutive segments of a line, or of a ring when `closed`
+fn segments(part: &[Point], closed: bool) -> impl Iterator<Item = ([f64; 2], [f64; 2])> + '_ {
+    let wrap = if closed && part.len() > 2 { 1 } else { 0 };
+    (0..part.len().saturating_sub(1) + wrap)
+        .map(move |i| (as_f64(part[i]), as_f64(part[(i + 1) % part.len()])))
+}
+
+fn distance(a: [f64; 2], b: [f64; 2]) -> f64 {
+    (a[0] - b[0]).hypot(a[1] - b[1])
+}
+
+fn segment_distance(p: [f64; 2], (a, b): ([f64; 2], [f64; 2])) -> f64 {
+    let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+    let length = dx * dx + dy * dy;
+    if length == 0.0 {
+        return distance(p, a);
+    }
+    let t = (((p[0] - a[0]) * dx + (p[1] - a[1]) * dy) / length).clamp(0.0, 1.0);
+    distance(p, [a[0] + t * dx, a[1] + t * dy])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(px: f64, py: f64) -> TilePosition {
+        TilePosition {
+            n: 1.0,
+            tx: 0,
+            ty: 0,
+            wx: px / 256.0,
+            wy: py / 256.0,
+            radius: 2.0 / 256.0,
+        }
+    }
+
+    fn layer(features: Vec<Feature>) -> Layer {
+        Layer {
+            version: 2,
+            name: "test".to_string(),
+            features,
+            extent: Some(256),
+            ..Default::default()
+        }
+    }
+
+    fn feature(geom_type: GeomType, geometry: Vec<u32>) -> Feature {
+        Feature {
+            r#type: Some(geom_type as i32),
+            geometry,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_pixel_query_validation() {
+        let query = PixelQuery::new(1, 1, 0, (0.0, 256.0), None, None).unwrap();
+        assert_eq!(query.radius, DEFAULT_RADIUS);
+        let (lon, lat) = query.lon_lat();
+        assert_eq!(lon, 0.0);
+        assert!((lat - 66.51326).abs() < 1e-5);
+
+        let query = PixelQuery::new(1, 1, 0, (0.0, 128.0), None, Some(256)).unwrap();
+        assert_eq!(query.lon_lat(), (lon, lat));
+
+        for (pixel, tile_size) in [
+            ((512.0, 0.0), None),
+            ((0.0, -1.0), None),
+            ((256.0, 0.0), Some(256)),
+        ] {
+            assert!(matches!(
+                PixelQuery::new(1, 1, 0, pixel, None, tile_size),
+                Err(TileServerError::BadRequest(_))
+            ));
+        }
+        assert!(matches!(
+            PixelQuery::new(1, 1, 0, (0.0, 0.0), None, Some(300)),
+            Err(TileServerError::BadRequest(_))
+        ));
+        assert!(matches!(
+            PixelQuery::new(1, 2, 0, (0.0, 0.0), None, None),
+            Err(TileServerError::InvalidCoordinates { .. })
+        ));
+        assert_eq!(
+            PixelQuery::new(1, 1, 0, (0.0, 0.0), Some(1000.0), None)
+                .unwrap()
+                .radius,
+            MAX_RADIUS
+        );
+    }
+
+    #[test]
+    fn test_hits() {
+        // Point at (10, 10)
+        let point = feature(GeomType::Point, vec![9, 20, 20]);
+        // Line from (0, 100) to (200, 100)
+        let line = feature(GeomType::Linestring, vec![9, 0, 200, 10, 400, 0]);
+        // Square (50, 150)-(150, 250) with a hole (90, 190)-(110, 210)
+        let square = feature(
+            GeomType::Polygon,
+            vec![
+                9, 100, 300, 26, 200, 0, 0, 200, 199, 0, 15, //
+                9, 80, 119, 26, 0, 40, 40, 0, 0, 39, 15,
+            ],
+        );
+        let layer = layer(vec![]);
+
+        assert!(position(11.0, 11.0).hits(&layer, &point));
+        assert!(!position(13.0, 13.0).hits(&layer, &point));
+        assert!(position(100.0, 101.5).hits(&layer, &line));
+        assert!(!position(100.0, 103.0).hits(&layer, &line));
+        assert!(!position(201.0, 103.0).hits(&layer, &line));
+        assert!(position(60.0, 160.0).hits(&layer, &square));
+        assert!(!position(100.0, 200.0).hits(&layer, &square), "In the hole");
+        assert!(
+            position(91.0, 200.0).hits(&layer, &square),
+            "On the hole's edge"
+        );
+        assert!(!position(160.0, 160.0).hits(&layer, &square));
+    }
+
+    #[test]
+    fn test_to_geojson() {
+        let square = feature(
+            GeomType::Polygon,
+            vec![9, 0, 0, 26, 512, 0, 0, 512, 511, 0, 15],
+        );
+        let geojson = position(0.0, 0.0).to_geojson(&layer(vec![]), &square);
+
+        assert_eq!(geojson["geometry"]["type"], "Polygon");
+        let ring = geojson["geometry"]["coordinates"][0].as_array().unwrap();
+        assert_eq!(ring.len(), 5);
+        assert_eq!(ring[0], ring[4]);
+        assert_eq!(ring[0][0], -180.0);
+        assert_eq!(ring[1][0], 180.0);
+
+        let points = feature(GeomType::Point, vec![17, 256, 256, 2, 2]);
+        let geojson = position(0.0, 0.0).to_geojson(&layer(vec![]), &points);
+        assert_eq!(geojson["geometry"]["type"], "MultiPoint");
+        assert_eq!(geojson["geometry"]["coordinates"][0][0], 0.0);
+    }
+}
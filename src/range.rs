@@ -0,0 +1,270 @@
+//! Byte range requests (`Range: bytes=start-end`)
+//!
+//! Static files seek to the requested range (see [`crate::files`]). Tiles and
+//! static images are fully in memory once rendered, so [`byte_ranges`] slices
+//! the finished response body. Only single ranges are served; a request for
+//! several ranges gets the whole body with `200 OK`.
+//!
+//! An `If-Range` header is honoured: when its ETag or date no longer matches
+//! the response, the range is ignored and the whole body is sent.
+
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{
+        header::{
+            ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE,
+        },
+        HeaderValue, Method, StatusCode,
+    },
+    middleware::Next,
+    response::Response,
+};
+use std::time::SystemTime;
+
+/// The part of a response body to send, per the `Range` header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    /// No usable range was requested
+    Full,
+    /// Inclusive byte offsets
+    Partial { start: u64, end: u64 },
+    /// The range lies beyond the end of the body
+    Unsatisfiable,
+}
+
+impl ByteRange {
+    /// Resolve a `Range` header against a body of `len` bytes
+    ///
+    /// Headers that are malformed, use another unit or ask for several ranges
+    /// are ignored, and the whole body is sent.
+    pub fn parse(header: Option<&str>, len: u64) -> Self {
+        let Some(spec) = header.and_then(|h| h.trim().strip_prefix("bytes=")) else {
+            return ByteRange::Full;
+        };
+        let Some((start, end)) = spec.trim().split_once('-') else {
+            return ByteRange::Full;
+        };
+        if spec.contains(',') {
+            return ByteRange::Full;
+        }
+
+        let (start, end) = match (start.trim(), end.trim()) {
+            // Last `n` bytes
+            ("", suffix) => match suffix.parse::<u64>() {
+                Ok(0) => return ByteRange::Unsatisfiable,
+                Ok(n) if len > 0 => (len.saturating_sub(n), len - 1),
+                Ok(_) => return ByteRange::Unsatisfiable,
+                Err(_) => return ByteRange::Full,
+            },
+            (start, "") => match start.parse::<u64>() {
+                Ok(start) => (start, len.saturating_sub(1)),
+                Err(_) => return ByteRange::Full,
+            },
+            (start, end) => match (start.parse::<u64>(), end.parse::<u64>()) {
+                (Ok(start), Ok(end)) if start <= end => (start, end.min(len.saturating_sub(1))),
+                _ => return ByteRange::Full,
+            },
+        };
+
+        if start >= len {
+            return ByteRange::Unsatisfiable;
+        }
+        ByteRange::Partial { start, end }
+    }
+}
+
+/// Whether the range of a request may be served, per its `If-Range` header
+///
+/// An entity tag must match `etag` by strong comparison, so weak ETags never
+/// match. A date must equal `last_modified` exactly.
+pub fn if_range_matches(
+    if_range: Option<&HeaderValue>,
+    etag: Option<&str>,
+    last_modified: Option<SystemTime>,
+) -> bool {
+    let Some(value) = if_range else {
+        return true;
+    };
+    let Ok(value) = value.to_str() else {
+        return false;
+    };
+    let value = value.trim();
+
+    if value.starts_with('"') || value.starts_with("W/") {
+        return etag.is_some_and(|etag| !etag.starts_with("W/") && etag == value);
+    }
+    match (httpdate::parse_http_date(value), last_modified) {
+        (Ok(date), Some(modified)) => {
+            httpdate::fmt_http_date(date) == httpdate::fmt_http_date(modified)
+        }
+        _ => false,
+    }
+}
+
+/// Middleware serving byte ranges of in-memory responses
+///
+/// Successful responses advertise `Accept-Ranges: bytes`. For a `GET` with a
+/// `Range` header, the body is collected and the requested range answered
+/// with `206 Partial Content`, or `416 Range Not Satisfiable` when it lies
+/// past the end.
+pub async fn byte_ranges(request: Request<Body>, next: Next) -> Response {
+    let range = (request.method() == Method::GET)
+        .then(|| request.headers().get(RANGE).cloned())
+        .flatten();
+    let if_range = request.headers().get(IF_RANGE).cloned();
+
+    let mut response = next.run(request).await;
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+    response
+        .headers_mut()
+        .insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+
+    let Some(range) = range else {
+        return response;
+    };
+    let headers = response.headers();
+    let etag = headers.get(ETAG).and_then(|v| v.to_str().ok());
+    let last_modified = headers
+        .get(LAST_MODIFIED)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| httpdate::parse_http_date(v).ok());
+    if !if_range_matches(if_range.as_ref(), etag, last_modified) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::warn!("Failed to read response body for a range request: {}", e);
+            parts.status = StatusCode::INTERNAL_SERVER_ERROR;
+            parts.headers.remove(CONTENT_LENGTH);
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+    let len = bytes.len() as u64;
+
+    match ByteRange::parse(range.to_str().ok(), len) {
+        ByteRange::Full => Response::from_parts(parts, Body::from(bytes)),
+        ByteRange::Partial { start, end } => {
+            parts.status = StatusCode::PARTIAL_CONTENT;
+            parts.headers.insert(
+                CONTENT_RANGE,
+                HeaderValue::from_str(&format!("bytes {}-{}/{}", start, end, len))
+                    .expect("Content-Range is ASCII"),
+            );
+            parts
+                .headers
+                .insert(CONTENT_LENGTH, HeaderValue::from(end - start + 1));
+            let body = bytes.slice(start as usize..=end as usize);
+            Response::from_parts(parts, Body::from(body))
+        }
+        ByteRange::Unsatisfiable => {
+            parts.status = StatusCode::RANGE_NOT_SATISFIABLE;
+            parts.headers.insert(
+                CONTENT_RANGE,
+                HeaderValue::from_str(&format!("bytes */{}", len)).expect("Content-Range is ASCII"),
+            );
+            parts.headers.insert(CONTENT_LENGTH, HeaderValue::from(0));
+            Response::from_parts(parts, Body::empty())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        let parse = |header| ByteRange::parse(Some(header), 100);
+
+        assert_eq!(
+            parse("bytes=10-19"),
+            ByteRange::Partial { start: 10, end: 19 }
+        );
+        assert_eq!(
+            parse("bytes=90-"),
+            ByteRange::Partial { start: 90, end: 99 }
+        );
+        assert_eq!(parse("bytes=-5"), ByteRange::Partial { start: 95, end: 99 });
+        assert_eq!(
+            parse("bytes=-500"),
+            ByteRange::Partial { start: 0, end: 99 }
+        );
+        assert_eq!(
+            parse("bytes=50-500"),
+            ByteRange::Partial { start: 50, end: 99 }
+        );
+
+        assert_eq!(parse("bytes=100-"), ByteRange::Unsatisfiable);
+        assert_eq!(parse("bytes=-0"), ByteRange::Unsatisfiable);
+        assert_eq!(
+            ByteRange::parse(Some("bytes=0-"), 0),
+            ByteRange::Unsatisfiable
+        );
+
+        // Ignored: served in full
+        assert_eq!(ByteRange::parse(None, 100), ByteRange::Full);
+        assert_eq!(parse("items=0-1"), ByteRange::Full);
+        assert_eq!(parse("bytes=0-1,5-6"), ByteRange::Full);
+        assert_eq!(parse("bytes=20-10"), ByteRange::Full);
+        assert_eq!(parse("bytes=a-b"), ByteRange::Full);
+    }
+
+    fn if_range(value: &str) -> Option<HeaderValue> {
+        Some(HeaderValue::from_str(value).unwrap())
+    }
+
+    #[test]
+    fn test_if_range() {
+        let modified = httpdate::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+        let strong = Some("\"5f3a-1c\"");
+        let weak = Some("W/\"5f3a-1c\"");
+
+        assert!(if_range_matches(None, None, None));
+
+        assert!(if_range_matches(
+            if_range("\"5f3a-1c\"").as_ref(),
+            strong,
+            None
+        ));
+        assert!(!if_range_matches(
+            if_range("\"other\"").as_ref(),
+            strong,
+            None
+        ));
+        // Weak validators never match
+        assert!(!if_range_matches(
+            if_range("W/\"5f3a-1c\"").as_ref(),
+            weak,
+            None
+        ));
+        assert!(!if_range_matches(
+            if_range("\"5f3a-1c\"").as_ref(),
+            weak,
+            None
+        ));
+
+        let date = "Wed, 21 Oct 2015 07:28:00 GMT";
+        assert!(if_range_matches(
+            if_range(date).as_ref(),
+            None,
+            Some(modified)
+        ));
+        assert!(!if_range_matches(
+            if_range("Wed, 21 Oct 2015 07:29:00 GMT").as_ref(),
+            None,
+            Some(modified)
+        ));
+        assert!(!if_range_matches(if_range(date).as_ref(), strong, None));
+        assert!(!if_range_matches(
+            if_range("garbage").as_ref(),
+            strong,
+            Some(modified)
+        ));
+    }
+}
@@ -0,0 +1,218 @@
+//! Coalescing of identical concurrent renders
+//!
+//! Map clients panning around, several browser tabs or racing CDN misses
+//! often ask for the same raster tile at once. [`SingleFlight`] lets the
+//! first request start the render and every identical request that arrives
+//! while it runs wait for that result instead of rendering again.
+//!
+//! The render runs as its own task, so it finishes even when the requests
+//! waiting for it are cancelled, and it leaves the in-flight map once done,
+//! whether it succeeded, failed or panicked. A later request for the same key
+//! then renders anew.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+
+use futures::future::{BoxFuture, FutureExt, Shared};
+use opentelemetry::metrics::Counter;
+
+use crate::error::{Result, TileServerError};
+
+/// A render in progress, shared by every request waiting for it
+type Flight = Shared<BoxFuture<'static, Arc<Result<Vec<u8>>>>>;
+
+static COALESCED_RENDERS: OnceLock<Counter<u64>> = OnceLock::new();
+
+fn coalesced_renders() -> &'static Counter<u64> {
+    COALESCED_RENDERS.get_or_init(|| {
+        opentelemetry::global::meter("tileserver-rs")
+            .u64_counter("tileserver_render_coalesced_total")
+            .with_description("Render requests served by an identical render already in progress")
+            .with_unit("requests")
+            .build()
+    })
+}
+
+/// Renders in progress, keyed by what they render
+pub struct SingleFlight<K> {
+    in_flight: Arc<Mutex<HashMap<K, Flight>>>,
+    coalesced: AtomicU64,
+}
+
+impl<K> Default for SingleFlight<K> {
+    fn default() -> Self {
+        Self {
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+            coalesced: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<K> SingleFlight<K>
+where
+    K: Hash + Eq + Clone + Send + 'static,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Result of `render`, or of the render already in progress for `key`
+    ///
+    /// `render` is only spawned when no render for `key` is in progress.
+    pub async fn run<F>(&self, key: K, render: F) -> Result<Vec<u8>>
+    where
+        F: Future<Output = Result<Vec<u8>>> + Send + 'static,
+    {
+        let flight = {
+            let mut in_flight = self
+                .in_flight
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            match in_flight.get(&key) {
+                Some(flight) => {
+                    self.coalesced.fetch_add(1, Ordering::Relaxed);
+                    coalesced_renders().add(1, &[]);
+                    flight.clone()
+                }
+                None => {
+                    let flight = self.spawn(key.clone(), render);
+                    in_flight.insert(key, flight.clone());
+                    flight
+                }
+            }
+        };
+
+        match &*flight.await {
+            Ok(data) => Ok(data.clone()),
+            Err(e) => Err(replay_error(e)),
+        }
+    }
+
+    /// Start `render` as its own task, removing `key` once it is done
+    fn spawn<F>(&self, key: K, render: F) -> Flight
+    where
+        F: Future<Output = Result<Vec<u8>>> + Send + 'static,
+    {
+        let in_flight = Arc::clone(&self.in_flight);
+        let task = tokio::spawn(async move {
+            let result = AssertUnwindSafe(render)
+                .catch_unwind()
+                .await
+                .unwrap_or_else(|_| {
+                    Err(TileServerError::RenderError(
+                        "Render task panicked".to_string(),
+                    ))
+                });
+            in_flight
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .remove(&key);
+            result
+        });
+        async move {
+            Arc::new(task.await.unwrap_or_else(|_| {
+                Err(TileServerError::RenderError(
+                    "Render task panicked".to_string(),
+                ))
+            }))
+        }
+        .boxed()
+        .shared()
+    }
+
+    /// Requests that waited for an identical render instead of starting one
+    pub fn coalesced(&self) -> u64 {
+        self.coalesced.load(Ordering::Relaxed)
+    }
+
+    /// Renders currently in progress
+    #[cfg(test)]
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+}
+
+/// Copy of a shared render error for one of the requests waiting for it
+///
+/// Errors a render can end with keep their kind, and so their status code;
+/// anything else becomes a render error with the same message.
+fn replay_error(err: &TileServerError) -> TileServerError {
+    match err {
+        TileServerError::RenderUnavailable => TileServerError::RenderUnavailable,
+        TileServerError::RenderQueueFull => TileServerError::RenderQueueFull,
+        TileServerError::Timeout(message) => TileServerError::Timeout(message.clone()),
+        TileServerError::RenderError(message) => TileServerError::RenderError(message.clone()),
+        other => TileServerError::RenderError(other.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_failures_are_shared_and_not_kept() {
+        let flights = SingleFlight::new();
+        let slow_failure = || async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Err(TileServerError::Timeout("render after 10s".to_string()))
+        };
+
+        let (first, second) = tokio::join!(
+            flights.run("tile", slow_failure()),
+            flights.run("tile", slow_failure())
+        );
+        assert!(matches!(first, Err(TileServerError::Timeout(_))));
+        assert!(matches!(second, Err(TileServerError::Timeout(_))));
+        assert_eq!(flights.coalesced(), 1);
+        assert_eq!(flights.in_flight(), 0);
+
+        let retry = flights.run("tile", async { Ok(vec![1]) }).await.unwrap();
+        assert_eq!(retry, vec![1]);
+    }
+
+    #[tokio::test]
+    async fn test_panic_does_not_poison_key() {
+        let flights = SingleFlight::new();
+        let err = flights
+            .run("tile", async { panic!("renderer crashed") })
+            .await
+            .unwrap_err();
+        assert!(matches!(err, TileServerError::RenderError(_)));
+        assert_eq!(flights.in_flight(), 0);
+
+        let data = flights.run("tile", async { Ok(vec![2]) }).await.unwrap();
+        assert_eq!(data, vec![2]);
+    }
+
+    #[tokio::test]
+    async fn test_render_outlives_cancelled_requests() {
+        let flights = Arc::new(SingleFlight::new());
+        let renders = Arc::new(AtomicUsize::new(0));
+        let render = {
+            let renders = Arc::clone(&renders);
+            async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                renders.fetch_add(1, Ordering::SeqCst);
+                Ok(vec![3])
+            }
+        };
+
+        let cancelled = tokio::time::timeout(Duration::from_millis(5), flights.run("tile", render));
+        assert!(cancelled.await.is_err());
+        assert_eq!(flights.in_flight(), 1);
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(renders.load(Ordering::SeqCst), 1);
+        assert_eq!(flights.in_flight(), 0);
+    }
+}
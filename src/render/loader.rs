@@ -0,0 +1,501 @@
+//! In-process resource loader for the native renderer
+//!
+//! Styles rewritten for native rendering point MapLibre at this server's own
+//! URLs, and at [`LOCAL_TILES`] URLs for the tiles of its sources. Maps
+//! created with a loader hand each resource request to
+//! [`ResourceLoader::load`] instead of fetching it over HTTP: tiles come
+//! straight from the [`SourceManager`](crate::sources::SourceManager),
+//! glyphs and sprites from the same code the `/fonts` and `/styles` handlers
+//! use, and everything else (other http(s) URLs, and local URLs such as
+//! TileJSON that have no shortcut) is fetched with reqwest. The only
+//! `file://` URLs loaded are those of sprites decoded from `data:` URIs.
+//!
+//! Requests arrive on render worker threads, outside the async runtime, so
+//! the loader blocks on async calls through a runtime [`Handle`].
+
+use bytes::Bytes;
+use maplibre_native_sys::resource_kind;
+use reqwest::StatusCode;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::Handle;
+
+use crate::error::{Result, TileServerError};
+use crate::fonts::{self, FontCatalog, GlyphCache};
+use crate::sources::{SharedSources, TileCompression};
+use crate::styles::sprite::{self, SpriteFile};
+use crate::styles::SharedStyles;
+
+/// Longest an external resource may take to fetch
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Scheme of `pmtiles://{source}/{z}/{x}/{y}` tile URLs, which only the
+/// loader serves
+///
+/// The callback file source takes every request of a map, so MapLibre's own
+/// PMTiles file source never sees these.
+pub const LOCAL_TILES: &str = "pmtiles://";
+
+/// Answer to a resource request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resource {
+    Data(Bytes),
+    /// Missing; MapLibre treats missing tiles as empty
+    NotFound,
+    Error(String),
+}
+
+impl Resource {
+    /// Data of `result`, or the HTTP handlers' 404s as `NotFound`
+    fn from_result(result: Result<Option<Bytes>>) -> Self {
+        match result {
+            Ok(Some(data)) => Resource::Data(data),
+            Ok(None) => Resource::NotFound,
+            Err(e) if e.status() == StatusCode::NOT_FOUND => Resource::NotFound,
+            Err(e) => Resource::Error(e.to_string()),
+        }
+    }
+}
+
+/// A resource of this server that can be loaded without HTTP
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum LocalResource {
+    /// `/data/{source}/{z}/{x}/{y}.{format}`
+    Tile {
+        source: String,
+        z: u8,
+        x: u32,
+        y: u32,
+    },
+    /// `/fonts/{fontstack}/{range}.pbf`
+    Glyphs { fontstack: String, range: String },
+    /// `/styles/{style}[/{sprite_id}]/sprite[@{n}x].{format}`
+    Sprite {
+        style: String,
+        sprite_id: Option<String>,
+        file: SpriteFile,
+    },
+}
+
+impl LocalResource {
+    /// Parse the path of a request of `kind`, without query string
+    fn parse(kind: u8, path: &str) -> Option<Self> {
+        let segments: Vec<&str> = path.strip_prefix('/')?.split('/').collect();
+        match (kind, segments.as_slice()) {
+            (resource_kind::TILE, ["data", source, z, x, y_format]) => {
+                let (y, _format) = y_format.split_once('.')?;
+                Some(LocalResource::Tile {
+                    source: source.to_string(),
+                    z: z.parse().ok()?,
+                    x: x.parse().ok()?,
+                    y: y.parse().ok()?,
+                })
+            }
+            (resource_kind::GLYPHS, ["fonts", fontstack, range]) => Some(LocalResource::Glyphs {
+                fontstack: urlencoding::decode(fontstack).ok()?.into_owned(),
+                range: range.to_string(),
+            }),
+            (
+                resource_kind::SPRITE_IMAGE | resource_kind::SPRITE_JSON,
+                ["styles", style, rest @ ..],
+            ) => {
+                let (sprite_id, file) = match rest {
+                    [file] => (None, file),
+                    [sprite_id, file] => (Some(sprite_id.to_string()), file),
+                    _ => return None,
+                };
+                Some(LocalResource::Sprite {
+                    style: style.to_string(),
+                    sprite_id,
+                    file: SpriteFile::parse(file)?,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Parse the `{source}/{z}/{x}/{y}` after [`LOCAL_TILES`]
+    fn parse_local_tile(kind: u8, path: &str) -> Option<Self> {
+        let path = path.split(['?', '#']).next().unwrap_or_default();
+        match (kind, path.split('/').collect::<Vec<_>>().as_slice()) {
+            (resource_kind::TILE, [source, z, x, y]) => Some(LocalResource::Tile {
+                source: source.to_string(),
+                z: z.parse().ok()?,
+                x: x.parse().ok()?,
+                y: y.parse().ok()?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Glyph sources, as configured for `/fonts`
+struct Fonts {
+    dir: PathBuf,
+    catalog: Arc<FontCatalog>,
+    cache: Arc<GlyphCache>,
+}
+
+/// Loads the resources of native renders in-process
+pub struct ResourceLoader {
+    runtime: Handle,
+    /// URL prefix of this server's resources in rewritten styles
+    base_url: String,
+    sources: SharedSources,
+    styles: SharedStyles,
+    fonts: Option<Fonts>,
+    client: reqwest::Client,
+}
+
+impl std::fmt::Debug for ResourceLoader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResourceLoader")
+            .field("base_url", &self.base_url)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ResourceLoader {
+    /// Loader for the resources of this server at `base_url`
+    pub fn new(
+        runtime: Handle,
+        base_url: &str,
+        sources: SharedSources,
+        styles: SharedStyles,
+    ) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .user_agent("tileserver-rs/0.1.0")
+            .timeout(FETCH_TIMEOUT)
+            .build()
+            .map_err(|e| {
+                TileServerError::ConfigError(format!("Failed to create HTTP client: {}", e))
+            })?;
+        Ok(Self {
+            runtime,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            sources,
+            styles,
+            fonts: None,
+            client,
+        })
+    }
+
+    /// Serve glyphs from the fonts directory, as `/fonts` does
+    pub fn with_fonts(
+        mut self,
+        dir: PathBuf,
+        catalog: Arc<FontCatalog>,
+        cache: Arc<GlyphCache>,
+    ) -> Self {
+        self.fonts = Some(Fonts {
+            dir,
+            catalog,
+            cache,
+        });
+        self
+    }
+
+    /// Load the resource at `url`, of a `resource_kind`
+    ///
+    /// Blocks the calling thread, which must not be a runtime worker.
+    pub fn load(&self, url: &str, kind: u8) -> Resource {
+        let local = match url.strip_prefix(LOCAL_TILES) {
+            Some(path) => LocalResource::parse_local_tile(kind, path),
+            None => url
+                .strip_prefix(&self.base_url)
+                .map(|path| path.split(['?', '#']).next().unwrap_or_default())
+                .and_then(|path| LocalResource::parse(kind, path)),
+        };
+        match local {
+            Some(resource) => self.runtime.block_on(self.load_local(resource)),
+            None if url.starts_with("http://") || url.starts_with("https://") => {
+                self.runtime.block_on(self.fetch(url))
+            }
+            None => match self.styles.inline_sprites().read(url) {
+                Some(data) => Resource::from_result(data.map(|data| Some(Bytes::from(data)))),
+                None => Resource::Error(format!("Unsupported resource URL: {}", url)),
+            },
+        }
+    }
+
+    async fn load_local(&self, resource: LocalResource) -> Resource {
+        match resource {
+            LocalResource::Tile { source, z, x, y } => {
+                let sources = self.sources.load();
+                let tile = sources.get_tile(&source, z, x, y).await;
+                // MapLibre expects tile data as HTTP clients hand it over,
+                // without the transfer encoding
+                Resource::from_result(tile.and_then(|tile| {
+                    tile.map(|tile| match tile.compression {
+                        TileCompression::None => Ok(tile.data),
+                        _ => tile.decompress().map(Bytes::from),
+                    })
+                    .transpose()
+                }))
+            }
+            LocalResource::Glyphs { fontstack, range } => {
+                let Some(fonts) = &self.fonts else {
+                    return Resource::NotFound;
+                };
+                let glyphs = fonts::load_glyphs(
+                    &fonts.dir,
+                    &fonts.catalog,
+                    &fontstack,
+                    &range,
+                    &fonts.cache,
+                )
+                .await;
+                Resource::from_result(glyphs.map(Some))
+            }
+            LocalResource::Sprite {
+                style,
+                sprite_id,
+                file,
+            } => {
+                let styles = self.styles.load();
+                let data = match styles.get(&style) {
+                    Some(style) => match style.sprite_dir(sprite_id.as_deref()) {
+                        Ok(dir) => sprite::read_sprite(&dir, file).await,
+                        Err(e) => Err(e),
+                    },
+                    None => Err(TileServerError::StyleNotFound(style)),
+                };
+                Resource::from_result(data.map(|data| Some(Bytes::from(data))))
+            }
+        }
+    }
+
+    async fn fetch(&self, url: &str) -> Resource {
+        let response = match self.client.get(url).send().await {
+            Ok(response) => response,
+            Err(e) => return Resource::Error(format!("Failed to fetch {}: {}", url, e)),
+        };
+        match response.status() {
+            StatusCode::NOT_FOUND => Resource::NotFound,
+            status if !status.is_success() => {
+                Resource::Error(format!("Failed to fetch {}: HTTP {}", url, status))
+            }
+            _ => match response.bytes().await {
+                Ok(data) => Resource::Data(data),
+                Err(e) => Resource::Error(format!("Failed to read {}: {}", url, e)),
+            },
+        }
+    }
+}
+
+/// Base URL of [`test_loader`]
+#[cfg(test)]
+pub(super) const BASE_URL: &str = "http://localhost:8080";
+
+/// Loader over the test config, with the runtime it blocks on
+#[cfg(test)]
+pub(super) fn test_loader() -> (tokio::runtime::Runtime, ResourceLoader) {
+    use crate::config::Config;
+    use crate::sources::SourceManager;
+    use crate::styles::StyleManager;
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let config = Config::load(Some("tests/config.test.toml".into())).unwrap();
+    let sources = runtime
+        .block_on(SourceManager::from_configs(&config.sources))
+        .unwrap();
+    let styles = StyleManager::from_configs(&config.styles).unwrap();
+    let loader = ResourceLoader::new(
+        runtime.handle().clone(),
+        BASE_URL,
+        SharedSources::new(sources),
+        SharedStyles::new(styles),
+    )
+    .unwrap();
+    (runtime, loader)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_local_resources() {
+        assert_eq!(
+            LocalResource::parse(resource_kind::TILE, "/data/zurich/14/8580/5737.pbf"),
+            Some(LocalResource::Tile {
+                source: "zurich".to_string(),
+                z: 14,
+                x: 8580,
+                y: 5737
+            })
+        );
+        assert_eq!(
+            LocalResource::parse(
+                resource_kind::GLYPHS,
+                "/fonts/Noto%20Sans%20Regular,Arial/0-255.pbf"
+            ),
+            Some(LocalResource::Glyphs {
+                fontstack: "Noto Sans Regular,Arial".to_string(),
+                range: "0-255.pbf".to_string()
+            })
+        );
+        assert_eq!(
+            LocalResource::parse(
+                resource_kind::SPRITE_IMAGE,
+                "/styles/basic/icons/sprite@2x.png"
+            ),
+            Some(LocalResource::Sprite {
+                style: "basic".to_string(),
+                sprite_id: Some("icons".to_string()),
+                file: SpriteFile::parse("sprite@2x.png").unwrap()
+            })
+        );
+
+        // The kind has to match, and TileJSON has no shortcut
+        assert_eq!(
+            LocalResource::parse(resource_kind::GLYPHS, "/data/zurich/14/8580/5737.pbf"),
+            None
+        );
+        assert_eq!(
+            LocalResource::parse(resource_kind::SOURCE, "/data/zurich.json"),
+            None
+        );
+        assert_eq!(
+            LocalResource::parse(resource_kind::TILE, "/data/zurich/14/x/5737.pbf"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_load_tiles_in_process() {
+        let (runtime, loader) = test_loader();
+        let sources = loader.sources.load();
+        let stored = runtime
+            .block_on(sources.get_tile("zurich", 0, 0, 0))
+            .unwrap()
+            .unwrap();
+
+        let url = format!("{}/data/zurich/0/0/0.pbf?key=abc", BASE_URL);
+        let Resource::Data(data) = loader.load(&url, resource_kind::TILE) else {
+            panic!("tile was not loaded");
+        };
+        assert_eq!(data, stored.decompress().unwrap());
+        // Rewritten styles name the tiles of in-process renders without a host
+        assert_eq!(
+            loader.load("pmtiles://zurich/0/0/0", resource_kind::TILE),
+            Resource::Data(data)
+        );
+
+        // Missing tiles and sources are 404s over HTTP too
+        let missing = format!("{}/data/zurich/14/0/0.pbf", BASE_URL);
+        assert_eq!(
+            loader.load(&missing, resource_kind::TILE),
+            Resource::NotFound
+        );
+        let unknown = format!("{}/data/unknown/0/0/0.pbf", BASE_URL);
+        assert_eq!(
+            loader.load(&unknown, resource_kind::TILE),
+            Resource::NotFound
+        );
+        assert_eq!(
+            loader.load("pmtiles://unknown/0/0/0", resource_kind::TILE),
+            Resource::NotFound
+        );
+
+        // Anything else under the scheme, such as archives, isn't served
+        let archive = "pmtiles://https://example.com/planet.pmtiles";
+        assert!(matches!(
+            loader.load(archive, resource_kind::SOURCE),
+            Resource::Error(_)
+        ));
+    }
+
+    #[test]
+    fn test_load_tiles_of_rewritten_styles() {
+        use crate::config::StyleRewriteConfig;
+        use crate::styles::rewrite_style_for_native;
+
+        let (_runtime, loader) = test_loader();
+        let style = serde_json::json!({
+            "version": 8,
+            "sources": { "zurich": { "type": "vector", "url": "/data/zurich.json" } },
+            "layers": []
+        });
+        let rewrite = StyleRewriteConfig {
+            local_tiles: true,
+            ..Default::default()
+        };
+        let native = rewrite_style_for_native(
+            &style,
+            "test",
+            BASE_URL,
+            &loader.sources.load(),
+            &rewrite,
+            loader.styles.inline_sprites(),
+        );
+
+        let template = native["sources"]["zurich"]["tiles"][0].as_str().unwrap();
+        assert_eq!(template, "pmtiles://zurich/{z}/{x}/{y}");
+        let url = template
+            .replace("{z}", "0")
+            .replace("{x}", "0")
+            .replace("{y}", "0");
+        assert!(matches!(
+            loader.load(&url, resource_kind::TILE),
+            Resource::Data(_)
+        ));
+    }
+
+    #[test]
+    fn test_load_other_resources() {
+        let (_runtime, loader) = test_loader();
+
+        // Glyphs without a fonts directory are missing, as from `/fonts`
+        let glyphs = format!("{}/fonts/Noto%20Sans/0-255.pbf", BASE_URL);
+        assert_eq!(
+            loader.load(&glyphs, resource_kind::GLYPHS),
+            Resource::NotFound
+        );
+
+        // Sprite IDs are checked as by `/styles`
+        let sprite = format!("{}/styles/protomaps-light/../sprite.json", BASE_URL);
+        assert_eq!(
+            loader.load(&sprite, resource_kind::SPRITE_JSON),
+            Resource::NotFound
+        );
+        let sprite = format!("{}/styles/unknown/sprite.json", BASE_URL);
+        assert_eq!(
+            loader.load(&sprite, resource_kind::SPRITE_JSON),
+            Resource::NotFound
+        );
+
+        let Resource::Error(message) = loader.load("file:///etc/passwd", resource_kind::STYLE)
+        else {
+            panic!("file URL was loaded");
+        };
+        assert!(message.contains("Unsupported"), "{}", message);
+    }
+
+    #[test]
+    fn test_load_inline_sprites() {
+        use base64::Engine;
+
+        let (_runtime, loader) = test_loader();
+        let mut png = Vec::new();
+        image::RgbaImage::new(2, 2)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let mut sprite = serde_json::json!(format!(
+            "data:image/png;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(&png)
+        ));
+        loader.styles.inline_sprites().extract("test", &mut sprite);
+        let url = sprite.as_str().unwrap();
+
+        assert_eq!(
+            loader.load(&format!("{}@2x.png", url), resource_kind::SPRITE_IMAGE),
+            Resource::Data(Bytes::from(png))
+        );
+        assert!(matches!(
+            loader.load(&format!("{}.json", url), resource_kind::SPRITE_JSON),
+            Resource::Data(_)
+        ));
+    }
+}
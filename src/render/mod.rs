@@ -1,8 +1,17 @@
+pub mod coalesce;
+pub mod loader;
 mod native;
 pub mod overlay;
 mod pool;
 mod renderer;
+pub mod solid;
 mod types;
 
+pub use native::{DebugOptions, TileView};
+pub use pool::PoolConfig;
 pub use renderer::Renderer;
-pub use types::{ImageFormat, RenderOptions, StaticQueryParams, StaticType};
+pub use solid::SolidTileCache;
+pub use types::{
+    output_size, parse_static_size, ImageFormat, RenderOptions, StaticQueryParams,
+    StaticRenderRequest, StaticType,
+};
@@ -2,24 +2,58 @@
 //!
 //! This module provides safe Rust wrappers around the MapLibre Native C API.
 //! It is designed for server-side rendering of map tiles and static images.
-
-use std::ffi::{CStr, CString};
-use std::ptr;
-use std::sync::Once;
+//!
+//! All `unsafe` code of the renderer lives here. The wrappers uphold these
+//! invariants for every call into the C API:
+//!
+//! - [`HeadlessFrontend`] and [`NativeMap`] hold non-null pointers returned by
+//!   their `mln_*_create` function and destroy them exactly once, in `Drop`.
+//!   A map keeps its frontend alive and is destroyed before it.
+//! - Images returned by `mln_map_render_still` are owned by [`NativeImage`],
+//!   which frees them exactly once, whether or not their pixels were copied.
+//!   So are the images handed to the callback of `mln_map_render_still_async`.
+//! - The `user_data` of an async render is a boxed [`PendingRender`] that
+//!   only [`render_callback`] turns back into a box, once. A map dropped with
+//!   renders pending fails them, and a callback arriving after that finds
+//!   the slot empty instead of freed memory; a callback that never arrives
+//!   leaks the box rather than risk a use after free.
+//! - The `user_data` of a map's resource callback is its boxed
+//!   [`LoaderState`], which the map drops only after destroying itself. The
+//!   data and error handed back by [`resource_callback`] stay in that state
+//!   until the next request of the map, as the C API requires.
+//! - Strings passed to C are `CString`s that outlive the call; strings from C
+//!   (`mln_get_last_error`) are copied before the next call can replace them.
+//! - The C library gives each thread its own RunLoop and binds a map to the
+//!   thread that created it, so frontends and maps are neither `Send` nor
+//!   `Sync`: they are created, used and dropped on one render worker.
+
+use std::ffi::{c_void, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr::{self, NonNull};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Once, PoisonError};
+
+use bytes::Bytes;
+use tokio::sync::oneshot;
 
 use maplibre_native_sys::{
     mln_cleanup, mln_get_last_error, mln_headless_frontend_create, mln_headless_frontend_destroy,
     mln_headless_frontend_set_size, mln_image_free, mln_init, mln_map_create,
     mln_map_create_with_loader, mln_map_destroy, mln_map_is_fully_loaded, mln_map_load_style,
-    mln_map_render_still, mln_map_set_camera, mln_map_set_size, MLNCameraOptions, MLNErrorCode,
-    MLNHeadlessFrontend, MLNImageData, MLNMap, MLNMapMode, MLNRenderOptions, MLNResourceCallback,
+    mln_map_render_still, mln_map_render_still_async, mln_map_set_camera, mln_map_set_size,
+    MLNCameraOptions, MLNDebugOptions, MLNErrorCode, MLNHeadlessFrontend, MLNImageData, MLNMap,
+    MLNMapMode, MLNRenderOptions, MLNResourceCallback, MLNResourceRequest, MLNResourceResponse,
     MLNSize,
 };
 
+use super::loader::{Resource, ResourceLoader};
 use crate::error::{Result, TileServerError};
 
+/// Size of MapLibre's own tiles; a tile of this size at zoom z covers tile z
+const MAPLIBRE_TILE_SIZE: u32 = 512;
+
 static INIT: Once = Once::new();
-static mut INITIALIZED: bool = false;
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
 
 /// Initialize the MapLibre Native library.
 /// This is called automatically when needed but can be called explicitly.
@@ -27,15 +61,10 @@ pub fn init() -> Result<()> {
     let mut result = Ok(());
 
     INIT.call_once(|| {
+        // SAFETY: `mln_init` takes no arguments and `Once` runs it a single time.
         let code = unsafe { mln_init() };
-        if code != MLNErrorCode::MLN_OK {
-            result = Err(TileServerError::RenderError(format!(
-                "Failed to initialize MapLibre Native: {:?}",
-                get_last_error()
-            )));
-        } else {
-            unsafe { INITIALIZED = true };
-        }
+        result = check(code, "Failed to initialize MapLibre Native");
+        INITIALIZED.store(result.is_ok(), Ordering::SeqCst);
     });
 
     result
@@ -45,16 +74,17 @@ pub fn init() -> Result<()> {
 /// Should be called when shutting down the application.
 #[allow(dead_code)]
 pub fn cleanup() {
-    unsafe {
-        if INITIALIZED {
-            mln_cleanup();
-            INITIALIZED = false;
-        }
+    if INITIALIZED.swap(false, Ordering::SeqCst) {
+        // SAFETY: the library was initialized, and the flag makes sure it is
+        // cleaned up at most once.
+        unsafe { mln_cleanup() };
     }
 }
 
-/// Get the last error message from MapLibre Native.
-fn get_last_error() -> Option<String> {
+/// Last error message of MapLibre Native on this thread, copied
+fn last_error() -> Option<String> {
+    // SAFETY: the C API returns NULL or a NUL-terminated thread-local buffer,
+    // which is copied here before any other call can overwrite it.
     unsafe {
         let ptr = mln_get_last_error();
         if ptr.is_null() {
@@ -65,6 +95,36 @@ fn get_last_error() -> Option<String> {
     }
 }
 
+/// `Ok` for `MLN_OK`, otherwise the error of a failed call, `what`
+/// describing the call
+///
+/// Timeouts become [`TileServerError::Timeout`], everything else a render
+/// error carrying the library's last error message.
+fn check(code: MLNErrorCode, what: &str) -> Result<()> {
+    if code == MLNErrorCode::MLN_OK {
+        return Ok(());
+    }
+    let message = match last_error() {
+        Some(error) => format!("{}: {}", what, error),
+        None => format!("{}: {:?}", what, code),
+    };
+    Err(match code {
+        MLNErrorCode::MLN_ERROR_TIMEOUT => TileServerError::Timeout(message),
+        _ => TileServerError::RenderError(message),
+    })
+}
+
+/// `ptr` as a non-null pointer, or the library's last error when creating it
+/// failed
+fn created<T>(ptr: *mut T, what: &str) -> Result<NonNull<T>> {
+    NonNull::new(ptr).ok_or_else(|| {
+        TileServerError::RenderError(match last_error() {
+            Some(error) => format!("{}: {}", what, error),
+            None => what.to_string(),
+        })
+    })
+}
+
 /// Size of a render target
 #[derive(Debug, Clone, Copy, Default)]
 pub struct Size {
@@ -171,6 +231,58 @@ impl From<MapMode> for MLNMapMode {
     }
 }
 
+bitflags::bitflags! {
+    /// Debug overlays MapLibre draws over a render
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+    pub struct DebugOptions: u32 {
+        const TILE_BORDERS = MLNDebugOptions::MLN_DEBUG_TILE_BORDERS.bits();
+        const PARSE_STATUS = MLNDebugOptions::MLN_DEBUG_PARSE_STATUS.bits();
+        const TIMESTAMPS = MLNDebugOptions::MLN_DEBUG_TIMESTAMPS.bits();
+        const COLLISION = MLNDebugOptions::MLN_DEBUG_COLLISION.bits();
+        const OVERDRAW = MLNDebugOptions::MLN_DEBUG_OVERDRAW.bits();
+    }
+}
+
+impl From<DebugOptions> for MLNDebugOptions {
+    fn from(debug: DebugOptions) -> Self {
+        MLNDebugOptions::from_bits_truncate(debug.bits())
+    }
+}
+
+/// Rotation and tilt of a rendered tile, north-up and flat by default
+///
+/// Compared and hashed by the bits of its angles, so it can key renders.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TileView {
+    /// Degrees clockwise from north
+    pub bearing: f64,
+    /// Degrees away from looking straight down
+    pub pitch: f64,
+}
+
+impl TileView {
+    /// Whether the tile is rendered north-up and flat
+    pub fn is_default(&self) -> bool {
+        self.bearing == 0.0 && self.pitch == 0.0
+    }
+}
+
+impl PartialEq for TileView {
+    fn eq(&self, other: &Self) -> bool {
+        self.bearing.to_bits() == other.bearing.to_bits()
+            && self.pitch.to_bits() == other.pitch.to_bits()
+    }
+}
+
+impl Eq for TileView {}
+
+impl std::hash::Hash for TileView {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.bearing.to_bits().hash(state);
+        self.pitch.to_bits().hash(state);
+    }
+}
+
 /// Rendered image data
 pub struct RenderedImage {
     data: Vec<u8>,
@@ -287,26 +399,19 @@ impl RenderedImage {
 
 /// Headless frontend for map rendering
 pub struct HeadlessFrontend {
-    ptr: *mut MLNHeadlessFrontend,
+    ptr: NonNull<MLNHeadlessFrontend>,
 }
 
-// Safety: HeadlessFrontend is thread-safe as long as each instance is only
-// used from one thread at a time. The MapLibre Native library handles
-// internal synchronization.
-unsafe impl Send for HeadlessFrontend {}
-
 impl HeadlessFrontend {
     /// Create a new headless frontend
     pub fn new(size: Size, pixel_ratio: f32) -> Result<Self> {
         init()?;
 
+        // SAFETY: plain values in; the result is checked for NULL below.
         let ptr = unsafe { mln_headless_frontend_create(size.into(), pixel_ratio) };
-
-        if ptr.is_null() {
-            return Err(TileServerError::RenderError(
-                get_last_error().unwrap_or_else(|| "Failed to create frontend".to_string()),
-            ));
-        }
+        let ptr = created(ptr, "Failed to create frontend")?;
+        #[cfg(test)]
+        live::add(live::Kind::Frontend, 1);
 
         Ok(Self { ptr })
     }
@@ -314,58 +419,73 @@ impl HeadlessFrontend {
     /// Set the size of the render target
     #[allow(dead_code)]
     pub fn set_size(&mut self, size: Size) {
-        unsafe {
-            mln_headless_frontend_set_size(self.ptr, size.into());
-        }
-    }
-
-    /// Get the raw pointer (for internal use)
-    pub(crate) fn as_ptr(&self) -> *mut MLNHeadlessFrontend {
-        self.ptr
+        // SAFETY: `ptr` is a live frontend owned by `self`.
+        unsafe { mln_headless_frontend_set_size(self.ptr.as_ptr(), size.into()) };
     }
 }
 
 impl Drop for HeadlessFrontend {
     fn drop(&mut self) {
-        if !self.ptr.is_null() {
-            unsafe {
-                mln_headless_frontend_destroy(self.ptr);
-            }
-        }
+        // SAFETY: `ptr` came from `mln_headless_frontend_create` and is
+        // destroyed only here; maps using it are dropped before it.
+        unsafe { mln_headless_frontend_destroy(self.ptr.as_ptr()) };
+        #[cfg(test)]
+        live::add(live::Kind::Frontend, -1);
     }
 }
 
 /// A MapLibre map instance for rendering
 pub struct NativeMap {
-    ptr: *mut MLNMap,
-    _frontend: HeadlessFrontend, // Keep frontend alive
+    ptr: NonNull<MLNMap>,
+    /// Async renders the library has not called back for yet
+    pending: Vec<PendingRender>,
+    /// Kept alive for the map; dropped after `Drop` destroyed the map
+    _frontend: HeadlessFrontend,
+    /// `user_data` of the map's resource callback, dropped like `_frontend`
+    _loader: Option<Box<LoaderState>>,
 }
 
-// Safety: Same as HeadlessFrontend
-unsafe impl Send for NativeMap {}
-
 impl NativeMap {
     /// Create a new map instance
     pub fn new(size: Size, pixel_ratio: f32, mode: MapMode) -> Result<Self> {
         let frontend = HeadlessFrontend::new(size, pixel_ratio)?;
 
-        let ptr = unsafe { mln_map_create(frontend.as_ptr(), pixel_ratio, mode.into()) };
-
-        if ptr.is_null() {
-            return Err(TileServerError::RenderError(
-                get_last_error().unwrap_or_else(|| "Failed to create map".to_string()),
-            ));
-        }
+        // SAFETY: `frontend` is live and outlives the map, which owns it.
+        let ptr = unsafe { mln_map_create(frontend.ptr.as_ptr(), pixel_ratio, mode.into()) };
+        Self::from_created(ptr, frontend, "Failed to create map")
+    }
 
-        Ok(Self {
-            ptr,
-            _frontend: frontend,
-        })
+    /// Create a new map instance that loads its resources through `loader`
+    /// instead of over HTTP
+    pub fn with_loader(
+        size: Size,
+        pixel_ratio: f32,
+        mode: MapMode,
+        loader: Arc<ResourceLoader>,
+    ) -> Result<Self> {
+        let state = Box::new(LoaderState {
+            loader,
+            last: Mutex::default(),
+        });
+        let user_data = ptr::addr_of!(*state).cast_mut().cast::<c_void>();
+
+        // SAFETY: `resource_callback` only reads `user_data` as a shared
+        // `LoaderState`, which the map keeps boxed until it is destroyed.
+        let mut map = unsafe {
+            Self::with_resource_loader(size, pixel_ratio, mode, Some(resource_callback), user_data)?
+        };
+        map._loader = Some(state);
+        Ok(map)
     }
 
     /// Create a new map instance with a custom resource loader callback
-    #[allow(dead_code)]
-    pub fn with_resource_loader(
+    ///
+    /// # Safety
+    ///
+    /// `callback` is called with `user_data` from this thread while the map
+    /// loads resources, so `user_data` must be valid for what `callback` does
+    /// with it until the map is dropped.
+    pub unsafe fn with_resource_loader(
         size: Size,
         pixel_ratio: f32,
         mode: MapMode,
@@ -374,25 +494,29 @@ impl NativeMap {
     ) -> Result<Self> {
         let frontend = HeadlessFrontend::new(size, pixel_ratio)?;
 
+        // SAFETY: as for `new`; the caller vouches for `callback` and
+        // `user_data`.
         let ptr = unsafe {
             mln_map_create_with_loader(
-                frontend.as_ptr(),
+                frontend.ptr.as_ptr(),
                 pixel_ratio,
                 mode.into(),
                 callback,
                 user_data,
             )
         };
+        Self::from_created(ptr, frontend, "Failed to create map with loader")
+    }
 
-        if ptr.is_null() {
-            return Err(TileServerError::RenderError(
-                get_last_error().unwrap_or_else(|| "Failed to create map with loader".to_string()),
-            ));
-        }
-
+    fn from_created(ptr: *mut MLNMap, frontend: HeadlessFrontend, what: &str) -> Result<Self> {
+        let ptr = created(ptr, what)?;
+        #[cfg(test)]
+        live::add(live::Kind::Map, 1);
         Ok(Self {
             ptr,
+            pending: Vec::new(),
             _frontend: frontend,
+            _loader: None,
         })
     }
 
@@ -402,89 +526,94 @@ impl NativeMap {
             TileServerError::RenderError("Style JSON contains null bytes".to_string())
         })?;
 
-        let code = unsafe { mln_map_load_style(self.ptr, c_style.as_ptr()) };
-
-        if code != MLNErrorCode::MLN_OK {
-            return Err(TileServerError::RenderError(
-                get_last_error().unwrap_or_else(|| format!("Failed to load style: {:?}", code)),
-            ));
-        }
-
-        Ok(())
+        // SAFETY: `ptr` is live, and `c_style` outlives the call, which
+        // copies it.
+        let code = unsafe { mln_map_load_style(self.ptr.as_ptr(), c_style.as_ptr()) };
+        check(code, "Failed to load style")
     }
 
     /// Check if the map is fully loaded
     #[allow(dead_code)]
     pub fn is_fully_loaded(&self) -> bool {
-        unsafe { mln_map_is_fully_loaded(self.ptr) }
+        // SAFETY: `ptr` is live.
+        unsafe { mln_map_is_fully_loaded(self.ptr.as_ptr()) }
     }
 
     /// Set the camera options
     #[allow(dead_code)]
     pub fn set_camera(&mut self, camera: CameraOptions) {
         let c_camera: MLNCameraOptions = camera.into();
-        unsafe {
-            mln_map_set_camera(self.ptr, &c_camera);
-        }
+        // SAFETY: `ptr` is live and `c_camera` is read during the call only.
+        unsafe { mln_map_set_camera(self.ptr.as_ptr(), &c_camera) };
     }
 
     /// Set the map size
     #[allow(dead_code)]
     pub fn set_size(&mut self, size: Size) {
-        unsafe {
-            mln_map_set_size(self.ptr, size.into());
-        }
+        // SAFETY: `ptr` is live.
+        unsafe { mln_map_set_size(self.ptr.as_ptr(), size.into()) };
     }
 
     /// Render a still image synchronously
     pub fn render(&mut self, options: Option<RenderOptions>) -> Result<RenderedImage> {
-        let mut image_data = MLNImageData::default();
-
-        let c_options = options.map(|o| o.into_native());
+        let c_options = options.map(RenderOptions::into_native);
+        let mut image = NativeImage::empty();
 
+        // SAFETY: `ptr` is live, the options are NULL or read during the call
+        // only, and `image` takes ownership of the returned pixels.
         let code = unsafe {
             mln_map_render_still(
-                self.ptr,
+                self.ptr.as_ptr(),
                 c_options
                     .as_ref()
-                    .map(|o| o as *const MLNRenderOptions)
-                    .unwrap_or(ptr::null()),
-                &mut image_data,
+                    .map_or(ptr::null(), |o| o as *const MLNRenderOptions),
+                image.as_mut_ptr(),
             )
         };
-
-        if code != MLNErrorCode::MLN_OK {
-            return Err(TileServerError::RenderError(
-                get_last_error().unwrap_or_else(|| format!("Render failed: {:?}", code)),
-            ));
+        #[cfg(test)]
+        if !image.data.data.is_null() {
+            live::add(live::Kind::Image, 1);
         }
+        check(code, "Render failed")?;
 
-        // Save dimensions before freeing (mln_image_free zeros them)
-        let width = image_data.width;
-        let height = image_data.height;
-
-        // Copy the data and free the native buffer
-        let data = if !image_data.data.is_null() && image_data.data_len > 0 {
-            let slice = unsafe { std::slice::from_raw_parts(image_data.data, image_data.data_len) };
-            let data = slice.to_vec();
-
-            unsafe {
-                mln_image_free(&mut image_data);
-            }
+        Ok(image.to_rendered())
+    }
 
-            data
-        } else {
-            Vec::new()
+    /// Start rendering a still image through `mln_map_render_still_async`
+    ///
+    /// The image arrives on the returned receiver once the library calls
+    /// back, exactly once. Dropping the receiver cancels nothing: the image
+    /// is still freed when it arrives. Dropping the map first fails the
+    /// render with an error.
+    pub fn render_async(&mut self, options: RenderOptions) -> RenderReceiver {
+        let (sender, receiver) = oneshot::channel();
+        let pending: PendingRender = Arc::new(Mutex::new(Some(sender)));
+        self.pending.retain(|render| {
+            render
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .is_some()
+        });
+        self.pending.push(Arc::clone(&pending));
+
+        let c_options = options.into_native();
+        let user_data = Box::into_raw(Box::new(pending)).cast::<c_void>();
+        // SAFETY: `ptr` is live and the options are read during the call
+        // only. `user_data` is a leaked `Box<PendingRender>` that
+        // `render_callback` reclaims when the library calls back.
+        unsafe {
+            mln_map_render_still_async(
+                self.ptr.as_ptr(),
+                &c_options,
+                Some(render_callback),
+                user_data,
+            )
         };
-
-        Ok(RenderedImage {
-            data,
-            width,
-            height,
-        })
+        receiver
     }
 
     /// Render a tile at the given coordinates
+    #[allow(clippy::too_many_arguments)]
     pub fn render_tile(
         &mut self,
         z: u8,
@@ -492,6 +621,8 @@ impl NativeMap {
         y: u32,
         tile_size: u32,
         pixel_ratio: f32,
+        debug: DebugOptions,
+        view: TileView,
     ) -> Result<RenderedImage> {
         // Calculate center of tile
         let n = 2_f64.powi(z as i32);
@@ -501,24 +632,152 @@ impl NativeMap {
             .atan();
         let lat = lat_rad.to_degrees();
 
-        let options = RenderOptions {
-            size: Size::new(tile_size, tile_size),
-            pixel_ratio,
-            camera: CameraOptions::new(lat, lon, z as f64),
-            mode: MapMode::Tile,
-        };
+        let options = RenderOptions::new(Size::new(tile_size, tile_size))
+            .with_pixel_ratio(pixel_ratio)
+            .with_camera(
+                CameraOptions::new(lat, lon, tile_zoom(z, tile_size))
+                    .with_bearing(view.bearing)
+                    .with_pitch(view.pitch),
+            )
+            .with_mode(MapMode::Tile)
+            .with_debug(debug);
 
         self.render(Some(options))
     }
 }
 
+/// Map zoom at which a `tile_size` pixel viewport covers a tile of zoom `z`
+fn tile_zoom(z: u8, tile_size: u32) -> f64 {
+    z as f64 + (tile_size as f64 / MAPLIBRE_TILE_SIZE as f64).log2()
+}
+
+/// Viewport size and pixel ratio for rendering a `tile_size` pixel tile of
+/// zoom `z`
+///
+/// Tiles smaller than 512px are shown at a lower zoom; where that would be
+/// below zoom 0, the tile is drawn at 512px and scaled down with the pixel
+/// ratio instead.
+pub fn tile_viewport(z: u8, tile_size: u32, pixel_ratio: f32) -> (u32, f32) {
+    if tile_zoom(z, tile_size) >= 0.0 {
+        (tile_size, pixel_ratio)
+    } else {
+        let ratio = pixel_ratio * tile_size as f32 / MAPLIBRE_TILE_SIZE as f32;
+        (MAPLIBRE_TILE_SIZE, ratio)
+    }
+}
+
 impl Drop for NativeMap {
     fn drop(&mut self) {
-        if !self.ptr.is_null() {
-            unsafe {
-                mln_map_destroy(self.ptr);
+        for render in self.pending.drain(..) {
+            let sender = render.lock().unwrap_or_else(PoisonError::into_inner).take();
+            if let Some(sender) = sender {
+                let _ = sender.send(Err(TileServerError::RenderError(
+                    "Map dropped before the render finished".to_string(),
+                )));
             }
         }
+        // SAFETY: `ptr` came from `mln_map_create*` and is destroyed only
+        // here, before `_frontend` and `_loader` are dropped with the other
+        // fields.
+        unsafe { mln_map_destroy(self.ptr.as_ptr()) };
+        #[cfg(test)]
+        live::add(live::Kind::Map, -1);
+    }
+}
+
+/// Result of [`NativeMap::render_async`]
+pub type RenderReceiver = oneshot::Receiver<Result<RenderedImage>>;
+
+/// Where an async render delivers its image, until it has or its map gave
+/// up on it
+type PendingRender = Arc<Mutex<Option<oneshot::Sender<Result<RenderedImage>>>>>;
+
+/// `MLNRenderCallback` of [`NativeMap::render_async`]
+///
+/// Takes ownership of `image`, copies and frees its pixels, and sends the
+/// result if the map still waits for it. Never unwinds into C.
+unsafe extern "C" fn render_callback(
+    code: MLNErrorCode,
+    image: *mut MLNImageData,
+    user_data: *mut c_void,
+) {
+    if user_data.is_null() {
+        return;
+    }
+    // SAFETY: `user_data` is the `Box<PendingRender>` leaked by
+    // `render_async`, and the library calls back once per render.
+    let pending = unsafe { Box::from_raw(user_data.cast::<PendingRender>()) };
+    // SAFETY: a non-null `image` holds pixels this callback now owns; the
+    // struct is copied so `NativeImage` frees them once.
+    let image = (!image.is_null()).then(|| NativeImage {
+        data: unsafe { ptr::read(image) },
+    });
+    #[cfg(test)]
+    if image
+        .as_ref()
+        .is_some_and(|image| !image.data.data.is_null())
+    {
+        live::add(live::Kind::Image, 1);
+    }
+
+    let result = check(code, "Render failed").and_then(|()| {
+        image
+            .as_ref()
+            .map(NativeImage::to_rendered)
+            .ok_or_else(|| TileServerError::RenderError("Render returned no image".to_string()))
+    });
+    drop(image);
+
+    let sender = pending
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .take();
+    if let Some(sender) = sender {
+        let _ = sender.send(result);
+    }
+}
+
+/// Pixels returned by `mln_map_render_still`, freed on drop
+struct NativeImage {
+    data: MLNImageData,
+}
+
+impl NativeImage {
+    fn empty() -> Self {
+        Self {
+            data: MLNImageData::default(),
+        }
+    }
+
+    /// Output argument for `mln_map_render_still`
+    fn as_mut_ptr(&mut self) -> *mut MLNImageData {
+        &mut self.data
+    }
+
+    /// Copy of the pixels, owned by Rust
+    fn to_rendered(&self) -> RenderedImage {
+        let data = if self.data.data.is_null() || self.data.data_len == 0 {
+            Vec::new()
+        } else {
+            // SAFETY: the C API returned `data_len` readable bytes at `data`,
+            // which stay valid until `self` frees them.
+            unsafe { std::slice::from_raw_parts(self.data.data, self.data.data_len) }.to_vec()
+        };
+        RenderedImage::from_rgba(self.data.width, self.data.height, data)
+    }
+}
+
+impl Drop for NativeImage {
+    fn drop(&mut self) {
+        if self.data.data.is_null() {
+            return;
+        }
+        // SAFETY: non-null pixels came from `mln_map_render_still` or its
+        // async callback, and
+        // `mln_image_free` sets them to NULL, so they are freed once.
+        unsafe { mln_image_free(&mut self.data) };
+        #[cfg(test)]
+        live::add(live::Kind::Image, -1);
     }
 }
 
@@ -529,40 +788,345 @@ pub struct RenderOptions {
     pub pixel_ratio: f32,
     pub camera: CameraOptions,
     pub mode: MapMode,
+    pub debug: DebugOptions,
 }
 
 impl Default for RenderOptions {
     fn default() -> Self {
+        Self::new(Size::new(512, 512))
+    }
+}
+
+impl RenderOptions {
+    /// Tile-mode options for an image of `size` at pixel ratio 1
+    pub fn new(size: Size) -> Self {
         Self {
-            size: Size::new(512, 512),
+            size,
             pixel_ratio: 1.0,
             camera: CameraOptions::default(),
             mode: MapMode::Tile,
+            debug: DebugOptions::empty(),
         }
     }
-}
 
-impl RenderOptions {
+    pub fn with_pixel_ratio(mut self, pixel_ratio: f32) -> Self {
+        self.pixel_ratio = pixel_ratio;
+        self
+    }
+
+    pub fn with_camera(mut self, camera: CameraOptions) -> Self {
+        self.camera = camera;
+        self
+    }
+
+    pub fn with_mode(mut self, mode: MapMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn with_debug(mut self, debug: DebugOptions) -> Self {
+        self.debug = debug;
+        self
+    }
+
     fn into_native(self) -> MLNRenderOptions {
         MLNRenderOptions {
             size: self.size.into(),
             pixel_ratio: self.pixel_ratio,
             camera: self.camera.into(),
             mode: self.mode.into(),
-            debug: maplibre_native_sys::MLNDebugOptions::MLN_DEBUG_NONE,
+            debug: self.debug.into(),
+        }
+    }
+}
+
+/// Resource loader of a map, handed to the C library as `user_data`
+struct LoaderState {
+    loader: Arc<ResourceLoader>,
+    /// Data and error of the last response, kept until the library has
+    /// copied them
+    last: Mutex<(Bytes, Option<CString>)>,
+}
+
+impl LoaderState {
+    /// Fill `response` with `resource`, which stays alive until the next
+    /// response
+    fn respond(&self, resource: Resource, response: &mut MLNResourceResponse) {
+        let mut last = self.last.lock().unwrap_or_else(PoisonError::into_inner);
+        *response = MLNResourceResponse::default();
+        *last = (Bytes::new(), None);
+        match resource {
+            Resource::Data(data) => {
+                response.data = data.as_ptr();
+                response.data_len = data.len();
+                last.0 = data;
+            }
+            Resource::NotFound => response.not_found = true,
+            Resource::Error(message) => {
+                let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+                response.error = message.as_ptr();
+                last.1 = Some(message);
+            }
         }
     }
 }
 
+/// `MLNResourceCallback` of [`NativeMap::with_loader`]
+///
+/// Loads the requested resource and fills `response` with it. Never unwinds
+/// into C.
+unsafe extern "C" fn resource_callback(
+    request: *const MLNResourceRequest,
+    response: *mut MLNResourceResponse,
+    user_data: *mut c_void,
+) {
+    if request.is_null() || response.is_null() || user_data.is_null() {
+        return;
+    }
+    // SAFETY: `user_data` is the `LoaderState` boxed by `with_loader`, alive
+    // while its map is, and the library passes valid request and response
+    // structs for the duration of the call.
+    let (state, request, response) =
+        unsafe { (&*user_data.cast::<LoaderState>(), &*request, &mut *response) };
+    let url = if request.url.is_null() {
+        String::new()
+    } else {
+        // SAFETY: a non-null URL is a NUL-terminated string for this call.
+        unsafe { CStr::from_ptr(request.url) }
+            .to_string_lossy()
+            .into_owned()
+    };
+
+    let resource = panic::catch_unwind(AssertUnwindSafe(|| state.loader.load(&url, request.kind)))
+        .unwrap_or_else(|_| Resource::Error(format!("Resource loader panicked on {}", url)));
+    state.respond(resource, response);
+}
+
+/// Native objects alive on the current thread, counted in tests to catch
+/// leaks and double frees
+#[cfg(test)]
+mod live {
+    use std::cell::Cell;
+
+    #[derive(Debug, Clone, Copy)]
+    pub enum Kind {
+        Frontend,
+        Map,
+        Image,
+    }
+
+    thread_local! {
+        static COUNTS: Cell<[i64; 3]> = const { Cell::new([0; 3]) };
+    }
+
+    pub fn add(kind: Kind, delta: i64) {
+        COUNTS.with(|counts| {
+            let mut current = counts.get();
+            current[kind as usize] += delta;
+            counts.set(current);
+        });
+    }
+
+    /// Live frontends, maps and images
+    pub fn counts() -> [i64; 3] {
+        COUNTS.with(Cell::get)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use maplibre_native_sys::resource_kind;
 
     #[test]
     fn test_init() {
         assert!(init().is_ok());
     }
 
+    #[test]
+    fn test_tile_viewport() {
+        assert_eq!(tile_zoom(5, 512), 5.0);
+        assert_eq!(tile_zoom(5, 256), 4.0);
+        assert!((tile_zoom(5, 384) - (5.0 + 0.75_f64.log2())).abs() < 1e-9);
+        assert_eq!(tile_viewport(5, 640, 2.0), (640, 2.0));
+        assert_eq!(tile_viewport(3, 256, 1.0), (256, 1.0));
+        // Zoom 0 has no lower zoom to show a small tile at
+        assert_eq!(tile_viewport(0, 256, 1.0), (512, 0.5));
+        assert_eq!(tile_viewport(0, 384, 2.0), (512, 1.5));
+        assert_eq!(tile_viewport(0, 640, 1.0), (640, 1.0));
+    }
+
+    #[test]
+    fn test_create_destroy_cycles() {
+        let start = live::counts();
+        for _ in 0..50 {
+            let mut map = NativeMap::new(Size::new(256, 256), 1.0, MapMode::Tile).unwrap();
+            assert_eq!(live::counts(), [start[0] + 1, start[1] + 1, 0]);
+            map.load_style(r#"{"version":8,"sources":{},"layers":[]}"#)
+                .unwrap();
+            let image = map
+                .render_tile(
+                    3,
+                    4,
+                    2,
+                    256,
+                    1.0,
+                    DebugOptions::empty(),
+                    TileView::default(),
+                )
+                .unwrap();
+            assert_eq!((image.width(), image.height()), (256, 256));
+            assert_eq!(image.data().len(), 256 * 256 * 4);
+        }
+        assert_eq!(live::counts(), start);
+
+        for _ in 0..50 {
+            drop(HeadlessFrontend::new(Size::new(64, 64), 2.0).unwrap());
+        }
+        assert_eq!(live::counts(), start);
+    }
+
+    #[test]
+    fn test_failures_free_everything() {
+        let start = live::counts();
+        let mut map = NativeMap::new(Size::new(64, 64), 1.0, MapMode::Static).unwrap();
+
+        // Rendering before a style is loaded fails with the library's message
+        let Err(err) = map.render(None) else {
+            panic!("rendered without a style");
+        };
+        assert!(matches!(err, TileServerError::RenderError(_)));
+        assert!(err.to_string().contains("Render failed"), "{}", err);
+        assert!(last_error().is_some());
+
+        assert!(map.load_style("{\"version\":\0}").is_err());
+        drop(map);
+        assert_eq!(live::counts(), start);
+    }
+
+    const EMPTY_STYLE: &str = r#"{"version":8,"sources":{},"layers":[]}"#;
+
+    #[test]
+    fn test_async_render_delivers_once() {
+        let start = live::counts();
+        let mut map = NativeMap::new(Size::new(64, 32), 1.0, MapMode::Static).unwrap();
+        map.load_style(EMPTY_STYLE).unwrap();
+
+        let mut receiver = map.render_async(RenderOptions::new(Size::new(64, 32)));
+        let image = receiver.try_recv().unwrap().unwrap();
+        assert_eq!((image.width(), image.height()), (64, 32));
+        assert_eq!(image.data().len(), 64 * 32 * 4);
+        assert!(matches!(
+            receiver.try_recv(),
+            Err(oneshot::error::TryRecvError::Closed)
+        ));
+
+        // Renders without a style fail through the same callback
+        let mut unstyled = NativeMap::new(Size::new(64, 64), 1.0, MapMode::Static).unwrap();
+        let mut receiver = unstyled.render_async(RenderOptions::new(Size::new(64, 64)));
+        let Ok(Err(err)) = receiver.try_recv() else {
+            panic!("rendered without a style");
+        };
+        assert!(err.to_string().contains("Render failed"), "{}", err);
+
+        drop((map, unstyled));
+        assert_eq!(live::counts(), start);
+    }
+
+    #[test]
+    fn test_async_render_frees_unwanted_images() {
+        let start = live::counts();
+        let mut map = NativeMap::new(Size::new(64, 64), 1.0, MapMode::Static).unwrap();
+        map.load_style(EMPTY_STYLE).unwrap();
+
+        // The receiver is gone before the library calls back
+        drop(map.render_async(RenderOptions::new(Size::new(64, 64))));
+        assert_eq!(live::counts()[2], 0);
+
+        let (sender, receiver) = oneshot::channel();
+        drop(receiver);
+        let pending: PendingRender = Arc::new(Mutex::new(Some(sender)));
+        let mut image = NativeImage::empty();
+        // SAFETY: the map is live and `image` receives the pixels, which the
+        // callback then owns.
+        let code =
+            unsafe { mln_map_render_still(map.ptr.as_ptr(), ptr::null(), image.as_mut_ptr()) };
+        assert_eq!(code, MLNErrorCode::MLN_OK);
+        // SAFETY: the pixels move out of `image`, which is never dropped
+        let mut data = unsafe { ptr::read(&image.data) };
+        std::mem::forget(image);
+        // SAFETY: `data` holds pixels nothing else frees and `user_data` is a
+        // leaked `Box<PendingRender>`, as the library would pass them.
+        unsafe {
+            render_callback(
+                MLNErrorCode::MLN_OK,
+                &mut data,
+                Box::into_raw(Box::new(pending)).cast(),
+            )
+        };
+        assert_eq!(live::counts()[2], 0, "callback freed the pixels");
+
+        drop(map);
+        assert_eq!(live::counts(), start);
+    }
+
+    #[test]
+    fn test_dropped_map_fails_pending_renders() {
+        let start = live::counts();
+        let mut map = NativeMap::new(Size::new(64, 64), 1.0, MapMode::Static).unwrap();
+        let (sender, mut receiver) = oneshot::channel();
+        let pending: PendingRender = Arc::new(Mutex::new(Some(sender)));
+        map.pending.push(Arc::clone(&pending));
+
+        drop(map);
+        let Ok(Err(err)) = receiver.try_recv() else {
+            panic!("pending render was not failed");
+        };
+        assert!(err.to_string().contains("Map dropped"), "{}", err);
+
+        // A late callback finds nothing to deliver to
+        // SAFETY: `user_data` is a leaked `Box<PendingRender>` and there is no
+        // image to take ownership of.
+        unsafe {
+            render_callback(
+                MLNErrorCode::MLN_ERROR_RENDER_FAILED,
+                ptr::null_mut(),
+                Box::into_raw(Box::new(pending)).cast(),
+            )
+        };
+        assert_eq!(live::counts(), start);
+    }
+
+    #[test]
+    fn test_render_options_builder() {
+        let camera = CameraOptions::new(47.4, 8.5, 11.0).with_bearing(30.0);
+        let options = RenderOptions::new(Size::new(300, 200))
+            .with_pixel_ratio(2.0)
+            .with_camera(camera)
+            .with_mode(MapMode::Static)
+            .into_native();
+        assert_eq!((options.size.width, options.size.height), (300, 200));
+        assert_eq!(options.pixel_ratio, 2.0);
+        assert_eq!(options.camera.zoom, 11.0);
+        assert_eq!(options.camera.bearing, 30.0);
+        assert_eq!(options.mode, MLNMapMode::MLN_MAP_MODE_STATIC);
+        assert_eq!(RenderOptions::default().mode, MapMode::Tile);
+    }
+
+    /// Callable without naming `A` only for types that are not `Send`: for
+    /// `Send` types both impls apply and the call is ambiguous
+    trait AmbiguousIfSend<A> {
+        fn check() {}
+    }
+    impl<T: ?Sized> AmbiguousIfSend<()> for T {}
+    impl<T: ?Sized + Send> AmbiguousIfSend<u8> for T {}
+
+    #[test]
+    fn test_handles_stay_on_their_thread() {
+        <NativeMap as AmbiguousIfSend<_>>::check();
+        <HeadlessFrontend as AmbiguousIfSend<_>>::check();
+    }
+
     #[test]
     fn test_size_conversion() {
         let size = Size::new(512, 256);
@@ -583,4 +1147,65 @@ mod tests {
         assert_eq!(camera.bearing, 45.0);
         assert_eq!(camera.pitch, 30.0);
     }
+
+    #[test]
+    fn test_resource_callback_fills_responses() {
+        let (_runtime, loader) = super::super::loader::test_loader();
+        let state = LoaderState {
+            loader: Arc::new(loader),
+            last: Mutex::default(),
+        };
+        let user_data = ptr::addr_of!(state).cast_mut().cast::<c_void>();
+        let request = |url: &str, kind| {
+            let url = CString::new(url).unwrap();
+            let mut response = MLNResourceResponse::default();
+            let request = MLNResourceRequest {
+                url: url.as_ptr(),
+                kind,
+            };
+            // SAFETY: the request, response and state outlive the call.
+            unsafe { resource_callback(&request, &mut response, user_data) };
+            response
+        };
+
+        let tile = request("pmtiles://zurich/0/0/0", resource_kind::TILE);
+        assert!(!tile.data.is_null() && tile.data_len > 0);
+        assert!(tile.error.is_null() && !tile.not_found);
+        // SAFETY: the data stays alive in `state` until the next request.
+        let data = unsafe { std::slice::from_raw_parts(tile.data, tile.data_len) };
+        assert_eq!(data, &state.last.lock().unwrap().0[..]);
+
+        let missing = request("pmtiles://zurich/14/0/0", resource_kind::TILE);
+        assert!(missing.not_found);
+        assert!(missing.data.is_null() && missing.error.is_null());
+
+        let failed = request("pmtiles://zurich.json", resource_kind::SOURCE);
+        assert!(!failed.error.is_null() && !failed.not_found);
+        // SAFETY: the message stays alive in `state` until the next request.
+        let message = unsafe { CStr::from_ptr(failed.error) };
+        assert!(message.to_string_lossy().contains("zurich.json"));
+    }
+
+    #[test]
+    fn test_map_with_loader() {
+        let (_runtime, loader) = super::super::loader::test_loader();
+        let start = live::counts();
+        let mut map =
+            NativeMap::with_loader(Size::new(256, 256), 1.0, MapMode::Tile, Arc::new(loader))
+                .unwrap();
+        map.load_style(r#"{"version":8,"sources":{},"layers":[]}"#)
+            .unwrap();
+        map.render_tile(
+            0,
+            0,
+            0,
+            256,
+            1.0,
+            DebugOptions::empty(),
+            TileView::default(),
+        )
+        .unwrap();
+        drop(map);
+        assert_eq!(live::counts(), start);
+    }
 }
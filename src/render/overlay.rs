@@ -3,42 +3,97 @@
 //! Supports drawing paths (polylines) and markers on rendered map images.
 
 use image::{Rgba, RgbaImage};
+use serde::{de::Error as _, Deserialize, Deserializer};
 
 /// A point in geographic coordinates
+///
+/// Deserializes from a `[lon, lat]` array.
 #[derive(Debug, Clone, Copy)]
 pub struct GeoPoint {
     pub lon: f64,
     pub lat: f64,
 }
 
+impl<'de> Deserialize<'de> for GeoPoint {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let [lon, lat] = <[f64; 2]>::deserialize(deserializer)?;
+        Ok(Self { lon, lat })
+    }
+}
+
 /// A path overlay to draw on the map
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct PathOverlay {
     /// Points along the path
     pub points: Vec<GeoPoint>,
     /// Stroke color (RGBA)
+    #[serde(
+        default = "default_stroke_color",
+        deserialize_with = "deserialize_color"
+    )]
     pub stroke_color: Rgba<u8>,
     /// Stroke width in pixels
+    #[serde(default = "default_stroke_width")]
     pub stroke_width: f32,
     /// Fill color (RGBA) - for closed polygons (reserved for future use)
     #[allow(dead_code)]
+    #[serde(default, deserialize_with = "deserialize_optional_color")]
     pub fill_color: Option<Rgba<u8>>,
 }
 
 /// A marker overlay to draw on the map
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct MarkerOverlay {
     /// Position of the marker
     pub position: GeoPoint,
     /// Marker color (RGBA)
+    #[serde(
+        default = "default_marker_color",
+        deserialize_with = "deserialize_color"
+    )]
     pub color: Rgba<u8>,
     /// Optional label text (reserved for future use)
     #[allow(dead_code)]
+    #[serde(default)]
     pub label: Option<String>,
     /// Marker size in pixels
+    #[serde(default = "default_marker_size")]
     pub size: f32,
 }
 
+fn default_stroke_color() -> Rgba<u8> {
+    Rgba([0, 0, 255, 255])
+}
+
+fn default_stroke_width() -> f32 {
+    3.0
+}
+
+fn default_marker_color() -> Rgba<u8> {
+    Rgba([255, 0, 0, 255])
+}
+
+fn default_marker_size() -> f32 {
+    24.0
+}
+
+/// Deserialize a hex color string (e.g. `"#f00"` or `"ff000080"`)
+fn deserialize_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Rgba<u8>, D::Error> {
+    let hex = String::deserialize(deserializer)?;
+    parse_hex_color(&hex).ok_or_else(|| D::Error::custom(format!("invalid color: {}", hex)))
+}
+
+fn deserialize_optional_color<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Rgba<u8>>, D::Error> {
+    match Option::<String>::deserialize(deserializer)? {
+        Some(hex) => parse_hex_color(&hex)
+            .map(Some)
+            .ok_or_else(|| D::Error::custom(format!("invalid color: {}", hex))),
+        None => Ok(None),
+    }
+}
+
 /// Decode a Google Encoded Polyline string into a vector of GeoPoints
 ///
 /// The Google Polyline Algorithm encodes coordinates as a series of ASCII characters.
@@ -185,8 +240,8 @@ fn is_encoded_polyline(s: &str) -> bool {
 /// Or simple encoded: `enc:_p~iF~ps|U_ulLnnqC_mqNvxq`
 pub fn parse_path(path_str: &str) -> Option<PathOverlay> {
     // Default values
-    let mut stroke_width = 3.0f32;
-    let mut stroke_color = Rgba([0, 0, 255, 255]); // Blue
+    let mut stroke_width = default_stroke_width();
+    let mut stroke_color = default_stroke_color();
     let mut fill_color: Option<Rgba<u8>> = None;
     let mut points = Vec::new();
 
@@ -270,9 +325,9 @@ pub fn parse_marker(marker_str: &str) -> Option<MarkerOverlay> {
     let marker_str = marker_str.trim();
 
     // Default values
-    let mut color = Rgba([255, 0, 0, 255]); // Red
+    let mut color = default_marker_color();
     let mut label: Option<String> = None;
-    let mut size = 24.0f32;
+    let mut size = default_marker_size();
 
     // Try to parse pin-{size}-{label}+{color}({lon},{lat}) format
     if marker_str.starts_with("pin-") {
@@ -330,7 +385,7 @@ pub fn parse_marker(marker_str: &str) -> Option<MarkerOverlay> {
 }
 
 /// Parse a hex color string (3 or 6 digits, with optional alpha)
-fn parse_hex_color(hex: &str) -> Option<Rgba<u8>> {
+pub(crate) fn parse_hex_color(hex: &str) -> Option<Rgba<u8>> {
     let hex = hex.trim_start_matches('#');
 
     match hex.len() {
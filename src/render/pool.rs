@@ -2,22 +2,74 @@
 //!
 //! This module provides a pool of native MapLibre renderers.
 //!
-//! IMPORTANT: MapLibre Native is NOT thread-safe for concurrent style loading.
-//! We use a global mutex to serialize all render operations, but run them in
-//! spawn_blocking to avoid blocking the async runtime (MapLibre fetches tiles
-//! from our server during rendering).
+//! MapLibre Native objects are not `Sync` and keep per-thread state, so each
+//! worker owns a dedicated OS thread and creates its own renderer instances
+//! there. Render jobs are dispatched to the workers through a bounded
+//! [`JobQueue`] and results are sent back on a `oneshot` channel, so the
+//! async runtime is never blocked (MapLibre loads tiles during rendering,
+//! through the pool's [`ResourceLoader`] or over HTTP from our server).
+//!
+//! By default, the queue hands out jobs by zoom: overview tiles cover more
+//! of a viewport than detailed ones, so when it backs up a z0 tile is
+//! rendered before the z15 tiles queued ahead of it. Jobs of zoom 10 and
+//! above share the lowest priority, and equal priorities run in order of
+//! arrival. [`RenderPriority`] also offers plain FIFO and LIFO queues.
+//!
+//! When the queue is full, new jobs are rejected immediately with
+//! [`TileServerError::RenderQueueFull`] instead of waiting indefinitely.
+//! Jobs that take longer than `render_timeout` to finish fail with
+//! [`TileServerError::Timeout`]; if they are still queued at that point,
+//! the workers skip them.
+
+use std::collections::BinaryHeap;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock, PoisonError};
+use std::time::Duration;
 
-use std::sync::{Mutex, OnceLock};
+use opentelemetry::metrics::UpDownCounter;
+use opentelemetry::KeyValue;
+use tokio::sync::oneshot;
 
-use super::native::{MapMode, NativeMap, RenderOptions, RenderedImage, Size};
+use super::loader::ResourceLoader;
+use super::native::{
+    self, DebugOptions, MapMode, NativeMap, RenderOptions, RenderedImage, Size, TileView,
+};
+use super::solid::uniform_color;
+use crate::config::RenderPriority;
 use crate::error::{Result, TileServerError};
 
-/// Global mutex to serialize all MapLibre Native operations
-/// This is necessary because MapLibre Native has shared state that isn't thread-safe
-static RENDER_MUTEX: OnceLock<Mutex<()>> = OnceLock::new();
+/// Zoom from which jobs share the lowest priority
+const MAX_ZOOM_PRIORITY: u8 = 10;
+
+/// A unit of work executed on a render worker thread
+///
+/// The job owns the `oneshot::Sender` used to deliver its result.
+type RenderJob = Box<dyn FnOnce() + Send + 'static>;
 
-fn get_render_mutex() -> &'static Mutex<()> {
-    RENDER_MUTEX.get_or_init(|| Mutex::new(()))
+struct RenderMetrics {
+    queue_depth: UpDownCounter<i64>,
+    workers_busy: UpDownCounter<i64>,
+}
+
+static RENDER_METRICS: OnceLock<RenderMetrics> = OnceLock::new();
+
+fn get_metrics() -> &'static RenderMetrics {
+    RENDER_METRICS.get_or_init(|| {
+        let meter = opentelemetry::global::meter("tileserver-rs");
+        RenderMetrics {
+            queue_depth: meter
+                .i64_up_down_counter("tileserver_render_queue_depth")
+                .with_description("Render jobs waiting for a worker, by zoom priority")
+                .with_unit("jobs")
+                .build(),
+            workers_busy: meter
+                .i64_up_down_counter("tileserver_render_workers_busy")
+                .with_description("Render workers currently rendering")
+                .with_unit("workers")
+                .build(),
+        }
+    })
 }
 
 /// Configuration for a renderer pool
@@ -25,24 +77,214 @@ fn get_render_mutex() -> &'static Mutex<()> {
 pub struct PoolConfig {
     /// Default tile size
     pub tile_size: u32,
+    /// Number of worker threads, each with its own MapLibre renderer
+    pub workers: usize,
+    /// Maximum number of jobs waiting for a free worker
+    pub queue_size: usize,
+    /// Maximum time a job may wait in the queue and run, `None` for no limit
+    pub render_timeout: Option<Duration>,
+    /// Order in which queued jobs are handed to workers
+    pub priority: RenderPriority,
+    /// Loader of the maps' resources, `None` to fetch them over HTTP
+    pub loader: Option<Arc<ResourceLoader>>,
 }
 
 impl Default for PoolConfig {
     fn default() -> Self {
-        Self { tile_size: 512 }
+        Self {
+            tile_size: 512,
+            workers: 4,
+            queue_size: 64,
+            render_timeout: Some(Duration::from_secs(10)),
+            priority: RenderPriority::default(),
+            loader: None,
+        }
     }
 }
 
+/// Job counters shared between the pool and its workers
+#[derive(Debug, Default)]
+struct PoolCounters {
+    queued: AtomicUsize,
+    busy: AtomicUsize,
+}
+
+impl PoolCounters {
+    fn enqueue(&self, priority: u8) {
+        self.queued.fetch_add(1, Ordering::SeqCst);
+        get_metrics()
+            .queue_depth
+            .add(1, &[KeyValue::new("priority", i64::from(priority))]);
+    }
+
+    fn dequeue(&self, priority: u8) {
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+        get_metrics()
+            .queue_depth
+            .add(-1, &[KeyValue::new("priority", i64::from(priority))]);
+    }
+
+    fn start(&self) {
+        self.busy.fetch_add(1, Ordering::SeqCst);
+        get_metrics().workers_busy.add(1, &[]);
+    }
+
+    fn finish(&self) {
+        self.busy.fetch_sub(1, Ordering::SeqCst);
+        get_metrics().workers_busy.add(-1, &[]);
+    }
+}
+
+/// Priority of a job rendering at `zoom`, 0 (first) to 10 (last)
+fn zoom_priority(zoom: f64) -> u8 {
+    // NaN and negative zooms saturate to 0
+    (zoom.floor() as u8).min(MAX_ZOOM_PRIORITY)
+}
+
+/// A job waiting in the [`JobQueue`]
+struct QueuedJob {
+    /// Jobs with the highest rank are handed out first
+    rank: (u8, u64),
+    /// Zoom priority, for the queue depth metric
+    priority: u8,
+    job: RenderJob,
+}
+
+impl PartialEq for QueuedJob {
+    fn eq(&self, other: &Self) -> bool {
+        self.rank == other.rank
+    }
+}
+
+impl Eq for QueuedJob {}
+
+impl PartialOrd for QueuedJob {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueuedJob {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.rank.cmp(&other.rank)
+    }
+}
+
+#[derive(Default)]
+struct QueueState {
+    jobs: BinaryHeap<QueuedJob>,
+    /// Jobs pushed so far, ordering jobs of equal priority
+    pushed: u64,
+    /// Set when the pool is dropped; workers exit once the queue drains
+    closed: bool,
+}
+
+/// Bounded queue of render jobs shared by the pool and its workers
+///
+/// Async callers only hold the lock to push a job, so it never blocks the
+/// runtime for long; workers block on `available` while it is empty.
+struct JobQueue {
+    state: Mutex<QueueState>,
+    available: Condvar,
+    capacity: usize,
+    order: RenderPriority,
+    counters: Arc<PoolCounters>,
+}
+
+impl JobQueue {
+    fn new(capacity: usize, order: RenderPriority, counters: Arc<PoolCounters>) -> Self {
+        Self {
+            state: Mutex::new(QueueState::default()),
+            available: Condvar::new(),
+            capacity,
+            order,
+            counters,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Queue a job rendering at `zoom`, failing if the queue is full
+    fn push(&self, zoom: f64, job: RenderJob) -> Result<()> {
+        let priority = zoom_priority(zoom);
+        let mut state = self.lock();
+        if state.closed {
+            return Err(TileServerError::RenderError(
+                "Renderer pool is shut down".to_string(),
+            ));
+        }
+        if state.jobs.len() >= self.capacity {
+            return Err(TileServerError::RenderQueueFull);
+        }
+
+        let seq = state.pushed;
+        state.pushed += 1;
+        let rank = match self.order {
+            RenderPriority::Zoom => (MAX_ZOOM_PRIORITY - priority, u64::MAX - seq),
+            RenderPriority::Fifo => (0, u64::MAX - seq),
+            RenderPriority::Lifo => (0, seq),
+        };
+        state.jobs.push(QueuedJob {
+            rank,
+            priority,
+            job,
+        });
+        self.counters.enqueue(priority);
+        drop(state);
+
+        self.available.notify_one();
+        Ok(())
+    }
+
+    /// Wait for the next job, `None` once the queue is closed and empty
+    fn pop(&self) -> Option<RenderJob> {
+        let mut state = self.lock();
+        loop {
+            if let Some(queued) = state.jobs.pop() {
+                self.counters.dequeue(queued.priority);
+                return Some(queued.job);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self
+                .available
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    fn close(&self) {
+        self.lock().closed = true;
+        self.available.notify_all();
+    }
+}
+
+/// A rendered tile
+#[derive(Debug, Clone, PartialEq)]
+pub enum RenderedTile {
+    /// PNG-encoded tile
+    Png(Vec<u8>),
+    /// Tile of `size`x`size` pixels that are all `color`, left unencoded
+    Solid { color: [u8; 4], size: u32 },
+}
+
 /// Pool of native MapLibre renderers
 ///
-/// Currently uses a global mutex to serialize all render operations.
-/// Each render creates a fresh renderer to avoid thread-safety issues
-/// with MapLibre Native's shared state.
+/// Runs `workers` render threads fed from a bounded job queue. Each render
+/// creates a fresh map on the worker thread to avoid leaking style state
+/// between requests.
 pub struct RendererPool {
     /// Configuration
     config: PoolConfig,
     /// Maximum scale factor
     max_scale: u8,
+    /// Job queue shared by all workers
+    queue: Arc<JobQueue>,
+    /// Queue depth and busy worker counters
+    counters: Arc<PoolCounters>,
 }
 
 impl RendererPool {
@@ -51,98 +293,449 @@ impl RendererPool {
         // Initialize MapLibre Native
         super::native::init()?;
 
+        let config = PoolConfig {
+            workers: config.workers.max(1),
+            queue_size: config.queue_size.max(1),
+            ..config
+        };
+
+        let counters = Arc::new(PoolCounters::default());
+        let queue = Arc::new(JobQueue::new(
+            config.queue_size,
+            config.priority,
+            counters.clone(),
+        ));
+
+        for id in 0..config.workers {
+            let queue = queue.clone();
+            let counters = counters.clone();
+            std::thread::Builder::new()
+                .name(format!("render-worker-{}", id))
+                .spawn(move || worker_loop(queue, counters))
+                .map_err(|e| {
+                    TileServerError::RenderError(format!("Failed to spawn render worker: {}", e))
+                })?;
+        }
+
         tracing::info!(
-            "Renderer pool initialized (tile_size={}, max_scale={})",
+            "Renderer pool initialized (tile_size={}, max_scale={}, workers={}, queue_size={}, render_timeout={:?}, priority={:?})",
             config.tile_size,
-            max_scale
+            max_scale,
+            config.workers,
+            config.queue_size,
+            config.render_timeout,
+            config.priority
         );
 
-        Ok(Self { config, max_scale })
+        Ok(Self {
+            config,
+            max_scale,
+            queue,
+            counters,
+        })
     }
 
-    /// Render a tile
+    /// Run a job rendering at `zoom` on the next free worker and wait for
+    /// its result
+    ///
+    /// Fails with [`TileServerError::RenderQueueFull`] if every worker is busy
+    /// and the queue has no free slot.
+    async fn execute<T, F>(&self, zoom: f64, job: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T> + Send + 'static,
+    {
+        let (result_tx, result_rx) = oneshot::channel();
+        let job: RenderJob = Box::new(move || {
+            // The receiver is gone if the request was cancelled or timed out
+            // while queued; don't spend a worker on it
+            if result_tx.is_closed() {
+                return;
+            }
+            let _ = result_tx.send(job());
+        });
+
+        self.queue.push(zoom, job)?;
+
+        let result = match self.config.render_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, result_rx)
+                .await
+                .map_err(|_| TileServerError::Timeout(format!("render after {:?}", timeout)))?,
+            None => result_rx.await,
+        };
+        result.map_err(|_| TileServerError::RenderError("Render task panicked".to_string()))?
+    }
+
+    /// Default size of rendered tiles, before scaling
+    pub fn tile_size(&self) -> u32 {
+        self.config.tile_size
+    }
+
+    /// Render a `tile_size` pixel tile
+    ///
+    /// Tiles of a single color are not encoded, so they can be served from
+    /// a shared encoding.
+    #[allow(clippy::too_many_arguments)]
     pub async fn render_tile(
         &self,
         style_json: &str,
         z: u8,
         x: u32,
         y: u32,
+        tile_size: u32,
         scale: u8,
-    ) -> Result<Vec<u8>> {
+        debug: DebugOptions,
+        view: TileView,
+    ) -> Result<RenderedTile> {
         let scale = scale.min(self.max_scale).max(1);
-        let tile_size = self.config.tile_size;
         let style_json = style_json.to_string();
+        let loader = self.config.loader.clone();
 
-        // Use spawn_blocking to avoid deadlock (MapLibre fetches tiles from our server)
-        tokio::task::spawn_blocking(move || {
-            // Acquire global render lock to serialize all MapLibre operations
-            let _global_lock = get_render_mutex().lock().map_err(|e| {
-                TileServerError::RenderError(format!("Failed to acquire render lock: {}", e))
-            })?;
-
-            // Create a fresh renderer for each request
-            // This avoids issues with MapLibre Native's shared state across threads
-            let mut map =
-                NativeMap::new(Size::new(tile_size, tile_size), scale as f32, MapMode::Tile)?;
+        self.execute(f64::from(z), move || {
+            let (size, pixel_ratio) = native::tile_viewport(z, tile_size, scale as f32);
+            let mut map = create_map(loader, Size::new(size, size), pixel_ratio, MapMode::Tile)?;
 
             map.load_style(&style_json)?;
-            let image = map.render_tile(z, x, y, tile_size, scale as f32)?;
-            image.to_png()
+            let image = map.render_tile(z, x, y, size, pixel_ratio, debug, view)?;
+            match uniform_color(image.data()) {
+                Some(color) => Ok(RenderedTile::Solid {
+                    color,
+                    size: image.width(),
+                }),
+                None => image.to_png().map(RenderedTile::Png),
+            }
         })
         .await
-        .map_err(|e| TileServerError::RenderError(format!("Render task panicked: {}", e)))?
     }
 
     /// Render a static image
+    ///
+    /// The image is delivered by the async render callback. Both the native
+    /// library and the stub call back before `mln_map_render_still_async`
+    /// returns, so it is waiting on the receiver once the job is done; a
+    /// render still pending when the job drops its map fails instead.
     pub async fn render_static(
         &self,
         style_json: &str,
         options: RenderOptions,
     ) -> Result<RenderedImage> {
         let style_json = style_json.to_string();
+        let loader = self.config.loader.clone();
 
-        tokio::task::spawn_blocking(move || {
-            // Acquire global render lock to serialize all MapLibre operations
-            let _global_lock = get_render_mutex().lock().map_err(|e| {
-                TileServerError::RenderError(format!("Failed to acquire render lock: {}", e))
-            })?;
-
-            let mut map = NativeMap::new(options.size, options.pixel_ratio, MapMode::Static)?;
-            map.load_style(&style_json)?;
-            map.render(Some(options))
-        })
-        .await
-        .map_err(|e| TileServerError::RenderError(format!("Render task panicked: {}", e)))?
+        let receiver = self
+            .execute(options.camera.zoom, move || {
+                let mut map =
+                    create_map(loader, options.size, options.pixel_ratio, MapMode::Static)?;
+                map.load_style(&style_json)?;
+                Ok(map.render_async(options))
+            })
+            .await?;
+        receiver.await.map_err(|_| {
+            TileServerError::RenderError("Render finished without a result".to_string())
+        })?
     }
 
     /// Get pool statistics
     pub fn stats(&self) -> PoolStats {
         PoolStats {
             max_scale: self.max_scale,
+            workers: self.config.workers,
+            queue_depth: self.counters.queued.load(Ordering::SeqCst),
+            workers_busy: self.counters.busy.load(Ordering::SeqCst),
         }
     }
 }
 
+/// A map loading its resources through `loader`, or over HTTP without one
+fn create_map(
+    loader: Option<Arc<ResourceLoader>>,
+    size: Size,
+    pixel_ratio: f32,
+    mode: MapMode,
+) -> Result<NativeMap> {
+    match loader {
+        Some(loader) => NativeMap::with_loader(size, pixel_ratio, mode, loader),
+        None => NativeMap::new(size, pixel_ratio, mode),
+    }
+}
+
 impl Drop for RendererPool {
     fn drop(&mut self) {
+        // Workers exit once the queue drains
+        self.queue.close();
         tracing::info!("Renderer pool shutting down");
     }
 }
 
+/// Take and run jobs until the pool is dropped
+fn worker_loop(queue: Arc<JobQueue>, counters: Arc<PoolCounters>) {
+    while let Some(job) = queue.pop() {
+        counters.start();
+        // A panicking job drops its result sender; keep the worker alive
+        if catch_unwind(AssertUnwindSafe(job)).is_err() {
+            tracing::error!("Render job panicked");
+        }
+        counters.finish();
+    }
+}
+
 /// Pool statistics
 #[derive(Debug, Clone)]
 pub struct PoolStats {
     pub max_scale: u8,
+    pub workers: usize,
+    pub queue_depth: usize,
+    pub workers_busy: usize,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    /// Job that reports when it starts and blocks until released
+    fn blocking_job(
+        id: usize,
+        started: mpsc::UnboundedSender<usize>,
+        release: oneshot::Receiver<()>,
+    ) -> impl FnOnce() -> Result<usize> + Send + 'static {
+        move || {
+            started.send(id).unwrap();
+            release.blocking_recv().unwrap();
+            Ok(id)
+        }
+    }
+
+    fn pool(workers: usize, queue_size: usize) -> Arc<RendererPool> {
+        pool_with_priority(workers, queue_size, RenderPriority::default())
+    }
+
+    fn pool_with_priority(
+        workers: usize,
+        queue_size: usize,
+        priority: RenderPriority,
+    ) -> Arc<RendererPool> {
+        let config = PoolConfig {
+            workers,
+            queue_size,
+            render_timeout: None,
+            priority,
+            ..PoolConfig::default()
+        };
+        Arc::new(RendererPool::new(config, 3).unwrap())
+    }
+
+    /// Zooms of the jobs queued behind a busy worker, in the order they run
+    async fn run_order(priority: RenderPriority, zooms: &[u8]) -> Vec<u8> {
+        let pool = pool_with_priority(1, 8, priority);
+        let (started_tx, mut started_rx) = mpsc::unbounded_channel();
+
+        let (release, release_rx) = oneshot::channel();
+        let busy = {
+            let pool = pool.clone();
+            let job = blocking_job(usize::MAX, started_tx.clone(), release_rx);
+            tokio::spawn(async move { pool.execute(0.0, job).await })
+        };
+        started_rx.recv().await.unwrap();
+
+        let mut handles = Vec::new();
+        for (queued, &z) in zooms.iter().enumerate() {
+            let job_pool = pool.clone();
+            let started_tx = started_tx.clone();
+            handles.push(tokio::spawn(async move {
+                job_pool
+                    .execute(f64::from(z), move || {
+                        started_tx.send(usize::from(z)).unwrap();
+                        Ok(())
+                    })
+                    .await
+            }));
+            while pool.stats().queue_depth <= queued {
+                tokio::task::yield_now().await;
+            }
+        }
+
+        release.send(()).unwrap();
+        busy.await.unwrap().unwrap();
+        for handle in handles {
+            handle.await.unwrap().unwrap();
+        }
+        let mut order = Vec::new();
+        while let Ok(z) = started_rx.try_recv() {
+            order.push(z as u8);
+        }
+        order
+    }
 
     #[tokio::test]
     async fn test_pool_creation() {
         let config = PoolConfig::default();
         let pool = RendererPool::new(config, 3);
         assert!(pool.is_ok());
+        assert_eq!(pool.unwrap().stats().workers, 4);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_two_workers_render_concurrently() {
+        let pool = pool(2, 4);
+        let (started_tx, mut started_rx) = mpsc::unbounded_channel();
+        let mut releases = Vec::new();
+        let mut handles = Vec::new();
+
+        for id in 0..3 {
+            let (release_tx, release_rx) = oneshot::channel();
+            releases.push(Some(release_tx));
+            let pool = pool.clone();
+            let job = blocking_job(id, started_tx.clone(), release_rx);
+            handles.push(tokio::spawn(async move { pool.execute(0.0, job).await }));
+        }
+
+        // Two jobs run at the same time...
+        let first = tokio::time::timeout(Duration::from_secs(5), started_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let second = tokio::time::timeout(Duration::from_secs(5), started_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_ne!(first, second);
+
+        // ...while the third waits in the queue
+        assert!(
+            tokio::time::timeout(Duration::from_millis(200), started_rx.recv())
+                .await
+                .is_err()
+        );
+        let stats = pool.stats();
+        assert_eq!(stats.workers_busy, 2);
+        assert_eq!(stats.queue_depth, 1);
+
+        // Freeing a worker lets the third job start
+        releases[first].take().unwrap().send(()).unwrap();
+        let third = tokio::time::timeout(Duration::from_secs(5), started_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_ne!(third, first);
+        assert_ne!(third, second);
+
+        for release in releases.iter_mut().filter_map(Option::take) {
+            release.send(()).unwrap();
+        }
+        let mut results = Vec::new();
+        for handle in handles {
+            results.push(handle.await.unwrap().unwrap());
+        }
+        results.sort_unstable();
+        assert_eq!(results, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_zoom_priority() {
+        assert_eq!(zoom_priority(0.0), 0);
+        assert_eq!(zoom_priority(8.7), 8);
+        assert_eq!(zoom_priority(10.0), 10);
+        assert_eq!(zoom_priority(14.0), 10);
+        assert_eq!(zoom_priority(-1.0), 0);
+        assert_eq!(zoom_priority(f64::NAN), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_low_zooms_render_first() {
+        // z0 runs first even when it is queued last; z10 and z15 share the
+        // lowest priority and keep their order
+        assert_eq!(
+            run_order(RenderPriority::Zoom, &[15, 8, 10, 0]).await,
+            vec![0, 8, 15, 10]
+        );
+        assert_eq!(
+            run_order(RenderPriority::Fifo, &[15, 8, 0]).await,
+            vec![15, 8, 0]
+        );
+        assert_eq!(
+            run_order(RenderPriority::Lifo, &[15, 8, 0, 12]).await,
+            vec![12, 0, 8, 15]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_full_queue_is_rejected() {
+        let pool = pool(1, 1);
+        let (started_tx, mut started_rx) = mpsc::unbounded_channel();
+
+        // Occupy the only worker
+        let (release_busy, release_rx) = oneshot::channel();
+        let busy = {
+            let pool = pool.clone();
+            let job = blocking_job(0, started_tx.clone(), release_rx);
+            tokio::spawn(async move { pool.execute(0.0, job).await })
+        };
+        started_rx.recv().await.unwrap();
+
+        // Fill the only queue slot
+        let (release_queued, release_rx) = oneshot::channel();
+        let queued = {
+            let pool = pool.clone();
+            let job = blocking_job(1, started_tx.clone(), release_rx);
+            tokio::spawn(async move { pool.execute(0.0, job).await })
+        };
+        while pool.stats().queue_depth == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        let result = pool.execute(0.0, || Ok(2)).await;
+        assert!(matches!(result, Err(TileServerError::RenderQueueFull)));
+
+        release_busy.send(()).unwrap();
+        release_queued.send(()).unwrap();
+        assert_eq!(busy.await.unwrap().unwrap(), 0);
+        assert_eq!(queued.await.unwrap().unwrap(), 1);
+        assert_eq!(pool.stats().queue_depth, 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_slow_render_times_out() {
+        let config = PoolConfig {
+            workers: 1,
+            queue_size: 4,
+            render_timeout: Some(Duration::from_millis(100)),
+            ..PoolConfig::default()
+        };
+        let pool = RendererPool::new(config, 3).unwrap();
+
+        // A renderer that takes longer than the timeout
+        let (ran_tx, mut ran_rx) = mpsc::unbounded_channel();
+        let slow = {
+            let ran_tx = ran_tx.clone();
+            move || {
+                std::thread::sleep(Duration::from_millis(500));
+                ran_tx.send("slow").unwrap();
+                Ok(0)
+            }
+        };
+        // Queued behind the slow job, so it times out before it starts
+        let queued = move || {
+            ran_tx.send("queued").unwrap();
+            Ok(1)
+        };
+
+        let (slow, queued) = tokio::join!(pool.execute(0.0, slow), pool.execute(0.0, queued));
+        let err = slow.unwrap_err();
+        assert!(matches!(err, TileServerError::Timeout(_)));
+        assert!(matches!(queued, Err(TileServerError::Timeout(_))));
+
+        let response = axum::response::IntoResponse::into_response(err);
+        assert_eq!(
+            response.status(),
+            axum::http::StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(response.headers()["retry-after"], "1");
+
+        // Once the slow render finishes, the worker skips the abandoned job
+        assert_eq!(ran_rx.recv().await, Some("slow"));
+        assert_eq!(pool.execute(0.0, || Ok(2)).await.unwrap(), 2);
+        assert!(ran_rx.try_recv().is_err());
     }
 }
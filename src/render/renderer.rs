@@ -3,15 +3,38 @@
 //! This module provides a high-level interface for rendering map tiles
 //! and static images using the native MapLibre renderer pool.
 
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::sync::Arc;
 
-use super::pool::{PoolConfig, RendererPool};
-use super::types::{ImageFormat, RenderOptions};
+use super::coalesce::SingleFlight;
+use super::native::{DebugOptions, TileView};
+use super::pool::{PoolConfig, RenderedTile, RendererPool};
+use super::solid::SolidTileCache;
+use super::types::{output_size, ImageFormat, RenderOptions};
 use crate::error::{Result, TileServerError};
 
+/// Hash of the style, z, x, y, tile size, scale, format, debug overlays and
+/// view of a tile
+type TileKey = (
+    u64,
+    u8,
+    u32,
+    u32,
+    u32,
+    u8,
+    ImageFormat,
+    DebugOptions,
+    TileView,
+);
+
 /// High-level renderer that manages the native renderer pool
 pub struct Renderer {
     pool: Arc<RendererPool>,
+    /// Shared encodings of solid-color tiles
+    solid_tiles: Arc<SolidTileCache>,
+    /// Tile renders in progress, shared by identical requests
+    tile_renders: SingleFlight<TileKey>,
 }
 
 impl Renderer {
@@ -25,10 +48,13 @@ impl Renderer {
         let pool = RendererPool::new(config, max_scale)?;
         Ok(Self {
             pool: Arc::new(pool),
+            solid_tiles: Arc::new(SolidTileCache::new()),
+            tile_renders: SingleFlight::new(),
         })
     }
 
     /// Render a map tile
+    #[allow(clippy::too_many_arguments)]
     pub async fn render_tile(
         &self,
         style_json: &str,
@@ -37,24 +63,84 @@ impl Renderer {
         y: u32,
         scale: u8,
         format: ImageFormat,
+        debug: DebugOptions,
+        view: TileView,
+    ) -> Result<Vec<u8>> {
+        let tile_size = self.pool.tile_size();
+        self.render_tile_with_size(style_json, z, x, y, tile_size, scale, format, debug, view)
+            .await
+    }
+
+    /// Render a map tile of `tile_size` pixels before scaling
+    ///
+    /// Requests for a tile that is already being rendered with the same style
+    /// wait for that render instead of starting another.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn render_tile_with_size(
+        &self,
+        style_json: &str,
+        z: u8,
+        x: u32,
+        y: u32,
+        tile_size: u32,
+        scale: u8,
+        format: ImageFormat,
+        debug: DebugOptions,
+        view: TileView,
     ) -> Result<Vec<u8>> {
+        // `tracing::debug!` shadows `debug` with its own helper
+        let overlays = debug;
         tracing::debug!(
-            "Rendering tile z={}, x={}, y={}, scale={}, format={:?}",
+            "Rendering tile z={}, x={}, y={}, size={}, scale={}, format={:?}, debug={:?}, view={:?}",
             z,
             x,
             y,
+            tile_size,
             scale,
-            format
+            format,
+            overlays,
+            view
         );
 
-        // Get PNG from pool
-        let png_data = self.pool.render_tile(style_json, z, x, y, scale).await?;
+        let style_hash = {
+            let mut hasher = DefaultHasher::new();
+            style_json.hash(&mut hasher);
+            hasher.finish()
+        };
+        let key = (style_hash, z, x, y, tile_size, scale, format, debug, view);
+        let pool = Arc::clone(&self.pool);
+        let solid_tiles = Arc::clone(&self.solid_tiles);
+        let style_json = style_json.to_string();
+        self.tile_renders
+            .run(key, async move {
+                let tile = pool
+                    .render_tile(&style_json, z, x, y, tile_size, scale, debug, view)
+                    .await?;
+                Self::encode_tile(&solid_tiles, tile, format)
+            })
+            .await
+    }
+
+    /// Encode a rendered tile in the requested format
+    ///
+    /// Solid-color tiles come from the shared cache instead.
+    fn encode_tile(
+        solid_tiles: &SolidTileCache,
+        tile: RenderedTile,
+        format: ImageFormat,
+    ) -> Result<Vec<u8>> {
+        let png_data = match tile {
+            RenderedTile::Png(png_data) => png_data,
+            RenderedTile::Solid { color, size } => {
+                return solid_tiles.tile(color, size, format, "uniform");
+            }
+        };
 
         // Convert to requested format if needed
         match format {
             ImageFormat::Png => Ok(png_data),
-            ImageFormat::Jpeg => self.convert_png_to_jpeg(&png_data, 90),
-            ImageFormat::Webp => self.convert_png_to_webp(&png_data, 90),
+            ImageFormat::Jpeg => Self::convert_png_to_jpeg(&png_data, 90),
+            ImageFormat::Webp => Self::convert_png_to_webp(&png_data, 90),
         }
     }
 
@@ -70,19 +156,25 @@ impl Renderer {
             options.lat
         );
 
-        let native_options = super::native::RenderOptions {
-            size: super::native::Size::new(options.width, options.height),
-            pixel_ratio: options.scale as f32,
-            camera: super::native::CameraOptions::new(options.lat, options.lon, options.zoom)
+        let native_options = super::native::RenderOptions::new(super::native::Size::new(
+            options.width,
+            options.height,
+        ))
+        .with_pixel_ratio(options.scale)
+        .with_camera(
+            super::native::CameraOptions::new(options.lat, options.lon, options.zoom)
                 .with_bearing(options.bearing)
                 .with_pitch(options.pitch),
-            mode: super::native::MapMode::Static,
-        };
+        )
+        .with_mode(super::native::MapMode::Static)
+        .with_debug(options.debug);
 
         let rendered_image = self
             .pool
             .render_static(&options.style_json, native_options)
             .await?;
+        let (width, height) = output_size(options.width, options.height, options.scale);
+        let rendered_image = Self::fit_size(rendered_image, width, height)?;
 
         // Apply overlays if specified
         let final_image = self.apply_overlays(rendered_image, &options)?;
@@ -101,9 +193,9 @@ impl Renderer {
         mut image: super::native::RenderedImage,
         options: &RenderOptions,
     ) -> Result<super::native::RenderedImage> {
-        // Parse paths and markers
-        let mut paths = Vec::new();
-        let mut markers = Vec::new();
+        // Start from structured overlays, then parse encoded paths and markers
+        let mut paths = options.paths.clone();
+        let mut markers = options.markers.clone();
 
         if let Some(ref path_str) = options.path {
             // Multiple paths can be separated by |
@@ -129,8 +221,8 @@ impl Renderer {
         }
 
         // Convert to image::RgbaImage for drawing
-        let actual_width = options.width * options.scale as u32;
-        let actual_height = options.height * options.scale as u32;
+        let actual_width = image.width();
+        let actual_height = image.height();
 
         let mut rgba_image =
             image::RgbaImage::from_raw(actual_width, actual_height, image.take_data()).ok_or_else(
@@ -145,7 +237,7 @@ impl Renderer {
             options.lon,
             options.lat,
             options.zoom,
-            options.scale as f32,
+            options.scale,
         );
 
         // Convert back to native RenderedImage
@@ -156,8 +248,34 @@ impl Renderer {
         ))
     }
 
+    /// Resize `image` to `width` x `height` pixels if it isn't already
+    ///
+    /// MapLibre truncates fractional framebuffer sizes, while images are
+    /// sized by rounding, so a fractional pixel ratio can leave a render one
+    /// pixel short.
+    fn fit_size(
+        mut image: super::native::RenderedImage,
+        width: u32,
+        height: u32,
+    ) -> Result<super::native::RenderedImage> {
+        if (image.width(), image.height()) == (width, height) {
+            return Ok(image);
+        }
+        let rgba = image::RgbaImage::from_raw(image.width(), image.height(), image.take_data())
+            .ok_or_else(|| {
+                TileServerError::RenderError("Failed to create image buffer".to_string())
+            })?;
+        let resized =
+            image::imageops::resize(&rgba, width, height, image::imageops::FilterType::Triangle);
+        Ok(super::native::RenderedImage::from_rgba(
+            width,
+            height,
+            resized.into_raw(),
+        ))
+    }
+
     /// Convert PNG data to JPEG
-    fn convert_png_to_jpeg(&self, png_data: &[u8], quality: u8) -> Result<Vec<u8>> {
+    fn convert_png_to_jpeg(png_data: &[u8], quality: u8) -> Result<Vec<u8>> {
         use image::ImageReader;
         use std::io::Cursor;
 
@@ -189,7 +307,7 @@ impl Renderer {
     }
 
     /// Convert PNG data to WebP
-    fn convert_png_to_webp(&self, png_data: &[u8], _quality: u8) -> Result<Vec<u8>> {
+    fn convert_png_to_webp(png_data: &[u8], _quality: u8) -> Result<Vec<u8>> {
         use image::ImageReader;
         use std::io::Cursor;
 
@@ -211,6 +329,16 @@ impl Renderer {
     pub fn pool(&self) -> Arc<RendererPool> {
         self.pool.clone()
     }
+
+    /// Shared encodings of solid-color tiles
+    pub fn solid_tiles(&self) -> Arc<SolidTileCache> {
+        self.solid_tiles.clone()
+    }
+
+    /// Tile requests that waited for an identical render in progress
+    pub fn coalesced_renders(&self) -> u64 {
+        self.tile_renders.coalesced()
+    }
 }
 
 #[cfg(test)]
@@ -222,4 +350,37 @@ mod tests {
         let renderer = Renderer::new();
         assert!(renderer.is_ok());
     }
+
+    #[tokio::test]
+    async fn test_solid_tiles_share_one_encoding() {
+        let renderer = Renderer::new().unwrap();
+        let ocean = |size| RenderedTile::Solid {
+            color: [170, 211, 223, 255],
+            size,
+        };
+
+        // Two ocean tiles: the second is served without encoding
+        let first =
+            Renderer::encode_tile(&renderer.solid_tiles, ocean(256), ImageFormat::Png).unwrap();
+        let second =
+            Renderer::encode_tile(&renderer.solid_tiles, ocean(256), ImageFormat::Png).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(renderer.solid_tiles().hits(), 1);
+
+        let retina =
+            Renderer::encode_tile(&renderer.solid_tiles, ocean(512), ImageFormat::Png).unwrap();
+        assert_ne!(first, retina);
+        assert_eq!(renderer.solid_tiles().hits(), 1);
+
+        // Other tiles are encoded as usual
+        let png = vec![1, 2, 3];
+        let tile = Renderer::encode_tile(
+            &renderer.solid_tiles,
+            RenderedTile::Png(png.clone()),
+            ImageFormat::Png,
+        )
+        .unwrap();
+        assert_eq!(tile, png);
+        assert_eq!(renderer.solid_tiles().hits(), 1);
+    }
 }
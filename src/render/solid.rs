@@ -0,0 +1,161 @@
+//! Solid-color tiles
+//!
+//! Tiles over open ocean or outside the data are often a single color. The
+//! render workers report such frames by their color instead of encoding them,
+//! and [`SolidTileCache`] serves one shared encoding per color, size and
+//! format.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock, PoisonError};
+
+use opentelemetry::metrics::Counter;
+use opentelemetry::KeyValue;
+
+use super::native::RenderedImage;
+use super::types::ImageFormat;
+use crate::error::Result;
+
+/// Transparent, or white for JPEG
+pub const TRANSPARENT: [u8; 4] = [255, 255, 255, 0];
+
+/// Encodings kept; tiles of further colors are encoded on every request
+const MAX_SOLID_TILES: usize = 256;
+
+/// Color, size and format of an encoded tile
+type SolidTileKey = ([u8; 4], u32, ImageFormat);
+
+static BLANK_TILE_HITS: OnceLock<Counter<u64>> = OnceLock::new();
+
+fn blank_tile_hits() -> &'static Counter<u64> {
+    BLANK_TILE_HITS.get_or_init(|| {
+        opentelemetry::global::meter("tileserver-rs")
+            .u64_counter("tileserver_blank_tile_hits_total")
+            .with_description("Solid-color tiles served from a shared encoding")
+            .with_unit("tiles")
+            .build()
+    })
+}
+
+/// The color of every pixel, if all RGBA pixels are the same
+///
+/// Stops at the first pixel that differs, so detailed tiles cost little.
+pub fn uniform_color(pixels: &[u8]) -> Option<[u8; 4]> {
+    let mut chunks = pixels.chunks_exact(4);
+    let first = chunks.next()?;
+    chunks
+        .all(|pixel| pixel == first)
+        .then(|| [first[0], first[1], first[2], first[3]])
+}
+
+/// Encode a `size`x`size` tile filled with `color`
+pub fn solid_tile(color: [u8; 4], size: u32, format: ImageFormat) -> Result<Vec<u8>> {
+    let image = RenderedImage::from_rgba(size, size, color.repeat((size * size) as usize));
+    match format {
+        ImageFormat::Png => image.to_png(),
+        ImageFormat::Jpeg => image.to_jpeg(90),
+        ImageFormat::Webp => image.to_webp(90),
+    }
+}
+
+/// Shared encodings of solid-color tiles
+#[derive(Debug, Default)]
+pub struct SolidTileCache {
+    tiles: Mutex<HashMap<SolidTileKey, Vec<u8>>>,
+    hits: AtomicU64,
+}
+
+impl SolidTileCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A `size`x`size` tile filled with `color`, encoded on first use
+    ///
+    /// `reason` labels hits in the `tileserver_blank_tile_hits_total` metric.
+    pub fn tile(
+        &self,
+        color: [u8; 4],
+        size: u32,
+        format: ImageFormat,
+        reason: &'static str,
+    ) -> Result<Vec<u8>> {
+        let key = (color, size, format);
+        let cached = self
+            .tiles
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&key)
+            .cloned();
+        if let Some(tile) = cached {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            blank_tile_hits().add(1, &[KeyValue::new("reason", reason)]);
+            return Ok(tile);
+        }
+
+        // Encode without holding the lock; a concurrent miss encodes the same bytes
+        let tile = solid_tile(color, size, format)?;
+        let mut tiles = self.tiles.lock().unwrap_or_else(PoisonError::into_inner);
+        if tiles.len() < MAX_SOLID_TILES {
+            tiles.insert(key, tile.clone());
+        }
+        Ok(tile)
+    }
+
+    /// Tiles served from a cached encoding
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uniform_color() {
+        let ocean = [170, 211, 223, 255].repeat(256 * 256);
+        assert_eq!(uniform_color(&ocean), Some([170, 211, 223, 255]));
+
+        let mut coast = ocean.clone();
+        coast[4 * 1000 + 1] = 0;
+        assert_eq!(uniform_color(&coast), None);
+        assert_eq!(uniform_color(&[]), None);
+    }
+
+    #[test]
+    fn test_solid_tile() {
+        let png = solid_tile(TRANSPARENT, 512, ImageFormat::Png).unwrap();
+        let image = image::load_from_memory(&png).unwrap().to_rgba8();
+        assert_eq!(image.dimensions(), (512, 512));
+        assert!(image.pixels().all(|p| p[3] == 0));
+
+        let jpeg = solid_tile(TRANSPARENT, 256, ImageFormat::Jpeg).unwrap();
+        let image = image::load_from_memory(&jpeg).unwrap().to_rgb8();
+        assert_eq!(image.dimensions(), (256, 256));
+        assert!(image.pixels().all(|p| p[0] > 250));
+    }
+
+    #[test]
+    fn test_cache_shares_encodings() {
+        let cache = SolidTileCache::new();
+        let ocean = [170, 211, 223, 255];
+
+        let first = cache.tile(ocean, 256, ImageFormat::Png, "uniform").unwrap();
+        assert_eq!(cache.hits(), 0);
+        let second = cache.tile(ocean, 256, ImageFormat::Png, "uniform").unwrap();
+        assert_eq!(first, second);
+        assert_eq!(cache.hits(), 1);
+
+        // Other sizes, formats and colors are encoded separately
+        let webp = cache
+            .tile(ocean, 256, ImageFormat::Webp, "uniform")
+            .unwrap();
+        assert_ne!(first, webp);
+        cache.tile(ocean, 512, ImageFormat::Png, "uniform").unwrap();
+        cache
+            .tile(TRANSPARENT, 256, ImageFormat::Png, "outside")
+            .unwrap();
+        assert_eq!(cache.hits(), 1);
+    }
+}
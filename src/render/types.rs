@@ -1,16 +1,31 @@
 use serde::Deserialize;
 use std::str::FromStr;
 
+use super::native::{DebugOptions, TileView};
+use super::overlay::{MarkerOverlay, PathOverlay};
+use crate::error::TileServerError;
+use crate::ogcapi::MAX_LATITUDE;
+
 /// Maximum allowed image dimension (width or height) in pixels
 pub const MAX_IMAGE_DIMENSION: u32 = 4096;
 
-/// Maximum allowed scale factor for retina images
-pub const MAX_SCALE_FACTOR: u8 = 4;
+/// Range of pixel ratios of static images, from `@{scale}x` or `?pixelRatio=`
+pub const PIXEL_RATIO_RANGE: std::ops::RangeInclusive<f32> = 0.5..=8.0;
+
+/// Size in pixels of an image of `width` x `height` CSS pixels rendered at
+/// `scale`, rounded to whole pixels
+pub fn output_size(width: u32, height: u32, scale: f32) -> (u32, u32) {
+    let scaled = |length: u32| (length as f64 * scale as f64).round() as u32;
+    (scaled(width), scaled(height))
+}
 
 /// Image format for rendered output
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ImageFormat {
+    #[default]
     Png,
+    #[serde(alias = "jpg")]
     Jpeg,
     Webp,
 }
@@ -23,6 +38,15 @@ impl ImageFormat {
             Self::Webp => "image/webp",
         }
     }
+
+    /// Lowercase format name, e.g. for the `X-Tile-Format` debug header
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpeg",
+            Self::Webp => "webp",
+        }
+    }
 }
 
 impl FromStr for ImageFormat {
@@ -38,6 +62,90 @@ impl FromStr for ImageFormat {
     }
 }
 
+/// Names of the [`DebugOptions`] accepted by `?debug=`
+const DEBUG_NAMES: [(&str, DebugOptions); 5] = [
+    ("tile-borders", DebugOptions::TILE_BORDERS),
+    ("parse-status", DebugOptions::PARSE_STATUS),
+    ("timestamps", DebugOptions::TIMESTAMPS),
+    ("collision", DebugOptions::COLLISION),
+    ("overdraw", DebugOptions::OVERDRAW),
+];
+
+impl DebugOptions {
+    /// Parse comma-separated overlay names, e.g. `tile-borders,collision`
+    pub fn parse(names: &str) -> Result<Self, String> {
+        names
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .try_fold(Self::empty(), |debug, name| {
+                let (_, flag) = DEBUG_NAMES
+                    .iter()
+                    .find(|(known, _)| known.eq_ignore_ascii_case(name))
+                    .ok_or_else(|| {
+                        let known: Vec<&str> =
+                            DEBUG_NAMES.iter().map(|(known, _)| *known).collect();
+                        format!(
+                            "Unknown debug option '{}', expected one of: {}",
+                            name,
+                            known.join(", ")
+                        )
+                    })?;
+                Ok(debug | *flag)
+            })
+    }
+
+    /// Overlays requested with `?debug=`, which only servers with
+    /// `debug_endpoints = true` draw
+    pub fn from_query(names: Option<&str>, allowed: bool) -> Result<Self, TileServerError> {
+        let Some(names) = names else {
+            return Ok(Self::empty());
+        };
+        if !allowed {
+            return Err(TileServerError::Forbidden(
+                "Debug rendering requires debug_endpoints = true".to_string(),
+            ));
+        }
+        Self::parse(names).map_err(TileServerError::BadRequest)
+    }
+}
+
+/// Largest `?pitch=` of rendered tiles, in degrees
+pub const MAX_TILE_PITCH: f64 = 60.0;
+
+/// Largest zoom MapLibre Native renders
+pub const MAX_RENDER_ZOOM: f64 = 25.5;
+
+/// Largest pitch of static images, in degrees
+pub const MAX_RENDER_PITCH: f64 = 85.0;
+
+impl TileView {
+    /// View requested with `?bearing=` (0–360) and `?pitch=` (0–60)
+    ///
+    /// A bearing of 360 is north-up, like 0, so both render the same tile.
+    pub fn from_query(bearing: Option<f64>, pitch: Option<f64>) -> Result<Self, TileServerError> {
+        let bearing = bearing.unwrap_or(0.0);
+        if !(0.0..=360.0).contains(&bearing) {
+            return Err(TileServerError::BadRequest(format!(
+                "Invalid bearing {}, expected 0 to 360",
+                bearing
+            )));
+        }
+        let pitch = pitch.unwrap_or(0.0);
+        if !(0.0..=MAX_TILE_PITCH).contains(&pitch) {
+            return Err(TileServerError::BadRequest(format!(
+                "Invalid pitch {}, expected 0 to {}",
+                pitch, MAX_TILE_PITCH
+            )));
+        }
+        // `+ 0.0` turns -0 into 0, which compares and hashes the same
+        Ok(Self {
+            bearing: bearing % 360.0 + 0.0,
+            pitch: pitch + 0.0,
+        })
+    }
+}
+
 /// Static image type (center, bbox, or auto)
 #[derive(Debug, Clone)]
 pub enum StaticType {
@@ -128,6 +236,32 @@ impl FromStr for StaticType {
     }
 }
 
+/// Width, height, scale and format of a static image from its
+/// `{width}x{height}[@{scale}x].{format}` path segment
+///
+/// The scale may be fractional, e.g. `800x600@3.5x.png`; its range is
+/// checked with the size, by [`RenderOptions::for_static`].
+pub fn parse_static_size(size_fmt: &str) -> Option<(u32, u32, f32, ImageFormat)> {
+    // Split extension: "800x600@2x" and "png"
+    let (size_and_scale, format_str) = size_fmt.rsplit_once('.')?;
+    let format = format_str.parse::<ImageFormat>().ok()?;
+
+    // Check for scale: "800x600@2x" or just "800x600"
+    let (size_str, scale) = match size_and_scale.split_once('@') {
+        Some((size, scale_str)) => (size, scale_str.strip_suffix('x')?.parse().ok()?),
+        None => (size_and_scale, 1.0),
+    };
+
+    // Parse width and height: "800x600"
+    let (width_str, height_str) = size_str.split_once('x')?;
+    Some((
+        width_str.parse().ok()?,
+        height_str.parse().ok()?,
+        scale,
+        format,
+    ))
+}
+
 /// Query parameters for static image rendering
 #[derive(Debug, Clone, Default, Deserialize)]
 pub struct StaticQueryParams {
@@ -145,6 +279,74 @@ pub struct StaticQueryParams {
     /// Maximum zoom level for auto-fit
     #[allow(dead_code)]
     pub maxzoom: Option<u8>,
+    /// Language to prefer for labels, e.g. `de`
+    pub language: Option<String>,
+    /// Debug overlays to draw, e.g. `tile-borders,collision`
+    pub debug: Option<String>,
+    /// Pixel ratio, e.g. `3.5`, in place of the `@{scale}x` of the path
+    #[serde(rename = "pixelRatio")]
+    pub pixel_ratio: Option<f32>,
+}
+
+/// JSON body for `POST /styles/{style}/render`
+#[derive(Debug, Clone, Deserialize)]
+pub struct StaticRenderRequest {
+    /// Map center as `[lon, lat, zoom]`
+    pub center: [f64; 3],
+    /// Image size as `[width, height]` in pixels
+    pub size: [u32; 2],
+    /// Pixel ratio, 0.5 to 8 (default 1)
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+    /// Output format (default png)
+    #[serde(default)]
+    pub format: ImageFormat,
+    /// Markers to draw on the image
+    #[serde(default)]
+    pub markers: Vec<MarkerOverlay>,
+    /// Paths to draw on the image
+    #[serde(default)]
+    pub paths: Vec<PathOverlay>,
+    /// Bearing (rotation) in degrees
+    #[serde(default)]
+    pub bearing: f64,
+    /// Pitch (tilt) in degrees
+    #[serde(default)]
+    pub pitch: f64,
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+/// Validate static image dimensions and scale
+///
+/// Security: Bounds image size to prevent DoS via memory exhaustion
+fn validate_static_size(width: u32, height: u32, scale: f32) -> Result<(), String> {
+    if width == 0 || height == 0 {
+        return Err("Image dimensions must be greater than 0".to_string());
+    }
+    if width > MAX_IMAGE_DIMENSION {
+        return Err(format!(
+            "Image width {} exceeds maximum of {}",
+            width, MAX_IMAGE_DIMENSION
+        ));
+    }
+    if height > MAX_IMAGE_DIMENSION {
+        return Err(format!(
+            "Image height {} exceeds maximum of {}",
+            height, MAX_IMAGE_DIMENSION
+        ));
+    }
+    if !PIXEL_RATIO_RANGE.contains(&scale) {
+        return Err(format!(
+            "Pixel ratio {} must be between {} and {}",
+            scale,
+            PIXEL_RATIO_RANGE.start(),
+            PIXEL_RATIO_RANGE.end()
+        ));
+    }
+    Ok(())
 }
 
 /// Options for rendering a map image
@@ -159,8 +361,8 @@ pub struct RenderOptions {
     pub width: u32,
     /// Image height in pixels
     pub height: u32,
-    /// Pixel ratio / scale (1-9)
-    pub scale: u8,
+    /// Pixel ratio, e.g. 2 for `@2x`
+    pub scale: f32,
     /// Center longitude
     pub lon: f64,
     /// Center latitude
@@ -181,6 +383,12 @@ pub struct RenderOptions {
     /// Optional marker overlay (reserved for future use)
     #[allow(dead_code)]
     pub marker: Option<String>,
+    /// Structured path overlays (from a JSON render request)
+    pub paths: Vec<PathOverlay>,
+    /// Structured marker overlays (from a JSON render request)
+    pub markers: Vec<MarkerOverlay>,
+    /// Debug overlays drawn by MapLibre
+    pub debug: DebugOptions,
 }
 
 impl RenderOptions {
@@ -210,7 +418,7 @@ impl RenderOptions {
             style_json,
             width: tile_size,
             height: tile_size,
-            scale,
+            scale: f32::from(scale),
             lon,
             lat,
             zoom: z as f64,
@@ -219,6 +427,9 @@ impl RenderOptions {
             format,
             path: None,
             marker: None,
+            paths: Vec::new(),
+            markers: Vec::new(),
+            debug: DebugOptions::empty(),
         }
     }
 
@@ -230,33 +441,13 @@ impl RenderOptions {
         static_type: StaticType,
         width: u32,
         height: u32,
-        scale: u8,
+        scale: f32,
         format: ImageFormat,
         query_params: StaticQueryParams,
     ) -> Result<Self, String> {
-        // Security: Validate image dimensions to prevent DoS via memory exhaustion
-        if width == 0 || height == 0 {
-            return Err("Image dimensions must be greater than 0".to_string());
-        }
-        if width > MAX_IMAGE_DIMENSION {
-            return Err(format!(
-                "Image width {} exceeds maximum of {}",
-                width, MAX_IMAGE_DIMENSION
-            ));
-        }
-        if height > MAX_IMAGE_DIMENSION {
-            return Err(format!(
-                "Image height {} exceeds maximum of {}",
-                height, MAX_IMAGE_DIMENSION
-            ));
-        }
-        if scale == 0 || scale > MAX_SCALE_FACTOR {
-            return Err(format!(
-                "Scale factor must be between 1 and {}",
-                MAX_SCALE_FACTOR
-            ));
-        }
+        validate_static_size(width, height, scale)?;
 
+        let auto = matches!(static_type, StaticType::Auto);
         let (lon, lat, zoom, bearing, pitch) = match static_type {
             StaticType::Center {
                 lon,
@@ -301,84 +492,378 @@ impl RenderOptions {
 
                 (center_lon, center_lat, zoom, 0.0, 0.0)
             }
-            StaticType::Auto => {
-                // For auto mode, calculate bounds from paths/markers
-                let mut paths = Vec::new();
-                let mut markers = Vec::new();
-
-                if let Some(ref path_str) = query_params.path {
-                    for path_part in path_str.split('~') {
-                        if let Some(path) = crate::render::overlay::parse_path(path_part) {
-                            paths.push(path);
-                        }
-                    }
+            // Fitted to the overlays below
+            StaticType::Auto => (0.0, 0.0, 1.0, 0.0, 0.0),
+        };
+
+        let mut options = Self {
+            style_id,
+            style_json,
+            width,
+            height,
+            scale,
+            lon,
+            lat,
+            zoom,
+            bearing,
+            pitch,
+            format,
+            path: query_params.path,
+            marker: query_params.marker,
+            paths: Vec::new(),
+            markers: Vec::new(),
+            debug: DebugOptions::empty(),
+        };
+        if auto {
+            options.fit_overlays(query_params.padding, query_params.maxzoom);
+        }
+        Ok(options)
+    }
+
+    /// Add structured overlays to draw on the image
+    pub fn with_overlays(mut self, paths: Vec<PathOverlay>, markers: Vec<MarkerOverlay>) -> Self {
+        self.paths.extend(paths);
+        self.markers.extend(markers);
+        self
+    }
+
+    /// Center and zoom the view on all overlays, as for `auto` images
+    ///
+    /// `padding` is a fraction of the overlays' extent (default 0.1). Without
+    /// overlays the view shows the world.
+    pub fn fit_overlays(&mut self, padding: Option<f64>, maxzoom: Option<u8>) {
+        let mut paths = self.paths.clone();
+        let mut markers = self.markers.clone();
+
+        if let Some(ref path_str) = self.path {
+            for path_part in path_str.split('~') {
+                if let Some(path) = crate::render::overlay::parse_path(path_part) {
+                    paths.push(path);
                 }
+            }
+        }
 
-                if let Some(ref marker_str) = query_params.marker {
-                    for marker_part in marker_str.split('~') {
-                        if let Some(marker) = crate::render::overlay::parse_marker(marker_part) {
-                            markers.push(marker);
-                        }
-                    }
+        if let Some(ref marker_str) = self.marker {
+            for marker_part in marker_str.split('~') {
+                if let Some(marker) = crate::render::overlay::parse_marker(marker_part) {
+                    markers.push(marker);
                 }
+            }
+        }
 
-                if let Some((min_lon, min_lat, max_lon, max_lat)) =
-                    crate::render::overlay::calculate_bounds(&paths, &markers)
-                {
-                    // Calculate center
-                    let center_lon = (min_lon + max_lon) / 2.0;
-                    let center_lat = (min_lat + max_lat) / 2.0;
-
-                    // Calculate zoom to fit bounds with padding
-                    let padding = query_params.padding.unwrap_or(0.1);
-                    let lon_diff = (max_lon - min_lon).abs() * (1.0 + padding);
-                    let lat_diff = (max_lat - min_lat).abs() * (1.0 + padding);
-
-                    // Account for image aspect ratio
-                    let aspect = width as f64 / height as f64;
-                    let adjusted_lon_diff = lon_diff.max(lat_diff * aspect);
-                    let adjusted_lat_diff = lat_diff.max(lon_diff / aspect);
-                    let max_diff = adjusted_lon_diff.max(adjusted_lat_diff);
-
-                    let zoom = if max_diff > 180.0 {
-                        0.0
-                    } else if max_diff > 0.0 {
-                        let zoom_lon = (360.0 / max_diff).log2();
-                        let zoom_lat = (180.0 / adjusted_lat_diff).log2();
-                        let calculated_zoom = zoom_lon.min(zoom_lat).floor();
-                        // Clamp to maxzoom if specified
-                        if let Some(max_zoom) = query_params.maxzoom {
-                            calculated_zoom.min(max_zoom as f64)
-                        } else {
-                            calculated_zoom.min(18.0)
-                        }
-                    } else {
-                        // Single point, use a reasonable default zoom
-                        query_params.maxzoom.map_or(14.0, |z| z as f64)
-                    };
-
-                    (center_lon, center_lat, zoom, 0.0, 0.0)
+        let (width, height) = (self.width, self.height);
+        (self.lon, self.lat, self.zoom) = if let Some((min_lon, min_lat, max_lon, max_lat)) =
+            crate::render::overlay::calculate_bounds(&paths, &markers)
+        {
+            // Calculate center
+            let center_lon = (min_lon + max_lon) / 2.0;
+            let center_lat = (min_lat + max_lat) / 2.0;
+
+            // Calculate zoom to fit bounds with padding
+            let padding = padding.unwrap_or(0.1);
+            let lon_diff = (max_lon - min_lon).abs() * (1.0 + padding);
+            let lat_diff = (max_lat - min_lat).abs() * (1.0 + padding);
+
+            // Account for image aspect ratio
+            let aspect = width as f64 / height as f64;
+            let adjusted_lon_diff = lon_diff.max(lat_diff * aspect);
+            let adjusted_lat_diff = lat_diff.max(lon_diff / aspect);
+            let max_diff = adjusted_lon_diff.max(adjusted_lat_diff);
+
+            let zoom = if max_diff > 180.0 {
+                0.0
+            } else if max_diff > 0.0 {
+                let zoom_lon = (360.0 / max_diff).log2();
+                let zoom_lat = (180.0 / adjusted_lat_diff).log2();
+                let calculated_zoom = zoom_lon.min(zoom_lat).floor();
+                // Clamp to maxzoom if specified
+                if let Some(max_zoom) = maxzoom {
+                    calculated_zoom.min(max_zoom as f64)
                 } else {
-                    // No paths or markers, default to world view
-                    (0.0, 0.0, 1.0, 0.0, 0.0)
+                    calculated_zoom.min(18.0)
                 }
-            }
+            } else {
+                // Single point, use a reasonable default zoom
+                maxzoom.map_or(14.0, |z| z as f64)
+            };
+
+            (center_lon, center_lat, zoom)
+        } else {
+            // No paths or markers, default to world view
+            (0.0, 0.0, 1.0)
+        };
+    }
+
+    /// Refuse a camera the renderer cannot show: a latitude beyond Web
+    /// Mercator's, a longitude outside -180 to 180, or a zoom or pitch
+    /// outside the renderer's range
+    pub fn check_camera(&self) -> Result<(), TileServerError> {
+        let problem = if !(-MAX_LATITUDE..=MAX_LATITUDE).contains(&self.lat) {
+            format!("latitude {} outside ±{}", self.lat, MAX_LATITUDE)
+        } else if !(-180.0..=180.0).contains(&self.lon) {
+            format!("longitude {} outside ±180", self.lon)
+        } else if !(0.0..=MAX_RENDER_ZOOM).contains(&self.zoom) {
+            format!("zoom {} outside 0 to {}", self.zoom, MAX_RENDER_ZOOM)
+        } else if !(0.0..=MAX_RENDER_PITCH).contains(&self.pitch) {
+            format!("pitch {} outside 0 to {}", self.pitch, MAX_RENDER_PITCH)
+        } else {
+            return Ok(());
         };
+        tracing::debug!("Refused render of {}: {}", self.style_id, problem);
+        Err(TileServerError::InvalidTileRequest)
+    }
+
+    /// Create options for a static image from a JSON render request
+    pub fn for_render_request(
+        style_id: String,
+        style_json: String,
+        request: StaticRenderRequest,
+    ) -> Result<Self, String> {
+        let [width, height] = request.size;
+        validate_static_size(width, height, request.scale)?;
+
+        if request.paths.iter().any(|path| path.points.len() < 2) {
+            return Err("Paths must have at least 2 points".to_string());
+        }
+
+        let [lon, lat, zoom] = request.center;
 
         Ok(Self {
             style_id,
             style_json,
             width,
             height,
-            scale,
+            scale: request.scale,
             lon,
             lat,
             zoom,
-            bearing,
-            pitch,
-            format,
-            path: query_params.path,
-            marker: query_params.marker,
+            bearing: request.bearing,
+            pitch: request.pitch,
+            format: request.format,
+            path: None,
+            marker: None,
+            paths: request.paths,
+            markers: request.markers,
+            debug: DebugOptions::empty(),
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_debug_options() {
+        assert_eq!(
+            DebugOptions::parse("tile-borders"),
+            Ok(DebugOptions::TILE_BORDERS)
+        );
+        assert_eq!(
+            DebugOptions::parse(" Collision , overdraw,"),
+            Ok(DebugOptions::COLLISION | DebugOptions::OVERDRAW)
+        );
+        assert_eq!(
+            DebugOptions::parse("parse-status,timestamps").map(|debug| debug.bits()),
+            Ok(0b0110)
+        );
+        assert_eq!(DebugOptions::parse(""), Ok(DebugOptions::empty()));
+
+        let err = DebugOptions::parse("tile-borders,labels").unwrap_err();
+        assert!(err.contains("'labels'"), "{}", err);
+        assert!(err.contains("tile-borders, parse-status"), "{}", err);
+    }
+
+    #[test]
+    fn test_tile_view_from_query() {
+        assert_eq!(
+            TileView::from_query(None, None).unwrap(),
+            TileView::default()
+        );
+        assert!(TileView::from_query(None, None).unwrap().is_default());
+
+        let view = TileView::from_query(Some(90.0), Some(45.0)).unwrap();
+        assert_eq!((view.bearing, view.pitch), (90.0, 45.0));
+        assert!(!view.is_default());
+
+        // Equivalent views are the same view
+        assert_eq!(
+            TileView::from_query(Some(360.0), Some(-0.0)).unwrap(),
+            TileView::default()
+        );
+
+        for (bearing, pitch) in [
+            (Some(-1.0), None),
+            (Some(360.5), None),
+            (Some(f64::NAN), None),
+            (None, Some(60.1)),
+            (None, Some(-5.0)),
+        ] {
+            let err = TileView::from_query(bearing, pitch).unwrap_err();
+            assert!(matches!(err, TileServerError::BadRequest(_)), "{}", err);
+        }
+    }
+
+    fn parse_request(body: &str) -> Result<StaticRenderRequest, serde_json::Error> {
+        serde_json::from_str(body)
+    }
+
+    fn options_for(body: &str) -> Result<RenderOptions, String> {
+        let request = parse_request(body).map_err(|e| e.to_string())?;
+        RenderOptions::for_render_request("test".to_string(), "{}".to_string(), request)
+    }
+
+    #[test]
+    fn test_render_request_camera_range() {
+        let camera = |center: &str, pitch: f64| {
+            options_for(&format!(
+                r#"{{"center": {}, "size": [256, 256], "pitch": {}}}"#,
+                center, pitch
+            ))
+            .unwrap()
+            .check_camera()
+        };
+
+        assert!(camera("[-180, 85.05, 0]", 0.0).is_ok());
+        assert!(camera("[180, -85.05, 25.5]", 85.0).is_ok());
+        for (center, pitch) in [
+            ("[0, 85.06, 1]", 0.0),
+            ("[0, -90, 1]", 0.0),
+            ("[180.5, 0, 1]", 0.0),
+            ("[0, 0, -1]", 0.0),
+            ("[0, 0, 26]", 0.0),
+            ("[0, 0, 1]", 85.5),
+            ("[0, 0, 1]", -1.0),
+        ] {
+            assert!(
+                matches!(
+                    camera(center, pitch),
+                    Err(TileServerError::InvalidTileRequest)
+                ),
+                "{} pitch {}",
+                center,
+                pitch
+            );
+        }
+    }
+
+    #[test]
+    fn test_render_request_full_body() {
+        let options = options_for(
+            r##"{
+                "center": [-122.4, 37.8, 12],
+                "size": [800, 600],
+                "scale": 2,
+                "format": "jpg",
+                "bearing": 45,
+                "pitch": 30,
+                "markers": [{"position": [-122.4, 37.8], "color": "#00f", "label": "A", "size": 28}],
+                "paths": [{"points": [[-122.5, 37.7], [-122.3, 37.9]], "stroke_color": "f00", "stroke_width": 5}]
+            }"##,
+        )
+        .unwrap();
+
+        assert_eq!(
+            (options.width, options.height, options.scale),
+            (800, 600, 2.0)
+        );
+        assert_eq!(options.format, ImageFormat::Jpeg);
+        assert_eq!(
+            (options.lon, options.lat, options.zoom),
+            (-122.4, 37.8, 12.0)
+        );
+        assert_eq!((options.bearing, options.pitch), (45.0, 30.0));
+        assert_eq!(options.markers.len(), 1);
+        assert_eq!(options.markers[0].color, image::Rgba([0, 0, 255, 255]));
+        assert_eq!(options.markers[0].size, 28.0);
+        assert_eq!(options.paths.len(), 1);
+        assert_eq!(options.paths[0].stroke_color, image::Rgba([255, 0, 0, 255]));
+        assert_eq!(options.paths[0].stroke_width, 5.0);
+    }
+
+    #[test]
+    fn test_render_request_defaults() {
+        let options = options_for(r#"{"center": [0, 0, 1], "size": [256, 256]}"#).unwrap();
+
+        assert_eq!(options.scale, 1.0);
+        assert_eq!(options.format, ImageFormat::Png);
+        assert_eq!((options.bearing, options.pitch), (0.0, 0.0));
+        assert!(options.markers.is_empty());
+        assert!(options.paths.is_empty());
+
+        let request = parse_request(
+            r#"{"center": [0, 0, 1], "size": [256, 256], "markers": [{"position": [1, 2]}]}"#,
+        )
+        .unwrap();
+        assert_eq!(request.markers[0].color, image::Rgba([255, 0, 0, 255]));
+        assert_eq!(request.markers[0].size, 24.0);
+    }
+
+    #[test]
+    fn test_render_request_invalid_json() {
+        assert!(parse_request("not json").is_err());
+        assert!(parse_request(r#"{"center": [0, 0, 1], "size": [256, 256]"#).is_err());
+        assert!(parse_request(r#"{"center": "0,0,1", "size": [256, 256]}"#).is_err());
+        assert!(
+            parse_request(r#"{"center": [0, 0, 1], "size": [256, 256], "format": "gif"}"#).is_err()
+        );
+        assert!(parse_request(
+            r#"{"center": [0, 0, 1], "size": [256, 256], "markers": [{"position": [0, 0], "color": "red"}]}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_render_request_missing_required_fields() {
+        let err = parse_request(r#"{"size": [256, 256]}"#).unwrap_err();
+        assert!(err.to_string().contains("center"));
+
+        let err = parse_request(r#"{"center": [0, 0, 1]}"#).unwrap_err();
+        assert!(err.to_string().contains("size"));
+
+        // Center requires lon, lat and zoom
+        assert!(parse_request(r#"{"center": [0, 0], "size": [256, 256]}"#).is_err());
+        assert!(
+            parse_request(r#"{"center": [0, 0, 1], "size": [256, 256], "markers": [{}]}"#).is_err()
+        );
+    }
+
+    #[test]
+    fn test_render_request_max_size_validation() {
+        assert!(options_for(r#"{"center": [0, 0, 1], "size": [4096, 4096], "scale": 4}"#).is_ok());
+
+        let err = options_for(r#"{"center": [0, 0, 1], "size": [4097, 600]}"#).unwrap_err();
+        assert!(err.contains("width"));
+
+        let err = options_for(r#"{"center": [0, 0, 1], "size": [800, 4097]}"#).unwrap_err();
+        assert!(err.contains("height"));
+
+        assert!(options_for(r#"{"center": [0, 0, 1], "size": [0, 600]}"#).is_err());
+        assert!(options_for(r#"{"center": [0, 0, 1], "size": [800, 600], "scale": 9}"#).is_err());
+        assert!(options_for(r#"{"center": [0, 0, 1], "size": [800, 600], "scale": 0}"#).is_err());
+        assert!(options_for(r#"{"center": [0, 0, 1], "size": [800, 600], "scale": 0.4}"#).is_err());
+    }
+
+    #[test]
+    fn test_fractional_pixel_ratio() {
+        let options =
+            options_for(r#"{"center": [0, 0, 1], "size": [801, 600], "scale": 3.5}"#).unwrap();
+        assert_eq!(options.scale, 3.5);
+        // 801 * 3.5 = 2803.5, rounded up
+        assert_eq!(
+            output_size(options.width, options.height, options.scale),
+            (2804, 2100)
+        );
+        assert_eq!(output_size(256, 256, 0.5), (128, 128));
+        assert!(options_for(r#"{"center": [0, 0, 1], "size": [800, 600], "scale": 8}"#).is_ok());
+    }
+
+    #[test]
+    fn test_render_request_rejects_degenerate_path() {
+        let err = options_for(
+            r#"{"center": [0, 0, 1], "size": [256, 256], "paths": [{"points": [[0, 0]]}]}"#,
+        )
+        .unwrap_err();
+        assert!(err.contains("at least 2 points"));
+    }
+}
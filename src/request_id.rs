@@ -0,0 +1,119 @@
+//! Request ID propagation
+//!
+//! Every request gets an ID, taken from its `X-Request-Id` header when
+//! `[server] trust_request_id` is on and otherwise generated as a UUIDv7.
+//! The ID is stored in the request extensions, recorded on the request's
+//! tracing span (and so on its OpenTelemetry span), added to JSON error
+//! bodies, and echoed back in the `X-Request-Id` response header.
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderName, HeaderValue},
+    middleware::{self, Next},
+    response::Response,
+    Router,
+};
+use tracing::Instrument;
+
+/// Header carrying the request ID, in both directions
+pub static REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest incoming ID that is reused
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static CURRENT: RequestId;
+}
+
+/// ID of the request being served, available from the request extensions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    /// A fresh, time-ordered ID
+    pub fn generate() -> Self {
+        Self(uuid::Uuid::now_v7().to_string())
+    }
+
+    /// ID of the request the current task is serving, if any
+    pub fn current() -> Option<RequestId> {
+        CURRENT.try_with(RequestId::clone).ok()
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Wrap `router` so every request gets an ID
+pub fn apply(router: Router, trust_incoming: bool) -> Router {
+    router.layer(middleware::from_fn_with_state(trust_incoming, request_id))
+}
+
+/// Middleware that assigns the request ID and echoes it in the response
+pub async fn request_id(
+    State(trust_incoming): State<bool>,
+    mut request: Request<Body>,
+    next: Next,
+) -> Response {
+    let id = trust_incoming
+        .then(|| incoming_id(&request))
+        .flatten()
+        .unwrap_or_else(RequestId::generate);
+    request.extensions_mut().insert(id.clone());
+
+    let span = crate::telemetry::request_span(&request, id.as_str());
+    let mut response = CURRENT
+        .scope(id.clone(), next.run(request).instrument(span))
+        .await;
+
+    if let Ok(value) = HeaderValue::from_str(id.as_str()) {
+        response
+            .headers_mut()
+            .insert(REQUEST_ID_HEADER.clone(), value);
+    }
+    response
+}
+
+/// The client's ID, if it is short and printable enough to log safely
+fn incoming_id(request: &Request<Body>) -> Option<RequestId> {
+    let value = request.headers().get(&REQUEST_ID_HEADER)?.to_str().ok()?;
+    let valid = !value.is_empty()
+        && value.len() <= MAX_REQUEST_ID_LEN
+        && value.bytes().all(|b| b.is_ascii_graphic());
+    valid.then(|| RequestId(value.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(id: Option<&str>) -> Request<Body> {
+        let mut builder = Request::builder().uri("/");
+        if let Some(id) = id {
+            builder = builder.header("x-request-id", id);
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn test_incoming_id() {
+        assert_eq!(
+            incoming_id(&request(Some("abc-123"))),
+            Some(RequestId("abc-123".into()))
+        );
+        assert_eq!(incoming_id(&request(None)), None);
+        assert_eq!(incoming_id(&request(Some(""))), None);
+        assert_eq!(incoming_id(&request(Some("two words"))), None);
+        assert_eq!(incoming_id(&request(Some(&"a".repeat(129)))), None);
+    }
+
+    #[test]
+    fn test_generated_ids_are_uuid_v7() {
+        let id = RequestId::generate();
+        let uuid = uuid::Uuid::parse_str(id.as_str()).unwrap();
+        assert_eq!(uuid.get_version_num(), 7);
+        assert_ne!(RequestId::generate(), id);
+    }
+}
@@ -1,26 +1,53 @@
 use async_trait::async_trait;
 use bytes::Bytes;
-use gdal::raster::{Buffer, ResampleAlg};
+use gdal::raster::Buffer;
 use gdal::spatial_ref::SpatialRef;
-use gdal::{Dataset, DriverManager};
+use gdal::{Dataset, DatasetOptions, DriverManager};
 use image::{ImageBuffer, RgbaImage};
+use std::ffi::CStr;
 use std::io::Cursor;
 use std::path::Path;
+use std::ptr;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
-use crate::config::{ColorMapConfig, ResamplingMethod, SourceConfig};
+use crate::config::{
+    ColorMapConfig, DemEncoding, DerivedProduct, ReliefConfig, ResamplingMethod, SourceConfig,
+    SourceType,
+};
 use crate::error::{Result, TileServerError};
+use crate::sources::{relief, terrain};
 use crate::sources::{TileCompression, TileData, TileFormat, TileMetadata, TileSource};
 
 const WEB_MERCATOR_EXTENT: f64 = 20037508.342789244;
 
+/// Relative slack when comparing resolutions, so an overview exactly as
+/// fine as a tile is not lost to rounding
+const RESOLUTION_TOLERANCE: f64 = 1e-6;
+
 pub struct CogSource {
     dataset: Arc<Mutex<Dataset>>,
+    /// Overviews of the dataset, each opened as a dataset of its own
+    overviews: Vec<Overview>,
+    /// Log2 of how much coarser than a tile's pixels an overview may be
+    overview_bias: f64,
     metadata: TileMetadata,
     default_resampling: ResamplingMethod,
     band_count: usize,
     colormap: Option<ColorMapConfig>,
+    /// Serve the first band as elevation tiles in this encoding
+    encoding: Option<DemEncoding>,
+    /// Elevation written for no-data pixels of elevation tiles
+    nodata_elevation: f64,
+    /// Hillshade or slope served instead of the first band
+    relief: Option<(DerivedProduct, ReliefConfig)>,
+}
+
+/// A reduced-resolution copy of a COG or VRT
+struct Overview {
+    dataset: Arc<Mutex<Dataset>>,
+    /// Ground size of a pixel, in Web Mercator meters
+    resolution: f64,
 }
 
 impl CogSource {
@@ -31,8 +58,50 @@ impl CogSource {
         let attribution = config.attribution.clone();
         let resampling = config.resampling.unwrap_or_default();
         let colormap = config.colormap.clone();
+        let encoding = match (&config.source_type, config.encoding) {
+            (SourceType::TerrainRgb, Some(DemEncoding::Terrarium)) => {
+                return Err(TileServerError::ConfigError(format!(
+                    "Source '{}': terrain-rgb sources are always Terrain-RGB; use type = \"cog\" with encoding = \"terrarium\"",
+                    config.id
+                )));
+            }
+            (SourceType::TerrainRgb, _) => Some(DemEncoding::TerrainRgb),
+            (_, encoding) => encoding,
+        };
+        let relief = config
+            .relief
+            .derived
+            .map(|product| (product, config.relief.clone()));
+        if relief.is_some() {
+            if encoding.is_some() {
+                return Err(TileServerError::ConfigError(format!(
+                    "Source '{}': `derived` is not supported by terrain-rgb sources or sources with an `encoding`",
+                    config.id
+                )));
+            }
+            if !(0.0..=90.0).contains(&config.relief.altitude) {
+                return Err(TileServerError::ConfigError(format!(
+                    "Source '{}': `altitude` must be between 0 and 90 degrees",
+                    config.id
+                )));
+            }
+            if !config.relief.z_factor.is_finite() || config.relief.z_factor <= 0.0 {
+                return Err(TileServerError::ConfigError(format!(
+                    "Source '{}': `z_factor` must be a positive number",
+                    config.id
+                )));
+            }
+        }
+
+        let overview_bias = config.overview_bias.unwrap_or(0.0);
+        if !overview_bias.is_finite() {
+            return Err(TileServerError::ConfigError(format!(
+                "Source '{}': `overview_bias` must be a number",
+                config.id
+            )));
+        }
 
-        let (dataset, band_count, bounds) = tokio::task::spawn_blocking(move || {
+        let (dataset, band_count, bounds, overviews) = tokio::task::spawn_blocking(move || {
             let dataset = Dataset::open(Path::new(&path)).map_err(|e| {
                 TileServerError::RasterError(format!("Failed to open COG file: {}", e))
             })?;
@@ -46,7 +115,13 @@ impl CogSource {
 
             let bounds = get_wgs84_bounds(&dataset)?;
 
-            Ok::<_, TileServerError>((dataset, band_count, bounds))
+            // Web Mercator meters are degrees of longitude scaled by
+            // the radius of the sphere
+            let width =
+                (bounds[2] - bounds[0]).to_radians() * WEB_MERCATOR_EXTENT / std::f64::consts::PI;
+            let overviews = open_overviews(&dataset, &path, width)?;
+
+            Ok::<_, TileServerError>((dataset, band_count, bounds, overviews))
         })
         .await
         .map_err(|e| TileServerError::RasterError(format!("Task failed: {}", e)))??;
@@ -66,14 +141,22 @@ impl CogSource {
                 10.0,
             ]),
             vector_layers: None,
+            scheme: config.scheme,
+            overzoom: config.overzoom,
+            encoding,
         };
 
         Ok(Self {
             dataset: Arc::new(Mutex::new(dataset)),
+            overviews,
+            overview_bias,
             metadata,
             default_resampling: resampling,
             band_count,
             colormap,
+            encoding,
+            nodata_elevation: config.nodata_elevation.unwrap_or(0.0),
+            relief,
         })
     }
 
@@ -81,6 +164,41 @@ impl CogSource {
         self.default_resampling
     }
 
+    /// Overview a `tile_size` pixel tile at zoom `z` is read from, counted
+    /// from 0 for the finest, or `None` for the full-resolution dataset
+    pub fn overview_for(&self, z: u8, tile_size: u32) -> Option<usize> {
+        let tile_resolution =
+            2.0 * WEB_MERCATOR_EXTENT / f64::from(z).exp2() / f64::from(tile_size);
+        self.overview_for_resolution(tile_resolution)
+    }
+
+    fn overview_for_resolution(&self, tile_resolution: f64) -> Option<usize> {
+        let resolutions: Vec<f64> = self.overviews.iter().map(|o| o.resolution).collect();
+        select_overview(&resolutions, tile_resolution, self.overview_bias)
+    }
+
+    /// Dataset to read the pixels of `bbox` from, `tile_size` pixels wide
+    fn dataset_for(
+        &self,
+        (minx, _, maxx, _): (f64, f64, f64, f64),
+        tile_size: u32,
+    ) -> Arc<Mutex<Dataset>> {
+        match self.overview_for_resolution((maxx - minx) / f64::from(tile_size)) {
+            Some(index) => {
+                tracing::debug!(
+                    "Reading {} from overview {} of {}",
+                    self.metadata.id,
+                    index,
+                    self.overviews.len()
+                );
+                self.overviews[index].dataset.clone()
+            }
+            None => self.dataset.clone(),
+        }
+    }
+
+    /// Get a tile at `tile_size` pixels, with the row addressed in the
+    /// source's scheme like `get_tile`
     pub async fn get_tile_with_resampling(
         &self,
         z: u8,
@@ -89,24 +207,120 @@ impl CogSource {
         tile_size: u32,
         resampling: ResamplingMethod,
     ) -> Result<Option<TileData>> {
+        let y = self.metadata.xyz_row(z, x, y)?;
+        self.get_xyz_tile_with_resampling(z, x, y, tile_size, resampling)
+            .await
+    }
+
+    async fn get_xyz_tile_with_resampling(
+        &self,
+        z: u8,
+        x: u32,
+        y: u32,
+        tile_size: u32,
+        resampling: ResamplingMethod,
+    ) -> Result<Option<TileData>> {
+        let max_tile = 1u32 << z;
+        if x >= max_tile || y >= max_tile {
+            return Err(TileServerError::InvalidCoordinates { z, x, y });
+        }
+
         let (minx, miny, maxx, maxy) = tile_to_web_mercator_bbox(z, x, y);
 
-        let dataset = self.dataset.clone();
+        if let Some(encoding) = self.encoding {
+            return self
+                .get_elevation_tile_in_bbox(
+                    (minx, miny, maxx, maxy),
+                    tile_size,
+                    resampling,
+                    encoding,
+                )
+                .await;
+        }
+
+        let dataset = self.dataset_for((minx, miny, maxx, maxy), tile_size);
         let band_count = self.band_count;
         let colormap = self.colormap.clone();
+        let relief = self.relief.clone();
+
+        let png_data = tokio::task::spawn_blocking(move || {
+            let dataset = dataset.blocking_lock();
+            match relief {
+                Some((product, relief)) => render_relief_from_dataset(
+                    &dataset,
+                    (minx, miny, maxx, maxy),
+                    tile_size,
+                    resampling,
+                    product,
+                    &relief,
+                    colormap.as_ref(),
+                ),
+                None => render_tile_from_dataset(
+                    &dataset,
+                    minx,
+                    miny,
+                    maxx,
+                    maxy,
+                    tile_size,
+                    band_count,
+                    resampling,
+                    colormap.as_ref(),
+                ),
+            }
+        })
+        .await
+        .map_err(|e| TileServerError::RasterError(format!("Task failed: {}", e)))??;
+
+        Ok(Some(TileData {
+            data: Bytes::from(png_data),
+            format: TileFormat::Png,
+            compression: TileCompression::None,
+        }))
+    }
+
+    /// Encode the first band as a Terrain-RGB tile, whatever the source type
+    pub async fn get_terrain_tile(
+        &self,
+        z: u8,
+        x: u32,
+        y: u32,
+        tile_size: u32,
+    ) -> Result<Option<TileData>> {
+        let max_tile = 1u32 << z;
+        if x >= max_tile || y >= max_tile {
+            return Err(TileServerError::InvalidCoordinates { z, x, y });
+        }
+
+        let y = self.metadata.xyz_row(z, x, y)?;
+        let bbox = tile_to_web_mercator_bbox(z, x, y);
+        self.get_elevation_tile_in_bbox(
+            bbox,
+            tile_size,
+            self.default_resampling,
+            DemEncoding::TerrainRgb,
+        )
+        .await
+    }
+
+    async fn get_elevation_tile_in_bbox(
+        &self,
+        bbox: (f64, f64, f64, f64),
+        tile_size: u32,
+        resampling: ResamplingMethod,
+        encoding: DemEncoding,
+    ) -> Result<Option<TileData>> {
+        let dataset = self.dataset_for(bbox, tile_size);
+        let nodata_elevation = self.nodata_elevation;
 
         let png_data = tokio::task::spawn_blocking(move || {
             let dataset = dataset.blocking_lock();
-            render_tile_from_dataset(
+            render_elevation_from_dataset(
                 &dataset,
-                minx,
-                miny,
-                maxx,
-                maxy,
+                bbox,
                 tile_size,
-                band_count,
-                resampling.into(),
-                colormap.as_ref(),
+                resampling,
+                nodata_elevation,
+                encoding,
             )
         })
         .await
@@ -122,8 +336,8 @@ impl CogSource {
 
 #[async_trait]
 impl TileSource for CogSource {
-    async fn get_tile(&self, z: u8, x: u32, y: u32) -> Result<Option<TileData>> {
-        self.get_tile_with_resampling(z, x, y, 256, self.default_resampling)
+    async fn get_xyz_tile(&self, z: u8, x: u32, y: u32) -> Result<Option<TileData>> {
+        self.get_xyz_tile_with_resampling(z, x, y, 256, self.default_resampling)
             .await
     }
 
@@ -131,6 +345,10 @@ impl TileSource for CogSource {
         &self.metadata
     }
 
+    fn backend(&self) -> &'static str {
+        "cog"
+    }
+
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
@@ -192,27 +410,118 @@ fn get_wgs84_bounds(dataset: &Dataset) -> Result<[f64; 4]> {
     Ok([min_lon, min_lat, max_lon, max_lat])
 }
 
-#[allow(clippy::too_many_arguments)]
-fn render_tile_from_dataset(
+/// Open each overview of `dataset` on its own, with its resolution given
+/// the dataset is `width` Web Mercator meters wide
+fn open_overviews(dataset: &Dataset, path: &str, width: f64) -> Result<Vec<Overview>> {
+    let band = dataset
+        .rasterband(1)
+        .map_err(|e| TileServerError::RasterError(format!("Failed to get band: {}", e)))?;
+    let count = band.overview_count().unwrap_or(0).max(0) as usize;
+
+    (0..count)
+        .map(|level| {
+            let overview_width = band
+                .overview(level)
+                .map_err(|e| {
+                    TileServerError::RasterError(format!("Failed to get overview {}: {}", level, e))
+                })?
+                .x_size();
+            let open_option = format!("OVERVIEW_LEVEL={}", level);
+            let dataset = Dataset::open_ex(
+                Path::new(path),
+                DatasetOptions {
+                    open_options: Some(&[open_option.as_str()]),
+                    ..Default::default()
+                },
+            )
+            .map_err(|e| {
+                TileServerError::RasterError(format!("Failed to open overview {}: {}", level, e))
+            })?;
+            Ok(Overview {
+                dataset: Arc::new(Mutex::new(dataset)),
+                resolution: width / overview_width as f64,
+            })
+        })
+        .collect()
+}
+
+/// Overview to read a tile with pixels `tile_resolution` wide from, or
+/// `None` for the full-resolution dataset
+///
+/// The coarsest overview no coarser than the tile's pixels times
+/// `2^bias` is picked: at a bias of 0 tiles are never magnified from an
+/// overview, above 0 coarser overviews trade detail for speed, below 0
+/// finer ones are used. When even the finest overview is too coarse, the
+/// full resolution is read.
+fn select_overview(overviews: &[f64], tile_resolution: f64, bias: f64) -> Option<usize> {
+    let limit = tile_resolution * bias.exp2() * (1.0 + RESOLUTION_TOLERANCE);
+    overviews
+        .iter()
+        .enumerate()
+        .filter(|(_, &overview)| overview <= limit)
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(index, _)| index)
+}
+
+/// Warp `src` into `dst` with `resampling`
+///
+/// `gdal::raster::reproject` always resamples bilinearly.
+fn reproject(src: &Dataset, dst: &Dataset, resampling: ResamplingMethod) -> Result<()> {
+    use gdal_sys::GDALResampleAlg::*;
+    let algorithm = match resampling {
+        ResamplingMethod::Nearest => GRA_NearestNeighbour,
+        ResamplingMethod::Bilinear => GRA_Bilinear,
+        ResamplingMethod::Cubic => GRA_Cubic,
+        ResamplingMethod::CubicSpline => GRA_CubicSpline,
+        ResamplingMethod::Lanczos => GRA_Lanczos,
+        ResamplingMethod::Average => GRA_Average,
+        ResamplingMethod::Mode => GRA_Mode,
+    };
+
+    // SAFETY: both dataset handles stay valid while borrowed, and null
+    // WKTs, progress callback and warp options select GDAL's defaults
+    let code = unsafe {
+        gdal_sys::GDALReprojectImage(
+            src.c_dataset(),
+            ptr::null(),
+            dst.c_dataset(),
+            ptr::null(),
+            algorithm,
+            0.0,
+            0.0,
+            None,
+            ptr::null_mut(),
+            ptr::null_mut(),
+        )
+    };
+    if code != gdal_sys::CPLErr::CE_None {
+        // SAFETY: GDAL returns a valid, possibly empty, C string
+        let message = unsafe { CStr::from_ptr(gdal_sys::CPLGetLastErrorMsg()) };
+        return Err(TileServerError::RasterError(format!(
+            "Failed to reproject/warp: {}",
+            message.to_string_lossy()
+        )));
+    }
+    Ok(())
+}
+
+/// Warp `dataset` into a `tile_size` square in Web Mercator covering `bbox`
+///
+/// Pixels not covered by the source keep `fill`, or 0 if unset.
+fn warp_to_tile(
     dataset: &Dataset,
-    minx: f64,
-    miny: f64,
-    maxx: f64,
-    maxy: f64,
+    (minx, miny, maxx, maxy): (f64, f64, f64, f64),
     tile_size: u32,
-    band_count: usize,
-    resampling: ResampleAlg,
-    colormap: Option<&ColorMapConfig>,
-) -> Result<Vec<u8>> {
+    output_bands: usize,
+    fill: Option<f64>,
+    resampling: ResamplingMethod,
+) -> Result<Dataset> {
     let web_mercator = SpatialRef::from_epsg(3857)
         .map_err(|e| TileServerError::RasterError(format!("Failed to create EPSG:3857: {}", e)))?;
 
     let mem_driver = DriverManager::get_driver_by_name("MEM")
         .map_err(|e| TileServerError::RasterError(format!("Failed to get MEM driver: {}", e)))?;
 
-    let use_colormap = colormap.is_some() && band_count == 1;
-    let output_bands = if use_colormap { 1 } else { band_count.min(4) };
-
     let mut warped = mem_driver
         .create_with_band_type::<f64, _>("", tile_size as usize, tile_size as usize, output_bands)
         .map_err(|e| TileServerError::RasterError(format!("Failed to create output: {}", e)))?;
@@ -228,8 +537,166 @@ fn render_tile_from_dataset(
         .set_spatial_ref(&web_mercator)
         .map_err(|e| TileServerError::RasterError(format!("Failed to set SRS: {}", e)))?;
 
-    gdal::raster::reproject(dataset, &warped)
-        .map_err(|e| TileServerError::RasterError(format!("Failed to reproject/warp: {}", e)))?;
+    if let Some(fill) = fill {
+        for band_idx in 1..=output_bands {
+            let mut band = warped.rasterband(band_idx).map_err(|e| {
+                TileServerError::RasterError(format!("Failed to get band {}: {}", band_idx, e))
+            })?;
+            band.fill(fill, None)
+                .map_err(|e| TileServerError::RasterError(format!("Failed to fill band: {}", e)))?;
+        }
+    }
+
+    reproject(dataset, &warped, resampling)?;
+
+    Ok(warped)
+}
+
+/// Render the first band of an elevation dataset as a Terrain-RGB or
+/// Terrarium PNG
+///
+/// Pixels outside the dataset or equal to its no-data value are encoded as
+/// `nodata_elevation`.
+fn render_elevation_from_dataset(
+    dataset: &Dataset,
+    bbox: (f64, f64, f64, f64),
+    tile_size: u32,
+    resampling: ResamplingMethod,
+    nodata_elevation: f64,
+    encoding: DemEncoding,
+) -> Result<Vec<u8>> {
+    let nodata = dataset
+        .rasterband(1)
+        .map_err(|e| TileServerError::RasterError(format!("Failed to get band: {}", e)))?
+        .no_data_value();
+
+    let warped = warp_to_tile(dataset, bbox, tile_size, 1, Some(f64::NAN), resampling)?;
+    let band = warped
+        .rasterband(1)
+        .map_err(|e| TileServerError::RasterError(format!("Failed to get band: {}", e)))?;
+
+    let buffer: Buffer<f64> = band
+        .read_as::<f64>(
+            (0, 0),
+            (tile_size as usize, tile_size as usize),
+            (tile_size as usize, tile_size as usize),
+            None,
+        )
+        .map_err(|e| TileServerError::RasterError(format!("Failed to read band: {}", e)))?;
+
+    terrain::elevation_png(buffer.data(), tile_size, nodata, nodata_elevation, encoding)
+}
+
+/// Render the hillshade or slope of the first band of an elevation dataset
+///
+/// The elevations are read one pixel past each tile edge, so edge pixels
+/// are derived from the same neighbours as in the adjacent tiles. With a
+/// colormap, hillshade levels (0 to 255) or slopes (degrees) are colored
+/// through it; otherwise the tile is grayscale.
+fn render_relief_from_dataset(
+    dataset: &Dataset,
+    (minx, miny, maxx, maxy): (f64, f64, f64, f64),
+    tile_size: u32,
+    resampling: ResamplingMethod,
+    product: DerivedProduct,
+    config: &ReliefConfig,
+    colormap: Option<&ColorMapConfig>,
+) -> Result<Vec<u8>> {
+    let nodata = dataset
+        .rasterband(1)
+        .map_err(|e| TileServerError::RasterError(format!("Failed to get band: {}", e)))?
+        .no_data_value();
+
+    let pixel_size = (maxx - minx) / tile_size as f64;
+    let buffered = (
+        minx - pixel_size,
+        miny - pixel_size,
+        maxx + pixel_size,
+        maxy + pixel_size,
+    );
+    let buffered_size = tile_size as usize + 2;
+    let warped = warp_to_tile(
+        dataset,
+        buffered,
+        tile_size + 2,
+        1,
+        Some(f64::NAN),
+        resampling,
+    )?;
+    let band = warped
+        .rasterband(1)
+        .map_err(|e| TileServerError::RasterError(format!("Failed to get band: {}", e)))?;
+
+    let buffer: Buffer<f64> = band
+        .read_as::<f64>(
+            (0, 0),
+            (buffered_size, buffered_size),
+            (buffered_size, buffered_size),
+            None,
+        )
+        .map_err(|e| TileServerError::RasterError(format!("Failed to read band: {}", e)))?;
+
+    let elevations: Vec<f64> = buffer
+        .data()
+        .iter()
+        .map(|&e| if Some(e) == nodata { f64::NAN } else { e })
+        .collect();
+    let cell_sizes = relief::mercator_cell_sizes(maxy, pixel_size, tile_size as usize);
+    let values = relief::derive(
+        &elevations,
+        tile_size as usize,
+        &cell_sizes,
+        product,
+        config,
+    )?;
+
+    let Some(cmap) = colormap else {
+        return relief::grayscale_png(&values, tile_size, product);
+    };
+
+    let nodata_color = cmap
+        .nodata_color
+        .as_deref()
+        .and_then(ColorMapConfig::parse_color)
+        .unwrap_or([0, 0, 0, 0]);
+    let mut img: RgbaImage = ImageBuffer::new(tile_size, tile_size);
+    for (pixel, &value) in img.pixels_mut().zip(&values) {
+        *pixel = image::Rgba(if value.is_nan() {
+            nodata_color
+        } else {
+            cmap.get_color(value)
+        });
+    }
+
+    let mut png_data = Vec::new();
+    img.write_to(&mut Cursor::new(&mut png_data), image::ImageFormat::Png)
+        .map_err(|e| TileServerError::RasterError(format!("Failed to encode PNG: {}", e)))?;
+    Ok(png_data)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn render_tile_from_dataset(
+    dataset: &Dataset,
+    minx: f64,
+    miny: f64,
+    maxx: f64,
+    maxy: f64,
+    tile_size: u32,
+    band_count: usize,
+    resampling: ResamplingMethod,
+    colormap: Option<&ColorMapConfig>,
+) -> Result<Vec<u8>> {
+    let use_colormap = colormap.is_some() && band_count == 1;
+    let output_bands = if use_colormap { 1 } else { band_count.min(4) };
+
+    let warped = warp_to_tile(
+        dataset,
+        (minx, miny, maxx, maxy),
+        tile_size,
+        output_bands,
+        None,
+        resampling,
+    )?;
 
     let mut img: RgbaImage = ImageBuffer::new(tile_size, tile_size);
 
@@ -244,7 +711,7 @@ fn render_tile_from_dataset(
                 (0, 0),
                 (tile_size as usize, tile_size as usize),
                 (tile_size as usize, tile_size as usize),
-                Some(resampling),
+                None,
             )
             .map_err(|e| TileServerError::RasterError(format!("Failed to read band: {}", e)))?;
 
@@ -267,7 +734,7 @@ fn render_tile_from_dataset(
                     (0, 0),
                     (tile_size as usize, tile_size as usize),
                     (tile_size as usize, tile_size as usize),
-                    Some(resampling),
+                    None,
                 )
                 .map_err(|e| TileServerError::RasterError(format!("Failed to read band: {}", e)))?;
 
@@ -337,4 +804,26 @@ mod tests {
         assert!((maxx - 0.0).abs() < 1e-6);
         assert!((miny - 0.0).abs() < 1e-6);
     }
+
+    #[test]
+    fn test_select_overview() {
+        // Overviews of a 1 m dataset, at 2, 4 and 8 m
+        let overviews = [2.0, 4.0, 8.0];
+        let select =
+            |tile_resolution: f64, bias: f64| select_overview(&overviews, tile_resolution, bias);
+
+        assert_eq!(select(1.0, 0.0), None);
+        assert_eq!(select(1.9, 0.0), None);
+        assert_eq!(select(2.0, 0.0), Some(0));
+        assert_eq!(select(5.0, 0.0), Some(1));
+        assert_eq!(select(100.0, 0.0), Some(2));
+
+        // A positive bias reads coarser overviews, a negative one finer
+        assert_eq!(select(2.0, 1.0), Some(1));
+        assert_eq!(select(1.0, 1.0), Some(0));
+        assert_eq!(select(5.0, -1.0), Some(0));
+        assert_eq!(select(2.0, -1.0), None);
+
+        assert_eq!(select_overview(&[], 100.0, 0.0), None);
+    }
 }
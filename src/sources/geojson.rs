@@ -0,0 +1,716 @@
+//! GeoJSON vector tile source
+//!
+//! Serves the features of a GeoJSON file as vector tiles built on request,
+//! in one layer named after the source. The file is read into memory when
+//! the source is loaded: its features are projected to Web Mercator once,
+//! and the ones within a tile are clipped to it and encoded as MVT with
+//! their properties.
+//!
+//! Once clipped, lines and polygons are simplified with `geo`'s
+//! Douglas-Peucker [`Simplify`] and a tolerance of
+//! `simplify_tolerance_factor` pixels in tile units, which covers more of
+//! the map at low zooms, up to `simplify_max_zoom`. Detailed data such as
+//! coastlines keeps small low zoom tiles this way.
+
+use async_trait::async_trait;
+use geo::{LineString, Simplify};
+use geozero::mvt::tile::{Feature, GeomType, Layer, Value};
+use geozero::mvt::{Message, Tile};
+use serde_json::Value as Json;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::config::SourceConfig;
+use crate::error::{Result, TileServerError};
+use crate::sources::overzoom::{
+    clip_lines, clip_points, clip_polygons, encode_geometry, ring_area, Point,
+};
+use crate::sources::{TileCompression, TileData, TileFormat, TileMetadata, TileSource};
+
+/// Extent of the layer
+const EXTENT: u32 = 4096;
+
+/// Geometry outside the tile kept for clean edges, in tile units, as the
+/// default of PostgreSQL sources
+const BUFFER: i64 = 64;
+
+/// Zoom levels tiles are built for
+const MIN_ZOOM: u8 = 0;
+const MAX_ZOOM: u8 = 22;
+
+/// Latitude limit of Web Mercator
+const MAX_LATITUDE: f64 = 85.051_128_779_806_59;
+
+/// Tile units to a pixel of a 256 pixel tile
+const UNITS_PER_PIXEL: f64 = EXTENT as f64 / 256.0;
+
+/// A longitude and latitude, or a position on the world square
+type Coord = [f64; 2];
+
+/// Parts of one kind of a feature's geometry
+#[derive(Debug)]
+enum Geometry {
+    Points(Vec<Coord>),
+    Lines(Vec<Vec<Coord>>),
+    /// Polygons, each an exterior ring followed by its holes
+    Polygons(Vec<Vec<Vec<Coord>>>),
+}
+
+/// Position of a longitude and latitude on the Web Mercator world square, 0
+/// to 1 from the top left
+fn project([lon, lat]: Coord) -> Coord {
+    let lat = lat.clamp(-MAX_LATITUDE, MAX_LATITUDE).to_radians();
+    [(lon + 180.0) / 360.0, (1.0 - lat.tan().asinh() / PI) / 2.0]
+}
+
+/// Parts of a geometry clipped to the tile, in its coordinates
+///
+/// Lines and rings are simplified with `tolerance`, in tile units, after
+/// clipping. Rings are oriented as MVT expects, exterior rings with a
+/// positive area, and the ones simplification collapses are dropped with
+/// their holes.
+fn tile_parts(
+    geometry: &Geometry,
+    to_tile: impl Fn(&Coord) -> Point,
+    tolerance: f64,
+) -> (GeomType, Vec<Vec<Point>>) {
+    let bounds = [
+        -BUFFER,
+        -BUFFER,
+        EXTENT as i64 + BUFFER,
+        EXTENT as i64 + BUFFER,
+    ];
+    let line = |coords: &[Coord]| {
+        let mut points: Vec<Point> = coords.iter().map(&to_tile).collect();
+        points.dedup();
+        points
+    };
+
+    match geometry {
+        Geometry::Points(points) => (
+            GeomType::Point,
+            clip_points(vec![points.iter().map(&to_tile).collect()], bounds),
+        ),
+        Geometry::Lines(lines) => {
+            let lines = lines.iter().map(|l| line(l)).filter(|l| l.len() >= 2);
+            let lines = clip_lines(lines.collect(), bounds)
+                .into_iter()
+                .map(|l| simplify(l, tolerance, false))
+                .filter(|l| l.len() >= 2);
+            (GeomType::Linestring, lines.collect())
+        }
+        Geometry::Polygons(polygons) => {
+            let mut rings = Vec::new();
+            for polygon in polygons {
+                for (i, ring) in polygon.iter().enumerate() {
+                    let mut ring = line(ring);
+                    if ring.len() > 1 && ring.first() == ring.last() {
+                        ring.pop();
+                    }
+                    let area = if ring.len() >= 3 { ring_area(&ring) } else { 0 };
+                    if area == 0 {
+                        // Holes of a collapsed exterior go with it
+                        if i == 0 {
+                            break;
+                        }
+                        continue;
+                    }
+                    if (i == 0) != (area > 0) {
+                        ring.reverse();
+                    }
+                    rings.push(ring);
+                }
+            }
+
+            let mut simplified = Vec::new();
+            let mut exterior_kept = false;
+            for ring in clip_polygons(rings, bounds) {
+                let exterior = ring_area(&ring) > 0;
+                if !exterior && !exterior_kept {
+                    continue;
+                }
+                let ring = simplify(ring, tolerance, true);
+                let kept = ring.len() >= 3 && (ring_area(&ring) > 0) == exterior;
+                if exterior {
+                    exterior_kept = kept;
+                }
+                if kept {
+                    simplified.push(ring);
+                }
+            }
+            (GeomType::Polygon, simplified)
+        }
+    }
+}
+
+/// Douglas-Peucker simplification of a line, or of a ring without its
+/// closing point, with `tolerance` in tile units
+fn simplify(points: Vec<Point>, tolerance: f64, ring: bool) -> Vec<Point> {
+    if tolerance <= 0.0 || points.len() < 3 {
+        return points;
+    }
+
+    let mut line: LineString = points.iter().map(|&[x, y]| (x as f64, y as f64)).collect();
+    if ring {
+        line.close();
+    }
+    let mut points: Vec<Point> = line
+        .simplify(tolerance)
+        .into_iter()
+        .map(|c| [c.x as i64, c.y as i64])
+        .collect();
+    if ring {
+        points.pop();
+    }
+    points
+}
+
+/// A feature of the file
+#[derive(Debug)]
+struct GeoJsonFeature {
+    /// The feature's `id`, if it is a non-negative integer
+    id: Option<u64>,
+    /// Points, lines and polygons on the Web Mercator world square, 0 to 1
+    /// from the top left; geometry collections have one of each kind
+    geometries: Vec<Geometry>,
+    /// `[min_x, min_y, max_x, max_y]` of `geometries`
+    extent: [f64; 4],
+    /// Indexes of the keys and values of the feature's properties
+    tags: Vec<(u32, u32)>,
+}
+
+/// Features of the file and the property keys and values they share
+#[derive(Debug, Default)]
+struct FeatureCollection {
+    features: Vec<GeoJsonFeature>,
+    keys: Vec<String>,
+    values: Vec<Value>,
+    /// `vector_layers` field type of each key
+    fields: serde_json::Map<String, Json>,
+    /// Longitude and latitude bounds of the features
+    bounds: Option<[f64; 4]>,
+}
+
+impl FeatureCollection {
+    /// Read the features of a FeatureCollection, a Feature or a geometry,
+    /// skipping features without a valid geometry
+    fn read(json: &Json) -> Self {
+        let mut collection = Self::default();
+        let mut key_index = HashMap::new();
+        let mut value_index = HashMap::new();
+        let features: Vec<&Json> = match json["type"].as_str() {
+            Some("FeatureCollection") => json["features"]
+                .as_array()
+                .map(|features| features.iter().collect())
+                .unwrap_or_default(),
+            _ => vec![json],
+        };
+
+        let mut skipped = 0;
+        for feature in features {
+            let (geometry, properties) = match feature["type"].as_str() {
+                Some("Feature") => (&feature["geometry"], feature["properties"].as_object()),
+                _ => (feature, None),
+            };
+            let mut parts = Parts::default();
+            if !parts.read(geometry) {
+                skipped += 1;
+                continue;
+            }
+            let Some(extent) = parts.extent(&mut collection.bounds) else {
+                continue;
+            };
+
+            let mut tags = Vec::new();
+            for (key, value) in properties.into_iter().flatten() {
+                // Keyed by type and value, as `Value` holds floats
+                let (index_key, field, value) = match value {
+                    Json::Null => continue,
+                    Json::Bool(v) => (
+                        format!("b{}", v),
+                        "Boolean",
+                        Value {
+                            bool_value: Some(*v),
+                            ..Default::default()
+                        },
+                    ),
+                    Json::Number(v) => match v.as_i64() {
+                        Some(v) => (
+                            format!("i{}", v),
+                            "Number",
+                            Value {
+                                int_value: Some(v),
+                                ..Default::default()
+                            },
+                        ),
+                        None => {
+                            let v = v.as_f64().unwrap_or_default();
+                            (
+                                format!("d{}", v.to_bits()),
+                                "Number",
+                                Value {
+                                    double_value: Some(v),
+                                    ..Default::default()
+                                },
+                            )
+                        }
+                    },
+                    // Nested objects and arrays are kept as JSON text
+                    _ => {
+                        let text = match value {
+                            Json::String(text) => text.clone(),
+                            _ => value.to_string(),
+                        };
+                        (
+                            format!("s{}", text),
+                            "String",
+                            Value {
+                                string_value: Some(text),
+                                ..Default::default()
+                            },
+                        )
+                    }
+                };
+
+                let next = collection.keys.len() as u32;
+                let key_id = *key_index.entry(key.clone()).or_insert(next);
+                if key_id == next {
+                    collection.keys.push(key.clone());
+                    collection.fields.insert(key.clone(), field.into());
+                } else if collection.fields[key] != field {
+                    // Keys of mixed types are declared as strings
+                    collection.fields.insert(key.clone(), "String".into());
+                }
+                let next = collection.values.len() as u32;
+                let value_id = *value_index.entry(index_key).or_insert(next);
+                if value_id == next {
+                    collection.values.push(value);
+                }
+                tags.push((key_id, value_id));
+            }
+
+            collection.features.push(GeoJsonFeature {
+                id: feature["id"].as_u64(),
+                geometries: parts.into_geometries(),
+                extent,
+                tags,
+            });
+        }
+
+        if skipped > 0 {
+            tracing::warn!("Skipped {} features without a valid geometry", skipped);
+        }
+        collection
+    }
+
+    /// The layer of tile `z/x/y`, if any feature is in it
+    fn layer(&self, name: &str, z: u8, x: u32, y: u32, tolerance: f64) -> Option<Layer> {
+        let scale = (1u64 << z) as f64;
+        let extent = EXTENT as f64;
+        let buffer = BUFFER as f64 / extent;
+        let tile = [
+            (x as f64 - buffer) / scale,
+            (y as f64 - buffer) / scale,
+            (x as f64 + 1.0 + buffer) / scale,
+            (y as f64 + 1.0 + buffer) / scale,
+        ];
+        let to_tile = |[wx, wy]: &Coord| -> Point {
+            [
+                ((wx * scale - x as f64) * extent).round() as i64,
+                ((wy * scale - y as f64) * extent).round() as i64,
+            ]
+        };
+
+        let mut features = Vec::new();
+        let mut values = Vec::new();
+        let mut value_index = HashMap::new();
+        for feature in &self.features {
+            let [min_x, min_y, max_x, max_y] = feature.extent;
+            if max_x < tile[0] || min_x > tile[2] || max_y < tile[1] || min_y > tile[3] {
+                continue;
+            }
+
+            let mut tags = Vec::new();
+            for geometry in &feature.geometries {
+                let (geom_type, parts) = tile_parts(geometry, to_tile, tolerance);
+                if parts.is_empty() {
+                    continue;
+                }
+                if tags.is_empty() {
+                    for &(key, value) in &feature.tags {
+                        let next = values.len() as u32;
+                        let index = *value_index.entry(value).or_insert(next);
+                        if index == next {
+                            values.push(self.values[value as usize].clone());
+                        }
+                        tags.extend([key, index]);
+                    }
+                }
+                features.push(Feature {
+                    id: feature.id,
+                    tags: tags.clone(),
+                    r#type: Some(geom_type as i32),
+                    geometry: encode_geometry(geom_type, &parts),
+                });
+            }
+        }
+
+        if features.is_empty() {
+            return None;
+        }
+        Some(Layer {
+            version: 2,
+            name: name.to_string(),
+            features,
+            keys: self.keys.clone(),
+            values,
+            extent: Some(EXTENT),
+        })
+    }
+}
+
+/// Points, lines and polygons of a GeoJSON geometry, in longitude and
+/// latitude
+#[derive(Debug, Default)]
+struct Parts {
+    points: Vec<Coord>,
+    lines: Vec<Vec<Coord>>,
+    polygons: Vec<Vec<Vec<Coord>>>,
+}
+
+impl Parts {
+    /// Add the parts of a geometry, false if it is not a valid one
+    fn read(&mut self, geometry: &Json) -> bool {
+        let coordinates = &geometry["coordinates"];
+        match geometry["type"].as_str() {
+            Some("Point") => coord(coordinates).map(|c| self.points.push(c)).is_some(),
+            Some("MultiPoint") => coords(coordinates).map(|c| self.points.extend(c)).is_some(),
+            Some("LineString") => coords(coordinates).map(|c| self.lines.push(c)).is_some(),
+            Some("MultiLineString") => list(coordinates, coords)
+                .map(|c| self.lines.extend(c))
+                .is_some(),
+            Some("Polygon") => list(coordinates, coords)
+                .map(|c| self.polygons.push(c))
+                .is_some(),
+            Some("MultiPolygon") => list(coordinates, |p| list(p, coords))
+                .map(|c| self.polygons.extend(c))
+                .is_some(),
+            Some("GeometryCollection") => geometry["geometries"]
+                .as_array()
+                .is_some_and(|geometries| geometries.iter().all(|g| self.read(g))),
+            _ => false,
+        }
+    }
+
+    /// Longitude and latitude extent of the parts, added to `bounds`, and
+    /// their extent on the world square; `None` for empty geometries
+    fn extent(&self, bounds: &mut Option<[f64; 4]>) -> Option<[f64; 4]> {
+        let coords = self
+            .points
+            .iter()
+            .chain(self.lines.iter().flatten())
+            .chain(self.polygons.iter().flatten().flatten());
+        let mut extent: Option<[f64; 4]> = None;
+        for &[lon, lat] in coords {
+            extent = Some(match extent {
+                Some(e) => [e[0].min(lon), e[1].min(lat), e[2].max(lon), e[3].max(lat)],
+                None => [lon, lat, lon, lat],
+            });
+        }
+        let [west, south, east, north] = extent?;
+        *bounds = Some(match *bounds {
+            Some(b) => [
+                b[0].min(west),
+                b[1].min(south),
+                b[2].max(east),
+                b[3].max(north),
+            ],
+            None => [west, south, east, north],
+        });
+
+        let [min_x, max_y] = project([west, south]);
+        let [max_x, min_y] = project([east, north]);
+        Some([min_x, min_y, max_x, max_y])
+    }
+
+    /// The non-empty kinds of parts, projected to the world square
+    fn into_geometries(self) -> Vec<Geometry> {
+        let project_line =
+            |line: Vec<Coord>| -> Vec<Coord> { line.into_iter().map(project).collect() };
+        let mut geometries = Vec::new();
+        if !self.points.is_empty() {
+            geometries.push(Geometry::Points(project_line(self.points)));
+        }
+        if !self.lines.is_empty() {
+            geometries.push(Geometry::Lines(
+                self.lines.into_iter().map(project_line).collect(),
+            ));
+        }
+        if !self.polygons.is_empty() {
+            geometries.push(Geometry::Polygons(
+                self.polygons
+                    .into_iter()
+                    .map(|rings| rings.into_iter().map(project_line).collect())
+                    .collect(),
+            ));
+        }
+        geometries
+    }
+}
+
+/// A GeoJSON position, ignoring any altitude
+fn coord(position: &Json) -> Option<Coord> {
+    match position.as_array()?.as_slice() {
+        [x, y, ..] => Some([x.as_f64()?, y.as_f64()?]),
+        _ => None,
+    }
+}
+
+fn coords(positions: &Json) -> Option<Vec<Coord>> {
+    list(positions, coord)
+}
+
+fn list<T>(json: &Json, item: impl Fn(&Json) -> Option<T>) -> Option<Vec<T>> {
+    json.as_array()?.iter().map(item).collect()
+}
+
+/// GeoJSON vector tile source
+pub struct GeoJsonSource {
+    collection: Arc<FeatureCollection>,
+    /// Douglas-Peucker tolerance in tile units, 0 for none
+    tolerance: f64,
+    /// Last zoom simplified with `tolerance`
+    simplify_max_zoom: u8,
+    metadata: TileMetadata,
+    /// Modification time of the file when it was read
+    modified: Option<SystemTime>,
+}
+
+impl GeoJsonSource {
+    /// Read a GeoJSON file into memory
+    pub async fn from_file(config: &SourceConfig) -> Result<Self> {
+        let path = Path::new(&config.path);
+        if !path.exists() {
+            return Err(TileServerError::FileError(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("GeoJSON file not found: {}", config.path),
+            )));
+        }
+
+        let factor = config.options.simplify_tolerance_factor.unwrap_or(1.0);
+        if !factor.is_finite() || factor < 0.0 {
+            return Err(TileServerError::ConfigError(format!(
+                "GeoJSON source '{}' has an invalid simplify_tolerance_factor {}; it must be 0 or more",
+                config.id, factor
+            )));
+        }
+
+        let data = tokio::fs::read(path)
+            .await
+            .map_err(TileServerError::FileError)?;
+        let collection = tokio::task::spawn_blocking(move || {
+            serde_json::from_slice::<Json>(&data).map(|json| FeatureCollection::read(&json))
+        })
+        .await
+        .map_err(|e| TileServerError::Internal(anyhow::anyhow!("Task join error: {}", e)))?
+        .map_err(|e| {
+            TileServerError::ConfigError(format!(
+                "GeoJSON source '{}': {} is not valid JSON: {}",
+                config.id, config.path, e
+            ))
+        })?;
+
+        let bounds = collection.bounds;
+        let center = bounds.map(|b| {
+            [
+                (b[0] + b[2]) / 2.0,
+                (b[1] + b[3]) / 2.0,
+                ((MIN_ZOOM as f64 + MAX_ZOOM as f64) / 2.0).floor(),
+            ]
+        });
+        let metadata = TileMetadata {
+            id: config.id.clone(),
+            name: config.name.clone().unwrap_or_else(|| config.id.clone()),
+            description: None,
+            attribution: config.attribution.clone(),
+            format: TileFormat::Pbf,
+            minzoom: MIN_ZOOM,
+            maxzoom: MAX_ZOOM,
+            bounds,
+            center,
+            vector_layers: Some(serde_json::json!([{
+                "id": config.id,
+                "fields": collection.fields,
+                "minzoom": MIN_ZOOM,
+                "maxzoom": MAX_ZOOM,
+            }])),
+            scheme: config.scheme,
+            overzoom: config.overzoom,
+            encoding: None,
+        };
+
+        tracing::info!(
+            "Loaded GeoJSON source '{}': {} ({} features)",
+            config.id,
+            metadata.name,
+            collection.features.len()
+        );
+
+        Ok(Self {
+            collection: Arc::new(collection),
+            tolerance: factor * UNITS_PER_PIXEL,
+            simplify_max_zoom: config.options.simplify_max_zoom.unwrap_or(MAX_ZOOM),
+            metadata,
+            modified: std::fs::metadata(path).and_then(|m| m.modified()).ok(),
+        })
+    }
+}
+
+#[async_trait]
+impl TileSource for GeoJsonSource {
+    async fn get_xyz_tile(&self, z: u8, x: u32, y: u32) -> Result<Option<TileData>> {
+        let max_tile = 1u32 << z;
+        if x >= max_tile || y >= max_tile {
+            return Err(TileServerError::InvalidCoordinates { z, x, y });
+        }
+        if z < self.metadata.minzoom || z > self.metadata.maxzoom {
+            return Ok(None);
+        }
+
+        let collection = Arc::clone(&self.collection);
+        let name = self.metadata.id.clone();
+        let tolerance = if z <= self.simplify_max_zoom {
+            self.tolerance
+        } else {
+            0.0
+        };
+        let layer =
+            tokio::task::spawn_blocking(move || collection.layer(&name, z, x, y, tolerance))
+                .await
+                .map_err(|e| {
+                    TileServerError::Internal(anyhow::anyhow!("Task join error: {}", e))
+                })?;
+
+        let Some(layer) = layer else {
+            return Ok(None);
+        };
+        Ok(Some(TileData {
+            data: Tile {
+                layers: vec![layer],
+            }
+            .encode_to_vec()
+            .into(),
+            format: TileFormat::Pbf,
+            compression: TileCompression::None,
+        }))
+    }
+
+    fn metadata(&self) -> &TileMetadata {
+        &self.metadata
+    }
+
+    fn backend(&self) -> &'static str {
+        "geojson"
+    }
+
+    fn last_modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_read_features_and_properties() {
+        let collection = FeatureCollection::read(&json!({
+            "type": "FeatureCollection",
+            "features": [
+                {
+                    "type": "Feature",
+                    "id": 7,
+                    "geometry": {
+                        "type": "GeometryCollection",
+                        "geometries": [
+                            { "type": "Point", "coordinates": [8.5, 47.4] },
+                            { "type": "LineString", "coordinates": [[8.0, 47.0], [9.0, 48.0, 410.0]] }
+                        ]
+                    },
+                    "properties": { "name": "Zurich", "rank": 1, "capital": false, "tags": ["a"], "note": null }
+                },
+                {
+                    "type": "Feature",
+                    "id": "bern",
+                    "geometry": { "type": "Point", "coordinates": [7.45, 46.95] },
+                    "properties": { "name": "Bern", "rank": 1.5 }
+                },
+                { "type": "Feature", "geometry": null, "properties": {} },
+                { "type": "Feature", "geometry": { "type": "Point", "coordinates": ["x", 0] } }
+            ]
+        }));
+
+        assert_eq!(collection.features.len(), 2);
+        let zurich = &collection.features[0];
+        assert_eq!(zurich.id, Some(7));
+        assert!(matches!(
+            zurich.geometries.as_slice(),
+            [Geometry::Points(_), Geometry::Lines(_)]
+        ));
+        assert_eq!(collection.features[1].id, None);
+
+        assert_eq!(collection.keys, ["capital", "name", "rank", "tags"]);
+        assert_eq!(
+            collection.fields,
+            *json!({ "name": "String", "rank": "Number", "capital": "Boolean", "tags": "String" })
+                .as_object()
+                .unwrap()
+        );
+        let tags = &collection.values[zurich.tags[3].1 as usize];
+        assert_eq!(tags.string_value.as_deref(), Some("[\"a\"]"));
+        // The shared rank of 1 is one value, 1.5 another
+        assert_eq!(zurich.tags[2].1, 2);
+        assert_eq!(collection.features[1].tags[1].1, 5);
+        assert_eq!(collection.values.len(), 6);
+
+        assert_eq!(collection.bounds, Some([7.45, 46.95, 9.0, 48.0]));
+    }
+
+    #[test]
+    fn test_simplify_after_clipping() {
+        // A square world with a zigzag edge of 1 unit amplitude, half of it
+        // in the tile
+        let mut ring: Vec<Coord> = (0..=100)
+            .map(|i| [i as f64 * 100.0, (i % 2) as f64])
+            .collect();
+        ring.extend([[10000.0, 10000.0], [0.0, 10000.0], [0.0, 0.0]]);
+        let geometry = Geometry::Polygons(vec![vec![ring]]);
+        let to_tile = |&[x, y]: &Coord| -> Point { [x as i64 - 5000, y as i64] };
+
+        let (_, plain) = tile_parts(&geometry, to_tile, 0.0);
+        let (geom_type, simplified) = tile_parts(&geometry, to_tile, 16.0);
+        assert_eq!(geom_type, GeomType::Polygon);
+        assert_eq!(simplified.len(), 1);
+        assert!(
+            plain[0].len() > 40,
+            "{} points unsimplified",
+            plain[0].len()
+        );
+        assert!(simplified[0].len() <= 5, "{:?}", simplified[0]);
+        assert!(ring_area(&simplified[0]) > 0);
+
+        // Every point is within the buffer, as clipping came first
+        let range = -BUFFER..=EXTENT as i64 + BUFFER;
+        for [x, y] in &simplified[0] {
+            assert!(range.contains(x) && range.contains(y), "{}, {}", x, y);
+        }
+    }
+}
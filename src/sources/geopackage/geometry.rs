@@ -0,0 +1,278 @@
+//! GeoPackage geometry blobs
+//!
+//! A blob is a small header (`GP`, version, flags, SRS id and an optional
+//! envelope) followed by the geometry as ISO WKB. Z and M values are read
+//! and dropped; geometry collections and the extended blob format are not
+//! supported.
+
+use crate::error::{Result, TileServerError};
+
+/// `[x, y]` in the coordinates of the table's SRS
+pub type Coord = [f64; 2];
+
+/// A decoded geometry, single geometries stored as multi-geometries of one
+#[derive(Debug, Clone, PartialEq)]
+pub enum Geometry {
+    Points(Vec<Coord>),
+    Lines(Vec<Vec<Coord>>),
+    /// Polygons, each an exterior ring followed by its holes
+    Polygons(Vec<Vec<Vec<Coord>>>),
+}
+
+const FLAG_LITTLE_ENDIAN: u8 = 0b0000_0001;
+const FLAG_EMPTY: u8 = 0b0001_0000;
+const FLAG_EXTENDED: u8 = 0b0010_0000;
+
+/// Decode a GeoPackage geometry blob, `None` for empty geometries
+pub fn decode(blob: &[u8]) -> Result<Option<Geometry>> {
+    if blob.len() < 8 || &blob[..2] != b"GP" {
+        return Err(invalid("missing GP header"));
+    }
+    let flags = blob[3];
+    if flags & FLAG_EXTENDED != 0 {
+        return Err(invalid("extended geometry blobs are not supported"));
+    }
+    if flags & FLAG_EMPTY != 0 {
+        return Ok(None);
+    }
+    let envelope = match (flags >> 1) & 0b111 {
+        0 => 0,
+        1 => 32,
+        2 | 3 => 48,
+        4 => 64,
+        code => return Err(invalid(&format!("unknown envelope code {}", code))),
+    };
+
+    let mut reader = Reader {
+        data: blob,
+        offset: 8 + envelope,
+        little_endian: flags & FLAG_LITTLE_ENDIAN != 0,
+    };
+    let geometry = reader.geometry()?;
+    Ok(match &geometry {
+        Geometry::Points(points) if points.is_empty() => None,
+        Geometry::Lines(lines) if lines.is_empty() => None,
+        Geometry::Polygons(polygons) if polygons.is_empty() => None,
+        _ => Some(geometry),
+    })
+}
+
+fn invalid(reason: &str) -> TileServerError {
+    TileServerError::GeoPackageError(format!("Invalid geometry blob: {}", reason))
+}
+
+/// WKB cursor; each geometry sets the byte order of what follows
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+    little_endian: bool,
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let bytes = self
+            .data
+            .get(self.offset..self.offset + N)
+            .ok_or_else(|| invalid("truncated WKB"))?;
+        self.offset += N;
+        Ok(bytes.try_into().expect("slice of N bytes"))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.take()?;
+        Ok(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    fn f64(&mut self) -> Result<f64> {
+        let bytes = self.take()?;
+        Ok(if self.little_endian {
+            f64::from_le_bytes(bytes)
+        } else {
+            f64::from_be_bytes(bytes)
+        })
+    }
+
+    /// Byte order and type of the next geometry, as `(type, dimensions)`
+    fn header(&mut self) -> Result<(u32, usize)> {
+        let [order] = self.take()?;
+        self.little_endian = order == 1;
+        let code = self.u32()?;
+        // ISO codes add 1000 for Z, 2000 for M and 3000 for ZM
+        let iso = code & 0x0fff_ffff;
+        let mut dimensions = match iso / 1000 {
+            1 | 2 => 3,
+            3 => 4,
+            _ => 2,
+        };
+        // EWKB-style writers flag Z and M in the high bits instead
+        dimensions += (code >> 31) as usize + ((code >> 30) & 1) as usize;
+        Ok((iso % 1000, dimensions))
+    }
+
+    fn coord(&mut self, dimensions: usize) -> Result<Coord> {
+        let coord = [self.f64()?, self.f64()?];
+        for _ in 2..dimensions {
+            self.f64()?;
+        }
+        Ok(coord)
+    }
+
+    fn count(&mut self) -> Result<usize> {
+        let count = self.u32()? as usize;
+        // Every element takes at least 4 bytes; reject counts the blob
+        // cannot hold before allocating for them
+        if count > (self.data.len() - self.offset) / 4 {
+            return Err(invalid("element count exceeds blob size"));
+        }
+        Ok(count)
+    }
+
+    fn line(&mut self, dimensions: usize) -> Result<Vec<Coord>> {
+        let count = self.count()?;
+        (0..count).map(|_| self.coord(dimensions)).collect()
+    }
+
+    fn polygon(&mut self, dimensions: usize) -> Result<Vec<Vec<Coord>>> {
+        let count = self.count()?;
+        (0..count).map(|_| self.line(dimensions)).collect()
+    }
+
+    /// Members of a multi-geometry, each with its own header
+    fn members<T>(
+        &mut self,
+        expected: u32,
+        mut read: impl FnMut(&mut Self, usize) -> Result<T>,
+    ) -> Result<Vec<T>> {
+        let count = self.count()?;
+        (0..count)
+            .map(|_| {
+                let (kind, dimensions) = self.header()?;
+                if kind != expected {
+                    return Err(invalid("multi-geometry with mixed member types"));
+                }
+                read(self, dimensions)
+            })
+            .collect()
+    }
+
+    fn geometry(&mut self) -> Result<Geometry> {
+        let (kind, dimensions) = self.header()?;
+        Ok(match kind {
+            1 => {
+                let point = self.coord(dimensions)?;
+                // Empty points are written as NaN coordinates
+                Geometry::Points(if point[0].is_nan() {
+                    vec![]
+                } else {
+                    vec![point]
+                })
+            }
+            2 => Geometry::Lines(vec![self.line(dimensions)?]),
+            3 => Geometry::Polygons(vec![self.polygon(dimensions)?]),
+            4 => Geometry::Points(
+                self.members(1, |reader, dimensions| reader.coord(dimensions))?
+                    .into_iter()
+                    .filter(|point| !point[0].is_nan())
+                    .collect(),
+            ),
+            5 => Geometry::Lines(self.members(2, Self::line)?),
+            6 => Geometry::Polygons(self.members(3, Self::polygon)?),
+            7 => return Err(invalid("geometry collections are not supported")),
+            kind => return Err(invalid(&format!("unknown geometry type {}", kind))),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Blob with an XY envelope around little-endian `wkb`
+    fn blob(wkb: &[u8]) -> Vec<u8> {
+        let mut blob = vec![b'G', b'P', 0, 0b0000_0011];
+        blob.extend_from_slice(&4326i32.to_le_bytes());
+        for value in [0.0f64, 1.0, 0.0, 1.0] {
+            blob.extend_from_slice(&value.to_le_bytes());
+        }
+        blob.extend_from_slice(wkb);
+        blob
+    }
+
+    fn wkb(kind: u32, body: &[f64], counts: &[u32]) -> Vec<u8> {
+        let mut wkb = vec![1];
+        wkb.extend_from_slice(&kind.to_le_bytes());
+        for count in counts {
+            wkb.extend_from_slice(&count.to_le_bytes());
+        }
+        for value in body {
+            wkb.extend_from_slice(&value.to_le_bytes());
+        }
+        wkb
+    }
+
+    #[test]
+    fn test_decode_point_and_line() {
+        let point = decode(&blob(&wkb(1, &[8.5, 47.4], &[]))).unwrap();
+        assert_eq!(point, Some(Geometry::Points(vec![[8.5, 47.4]])));
+
+        // LineString Z: the third value of each coordinate is skipped
+        let line = decode(&blob(&wkb(1002, &[0.0, 1.0, 9.0, 2.0, 3.0, 9.0], &[2]))).unwrap();
+        assert_eq!(
+            line,
+            Some(Geometry::Lines(vec![vec![[0.0, 1.0], [2.0, 3.0]]]))
+        );
+    }
+
+    #[test]
+    fn test_decode_big_endian_polygon() {
+        let mut wkb = vec![0];
+        wkb.extend_from_slice(&3u32.to_be_bytes());
+        wkb.extend_from_slice(&1u32.to_be_bytes());
+        wkb.extend_from_slice(&4u32.to_be_bytes());
+        for value in [0.0f64, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 0.0] {
+            wkb.extend_from_slice(&value.to_be_bytes());
+        }
+        let polygon = decode(&blob(&wkb)).unwrap();
+        assert_eq!(
+            polygon,
+            Some(Geometry::Polygons(vec![vec![vec![
+                [0.0, 0.0],
+                [1.0, 0.0],
+                [1.0, 1.0],
+                [0.0, 0.0]
+            ]]]))
+        );
+    }
+
+    #[test]
+    fn test_decode_multipoint_and_empty() {
+        let mut multi = wkb(4, &[], &[2]);
+        multi.extend(wkb(1, &[1.0, 2.0], &[]));
+        multi.extend(wkb(1, &[3.0, 4.0], &[]));
+        assert_eq!(
+            decode(&blob(&multi)).unwrap(),
+            Some(Geometry::Points(vec![[1.0, 2.0], [3.0, 4.0]]))
+        );
+
+        let empty_point = wkb(1, &[f64::NAN, f64::NAN], &[]);
+        assert_eq!(decode(&blob(&empty_point)).unwrap(), None);
+
+        let mut flagged_empty = blob(&wkb(4, &[], &[0]));
+        flagged_empty[3] |= FLAG_EMPTY;
+        assert_eq!(decode(&flagged_empty).unwrap(), None);
+    }
+
+    #[test]
+    fn test_decode_rejects_malformed_blobs() {
+        assert!(decode(b"XX\0\x01\0\0\0\0").is_err());
+        // Truncated coordinates
+        assert!(decode(&blob(&wkb(1, &[8.5], &[]))).is_err());
+        // A count larger than the blob could hold
+        assert!(decode(&blob(&wkb(2, &[], &[u32::MAX]))).is_err());
+        assert!(decode(&blob(&wkb(7, &[], &[0]))).is_err());
+    }
+}
@@ -0,0 +1,875 @@
+//! GeoPackage vector tile source
+//!
+//! Serves the feature tables of an OGC GeoPackage (`.gpkg`) as vector tiles
+//! built on request. Each table is a layer named after it: the features
+//! within a tile are looked up through the table's RTree index, projected
+//! to Web Mercator, clipped to the tile and encoded as MVT, with the
+//! table's other columns as properties.
+//!
+//! Tables in EPSG:4326 and EPSG:3857 are supported. Tables without an
+//! RTree index are scanned in full for every tile. Lines and polygons of
+//! layers with a `simplify` tolerance are simplified in tile units, so low
+//! zoom tiles of detailed tables stay small.
+
+mod geometry;
+
+use async_trait::async_trait;
+use geozero::mvt::tile::{Feature, GeomType, Layer, Value};
+use geozero::mvt::{Message, Tile};
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, OptionalExtension};
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::SystemTime;
+
+use self::geometry::{Coord, Geometry};
+use crate::config::{SimplifyTolerance, SourceConfig};
+use crate::error::{Result, TileServerError};
+use crate::sources::mbtiles::open_connection;
+use crate::sources::overzoom::{
+    clip_lines, clip_points, clip_polygons, encode_geometry, ring_area, Point,
+};
+use crate::sources::{TileCompression, TileData, TileFormat, TileMetadata, TileSource};
+
+/// Extent of the layers
+const EXTENT: u32 = 4096;
+
+/// Geometry outside the tile kept for clean edges, in tile units, as the
+/// default of PostgreSQL sources
+const BUFFER: i64 = 64;
+
+/// Zoom levels tiles are built for
+const MIN_ZOOM: u8 = 0;
+const MAX_ZOOM: u8 = 22;
+
+/// Latitude limit of Web Mercator
+const MAX_LATITUDE: f64 = 85.051_128_779_806_59;
+
+/// Circumference of the Web Mercator sphere, in EPSG:3857 meters
+const WORLD_METERS: f64 = 2.0 * PI * 6_378_137.0;
+
+/// Coordinate systems feature tables can be served from
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Projection {
+    /// EPSG:4326 longitude and latitude
+    Wgs84,
+    /// EPSG:3857 meters
+    WebMercator,
+}
+
+impl Projection {
+    /// Projection of an SRS registered in `gpkg_spatial_ref_sys`
+    fn from_srs(organization: &str, code: i64) -> Option<Self> {
+        if !organization.eq_ignore_ascii_case("EPSG") {
+            return None;
+        }
+        match code {
+            4326 => Some(Self::Wgs84),
+            3857 | 900913 => Some(Self::WebMercator),
+            _ => None,
+        }
+    }
+
+    /// Position on the Web Mercator world square, 0 to 1 from the top left
+    fn project(self, [x, y]: Coord) -> Coord {
+        match self {
+            Self::Wgs84 => {
+                let lat = y.clamp(-MAX_LATITUDE, MAX_LATITUDE).to_radians();
+                [(x + 180.0) / 360.0, (1.0 - lat.tan().asinh() / PI) / 2.0]
+            }
+            Self::WebMercator => [x / WORLD_METERS + 0.5, 0.5 - y / WORLD_METERS],
+        }
+    }
+
+    /// Longitude and latitude of a coordinate
+    fn lon_lat(self, coord: Coord) -> Coord {
+        match self {
+            Self::Wgs84 => coord,
+            Self::WebMercator => Self::Wgs84.unproject(self.project(coord)),
+        }
+    }
+
+    /// Inverse of [`Projection::project`]
+    fn unproject(self, [x, y]: Coord) -> Coord {
+        match self {
+            Self::Wgs84 => [
+                x * 360.0 - 180.0,
+                (PI * (1.0 - 2.0 * y)).sinh().atan().to_degrees(),
+            ],
+            Self::WebMercator => [(x - 0.5) * WORLD_METERS, (0.5 - y) * WORLD_METERS],
+        }
+    }
+}
+
+/// A feature table, served as the layer of the same name
+#[derive(Debug)]
+struct FeatureTable {
+    name: String,
+    /// Columns served as properties, and whether they are declared BOOLEAN
+    columns: Vec<(String, bool)>,
+    projection: Projection,
+    /// Whether the table has an RTree index, which `query` filters with
+    indexed: bool,
+    /// Tolerance lines and polygons are simplified with, by zoom
+    simplify: Option<SimplifyTolerance>,
+    /// Selects the rowid, geometry and `columns` of the features within
+    /// `minx, maxx, miny, maxy` if `indexed`, of every feature otherwise
+    query: String,
+}
+
+/// Quote an SQL identifier
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn query_error(e: rusqlite::Error) -> TileServerError {
+    TileServerError::GeoPackageError(e.to_string())
+}
+
+impl FeatureTable {
+    /// The layer of tile `z/x/y`, if any feature of the table is in it
+    fn layer(&self, conn: &Connection, z: u8, x: u32, y: u32) -> Result<Option<Layer>> {
+        let scale = (1u64 << z) as f64;
+        let extent = EXTENT as f64;
+        let tolerance = self.simplify.as_ref().map_or(0.0, |s| s.at(z));
+        let to_tile = |coord: &Coord| -> Point {
+            let [wx, wy] = self.projection.project(*coord);
+            [
+                ((wx * scale - x as f64) * extent).round() as i64,
+                ((wy * scale - y as f64) * extent).round() as i64,
+            ]
+        };
+
+        let mut stmt = conn.prepare_cached(&self.query).map_err(query_error)?;
+        let mut rows = if self.indexed {
+            let buffer = BUFFER as f64 / extent;
+            let [min_x, max_y] = self
+                .projection
+                .unproject([(x as f64 - buffer) / scale, (y as f64 - buffer) / scale]);
+            let [max_x, min_y] = self.projection.unproject([
+                (x as f64 + 1.0 + buffer) / scale,
+                (y as f64 + 1.0 + buffer) / scale,
+            ]);
+            stmt.query(rusqlite::params![min_x, max_x, min_y, max_y])
+        } else {
+            stmt.query([])
+        }
+        .map_err(query_error)?;
+
+        let mut layer = LayerBuilder::new(self);
+        while let Some(row) = rows.next().map_err(query_error)? {
+            let id: i64 = row.get(0).map_err(query_error)?;
+            let Some(blob) = row
+                .get_ref(1)
+                .map_err(query_error)?
+                .as_blob_or_null()
+                .ok()
+                .flatten()
+            else {
+                continue;
+            };
+            let geometry = match geometry::decode(blob) {
+                Ok(Some(geometry)) => geometry,
+                Ok(None) => continue,
+                Err(e) => {
+                    tracing::debug!("Skipping feature {} of table '{}': {}", id, self.name, e);
+                    continue;
+                }
+            };
+
+            let (geom_type, parts) = tile_parts(&geometry, to_tile, tolerance);
+            if parts.is_empty() {
+                continue;
+            }
+            let values = (0..self.columns.len())
+                .map(|i| row.get_ref(i + 2).map_err(query_error))
+                .collect::<Result<Vec<_>>>()?;
+            layer.push(id, geom_type, &parts, &values);
+        }
+
+        Ok(layer.finish())
+    }
+}
+
+/// Clipped parts of a geometry in the tile's coordinates
+///
+/// Lines and rings are simplified with `tolerance`, in tile units, and
+/// rings are oriented as MVT expects, exterior rings with a positive area.
+fn tile_parts(
+    geometry: &Geometry,
+    to_tile: impl Fn(&Coord) -> Point,
+    tolerance: f64,
+) -> (GeomType, Vec<Vec<Point>>) {
+    let bounds = [
+        -BUFFER,
+        -BUFFER,
+        EXTENT as i64 + BUFFER,
+        EXTENT as i64 + BUFFER,
+    ];
+    let line = |coords: &[Coord]| {
+        let mut points: Vec<Point> = coords.iter().map(&to_tile).collect();
+        points.dedup();
+        simplify(points, tolerance)
+    };
+
+    match geometry {
+        Geometry::Points(points) => (
+            GeomType::Point,
+            clip_points(vec![points.iter().map(&to_tile).collect()], bounds),
+        ),
+        Geometry::Lines(lines) => {
+            let lines = lines.iter().map(|l| line(l)).filter(|l| l.len() >= 2);
+            (GeomType::Linestring, clip_lines(lines.collect(), bounds))
+        }
+        Geometry::Polygons(polygons) => {
+            let mut rings = Vec::new();
+            for polygon in polygons {
+                for (i, ring) in polygon.iter().enumerate() {
+                    let mut ring = line(ring);
+                    if ring.len() > 1 && ring.first() == ring.last() {
+                        ring.pop();
+                    }
+                    let area = if ring.len() >= 3 { ring_area(&ring) } else { 0 };
+                    if area == 0 {
+                        // Holes of a collapsed exterior go with it
+                        if i == 0 {
+                            break;
+                        }
+                        continue;
+                    }
+                    if (i == 0) != (area > 0) {
+                        ring.reverse();
+                    }
+                    rings.push(ring);
+                }
+            }
+            (GeomType::Polygon, clip_polygons(rings, bounds))
+        }
+    }
+}
+
+/// Douglas-Peucker simplification of a line or closed ring, keeping its end
+/// points and every point further than `tolerance` from the simplified line
+fn simplify(points: Vec<Point>, tolerance: f64) -> Vec<Point> {
+    if tolerance <= 0.0 || points.len() < 3 {
+        return points;
+    }
+
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+    let mut spans = vec![(0, points.len() - 1)];
+    while let Some((first, last)) = spans.pop() {
+        let farthest = (first + 1..last)
+            .map(|i| (i, segment_distance(points[i], points[first], points[last])))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((i, distance)) = farthest {
+            if distance > tolerance {
+                keep[i] = true;
+                spans.extend([(first, i), (i, last)]);
+            }
+        }
+    }
+
+    points
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(point, keep)| keep.then_some(point))
+        .collect()
+}
+
+/// Distance from `point` to the segment from `a` to `b`
+fn segment_distance(point: Point, a: Point, b: Point) -> f64 {
+    let [px, py] = [point[0] as f64, point[1] as f64];
+    let [ax, ay] = [a[0] as f64, a[1] as f64];
+    let [dx, dy] = [b[0] as f64 - ax, b[1] as f64 - ay];
+    let length = dx * dx + dy * dy;
+    let t = if length == 0.0 {
+        0.0
+    } else {
+        (((px - ax) * dx + (py - ay) * dy) / length).clamp(0.0, 1.0)
+    };
+    (px - ax - t * dx).hypot(py - ay - t * dy)
+}
+
+/// Features and shared property values of a layer being built
+struct LayerBuilder<'a> {
+    table: &'a FeatureTable,
+    features: Vec<Feature>,
+    values: Vec<Value>,
+    value_index: HashMap<String, u32>,
+}
+
+impl<'a> LayerBuilder<'a> {
+    fn new(table: &'a FeatureTable) -> Self {
+        Self {
+            table,
+            features: Vec::new(),
+            values: Vec::new(),
+            value_index: HashMap::new(),
+        }
+    }
+
+    fn push(&mut self, id: i64, geom_type: GeomType, parts: &[Vec<Point>], values: &[ValueRef]) {
+        let mut tags = Vec::new();
+        for (key, (value, (_, boolean))) in values.iter().zip(&self.table.columns).enumerate() {
+            // Keyed by type and value, as `Value` holds floats
+            let (index_key, value) = match *value {
+                ValueRef::Null | ValueRef::Blob(_) => continue,
+                ValueRef::Integer(v) if *boolean => (
+                    format!("b{}", v != 0),
+                    Value {
+                        bool_value: Some(v != 0),
+                        ..Default::default()
+                    },
+                ),
+                ValueRef::Integer(v) => (
+                    format!("i{}", v),
+                    Value {
+                        int_value: Some(v),
+                        ..Default::default()
+                    },
+                ),
+                ValueRef::Real(v) => (
+                    format!("d{}", v.to_bits()),
+                    Value {
+                        double_value: Some(v),
+                        ..Default::default()
+                    },
+                ),
+                ValueRef::Text(text) => {
+                    let text = String::from_utf8_lossy(text).into_owned();
+                    (
+                        format!("s{}", text),
+                        Value {
+                            string_value: Some(text),
+                            ..Default::default()
+                        },
+                    )
+                }
+            };
+            let next = self.values.len() as u32;
+            let index = *self.value_index.entry(index_key).or_insert(next);
+            if index == next {
+                self.values.push(value);
+            }
+            tags.extend([key as u32, index]);
+        }
+
+        self.features.push(Feature {
+            id: u64::try_from(id).ok(),
+            tags,
+            r#type: Some(geom_type as i32),
+            geometry: encode_geometry(geom_type, parts),
+        });
+    }
+
+    fn finish(self) -> Option<Layer> {
+        if self.features.is_empty() {
+            return None;
+        }
+        Some(Layer {
+            version: 2,
+            name: self.table.name.clone(),
+            features: self.features,
+            keys: self
+                .table
+                .columns
+                .iter()
+                .map(|(name, _)| name.clone())
+                .collect(),
+            values: self.values,
+            extent: Some(EXTENT),
+        })
+    }
+}
+
+/// Read-only connections to the GeoPackage, opened as needed
+struct Connections {
+    path: PathBuf,
+    immutable: bool,
+    idle: Mutex<Vec<Connection>>,
+}
+
+impl Connections {
+    fn take(&self) -> Result<Connection> {
+        let idle = self
+            .idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop();
+        match idle {
+            Some(conn) => Ok(conn),
+            None => open_connection(&self.path, self.immutable).map_err(query_error),
+        }
+    }
+
+    fn put(&self, conn: Connection) {
+        self.idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(conn);
+    }
+}
+
+/// GeoPackage vector tile source
+pub struct GeoPackageSource {
+    connections: Arc<Connections>,
+    tables: Arc<Vec<FeatureTable>>,
+    metadata: TileMetadata,
+    /// Modification time of the file when it was opened
+    modified: Option<SystemTime>,
+}
+
+/// A row of `gpkg_contents` joined with its geometry column and SRS
+struct Contents {
+    table: String,
+    geometry_column: String,
+    /// Extent of the table in its SRS, `[min_x, min_y, max_x, max_y]`
+    extent: Option<[f64; 4]>,
+    identifier: Option<String>,
+    description: Option<String>,
+    srs: (String, i64),
+}
+
+impl GeoPackageSource {
+    /// Open a GeoPackage and inspect the feature tables it serves
+    pub async fn from_file(config: &SourceConfig) -> Result<Self> {
+        let path = Path::new(&config.path);
+        if !path.exists() {
+            return Err(TileServerError::FileError(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("GeoPackage file not found: {}", config.path),
+            )));
+        }
+
+        let immutable = config.options.immutable;
+        let conn = open_connection(path, immutable).map_err(query_error)?;
+        let contents = Self::read_contents(&conn, config)?;
+        if contents.len() > 1 {
+            tracing::warn!(
+                "GeoPackage source '{}' has {} feature tables, served as one layer each; set `table` to serve only one",
+                config.id,
+                contents.len()
+            );
+        }
+
+        let mut tables = Vec::new();
+        let mut vector_layers = Vec::new();
+        let mut bounds: Option<[f64; 4]> = None;
+        for contents in &contents {
+            let (table, fields) = Self::read_table(&conn, config, contents)?;
+            if let Some(extent) = contents.extent {
+                let [west, south] = table.projection.lon_lat([extent[0], extent[1]]);
+                let [east, north] = table.projection.lon_lat([extent[2], extent[3]]);
+                bounds = Some(match bounds {
+                    Some(b) => [
+                        b[0].min(west),
+                        b[1].min(south),
+                        b[2].max(east),
+                        b[3].max(north),
+                    ],
+                    None => [west, south, east, north],
+                });
+            }
+            vector_layers.push(serde_json::json!({
+                "id": table.name,
+                "description": contents.description.clone().unwrap_or_default(),
+                "fields": fields,
+                "minzoom": MIN_ZOOM,
+                "maxzoom": MAX_ZOOM,
+            }));
+            tables.push(table);
+        }
+
+        if let Some(layer) = config
+            .geopackage
+            .simplify
+            .keys()
+            .find(|layer| !tables.iter().any(|table| &table.name == *layer))
+        {
+            return Err(TileServerError::ConfigError(format!(
+                "GeoPackage source '{}' has no feature table '{}' to simplify (served tables: {})",
+                config.id,
+                layer,
+                tables
+                    .iter()
+                    .map(|t| t.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )));
+        }
+
+        // A single table names the source
+        let single = match contents.as_slice() {
+            [contents] => Some(contents),
+            _ => None,
+        };
+        let name = config
+            .name
+            .clone()
+            .or_else(|| single.and_then(|c| c.identifier.clone()))
+            .unwrap_or_else(|| config.id.clone());
+        let center = bounds.map(|b| {
+            [
+                (b[0] + b[2]) / 2.0,
+                (b[1] + b[3]) / 2.0,
+                ((MIN_ZOOM as f64 + MAX_ZOOM as f64) / 2.0).floor(),
+            ]
+        });
+        let metadata = TileMetadata {
+            id: config.id.clone(),
+            name,
+            description: single.and_then(|c| c.description.clone()),
+            attribution: config.attribution.clone(),
+            format: TileFormat::Pbf,
+            minzoom: MIN_ZOOM,
+            maxzoom: MAX_ZOOM,
+            bounds,
+            center,
+            vector_layers: Some(serde_json::Value::Array(vector_layers)),
+            scheme: config.scheme,
+            overzoom: config.overzoom,
+            encoding: None,
+        };
+
+        tracing::info!(
+            "Loaded GeoPackage source '{}': {} ({})",
+            config.id,
+            metadata.name,
+            tables
+                .iter()
+                .map(|t| t.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+
+        Ok(Self {
+            connections: Arc::new(Connections {
+                path: path.to_path_buf(),
+                immutable,
+                idle: Mutex::new(vec![conn]),
+            }),
+            tables: Arc::new(tables),
+            metadata,
+            modified: std::fs::metadata(path).and_then(|m| m.modified()).ok(),
+        })
+    }
+
+    /// Feature tables to serve: every one, or the configured `table`
+    fn read_contents(conn: &Connection, config: &SourceConfig) -> Result<Vec<Contents>> {
+        let mut stmt = conn
+            .prepare(
+                "SELECT c.table_name, g.column_name, c.min_x, c.min_y, c.max_x, c.max_y,
+                        c.identifier, c.description, s.organization, s.organization_coordsys_id
+                 FROM gpkg_contents AS c
+                 JOIN gpkg_geometry_columns AS g ON g.table_name = c.table_name
+                 LEFT JOIN gpkg_spatial_ref_sys AS s ON s.srs_id = g.srs_id
+                 WHERE c.data_type = 'features'
+                 ORDER BY c.table_name",
+            )
+            .map_err(|e| {
+                TileServerError::GeoPackageError(format!(
+                    "{} is not a GeoPackage: {}",
+                    config.path, e
+                ))
+            })?;
+        let mut contents = stmt
+            .query_map([], |row| {
+                let extent: [Option<f64>; 4] = [row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?];
+                Ok(Contents {
+                    table: row.get(0)?,
+                    geometry_column: row.get(1)?,
+                    extent: match extent {
+                        [Some(min_x), Some(min_y), Some(max_x), Some(max_y)] => {
+                            Some([min_x, min_y, max_x, max_y])
+                        }
+                        _ => None,
+                    },
+                    identifier: row.get(6)?,
+                    description: row.get::<_, Option<String>>(7)?.filter(|d| !d.is_empty()),
+                    srs: (
+                        row.get::<_, Option<String>>(8)?.unwrap_or_default(),
+                        row.get::<_, Option<i64>>(9)?.unwrap_or_default(),
+                    ),
+                })
+            })
+            .and_then(|rows| rows.collect::<rusqlite::Result<Vec<_>>>())
+            .map_err(query_error)?;
+
+        let available = contents
+            .iter()
+            .map(|c| c.table.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        if let Some(table) = &config.geopackage.table {
+            contents.retain(|c| &c.table == table);
+            if contents.is_empty() {
+                return Err(TileServerError::ConfigError(format!(
+                    "GeoPackage source '{}' has no feature table '{}' (feature tables: {})",
+                    config.id, table, available
+                )));
+            }
+        }
+        if contents.is_empty() {
+            return Err(TileServerError::ConfigError(format!(
+                "GeoPackage source '{}' has no feature tables",
+                config.id
+            )));
+        }
+        Ok(contents)
+    }
+
+    /// Columns, projection and index of a feature table, with its fields
+    /// for `vector_layers`
+    fn read_table(
+        conn: &Connection,
+        config: &SourceConfig,
+        contents: &Contents,
+    ) -> Result<(FeatureTable, serde_json::Map<String, serde_json::Value>)> {
+        let name = &contents.table;
+        let geometry_column = config
+            .geopackage
+            .geometry_column
+            .as_ref()
+            .unwrap_or(&contents.geometry_column);
+
+        let (organization, code) = &contents.srs;
+        let projection = Projection::from_srs(organization, *code).ok_or_else(|| {
+            TileServerError::ConfigError(format!(
+                "Table '{}' of GeoPackage source '{}' uses SRS {}:{}; only EPSG:4326 and EPSG:3857 are supported",
+                name, config.id, organization, code
+            ))
+        })?;
+
+        // (name, declared type, part of the primary key)
+        let mut stmt = conn
+            .prepare(&format!("PRAGMA table_info({})", quote(name)))
+            .map_err(query_error)?;
+        let table_columns = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, i64>(5)? > 0,
+                ))
+            })
+            .and_then(|rows| rows.collect::<rusqlite::Result<Vec<_>>>())
+            .map_err(query_error)?;
+        if !table_columns
+            .iter()
+            .any(|(column, ..)| column == geometry_column)
+        {
+            return Err(TileServerError::ConfigError(format!(
+                "Table '{}' of GeoPackage source '{}' has no column '{}'",
+                name, config.id, geometry_column
+            )));
+        }
+
+        let mut columns = Vec::new();
+        let mut fields = serde_json::Map::new();
+        for (column, declared, primary_key) in table_columns {
+            let declared = declared.to_uppercase();
+            if column == *geometry_column || (primary_key && declared == "INTEGER") {
+                continue;
+            }
+            let field = match declared.as_str() {
+                "BOOLEAN" => "Boolean",
+                "TINYINT" | "SMALLINT" | "MEDIUMINT" | "INT" | "INTEGER" | "FLOAT" | "DOUBLE"
+                | "REAL" => "Number",
+                "BLOB" => continue,
+                _ => "String",
+            };
+            fields.insert(column.clone(), field.into());
+            columns.push((column, declared == "BOOLEAN"));
+        }
+
+        let rtree = format!("rtree_{}_{}", name, geometry_column);
+        let indexed = conn
+            .query_row(
+                "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+                [&rtree],
+                |_| Ok(()),
+            )
+            .optional()
+            .map_err(query_error)?
+            .is_some();
+        if !indexed {
+            tracing::warn!(
+                "Table '{}' of GeoPackage source '{}' has no RTree index; every tile scans the whole table",
+                name,
+                config.id
+            );
+        }
+
+        let mut select = format!("SELECT t.rowid, t.{}", quote(geometry_column));
+        for (column, _) in &columns {
+            select.push_str(&format!(", t.{}", quote(column)));
+        }
+        let query = if indexed {
+            format!(
+                "{} FROM {} AS t JOIN {} AS r ON r.id = t.rowid
+                 WHERE r.maxx >= ?1 AND r.minx <= ?2 AND r.maxy >= ?3 AND r.miny <= ?4",
+                select,
+                quote(name),
+                quote(&rtree)
+            )
+        } else {
+            format!("{} FROM {} AS t", select, quote(name))
+        };
+
+        Ok((
+            FeatureTable {
+                name: name.clone(),
+                columns,
+                projection,
+                indexed,
+                simplify: config.geopackage.simplify.get(name).cloned(),
+                query,
+            },
+            fields,
+        ))
+    }
+}
+
+#[async_trait]
+impl TileSource for GeoPackageSource {
+    async fn get_xyz_tile(&self, z: u8, x: u32, y: u32) -> Result<Option<TileData>> {
+        let max_tile = 1u32 << z;
+        if x >= max_tile || y >= max_tile {
+            return Err(TileServerError::InvalidCoordinates { z, x, y });
+        }
+        if z < self.metadata.minzoom || z > self.metadata.maxzoom {
+            return Ok(None);
+        }
+
+        let connections = Arc::clone(&self.connections);
+        let tables = Arc::clone(&self.tables);
+        let tile = tokio::task::spawn_blocking(move || {
+            let conn = connections.take()?;
+            let layers = tables
+                .iter()
+                .filter_map(|table| table.layer(&conn, z, x, y).transpose())
+                .collect::<Result<Vec<_>>>();
+            connections.put(conn);
+            layers.map(|layers| Tile { layers })
+        })
+        .await
+        .map_err(|e| TileServerError::GeoPackageError(format!("Task join error: {}", e)))??;
+
+        if tile.layers.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(TileData {
+            data: tile.encode_to_vec().into(),
+            format: TileFormat::Pbf,
+            compression: TileCompression::None,
+        }))
+    }
+
+    fn metadata(&self) -> &TileMetadata {
+        &self.metadata
+    }
+
+    fn backend(&self) -> &'static str {
+        "geopackage"
+    }
+
+    async fn health_check(&self) -> Result<()> {
+        let connections = Arc::clone(&self.connections);
+        tokio::task::spawn_blocking(move || {
+            let conn = connections.take()?;
+            let result = conn
+                .query_row("SELECT count(*) FROM gpkg_contents", [], |_| Ok(()))
+                .map_err(query_error);
+            connections.put(conn);
+            result
+        })
+        .await
+        .map_err(|e| TileServerError::GeoPackageError(format!("Task join error: {}", e)))?
+    }
+
+    fn last_modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_projections_meet_on_the_world_square() {
+        let zurich = [8.5417, 47.3769];
+        let world = Projection::Wgs84.project(zurich);
+        let meters = Projection::WebMercator.unproject(world);
+        assert!((meters[0] - 950_857.0).abs() < 1.0, "{:?}", meters);
+        assert!((meters[1] - 6_003_812.0).abs() < 1.0, "{:?}", meters);
+
+        let [lon, lat] = Projection::WebMercator.lon_lat(meters);
+        assert!((lon - zurich[0]).abs() < 1e-9 && (lat - zurich[1]).abs() < 1e-9);
+        assert_eq!(
+            Projection::from_srs("epsg", 900913),
+            Some(Projection::WebMercator)
+        );
+        assert_eq!(Projection::from_srs("EPSG", 2056), None);
+    }
+
+    #[test]
+    fn test_tile_parts_orient_rings() {
+        // Exterior ring clockwise and hole counterclockwise on the map
+        // (y up) are both the wrong way around for MVT (y down)
+        let square = |min: f64, max: f64, clockwise: bool| {
+            let mut ring = vec![[min, min], [max, min], [max, max], [min, max], [min, min]];
+            if clockwise {
+                ring.reverse();
+            }
+            ring
+        };
+        let polygon =
+            Geometry::Polygons(vec![vec![square(0.0, 4.0, true), square(1.0, 2.0, false)]]);
+        let to_tile = |coord: &Coord| [coord[0] as i64 * 512, 4096 - coord[1] as i64 * 512];
+
+        let (geom_type, rings) = tile_parts(&polygon, to_tile, 0.0);
+        assert_eq!(geom_type, GeomType::Polygon);
+        assert_eq!(rings.len(), 2);
+        assert!(ring_area(&rings[0]) > 0);
+        assert!(ring_area(&rings[1]) < 0);
+
+        // Rings collapsing to a point at this zoom are dropped, holes with them
+        let tiny = Geometry::Polygons(vec![vec![
+            square(0.0, 0.001, false),
+            square(0.0002, 0.0004, true),
+        ]]);
+        assert!(tile_parts(&tiny, to_tile, 0.0).1.is_empty());
+    }
+
+    #[test]
+    fn test_simplify() {
+        let line = vec![[0, 0], [100, 1], [200, -1], [300, 40], [400, 0]];
+        assert_eq!(simplify(line.clone(), 0.0), line);
+        assert_eq!(
+            simplify(line.clone(), 2.0),
+            vec![[0, 0], [200, -1], [300, 40], [400, 0]]
+        );
+        assert_eq!(simplify(line, 50.0), vec![[0, 0], [400, 0]]);
+
+        // A ring narrower than the tolerance collapses and is dropped
+        let sliver = Geometry::Polygons(vec![vec![vec![
+            [0.0, 0.0],
+            [100.0, 0.0],
+            [100.0, 1.0],
+            [0.0, 1.0],
+            [0.0, 0.0],
+        ]]]);
+        let to_tile = |coord: &Coord| [coord[0] as i64, coord[1] as i64];
+        assert_eq!(tile_parts(&sliver, to_tile, 0.0).1.len(), 1);
+        assert!(tile_parts(&sliver, to_tile, 4.0).1.is_empty());
+    }
+}
@@ -0,0 +1,212 @@
+//! Per-layer virtual sources of multi-layer archives
+//!
+//! With `expand_layers` set, each named entry in an archive's embedded
+//! `vector_layers` metadata is also served as its own source,
+//! `{id}.{layer}`, whose tiles keep only that layer. Zoom range and bounds
+//! come from the layer's entry, falling back to the archive's.
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use geozero::mvt::Message;
+use std::sync::Arc;
+
+use crate::error::Result;
+use crate::sources::{TileCompression, TileData, TileFormat, TileMetadata, TileSource};
+
+/// A single layer of a vector archive, served as a source of its own
+pub struct LayerSource {
+    archive: Arc<dyn TileSource>,
+    layer: String,
+    metadata: TileMetadata,
+}
+
+impl LayerSource {
+    /// Virtual sources for each layer of `archive`
+    ///
+    /// Empty unless the archive holds vector tiles whose metadata names more
+    /// than one layer; a single dataset is served as it is.
+    pub fn expand(archive: &Arc<dyn TileSource>) -> Vec<LayerSource> {
+        layer_metadata(archive.metadata())
+            .into_iter()
+            .map(|(layer, metadata)| LayerSource {
+                archive: Arc::clone(archive),
+                layer,
+                metadata,
+            })
+            .collect()
+    }
+
+    /// Name of the layer kept in tiles
+    pub fn layer(&self) -> &str {
+        &self.layer
+    }
+}
+
+/// ID of the virtual source serving `layer` of source `id`
+pub fn layer_source_id(id: &str, layer: &str) -> String {
+    format!("{}.{}", id, layer)
+}
+
+/// Layer names and metadata of the virtual sources for an archive
+fn layer_metadata(archive: &TileMetadata) -> Vec<(String, TileMetadata)> {
+    if archive.format != TileFormat::Pbf {
+        return Vec::new();
+    }
+    let Some(entries) = archive.vector_layers.as_ref().and_then(|v| v.as_array()) else {
+        return Vec::new();
+    };
+    let named: Vec<(&str, &serde_json::Value)> = entries
+        .iter()
+        .filter_map(|entry| Some((entry.get("id")?.as_str()?, entry)))
+        .filter(|(name, _)| !name.is_empty())
+        .collect();
+    if named.len() < 2 {
+        return Vec::new();
+    }
+
+    named
+        .into_iter()
+        .map(|(name, entry)| {
+            let zoom = |key: &str| {
+                entry
+                    .get(key)
+                    .and_then(|v| v.as_u64())
+                    .and_then(|z| u8::try_from(z).ok())
+            };
+            let minzoom = zoom("minzoom").unwrap_or(archive.minzoom);
+            let maxzoom = zoom("maxzoom").unwrap_or(archive.maxzoom).max(minzoom);
+            let bounds = entry
+                .get("bounds")
+                .and_then(|v| serde_json::from_value::<[f64; 4]>(v.clone()).ok())
+                .or(archive.bounds);
+            let center = bounds.map(|[west, south, east, north]| {
+                [(west + east) / 2.0, (south + north) / 2.0, minzoom as f64]
+            });
+
+            let metadata = TileMetadata {
+                id: layer_source_id(&archive.id, name),
+                name: format!("{} {}", archive.name, name),
+                description: entry
+                    .get("description")
+                    .and_then(|v| v.as_str())
+                    .filter(|description| !description.is_empty())
+                    .map(str::to_string),
+                attribution: archive.attribution.clone(),
+                format: archive.format,
+                minzoom,
+                maxzoom,
+                bounds,
+                center,
+                vector_layers: Some(serde_json::Value::Array(vec![entry.clone()])),
+                scheme: archive.scheme,
+                overzoom: archive.overzoom,
+                encoding: None,
+            };
+            (name.to_string(), metadata)
+        })
+        .collect()
+}
+
+#[async_trait]
+impl TileSource for LayerSource {
+    async fn get_xyz_tile(&self, z: u8, x: u32, y: u32) -> Result<Option<TileData>> {
+        if z < self.metadata.minzoom || z > self.metadata.maxzoom {
+            return Ok(None);
+        }
+        let Some(tile) = self.archive.get_xyz_tile(z, x, y).await? else {
+            return Ok(None);
+        };
+
+        let mut mvt = tile.decode_mvt()?;
+        mvt.layers.retain(|layer| layer.name == self.layer);
+        if mvt.layers.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(TileData {
+            data: Bytes::from(mvt.encode_to_vec()),
+            format: TileFormat::Pbf,
+            compression: TileCompression::None,
+        }))
+    }
+
+    fn metadata(&self) -> &TileMetadata {
+        &self.metadata
+    }
+
+    fn backend(&self) -> &'static str {
+        self.archive.backend()
+    }
+
+    async fn health_check(&self) -> Result<()> {
+        self.archive.health_check().await
+    }
+
+    fn last_modified(&self) -> Option<std::time::SystemTime> {
+        self.archive.last_modified()
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sources::TileScheme;
+    use serde_json::json;
+
+    fn archive(vector_layers: serde_json::Value) -> TileMetadata {
+        TileMetadata {
+            id: "planet".to_string(),
+            name: "Planet".to_string(),
+            description: None,
+            attribution: Some("OSM".to_string()),
+            format: TileFormat::Pbf,
+            minzoom: 0,
+            maxzoom: 14,
+            bounds: Some([-180.0, -85.0, 180.0, 85.0]),
+            center: None,
+            vector_layers: Some(vector_layers),
+            scheme: TileScheme::Xyz,
+            overzoom: false,
+            encoding: None,
+        }
+    }
+
+    #[test]
+    fn test_layer_metadata() {
+        let layers = layer_metadata(&archive(json!([
+            { "id": "roads", "minzoom": 6, "maxzoom": 12, "bounds": [5.0, 45.0, 11.0, 48.0] },
+            { "id": "water", "description": "Oceans and lakes" },
+        ])));
+        assert_eq!(layers.len(), 2);
+
+        let (name, roads) = &layers[0];
+        assert_eq!(name, "roads");
+        assert_eq!(roads.id, "planet.roads");
+        assert_eq!((roads.minzoom, roads.maxzoom), (6, 12));
+        assert_eq!(roads.bounds, Some([5.0, 45.0, 11.0, 48.0]));
+        assert_eq!(roads.center, Some([8.0, 46.5, 6.0]));
+        assert_eq!(roads.attribution.as_deref(), Some("OSM"));
+        assert_eq!(roads.vector_layers.as_ref().unwrap()[0]["id"], "roads");
+
+        // Missing ranges fall back to the archive's
+        let (_, water) = &layers[1];
+        assert_eq!((water.minzoom, water.maxzoom), (0, 14));
+        assert_eq!(water.bounds, Some([-180.0, -85.0, 180.0, 85.0]));
+        assert_eq!(water.description.as_deref(), Some("Oceans and lakes"));
+    }
+
+    #[test]
+    fn test_single_dataset_is_not_expanded() {
+        assert!(layer_metadata(&archive(json!([{ "id": "roads" }]))).is_empty());
+        assert!(layer_metadata(&archive(json!([{ "id": "roads" }, { "fields": {} }]))).is_empty());
+        assert!(layer_metadata(&archive(json!({}))).is_empty());
+
+        let mut raster = archive(json!([{ "id": "a" }, { "id": "b" }]));
+        raster.format = TileFormat::Png;
+        assert!(layer_metadata(&raster).is_empty());
+    }
+}
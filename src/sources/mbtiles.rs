@@ -1,21 +1,180 @@
 use async_trait::async_trait;
-use rusqlite::{Connection, OpenFlags};
+use opentelemetry::metrics::Histogram;
+use opentelemetry::KeyValue;
+use rusqlite::{Connection, InterruptHandle, OpenFlags};
+use std::collections::HashMap;
+use std::ops::Deref;
 use std::path::Path;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::time::{Instant, SystemTime};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 
 use crate::config::SourceConfig;
 use crate::error::{Result, TileServerError};
-use crate::sources::{TileCompression, TileData, TileFormat, TileMetadata, TileSource};
+use crate::integrity::IntegrityIssue;
+use crate::sources::{
+    sniff_compression, FormatCheck, TileCompression, TileData, TileFormat, TileInfo, TileMetadata,
+    TileRange, TileScheme, TileSource,
+};
+
+/// Read-only connections opened per MBTiles file unless `pool_size` is set
+pub const DEFAULT_POOL_SIZE: usize = 4;
+
+static POOL_WAIT: OnceLock<Histogram<f64>> = OnceLock::new();
+
+fn pool_wait() -> &'static Histogram<f64> {
+    POOL_WAIT.get_or_init(|| {
+        opentelemetry::global::meter("tileserver-rs")
+            .f64_histogram("tileserver_mbtiles_pool_wait_seconds")
+            .with_description("Time spent waiting for a free MBTiles connection")
+            .with_unit("s")
+            .build()
+    })
+}
+
+/// Fixed set of read-only connections to one MBTiles file
+///
+/// Each query takes a connection for itself, so connections are opened
+/// without SQLite's own mutex and concurrent reads don't serialize.
+struct ConnectionPool {
+    id: String,
+    idle: Mutex<Vec<Connection>>,
+    permits: Arc<Semaphore>,
+}
+
+/// Connection checked out of a [`ConnectionPool`], returned on drop
+struct PooledConnection {
+    conn: Option<Connection>,
+    pool: Arc<ConnectionPool>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl ConnectionPool {
+    fn new(id: &str, connections: Vec<Connection>) -> Arc<Self> {
+        Arc::new(Self {
+            id: id.to_string(),
+            permits: Arc::new(Semaphore::new(connections.len())),
+            idle: Mutex::new(connections),
+        })
+    }
+
+    /// Wait for a free connection
+    async fn get(self: &Arc<Self>) -> Result<PooledConnection> {
+        let start = Instant::now();
+        let permit =
+            self.permits.clone().acquire_owned().await.map_err(|e| {
+                TileServerError::MbTilesError(format!("Connection pool closed: {}", e))
+            })?;
+        pool_wait().record(
+            start.elapsed().as_secs_f64(),
+            &[KeyValue::new("source", self.id.clone())],
+        );
+
+        // Holding a permit guarantees an idle connection
+        let conn = self
+            .idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop()
+            .expect("a permit is held for every checked out connection");
+        Ok(PooledConnection {
+            conn: Some(conn),
+            pool: self.clone(),
+            _permit: permit,
+        })
+    }
+}
+
+impl Deref for PooledConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn
+            .as_ref()
+            .expect("connection is taken on drop only")
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            self.pool
+                .idle
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(conn);
+        }
+    }
+}
+
+/// Interrupts the query running on a connection when dropped, until the
+/// blocking task running it takes the handle back
+struct InterruptOnDrop {
+    handle: Arc<Mutex<Option<InterruptHandle>>>,
+}
+
+impl InterruptOnDrop {
+    fn new(conn: &Connection) -> Self {
+        Self {
+            handle: Arc::new(Mutex::new(Some(conn.get_interrupt_handle()))),
+        }
+    }
+}
+
+impl Drop for InterruptOnDrop {
+    fn drop(&mut self) {
+        if let Some(handle) = self
+            .handle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+        {
+            handle.interrupt();
+        }
+    }
+}
+
+/// Open a read-only SQLite connection, skipping locking and change
+/// detection for files marked immutable
+pub(crate) fn open_connection(path: &Path, immutable: bool) -> rusqlite::Result<Connection> {
+    let flags = OpenFlags::SQLITE_OPEN_READ_ONLY
+        | OpenFlags::SQLITE_OPEN_NO_MUTEX
+        | OpenFlags::SQLITE_OPEN_URI;
+    let uri = format!(
+        "file:{}{}",
+        uri_path(path),
+        if immutable { "?immutable=1" } else { "" }
+    );
+    Connection::open_with_flags(uri, flags)
+}
+
+/// Percent-encode the characters that would end the path of a `file:` URI
+fn uri_path(path: &Path) -> String {
+    let mut encoded = String::new();
+    for c in path.to_string_lossy().chars() {
+        match c {
+            '%' => encoded.push_str("%25"),
+            '?' => encoded.push_str("%3f"),
+            '#' => encoded.push_str("%23"),
+            _ => encoded.push(c),
+        }
+    }
+    encoded
+}
 
 /// MBTiles tile source
 ///
 /// Implements the MBTiles 1.3 specification for serving tiles from SQLite databases.
 /// See: https://github.com/mapbox/mbtiles-spec/blob/master/1.3/spec.md
 pub struct MbTilesSource {
-    /// SQLite connection (wrapped in Arc<Mutex> for thread-safety)
-    conn: Arc<Mutex<Connection>>,
+    /// Read-only SQLite connections, one per concurrent query
+    pool: Arc<ConnectionPool>,
     /// Cached metadata
     metadata: TileMetadata,
+    /// Corrects mislabeled tile formats, unless `verify_format = false`
+    format_check: Option<FormatCheck>,
+    /// Modification time of the database file when it was opened
+    modified: Option<SystemTime>,
 }
 
 impl MbTilesSource {
@@ -31,24 +190,50 @@ impl MbTilesSource {
             )));
         }
 
-        // Open SQLite connection in read-only mode
-        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        let immutable = config.options.immutable;
+        let pool_size = config.options.pool_size.unwrap_or(DEFAULT_POOL_SIZE).max(1);
+        let connections = (0..pool_size)
+            .map(|_| open_connection(path, immutable))
+            .collect::<rusqlite::Result<Vec<_>>>()
             .map_err(|e| TileServerError::MbTilesError(e.to_string()))?;
 
         // Read metadata from the database
-        let metadata = Self::read_metadata(&conn, config)?;
+        let metadata = Self::read_metadata(&connections[0], config)?;
+
+        // Readers see committed WAL transactions, except with immutable=1,
+        // which reads the main file only
+        let journal_mode: String = connections[0]
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+            .map_err(|e| TileServerError::MbTilesError(e.to_string()))?;
+        if immutable && journal_mode.eq_ignore_ascii_case("wal") {
+            tracing::warn!(
+                "MBTiles source '{}' is in WAL mode but marked immutable: changes not yet checkpointed into {} are not served",
+                config.id,
+                config.path
+            );
+        }
 
         tracing::info!(
-            "Loaded MBTiles source '{}': {} (zoom {}-{})",
+            "Loaded MBTiles source '{}': {} (zoom {}-{}, {} connections{})",
             config.id,
             metadata.name,
             metadata.minzoom,
-            metadata.maxzoom
+            metadata.maxzoom,
+            pool_size,
+            if immutable { ", immutable" } else { "" }
         );
 
+        let format_check = config
+            .options
+            .verify_format
+            .unwrap_or(true)
+            .then(|| FormatCheck::new(&config.id, metadata.format, TileCompression::None));
+
         Ok(Self {
-            conn: Arc::new(Mutex::new(conn)),
+            pool: ConnectionPool::new(&config.id, connections),
             metadata,
+            format_check,
+            modified: std::fs::metadata(path).and_then(|m| m.modified()).ok(),
         })
     }
 
@@ -82,11 +267,7 @@ impl MbTilesSource {
             match key.as_str() {
                 "name" => name = value,
                 "description" => description = Some(value),
-                "attribution" => {
-                    if attribution.is_none() {
-                        attribution = Some(value);
-                    }
-                }
+                "attribution" if attribution.is_none() => attribution = Some(value),
                 "format" => {
                     format = match value.to_lowercase().as_str() {
                         "pbf" => TileFormat::Pbf,
@@ -159,9 +340,24 @@ impl MbTilesSource {
             bounds,
             center,
             vector_layers,
+            scheme: config.scheme,
+            overzoom: config.overzoom,
+            encoding: None,
         })
     }
 
+    /// Detect gzip or zstd compression by checking magic bytes
+    fn detect_compression(data: &[u8]) -> TileCompression {
+        sniff_compression(data).unwrap_or(TileCompression::None)
+    }
+
+    /// Format tiles are served as, after any correction of the metadata
+    fn served_format(&self) -> TileFormat {
+        self.format_check
+            .as_ref()
+            .map_or(self.metadata.format, |check| check.format())
+    }
+
     /// Flip Y coordinate for TMS scheme (MBTiles uses TMS, most clients use XYZ)
     fn flip_y(z: u8, y: u32) -> u32 {
         (1u32 << z) - 1 - y
@@ -170,7 +366,7 @@ impl MbTilesSource {
 
 #[async_trait]
 impl TileSource for MbTilesSource {
-    async fn get_tile(&self, z: u8, x: u32, y: u32) -> Result<Option<TileData>> {
+    async fn get_xyz_tile(&self, z: u8, x: u32, y: u32) -> Result<Option<TileData>> {
         // Validate coordinates
         let max_tile = 1u32 << z;
         if x >= max_tile || y >= max_tile {
@@ -185,16 +381,11 @@ impl TileSource for MbTilesSource {
         // MBTiles uses TMS scheme (Y is flipped)
         let tms_y = Self::flip_y(z, y);
 
-        // Clone the connection Arc for use in the blocking task
-        let conn = self.conn.clone();
+        let conn = self.pool.get().await?;
         let format = self.metadata.format;
 
         // Run the SQLite query in a blocking task to avoid blocking the async runtime
         let result = tokio::task::spawn_blocking(move || {
-            let conn = conn.lock().map_err(|e| {
-                TileServerError::MbTilesError(format!("Failed to acquire connection lock: {}", e))
-            })?;
-
             let mut stmt = conn
                 .prepare_cached("SELECT tile_data FROM tiles WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3")
                 .map_err(|e| TileServerError::MbTilesError(e.to_string()))?;
@@ -203,36 +394,212 @@ impl TileSource for MbTilesSource {
                 .query_row([z as i32, x as i32, tms_y as i32], |row| row.get(0))
                 .ok();
 
-            Ok::<_, TileServerError>(tile_data.map(|data| {
-                // Detect gzip compression by checking magic bytes
-                let compression = if data.len() >= 2 && data[0] == 0x1f && data[1] == 0x8b {
-                    TileCompression::Gzip
-                } else {
-                    TileCompression::None
-                };
-
-                TileData {
-                    data: data.into(),
-                    format,
-                    compression,
-                }
+            Ok::<_, TileServerError>(tile_data.map(|data| TileData {
+                compression: Self::detect_compression(&data),
+                data: data.into(),
+                format,
             }))
         })
         .await
         .map_err(|e| TileServerError::MbTilesError(format!("Task join error: {}", e)))??;
 
-        Ok(result)
+        Ok(match &self.format_check {
+            Some(check) => result.map(|tile| check.check(tile)),
+            None => result,
+        })
+    }
+
+    async fn tile_info(&self, z: u8, x: u32, y: u32) -> Result<Option<TileInfo>> {
+        if self.metadata.overzoom && z > self.metadata.maxzoom {
+            return Ok(self
+                .get_tile_with_overzoom(z, x, y)
+                .await?
+                .map(|tile| tile.info()));
+        }
+
+        let max_tile = 1u32 << z;
+        if x >= max_tile || y >= max_tile {
+            return Err(TileServerError::InvalidCoordinates { z, x, y });
+        }
+        if z < self.metadata.minzoom || z > self.metadata.maxzoom {
+            return Ok(None);
+        }
+
+        // MBTiles stores TMS rows, which TMS sources are already addressed in
+        let tms_y = match self.metadata.scheme {
+            TileScheme::Tms => y,
+            TileScheme::Xyz => Self::flip_y(z, y),
+        };
+        let conn = self.pool.get().await?;
+        let format = self.served_format();
+
+        // Only the size and the compression magic bytes leave SQLite
+        tokio::task::spawn_blocking(move || {
+            let mut stmt = conn
+                .prepare_cached("SELECT length(tile_data), substr(tile_data, 1, 4) FROM tiles WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3")
+                .map_err(|e| TileServerError::MbTilesError(e.to_string()))?;
+
+            let info: Option<(i64, Vec<u8>)> = stmt
+                .query_row([z as i32, x as i32, tms_y as i32], |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })
+                .ok();
+
+            Ok(info.map(|(size, magic)| TileInfo {
+                size: size as u64,
+                format,
+                compression: Self::detect_compression(&magic),
+            }))
+        })
+        .await
+        .map_err(|e| TileServerError::MbTilesError(format!("Task join error: {}", e)))?
+    }
+
+    fn last_modified(&self) -> Option<SystemTime> {
+        self.modified
     }
 
     fn metadata(&self) -> &TileMetadata {
         &self.metadata
     }
 
+    async fn health_check(&self) -> Result<()> {
+        let conn = self.pool.get().await?;
+
+        tokio::task::spawn_blocking(move || {
+            conn.query_row("SELECT 1", [], |row| row.get::<_, i32>(0))
+                .map(|_| ())
+                .map_err(|e| TileServerError::MbTilesError(e.to_string()))
+        })
+        .await
+        .map_err(|e| TileServerError::MbTilesError(format!("Task join error: {}", e)))?
+    }
+
+    async fn tile_count(&self) -> Result<Option<u64>> {
+        let conn = self.pool.get().await?;
+
+        tokio::task::spawn_blocking(move || {
+            conn.query_row("SELECT COUNT(*) FROM tiles", [], |row| row.get::<_, i64>(0))
+                .map(|count| Some(count as u64))
+                .map_err(|e| TileServerError::MbTilesError(e.to_string()))
+        })
+        .await
+        .map_err(|e| TileServerError::MbTilesError(format!("Task join error: {}", e)))?
+    }
+
+    async fn check_integrity(&self) -> Result<Vec<IntegrityIssue>> {
+        let conn = self.pool.get().await?;
+
+        // A validation that runs out of time drops this future, which stops
+        // the check so its connection goes back to the pool
+        let interrupt = InterruptOnDrop::new(&conn);
+        let running = interrupt.handle.clone();
+        tokio::task::spawn_blocking(move || {
+            if running
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .is_none()
+            {
+                return Err(TileServerError::MbTilesError(
+                    "Integrity check interrupted".to_string(),
+                ));
+            }
+            let issues = integrity_issues(&conn);
+            // Disarm before the connection returns to the pool, where an
+            // interrupt would stop somebody else's query
+            running
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .take();
+            issues.map_err(|e| TileServerError::MbTilesError(e.to_string()))
+        })
+        .await
+        .map_err(|e| TileServerError::MbTilesError(format!("Task join error: {}", e)))?
+    }
+
+    async fn list_tiles(&self, range: TileRange, limit: usize) -> Result<Option<Vec<(u32, u32)>>> {
+        let conn = self.pool.get().await?;
+        let z = range.z;
+
+        tokio::task::spawn_blocking(move || {
+            // Rows are stored in TMS, so the XYZ row range flips
+            let mut stmt = conn
+                .prepare_cached(
+                    "SELECT tile_column, tile_row FROM tiles \
+                     WHERE zoom_level = ?1 AND tile_column BETWEEN ?2 AND ?3 AND tile_row BETWEEN ?4 AND ?5 \
+                     ORDER BY tile_column, tile_row DESC LIMIT ?6",
+                )
+                .map_err(|e| TileServerError::MbTilesError(e.to_string()))?;
+
+            let rows = stmt
+                .query_map(
+                    rusqlite::params![
+                        z,
+                        range.min_x,
+                        range.max_x,
+                        Self::flip_y(z, range.max_y),
+                        Self::flip_y(z, range.min_y),
+                        limit as i64,
+                    ],
+                    |row| Ok((row.get::<_, u32>(0)?, row.get::<_, u32>(1)?)),
+                )
+                .map_err(|e| TileServerError::MbTilesError(e.to_string()))?;
+
+            rows.map(|row| {
+                row.map(|(x, tms_y)| (x, Self::flip_y(z, tms_y)))
+                    .map_err(|e| TileServerError::MbTilesError(e.to_string()))
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(Some)
+        })
+        .await
+        .map_err(|e| TileServerError::MbTilesError(format!("Task join error: {}", e)))?
+    }
+
+    fn backend(&self) -> &'static str {
+        "mbtiles"
+    }
+
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
 }
 
+/// Problems found by SQLite's `PRAGMA integrity_check` and in the metadata
+/// table, which MBTiles 1.3 requires to have `name` and `format`, plus `json`
+/// for vector tiles
+fn integrity_issues(conn: &Connection) -> rusqlite::Result<Vec<IntegrityIssue>> {
+    let mut issues = Vec::new();
+
+    let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+    let results = stmt.query_map([], |row| row.get::<_, String>(0))?;
+    for result in results {
+        let result = result?;
+        if result != "ok" {
+            issues.push(IntegrityIssue::new("integrity_check", result));
+        }
+    }
+
+    let mut stmt = conn.prepare("SELECT name, value FROM metadata")?;
+    let metadata: HashMap<String, String> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    let mut required = vec!["name", "format"];
+    if metadata.get("format").map(String::as_str) == Some("pbf") {
+        required.push("json");
+    }
+    for key in required {
+        if !metadata.contains_key(key) {
+            issues.push(IntegrityIssue::new(
+                "metadata",
+                format!("Metadata table has no '{}'", key),
+            ));
+        }
+    }
+
+    Ok(issues)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -252,4 +619,164 @@ mod tests {
         assert_eq!(MbTilesSource::flip_y(2, 2), 1);
         assert_eq!(MbTilesSource::flip_y(2, 3), 0);
     }
+
+    fn zurich_config(pool_size: usize) -> SourceConfig {
+        toml::from_str(&format!(
+            r#"
+            id = "zurich"
+            type = "mbtiles"
+            path = "data/tiles/zurich_switzerland.mbtiles"
+
+            [options]
+            pool_size = {}
+            immutable = true
+            "#,
+            pool_size
+        ))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_queries_share_the_pool() {
+        let source = MbTilesSource::from_file(&zurich_config(4)).await.unwrap();
+        let timeout = std::time::Duration::from_secs(5);
+
+        // Three connections stay busy, as under a slow query, and the 32
+        // reads still go through on the remaining one
+        let held = vec![
+            source.pool.get().await.unwrap(),
+            source.pool.get().await.unwrap(),
+            source.pool.get().await.unwrap(),
+        ];
+        let reads = futures::future::join_all((0..32).map(|_| source.get_tile(7, 67, 44)));
+        let tiles = tokio::time::timeout(timeout, reads).await.unwrap();
+        assert!(tiles.iter().all(|tile| matches!(tile, Ok(Some(_)))));
+
+        // Once every connection is taken, queries wait for one to return
+        let last = source.pool.get().await.unwrap();
+        let wait = std::time::Duration::from_millis(50);
+        assert!(tokio::time::timeout(wait, source.get_tile(0, 0, 0))
+            .await
+            .is_err());
+        drop(last);
+        drop(held);
+        assert!(tokio::time::timeout(timeout, source.get_tile(0, 0, 0))
+            .await
+            .unwrap()
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn test_reads_run_concurrently() {
+        let source = MbTilesSource::from_file(&zurich_config(8)).await.unwrap();
+
+        // Every query waits for all the others to start, which only
+        // finishes if the eight of them run at the same time
+        let barrier = Arc::new(std::sync::Barrier::new(8));
+        let reads = (0..8).map(|_| {
+            let barrier = barrier.clone();
+            let pool = source.pool.clone();
+            async move {
+                let conn = pool.get().await.unwrap();
+                tokio::task::spawn_blocking(move || {
+                    barrier.wait();
+                    conn.query_row(
+                        "SELECT length(tile_data) FROM tiles WHERE zoom_level = 7 \
+                         AND tile_column = 66 AND tile_row = 83",
+                        [],
+                        |row| row.get::<_, i64>(0),
+                    )
+                    .unwrap()
+                })
+                .await
+                .unwrap()
+            }
+        });
+        let timeout = std::time::Duration::from_secs(5);
+        let sizes = tokio::time::timeout(timeout, futures::future::join_all(reads))
+            .await
+            .expect("Reads should not wait for each other");
+        assert!(sizes.iter().all(|&size| size > 0));
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_integrity_check_returns_its_connection() {
+        // Enough tiles for the integrity check to take a while
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("large.mbtiles");
+        Connection::open(&path)
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE metadata (name TEXT, value TEXT);
+                 INSERT INTO metadata VALUES ('name', 'large'), ('format', 'png');
+                 CREATE TABLE tiles (zoom_level INTEGER, tile_column INTEGER,
+                                     tile_row INTEGER, tile_data BLOB);
+                 CREATE UNIQUE INDEX tile_index ON tiles (zoom_level, tile_column, tile_row);
+                 WITH RECURSIVE n(i) AS (SELECT 0 UNION ALL SELECT i + 1 FROM n WHERE i < 199999)
+                 INSERT INTO tiles SELECT 18, i / 512, i % 512, randomblob(256) FROM n;",
+            )
+            .unwrap();
+        let config: SourceConfig = toml::from_str(&format!(
+            "id = \"large\"\ntype = \"mbtiles\"\npath = \"{}\"\n[options]\npool_size = 1",
+            path.display()
+        ))
+        .unwrap();
+        let source = MbTilesSource::from_file(&config).await.unwrap();
+
+        let started = Instant::now();
+        assert!(source.check_integrity().await.unwrap().is_empty());
+        let full = started.elapsed();
+
+        // Giving up on the check stops it, freeing the only connection
+        let started = Instant::now();
+        let check = tokio::time::timeout(full / 10, source.check_integrity()).await;
+        assert!(check.is_err(), "the check finished within {:?}", full / 10);
+        let count = tokio::time::timeout(full, source.tile_count()).await;
+        assert_eq!(count.unwrap().unwrap(), Some(200_000));
+        assert!(
+            started.elapsed() < full / 2,
+            "waited {:?} for a check taking {:?}",
+            started.elapsed(),
+            full
+        );
+    }
+
+    #[test]
+    fn test_uri_path() {
+        assert_eq!(
+            uri_path(Path::new("data/tiles/a.mbtiles")),
+            "data/tiles/a.mbtiles"
+        );
+        assert_eq!(
+            uri_path(Path::new("/tiles/50%?#.mbtiles")),
+            "/tiles/50%25%3f%23.mbtiles"
+        );
+    }
+
+    #[test]
+    fn test_integrity_issues() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE metadata (name TEXT, value TEXT);
+             CREATE TABLE tiles (zoom_level INTEGER, tile_column INTEGER,
+                                 tile_row INTEGER, tile_data BLOB);
+             INSERT INTO metadata VALUES ('format', 'pbf');",
+        )
+        .unwrap();
+
+        let issues = integrity_issues(&conn).unwrap();
+        let messages: Vec<&str> = issues.iter().map(|i| i.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "Metadata table has no 'name'",
+                "Metadata table has no 'json'"
+            ]
+        );
+
+        conn.execute_batch("INSERT INTO metadata VALUES ('name', 'test'), ('json', '{}');")
+            .unwrap();
+        assert!(integrity_issues(&conn).unwrap().is_empty());
+    }
 }
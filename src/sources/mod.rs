@@ -1,17 +1,31 @@
 use async_trait::async_trait;
 use bytes::Bytes;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::str::FromStr;
 
+use crate::config::DemEncoding;
+
 #[cfg(feature = "raster")]
 pub mod cog;
+pub mod gcs;
+pub mod geojson;
+pub mod geopackage;
+pub mod layers;
 pub mod manager;
 pub mod mbtiles;
+pub mod overzoom;
 pub mod pmtiles;
 #[cfg(feature = "postgres")]
 pub mod postgres;
+pub mod proxy;
+#[cfg(feature = "raster")]
+pub mod relief;
+pub mod resilient;
+#[cfg(feature = "raster")]
+pub mod terrain;
 
-pub use manager::SourceManager;
+pub use manager::{SharedSources, SourceManager};
 
 /// Tile format enum
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -25,6 +39,15 @@ pub enum TileFormat {
     Unknown,
 }
 
+/// How a tile is sent, picked from the request's `Accept` header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileRepresentation {
+    /// The tile as stored
+    Native,
+    /// A vector tile converted to a GeoJSON FeatureCollection
+    GeoJson,
+}
+
 impl TileFormat {
     pub fn content_type(&self) -> &'static str {
         match self {
@@ -37,6 +60,18 @@ impl TileFormat {
         }
     }
 
+    /// Lowercase format name, e.g. for the `X-Tile-Format` debug header
+    pub fn name(&self) -> &'static str {
+        match self {
+            TileFormat::Pbf => "pbf",
+            TileFormat::Png => "png",
+            TileFormat::Jpeg => "jpeg",
+            TileFormat::Webp => "webp",
+            TileFormat::Avif => "avif",
+            TileFormat::Unknown => "unknown",
+        }
+    }
+
     pub fn extension(&self) -> &'static str {
         match self {
             TileFormat::Pbf => "pbf",
@@ -47,6 +82,77 @@ impl TileFormat {
             TileFormat::Unknown => "bin",
         }
     }
+
+    /// Media types tiles of this format can be sent as, preferred first
+    pub fn media_types(&self) -> &'static [(&'static str, TileRepresentation)] {
+        match self {
+            TileFormat::Pbf => &[
+                (
+                    "application/vnd.mapbox-vector-tile",
+                    TileRepresentation::Native,
+                ),
+                ("application/x-protobuf", TileRepresentation::Native),
+                ("application/geo+json", TileRepresentation::GeoJson),
+            ],
+            TileFormat::Png => &[("image/png", TileRepresentation::Native)],
+            TileFormat::Jpeg => &[("image/jpeg", TileRepresentation::Native)],
+            TileFormat::Webp => &[("image/webp", TileRepresentation::Native)],
+            TileFormat::Avif => &[("image/avif", TileRepresentation::Native)],
+            TileFormat::Unknown => &[("application/octet-stream", TileRepresentation::Native)],
+        }
+    }
+
+    /// Pick the representation of a tile of this format that best matches
+    /// an `Accept` header, or `None` when nothing offered is acceptable
+    ///
+    /// Media ranges are matched most specific first (`type/subtype`, then
+    /// `type/*`, then `*/*`) and weighted by their `q` value; ties go to the
+    /// preferred media type. A missing header, or one without a single
+    /// media range, gets the native format.
+    pub fn negotiate(&self, accept: Option<&str>) -> Option<TileRepresentation> {
+        let ranges: Vec<(&str, &str, f32)> = accept
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|entry| {
+                let mut parts = entry.split(';');
+                let (kind, subtype) = parts.next()?.trim().split_once('/')?;
+                let q = parts
+                    .filter_map(|param| param.trim().strip_prefix("q="))
+                    .find_map(|q| q.trim().parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                Some((kind.trim(), subtype.trim(), q))
+            })
+            .collect();
+        if ranges.is_empty() {
+            return Some(TileRepresentation::Native);
+        }
+
+        let mut best: Option<(f32, TileRepresentation)> = None;
+        for (media_type, representation) in self.media_types() {
+            let (kind, subtype) = media_type.split_once('/').unwrap_or_default();
+            let q = ranges
+                .iter()
+                .filter_map(|(k, s, q)| {
+                    let specificity = match (*k, *s) {
+                        ("*", "*") => 0,
+                        (k, "*") if k.eq_ignore_ascii_case(kind) => 1,
+                        (k, s)
+                            if k.eq_ignore_ascii_case(kind) && s.eq_ignore_ascii_case(subtype) =>
+                        {
+                            2
+                        }
+                        _ => return None,
+                    };
+                    Some((specificity, *q))
+                })
+                .max_by_key(|(specificity, _)| *specificity)
+                .map_or(0.0, |(_, q)| q);
+            if q > 0.0 && best.map_or(true, |(best_q, _)| q > best_q) {
+                best = Some((q, *representation));
+            }
+        }
+        best.map(|(_, representation)| representation)
+    }
 }
 
 impl FromStr for TileFormat {
@@ -82,6 +188,162 @@ impl TileCompression {
             TileCompression::Brotli => Some("br"),
         }
     }
+
+    /// Whether a client sending the given `Accept-Encoding` header accepts this encoding
+    ///
+    /// A missing header accepts any encoding, as does `*`. Codings with
+    /// `q=0` are refused.
+    pub fn is_accepted_by(&self, accept_encoding: Option<&str>) -> bool {
+        let Some(encoding) = self.content_encoding() else {
+            return true;
+        };
+        let Some(accept_encoding) = accept_encoding else {
+            return true;
+        };
+
+        let mut wildcard = false;
+        for entry in accept_encoding.split(',') {
+            let mut parts = entry.split(';');
+            let coding = parts.next().unwrap_or_default().trim();
+            let accepted = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .all(|q| q.trim().parse::<f32>().map_or(true, |q| q > 0.0));
+
+            if coding.eq_ignore_ascii_case(encoding) {
+                return accepted;
+            }
+            if coding == "*" {
+                wildcard = accepted;
+            }
+        }
+        wildcard
+    }
+}
+
+/// Tile row addressing scheme
+///
+/// XYZ (the default) puts row 0 at the top of the map, TMS puts it at the bottom.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TileScheme {
+    #[default]
+    Xyz,
+    Tms,
+}
+
+impl TileScheme {
+    pub fn is_xyz(&self) -> bool {
+        *self == TileScheme::Xyz
+    }
+
+    /// Convert a row addressed in this scheme to the equivalent XYZ row
+    ///
+    /// Returns `None` if the row is out of range for the zoom level.
+    pub fn xyz_row(&self, z: u8, y: u32) -> Option<u32> {
+        let flipped = flip_y(z, y)?;
+        Some(match self {
+            TileScheme::Xyz => y,
+            TileScheme::Tms => flipped,
+        })
+    }
+}
+
+impl FromStr for TileScheme {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "xyz" => Ok(TileScheme::Xyz),
+            "tms" => Ok(TileScheme::Tms),
+            _ => Err(()),
+        }
+    }
+}
+
+/// TileJSON spec version of a response
+///
+/// 3.0.0 is the default. 2.2.0 is for older clients: it always states the
+/// `scheme` and has no top-level `vector_layers`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TileJsonVersion {
+    #[serde(rename = "2.2.0", alias = "2.2")]
+    V2_2,
+    #[default]
+    #[serde(rename = "3.0.0", alias = "3.0")]
+    V3_0,
+}
+
+impl TileJsonVersion {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TileJsonVersion::V2_2 => "2.2.0",
+            TileJsonVersion::V3_0 => "3.0.0",
+        }
+    }
+}
+
+/// Flip a tile row between the XYZ and TMS schemes (y = 2^z - 1 - y)
+///
+/// Returns `None` if `y` is out of range for the zoom level.
+pub fn flip_y(z: u8, y: u32) -> Option<u32> {
+    1u32.checked_shl(u32::from(z))?
+        .checked_sub(1)?
+        .checked_sub(y)
+}
+
+/// Highest zoom level a tile can be requested at
+pub const MAX_TILE_ZOOM: u8 = 30;
+
+/// The first coordinate of z/x/y that names no tile, with its value and
+/// the largest value allowed
+///
+/// `z` must be at most [`MAX_TILE_ZOOM`]; `x` and `y` must be below `2^z`.
+pub fn out_of_range_coordinate(z: u8, x: u32, y: u32) -> Option<(&'static str, u32, u32)> {
+    if z > MAX_TILE_ZOOM {
+        return Some(("z", u32::from(z), u32::from(MAX_TILE_ZOOM)));
+    }
+    let max = (1u32 << z) - 1;
+    if x > max {
+        Some(("x", x, max))
+    } else if y > max {
+        Some(("y", y, max))
+    } else {
+        None
+    }
+}
+
+/// Reject tile coordinates outside the tile grid with a 400
+pub fn validate_tile_coords(z: u8, x: u32, y: u32) -> crate::error::Result<()> {
+    match out_of_range_coordinate(z, x, y) {
+        Some(_) => Err(crate::error::TileServerError::InvalidCoordinates { z, x, y }),
+        None => Ok(()),
+    }
+}
+
+/// Largest `?buffer=` accepted, in pixels: a whole tile, as in MapLibre
+pub const MAX_TILE_BUFFER: u32 = 512;
+
+/// Pixels per tile side that `?buffer=` is measured against, as for
+/// MapLibre's GeoJSON `buffer` option (whose default is 128)
+const BUFFER_TILE_SIZE: u32 = 512;
+
+/// Parse a `?buffer=` value in pixels
+pub fn parse_tile_buffer(value: &str) -> crate::error::Result<u32> {
+    value
+        .parse::<u32>()
+        .ok()
+        .filter(|&buffer| buffer <= MAX_TILE_BUFFER)
+        .ok_or_else(|| {
+            crate::error::TileServerError::BadRequest(format!(
+                "buffer must be a number of pixels from 0 to {}",
+                MAX_TILE_BUFFER
+            ))
+        })
+}
+
+/// A buffer in pixels as units of a tile with the given `extent`
+pub fn buffer_units(buffer: u32, extent: u32) -> u32 {
+    (u64::from(buffer) * u64::from(extent) / u64::from(BUFFER_TILE_SIZE)) as u32
 }
 
 /// Metadata for a tile source
@@ -112,9 +374,18 @@ pub struct TileMetadata {
     /// Vector layers (for vector tiles)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub vector_layers: Option<serde_json::Value>,
+    /// Row addressing scheme used by tile requests against this source
+    #[serde(default, skip_serializing_if = "TileScheme::is_xyz")]
+    pub scheme: TileScheme,
+    /// Serve tiles beyond `maxzoom` by overzooming the `maxzoom` tile
+    #[serde(skip)]
+    pub overzoom: bool,
+    /// Elevation encoding of the tiles of raster-dem sources
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<DemEncoding>,
 }
 
-/// TileJSON 3.0 response
+/// TileJSON 3.0 (or 2.2) response
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TileJson {
     pub tilejson: String,
@@ -132,18 +403,45 @@ pub struct TileJson {
     pub bounds: Option<[f64; 4]>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub center: Option<[f64; 3]>,
+    /// Not part of TileJSON 2.2, so always absent there
     #[serde(skip_serializing_if = "Option::is_none")]
     pub vector_layers: Option<serde_json::Value>,
+    /// Always present in TileJSON 2.2; in 3.0 only when the source is
+    /// addressed with a non-default scheme
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scheme: Option<TileScheme>,
+    /// Number of stored tiles; only filled in by `/index.json`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tile_count: Option<u64>,
+    /// Size of the source file; only filled in by `/index.json`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size_bytes: Option<u64>,
+    /// HTML preview page; only filled in by `/index.json`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preview: Option<String>,
+    /// Elevation encoding of raster-dem tiles (`mapbox` or `terrarium`),
+    /// read by MapLibre from the TileJSON of `raster-dem` sources
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+    /// `x-` extension fields, e.g. the storage statistics sent with
+    /// `?extended=true`
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub extensions: Option<HashMap<String, serde_json::Value>>,
 }
 
 impl TileMetadata {
     /// Convert to TileJSON format
-    pub fn to_tilejson(&self, base_url: &str) -> TileJson {
-        self.to_tilejson_with_key(base_url, None)
+    pub fn to_tilejson(&self, base_url: &str, version: TileJsonVersion) -> TileJson {
+        self.to_tilejson_with_key(base_url, None, version)
     }
 
     /// Convert to TileJSON format with optional API key
-    pub fn to_tilejson_with_key(&self, base_url: &str, key: Option<&str>) -> TileJson {
+    pub fn to_tilejson_with_key(
+        &self,
+        base_url: &str,
+        key: Option<&str>,
+        version: TileJsonVersion,
+    ) -> TileJson {
         let key_query = key
             .map(|k| format!("?key={}", urlencoding::encode(k)))
             .unwrap_or_default();
@@ -156,8 +454,16 @@ impl TileMetadata {
             key_query
         );
 
+        let (vector_layers, scheme) = match version {
+            TileJsonVersion::V2_2 => (None, Some(self.scheme)),
+            TileJsonVersion::V3_0 => (
+                self.vector_layers.clone(),
+                (!self.scheme.is_xyz()).then_some(self.scheme),
+            ),
+        };
+
         TileJson {
-            tilejson: "3.0.0".to_string(),
+            tilejson: version.as_str().to_string(),
             id: self.id.clone(),
             tiles: vec![tile_url],
             name: self.name.clone(),
@@ -167,9 +473,54 @@ impl TileMetadata {
             maxzoom: self.maxzoom,
             bounds: self.bounds,
             center: self.center,
-            vector_layers: self.vector_layers.clone(),
+            vector_layers,
+            scheme,
+            tile_count: None,
+            size_bytes: None,
+            preview: None,
+            encoding: self
+                .encoding
+                .map(|encoding| encoding.tilejson_name().to_string()),
+            extensions: None,
+        }
+    }
+
+    /// Convert the row of a tile request from the request's scheme to this
+    /// source's scheme, the rows `get_tile` takes
+    ///
+    /// Requests without a scheme of their own are addressed in the source's.
+    /// A row is flipped only when the two schemes differ; `get_tile` then
+    /// converts it to XYZ once. Returns `None` if the row is out of range for
+    /// the zoom level.
+    pub fn request_row(&self, z: u8, y: u32, requested: Option<TileScheme>) -> Option<u32> {
+        match requested {
+            Some(requested) if requested != self.scheme => flip_y(z, y),
+            _ => flip_y(z, y).map(|_| y),
         }
     }
+
+    /// XYZ row of tile `z/x/y`, addressed in this source's scheme
+    pub fn xyz_row(&self, z: u8, x: u32, y: u32) -> crate::error::Result<u32> {
+        self.scheme
+            .xyz_row(z, y)
+            .ok_or(crate::error::TileServerError::InvalidCoordinates { z, x, y })
+    }
+}
+
+/// Tile columns and XYZ rows at one zoom level, bounds inclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileRange {
+    pub z: u8,
+    pub min_x: u32,
+    pub max_x: u32,
+    pub min_y: u32,
+    pub max_y: u32,
+}
+
+impl TileRange {
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        (self.min_x..=self.max_x).contains(&x) && (self.min_y..=self.max_y).contains(&y)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -179,19 +530,720 @@ pub struct TileData {
     pub compression: TileCompression,
 }
 
+/// Size and encoding of a stored tile, enough to answer `HEAD` requests
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileInfo {
+    /// Size of the tile as stored, in bytes
+    pub size: u64,
+    pub format: TileFormat,
+    pub compression: TileCompression,
+}
+
+impl TileData {
+    /// Size and encoding of the tile
+    pub fn info(&self) -> TileInfo {
+        TileInfo {
+            size: self.data.len() as u64,
+            format: self.format,
+            compression: self.compression,
+        }
+    }
+
+    /// Decompress the tile data
+    pub fn decompress(&self) -> crate::error::Result<Vec<u8>> {
+        use crate::error::TileServerError;
+        use flate2::read::GzDecoder;
+        use std::io::Read;
+
+        match self.compression {
+            TileCompression::Gzip => {
+                let mut decoder = GzDecoder::new(&self.data[..]);
+                let mut decompressed = Vec::new();
+                decoder.read_to_end(&mut decompressed).map_err(|e| {
+                    TileServerError::RenderError(format!("Failed to decompress tile: {}", e))
+                })?;
+                Ok(decompressed)
+            }
+            TileCompression::None => Ok(self.data.to_vec()),
+            _ => Err(TileServerError::RenderError(format!(
+                "Unsupported compression: {:?}",
+                self.compression
+            ))),
+        }
+    }
+
+    /// Decompress and decode a vector tile (PBF)
+    pub fn decode_mvt(&self) -> crate::error::Result<geozero::mvt::Tile> {
+        use crate::error::TileServerError;
+        use geozero::mvt::Message;
+
+        let raw_data = self.decompress()?;
+
+        geozero::mvt::Tile::decode(raw_data.as_slice())
+            .map_err(|e| TileServerError::RenderError(format!("Failed to decode MVT tile: {}", e)))
+    }
+
+    /// Return the tile in an encoding the client accepts
+    ///
+    /// Gzip tiles are decompressed on the fly for clients that do not accept
+    /// gzip. Other codecs are passed through unchanged.
+    pub fn negotiate_encoding(self, accept_encoding: Option<&str>) -> crate::error::Result<Self> {
+        if self.compression != TileCompression::Gzip
+            || self.compression.is_accepted_by(accept_encoding)
+        {
+            return Ok(self);
+        }
+
+        Ok(Self {
+            data: Bytes::from(self.decompress()?),
+            format: self.format,
+            compression: TileCompression::None,
+        })
+    }
+}
+
+/// Compression recognized from a tile's leading bytes
+///
+/// Brotli streams have no magic bytes, so they are never recognized.
+pub fn sniff_compression(data: &[u8]) -> Option<TileCompression> {
+    if data.starts_with(&[0x1f, 0x8b]) {
+        Some(TileCompression::Gzip)
+    } else if data.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        Some(TileCompression::Zstd)
+    } else {
+        None
+    }
+}
+
+/// Format recognized from the leading bytes of an uncompressed tile
+///
+/// Vector tiles have no magic bytes; they are recognized by their first
+/// field, a length-delimited `layers` entry (tag 3) that fits in the data.
+pub fn sniff_format(data: &[u8]) -> Option<TileFormat> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some(TileFormat::Png);
+    }
+    if data.starts_with(&[0xff, 0xd8, 0xff]) {
+        return Some(TileFormat::Jpeg);
+    }
+    if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        return Some(TileFormat::Webp);
+    }
+    if data.len() >= 12 && &data[4..8] == b"ftyp" && matches!(&data[8..12], b"avif" | b"avis") {
+        return Some(TileFormat::Avif);
+    }
+
+    // Varint length of the first layer
+    let (&tag, rest) = data.split_first()?;
+    let mut len: u64 = 0;
+    for (i, byte) in rest.iter().take(10).enumerate() {
+        len |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            let remaining = (rest.len() - i - 1) as u64;
+            return (tag == 0x1a && len <= remaining).then_some(TileFormat::Pbf);
+        }
+    }
+    None
+}
+
+/// Tiles per source whose format is sniffed
+const FORMAT_CHECK_TILES: usize = 8;
+
+/// Verifies the format and compression a source declares against its tiles
+///
+/// Compression is taken from the magic bytes of every tile. The format is
+/// sniffed on the first few tiles; if it differs from the declared one, a
+/// warning is logged and the sniffed format is served from then on.
+#[derive(Debug)]
+pub struct FormatCheck {
+    source_id: String,
+    declared_format: TileFormat,
+    declared_compression: TileCompression,
+    remaining: std::sync::atomic::AtomicUsize,
+    sniffed_format: std::sync::OnceLock<TileFormat>,
+    compression_warned: std::sync::atomic::AtomicBool,
+}
+
+impl FormatCheck {
+    pub fn new(source_id: &str, format: TileFormat, compression: TileCompression) -> Self {
+        Self {
+            source_id: source_id.to_string(),
+            declared_format: format,
+            declared_compression: compression,
+            remaining: FORMAT_CHECK_TILES.into(),
+            sniffed_format: std::sync::OnceLock::new(),
+            compression_warned: false.into(),
+        }
+    }
+
+    /// Correct the format and compression of a tile read from the source
+    pub fn check(&self, mut tile: TileData) -> TileData {
+        use std::sync::atomic::Ordering;
+
+        let compression = match sniff_compression(&tile.data) {
+            Some(compression) => compression,
+            None if self.declared_compression == TileCompression::Brotli => TileCompression::Brotli,
+            None => TileCompression::None,
+        };
+        if compression != tile.compression && !self.compression_warned.swap(true, Ordering::Relaxed)
+        {
+            tracing::warn!(
+                "Source '{}' declares {:?} compression, but its tiles are {:?}; serving them as {:?}",
+                self.source_id,
+                tile.compression,
+                compression,
+                compression
+            );
+        }
+        tile.compression = compression;
+
+        let sniff = self
+            .remaining
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+            .is_ok();
+        if sniff {
+            let format = match compression {
+                TileCompression::None => sniff_format(&tile.data),
+                TileCompression::Gzip => tile.decompress().ok().and_then(|d| sniff_format(&d)),
+                _ => None,
+            };
+            if let Some(format) = format.filter(|f| *f != self.declared_format) {
+                if self.sniffed_format.set(format).is_ok() {
+                    tracing::warn!(
+                        "Source '{}' declares format '{}', but its tiles are '{}'; serving them as '{}'",
+                        self.source_id,
+                        self.declared_format.name(),
+                        format.name(),
+                        format.name()
+                    );
+                }
+            }
+        }
+        tile.format = self.format();
+        tile
+    }
+
+    /// Format the source's tiles are served as
+    pub fn format(&self) -> TileFormat {
+        self.sniffed_format
+            .get()
+            .copied()
+            .unwrap_or(self.declared_format)
+    }
+}
+
+/// Convert a decoded MVT layer to GeoJSON features, tagging each with a `_layer` property
+pub fn mvt_layer_features(layer: &mut geozero::mvt::tile::Layer) -> Vec<serde_json::Value> {
+    use geozero::ProcessToJson;
+
+    // Each layer implements GeozeroDatasource which can convert to JSON
+    let Ok(layer_json) = layer.to_json() else {
+        return Vec::new();
+    };
+    // Parse the layer GeoJSON (it's a FeatureCollection)
+    let Ok(mut fc) = serde_json::from_str::<serde_json::Value>(&layer_json) else {
+        return Vec::new();
+    };
+    let Some(features) = fc.get_mut("features").and_then(|f| f.as_array_mut()) else {
+        return Vec::new();
+    };
+
+    // Add layer name to each feature's properties
+    for feature in features.iter_mut() {
+        if let Some(props_obj) = feature
+            .get_mut("properties")
+            .and_then(|props| props.as_object_mut())
+        {
+            props_obj.insert(
+                "_layer".to_string(),
+                serde_json::Value::String(layer.name.clone()),
+            );
+        }
+    }
+
+    std::mem::take(features)
+}
+
 /// Trait for tile sources
 #[async_trait]
 pub trait TileSource: Send + Sync {
-    /// Get a tile at the specified coordinates
-    async fn get_tile(&self, z: u8, x: u32, y: u32) -> crate::error::Result<Option<TileData>>;
+    /// Get a tile at the specified coordinates, with the row addressed in
+    /// the source's scheme
+    ///
+    /// Rows of TMS sources are converted to XYZ here, once for every
+    /// backend.
+    async fn get_tile(&self, z: u8, x: u32, y: u32) -> crate::error::Result<Option<TileData>> {
+        let y = self.metadata().xyz_row(z, x, y)?;
+        self.get_xyz_tile(z, x, y).await
+    }
+
+    /// Get a tile at the specified coordinates, with an XYZ row
+    async fn get_xyz_tile(&self, z: u8, x: u32, y: u32) -> crate::error::Result<Option<TileData>>;
 
     /// Get metadata for this source
     fn metadata(&self) -> &TileMetadata;
 
+    /// Backend name reported in the `X-Tile-Source` debug header
+    fn backend(&self) -> &'static str;
+
     /// Get the tile format
     fn format(&self) -> TileFormat {
         self.metadata().format
     }
 
+    /// Run a lightweight probe against the backing store for readiness checks
+    async fn health_check(&self) -> crate::error::Result<()> {
+        Ok(())
+    }
+
+    /// Number of stored tiles, for backends that record it
+    async fn tile_count(&self) -> crate::error::Result<Option<u64>> {
+        Ok(None)
+    }
+
+    /// Backend-specific integrity checks for `POST /data/{source}/validate`
+    ///
+    /// Returns the problems found; by default there are none to look for.
+    /// Validations that run out of time drop the future, which should stop
+    /// any work it started and release what it holds.
+    async fn check_integrity(&self) -> crate::error::Result<Vec<crate::integrity::IntegrityIssue>> {
+        Ok(Vec::new())
+    }
+
+    /// Stored tiles within `range` as `(x, y)` with XYZ rows, for backends
+    /// that can list them without probing every coordinate
+    ///
+    /// Returns at most `limit` tiles, sorted by column then row.
+    async fn list_tiles(
+        &self,
+        _range: TileRange,
+        _limit: usize,
+    ) -> crate::error::Result<Option<Vec<(u32, u32)>>> {
+        Ok(None)
+    }
+
+    /// Size and encoding of the tile `get_tile_with_overzoom` would return
+    ///
+    /// Backends that can look the size up without reading the tile override
+    /// this; by default the tile is fetched and measured.
+    async fn tile_info(&self, z: u8, x: u32, y: u32) -> crate::error::Result<Option<TileInfo>> {
+        Ok(self
+            .get_tile_with_overzoom(z, x, y)
+            .await?
+            .map(|tile| tile.info()))
+    }
+
+    /// When the backing file was last modified, for `Last-Modified` and
+    /// `ETag` headers
+    fn last_modified(&self) -> Option<std::time::SystemTime> {
+        None
+    }
+
+    /// Get a tile, overzooming the `maxzoom` ancestor when `z > maxzoom`
+    ///
+    /// Falls back to `get_tile` unless overzoom is enabled for the source.
+    /// Rows are addressed in the source's scheme, like `get_tile`.
+    async fn get_tile_with_overzoom(
+        &self,
+        z: u8,
+        x: u32,
+        y: u32,
+    ) -> crate::error::Result<Option<TileData>> {
+        self.get_tile_with_buffer(z, x, y, None).await
+    }
+
+    /// Like `get_tile_with_overzoom`, keeping features within `buffer`
+    /// pixels outside the tile in vector tiles built by the server
+    ///
+    /// Stored tiles already have their buffer baked in, so by default only
+    /// overzoomed tiles use it. `None` keeps the source's own buffer.
+    async fn get_tile_with_buffer(
+        &self,
+        z: u8,
+        x: u32,
+        y: u32,
+        buffer: Option<u32>,
+    ) -> crate::error::Result<Option<TileData>> {
+        let metadata = self.metadata();
+        if !metadata.overzoom || z <= metadata.maxzoom {
+            return self.get_tile(z, x, y).await;
+        }
+        if z > 30 || x >= (1u32 << z) || y >= (1u32 << z) {
+            return Ok(None);
+        }
+
+        let y = metadata.xyz_row(z, x, y)?;
+        let dz = z - metadata.maxzoom;
+        let Some(parent) = self
+            .get_xyz_tile(metadata.maxzoom, x >> dz, y >> dz)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let mask = (1u32 << dz) - 1;
+        overzoom::overzoom_tile(&parent, dz, x & mask, y & mask, buffer)
+    }
+
+    /// The timeout and circuit breaker wrapping this source, which
+    /// backend-specific reads through `as_any` have to run under
+    fn resilient(&self) -> Option<&resilient::ResilientSource> {
+        None
+    }
+
     fn as_any(&self) -> &dyn std::any::Any;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata_with_scheme(scheme: TileScheme) -> TileMetadata {
+        TileMetadata {
+            id: "test".to_string(),
+            name: "Test".to_string(),
+            description: None,
+            attribution: None,
+            format: TileFormat::Pbf,
+            minzoom: 0,
+            maxzoom: 14,
+            bounds: None,
+            center: None,
+            vector_layers: None,
+            scheme,
+            overzoom: false,
+            encoding: None,
+        }
+    }
+
+    #[test]
+    fn test_sniff_compression() {
+        assert_eq!(
+            sniff_compression(&[0x1f, 0x8b, 0x08, 0x00]),
+            Some(TileCompression::Gzip)
+        );
+        assert_eq!(
+            sniff_compression(&[0x28, 0xb5, 0x2f, 0xfd, 0x00]),
+            Some(TileCompression::Zstd)
+        );
+        assert_eq!(sniff_compression(&[0x1a, 0x02, 0x78, 0x02]), None);
+        assert_eq!(sniff_compression(&[0x1f]), None);
+        assert_eq!(sniff_compression(&[]), None);
+    }
+
+    #[test]
+    fn test_sniff_format() {
+        assert_eq!(
+            sniff_format(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"),
+            Some(TileFormat::Png)
+        );
+        assert_eq!(
+            sniff_format(&[0xff, 0xd8, 0xff, 0xe0, 0x00, 0x10]),
+            Some(TileFormat::Jpeg)
+        );
+        assert_eq!(
+            sniff_format(b"RIFF\x24\0\0\0WEBPVP8 "),
+            Some(TileFormat::Webp)
+        );
+        assert_eq!(
+            sniff_format(b"\0\0\0\x1cftypavif\0\0\0\0"),
+            Some(TileFormat::Avif)
+        );
+        assert_eq!(
+            sniff_format(b"\0\0\0\x1cftypavis\0\0\0\0"),
+            Some(TileFormat::Avif)
+        );
+        // RIFF containers other than WebP, and other ISO media brands
+        assert_eq!(sniff_format(b"RIFF\x24\0\0\0WAVEfmt "), None);
+        assert_eq!(sniff_format(b"\0\0\0\x1cftypmp42\0\0\0\0"), None);
+
+        // A layer of 2 bytes, then one whose length runs past the data
+        assert_eq!(
+            sniff_format(&[0x1a, 0x02, 0x78, 0x02]),
+            Some(TileFormat::Pbf)
+        );
+        assert_eq!(sniff_format(&[0x1a, 0x80, 0x01, 0x78, 0x02]), None);
+        assert_eq!(sniff_format(&[0x0a, 0x02, 0x78, 0x02]), None);
+        assert_eq!(sniff_format(&[0x1a]), None);
+        assert_eq!(sniff_format(&[]), None);
+    }
+
+    fn gzip(data: &[u8]) -> Bytes {
+        use std::io::Write;
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap().into()
+    }
+
+    #[test]
+    fn test_format_check() {
+        let layer = [0x1a, 0x02, 0x78, 0x02];
+
+        // Gzip vector tiles declared as uncompressed
+        let check = FormatCheck::new("roads", TileFormat::Pbf, TileCompression::None);
+        let tile = check.check(TileData {
+            data: gzip(&layer),
+            format: TileFormat::Pbf,
+            compression: TileCompression::None,
+        });
+        assert_eq!(tile.compression, TileCompression::Gzip);
+        assert_eq!(tile.format, TileFormat::Pbf);
+        assert_eq!(check.format(), TileFormat::Pbf);
+
+        // WebP tiles declared as PNG, served as WebP from then on
+        let check = FormatCheck::new("imagery", TileFormat::Png, TileCompression::None);
+        let tile = check.check(TileData {
+            data: Bytes::from_static(b"RIFF\x24\0\0\0WEBPVP8 "),
+            format: TileFormat::Png,
+            compression: TileCompression::None,
+        });
+        assert_eq!(tile.format, TileFormat::Webp);
+        assert_eq!(check.format(), TileFormat::Webp);
+
+        // Brotli can't be sniffed, so the declared compression stands
+        let check = FormatCheck::new("brotli", TileFormat::Pbf, TileCompression::Brotli);
+        let tile = check.check(TileData {
+            data: Bytes::from_static(&[0x8b, 0x01, 0x80]),
+            format: TileFormat::Pbf,
+            compression: TileCompression::Brotli,
+        });
+        assert_eq!(tile.compression, TileCompression::Brotli);
+        assert_eq!(tile.format, TileFormat::Pbf);
+    }
+
+    #[test]
+    fn test_format_check_stops_sniffing() {
+        let check = FormatCheck::new("imagery", TileFormat::Png, TileCompression::None);
+        let tile = |data: &'static [u8]| TileData {
+            data: Bytes::from_static(data),
+            format: TileFormat::Png,
+            compression: TileCompression::None,
+        };
+
+        for _ in 0..FORMAT_CHECK_TILES {
+            check.check(tile(b"\x89PNG\r\n\x1a\n"));
+        }
+        // Only the first tiles are sniffed
+        let served = check.check(tile(&[0xff, 0xd8, 0xff, 0xe0]));
+        assert_eq!(served.format, TileFormat::Png);
+    }
+
+    #[test]
+    fn test_tile_buffer() {
+        assert_eq!(parse_tile_buffer("0").unwrap(), 0);
+        assert_eq!(parse_tile_buffer("128").unwrap(), 128);
+        assert_eq!(parse_tile_buffer("512").unwrap(), 512);
+        assert!(parse_tile_buffer("513").is_err());
+        assert!(parse_tile_buffer("-1").is_err());
+        assert!(parse_tile_buffer("wide").is_err());
+
+        assert_eq!(buffer_units(128, 4096), 1024);
+        assert_eq!(buffer_units(64, 512), 64);
+        assert_eq!(buffer_units(512, 4096), 4096);
+    }
+
+    #[test]
+    fn test_flip_y() {
+        assert_eq!(flip_y(0, 0), Some(0));
+        assert_eq!(flip_y(1, 0), Some(1));
+        assert_eq!(flip_y(2, 1), Some(2));
+        assert_eq!(flip_y(2, 3), Some(0));
+        assert_eq!(flip_y(10, 0), Some(1023));
+        assert_eq!(flip_y(14, 5461), Some(10922));
+        assert_eq!(flip_y(31, 0), Some(u32::MAX >> 1));
+        // Flipping twice returns the original row
+        assert_eq!(flip_y(14, 5461).and_then(|y| flip_y(14, y)), Some(5461));
+        // Rows out of range have no flipped row
+        assert_eq!(flip_y(2, 4), None);
+        assert_eq!(flip_y(0, 1), None);
+        assert_eq!(flip_y(32, 0), None);
+    }
+
+    #[test]
+    fn test_validate_tile_coords() {
+        // z = 0 has a single tile
+        assert!(validate_tile_coords(0, 0, 0).is_ok());
+        assert!(validate_tile_coords(0, 1, 0).is_err());
+        assert!(validate_tile_coords(0, 0, 1).is_err());
+
+        // x and y up to 2^z - 1
+        assert!(validate_tile_coords(3, 7, 7).is_ok());
+        assert_eq!(out_of_range_coordinate(3, 8, 0), Some(("x", 8, 7)));
+        assert_eq!(out_of_range_coordinate(3, 0, 8), Some(("y", 8, 7)));
+        assert_eq!(out_of_range_coordinate(3, 999, 999), Some(("x", 999, 7)));
+
+        // Up to zoom 30, where the shift still fits
+        assert!(validate_tile_coords(30, (1 << 30) - 1, 0).is_ok());
+        assert_eq!(
+            out_of_range_coordinate(30, 1 << 30, 0),
+            Some(("x", 1 << 30, (1 << 30) - 1))
+        );
+        assert_eq!(out_of_range_coordinate(31, 0, 0), Some(("z", 31, 30)));
+        assert!(matches!(
+            validate_tile_coords(45, 0, 0),
+            Err(crate::error::TileServerError::InvalidCoordinates { z: 45, .. })
+        ));
+    }
+
+    #[test]
+    fn test_scheme_xyz_row() {
+        assert_eq!(TileScheme::Xyz.xyz_row(3, 2), Some(2));
+        assert_eq!(TileScheme::Tms.xyz_row(3, 2), Some(5));
+        assert_eq!(TileScheme::Xyz.xyz_row(3, 8), None);
+        assert_eq!(TileScheme::Tms.xyz_row(3, 8), None);
+    }
+
+    #[test]
+    fn test_scheme_parsing() {
+        assert_eq!("tms".parse::<TileScheme>(), Ok(TileScheme::Tms));
+        assert_eq!("XYZ".parse::<TileScheme>(), Ok(TileScheme::Xyz));
+        assert!("wmts".parse::<TileScheme>().is_err());
+        assert_eq!(serde_json::to_string(&TileScheme::Tms).unwrap(), "\"tms\"");
+    }
+
+    #[test]
+    fn test_request_row_flips_once() {
+        let xyz = metadata_with_scheme(TileScheme::Xyz);
+        let tms = metadata_with_scheme(TileScheme::Tms);
+
+        // Requests in the source's scheme keep their row
+        assert_eq!(xyz.request_row(2, 1, None), Some(1));
+        assert_eq!(xyz.request_row(2, 1, Some(TileScheme::Xyz)), Some(1));
+        assert_eq!(tms.request_row(2, 1, None), Some(1));
+        assert_eq!(tms.request_row(2, 1, Some(TileScheme::Tms)), Some(1));
+        // ?scheme=tms against an XYZ source, and ?scheme=xyz against a TMS
+        // source, are flipped to the source's scheme
+        assert_eq!(xyz.request_row(2, 1, Some(TileScheme::Tms)), Some(2));
+        assert_eq!(tms.request_row(2, 1, Some(TileScheme::Xyz)), Some(2));
+        // get_tile then converts to XYZ, so both address XYZ row 1
+        assert_eq!(tms.xyz_row(2, 0, 2).unwrap(), 1);
+        // Out of range rows are refused in any scheme
+        assert_eq!(xyz.request_row(2, 4, Some(TileScheme::Tms)), None);
+        assert_eq!(tms.request_row(2, 4, None), None);
+        assert!(tms.xyz_row(2, 0, 4).is_err());
+    }
+
+    #[test]
+    fn test_tilejson_scheme_only_when_non_default() {
+        let xyz = metadata_with_scheme(TileScheme::Xyz)
+            .to_tilejson("http://localhost", TileJsonVersion::default());
+        assert!(xyz.scheme.is_none());
+        let json = serde_json::to_value(&xyz).unwrap();
+        assert!(json.get("scheme").is_none());
+
+        let tms = metadata_with_scheme(TileScheme::Tms)
+            .to_tilejson("http://localhost", TileJsonVersion::default());
+        let json = serde_json::to_value(&tms).unwrap();
+        assert_eq!(json["scheme"], "tms");
+    }
+
+    #[test]
+    fn test_tilejson_encoding_of_elevation_sources() {
+        let mut metadata = metadata_with_scheme(TileScheme::Xyz);
+        let json = serde_json::to_value(
+            metadata.to_tilejson("http://localhost", TileJsonVersion::default()),
+        )
+        .unwrap();
+        assert!(json.get("encoding").is_none());
+
+        for (encoding, name) in [
+            (DemEncoding::TerrainRgb, "mapbox"),
+            (DemEncoding::Terrarium, "terrarium"),
+        ] {
+            metadata.encoding = Some(encoding);
+            for version in [TileJsonVersion::V2_2, TileJsonVersion::V3_0] {
+                let json = serde_json::to_value(metadata.to_tilejson("http://localhost", version))
+                    .unwrap();
+                assert_eq!(json["encoding"], name);
+            }
+        }
+    }
+
+    fn gzip_tile(data: &[u8]) -> TileData {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        TileData {
+            data: Bytes::from(encoder.finish().unwrap()),
+            format: TileFormat::Pbf,
+            compression: TileCompression::Gzip,
+        }
+    }
+
+    #[test]
+    fn test_negotiate_tile_format() {
+        use TileRepresentation::{GeoJson, Native};
+        let pbf = TileFormat::Pbf;
+        assert_eq!(pbf.negotiate(None), Some(Native));
+        assert_eq!(pbf.negotiate(Some("")), Some(Native));
+        assert_eq!(pbf.negotiate(Some("garbage")), Some(Native));
+        assert_eq!(pbf.negotiate(Some("application/x-protobuf")), Some(Native));
+        assert_eq!(
+            pbf.negotiate(Some("Application/Vnd.Mapbox-Vector-Tile")),
+            Some(Native)
+        );
+        assert_eq!(pbf.negotiate(Some("application/geo+json")), Some(GeoJson));
+        assert_eq!(pbf.negotiate(Some("application/*")), Some(Native));
+        assert_eq!(pbf.negotiate(Some("*/*")), Some(Native));
+        assert_eq!(pbf.negotiate(Some("image/*")), None);
+        assert_eq!(pbf.negotiate(Some("text/html")), None);
+
+        // q-values pick between representations, q=0 refuses one
+        assert_eq!(
+            pbf.negotiate(Some("application/x-protobuf;q=0.5, application/geo+json")),
+            Some(GeoJson)
+        );
+        assert_eq!(
+            pbf.negotiate(Some("application/geo+json;q=0.9, */*;q=0.1")),
+            Some(GeoJson)
+        );
+        assert_eq!(
+            pbf.negotiate(Some("application/*, application/geo+json;q=0")),
+            Some(Native)
+        );
+        assert_eq!(pbf.negotiate(Some("*/*;q=0")), None);
+        // The most specific range wins over a wildcard
+        assert_eq!(
+            pbf.negotiate(Some("application/*;q=0, application/geo+json")),
+            Some(GeoJson)
+        );
+
+        let png = TileFormat::Png;
+        assert_eq!(png.negotiate(Some("image/png")), Some(Native));
+        assert_eq!(png.negotiate(Some("image/*")), Some(Native));
+        assert_eq!(png.negotiate(Some("image/webp,*/*;q=0.8")), Some(Native));
+        assert_eq!(png.negotiate(Some("image/webp")), None);
+        assert_eq!(png.negotiate(Some("application/geo+json")), None);
+    }
+
+    #[test]
+    fn test_compression_is_accepted_by() {
+        let gzip = TileCompression::Gzip;
+        assert!(gzip.is_accepted_by(None));
+        assert!(gzip.is_accepted_by(Some("gzip, deflate, br")));
+        assert!(gzip.is_accepted_by(Some("GZIP;q=0.5")));
+        assert!(gzip.is_accepted_by(Some("*")));
+        assert!(!gzip.is_accepted_by(Some("identity")));
+        assert!(!gzip.is_accepted_by(Some("br, gzip;q=0")));
+        assert!(!gzip.is_accepted_by(Some("*, gzip;q=0")));
+        assert!(!gzip.is_accepted_by(Some("")));
+        assert!(TileCompression::None.is_accepted_by(Some("identity")));
+    }
+
+    #[test]
+    fn test_negotiate_encoding() {
+        let tile = gzip_tile(b"tile bytes");
+        let compressed_len = tile.data.len();
+
+        let kept = tile.clone().negotiate_encoding(Some("gzip")).unwrap();
+        assert_eq!(kept.compression, TileCompression::Gzip);
+        assert_eq!(kept.data.len(), compressed_len);
+
+        let raw = tile.negotiate_encoding(Some("identity")).unwrap();
+        assert_eq!(raw.compression, TileCompression::None);
+        assert_eq!(&raw.data[..], b"tile bytes");
+    }
+}
@@ -0,0 +1,600 @@
+//! Server-side overzooming
+//!
+//! Builds tiles beyond a source's `maxzoom` from the ancestor tile at
+//! `maxzoom`. Vector tiles are scaled and clipped to the requested sub-tile;
+//! raster tiles are cropped and upscaled.
+
+use bytes::Bytes;
+use geozero::mvt::tile::GeomType;
+use geozero::mvt::Message;
+
+use crate::error::{Result, TileServerError};
+use crate::sources::{buffer_units, TileCompression, TileData, TileFormat};
+
+/// Geometry outside the tile kept for clean edges, as a fraction of the extent
+const CLIP_BUFFER_DIVISOR: i64 = 64;
+
+const CMD_MOVE_TO: u32 = 1;
+const CMD_LINE_TO: u32 = 2;
+const CMD_CLOSE_PATH: u32 = 7;
+
+pub(crate) type Point = [i64; 2];
+
+/// Extract the sub-tile `(dx, dy)` of `parent`, `dz` zoom levels below it
+///
+/// Returns `None` if no vector feature intersects the sub-tile.
+///
+/// Vector geometry within `buffer` pixels (see [`buffer_units`]) outside the
+/// sub-tile is kept, or a small default margin if `None`.
+pub fn overzoom_tile(
+    parent: &TileData,
+    dz: u8,
+    dx: u32,
+    dy: u32,
+    buffer: Option<u32>,
+) -> Result<Option<TileData>> {
+    match parent.format {
+        TileFormat::Pbf => overzoom_vector(parent, dz, dx, dy, buffer),
+        TileFormat::Png | TileFormat::Jpeg | TileFormat::Webp => {
+            overzoom_raster(parent, dz, dx, dy).map(Some)
+        }
+        format => Err(TileServerError::RenderError(format!(
+            "Overzoom is not supported for {:?} tiles",
+            format
+        ))),
+    }
+}
+
+/// Scale and clip every feature of a vector tile to the sub-tile
+fn overzoom_vector(
+    parent: &TileData,
+    dz: u8,
+    dx: u32,
+    dy: u32,
+    buffer: Option<u32>,
+) -> Result<Option<TileData>> {
+    let mut tile = parent.decode_mvt()?;
+    let scale = 1i64 << dz;
+
+    for layer in &mut tile.layers {
+        let extent = layer.extent.unwrap_or(4096) as i64;
+        let buffer = match buffer {
+            Some(buffer) => buffer_units(buffer, extent as u32) as i64,
+            None => extent / CLIP_BUFFER_DIVISOR,
+        };
+        let bounds = [-buffer, -buffer, extent + buffer, extent + buffer];
+        let offset = [dx as i64 * extent, dy as i64 * extent];
+
+        layer.features.retain_mut(|feature| {
+            let geom_type = feature.r#type();
+            let parts: Vec<Vec<Point>> = decode_geometry(&feature.geometry)
+                .into_iter()
+                .map(|part| {
+                    part.into_iter()
+                        .map(|[x, y]| [x * scale - offset[0], y * scale - offset[1]])
+                        .collect()
+                })
+                .collect();
+
+            let clipped = match geom_type {
+                GeomType::Point => clip_points(parts, bounds),
+                GeomType::Linestring => clip_lines(parts, bounds),
+                GeomType::Polygon => clip_polygons(parts, bounds),
+                GeomType::Unknown => Vec::new(),
+            };
+            if clipped.is_empty() {
+                return false;
+            }
+
+            feature.geometry = encode_geometry(geom_type, &clipped);
+            true
+        });
+    }
+    tile.layers.retain(|layer| !layer.features.is_empty());
+
+    if tile.layers.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(TileData {
+        data: Bytes::from(tile.encode_to_vec()),
+        format: TileFormat::Pbf,
+        compression: TileCompression::None,
+    }))
+}
+
+/// Crop the sub-tile from a raster tile and scale it back to full size
+fn overzoom_raster(parent: &TileData, dz: u8, dx: u32, dy: u32) -> Result<TileData> {
+    use image::{imageops::FilterType, ImageFormat};
+
+    let data = parent.decompress()?;
+    let image = image::load_from_memory(&data)
+        .map_err(|e| TileServerError::RenderError(format!("Failed to decode tile: {}", e)))?;
+
+    let (width, height) = (image.width(), image.height());
+    let divisor = 1u64 << dz;
+    let sub_width = (width as u64 / divisor).max(1) as u32;
+    let sub_height = (height as u64 / divisor).max(1) as u32;
+    let left = ((dx as u64 * width as u64) / divisor) as u32;
+    let top = ((dy as u64 * height as u64) / divisor) as u32;
+
+    let sub_tile = image
+        .crop_imm(left, top, sub_width, sub_height)
+        .resize_exact(width, height, FilterType::Triangle);
+
+    let format = match parent.format {
+        TileFormat::Jpeg => ImageFormat::Jpeg,
+        TileFormat::Webp => ImageFormat::WebP,
+        _ => ImageFormat::Png,
+    };
+    let mut buffer = std::io::Cursor::new(Vec::new());
+    // JPEG has no alpha channel
+    let encoded = if format == ImageFormat::Jpeg {
+        image::DynamicImage::ImageRgb8(sub_tile.to_rgb8()).write_to(&mut buffer, format)
+    } else {
+        sub_tile.write_to(&mut buffer, format)
+    };
+    encoded.map_err(|e| TileServerError::RenderError(format!("Failed to encode tile: {}", e)))?;
+
+    Ok(TileData {
+        data: Bytes::from(buffer.into_inner()),
+        format: parent.format,
+        compression: TileCompression::None,
+    })
+}
+
+/// Decode MVT geometry commands into parts (points, lines or rings)
+///
+/// Polygon rings are returned without the closing point.
+pub(crate) fn decode_geometry(geometry: &[u32]) -> Vec<Vec<Point>> {
+    let zigzag = |v: u32| ((v >> 1) as i64) ^ -((v & 1) as i64);
+
+    let mut parts: Vec<Vec<Point>> = Vec::new();
+    let (mut cx, mut cy) = (0i64, 0i64);
+    let mut idx = 0;
+
+    while idx < geometry.len() {
+        let command = geometry[idx] & 0x7;
+        let count = geometry[idx] >> 3;
+        idx += 1;
+
+        if command == CMD_CLOSE_PATH {
+            continue;
+        }
+
+        for i in 0..count {
+            let (Some(&dx), Some(&dy)) = (geometry.get(idx), geometry.get(idx + 1)) else {
+                return parts;
+            };
+            idx += 2;
+            cx += zigzag(dx);
+            cy += zigzag(dy);
+
+            // Each MoveTo command starts a new part (multi-points stay together)
+            if command == CMD_MOVE_TO && i == 0 {
+                parts.push(Vec::new());
+            }
+            if let Some(part) = parts.last_mut() {
+                part.push([cx, cy]);
+            }
+        }
+    }
+
+    parts
+}
+
+/// Encode parts back into MVT geometry commands
+pub(crate) fn encode_geometry(geom_type: GeomType, parts: &[Vec<Point>]) -> Vec<u32> {
+    let command = |id: u32, count: usize| id | ((count as u32) << 3);
+    let zigzag = |v: i64| ((v << 1) ^ (v >> 63)) as u32;
+
+    let mut geometry = Vec::new();
+    let mut cursor = [0i64, 0i64];
+    let mut push_point = |geometry: &mut Vec<u32>, point: &Point| {
+        geometry.push(zigzag(point[0] - cursor[0]));
+        geometry.push(zigzag(point[1] - cursor[1]));
+        cursor = *point;
+    };
+
+    if geom_type == GeomType::Point {
+        let points: Vec<&Point> = parts.iter().flatten().collect();
+        geometry.push(command(CMD_MOVE_TO, points.len()));
+        for point in points {
+            push_point(&mut geometry, point);
+        }
+        return geometry;
+    }
+
+    for part in parts {
+        geometry.push(command(CMD_MOVE_TO, 1));
+        push_point(&mut geometry, &part[0]);
+        geometry.push(command(CMD_LINE_TO, part.len() - 1));
+        for point in &part[1..] {
+            push_point(&mut geometry, point);
+        }
+        if geom_type == GeomType::Polygon {
+            geometry.push(command(CMD_CLOSE_PATH, 1));
+        }
+    }
+
+    geometry
+}
+
+fn inside(point: &Point, bounds: [i64; 4]) -> bool {
+    point[0] >= bounds[0] && point[0] <= bounds[2] && point[1] >= bounds[1] && point[1] <= bounds[3]
+}
+
+/// Keep points inside the bounds
+pub(crate) fn clip_points(parts: Vec<Vec<Point>>, bounds: [i64; 4]) -> Vec<Vec<Point>> {
+    let points: Vec<Point> = parts
+        .into_iter()
+        .flatten()
+        .filter(|p| inside(p, bounds))
+        .collect();
+
+    if points.is_empty() {
+        Vec::new()
+    } else {
+        vec![points]
+    }
+}
+
+/// Clip a segment to the bounds (Liang-Barsky)
+fn clip_segment(a: Point, b: Point, bounds: [i64; 4]) -> Option<(Point, Point)> {
+    let (x0, y0) = (a[0] as f64, a[1] as f64);
+    let (ddx, ddy) = ((b[0] - a[0]) as f64, (b[1] - a[1]) as f64);
+    let (mut t0, mut t1) = (0.0f64, 1.0f64);
+
+    let checks = [
+        (-ddx, x0 - bounds[0] as f64),
+        (ddx, bounds[2] as f64 - x0),
+        (-ddy, y0 - bounds[1] as f64),
+        (ddy, bounds[3] as f64 - y0),
+    ];
+    for (p, q) in checks {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+        } else {
+            let t = q / p;
+            if p < 0.0 {
+                t0 = t0.max(t);
+            } else {
+                t1 = t1.min(t);
+            }
+            if t0 > t1 {
+                return None;
+            }
+        }
+    }
+
+    let at = |t: f64| [(x0 + t * ddx).round() as i64, (y0 + t * ddy).round() as i64];
+    Some((
+        if t0 > 0.0 { at(t0) } else { a },
+        if t1 < 1.0 { at(t1) } else { b },
+    ))
+}
+
+/// Clip lines to the bounds, splitting them where they leave and re-enter
+pub(crate) fn clip_lines(parts: Vec<Vec<Point>>, bounds: [i64; 4]) -> Vec<Vec<Point>> {
+    let mut lines: Vec<Vec<Point>> = Vec::new();
+
+    for part in parts {
+        let mut current: Vec<Point> = Vec::new();
+        for segment in part.windows(2) {
+            let Some((start, end)) = clip_segment(segment[0], segment[1], bounds) else {
+                continue;
+            };
+
+            if current.last() != Some(&start) {
+                if current.len() >= 2 {
+                    lines.push(std::mem::take(&mut current));
+                }
+                current.clear();
+                current.push(start);
+            }
+            if end != start {
+                current.push(end);
+            }
+
+            // The line leaves the bounds here
+            if end != segment[1] {
+                if current.len() >= 2 {
+                    lines.push(std::mem::take(&mut current));
+                }
+                current.clear();
+            }
+        }
+        if current.len() >= 2 {
+            lines.push(current);
+        }
+    }
+
+    lines
+}
+
+/// Twice the signed ring area; positive for exterior rings in tile coordinates
+///
+/// Summed in 128 bits and saturated, so rings reaching far beyond the tile
+/// keep their sign.
+pub(crate) fn ring_area(ring: &[Point]) -> i64 {
+    let n = ring.len();
+    let area: i128 = (0..n)
+        .map(|i| {
+            let (a, b) = (ring[i], ring[(i + 1) % n]);
+            a[0] as i128 * b[1] as i128 - b[0] as i128 * a[1] as i128
+        })
+        .sum();
+    area.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+}
+
+/// Clip a ring to the bounds (Sutherland-Hodgman)
+fn clip_ring(ring: &[Point], bounds: [i64; 4]) -> Vec<Point> {
+    // (axis, limit, keep the side above the limit)
+    let edges = [
+        (0, bounds[0], true),
+        (0, bounds[2], false),
+        (1, bounds[1], true),
+        (1, bounds[3], false),
+    ];
+
+    let mut output = ring.to_vec();
+    for (axis, limit, keep_above) in edges {
+        let input = std::mem::take(&mut output);
+        let is_in = |p: &Point| {
+            if keep_above {
+                p[axis] >= limit
+            } else {
+                p[axis] <= limit
+            }
+        };
+        let intersect = |a: &Point, b: &Point| {
+            let t = (limit - a[axis]) as f64 / (b[axis] - a[axis]) as f64;
+            let mut p = [0i64; 2];
+            p[axis] = limit;
+            p[1 - axis] =
+                (a[1 - axis] as f64 + t * (b[1 - axis] - a[1 - axis]) as f64).round() as i64;
+            p
+        };
+
+        for i in 0..input.len() {
+            let current = &input[i];
+            let previous = &input[(i + input.len() - 1) % input.len()];
+            match (is_in(previous), is_in(current)) {
+                (true, true) => output.push(*current),
+                (true, false) => output.push(intersect(previous, current)),
+                (false, true) => {
+                    output.push(intersect(previous, current));
+                    output.push(*current);
+                }
+                (false, false) => {}
+            }
+        }
+    }
+
+    output.dedup();
+    if output.len() > 1 && output.first() == output.last() {
+        output.pop();
+    }
+    output
+}
+
+/// Clip polygon rings, dropping holes whose exterior ring was clipped away
+pub(crate) fn clip_polygons(parts: Vec<Vec<Point>>, bounds: [i64; 4]) -> Vec<Vec<Point>> {
+    let mut rings = Vec::new();
+    let mut exterior_kept = false;
+
+    for ring in parts {
+        let is_exterior = ring_area(&ring) > 0;
+        if !is_exterior && !exterior_kept {
+            continue;
+        }
+
+        let clipped = clip_ring(&ring, bounds);
+        let kept = clipped.len() >= 3 && ring_area(&clipped) != 0;
+        if is_exterior {
+            exterior_kept = kept;
+        }
+        if kept {
+            rings.push(clipped);
+        }
+    }
+
+    rings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geozero::mvt::tile::{Feature, Layer};
+    use geozero::mvt::Tile;
+
+    const BOUNDS: [i64; 4] = [0, 0, 4096, 4096];
+
+    fn vector_tile(features: Vec<(GeomType, Vec<Vec<Point>>)>) -> TileData {
+        let tile = Tile {
+            layers: vec![Layer {
+                version: 2,
+                name: "test".to_string(),
+                features: features
+                    .into_iter()
+                    .map(|(geom_type, parts)| Feature {
+                        r#type: Some(geom_type as i32),
+                        geometry: encode_geometry(geom_type, &parts),
+                        ..Default::default()
+                    })
+                    .collect(),
+                extent: Some(4096),
+                ..Default::default()
+            }],
+        };
+
+        TileData {
+            data: Bytes::from(tile.encode_to_vec()),
+            format: TileFormat::Pbf,
+            compression: TileCompression::None,
+        }
+    }
+
+    fn features(tile: &TileData) -> Vec<(GeomType, Vec<Vec<Point>>)> {
+        tile.decode_mvt()
+            .unwrap()
+            .layers
+            .iter()
+            .flat_map(|layer| &layer.features)
+            .map(|f| (f.r#type(), decode_geometry(&f.geometry)))
+            .collect()
+    }
+
+    #[test]
+    fn test_geometry_roundtrip() {
+        let parts = vec![
+            vec![[0, 0], [10, 0], [10, 10], [0, 10]],
+            vec![[2, 2], [2, 8], [8, 8], [8, 2]],
+        ];
+        let encoded = encode_geometry(GeomType::Polygon, &parts);
+        assert_eq!(decode_geometry(&encoded), parts);
+
+        let points = vec![vec![[5, 5], [7, 9]]];
+        let encoded = encode_geometry(GeomType::Point, &points);
+        assert_eq!(decode_geometry(&encoded), points);
+    }
+
+    #[test]
+    fn test_clip_lines_splits_on_exit() {
+        let line = vec![vec![
+            [-100, 100],
+            [100, 100],
+            [100, 5000],
+            [200, 5000],
+            [200, 100],
+        ]];
+        let clipped = clip_lines(line, BOUNDS);
+        assert_eq!(
+            clipped,
+            vec![
+                vec![[0, 100], [100, 100], [100, 4096]],
+                vec![[200, 4096], [200, 100]],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_clip_polygon() {
+        let square = vec![[-100, -100], [200, -100], [200, 200], [-100, 200]];
+        assert!(ring_area(&square) > 0);
+        let clipped = clip_polygons(vec![square], BOUNDS);
+        assert_eq!(clipped, vec![vec![[0, 0], [200, 0], [200, 200], [0, 200]]]);
+
+        // Holes of an exterior ring outside the bounds are dropped too
+        let outside = vec![[5000, 5000], [6000, 5000], [6000, 6000], [5000, 6000]];
+        let hole = vec![[5100, 5100], [5100, 5200], [5200, 5200], [5200, 5100]];
+        assert!(clip_polygons(vec![outside, hole], BOUNDS).is_empty());
+    }
+
+    /// Parent tile with a point and a line in each quadrant
+    fn quadrant_tile() -> TileData {
+        vector_tile(vec![
+            (GeomType::Point, vec![vec![[1024, 1024], [3072, 3072]]]),
+            (GeomType::Linestring, vec![vec![[0, 1024], [4096, 1024]]]),
+            (
+                GeomType::Polygon,
+                vec![vec![[2048, 2048], [4096, 2048], [4096, 4096], [2048, 4096]]],
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_overzoom_vector_one_level() {
+        let parent = quadrant_tile();
+
+        // Top-left quadrant: one point, the line, and the polygon corner in the buffer
+        let child = overzoom_tile(&parent, 1, 0, 0, None).unwrap().unwrap();
+        let child_features = features(&child);
+        assert_eq!(
+            child_features,
+            vec![
+                (GeomType::Point, vec![vec![[2048, 2048]]]),
+                (GeomType::Linestring, vec![vec![[0, 2048], [4160, 2048]]]),
+                (
+                    GeomType::Polygon,
+                    vec![vec![[4096, 4160], [4096, 4096], [4160, 4096], [4160, 4160]]]
+                ),
+            ]
+        );
+
+        // Bottom-right quadrant: the other point and the polygon filling it
+        let child = overzoom_tile(&parent, 1, 1, 1, None).unwrap().unwrap();
+        let child_features = features(&child);
+        assert_eq!(
+            child_features,
+            vec![
+                (GeomType::Point, vec![vec![[2048, 2048]]]),
+                (
+                    GeomType::Polygon,
+                    vec![vec![[0, 0], [4096, 0], [4096, 4096], [0, 4096]]]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_overzoom_vector_buffer() {
+        let parent = quadrant_tile();
+
+        // 128px of a 512px tile is a quarter of the extent
+        let child = overzoom_tile(&parent, 1, 0, 0, Some(128)).unwrap().unwrap();
+        let child_features = features(&child);
+        assert_eq!(
+            child_features[1],
+            (GeomType::Linestring, vec![vec![[0, 2048], [5120, 2048]]])
+        );
+        assert_eq!(
+            child_features[2],
+            (
+                GeomType::Polygon,
+                vec![vec![[4096, 5120], [4096, 4096], [5120, 4096], [5120, 5120]]]
+            )
+        );
+
+        // Without a buffer nothing extends past the tile edge
+        let child = overzoom_tile(&parent, 1, 0, 0, Some(0)).unwrap().unwrap();
+        let child_features = features(&child);
+        assert!(child_features
+            .iter()
+            .flat_map(|(_, parts)| parts.iter().flatten())
+            .all(|&[x, y]| (0..=4096).contains(&x) && (0..=4096).contains(&y)));
+    }
+
+    #[test]
+    fn test_overzoom_vector_empty_sub_tile() {
+        let parent = vector_tile(vec![(GeomType::Point, vec![vec![[100, 100]]])]);
+        assert!(overzoom_tile(&parent, 2, 3, 3, None).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_overzoom_raster() {
+        // Red left half, blue right half
+        let mut image = image::RgbaImage::new(256, 256);
+        for (x, _, pixel) in image.enumerate_pixels_mut() {
+            *pixel = if x < 128 {
+                image::Rgba([255, 0, 0, 255])
+            } else {
+                image::Rgba([0, 0, 255, 255])
+            };
+        }
+        let mut png = std::io::Cursor::new(Vec::new());
+        image.write_to(&mut png, image::ImageFormat::Png).unwrap();
+        let parent = TileData {
+            data: Bytes::from(png.into_inner()),
+            format: TileFormat::Png,
+            compression: TileCompression::None,
+        };
+
+        let child = overzoom_tile(&parent, 2, 3, 1, None).unwrap().unwrap();
+        let decoded = image::load_from_memory(&child.data).unwrap().to_rgba8();
+        assert_eq!(decoded.dimensions(), (256, 256));
+        assert_eq!(decoded.get_pixel(128, 128), &image::Rgba([0, 0, 255, 255]));
+    }
+}
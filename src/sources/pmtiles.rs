@@ -1,2 +1,48 @@
+pub mod checksum;
 pub mod http;
 pub mod local;
+pub mod locate;
+pub mod v2;
+
+use std::path::Path;
+
+/// Magic bytes at the start of PMTiles v3 archives
+pub const V3_MAGIC: &[u8; 7] = b"PMTiles";
+
+/// Spec version of a PMTiles archive, from its first 7 bytes
+///
+/// v3 archives start with `PMTiles`; v2 archives with `PM` followed by a
+/// little-endian `u16` version of 2.
+pub fn archive_version(magic: &[u8]) -> Option<u8> {
+    if magic.starts_with(V3_MAGIC) {
+        return Some(3);
+    }
+    match magic {
+        [b'P', b'M', 2, 0, ..] => Some(2),
+        _ => None,
+    }
+}
+
+/// Spec version of a local PMTiles file, `None` if it cannot be read
+pub async fn file_version(path: &Path) -> Option<u8> {
+    use tokio::io::AsyncReadExt;
+
+    let mut magic = [0u8; 7];
+    let mut file = tokio::fs::File::open(path).await.ok()?;
+    file.read_exact(&mut magic).await.ok()?;
+    archive_version(&magic)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archive_version() {
+        assert_eq!(archive_version(b"PMTiles\x03"), Some(3));
+        assert_eq!(archive_version(b"PM\x02\x00\x10\x00\x00"), Some(2));
+        assert_eq!(archive_version(b"PM\x01\x00\x10\x00\x00"), None);
+        assert_eq!(archive_version(b"SQLite format 3"), None);
+        assert_eq!(archive_version(b"PM"), None);
+    }
+}
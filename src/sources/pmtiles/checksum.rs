@@ -0,0 +1,161 @@
+//! Checksum verification of PMTiles tiles
+//!
+//! PMTiles v3 headers and directory entries store no hashes, so the only
+//! checksums in an archive are those of its tile compression: every gzip
+//! member ends with the CRC-32 and length of the data it holds. With
+//! `verify_checksums = true`, gzip tiles are decompressed on every read to
+//! check them against their trailer. Brotli, zstd and uncompressed tiles
+//! carry nothing to check and are served as they are.
+
+use opentelemetry::metrics::Counter;
+use opentelemetry::KeyValue;
+use std::sync::OnceLock;
+
+use crate::error::{Result, TileServerError};
+use crate::sources::{TileCompression, TileData};
+
+static CHECKSUM_FAILURES: OnceLock<Counter<u64>> = OnceLock::new();
+
+fn checksum_failures() -> &'static Counter<u64> {
+    CHECKSUM_FAILURES.get_or_init(|| {
+        opentelemetry::global::meter("tileserver-rs")
+            .u64_counter("tileserver_checksum_failures_total")
+            .with_description("Tiles whose data did not match their checksum")
+            .with_unit("tiles")
+            .build()
+    })
+}
+
+/// Verifies the checksums of a source's tiles as they are read
+#[derive(Debug)]
+pub struct ChecksumCheck {
+    source_id: String,
+}
+
+impl ChecksumCheck {
+    pub fn new(source_id: &str, compression: TileCompression) -> Self {
+        if compression != TileCompression::Gzip {
+            tracing::warn!(
+                "Source '{}' has {:?} tiles, which carry no checksums; verify_checksums only checks gzip tiles",
+                source_id,
+                compression
+            );
+        }
+        Self {
+            source_id: source_id.to_string(),
+        }
+    }
+
+    /// Pass `tile` on if its data matches its checksum
+    ///
+    /// A mismatch is logged and counted, and fails the request rather than
+    /// serve corrupt data.
+    pub fn check(&self, z: u8, x: u32, y: u32, tile: TileData) -> Result<TileData> {
+        match verify(&tile) {
+            Ok(()) => Ok(tile),
+            Err(reason) => {
+                checksum_failures().add(1, &[KeyValue::new("source", self.source_id.clone())]);
+                tracing::error!(
+                    "Checksum failure in tile {}/{}/{} of source {}: {}",
+                    z,
+                    x,
+                    y,
+                    self.source_id,
+                    reason
+                );
+                Err(TileServerError::FileError(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!(
+                        "Tile {}/{}/{} of source '{}' failed its checksum: {}",
+                        z, x, y, self.source_id, reason
+                    ),
+                )))
+            }
+        }
+    }
+}
+
+/// Check `tile` against the checksum of its compression, if it has one
+fn verify(tile: &TileData) -> std::result::Result<(), String> {
+    match tile.compression {
+        TileCompression::Gzip => {
+            // The decoder compares the CRC-32 and length in the trailer with
+            // the data once it reaches the end of the member
+            let mut decoder = flate2::read::GzDecoder::new(&tile.data[..]);
+            std::io::copy(&mut decoder, &mut std::io::sink())
+                .map(drop)
+                .map_err(|e| e.to_string())
+        }
+        TileCompression::None | TileCompression::Brotli | TileCompression::Zstd => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    fn gzip_tile(data: &[u8]) -> TileData {
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        TileData {
+            data: Bytes::from(encoder.finish().unwrap()),
+            format: crate::sources::TileFormat::Pbf,
+            compression: TileCompression::Gzip,
+        }
+    }
+
+    fn corrupt(tile: &TileData, index: usize) -> TileData {
+        let mut data = tile.data.to_vec();
+        data[index] ^= 0x01;
+        TileData {
+            data: Bytes::from(data),
+            ..tile.clone()
+        }
+    }
+
+    #[test]
+    fn test_verify_gzip_checksums() {
+        let tile = gzip_tile(b"layer data layer data layer data");
+        assert_eq!(verify(&tile), Ok(()));
+
+        // The CRC-32 is the first half of the 8 byte trailer
+        let crc = tile.data.len() - 8;
+        assert!(verify(&corrupt(&tile, crc)).is_err());
+        // So is the length, in the second half
+        assert!(verify(&corrupt(&tile, tile.data.len() - 1)).is_err());
+        // And a damaged deflate stream fails before reaching the trailer
+        assert!(verify(&corrupt(&tile, 12)).is_err());
+    }
+
+    #[test]
+    fn test_other_compressions_pass() {
+        for compression in [
+            TileCompression::None,
+            TileCompression::Brotli,
+            TileCompression::Zstd,
+        ] {
+            let tile = TileData {
+                data: Bytes::from_static(b"\x1f\x8b not gzip"),
+                format: crate::sources::TileFormat::Pbf,
+                compression,
+            };
+            assert_eq!(verify(&tile), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_check_fails_corrupt_tiles() {
+        let check = ChecksumCheck::new("corrupt", TileCompression::Gzip);
+        let tile = gzip_tile(b"features");
+        assert!(check.check(1, 0, 1, tile.clone()).is_ok());
+
+        let err = check
+            .check(1, 0, 1, corrupt(&tile, tile.data.len() - 8))
+            .unwrap_err();
+        assert_eq!(err.status(), axum::http::StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(err.to_string().contains("1/0/1"), "{}", err);
+    }
+}
@@ -3,27 +3,91 @@ use pmtiles::{
     AsyncPmTilesReader, Compression as PmCompression, HashMapCache, HttpBackend, TileCoord,
     TileType,
 };
+use serde::Serialize;
+use std::ops::Range;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 use tokio::sync::RwLock;
 
 use crate::config::SourceConfig;
 use crate::error::{Result, TileServerError};
-use crate::sources::{TileCompression, TileData, TileFormat, TileMetadata, TileSource};
+use crate::sources::gcs::{self, BlobSigner, GcsObject, ServiceAccountSigner};
+use crate::sources::pmtiles::checksum::ChecksumCheck;
+use crate::sources::pmtiles::locate::{self, LocatingBackend};
+use crate::sources::{
+    FormatCheck, TileCompression, TileData, TileFormat, TileMetadata, TileSource,
+};
 
 /// Type alias for HTTP PMTiles reader: Backend=HttpBackend, Cache=HashMapCache
-type HttpReader = AsyncPmTilesReader<HttpBackend, HashMapCache>;
+type HttpReader = AsyncPmTilesReader<LocatingBackend<HttpBackend>, HashMapCache>;
 
 /// HTTP-based PMTiles tile source
 pub struct HttpPmTilesSource {
     reader: Arc<RwLock<HttpReader>>,
     metadata: TileMetadata,
     tile_compression: TileCompression,
+    /// Corrects mislabeled tiles when `verify_format = true`
+    format_check: Option<FormatCheck>,
+    /// Fails corrupt tiles when `verify_checksums = true`
+    checksum_check: Option<ChecksumCheck>,
+    /// Byte range of the archive's tile data section
+    data: Range<u64>,
+    signing: Option<UrlSigning>,
+}
+
+/// Signs URLs for the archive's object in GCS
+struct UrlSigning {
+    object: GcsObject,
+    signer: Box<dyn BlobSigner>,
+}
+
+/// Time-limited direct access to a tile in an archive on GCS
+#[derive(Debug, Clone, Serialize)]
+pub struct SignedTileUrl {
+    pub url: String,
+    /// RFC 3339 UTC timestamp
+    pub expires_at: String,
+    /// `Range` header value selecting the tile within the archive
+    pub range: String,
 }
 
 impl HttpPmTilesSource {
-    /// Create a new PMTiles source from an HTTP URL
+    /// Create a new PMTiles source from an HTTP URL, or a `gs://` URL read
+    /// through the GCS HTTPS endpoint
     pub async fn from_url(config: &SourceConfig, _client: reqwest::Client) -> Result<Self> {
-        let url = &config.path;
+        let object = GcsObject::from_url(&config.path);
+        let url = match &object {
+            Some(object) if config.path.starts_with("gs://") => object.https_url(),
+            _ => config.path.clone(),
+        };
+        let url = &url;
+
+        let signing = if config.options.allow_signed_urls {
+            let object = object.ok_or_else(|| {
+                TileServerError::ConfigError(format!(
+                    "Source '{}' allows signed URLs but is not in Google Cloud Storage",
+                    config.id
+                ))
+            })?;
+            let key = config
+                .options
+                .service_account_key
+                .clone()
+                .or_else(|| std::env::var_os("GOOGLE_APPLICATION_CREDENTIALS").map(PathBuf::from))
+                .ok_or_else(|| {
+                    TileServerError::ConfigError(format!(
+                        "Source '{}' allows signed URLs but has no service_account_key",
+                        config.id
+                    ))
+                })?;
+            Some(UrlSigning {
+                object,
+                signer: Box::new(ServiceAccountSigner::from_file(&key)?),
+            })
+        } else {
+            None
+        };
 
         tracing::info!("Opening HTTP PMTiles source: {}", url);
 
@@ -39,12 +103,16 @@ impl HttpPmTilesSource {
                 TileServerError::MetadataError(format!("Failed to create HTTP client: {}", e))
             })?;
 
-        // Create async reader with cached URL
-        let reader: HttpReader = AsyncPmTilesReader::new_with_cached_url(cache, client, url)
-            .await
-            .map_err(|e| {
-                TileServerError::MetadataError(format!("Failed to read PMTiles header: {}", e))
-            })?;
+        // Create async reader with cached URL, over a backend that can
+        // locate tiles for signed URLs
+        let header_error =
+            |e| TileServerError::MetadataError(format!("Failed to read PMTiles header: {}", e));
+        let backend = HttpBackend::try_from(client, url.as_str()).map_err(header_error)?;
+        let data = locate::data_section(&backend).await.map_err(header_error)?;
+        let reader: HttpReader =
+            AsyncPmTilesReader::try_from_cached_source(LocatingBackend(backend), cache)
+                .await
+                .map_err(header_error)?;
 
         let header = reader.get_header();
 
@@ -95,6 +163,9 @@ impl HttpPmTilesSource {
                 header.center_zoom as f64,
             ]),
             vector_layers,
+            scheme: config.scheme,
+            overzoom: config.overzoom,
+            encoding: None,
         };
 
         tracing::info!(
@@ -105,10 +176,82 @@ impl HttpPmTilesSource {
             format
         );
 
-        Ok(Self {
+        let source = Self {
             reader: Arc::new(RwLock::new(reader)),
             metadata,
             tile_compression,
+            format_check: config
+                .options
+                .verify_format
+                .unwrap_or(false)
+                .then(|| FormatCheck::new(&config.id, format, tile_compression)),
+            checksum_check: config
+                .options
+                .verify_checksums
+                .unwrap_or(false)
+                .then(|| ChecksumCheck::new(&config.id, tile_compression)),
+            data,
+            signing: None,
+        };
+        Ok(match signing {
+            Some(UrlSigning { object, signer }) => source.with_url_signer(object, signer),
+            None => source,
+        })
+    }
+
+    /// Sign URLs for tiles as `object`, with `signer`
+    pub fn with_url_signer(mut self, object: GcsObject, signer: Box<dyn BlobSigner>) -> Self {
+        self.signing = Some(UrlSigning { object, signer });
+        self
+    }
+
+    /// Byte range of a tile within the archive, without reading the tile
+    pub async fn tile_range(&self, z: u8, x: u32, y: u32) -> Result<Option<Range<u64>>> {
+        let max_tile = 1u32 << z;
+        if x >= max_tile || y >= max_tile {
+            return Err(TileServerError::InvalidCoordinates { z, x, y });
+        }
+        if z < self.metadata.minzoom || z > self.metadata.maxzoom {
+            return Ok(None);
+        }
+
+        let y = self.metadata.xyz_row(z, x, y)?;
+        let coord =
+            TileCoord::new(z, x, y).map_err(|_| TileServerError::InvalidCoordinates { z, x, y })?;
+
+        let reader = self.reader.read().await;
+        let (result, range) = locate::locate(self.data.clone(), reader.get_tile(coord)).await;
+        result.map_err(|e| {
+            TileServerError::UpstreamError(format!("Failed to read PMTiles directory: {}", e))
+        })?;
+        Ok(range)
+    }
+
+    /// Signed GCS URL for the archive, valid for `ttl` from `now`, with the
+    /// byte range holding the tile
+    ///
+    /// Returns `NotFound` unless the source allows signed URLs.
+    pub async fn signed_tile_url(
+        &self,
+        z: u8,
+        x: u32,
+        y: u32,
+        ttl: Duration,
+        now: SystemTime,
+    ) -> Result<SignedTileUrl> {
+        let signing = self.signing.as_ref().ok_or_else(|| {
+            TileServerError::NotFound(format!("Signed URLs for source {}", self.metadata.id))
+        })?;
+        let range = self
+            .tile_range(z, x, y)
+            .await?
+            .ok_or(TileServerError::TileNotFound { z, x, y })?;
+        let signed = gcs::sign_url(signing.signer.as_ref(), &signing.object, now, ttl)?;
+
+        Ok(SignedTileUrl {
+            url: signed.url,
+            expires_at: signed.expires_at,
+            range: format!("bytes={}-{}", range.start, range.end - 1),
         })
     }
 }
@@ -126,7 +269,7 @@ fn convert_compression(compression: PmCompression) -> TileCompression {
 
 #[async_trait]
 impl TileSource for HttpPmTilesSource {
-    async fn get_tile(&self, z: u8, x: u32, y: u32) -> Result<Option<TileData>> {
+    async fn get_xyz_tile(&self, z: u8, x: u32, y: u32) -> Result<Option<TileData>> {
         // Validate coordinates
         let max_tile = 1u32 << z;
         if x >= max_tile || y >= max_tile {
@@ -148,11 +291,21 @@ impl TileSource for HttpPmTilesSource {
 
         // Get tile from PMTiles over HTTP
         match reader.get_tile(coord).await {
-            Ok(Some(tile_data)) => Ok(Some(TileData {
-                data: tile_data,
-                format: self.metadata.format,
-                compression: self.tile_compression,
-            })),
+            Ok(Some(tile_data)) => {
+                let tile = TileData {
+                    data: tile_data,
+                    format: self.metadata.format,
+                    compression: self.tile_compression,
+                };
+                let tile = match &self.format_check {
+                    Some(check) => check.check(tile),
+                    None => tile,
+                };
+                match &self.checksum_check {
+                    Some(check) => check.check(z, x, y, tile).map(Some),
+                    None => Ok(Some(tile)),
+                }
+            }
             Ok(None) => Ok(None),
             Err(e) => {
                 tracing::warn!("Error reading HTTP tile z={} x={} y={}: {}", z, x, y, e);
@@ -165,6 +318,17 @@ impl TileSource for HttpPmTilesSource {
         &self.metadata
     }
 
+    async fn health_check(&self) -> Result<()> {
+        let reader = self.reader.read().await;
+        reader.get_metadata().await.map(|_| ()).map_err(|e| {
+            TileServerError::MetadataError(format!("Failed to read PMTiles metadata: {}", e))
+        })
+    }
+
+    fn backend(&self) -> &'static str {
+        "pmtiles"
+    }
+
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
@@ -1,21 +1,33 @@
 use async_trait::async_trait;
+use futures::TryStreamExt;
 use pmtiles::{AsyncPmTilesReader, Compression as PmCompression, MmapBackend, TileCoord, TileType};
+use std::collections::BTreeSet;
 use std::path::Path;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::SystemTime;
 
 use crate::config::SourceConfig;
 use crate::error::{Result, TileServerError};
-use crate::sources::{TileCompression, TileData, TileFormat, TileMetadata, TileSource};
+use crate::integrity::IntegrityIssue;
+use crate::sources::pmtiles::checksum::ChecksumCheck;
+use crate::sources::{
+    FormatCheck, TileCompression, TileData, TileFormat, TileMetadata, TileRange, TileSource,
+};
 
 /// Type alias for local PMTiles reader: Backend=MmapBackend
 type LocalReader = AsyncPmTilesReader<MmapBackend>;
 
 /// Local file-based PMTiles tile source using memory-mapped I/O
 pub struct LocalPmTilesSource {
-    reader: Arc<RwLock<LocalReader>>,
+    path: String,
+    reader: Arc<LocalReader>,
     metadata: TileMetadata,
     tile_compression: TileCompression,
+    /// Corrects mislabeled tiles when `verify_format = true`
+    format_check: Option<FormatCheck>,
+    /// Fails corrupt tiles when `verify_checksums = true`
+    checksum_check: Option<ChecksumCheck>,
+    modified: Option<SystemTime>,
 }
 
 impl LocalPmTilesSource {
@@ -95,6 +107,9 @@ impl LocalPmTilesSource {
                 header.center_zoom as f64,
             ]),
             vector_layers,
+            scheme: config.scheme,
+            overzoom: config.overzoom,
+            encoding: None,
         };
 
         tracing::info!(
@@ -106,13 +121,82 @@ impl LocalPmTilesSource {
         );
 
         Ok(Self {
-            reader: Arc::new(RwLock::new(reader)),
+            path: path.clone(),
+            reader: Arc::new(reader),
             metadata,
             tile_compression,
+            format_check: config
+                .options
+                .verify_format
+                .unwrap_or(false)
+                .then(|| FormatCheck::new(&config.id, format, tile_compression)),
+            checksum_check: config
+                .options
+                .verify_checksums
+                .unwrap_or(false)
+                .then(|| ChecksumCheck::new(&config.id, tile_compression)),
+            modified: std::fs::metadata(path).and_then(|m| m.modified()).ok(),
         })
     }
 }
 
+/// Size of the PMTiles v3 header
+const HEADER_LEN: usize = 127;
+
+/// The root directory must fit in the first 16 KiB (PMTiles v3, section 3)
+const ROOT_DIRECTORY_LIMIT: u64 = 16_384;
+
+/// Problems with a PMTiles v3 header: magic and version, and sections that
+/// run past the end of the file
+///
+/// v3 headers carry no checksum, so consistency is all that can be checked.
+fn header_issues(header: &[u8; HEADER_LEN], file_len: u64) -> Vec<IntegrityIssue> {
+    let mut issues = Vec::new();
+    if &header[0..7] != b"PMTiles" {
+        issues.push(IntegrityIssue::new("header", "Missing PMTiles magic bytes"));
+        return issues;
+    }
+    if header[7] != 3 {
+        issues.push(IntegrityIssue::new(
+            "header",
+            format!("Unsupported PMTiles version {}", header[7]),
+        ));
+        return issues;
+    }
+
+    let field = |offset: usize| u64::from_le_bytes(header[offset..offset + 8].try_into().unwrap());
+    for (name, offset) in [
+        ("root directory", 8),
+        ("metadata", 24),
+        ("leaf directories", 40),
+        ("tile data", 56),
+    ] {
+        let (start, len) = (field(offset), field(offset + 8));
+        if start.checked_add(len).map_or(true, |end| end > file_len) {
+            issues.push(IntegrityIssue::new(
+                "header",
+                format!(
+                    "The {} ({} bytes at offset {}) runs past the end of the {} byte file",
+                    name, len, start, file_len
+                ),
+            ));
+        }
+    }
+    if field(8).saturating_add(field(16)) > ROOT_DIRECTORY_LIMIT {
+        issues.push(IntegrityIssue::new(
+            "header",
+            "The root directory does not fit in the first 16384 bytes",
+        ));
+    }
+    if header[100] > header[101] {
+        issues.push(IntegrityIssue::new(
+            "header",
+            format!("Min zoom {} is above max zoom {}", header[100], header[101]),
+        ));
+    }
+    issues
+}
+
 /// Convert PMTiles compression to our compression enum
 fn convert_compression(compression: PmCompression) -> TileCompression {
     match compression {
@@ -126,7 +210,7 @@ fn convert_compression(compression: PmCompression) -> TileCompression {
 
 #[async_trait]
 impl TileSource for LocalPmTilesSource {
-    async fn get_tile(&self, z: u8, x: u32, y: u32) -> Result<Option<TileData>> {
+    async fn get_xyz_tile(&self, z: u8, x: u32, y: u32) -> Result<Option<TileData>> {
         // Validate coordinates
         let max_tile = 1u32 << z;
         if x >= max_tile || y >= max_tile {
@@ -144,15 +228,23 @@ impl TileSource for LocalPmTilesSource {
             Err(_) => return Err(TileServerError::InvalidCoordinates { z, x, y }),
         };
 
-        let reader = self.reader.read().await;
-
         // Get tile from PMTiles via memory-mapped I/O
-        match reader.get_tile(coord).await {
-            Ok(Some(tile_data)) => Ok(Some(TileData {
-                data: tile_data,
-                format: self.metadata.format,
-                compression: self.tile_compression,
-            })),
+        match self.reader.get_tile(coord).await {
+            Ok(Some(tile_data)) => {
+                let tile = TileData {
+                    data: tile_data,
+                    format: self.metadata.format,
+                    compression: self.tile_compression,
+                };
+                let tile = match &self.format_check {
+                    Some(check) => check.check(tile),
+                    None => tile,
+                };
+                match &self.checksum_check {
+                    Some(check) => check.check(z, x, y, tile).map(Some),
+                    None => Ok(Some(tile)),
+                }
+            }
             Ok(None) => Ok(None),
             Err(e) => {
                 tracing::warn!("Error reading local tile z={} x={} y={}: {}", z, x, y, e);
@@ -165,7 +257,175 @@ impl TileSource for LocalPmTilesSource {
         &self.metadata
     }
 
+    // `tile_info` keeps the default: the pmtiles crate does not expose
+    // directory entry lengths, and reads are served from the memory map
+    fn last_modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+
+    async fn health_check(&self) -> Result<()> {
+        use tokio::io::AsyncReadExt;
+
+        // Re-read the header magic from disk to catch removed or replaced files
+        let mut magic = [0u8; 7];
+        let mut file = tokio::fs::File::open(&self.path).await?;
+        file.read_exact(&mut magic).await?;
+        if &magic != b"PMTiles" {
+            return Err(TileServerError::MetadataError(format!(
+                "Invalid PMTiles header: {}",
+                self.path
+            )));
+        }
+        Ok(())
+    }
+
+    async fn tile_count(&self) -> Result<Option<u64>> {
+        use tokio::io::AsyncReadExt;
+
+        // Addressed tile count from the v3 header; zero means unknown
+        let mut header = [0u8; 80];
+        let mut file = tokio::fs::File::open(&self.path).await?;
+        file.read_exact(&mut header).await?;
+        let count = u64::from_le_bytes(header[72..80].try_into().unwrap());
+        Ok((count > 0).then_some(count))
+    }
+
+    async fn check_integrity(&self) -> Result<Vec<IntegrityIssue>> {
+        use tokio::io::AsyncReadExt;
+
+        let mut header = [0u8; HEADER_LEN];
+        let mut file = tokio::fs::File::open(&self.path).await?;
+        let file_len = file.metadata().await?.len();
+        file.read_exact(&mut header).await?;
+        let mut issues = header_issues(&header, file_len);
+
+        // Walk every directory: entries must be readable and ordered by tile ID
+        let mut entries = self.reader.clone().entries();
+        let mut last_id: Option<u64> = None;
+        let mut addressed = 0u64;
+        loop {
+            let entry = match entries.try_next().await {
+                Ok(Some(entry)) => entry,
+                Ok(None) => break,
+                Err(e) => {
+                    issues.push(IntegrityIssue::new(
+                        "directory",
+                        format!("Failed to read directory: {}", e),
+                    ));
+                    return Ok(issues);
+                }
+            };
+            let mut ids = entry.iter_coords().map(|id| id.value());
+            let Some(first) = ids.next() else {
+                continue;
+            };
+            if last_id.is_some_and(|last| first <= last) {
+                issues.push(IntegrityIssue::new(
+                    "directory",
+                    format!("Directory entries out of order at tile ID {}", first),
+                ));
+                return Ok(issues);
+            }
+            let last = ids.last().unwrap_or(first);
+            addressed += last - first + 1;
+            last_id = Some(last);
+        }
+
+        let expected = u64::from_le_bytes(header[72..80].try_into().unwrap());
+        if expected > 0 && expected != addressed {
+            issues.push(IntegrityIssue::new(
+                "directory",
+                format!(
+                    "Header counts {} addressed tiles, directories address {}",
+                    expected, addressed
+                ),
+            ));
+        }
+        Ok(issues)
+    }
+
+    async fn list_tiles(&self, range: TileRange, limit: usize) -> Result<Option<Vec<(u32, u32)>>> {
+        let mut entries = self.reader.clone().entries();
+        // Keep only the first `limit` tiles so the result is the same on every
+        // call, without holding every tile of a large archive
+        let mut tiles = BTreeSet::new();
+
+        // Entries are ordered by tile ID, which orders by zoom level first
+        'entries: while let Some(entry) = entries.try_next().await.map_err(|e| {
+            TileServerError::MetadataError(format!("Failed to read PMTiles directory: {}", e))
+        })? {
+            for id in entry.iter_coords() {
+                let coord = TileCoord::from(id);
+                if coord.z() > range.z {
+                    break 'entries;
+                }
+                if coord.z() == range.z && range.contains(coord.x(), coord.y()) {
+                    tiles.insert((coord.x(), coord.y()));
+                    if tiles.len() > limit {
+                        tiles.pop_last();
+                    }
+                }
+            }
+        }
+
+        Ok(Some(tiles.into_iter().collect()))
+    }
+
+    fn backend(&self) -> &'static str {
+        "pmtiles"
+    }
+
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(file_len: u64) -> [u8; HEADER_LEN] {
+        let mut header = [0u8; HEADER_LEN];
+        header[0..7].copy_from_slice(b"PMTiles");
+        header[7] = 3;
+        let mut set = |offset: usize, value: u64| {
+            header[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+        };
+        set(8, 127); // root directory
+        set(16, 100);
+        set(24, 227); // metadata
+        set(32, 50);
+        set(40, 277); // leaf directories
+        set(48, 0);
+        set(56, 277); // tile data
+        set(64, file_len - 277);
+        header[101] = 14;
+        header
+    }
+
+    #[test]
+    fn test_header_issues() {
+        assert!(header_issues(&header(1000), 1000).is_empty());
+
+        // Truncated file
+        let issues = header_issues(&header(1000), 900);
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].message.starts_with("The tile data"));
+
+        let mut bad = header(1000);
+        bad[7] = 4;
+        assert_eq!(
+            header_issues(&bad, 1000)[0].message,
+            "Unsupported PMTiles version 4"
+        );
+
+        let mut bad = header(1000);
+        bad[100] = 15;
+        assert_eq!(header_issues(&bad, 1000).len(), 1);
+
+        assert_eq!(
+            header_issues(&[0; HEADER_LEN], 1000)[0].message,
+            "Missing PMTiles magic bytes"
+        );
+    }
+}
@@ -0,0 +1,119 @@
+//! Find where a tile is stored in a PMTiles archive without reading it
+//!
+//! The pmtiles crate keeps directory entries private, so [`LocatingBackend`]
+//! wraps the archive's backend and, while [`locate`] runs, answers reads of
+//! the tile data section with an empty buffer and records their range
+//! instead. Directory reads go through as usual.
+
+use bytes::Bytes;
+use pmtiles::{AsyncBackend, PmtResult};
+use std::cell::Cell;
+use std::future::Future;
+use std::ops::Range;
+
+/// Size of the fixed PMTiles v3 header
+const HEADER_LEN: usize = 127;
+
+struct Probe {
+    data: Range<u64>,
+    found: Cell<Option<Range<u64>>>,
+}
+
+tokio::task_local! {
+    static PROBE: Probe;
+}
+
+/// Archive backend whose tile reads [`locate`] can intercept
+pub struct LocatingBackend<B>(pub B);
+
+/// Byte range of the tile data section, from the archive's v3 header
+pub async fn data_section(backend: &(impl AsyncBackend + Sync)) -> PmtResult<Range<u64>> {
+    let header = backend.read_exact(0, HEADER_LEN).await?;
+    let field = |at: usize| {
+        let bytes: [u8; 8] = header[at..at + 8].try_into().unwrap_or_default();
+        u64::from_le_bytes(bytes)
+    };
+    let offset = field(56);
+    Ok(offset..offset.saturating_add(field(64)))
+}
+
+impl<B: AsyncBackend + Sync + Send> AsyncBackend for LocatingBackend<B> {
+    async fn read_exact(&self, offset: usize, length: usize) -> PmtResult<Bytes> {
+        let start = offset as u64;
+        let located = PROBE
+            .try_with(|probe| {
+                let in_data = probe.data.contains(&start);
+                if in_data {
+                    probe.found.set(Some(start..start + length as u64));
+                }
+                in_data
+            })
+            .unwrap_or(false);
+        if located {
+            return Ok(Bytes::new());
+        }
+        self.0.read_exact(offset, length).await
+    }
+
+    async fn read(&self, offset: usize, length: usize) -> PmtResult<Bytes> {
+        self.0.read(offset, length).await
+    }
+}
+
+/// Run `fetch`, a tile read from a reader over a [`LocatingBackend`], and
+/// return the byte range the tile is stored at instead of reading it
+///
+/// `data` is the archive's tile data section. The range is `None` when
+/// `fetch` did not reach the data section, that is when the tile does not
+/// exist.
+pub async fn locate<F: Future>(data: Range<u64>, fetch: F) -> (F::Output, Option<Range<u64>>) {
+    let probe = Probe {
+        data,
+        found: Cell::new(None),
+    };
+    PROBE
+        .scope(probe, async {
+            let result = fetch.await;
+            (result, PROBE.with(|probe| probe.found.take()))
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pmtiles::{AsyncPmTilesReader, MmapBackend, TileCoord};
+    use std::path::Path;
+
+    const ARCHIVE: &str = "data/tiles/protomaps-sample.pmtiles";
+
+    #[tokio::test]
+    async fn test_locate_tile() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(ARCHIVE);
+        let backend = MmapBackend::try_from(&path).await.unwrap();
+        let data = data_section(&backend).await.unwrap();
+        let reader = AsyncPmTilesReader::try_from_source(LocatingBackend(backend))
+            .await
+            .unwrap();
+
+        // Florence at z14, inside the sample extract
+        let coord = TileCoord::new(14, 8704, 5972).unwrap();
+        let tile = reader.get_tile(coord).await.unwrap().unwrap();
+
+        let (located, range) = locate(data.clone(), reader.get_tile(coord)).await;
+        assert!(located.unwrap().unwrap().is_empty());
+        let range = range.expect("Tile should be located");
+
+        let file = std::fs::read(&path).unwrap();
+        assert_eq!(&file[range.start as usize..range.end as usize], &tile[..]);
+
+        // Missing tiles never reach the data section
+        let coord = TileCoord::new(14, 0, 0).unwrap();
+        let (located, range) = locate(data, reader.get_tile(coord)).await;
+        assert!(located.unwrap().is_none());
+        assert!(range.is_none());
+
+        // Outside `locate`, reads are untouched
+        assert_eq!(reader.get_tile(coord).await.unwrap(), None);
+    }
+}
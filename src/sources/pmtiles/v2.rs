@@ -0,0 +1,398 @@
+//! Read-only support for PMTiles v2 archives
+//!
+//! The pmtiles crate reads v3 only, but v2 archives are still served in the
+//! wild. A v2 archive starts with a 10-byte header (`PM`, a `u16` version, a
+//! `u32` metadata length and a `u16` root entry count), followed by the
+//! metadata JSON and the root directory. Directory entries are 17 bytes:
+//! zoom (`u8`), column and row (`u24`), offset (`u48`) and length (`u32`),
+//! little-endian, with rows counted from the top as in XYZ. Entries whose
+//! zoom has the high bit set point to leaf directories holding the tiles
+//! below that tile.
+//!
+//! Metadata is MBTiles-style JSON, so bounds and zooms are often strings and
+//! compression is a metadata key rather than a header field.
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use pmtiles::{AsyncBackend, MmapBackend};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::config::SourceConfig;
+use crate::error::{Result, TileServerError};
+use crate::sources::{TileCompression, TileData, TileFormat, TileMetadata, TileSource};
+
+/// Size of the fixed v2 header
+const HEADER_LEN: usize = 10;
+
+/// Size of a directory entry
+const ENTRY_LEN: usize = 17;
+
+/// Leaf directories kept in memory
+const LEAF_CACHE_CAPACITY: usize = 64;
+
+type TileKey = (u8, u32, u32);
+
+/// Where a tile or leaf directory is stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Entry {
+    offset: u64,
+    length: u32,
+}
+
+/// Tiles and leaf directories listed in one directory
+#[derive(Debug, Default)]
+struct Directory {
+    tiles: HashMap<TileKey, Entry>,
+    leaves: HashMap<TileKey, Entry>,
+}
+
+impl Directory {
+    fn parse(data: &[u8]) -> Self {
+        let mut directory = Self::default();
+        for entry in data.chunks_exact(ENTRY_LEN) {
+            let uint = |bytes: &[u8]| {
+                bytes
+                    .iter()
+                    .rev()
+                    .fold(0u64, |value, &byte| (value << 8) | byte as u64)
+            };
+            let z = entry[0];
+            let key = (
+                z & 0x7f,
+                uint(&entry[1..4]) as u32,
+                uint(&entry[4..7]) as u32,
+            );
+            let location = Entry {
+                offset: uint(&entry[7..13]),
+                length: uint(&entry[13..17]) as u32,
+            };
+            if z & 0x80 != 0 {
+                directory.leaves.insert(key, location);
+            } else {
+                directory.tiles.insert(key, location);
+            }
+        }
+        directory
+    }
+
+    /// Zoom level of the leaf directories, the same for all of them
+    fn leaf_level(&self) -> Option<u8> {
+        self.leaves.keys().map(|&(z, _, _)| z).min()
+    }
+}
+
+/// Local PMTiles v2 archive
+pub struct PmTilesV2Source {
+    path: String,
+    backend: MmapBackend,
+    root: Directory,
+    leaves: Mutex<HashMap<TileKey, Arc<Directory>>>,
+    metadata: TileMetadata,
+    /// From the metadata; `None` detects gzip per tile
+    compression: Option<TileCompression>,
+    modified: Option<SystemTime>,
+}
+
+impl PmTilesV2Source {
+    /// Open a v2 archive from a local file
+    pub async fn from_file(config: &SourceConfig) -> Result<Self> {
+        let path = &config.path;
+        tracing::warn!(
+            "PMTiles source '{}' is a deprecated v2 archive; convert it with `pmtiles convert` for full support",
+            config.id
+        );
+
+        let backend = MmapBackend::try_from(Path::new(path)).await.map_err(|e| {
+            TileServerError::MetadataError(format!("Failed to open PMTiles file: {}", e))
+        })?;
+        let read_error =
+            |e| TileServerError::MetadataError(format!("Failed to read PMTiles v2 header: {}", e));
+
+        let header = backend
+            .read_exact(0, HEADER_LEN)
+            .await
+            .map_err(read_error)?;
+        if super::archive_version(&header) != Some(2) {
+            return Err(TileServerError::MetadataError(format!(
+                "Not a PMTiles v2 archive: {}",
+                path
+            )));
+        }
+        let metadata_len =
+            u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        let root_entries = u16::from_le_bytes([header[8], header[9]]) as usize;
+
+        let json = backend
+            .read_exact(HEADER_LEN, metadata_len)
+            .await
+            .map_err(read_error)?;
+        let json: serde_json::Value = serde_json::from_slice(&json).map_err(|e| {
+            TileServerError::MetadataError(format!("Invalid PMTiles v2 metadata: {}", e))
+        })?;
+        let root = backend
+            .read_exact(HEADER_LEN + metadata_len, root_entries * ENTRY_LEN)
+            .await
+            .map_err(read_error)?;
+        let root = Directory::parse(&root);
+
+        let metadata = read_metadata(&json, config);
+        let compression = json
+            .get("compression")
+            .and_then(|c| c.as_str())
+            .map(|c| match c {
+                "gzip" => TileCompression::Gzip,
+                "br" | "brotli" => TileCompression::Brotli,
+                "zstd" => TileCompression::Zstd,
+                _ => TileCompression::None,
+            });
+
+        tracing::info!(
+            "Loaded PMTiles v2 source '{}': zoom {}-{}, format {:?}",
+            config.id,
+            metadata.minzoom,
+            metadata.maxzoom,
+            metadata.format
+        );
+
+        Ok(Self {
+            path: path.clone(),
+            backend,
+            root,
+            leaves: Mutex::new(HashMap::new()),
+            metadata,
+            compression,
+            modified: std::fs::metadata(path).and_then(|m| m.modified()).ok(),
+        })
+    }
+
+    /// Location of a tile, reading its leaf directory if needed
+    async fn find(&self, z: u8, x: u32, y: u32) -> Result<Option<Entry>> {
+        if let Some(entry) = self.root.tiles.get(&(z, x, y)) {
+            return Ok(Some(*entry));
+        }
+        let Some(level) = self.root.leaf_level().filter(|&level| z >= level) else {
+            return Ok(None);
+        };
+
+        let shift = z - level;
+        let key = (level, x >> shift, y >> shift);
+        let Some(leaf) = self.root.leaves.get(&key) else {
+            return Ok(None);
+        };
+        let cached = self.leaves.lock().ok().and_then(|l| l.get(&key).cloned());
+        let directory = match cached {
+            Some(directory) => directory,
+            None => {
+                let data = self.read(*leaf).await?;
+                let directory = Arc::new(Directory::parse(&data));
+                if let Ok(mut leaves) = self.leaves.lock() {
+                    if leaves.len() >= LEAF_CACHE_CAPACITY {
+                        leaves.clear();
+                    }
+                    leaves.insert(key, directory.clone());
+                }
+                directory
+            }
+        };
+        Ok(directory.tiles.get(&(z, x, y)).copied())
+    }
+
+    async fn read(&self, entry: Entry) -> Result<Bytes> {
+        self.backend
+            .read_exact(entry.offset as usize, entry.length as usize)
+            .await
+            .map_err(|e| {
+                TileServerError::MetadataError(format!("Failed to read PMTiles v2: {}", e))
+            })
+    }
+}
+
+/// Metadata as the v3 reader builds it, from MBTiles-style JSON
+fn read_metadata(json: &serde_json::Value, config: &SourceConfig) -> TileMetadata {
+    // Values are usually strings, as copied from an MBTiles metadata table
+    let numbers = |key: &str| -> Vec<f64> {
+        match json.get(key) {
+            Some(serde_json::Value::String(s)) => {
+                s.split(',').filter_map(|v| v.trim().parse().ok()).collect()
+            }
+            Some(serde_json::Value::Array(values)) => {
+                values.iter().filter_map(|v| v.as_f64()).collect()
+            }
+            Some(value) => value.as_f64().into_iter().collect(),
+            None => Vec::new(),
+        }
+    };
+    let zoom = |key: &str, default: u8| numbers(key).first().map_or(default, |&z| z as u8);
+
+    let format = match json.get("format").and_then(|f| f.as_str()) {
+        Some("png") => TileFormat::Png,
+        Some("jpg" | "jpeg") => TileFormat::Jpeg,
+        Some("webp") => TileFormat::Webp,
+        Some("avif") => TileFormat::Avif,
+        _ => TileFormat::Pbf,
+    };
+    let (minzoom, maxzoom) = (zoom("minzoom", 0), zoom("maxzoom", 22));
+    let bounds = <[f64; 4]>::try_from(numbers("bounds")).ok();
+    // Without a center, the middle of the bounds as for MBTiles
+    let center = match numbers("center")[..] {
+        [lon, lat, zoom, ..] => Some([lon, lat, zoom]),
+        _ => bounds.map(|b| {
+            let zoom = ((minzoom as f64 + maxzoom as f64) / 2.0).floor();
+            [(b[0] + b[2]) / 2.0, (b[1] + b[3]) / 2.0, zoom]
+        }),
+    };
+
+    // Either top-level or inside the MBTiles `json` string
+    let vector_layers = json.get("vector_layers").cloned().or_else(|| {
+        json.get("json")
+            .and_then(|j| j.as_str())
+            .and_then(|j| serde_json::from_str::<serde_json::Value>(j).ok())
+            .and_then(|j| j.get("vector_layers").cloned())
+    });
+
+    TileMetadata {
+        id: config.id.clone(),
+        name: config.name.clone().unwrap_or_else(|| config.id.clone()),
+        description: None,
+        attribution: config.attribution.clone(),
+        format,
+        minzoom,
+        maxzoom,
+        bounds,
+        center,
+        vector_layers,
+        scheme: config.scheme,
+        overzoom: config.overzoom,
+        encoding: None,
+    }
+}
+
+#[async_trait]
+impl TileSource for PmTilesV2Source {
+    async fn get_xyz_tile(&self, z: u8, x: u32, y: u32) -> Result<Option<TileData>> {
+        let max_tile = 1u32 << z;
+        if x >= max_tile || y >= max_tile {
+            return Err(TileServerError::InvalidCoordinates { z, x, y });
+        }
+        if z < self.metadata.minzoom || z > self.metadata.maxzoom {
+            return Ok(None);
+        }
+
+        let Some(entry) = self.find(z, x, y).await? else {
+            return Ok(None);
+        };
+        let data = self.read(entry).await?;
+        let compression = self
+            .compression
+            .unwrap_or(if data.starts_with(&[0x1f, 0x8b]) {
+                TileCompression::Gzip
+            } else {
+                TileCompression::None
+            });
+        Ok(Some(TileData {
+            data,
+            format: self.metadata.format,
+            compression,
+        }))
+    }
+
+    fn metadata(&self) -> &TileMetadata {
+        &self.metadata
+    }
+
+    fn last_modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+
+    async fn health_check(&self) -> Result<()> {
+        use tokio::io::AsyncReadExt;
+
+        // Re-read the header from disk to catch removed or replaced files
+        let mut magic = [0u8; 7];
+        let mut file = tokio::fs::File::open(&self.path).await?;
+        file.read_exact(&mut magic).await?;
+        if super::archive_version(&magic) != Some(2) {
+            return Err(TileServerError::MetadataError(format!(
+                "Invalid PMTiles v2 header: {}",
+                self.path
+            )));
+        }
+        Ok(())
+    }
+
+    fn backend(&self) -> &'static str {
+        "pmtiles"
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(z: u8, x: u32, y: u32, offset: u64, length: u32) -> Vec<u8> {
+        let mut bytes = vec![z];
+        bytes.extend(&x.to_le_bytes()[..3]);
+        bytes.extend(&y.to_le_bytes()[..3]);
+        bytes.extend(&offset.to_le_bytes()[..6]);
+        bytes.extend(length.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_parse_directory() {
+        let data = [
+            entry(0, 0, 0, 100, 20),
+            entry(3, 5, 7, 0x0102_0304_0506, 30),
+            entry(7 | 0x80, 66, 44, 5000, 17 * 4),
+        ]
+        .concat();
+        let directory = Directory::parse(&data);
+        assert_eq!(directory.tiles.len(), 2);
+        assert_eq!(
+            directory.tiles[&(3, 5, 7)],
+            Entry {
+                offset: 0x0102_0304_0506,
+                length: 30
+            }
+        );
+        assert_eq!(directory.leaves[&(7, 66, 44)].length, 68);
+        assert_eq!(directory.leaf_level(), Some(7));
+    }
+
+    #[test]
+    fn test_read_metadata() {
+        let config: SourceConfig = toml::from_str(
+            r#"
+            id = "old"
+            type = "pmtiles"
+            path = "old.pmtiles"
+            "#,
+        )
+        .unwrap();
+        let json = serde_json::json!({
+            "format": "png",
+            "minzoom": "2",
+            "maxzoom": 9,
+            "bounds": "8.2,47.2,8.8,47.5",
+            "center": "8.5,47.3,6",
+            "json": r#"{"vector_layers":[{"id":"water"}]}"#
+        });
+        let metadata = read_metadata(&json, &config);
+        assert_eq!(metadata.name, "old");
+        assert_eq!(metadata.format, TileFormat::Png);
+        assert_eq!((metadata.minzoom, metadata.maxzoom), (2, 9));
+        assert_eq!(metadata.bounds, Some([8.2, 47.2, 8.8, 47.5]));
+        assert_eq!(metadata.center, Some([8.5, 47.3, 6.0]));
+        assert_eq!(
+            metadata.vector_layers,
+            Some(serde_json::json!([{"id": "water"}]))
+        );
+    }
+}
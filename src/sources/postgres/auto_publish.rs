@@ -0,0 +1,278 @@
+//! Table discovery for `[postgres.auto_publish]`
+//!
+//! Every table and view with a geometry column in the configured schemas
+//! becomes a table source. Its properties, their types and its bounds are
+//! then discovered the same way as for a table listed in `[[postgres.tables]]`.
+
+use std::collections::HashSet;
+
+use crate::config::{
+    default_buffer, default_extent, PostgresAutoPublishConfig, PostgresTableConfig,
+};
+use crate::error::{Result, TileServerError};
+
+/// A row of PostGIS' `geometry_columns` view
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GeometryColumn {
+    pub schema: String,
+    pub table: String,
+    pub column: String,
+    pub srid: i32,
+}
+
+/// Table source configs for the geometry tables in the configured schemas
+///
+/// Tables already listed in `configured` keep their own settings and are
+/// skipped.
+pub async fn discover_tables(
+    conn: &deadpool_postgres::Object,
+    config: &PostgresAutoPublishConfig,
+    configured: &[PostgresTableConfig],
+) -> Result<Vec<PostgresTableConfig>> {
+    let query = r#"
+        SELECT f_table_schema::text, f_table_name::text, f_geometry_column::text, srid
+        FROM geometry_columns
+        WHERE f_table_schema = ANY($1)
+        ORDER BY f_table_schema, f_table_name, f_geometry_column
+    "#;
+
+    let columns = conn
+        .query(query, &[&config.schemas])
+        .await
+        .map_err(|e| {
+            TileServerError::PostgresError(format!("Failed to list geometry columns: {}", e))
+        })?
+        .iter()
+        .map(|row| GeometryColumn {
+            schema: row.get(0),
+            table: row.get(1),
+            column: row.get(2),
+            srid: row.get(3),
+        })
+        .collect();
+
+    select_tables(config, configured, columns)
+}
+
+/// Pick the geometry columns to publish and build their source configs
+fn select_tables(
+    config: &PostgresAutoPublishConfig,
+    configured: &[PostgresTableConfig],
+    columns: Vec<GeometryColumn>,
+) -> Result<Vec<PostgresTableConfig>> {
+    // Without `{column}` in the ID, a table's first geometry column is used
+    let per_column = config.id_format.contains("{column}");
+    let mut published_tables = HashSet::new();
+    let mut ids = HashSet::new();
+    let mut tables = Vec::new();
+
+    for column in columns {
+        let listed = configured
+            .iter()
+            .any(|t| t.schema == column.schema && t.table == column.table);
+        if listed || is_excluded(&config.exclude, &column.schema, &column.table) {
+            continue;
+        }
+        if !per_column && !published_tables.insert((column.schema.clone(), column.table.clone())) {
+            continue;
+        }
+        if column.srid <= 0 {
+            tracing::warn!(
+                "Not publishing {}.{}: column '{}' has no SRID",
+                column.schema,
+                column.table,
+                column.column
+            );
+            continue;
+        }
+
+        let id = source_id(&config.id_format, &column);
+        if !ids.insert(id.clone()) {
+            tracing::warn!(
+                "Not publishing {}.{}: source ID '{}' is already used by another table",
+                column.schema,
+                column.table,
+                id
+            );
+            continue;
+        }
+
+        tables.push(PostgresTableConfig {
+            id,
+            schema: column.schema,
+            table: column.table,
+            geometry_column: Some(column.column),
+            id_column: None,
+            properties: None,
+            name: None,
+            attribution: None,
+            description: None,
+            minzoom: config.minzoom,
+            maxzoom: config.maxzoom,
+            bounds: None,
+            extent: default_extent(),
+            buffer: default_buffer(),
+            max_features: None,
+            resilience: None,
+        });
+    }
+
+    if tables.len() > config.max_tables {
+        return Err(TileServerError::ConfigError(format!(
+            "postgres.auto_publish found {} tables, more than max_tables = {}. \
+             Narrow down schemas, add exclude patterns or raise max_tables",
+            tables.len(),
+            config.max_tables
+        )));
+    }
+    Ok(tables)
+}
+
+/// Source ID for `column`, filling in `{schema}`, `{table}` and `{column}`
+pub fn source_id(id_format: &str, column: &GeometryColumn) -> String {
+    id_format
+        .replace("{schema}", &column.schema)
+        .replace("{table}", &column.table)
+        .replace("{column}", &column.column)
+}
+
+/// Whether a pattern in `exclude` matches the table, or `schema.table` for
+/// patterns containing a dot
+pub fn is_excluded(exclude: &[String], schema: &str, table: &str) -> bool {
+    let qualified = format!("{}.{}", schema, table);
+    exclude.iter().any(|pattern| {
+        let name = if pattern.contains('.') {
+            &qualified
+        } else {
+            table
+        };
+        wildcard_match(pattern, name)
+    })
+}
+
+/// Match `text` against `pattern`, where `*` matches any run of characters
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No wildcard, so the whole text must match
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(schema: &str, table: &str, column: &str) -> GeometryColumn {
+        GeometryColumn {
+            schema: schema.to_string(),
+            table: table.to_string(),
+            column: column.to_string(),
+            srid: 4326,
+        }
+    }
+
+    fn auto_publish(exclude: &[&str], max_tables: usize) -> PostgresAutoPublishConfig {
+        PostgresAutoPublishConfig {
+            schemas: vec!["public".to_string(), "gis".to_string()],
+            id_format: "{schema}.{table}".to_string(),
+            exclude: exclude.iter().map(|p| p.to_string()).collect(),
+            max_tables,
+            minzoom: 0,
+            maxzoom: 14,
+        }
+    }
+
+    #[test]
+    fn test_source_id() {
+        let roads = column("gis", "roads", "geom");
+        assert_eq!(source_id("{schema}.{table}", &roads), "gis.roads");
+        assert_eq!(source_id("{table}", &roads), "roads");
+        assert_eq!(source_id("pg_{table}_{column}", &roads), "pg_roads_geom");
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("tmp_*", "tmp_roads"));
+        assert!(wildcard_match("tmp_*", "tmp_"));
+        assert!(!wildcard_match("tmp_*", "roads_tmp"));
+        assert!(wildcard_match("*_backup", "roads_backup"));
+        assert!(wildcard_match("*old*", "roads_old_2020"));
+        assert!(wildcard_match("a*b*c", "abc"));
+        assert!(!wildcard_match("a*b*c", "acb"));
+        assert!(!wildcard_match("ab*ba", "aba"));
+        assert!(wildcard_match("roads", "roads"));
+        assert!(!wildcard_match("roads", "roads2"));
+        assert!(wildcard_match("*", ""));
+    }
+
+    #[test]
+    fn test_is_excluded() {
+        let exclude = ["tmp_*".to_string(), "staging.*".to_string()];
+        assert!(is_excluded(&exclude, "public", "tmp_import"));
+        assert!(is_excluded(&exclude, "staging", "roads"));
+        assert!(!is_excluded(&exclude, "public", "roads"));
+        assert!(!is_excluded(&exclude, "public", "staging"));
+        assert!(!is_excluded(&[], "public", "tmp_import"));
+    }
+
+    #[test]
+    fn test_select_tables() {
+        let columns = vec![
+            column("gis", "buildings", "footprint"),
+            column("gis", "buildings", "centroid"),
+            column("public", "roads", "geom"),
+            column("public", "tmp_roads", "geom"),
+            GeometryColumn {
+                srid: 0,
+                ..column("public", "unknown_srid", "geom")
+            },
+        ];
+
+        let tables = select_tables(&auto_publish(&["tmp_*"], 10), &[], columns.clone()).unwrap();
+        let ids: Vec<&str> = tables.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(ids, vec!["gis.buildings", "public.roads"]);
+        assert_eq!(tables[0].geometry_column.as_deref(), Some("footprint"));
+        assert_eq!(tables[0].maxzoom, 14);
+        assert_eq!(tables[0].extent, 4096);
+
+        // One source per geometry column when the ID includes it
+        let config = PostgresAutoPublishConfig {
+            id_format: "{table}.{column}".to_string(),
+            ..auto_publish(&["tmp_*"], 10)
+        };
+        let tables = select_tables(&config, &[], columns.clone()).unwrap();
+        let ids: Vec<&str> = tables.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            vec!["buildings.footprint", "buildings.centroid", "roads.geom"]
+        );
+
+        // Listed tables keep their own configuration
+        let listed = &tables[2];
+        let tables = select_tables(
+            &auto_publish(&["tmp_*"], 10),
+            std::slice::from_ref(listed),
+            columns.clone(),
+        )
+        .unwrap();
+        let ids: Vec<&str> = tables.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(ids, vec!["gis.buildings"]);
+
+        let error = select_tables(&auto_publish(&[], 2), &[], columns).unwrap_err();
+        assert!(error.to_string().contains("found 3 tables"));
+    }
+}
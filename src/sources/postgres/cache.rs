@@ -2,9 +2,11 @@
 
 use moka::future::Cache;
 use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use crate::sources::manager::CacheStats;
 use crate::sources::TileData;
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -13,6 +15,8 @@ pub struct TileCacheKey {
     pub z: u8,
     pub x: u32,
     pub y: u32,
+    /// Query parameters passed to a function source, as canonical JSON
+    pub query: Option<Arc<str>>,
 }
 
 impl Hash for TileCacheKey {
@@ -21,12 +25,17 @@ impl Hash for TileCacheKey {
         self.z.hash(state);
         self.x.hash(state);
         self.y.hash(state);
+        self.query.hash(state);
     }
 }
 
 #[derive(Clone)]
 pub struct TileCache {
     cache: Cache<TileCacheKey, TileData>,
+    /// When the cache was created, so generations don't repeat across restarts
+    created: u64,
+    /// Bumped whenever cached tiles are dropped
+    generation: Arc<AtomicU64>,
 }
 
 impl TileCache {
@@ -39,9 +48,32 @@ impl TileCache {
                 value.data.len().try_into().unwrap_or(u32::MAX)
             })
             .time_to_live(Duration::from_secs(ttl_seconds))
+            .support_invalidation_closures()
             .build();
 
-        Self { cache }
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos() as u64)
+            .unwrap_or_default();
+        Self {
+            cache,
+            created,
+            generation: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Changes whenever cached tiles are cleared or invalidated, for use as a
+    /// data version of sources invalidated through `NOTIFY`
+    pub fn generation(&self) -> String {
+        format!(
+            "{:x}-{:x}",
+            self.created,
+            self.generation.load(Ordering::Acquire)
+        )
+    }
+
+    fn bump(&self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
     }
 
     pub async fn get(&self, key: &TileCacheKey) -> Option<TileData> {
@@ -61,6 +93,60 @@ impl TileCache {
     pub fn weighted_size(&self) -> u64 {
         self.cache.weighted_size()
     }
+
+    /// Entry count and size, after applying pending evictions
+    pub async fn stats(&self) -> CacheStats {
+        self.cache.run_pending_tasks().await;
+        CacheStats {
+            entries: self.cache.entry_count(),
+            size_bytes: self.cache.weighted_size(),
+            capacity_bytes: self.cache.policy().max_capacity().unwrap_or(0),
+        }
+    }
+
+    /// Drop every cached tile
+    pub async fn clear(&self) {
+        self.cache.invalidate_all();
+        self.cache.run_pending_tasks().await;
+        self.bump();
+    }
+
+    /// Drop one cached tile, for every set of query parameters
+    pub async fn invalidate(&self, key: &TileCacheKey) {
+        let key = key.clone();
+        self.cache
+            .invalidate_entries_if(move |cached, _| {
+                cached.source_id == key.source_id
+                    && (cached.z, cached.x, cached.y) == (key.z, key.x, key.y)
+            })
+            .expect("invalidation closures are enabled in new()");
+        self.cache.run_pending_tasks().await;
+        self.bump();
+    }
+
+    /// Drop one cached tile and every tile within it at higher zooms
+    pub async fn invalidate_descendants(&self, source_id: &str, z: u8, x: u32, y: u32) {
+        let source_id: Arc<str> = source_id.into();
+        self.cache
+            .invalidate_entries_if(move |key, _| {
+                key.source_id == source_id
+                    && key.z >= z
+                    && (key.x >> (key.z - z), key.y >> (key.z - z)) == (x, y)
+            })
+            .expect("invalidation closures are enabled in new()");
+        self.cache.run_pending_tasks().await;
+        self.bump();
+    }
+
+    /// Drop the cached tiles of one source
+    pub async fn clear_source(&self, source_id: &str) {
+        let source_id: Arc<str> = source_id.into();
+        self.cache
+            .invalidate_entries_if(move |key, _| key.source_id == source_id)
+            .expect("invalidation closures are enabled in new()");
+        self.cache.run_pending_tasks().await;
+        self.bump();
+    }
 }
 
 impl std::fmt::Debug for TileCache {
@@ -94,6 +180,7 @@ mod tests {
             z: 14,
             x: 8580,
             y: 5737,
+            query: None,
         };
         let tile = make_tile_data(1024);
 
@@ -112,6 +199,7 @@ mod tests {
             z: 14,
             x: 8580,
             y: 5737,
+            query: None,
         };
 
         let result = cache.get(&key).await;
@@ -128,6 +216,7 @@ mod tests {
                 z: 14,
                 x: i,
                 y: 0,
+                query: None,
             };
             cache.insert(key, make_tile_data(1000)).await;
         }
@@ -135,4 +224,97 @@ mod tests {
         cache.cache.run_pending_tasks().await;
         assert!(cache.weighted_size() >= 4000);
     }
+
+    #[tokio::test]
+    async fn test_cache_clear_source() {
+        let cache = TileCache::new(1, 3600);
+        let key = |source_id: &str| TileCacheKey {
+            source_id: source_id.into(),
+            z: 0,
+            x: 0,
+            y: 0,
+            query: None,
+        };
+        cache.insert(key("roads"), make_tile_data(100)).await;
+        cache.insert(key("water"), make_tile_data(100)).await;
+
+        cache.clear_source("roads").await;
+
+        assert!(cache.get(&key("roads")).await.is_none());
+        assert!(cache.get(&key("water")).await.is_some());
+        assert_eq!(cache.stats().await.entries, 1);
+    }
+
+    #[tokio::test]
+    async fn test_cache_stats_and_clear() {
+        let cache = TileCache::new(1, 3600);
+        for i in 0..3 {
+            let key = TileCacheKey {
+                source_id: "test".into(),
+                z: 14,
+                x: i,
+                y: 0,
+                query: None,
+            };
+            cache.insert(key, make_tile_data(100)).await;
+        }
+
+        let stats = cache.stats().await;
+        assert_eq!(stats.entries, 3);
+        assert_eq!(stats.size_bytes, 300);
+        assert_eq!(stats.capacity_bytes, 1024 * 1024);
+
+        cache.clear().await;
+        assert_eq!(cache.stats().await.entries, 0);
+    }
+
+    #[tokio::test]
+    async fn test_cache_query_params() {
+        let cache = TileCache::new(1, 3600);
+        let key = |query: Option<&str>| TileCacheKey {
+            source_id: "filtered".into(),
+            z: 10,
+            x: 536,
+            y: 358,
+            query: query.map(Into::into),
+        };
+        cache.insert(key(None), make_tile_data(100)).await;
+        cache
+            .insert(key(Some(r#"{"category":"parks"}"#)), make_tile_data(200))
+            .await;
+
+        assert_eq!(cache.get(&key(None)).await.unwrap().data.len(), 100);
+        assert_eq!(
+            cache
+                .get(&key(Some(r#"{"category":"parks"}"#)))
+                .await
+                .unwrap()
+                .data
+                .len(),
+            200
+        );
+        assert!(cache
+            .get(&key(Some(r#"{"category":"water"}"#)))
+            .await
+            .is_none());
+
+        // Invalidating a tile drops it for every set of parameters
+        cache.invalidate(&key(None)).await;
+        assert_eq!(cache.stats().await.entries, 0);
+    }
+
+    #[tokio::test]
+    async fn test_generation_changes_on_invalidation() {
+        let cache = TileCache::new(1, 3600);
+        let initial = cache.generation();
+        assert_eq!(cache.clone().generation(), initial);
+
+        cache.clear_source("roads").await;
+        let cleared = cache.generation();
+        assert_ne!(cleared, initial);
+
+        // Clones share the generation, as the NOTIFY listener holds one
+        cache.clone().invalidate_descendants("roads", 1, 0, 0).await;
+        assert_ne!(cache.generation(), cleared);
+    }
 }
@@ -0,0 +1,279 @@
+//! Tile cache invalidation through PostgreSQL `LISTEN`/`NOTIFY`.
+//!
+//! Triggers or jobs that edit source tables send `NOTIFY` on the configured
+//! channel with the ID of the changed source, or `source_id:z/x/y` for a
+//! single tile:
+//!
+//! ```sql
+//! SELECT pg_notify('tiles_changed', 'roads:14/8580/5737');
+//! ```
+
+use futures::{stream, Stream, StreamExt};
+use opentelemetry::metrics::Counter;
+use opentelemetry::KeyValue;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock, Weak};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio_postgres::{AsyncMessage, Client, NoTls};
+
+use super::TileCache;
+
+/// Delay before the first reconnect attempt, doubled after every failure
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+static INVALIDATIONS: OnceLock<Counter<u64>> = OnceLock::new();
+
+fn invalidations() -> &'static Counter<u64> {
+    INVALIDATIONS.get_or_init(|| {
+        opentelemetry::global::meter("tileserver-rs")
+            .u64_counter("tileserver_postgres_cache_invalidations_total")
+            .with_description("Tile cache invalidations received through NOTIFY")
+            .with_unit("events")
+            .build()
+    })
+}
+
+/// Cache entries named by a `NOTIFY` payload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Invalidation {
+    /// Every cached tile of a source
+    Source(String),
+    /// One tile of a source and the tiles below it at higher zooms
+    Tile {
+        source_id: String,
+        z: u8,
+        x: u32,
+        y: u32,
+    },
+}
+
+impl Invalidation {
+    /// Parse `source_id` or `source_id:z/x/y`, `None` for malformed payloads
+    pub fn parse(payload: &str) -> Option<Self> {
+        let payload = payload.trim();
+        let Some((source_id, tile)) = payload.rsplit_once(':') else {
+            return (!payload.is_empty()).then(|| Self::Source(payload.to_string()));
+        };
+        if source_id.is_empty() {
+            return None;
+        }
+
+        let mut parts = tile.split('/');
+        let z: u8 = parts.next()?.parse().ok()?;
+        let x: u32 = parts.next()?.parse().ok()?;
+        let y: u32 = parts.next()?.parse().ok()?;
+        if parts.next().is_some() || z > 30 || x >= 1 << z || y >= 1 << z {
+            return None;
+        }
+
+        Some(Self::Tile {
+            source_id: source_id.to_string(),
+            z,
+            x,
+            y,
+        })
+    }
+
+    /// Evict the named entries from the cache
+    pub async fn apply(&self, cache: &TileCache) {
+        match self {
+            Self::Source(source_id) => {
+                cache.clear_source(source_id).await;
+                invalidations().add(1, &[KeyValue::new("scope", "source")]);
+            }
+            Self::Tile { source_id, z, x, y } => {
+                cache.invalidate_descendants(source_id, *z, *x, *y).await;
+                invalidations().add(1, &[KeyValue::new("scope", "tile")]);
+            }
+        }
+    }
+}
+
+/// Listen on `channel` and evict the tiles named by its notifications
+///
+/// The listener keeps its own connection outside the pool and reconnects with
+/// exponential backoff when it drops. Since notifications sent while it was
+/// disconnected are lost, the whole cache is cleared after reconnecting. The
+/// task ends once the cache is dropped.
+pub fn spawn(
+    config: tokio_postgres::Config,
+    channel: String,
+    cache: &Arc<TileCache>,
+) -> JoinHandle<()> {
+    let cache = Arc::downgrade(cache);
+    tokio::spawn(async move {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut reconnecting = false;
+        while cache.strong_count() > 0 {
+            let error = match connect(&config, &channel).await {
+                Ok((_client, messages)) => {
+                    tracing::info!("Listening for tile cache invalidations on '{}'", channel);
+                    backoff = INITIAL_BACKOFF;
+                    if let Some(cache) = cache.upgrade().filter(|_| reconnecting) {
+                        cache.clear().await;
+                    }
+                    match receive(messages, &channel, &cache).await {
+                        Ok(()) => break,
+                        Err(e) => e,
+                    }
+                }
+                Err(e) => e.to_string(),
+            };
+            tracing::warn!(
+                "Cache invalidation listener on '{}' disconnected: {}. Reconnecting in {}s",
+                channel,
+                error,
+                backoff.as_secs()
+            );
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+            reconnecting = true;
+        }
+    })
+}
+
+type Messages = Pin<Box<dyn Stream<Item = Result<AsyncMessage, tokio_postgres::Error>> + Send>>;
+
+/// Open a connection and `LISTEN` on `channel`
+///
+/// The client must be kept alive for as long as messages are read.
+async fn connect(
+    config: &tokio_postgres::Config,
+    channel: &str,
+) -> Result<(Client, Messages), tokio_postgres::Error> {
+    let (client, mut connection) = config.connect(NoTls).await?;
+    let mut messages: Messages = Box::pin(stream::poll_fn(move |cx| connection.poll_message(cx)));
+
+    let query = format!("LISTEN \"{}\"", channel.replace('"', "\"\""));
+    // The connection only makes progress while its messages are polled
+    {
+        let listen = client.batch_execute(&query);
+        tokio::pin!(listen);
+        tokio::select! {
+            result = &mut listen => result?,
+            Some(Err(e)) = messages.next() => return Err(e),
+        }
+    }
+
+    Ok((client, messages))
+}
+
+/// Apply notifications until the connection drops, or `Ok` once the cache
+/// is gone
+async fn receive(
+    mut messages: Messages,
+    channel: &str,
+    cache: &Weak<TileCache>,
+) -> Result<(), String> {
+    while let Some(message) = messages.next().await {
+        let AsyncMessage::Notification(notification) = message.map_err(|e| e.to_string())? else {
+            continue;
+        };
+        let Some(cache) = cache.upgrade() else {
+            return Ok(());
+        };
+        match Invalidation::parse(notification.payload()) {
+            Some(invalidation) => {
+                tracing::debug!("Invalidating cached tiles: {:?}", invalidation);
+                invalidation.apply(&cache).await;
+            }
+            None => tracing::warn!(
+                "Ignoring invalid cache invalidation payload on '{}': {:?}",
+                channel,
+                notification.payload()
+            ),
+        }
+    }
+    Err("connection closed".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sources::postgres::TileCacheKey;
+    use crate::sources::{TileCompression, TileData, TileFormat};
+    use bytes::Bytes;
+
+    #[test]
+    fn test_parse_payload() {
+        assert_eq!(
+            Invalidation::parse("roads"),
+            Some(Invalidation::Source("roads".to_string()))
+        );
+        assert_eq!(
+            Invalidation::parse(" public.roads:14/8580/5737\n"),
+            Some(Invalidation::Tile {
+                source_id: "public.roads".to_string(),
+                z: 14,
+                x: 8580,
+                y: 5737,
+            })
+        );
+
+        for payload in [
+            "",
+            ":1/0/0",
+            "roads:",
+            "roads:1/0",
+            "roads:1/0/0/0",
+            "roads:1/2/0",
+            "roads:a/0/0",
+            "roads:31/0/0",
+        ] {
+            assert_eq!(Invalidation::parse(payload), None, "{:?}", payload);
+        }
+    }
+
+    fn key(source_id: &str, z: u8, x: u32, y: u32) -> TileCacheKey {
+        TileCacheKey {
+            source_id: source_id.into(),
+            z,
+            x,
+            y,
+            query: None,
+        }
+    }
+
+    fn tile() -> TileData {
+        TileData {
+            data: Bytes::from_static(b"tile"),
+            format: TileFormat::Pbf,
+            compression: TileCompression::None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tile_invalidation_fans_out() {
+        let cache = TileCache::new(1, 3600);
+        let cached = [
+            key("roads", 1, 1, 0),
+            // Below the changed tile
+            key("roads", 2, 2, 0),
+            key("roads", 2, 3, 1),
+            key("roads", 5, 31, 15),
+            // Outside of it, or above it
+            key("roads", 2, 1, 0),
+            key("roads", 2, 2, 2),
+            key("roads", 0, 0, 0),
+            key("water", 1, 1, 0),
+        ];
+        for key in &cached {
+            cache.insert(key.clone(), tile()).await;
+        }
+
+        Invalidation::parse("roads:1/1/0")
+            .unwrap()
+            .apply(&cache)
+            .await;
+
+        for (i, key) in cached.iter().enumerate() {
+            assert_eq!(cache.get(key).await.is_none(), i < 4, "{:?}", key);
+        }
+
+        Invalidation::parse("roads").unwrap().apply(&cache).await;
+        assert!(cache.get(&key("roads", 0, 0, 0)).await.is_none());
+        assert!(cache.get(&key("water", 1, 1, 0)).await.is_some());
+    }
+}
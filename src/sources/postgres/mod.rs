@@ -16,8 +16,17 @@
 //!
 //! Automatically discovers geometry columns and generates efficient tile queries
 //! using `ST_TileEnvelope` for spatial filtering that utilizes spatial indexes.
+//! With `[postgres.auto_publish]`, every geometry table in the listed schemas
+//! is published this way.
+//!
+//! # Cache Invalidation
+//!
+//! With `cache_invalidation_channel`, cached tiles are evicted when a
+//! `NOTIFY` names their source or tile; see [`listener`].
 
+pub mod auto_publish;
 mod cache;
+pub mod listener;
 #[cfg(feature = "raster")]
 mod outdb;
 mod pool;
@@ -32,6 +41,9 @@ pub use source::PostgresFunctionSource;
 pub use table::PostgresTableSource;
 
 use semver::Version;
+use tokio_postgres::error::SqlState;
+
+use crate::error::TileServerError;
 
 /// Minimum PostgreSQL version required (11.0.0)
 pub const MINIMUM_POSTGRES_VERSION: Version = Version::new(11, 0, 0);
@@ -41,3 +53,12 @@ pub const MINIMUM_POSTGIS_VERSION: Version = Version::new(3, 0, 0);
 
 /// PostGIS version that supports margin parameter in ST_TileEnvelope (3.1.0)
 pub const ST_TILE_ENVELOPE_MARGIN_VERSION: Version = Version::new(3, 1, 0);
+
+/// Error for a failed tile query, a timeout if `statement_timeout` cancelled it
+pub(crate) fn query_error(error: tokio_postgres::Error, context: String) -> TileServerError {
+    if error.code() == Some(&SqlState::QUERY_CANCELED) {
+        TileServerError::Timeout(context)
+    } else {
+        TileServerError::PostgresError(format!("{}: {}", context, error))
+    }
+}
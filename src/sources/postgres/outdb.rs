@@ -17,7 +17,7 @@ use tokio_postgres::types::Type;
 
 use crate::config::{ColorMapConfig, PostgresOutDbRasterConfig, ResamplingMethod, RescaleMode};
 use crate::error::{Result, TileServerError};
-use crate::sources::{TileCompression, TileData, TileFormat, TileMetadata, TileSource};
+use crate::sources::{TileCompression, TileData, TileFormat, TileMetadata, TileScheme, TileSource};
 
 use super::PostgresPool;
 
@@ -80,6 +80,9 @@ impl PostgresOutDbRasterSource {
                 [center_lon, center_lat, center_zoom]
             }),
             vector_layers: None,
+            scheme: TileScheme::Xyz,
+            overzoom: false,
+            encoding: None,
         };
 
         tracing::info!(
@@ -488,7 +491,7 @@ impl PostgresOutDbRasterSource {
 
 #[async_trait]
 impl TileSource for PostgresOutDbRasterSource {
-    async fn get_tile(&self, z: u8, x: u32, y: u32) -> Result<Option<TileData>> {
+    async fn get_xyz_tile(&self, z: u8, x: u32, y: u32) -> Result<Option<TileData>> {
         self.get_tile_with_params(z, x, y, 256, None, None).await
     }
 
@@ -496,6 +499,14 @@ impl TileSource for PostgresOutDbRasterSource {
         &self.metadata
     }
 
+    async fn health_check(&self) -> Result<()> {
+        self.pool.get().await.map(|_| ())
+    }
+
+    fn backend(&self) -> &'static str {
+        "postgres"
+    }
+
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
@@ -3,12 +3,15 @@
 use deadpool_postgres::{Manager, ManagerConfig, Object, Pool, RecyclingMethod, Timeouts};
 use semver::Version;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
+use tokio::task::JoinHandle;
 use tokio_postgres::types::Type;
 use tokio_postgres::NoTls;
 
 use crate::error::{Result, TileServerError};
 
+use super::{listener, TileCache};
 use super::{MINIMUM_POSTGIS_VERSION, MINIMUM_POSTGRES_VERSION, ST_TILE_ENVELOPE_MARGIN_VERSION};
 
 #[derive(Clone, Debug, Default)]
@@ -17,6 +20,8 @@ pub struct PoolSettings {
     pub wait_timeout_ms: u64,
     pub create_timeout_ms: u64,
     pub recycle_timeout_ms: u64,
+    /// `statement_timeout` of every connection in milliseconds, 0 for none
+    pub statement_timeout_ms: u64,
     pub pre_warm: bool,
 }
 
@@ -24,6 +29,8 @@ pub struct PoolSettings {
 pub struct PostgresPool {
     id: String,
     pool: Pool,
+    /// Connection settings, for connections made outside the pool
+    pg_config: tokio_postgres::Config,
     supports_tile_margin: bool,
     postgres_version: Version,
     postgis_version: Version,
@@ -37,10 +44,20 @@ impl PostgresPool {
         _ssl_key: Option<&PathBuf>,
         _ssl_root_cert: Option<&PathBuf>,
     ) -> Result<Self> {
-        let pg_config: tokio_postgres::Config = connection_string.parse().map_err(|e| {
+        let mut pg_config: tokio_postgres::Config = connection_string.parse().map_err(|e| {
             TileServerError::PostgresError(format!("Invalid connection string: {}", e))
         })?;
 
+        // Set on connect, so the server cancels slow tile queries itself
+        if settings.statement_timeout_ms > 0 {
+            let timeout = format!("-c statement_timeout={}", settings.statement_timeout_ms);
+            let options = match pg_config.get_options() {
+                Some(options) => format!("{} {}", options, timeout),
+                None => timeout,
+            };
+            pg_config.options(&options);
+        }
+
         let id = pg_config
             .get_dbname()
             .map(ToString::to_string)
@@ -50,7 +67,7 @@ impl PostgresPool {
             recycling_method: RecyclingMethod::Fast,
         };
 
-        let mgr = Manager::from_config(pg_config, NoTls, mgr_config);
+        let mgr = Manager::from_config(pg_config.clone(), NoTls, mgr_config);
 
         let timeouts = Timeouts {
             wait: Some(Duration::from_millis(settings.wait_timeout_ms)),
@@ -70,6 +87,7 @@ impl PostgresPool {
         let mut result = Self {
             id: id.clone(),
             pool,
+            pg_config,
             supports_tile_margin: false,
             postgres_version: Version::new(0, 0, 0),
             postgis_version: Version::new(0, 0, 0),
@@ -164,6 +182,15 @@ impl PostgresPool {
         Ok(())
     }
 
+    /// Spawn a task evicting cached tiles named by `NOTIFY`s on `channel`
+    pub fn spawn_invalidation_listener(
+        &self,
+        channel: &str,
+        cache: &Arc<TileCache>,
+    ) -> JoinHandle<()> {
+        listener::spawn(self.pg_config.clone(), channel.to_string(), cache)
+    }
+
     #[allow(dead_code)]
     pub fn id(&self) -> &str {
         &self.id
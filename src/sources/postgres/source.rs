@@ -2,12 +2,13 @@
 
 use async_trait::async_trait;
 use bytes::Bytes;
+use std::collections::BTreeMap;
 use std::sync::Arc;
-use tokio_postgres::types::{ToSql, Type};
+use tokio_postgres::types::{Json, ToSql, Type};
 
 use crate::config::PostgresFunctionConfig;
 use crate::error::{Result, TileServerError};
-use crate::sources::{TileCompression, TileData, TileFormat, TileMetadata, TileSource};
+use crate::sources::{TileCompression, TileData, TileFormat, TileMetadata, TileScheme, TileSource};
 
 use super::{PostgresPool, TileCache, TileCacheKey};
 
@@ -18,7 +19,7 @@ use super::{PostgresPool, TileCache, TileCacheKey};
 /// function_name(z integer, x integer, y integer) RETURNS bytea
 /// ```
 ///
-/// Or with query parameters:
+/// Or with query parameters, as `json` or `jsonb`:
 /// ```sql
 /// function_name(z integer, x integer, y integer, query json) RETURNS bytea
 /// ```
@@ -29,7 +30,9 @@ pub struct PostgresFunctionSource {
     schema: String,
     function: String,
     sql_query: String,
-    supports_query_params: bool,
+    /// Type of the function's query parameter argument, if it takes one
+    query_param_type: Option<Type>,
+    allowed_params: Option<Vec<String>>,
     cache: Option<Arc<TileCache>>,
 }
 
@@ -54,10 +57,10 @@ impl PostgresFunctionSource {
         let function_info =
             Self::get_function_info(&conn, &config.schema, &config.function).await?;
 
-        let (sql_query, supports_query_params) = Self::build_sql_query(
+        let sql_query = Self::build_sql_query(
             &config.schema,
             &config.function,
-            function_info.has_query_param,
+            function_info.query_param_type.as_ref(),
         );
 
         let metadata = TileMetadata {
@@ -79,6 +82,9 @@ impl PostgresFunctionSource {
                 [center_lon, center_lat, center_zoom]
             }),
             vector_layers: None,
+            scheme: TileScheme::Xyz,
+            overzoom: false,
+            encoding: None,
         };
 
         tracing::info!(
@@ -97,7 +103,8 @@ impl PostgresFunctionSource {
             schema: config.schema.clone(),
             function: config.function.clone(),
             sql_query,
-            supports_query_params,
+            query_param_type: function_info.query_param_type,
+            allowed_params: config.allowed_params.clone(),
             cache,
         })
     }
@@ -108,12 +115,9 @@ impl PostgresFunctionSource {
 
     #[allow(dead_code)]
     pub fn query_param_types(&self) -> Vec<tokio_postgres::types::Type> {
-        use tokio_postgres::types::Type;
-        if self.supports_query_params {
-            vec![Type::INT4, Type::INT4, Type::INT4, Type::JSON]
-        } else {
-            vec![Type::INT4, Type::INT4, Type::INT4]
-        }
+        let mut types = vec![Type::INT4, Type::INT4, Type::INT4];
+        types.extend(self.query_param_type.clone());
+        types
     }
 
     async fn get_function_info(
@@ -127,11 +131,9 @@ impl PostgresFunctionSource {
                 p.proname as name,
                 pg_catalog.pg_get_function_arguments(p.oid) as args,
                 CASE
-                    WHEN pg_catalog.pg_get_function_arguments(p.oid) LIKE '%json%'
-                        OR pg_catalog.pg_get_function_arguments(p.oid) LIKE '%jsonb%'
-                    THEN true
-                    ELSE false
-                END as has_query_param
+                    WHEN pg_catalog.pg_get_function_arguments(p.oid) LIKE '%jsonb%' THEN 'jsonb'
+                    WHEN pg_catalog.pg_get_function_arguments(p.oid) LIKE '%json%' THEN 'json'
+                END as query_param_type
             FROM pg_catalog.pg_proc p
             JOIN pg_catalog.pg_namespace n ON n.oid = p.pronamespace
             WHERE n.nspname = $1
@@ -153,40 +155,41 @@ impl PostgresFunctionSource {
                 ))
             })?;
 
-        let has_query_param: bool = row.get("has_query_param");
+        let query_param_type = match row.get::<_, Option<&str>>("query_param_type") {
+            Some("jsonb") => Some(Type::JSONB),
+            Some(_) => Some(Type::JSON),
+            None => None,
+        };
 
-        Ok(FunctionInfo { has_query_param })
+        Ok(FunctionInfo { query_param_type })
     }
 
-    fn build_sql_query(schema: &str, function: &str, has_query_param: bool) -> (String, bool) {
-        if has_query_param {
-            (
-                format!(
-                    "SELECT \"{}\".\"{}\"($1::integer, $2::integer, $3::integer, $4::json)",
-                    schema, function
-                ),
-                true,
-            )
-        } else {
-            (
-                format!(
-                    "SELECT \"{}\".\"{}\"($1::integer, $2::integer, $3::integer)",
-                    schema, function
-                ),
-                false,
-            )
+    fn build_sql_query(schema: &str, function: &str, query_param_type: Option<&Type>) -> String {
+        match query_param_type {
+            Some(param_type) => format!(
+                "SELECT \"{}\".\"{}\"($1::integer, $2::integer, $3::integer, $4::{})",
+                schema,
+                function,
+                param_type.name()
+            ),
+            None => format!(
+                "SELECT \"{}\".\"{}\"($1::integer, $2::integer, $3::integer)",
+                schema, function
+            ),
         }
     }
 
     #[allow(dead_code)]
     pub fn supports_query_params(&self) -> bool {
-        self.supports_query_params
+        self.query_param_type.is_some()
     }
 
     /// Get a tile with query parameters passed to the PostgreSQL function.
     ///
-    /// Query parameters are passed as a JSON object to the function's 4th parameter.
-    /// If the function doesn't support query params, they are ignored.
+    /// The parameters named in `allowed_params` (all of them if it is unset)
+    /// are passed as a JSON object to the function's 4th parameter, and are
+    /// part of the cache key. If the function doesn't support query params,
+    /// they are ignored.
     pub async fn get_tile_with_query_params(
         &self,
         z: u8,
@@ -203,78 +206,17 @@ impl PostgresFunctionSource {
             return Ok(None);
         }
 
-        let conn = self.pool.get().await?;
-
-        let param_types: &[Type] = if self.supports_query_params {
-            &[Type::INT4, Type::INT4, Type::INT4, Type::JSON]
-        } else {
-            &[Type::INT4, Type::INT4, Type::INT4]
+        let query_params = match self.query_param_type {
+            Some(_) => filter_query_params(self.allowed_params.as_deref(), query_params),
+            None => BTreeMap::new(),
         };
 
-        let prep_query = conn
-            .prepare_typed_cached(&self.sql_query, param_types)
-            .await
-            .map_err(|e| {
-                TileServerError::PostgresError(format!(
-                    "Failed to prepare query for {}.{}: {}",
-                    self.schema, self.function, e
-                ))
-            })?;
-
-        let tile_data: Option<Vec<u8>> = if self.supports_query_params {
-            let params: &[&(dyn ToSql + Sync)] =
-                &[&(z as i32), &(x as i32), &(y as i32), query_params];
-            conn.query_opt(&prep_query, params).await
-        } else {
-            conn.query_opt(&prep_query, &[&(z as i32), &(x as i32), &(y as i32)])
-                .await
-        }
-        .map_err(|e| {
-            TileServerError::PostgresError(format!(
-                "Failed to execute query for {}.{} at z={}, x={}, y={}: {}",
-                self.schema, self.function, z, x, y, e
-            ))
-        })?
-        .and_then(|row| row.get::<_, Option<Vec<u8>>>(0));
-
-        Ok(tile_data.map(|data| {
-            let compression = if data.len() >= 2 && data[0] == 0x1f && data[1] == 0x8b {
-                TileCompression::Gzip
-            } else {
-                TileCompression::None
-            };
-
-            TileData {
-                data: Bytes::from(data),
-                format: TileFormat::Pbf,
-                compression,
-            }
-        }))
-    }
-}
-
-/// Information about a PostgreSQL function.
-struct FunctionInfo {
-    has_query_param: bool,
-}
-
-#[async_trait]
-impl TileSource for PostgresFunctionSource {
-    async fn get_tile(&self, z: u8, x: u32, y: u32) -> Result<Option<TileData>> {
-        let max_tile = 1u32 << z;
-        if x >= max_tile || y >= max_tile {
-            return Err(TileServerError::InvalidCoordinates { z, x, y });
-        }
-
-        if z < self.metadata.minzoom || z > self.metadata.maxzoom {
-            return Ok(None);
-        }
-
         let cache_key = self.cache.as_ref().map(|_| TileCacheKey {
             source_id: self.metadata.id.clone().into(),
             z,
             x,
             y,
+            query: query_cache_key(&query_params),
         });
 
         if let (Some(cache), Some(key)) = (&self.cache, &cache_key) {
@@ -285,14 +227,8 @@ impl TileSource for PostgresFunctionSource {
 
         let conn = self.pool.get().await?;
 
-        let param_types: &[Type] = if self.supports_query_params {
-            &[Type::INT4, Type::INT4, Type::INT4, Type::JSON]
-        } else {
-            &[Type::INT4, Type::INT4, Type::INT4]
-        };
-
         let prep_query = conn
-            .prepare_typed_cached(&self.sql_query, param_types)
+            .prepare_typed_cached(&self.sql_query, &self.query_param_types())
             .await
             .map_err(|e| {
                 TileServerError::PostgresError(format!(
@@ -301,20 +237,23 @@ impl TileSource for PostgresFunctionSource {
                 ))
             })?;
 
-        let tile_data: Option<Vec<u8>> = if self.supports_query_params {
-            let empty_json = serde_json::json!({});
+        let tile_data: Option<Vec<u8>> = if self.query_param_type.is_some() {
+            let query_json = Json(&query_params);
             let params: &[&(dyn ToSql + Sync)] =
-                &[&(z as i32), &(x as i32), &(y as i32), &empty_json];
+                &[&(z as i32), &(x as i32), &(y as i32), &query_json];
             conn.query_opt(&prep_query, params).await
         } else {
             conn.query_opt(&prep_query, &[&(z as i32), &(x as i32), &(y as i32)])
                 .await
         }
         .map_err(|e| {
-            TileServerError::PostgresError(format!(
-                "Failed to execute query for {}.{} at z={}, x={}, y={}: {}",
-                self.schema, self.function, z, x, y, e
-            ))
+            super::query_error(
+                e,
+                format!(
+                    "Failed to execute query for {}.{} at z={}, x={}, y={}",
+                    self.schema, self.function, z, x, y
+                ),
+            )
         })?
         .and_then(|row| row.get::<_, Option<Vec<u8>>>(0));
 
@@ -338,11 +277,54 @@ impl TileSource for PostgresFunctionSource {
 
         Ok(result)
     }
+}
+
+/// Query parameters named in `allowed`, or all of them if it is unset,
+/// sorted by name
+fn filter_query_params(
+    allowed: Option<&[String]>,
+    params: &serde_json::Value,
+) -> BTreeMap<String, serde_json::Value> {
+    let Some(params) = params.as_object() else {
+        return BTreeMap::new();
+    };
+    params
+        .iter()
+        .filter(|(name, _)| allowed.map_or(true, |allowed| allowed.contains(name)))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect()
+}
+
+/// Cache key part for the query parameters, `None` without any so that
+/// plain tile requests share their entry
+fn query_cache_key(params: &BTreeMap<String, serde_json::Value>) -> Option<Arc<str>> {
+    (!params.is_empty()).then(|| serde_json::to_string(params).unwrap_or_default().into())
+}
+
+/// Information about a PostgreSQL function.
+struct FunctionInfo {
+    query_param_type: Option<Type>,
+}
+
+#[async_trait]
+impl TileSource for PostgresFunctionSource {
+    async fn get_xyz_tile(&self, z: u8, x: u32, y: u32) -> Result<Option<TileData>> {
+        self.get_tile_with_query_params(z, x, y, &serde_json::Value::Null)
+            .await
+    }
 
     fn metadata(&self) -> &TileMetadata {
         &self.metadata
     }
 
+    async fn health_check(&self) -> Result<()> {
+        self.pool.get().await.map(|_| ())
+    }
+
+    fn backend(&self) -> &'static str {
+        "postgres"
+    }
+
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
@@ -354,32 +336,71 @@ mod tests {
 
     #[test]
     fn test_build_sql_query_without_params() {
-        let (sql, has_params) =
-            PostgresFunctionSource::build_sql_query("public", "my_tiles", false);
+        let sql = PostgresFunctionSource::build_sql_query("public", "my_tiles", None);
         assert_eq!(
             sql,
             "SELECT \"public\".\"my_tiles\"($1::integer, $2::integer, $3::integer)"
         );
-        assert!(!has_params);
     }
 
     #[test]
     fn test_build_sql_query_with_params() {
-        let (sql, has_params) = PostgresFunctionSource::build_sql_query("public", "my_tiles", true);
+        let sql = PostgresFunctionSource::build_sql_query("public", "my_tiles", Some(&Type::JSON));
         assert_eq!(
             sql,
             "SELECT \"public\".\"my_tiles\"($1::integer, $2::integer, $3::integer, $4::json)"
         );
-        assert!(has_params);
+
+        let sql = PostgresFunctionSource::build_sql_query("public", "my_tiles", Some(&Type::JSONB));
+        assert!(sql.ends_with("$4::jsonb)"));
     }
 
     #[test]
     fn test_build_sql_query_escapes_schema_and_function() {
-        let (sql, _) = PostgresFunctionSource::build_sql_query("my_schema", "tile_func", false);
+        let sql = PostgresFunctionSource::build_sql_query("my_schema", "tile_func", None);
         assert!(sql.contains("\"my_schema\""));
         assert!(sql.contains("\"tile_func\""));
     }
 
+    #[test]
+    fn test_filter_query_params() {
+        let params = serde_json::json!({
+            "date": "2024-01-01",
+            "category": "parks",
+            "debug": "1"
+        });
+        let allowed = vec!["category".to_string(), "date".to_string()];
+
+        let filtered = filter_query_params(Some(&allowed), &params);
+        assert_eq!(
+            serde_json::to_string(&filtered).unwrap(),
+            r#"{"category":"parks","date":"2024-01-01"}"#
+        );
+        assert_eq!(filter_query_params(None, &params).len(), 3);
+        assert!(filter_query_params(Some(&[]), &params).is_empty());
+        assert!(filter_query_params(None, &serde_json::Value::Null).is_empty());
+    }
+
+    #[test]
+    fn test_query_cache_key() {
+        let key = |params: serde_json::Value| {
+            let allowed = vec!["category".to_string()];
+            query_cache_key(&filter_query_params(Some(&allowed), &params))
+        };
+
+        assert_eq!(key(serde_json::json!({})), None);
+        // Parameters that are not allowed don't split the cache
+        assert_eq!(key(serde_json::json!({ "debug": "1" })), None);
+        assert_ne!(
+            key(serde_json::json!({ "category": "parks" })),
+            key(serde_json::json!({ "category": "water" }))
+        );
+        assert_eq!(
+            key(serde_json::json!({ "category": "parks", "debug": "1" })),
+            key(serde_json::json!({ "category": "parks" }))
+        );
+    }
+
     #[test]
     fn test_tile_metadata_defaults() {
         let config = PostgresFunctionConfig {
@@ -392,6 +413,8 @@ mod tests {
             minzoom: 0,
             maxzoom: 14,
             bounds: Some([-180.0, -85.0, 180.0, 85.0]),
+            allowed_params: None,
+            resilience: None,
         };
 
         // Test that center is calculated from bounds
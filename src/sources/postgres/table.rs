@@ -2,12 +2,16 @@
 
 use async_trait::async_trait;
 use bytes::Bytes;
+use std::collections::HashMap;
 use std::sync::Arc;
 use tokio_postgres::types::Type;
 
 use crate::config::PostgresTableConfig;
 use crate::error::{Result, TileServerError};
-use crate::sources::{TileCompression, TileData, TileFormat, TileMetadata, TileSource};
+use crate::integrity::IntegrityIssue;
+use crate::sources::{
+    buffer_units, TileCompression, TileData, TileFormat, TileMetadata, TileScheme, TileSource,
+};
 
 use super::{PostgresPool, TileCache, TileCacheKey};
 
@@ -21,6 +25,8 @@ pub struct TableInfo {
     pub geometry_type: String,
     pub id_column: Option<String>,
     pub properties: Vec<String>,
+    /// PostgreSQL `data_type` of the columns usable as properties
+    pub column_types: HashMap<String, String>,
     pub bounds: Option<[f64; 4]>,
     pub has_spatial_index: bool,
 }
@@ -31,13 +37,8 @@ pub struct PostgresTableSource {
     metadata: TileMetadata,
     table_info: TableInfo,
     tile_query: String,
-    #[allow(dead_code)]
-    extent: u32,
-    #[allow(dead_code)]
-    buffer: u32,
-    #[allow(dead_code)]
-    max_features: Option<u32>,
-    #[allow(dead_code)]
+    /// Kept to rebuild the tile query for a requested buffer
+    config: PostgresTableConfig,
     supports_tile_margin: bool,
     cache: Option<Arc<TileCache>>,
 }
@@ -54,6 +55,15 @@ impl std::fmt::Debug for PostgresTableSource {
     }
 }
 
+/// TileJSON field type for a PostgreSQL `data_type`
+fn field_type(data_type: &str) -> &'static str {
+    match data_type {
+        "smallint" | "integer" | "bigint" | "real" | "double precision" | "numeric" => "Number",
+        "boolean" => "Boolean",
+        _ => "String",
+    }
+}
+
 impl PostgresTableSource {
     pub async fn new(
         pool: Arc<PostgresPool>,
@@ -92,7 +102,10 @@ impl PostgresTableSource {
                 let center_zoom = ((config.minzoom as f64 + config.maxzoom as f64) / 2.0).floor();
                 [center_lon, center_lat, center_zoom]
             }),
-            vector_layers: None,
+            vector_layers: Some(Self::vector_layers(&table_info, config)),
+            scheme: TileScheme::Xyz,
+            overzoom: false,
+            encoding: None,
         };
 
         tracing::info!(
@@ -112,9 +125,7 @@ impl PostgresTableSource {
             metadata,
             table_info,
             tile_query,
-            extent: config.extent,
-            buffer: config.buffer,
-            max_features: config.max_features,
+            config: config.clone(),
             supports_tile_margin,
             cache,
         })
@@ -137,10 +148,11 @@ impl PostgresTableSource {
         let (srid, geometry_type) =
             Self::get_geometry_info(conn, &config.schema, &config.table, &geometry_column).await?;
 
-        let properties = if let Some(ref props) = config.properties {
-            props.clone()
-        } else {
-            Self::discover_properties(conn, &config.schema, &config.table, &geometry_column).await?
+        let columns =
+            Self::discover_columns(conn, &config.schema, &config.table, &geometry_column).await?;
+        let properties = match config.properties {
+            Some(ref props) => props.clone(),
+            None => columns.iter().map(|(name, _)| name.clone()).collect(),
         };
 
         let has_spatial_index =
@@ -152,7 +164,10 @@ impl PostgresTableSource {
         } else {
             Self::estimate_bounds(conn, &config.schema, &config.table, &geometry_column, srid)
                 .await
-                .ok()
+                .unwrap_or_else(|e| {
+                    tracing::warn!("{}", e);
+                    None
+                })
         };
 
         Ok(TableInfo {
@@ -163,6 +178,7 @@ impl PostgresTableSource {
             geometry_type,
             id_column: config.id_column.clone(),
             properties,
+            column_types: columns.into_iter().collect(),
             bounds,
             has_spatial_index,
         })
@@ -222,14 +238,15 @@ impl PostgresTableSource {
             })
     }
 
-    async fn discover_properties(
+    /// Columns that can be tile properties, with their `data_type`
+    async fn discover_columns(
         conn: &deadpool_postgres::Object,
         schema: &str,
         table: &str,
         geometry_column: &str,
-    ) -> Result<Vec<String>> {
+    ) -> Result<Vec<(String, String)>> {
         let query = r#"
-            SELECT column_name::text
+            SELECT column_name::text, data_type::text
             FROM information_schema.columns
             WHERE table_schema = $1 
               AND table_name = $2
@@ -250,7 +267,7 @@ impl PostgresTableSource {
                 TileServerError::PostgresError(format!("Failed to discover properties: {}", e))
             })?;
 
-        Ok(rows.iter().map(|row| row.get(0)).collect())
+        Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
     }
 
     async fn check_spatial_index(
@@ -285,52 +302,126 @@ impl PostgresTableSource {
             })
     }
 
+    /// Bounds in WGS84 from the planner statistics, or by scanning the
+    /// table when it has never been analyzed
     async fn estimate_bounds(
         conn: &deadpool_postgres::Object,
         schema: &str,
         table: &str,
         geometry_column: &str,
         srid: i32,
-    ) -> Result<[f64; 4]> {
-        let query = if srid == 4326 {
-            format!(
-                r#"
-                SELECT 
-                    ST_XMin(ext)::float8, ST_YMin(ext)::float8,
-                    ST_XMax(ext)::float8, ST_YMax(ext)::float8
-                FROM (
-                    SELECT ST_EstimatedExtent('{}', '{}', '{}') AS ext
-                ) sub
-                "#,
-                schema, table, geometry_column
-            )
-        } else {
-            format!(
-                r#"
-                SELECT 
-                    ST_XMin(ext)::float8, ST_YMin(ext)::float8,
-                    ST_XMax(ext)::float8, ST_YMax(ext)::float8
-                FROM (
-                    SELECT ST_Transform(ST_EstimatedExtent('{}', '{}', '{}'), 4326) AS ext
-                ) sub
-                "#,
-                schema, table, geometry_column
-            )
-        };
+    ) -> Result<Option<[f64; 4]>> {
+        let estimated = format!(
+            "ST_EstimatedExtent('{}', '{}', '{}')",
+            schema, table, geometry_column
+        );
+        let scanned = format!(
+            r#"(SELECT ST_Extent("{}") FROM "{}"."{}")"#,
+            geometry_column, schema, table
+        );
 
-        conn.query_one(&query, &[])
-            .await
-            .map(|row| {
-                [
-                    row.get::<_, f64>(0),
-                    row.get::<_, f64>(1),
-                    row.get::<_, f64>(2),
-                    row.get::<_, f64>(3),
-                ]
+        // ST_EstimatedExtent fails or returns NULL without statistics
+        match Self::query_bounds(conn, &estimated, srid).await {
+            Ok(Some(bounds)) => return Ok(Some(bounds)),
+            Ok(None) => {}
+            Err(e) => tracing::debug!(
+                "No estimated extent for {}.{}, scanning the table: {}",
+                schema,
+                table,
+                e
+            ),
+        }
+        Self::query_bounds(conn, &scanned, srid).await.map_err(|e| {
+            TileServerError::PostgresError(format!(
+                "Failed to estimate bounds of {}.{}: {}",
+                schema, table, e
+            ))
+        })
+    }
+
+    /// Transform the `box2d` returned by `extent` to WGS84 bounds
+    async fn query_bounds(
+        conn: &deadpool_postgres::Object,
+        extent: &str,
+        srid: i32,
+    ) -> std::result::Result<Option<[f64; 4]>, tokio_postgres::Error> {
+        let query = format!(
+            r#"
+            SELECT
+                ST_XMin(ext)::float8, ST_YMin(ext)::float8,
+                ST_XMax(ext)::float8, ST_YMax(ext)::float8
+            FROM (
+                SELECT ST_Transform(ST_SetSRID({}::geometry, {}), 4326) AS ext
+            ) sub
+            "#,
+            extent, srid
+        );
+
+        let row = conn.query_one(&query, &[]).await?;
+        let values: Option<Vec<f64>> = (0..4).map(|i| row.get::<_, Option<f64>>(i)).collect();
+        Ok(values.map(|v| [v[0], v[1], v[2], v[3]]))
+    }
+
+    /// TileJSON `vector_layers` for the single layer the tile query writes
+    fn vector_layers(table_info: &TableInfo, config: &PostgresTableConfig) -> serde_json::Value {
+        let fields: serde_json::Map<String, serde_json::Value> = table_info
+            .properties
+            .iter()
+            .map(|name| {
+                let data_type = table_info.column_types.get(name).map_or("", String::as_str);
+                (name.clone(), field_type(data_type).into())
             })
+            .collect();
+
+        serde_json::json!([{
+            "id": config.id,
+            "fields": fields,
+            "minzoom": config.minzoom,
+            "maxzoom": config.maxzoom,
+        }])
+    }
+
+    /// Run a tile query for `z/x/y`
+    async fn query_tile(&self, query: &str, z: u8, x: u32, y: u32) -> Result<Option<TileData>> {
+        let conn = self.pool.get().await?;
+
+        let prep_query = conn
+            .prepare_typed_cached(query, &[Type::INT4, Type::INT4, Type::INT4])
+            .await
             .map_err(|e| {
-                TileServerError::PostgresError(format!("Failed to estimate bounds: {}", e))
-            })
+                TileServerError::PostgresError(format!(
+                    "Failed to prepare tile query for {}.{}: {}",
+                    self.table_info.schema, self.table_info.table, e
+                ))
+            })?;
+
+        let tile_data: Option<Vec<u8>> = conn
+            .query_opt(&prep_query, &[&(z as i32), &(x as i32), &(y as i32)])
+            .await
+            .map_err(|e| {
+                super::query_error(
+                    e,
+                    format!(
+                        "Failed to execute tile query for {}.{} at z={}, x={}, y={}",
+                        self.table_info.schema, self.table_info.table, z, x, y
+                    ),
+                )
+            })?
+            .and_then(|row| row.get::<_, Option<Vec<u8>>>(0));
+
+        Ok(tile_data.filter(|d| !d.is_empty()).map(|data| {
+            let compression = if data.len() >= 2 && data[0] == 0x1f && data[1] == 0x8b {
+                TileCompression::Gzip
+            } else {
+                TileCompression::None
+            };
+
+            TileData {
+                data: Bytes::from(data),
+                format: TileFormat::Pbf,
+                compression,
+            }
+        }))
     }
 
     fn build_tile_query(
@@ -430,7 +521,7 @@ impl PostgresTableSource {
 
 #[async_trait]
 impl TileSource for PostgresTableSource {
-    async fn get_tile(&self, z: u8, x: u32, y: u32) -> Result<Option<TileData>> {
+    async fn get_xyz_tile(&self, z: u8, x: u32, y: u32) -> Result<Option<TileData>> {
         let max_tile = 1u32 << z;
         if x >= max_tile || y >= max_tile {
             return Err(TileServerError::InvalidCoordinates { z, x, y });
@@ -445,6 +536,7 @@ impl TileSource for PostgresTableSource {
             z,
             x,
             y,
+            query: None,
         });
 
         if let (Some(cache), Some(key)) = (&self.cache, &cache_key) {
@@ -453,42 +545,7 @@ impl TileSource for PostgresTableSource {
             }
         }
 
-        let conn = self.pool.get().await?;
-
-        let prep_query = conn
-            .prepare_typed_cached(&self.tile_query, &[Type::INT4, Type::INT4, Type::INT4])
-            .await
-            .map_err(|e| {
-                TileServerError::PostgresError(format!(
-                    "Failed to prepare tile query for {}.{}: {}",
-                    self.table_info.schema, self.table_info.table, e
-                ))
-            })?;
-
-        let tile_data: Option<Vec<u8>> = conn
-            .query_opt(&prep_query, &[&(z as i32), &(x as i32), &(y as i32)])
-            .await
-            .map_err(|e| {
-                TileServerError::PostgresError(format!(
-                    "Failed to execute tile query for {}.{} at z={}, x={}, y={}: {}",
-                    self.table_info.schema, self.table_info.table, z, x, y, e
-                ))
-            })?
-            .and_then(|row| row.get::<_, Option<Vec<u8>>>(0));
-
-        let result = tile_data.filter(|d| !d.is_empty()).map(|data| {
-            let compression = if data.len() >= 2 && data[0] == 0x1f && data[1] == 0x8b {
-                TileCompression::Gzip
-            } else {
-                TileCompression::None
-            };
-
-            TileData {
-                data: Bytes::from(data),
-                format: TileFormat::Pbf,
-                compression,
-            }
-        });
+        let result = self.query_tile(&self.tile_query, z, x, y).await?;
 
         if let (Some(cache), Some(key), Some(tile)) = (&self.cache, cache_key, &result) {
             cache.insert(key, tile.clone()).await;
@@ -497,10 +554,104 @@ impl TileSource for PostgresTableSource {
         Ok(result)
     }
 
+    /// Runs the tile query with the requested buffer instead of the
+    /// configured one, bypassing the cache since its keys don't include it
+    async fn get_tile_with_buffer(
+        &self,
+        z: u8,
+        x: u32,
+        y: u32,
+        buffer: Option<u32>,
+    ) -> Result<Option<TileData>> {
+        let Some(buffer) = buffer else {
+            return self.get_tile(z, x, y).await;
+        };
+        let max_tile = 1u32 << z;
+        if x >= max_tile || y >= max_tile {
+            return Err(TileServerError::InvalidCoordinates { z, x, y });
+        }
+        if z < self.metadata.minzoom || z > self.metadata.maxzoom {
+            return Ok(None);
+        }
+
+        let config = PostgresTableConfig {
+            buffer: buffer_units(buffer, self.config.extent),
+            ..self.config.clone()
+        };
+        let query = Self::build_tile_query(&self.table_info, &config, self.supports_tile_margin);
+        self.query_tile(&query, z, x, y).await
+    }
+
     fn metadata(&self) -> &TileMetadata {
         &self.metadata
     }
 
+    async fn health_check(&self) -> Result<()> {
+        self.pool.get().await.map(|_| ())
+    }
+
+    /// Look for a missing spatial index and for a full table scan in the
+    /// plan of the tile query at the center of the source at `maxzoom`
+    async fn check_integrity(&self) -> Result<Vec<IntegrityIssue>> {
+        let TableInfo {
+            schema,
+            table,
+            geometry_column,
+            ..
+        } = &self.table_info;
+        let mut issues = Vec::new();
+        if !self.table_info.has_spatial_index {
+            issues.push(IntegrityIssue::new(
+                "spatial_index",
+                format!(
+                    "No spatial index on {}.{} ({})",
+                    schema, table, geometry_column
+                ),
+            ));
+        }
+
+        let z = self.metadata.maxzoom;
+        let [lon, lat, _] = self.metadata.center.unwrap_or([0.0, 0.0, 0.0]);
+        let (x, y) = crate::ogcapi::lon_lat_to_tile(lon, lat, z);
+
+        let conn = self.pool.get().await?;
+        let explain = conn
+            .prepare_typed(
+                &format!("EXPLAIN {}", self.tile_query),
+                &[Type::INT4, Type::INT4, Type::INT4],
+            )
+            .await
+            .map_err(|e| super::query_error(e, "Failed to prepare EXPLAIN of tile query".into()))?;
+        let plan: Vec<String> = conn
+            .query(&explain, &[&(z as i32), &(x as i32), &(y as i32)])
+            .await
+            .map_err(|e| super::query_error(e, "Failed to EXPLAIN tile query".into()))?
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+
+        if let Some(scan) = plan
+            .iter()
+            .find(|line| line.contains("Seq Scan on") && line.contains(table.as_str()))
+        {
+            issues.push(IntegrityIssue::new(
+                "spatial_index",
+                format!(
+                    "Tile query for {}/{}/{} scans the whole table: {}",
+                    z,
+                    x,
+                    y,
+                    scan.trim()
+                ),
+            ));
+        }
+        Ok(issues)
+    }
+
+    fn backend(&self) -> &'static str {
+        "postgres"
+    }
+
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
@@ -520,6 +671,10 @@ mod tests {
             geometry_type: "POINT".to_string(),
             id_column: Some("id".to_string()),
             properties: vec!["name".to_string(), "category".to_string()],
+            column_types: HashMap::from([
+                ("id".to_string(), "integer".to_string()),
+                ("name".to_string(), "character varying".to_string()),
+            ]),
             bounds: Some([8.0, 47.0, 9.0, 48.0]),
             has_spatial_index: true,
         }
@@ -542,6 +697,7 @@ mod tests {
             extent: 4096,
             buffer: 64,
             max_features: None,
+            resilience: None,
         }
     }
 
@@ -603,4 +759,27 @@ mod tests {
         assert!(!query.contains(r#""name""#));
         assert!(!query.contains(r#""id"::bigint"#));
     }
+
+    #[test]
+    fn test_vector_layers() {
+        let table_info = make_table_info();
+        let mut config = make_config();
+        config.properties = None;
+
+        let layers = PostgresTableSource::vector_layers(&table_info, &config);
+        assert_eq!(
+            layers,
+            serde_json::json!([{
+                "id": "test_layer",
+                "fields": { "name": "String", "category": "String" },
+                "minzoom": 0,
+                "maxzoom": 14,
+            }])
+        );
+
+        assert_eq!(field_type("bigint"), "Number");
+        assert_eq!(field_type("double precision"), "Number");
+        assert_eq!(field_type("boolean"), "Boolean");
+        assert_eq!(field_type("jsonb"), "String");
+    }
 }
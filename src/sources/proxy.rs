@@ -0,0 +1,566 @@
+//! XYZ proxy tile source
+//!
+//! Fronts an upstream HTTP tile server through a `{z}/{x}/{y}` URL template,
+//! so its tiles can be served with this server's caching headers, CORS and
+//! API routes. Tile bytes, format and compression are passed through as-is.
+
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING};
+use reqwest::StatusCode;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+use crate::config::SourceConfig;
+use crate::error::{Result, TileServerError};
+use crate::sources::{flip_y, TileCompression, TileData, TileFormat, TileMetadata, TileSource};
+
+/// Outcome of a failed upstream request
+#[derive(Debug)]
+enum FetchError {
+    /// Upstream 5xx, 429 or connection failure, worth retrying
+    Retryable(String),
+    /// Any other upstream failure
+    Fatal(String),
+}
+
+/// Tile source that proxies an upstream XYZ tile server
+pub struct ProxySource {
+    /// Pooled HTTP client carrying the configured upstream headers
+    client: reqwest::Client,
+    /// Upstream URL template with `{z}`, `{x}` and `{y}` (or `{-y}`) placeholders
+    url_template: String,
+    metadata: TileMetadata,
+    /// Limits the number of in-flight upstream requests
+    permits: Semaphore,
+    retries: u32,
+    retry_backoff: Duration,
+}
+
+impl ProxySource {
+    /// Create a new proxy source from configuration
+    ///
+    /// If `tilejson_url` is set, the upstream TileJSON is fetched to fill in
+    /// metadata not given in the configuration.
+    pub async fn from_config(config: &SourceConfig) -> Result<Self> {
+        let proxy = &config.proxy;
+        let url_template = config.path.clone();
+
+        if !url_template.contains("{z}")
+            || !url_template.contains("{x}")
+            || !(url_template.contains("{y}") || url_template.contains("{-y}"))
+        {
+            return Err(TileServerError::ConfigError(format!(
+                "Proxy source {} URL must contain {{z}}, {{x}} and {{y}} placeholders: {}",
+                config.id, url_template
+            )));
+        }
+
+        let mut headers = HeaderMap::new();
+        // Ask for compressed tiles; they are passed through to clients that accept gzip
+        headers.insert(ACCEPT_ENCODING, HeaderValue::from_static("gzip"));
+        for (name, value) in &proxy.headers {
+            let name = HeaderName::from_str(name).map_err(|e| {
+                TileServerError::ConfigError(format!("Invalid proxy header name {}: {}", name, e))
+            })?;
+            let value = HeaderValue::from_str(value).map_err(|e| {
+                TileServerError::ConfigError(format!("Invalid proxy header value: {}", e))
+            })?;
+            headers.insert(name, value);
+        }
+
+        let client = reqwest::Client::builder()
+            .user_agent("tileserver-rs/0.1.0")
+            .default_headers(headers)
+            .timeout(Duration::from_millis(proxy.timeout_ms))
+            .build()
+            .map_err(|e| {
+                TileServerError::ConfigError(format!("Failed to create HTTP client: {}", e))
+            })?;
+
+        let tilejson = match proxy.tilejson_url {
+            Some(ref url) => Some(fetch_tilejson(&client, url).await?),
+            None => None,
+        };
+        let metadata = build_metadata(config, tilejson.as_ref());
+
+        tracing::info!(
+            "Loaded proxy source '{}': {} (zoom {}-{}, format {:?})",
+            config.id,
+            url_template,
+            metadata.minzoom,
+            metadata.maxzoom,
+            metadata.format
+        );
+
+        Ok(Self {
+            client,
+            url_template,
+            metadata,
+            permits: Semaphore::new(proxy.max_concurrency.max(1)),
+            retries: proxy.retries,
+            retry_backoff: Duration::from_millis(proxy.retry_backoff_ms),
+        })
+    }
+
+    /// Build the upstream URL for an XYZ tile, whose coordinates are in range
+    fn tile_url(&self, z: u8, x: u32, y: u32) -> String {
+        self.url_template
+            .replace("{z}", &z.to_string())
+            .replace("{x}", &x.to_string())
+            .replace("{y}", &y.to_string())
+            .replace("{-y}", &flip_y(z, y).unwrap_or_default().to_string())
+    }
+
+    /// Make a single upstream request
+    async fn fetch(&self, url: &str) -> std::result::Result<Option<TileData>, FetchError> {
+        let _permit = self
+            .permits
+            .acquire()
+            .await
+            .map_err(|e| FetchError::Fatal(format!("Proxy source closed: {}", e)))?;
+
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| FetchError::Retryable(format!("Request to {} failed: {}", url, e)))?;
+
+        let status = response.status();
+        if status == StatusCode::NOT_FOUND || status == StatusCode::NO_CONTENT {
+            return Ok(None);
+        }
+        if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(FetchError::Retryable(format!(
+                "{} returned {}",
+                url, status
+            )));
+        }
+        if !status.is_success() {
+            return Err(FetchError::Fatal(format!("{} returned {}", url, status)));
+        }
+
+        let encoding = response
+            .headers()
+            .get(CONTENT_ENCODING)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_ascii_lowercase);
+        let format = match self.metadata.format {
+            TileFormat::Unknown => response
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .map_or(TileFormat::Unknown, format_from_content_type),
+            format => format,
+        };
+
+        let data = response.bytes().await.map_err(|e| {
+            FetchError::Retryable(format!("Failed to read response from {}: {}", url, e))
+        })?;
+        if data.is_empty() {
+            return Ok(None);
+        }
+
+        let compression = match encoding.as_deref() {
+            Some("gzip") => TileCompression::Gzip,
+            Some("br") => TileCompression::Brotli,
+            Some("zstd") => TileCompression::Zstd,
+            // Some upstreams serve gzip tiles without a Content-Encoding header
+            _ if data.starts_with(&[0x1f, 0x8b]) => TileCompression::Gzip,
+            _ => TileCompression::None,
+        };
+
+        Ok(Some(TileData {
+            data,
+            format,
+            compression,
+        }))
+    }
+}
+
+#[async_trait]
+impl TileSource for ProxySource {
+    async fn get_xyz_tile(&self, z: u8, x: u32, y: u32) -> Result<Option<TileData>> {
+        // Validate coordinates
+        let max_tile = 1u32 << z;
+        if x >= max_tile || y >= max_tile {
+            return Err(TileServerError::InvalidCoordinates { z, x, y });
+        }
+
+        // Check zoom bounds
+        if z < self.metadata.minzoom || z > self.metadata.maxzoom {
+            return Ok(None);
+        }
+
+        let url = self.tile_url(z, x, y);
+
+        let mut attempt = 0;
+        loop {
+            match self.fetch(&url).await {
+                Ok(tile) => return Ok(tile),
+                Err(FetchError::Retryable(message)) if attempt < self.retries => {
+                    let delay = self.retry_backoff * 2u32.saturating_pow(attempt);
+                    attempt += 1;
+                    tracing::warn!(
+                        "Proxy source '{}': {} (retry {}/{} in {:?})",
+                        self.metadata.id,
+                        message,
+                        attempt,
+                        self.retries,
+                        delay
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                Err(FetchError::Retryable(message) | FetchError::Fatal(message)) => {
+                    return Err(TileServerError::UpstreamError(message));
+                }
+            }
+        }
+    }
+
+    fn metadata(&self) -> &TileMetadata {
+        &self.metadata
+    }
+
+    fn backend(&self) -> &'static str {
+        "proxy"
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+/// Fetch and parse the upstream TileJSON
+async fn fetch_tilejson(client: &reqwest::Client, url: &str) -> Result<serde_json::Value> {
+    let response = client.get(url).send().await.map_err(|e| {
+        TileServerError::MetadataError(format!("Failed to fetch TileJSON {}: {}", url, e))
+    })?;
+    if !response.status().is_success() {
+        return Err(TileServerError::MetadataError(format!(
+            "Failed to fetch TileJSON {}: {}",
+            url,
+            response.status()
+        )));
+    }
+
+    let body = response.bytes().await.map_err(|e| {
+        TileServerError::MetadataError(format!("Failed to read TileJSON {}: {}", url, e))
+    })?;
+    serde_json::from_slice(&body).map_err(|e| {
+        TileServerError::MetadataError(format!("Failed to parse TileJSON {}: {}", url, e))
+    })
+}
+
+/// Merge configured metadata with the upstream TileJSON, configuration first
+fn build_metadata(config: &SourceConfig, tilejson: Option<&serde_json::Value>) -> TileMetadata {
+    let proxy = &config.proxy;
+    let field = |key: &str| tilejson.and_then(|json| json.get(key));
+    let string_field = |key: &str| field(key).and_then(|v| v.as_str()).map(str::to_string);
+    let numbers = |key: &str| -> Option<Vec<f64>> {
+        field(key)?.as_array()?.iter().map(|v| v.as_f64()).collect()
+    };
+
+    let format = proxy
+        .format
+        .clone()
+        .or_else(|| string_field("format"))
+        .or_else(|| template_extension(&config.path).map(str::to_string))
+        .map_or(TileFormat::Unknown, |f| {
+            TileFormat::from_str(&f).unwrap_or(TileFormat::Unknown)
+        });
+
+    let minzoom = proxy
+        .minzoom
+        .or_else(|| field("minzoom")?.as_u64().map(|z| z.min(30) as u8))
+        .unwrap_or(0);
+    let maxzoom = proxy
+        .maxzoom
+        .or_else(|| field("maxzoom")?.as_u64().map(|z| z.min(30) as u8))
+        .unwrap_or(22);
+
+    let bounds = proxy.bounds.or_else(|| {
+        numbers("bounds")
+            .filter(|b| b.len() == 4)
+            .map(|b| [b[0], b[1], b[2], b[3]])
+    });
+
+    // Use the upstream center, otherwise calculate from bounds
+    let center = numbers("center")
+        .filter(|c| c.len() >= 3)
+        .map(|c| [c[0], c[1], c[2]])
+        .or_else(|| {
+            bounds.map(|b| {
+                [
+                    (b[0] + b[2]) / 2.0,
+                    (b[1] + b[3]) / 2.0,
+                    ((minzoom as f64 + maxzoom as f64) / 2.0).floor(),
+                ]
+            })
+        });
+
+    TileMetadata {
+        id: config.id.clone(),
+        name: config
+            .name
+            .clone()
+            .or_else(|| string_field("name"))
+            .unwrap_or_else(|| config.id.clone()),
+        description: string_field("description"),
+        attribution: config
+            .attribution
+            .clone()
+            .or_else(|| string_field("attribution")),
+        format,
+        minzoom,
+        maxzoom,
+        bounds,
+        center,
+        vector_layers: field("vector_layers").cloned(),
+        scheme: config.scheme,
+        overzoom: config.overzoom,
+        encoding: None,
+    }
+}
+
+/// File extension of the last path segment of a URL template, if any
+fn template_extension(template: &str) -> Option<&str> {
+    let path = template.split(['?', '#']).next().unwrap_or(template);
+    let segment = path.rsplit('/').next()?;
+    segment.rsplit_once('.').map(|(_, ext)| ext)
+}
+
+/// Map an upstream `Content-Type` to a tile format
+fn format_from_content_type(content_type: &str) -> TileFormat {
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+    match mime {
+        "application/x-protobuf" | "application/vnd.mapbox-vector-tile" => TileFormat::Pbf,
+        "image/png" => TileFormat::Png,
+        "image/jpeg" => TileFormat::Jpeg,
+        "image/webp" => TileFormat::Webp,
+        "image/avif" => TileFormat::Avif,
+        _ => TileFormat::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ProxySourceConfig, SourceType};
+    use axum::{extract::Path, http::header, response::IntoResponse, routing::get, Router};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Start a stub upstream server and return its base URL and request counter
+    ///
+    /// Rows select the behaviour: 0 serves a tile, 1 is missing, 2 fails
+    /// twice before succeeding, 3 always fails, 4 serves a gzip tile.
+    async fn stub_upstream() -> (String, Arc<AtomicUsize>) {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+
+        let app = Router::new()
+            .route(
+                "/tiles/{z}/{x}/{y}",
+                get(move |Path((_z, _x, y)): Path<(u8, u32, String)>| {
+                    let counter = counter.clone();
+                    async move {
+                        let hit = counter.fetch_add(1, Ordering::SeqCst);
+                        let y = y.trim_end_matches(".pbf");
+                        match y {
+                            "0" => ([(header::CONTENT_TYPE, "image/png")], "tile").into_response(),
+                            "2" if hit >= 2 => "tile".into_response(),
+                            "2" | "3" => StatusCode::SERVICE_UNAVAILABLE.into_response(),
+                            "4" => (
+                                [(header::CONTENT_ENCODING, "gzip")],
+                                vec![0x1f_u8, 0x8b, 0x08, 0x00],
+                            )
+                                .into_response(),
+                            _ => StatusCode::NOT_FOUND.into_response(),
+                        }
+                    }
+                }),
+            )
+            .route(
+                "/tiles.json",
+                get(|| async {
+                    axum::Json(serde_json::json!({
+                        "tilejson": "3.0.0",
+                        "name": "Upstream",
+                        "attribution": "© Upstream",
+                        "format": "pbf",
+                        "minzoom": 2,
+                        "maxzoom": 12,
+                        "bounds": [5.9, 45.8, 10.5, 47.8],
+                        "vector_layers": [{ "id": "water" }]
+                    }))
+                }),
+            );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        (format!("http://{}", addr), hits)
+    }
+
+    fn proxy_config(url: String, proxy: ProxySourceConfig) -> SourceConfig {
+        SourceConfig {
+            id: "upstream".to_string(),
+            aliases: Vec::new(),
+            source_type: SourceType::Proxy,
+            path: url,
+            name: None,
+            attribution: None,
+            scheme: Default::default(),
+            optional: false,
+            overzoom: false,
+            resampling: None,
+            #[cfg(feature = "raster")]
+            colormap: None,
+            #[cfg(feature = "raster")]
+            nodata_elevation: None,
+            #[cfg(feature = "raster")]
+            encoding: None,
+            #[cfg(feature = "raster")]
+            overview_bias: None,
+            #[cfg(feature = "raster")]
+            relief: Default::default(),
+            proxy,
+            geopackage: Default::default(),
+            tile_limits: Default::default(),
+            options: Default::default(),
+            resilience: None,
+        }
+    }
+
+    fn fast_retries() -> ProxySourceConfig {
+        ProxySourceConfig {
+            retry_backoff_ms: 1,
+            ..ProxySourceConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_proxy_fetches_tile() {
+        let (base, hits) = stub_upstream().await;
+        let config = proxy_config(format!("{}/tiles/{{z}}/{{x}}/{{y}}", base), fast_retries());
+        let source = ProxySource::from_config(&config).await.unwrap();
+
+        let tile = source.get_tile(3, 1, 0).await.unwrap().unwrap();
+        assert_eq!(&tile.data[..], b"tile");
+        // No extension in the template, so the format comes from Content-Type
+        assert_eq!(tile.format, TileFormat::Png);
+        assert_eq!(tile.compression, TileCompression::None);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_proxy_maps_404_to_none() {
+        let (base, hits) = stub_upstream().await;
+        let config = proxy_config(format!("{}/tiles/{{z}}/{{x}}/{{y}}", base), fast_retries());
+        let source = ProxySource::from_config(&config).await.unwrap();
+
+        assert!(source.get_tile(3, 1, 1).await.unwrap().is_none());
+        // A missing tile is not retried
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_proxy_retries_server_errors() {
+        let (base, hits) = stub_upstream().await;
+        let config = proxy_config(format!("{}/tiles/{{z}}/{{x}}/{{y}}", base), fast_retries());
+        let source = ProxySource::from_config(&config).await.unwrap();
+
+        let tile = source.get_tile(3, 1, 2).await.unwrap();
+        assert!(tile.is_some());
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_proxy_gives_up_after_retries() {
+        let (base, hits) = stub_upstream().await;
+        let config = proxy_config(format!("{}/tiles/{{z}}/{{x}}/{{y}}", base), fast_retries());
+        let source = ProxySource::from_config(&config).await.unwrap();
+
+        let result = source.get_tile(3, 1, 3).await;
+        assert!(matches!(result, Err(TileServerError::UpstreamError(_))));
+        // One attempt plus two retries
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_proxy_passes_through_compression() {
+        let (base, _) = stub_upstream().await;
+        let config = proxy_config(
+            format!("{}/tiles/{{z}}/{{x}}/{{y}}.pbf", base),
+            fast_retries(),
+        );
+        let source = ProxySource::from_config(&config).await.unwrap();
+        assert_eq!(source.metadata().format, TileFormat::Pbf);
+
+        let tile = source.get_tile(3, 1, 4).await.unwrap().unwrap();
+        assert_eq!(tile.format, TileFormat::Pbf);
+        assert_eq!(tile.compression, TileCompression::Gzip);
+    }
+
+    #[tokio::test]
+    async fn test_proxy_metadata_from_tilejson() {
+        let (base, _) = stub_upstream().await;
+        let config = proxy_config(
+            format!("{}/tiles/{{z}}/{{x}}/{{y}}", base),
+            ProxySourceConfig {
+                tilejson_url: Some(format!("{}/tiles.json", base)),
+                maxzoom: Some(10),
+                ..fast_retries()
+            },
+        );
+        let source = ProxySource::from_config(&config).await.unwrap();
+        let metadata = source.metadata();
+
+        assert_eq!(metadata.name, "Upstream");
+        assert_eq!(metadata.attribution.as_deref(), Some("© Upstream"));
+        assert_eq!(metadata.format, TileFormat::Pbf);
+        assert_eq!(metadata.minzoom, 2);
+        // Configuration takes precedence over the upstream TileJSON
+        assert_eq!(metadata.maxzoom, 10);
+        assert_eq!(metadata.bounds, Some([5.9, 45.8, 10.5, 47.8]));
+        assert!(metadata.vector_layers.is_some());
+
+        // Zoom levels outside the range are not requested upstream
+        assert!(source.get_tile(1, 0, 0).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_proxy_rejects_template_without_placeholders() {
+        let config = proxy_config(
+            "http://localhost/tiles.png".to_string(),
+            ProxySourceConfig::default(),
+        );
+        assert!(ProxySource::from_config(&config).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tile_url() {
+        let config = proxy_config(
+            "https://a.example/{z}/{x}/{-y}.png?y={y}".to_string(),
+            ProxySourceConfig::default(),
+        );
+        let source = ProxySource::from_config(&config).await.unwrap();
+        assert_eq!(source.tile_url(2, 1, 0), "https://a.example/2/1/3.png?y=0");
+    }
+
+    #[test]
+    fn test_template_helpers() {
+        assert_eq!(
+            template_extension("https://a.example/{z}/{x}/{y}.png?key=1"),
+            Some("png")
+        );
+        assert_eq!(template_extension("https://a.example/{z}/{x}/{y}"), None);
+        assert_eq!(
+            format_from_content_type("application/x-protobuf; charset=binary"),
+            TileFormat::Pbf
+        );
+    }
+}
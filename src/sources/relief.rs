@@ -0,0 +1,277 @@
+//! Hillshade and slope of elevation rasters
+//!
+//! Derivatives come from the 3x3 neighbourhood of each pixel (Horn's
+//! method, as `gdaldem` and ArcGIS use). Tiles are read with one extra
+//! pixel on every side, so pixels at the tile edge see the same neighbours
+//! as in the adjacent tile and no seams show. A pixel with no-data anywhere
+//! in its neighbourhood is no-data itself.
+
+use image::{GrayAlphaImage, GrayImage, ImageBuffer};
+use std::io::Cursor;
+
+use crate::config::{DerivedProduct, ReliefConfig};
+use crate::error::{Result, TileServerError};
+
+/// Radius of the Web Mercator sphere, in meters
+const EARTH_RADIUS: f64 = 6_378_137.0;
+
+/// Ground size in meters of the pixels of each row of a Web Mercator tile
+///
+/// Web Mercator stretches distances by `1 / cos(latitude)`, so a pixel
+/// `pixel_size` projected meters wide covers less ground away from the
+/// equator. `max_y` is the top edge of the tile in EPSG:3857 meters.
+pub fn mercator_cell_sizes(max_y: f64, pixel_size: f64, rows: usize) -> Vec<f64> {
+    (0..rows)
+        .map(|row| {
+            let y = max_y - (row as f64 + 0.5) * pixel_size;
+            let latitude = (y / EARTH_RADIUS).sinh().atan();
+            pixel_size * latitude.cos()
+        })
+        .collect()
+}
+
+/// Hillshade (0 to 255) or slope (degrees) of the `size`x`size` pixels
+/// inside a grid of `(size + 2)`x`(size + 2)` elevations, row by row
+///
+/// `cell_sizes` holds the ground size of the pixels of each output row, in
+/// the unit of the elevations. No-data elevations are NaN, and so are the
+/// pixels next to them.
+pub fn derive(
+    elevations: &[f64],
+    size: usize,
+    cell_sizes: &[f64],
+    product: DerivedProduct,
+    config: &ReliefConfig,
+) -> Result<Vec<f64>> {
+    let width = size + 2;
+    if elevations.len() != width * width || cell_sizes.len() != size {
+        return Err(TileServerError::RasterError(format!(
+            "Expected {} elevations and {} cell sizes for a {}px tile, got {} and {}",
+            width * width,
+            size,
+            size,
+            elevations.len(),
+            cell_sizes.len()
+        )));
+    }
+
+    let zenith = (90.0 - config.altitude).to_radians();
+    let azimuth = (450.0 - config.azimuth).rem_euclid(360.0).to_radians();
+
+    let mut values = Vec::with_capacity(size * size);
+    for (row, &cell_size) in cell_sizes.iter().enumerate() {
+        for col in 0..size {
+            // a b c
+            // d e f
+            // g h i
+            let at = |r: usize, c: usize| elevations[(row + r) * width + col + c];
+            let window = [
+                at(0, 0),
+                at(0, 1),
+                at(0, 2),
+                at(1, 0),
+                at(1, 1),
+                at(1, 2),
+                at(2, 0),
+                at(2, 1),
+                at(2, 2),
+            ];
+            if window.iter().any(|e| e.is_nan()) {
+                values.push(f64::NAN);
+                continue;
+            }
+            let [a, b, c, d, _, f, g, h, i] = window;
+
+            let scale = config.z_factor / (8.0 * cell_size);
+            let dz_dx = ((c + 2.0 * f + i) - (a + 2.0 * d + g)) * scale;
+            let dz_dy = ((g + 2.0 * h + i) - (a + 2.0 * b + c)) * scale;
+            let slope = dz_dx.hypot(dz_dy).atan();
+
+            values.push(match product {
+                DerivedProduct::Slope => slope.to_degrees(),
+                DerivedProduct::Hillshade => {
+                    let aspect = dz_dy.atan2(-dz_dx);
+                    let light = zenith.cos() * slope.cos()
+                        + zenith.sin() * slope.sin() * (azimuth - aspect).cos();
+                    (255.0 * light).max(0.0)
+                }
+            });
+        }
+    }
+    Ok(values)
+}
+
+/// Gray level of a derived value: hillshade as is, slope from white when
+/// flat to black when vertical
+fn gray(value: f64, product: DerivedProduct) -> u8 {
+    let level = match product {
+        DerivedProduct::Hillshade => value,
+        DerivedProduct::Slope => 255.0 * (1.0 - value / 90.0),
+    };
+    level.round().clamp(0.0, 255.0) as u8
+}
+
+/// Encode a `size`x`size` grid of derived values as a grayscale PNG
+///
+/// Tiles with no-data (NaN) pixels get an alpha channel, transparent where
+/// there is no data; the others are a single gray band.
+pub fn grayscale_png(values: &[f64], size: u32, product: DerivedProduct) -> Result<Vec<u8>> {
+    if values.len() != (size as usize) * (size as usize) {
+        return Err(TileServerError::RasterError(format!(
+            "Expected {} values for a {}px tile, got {}",
+            size * size,
+            size,
+            values.len()
+        )));
+    }
+
+    let mut png_data = Vec::new();
+    let mut cursor = Cursor::new(&mut png_data);
+    let written = if values.iter().any(|v| v.is_nan()) {
+        let mut img: GrayAlphaImage = ImageBuffer::new(size, size);
+        for (pixel, &value) in img.pixels_mut().zip(values) {
+            pixel.0 = if value.is_nan() {
+                [0, 0]
+            } else {
+                [gray(value, product), 255]
+            };
+        }
+        img.write_to(&mut cursor, image::ImageFormat::Png)
+    } else {
+        let mut img: GrayImage = ImageBuffer::new(size, size);
+        for (pixel, &value) in img.pixels_mut().zip(values) {
+            pixel.0 = [gray(value, product)];
+        }
+        img.write_to(&mut cursor, image::ImageFormat::Png)
+    };
+    written.map_err(|e| TileServerError::RasterError(format!("Failed to encode PNG: {}", e)))?;
+    Ok(png_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 3x3 elevations of a plane with the given rise per cell eastward
+    /// and southward, around a single output pixel
+    fn plane(east: f64, south: f64) -> Vec<f64> {
+        (0..3)
+            .flat_map(|row| (0..3).map(move |col| 100.0 + east * col as f64 + south * row as f64))
+            .collect()
+    }
+
+    fn one(elevations: &[f64], product: DerivedProduct) -> f64 {
+        derive(elevations, 1, &[1.0], product, &ReliefConfig::default()).unwrap()[0]
+    }
+
+    #[test]
+    fn test_slope() {
+        assert_eq!(one(&plane(0.0, 0.0), DerivedProduct::Slope), 0.0);
+        // Rising one cell per cell is 45 degrees, whichever the direction
+        for (east, south) in [(1.0, 0.0), (-1.0, 0.0), (0.0, 1.0), (0.0, -1.0)] {
+            let slope = one(&plane(east, south), DerivedProduct::Slope);
+            assert!(
+                (slope - 45.0).abs() < 1e-9,
+                "{} for {:?}",
+                slope,
+                (east, south)
+            );
+        }
+
+        // The z factor exaggerates relief
+        let config = ReliefConfig {
+            z_factor: 3.0_f64.sqrt(),
+            ..Default::default()
+        };
+        let steep = derive(&plane(1.0, 0.0), 1, &[1.0], DerivedProduct::Slope, &config).unwrap();
+        assert!((steep[0] - 60.0).abs() < 1e-9, "{}", steep[0]);
+    }
+
+    #[test]
+    fn test_hillshade_faces_the_light() {
+        let flat = one(&plane(0.0, 0.0), DerivedProduct::Hillshade);
+        assert!(
+            (flat - 255.0 * 45.0_f64.to_radians().sin()).abs() < 1e-9,
+            "{}",
+            flat
+        );
+
+        // Lit from the northwest: slopes facing west and north are
+        // brighter than flat ground, those facing east and south darker
+        let facing_west = one(&plane(1.0, 0.0), DerivedProduct::Hillshade);
+        let facing_north = one(&plane(0.0, 1.0), DerivedProduct::Hillshade);
+        let facing_east = one(&plane(-1.0, 0.0), DerivedProduct::Hillshade);
+        let facing_south = one(&plane(0.0, -1.0), DerivedProduct::Hillshade);
+        assert!(facing_west > flat && facing_north > flat);
+        assert!(facing_east < flat && facing_south < flat);
+        assert!((facing_west - facing_north).abs() < 1e-9);
+
+        // Facing straight into a light from the west
+        let config = ReliefConfig {
+            azimuth: 270.0,
+            altitude: 45.0,
+            ..Default::default()
+        };
+        let lit = derive(
+            &plane(1.0, 0.0),
+            1,
+            &[1.0],
+            DerivedProduct::Hillshade,
+            &config,
+        )
+        .unwrap();
+        assert!((lit[0] - 255.0).abs() < 1e-9, "{}", lit[0]);
+    }
+
+    #[test]
+    fn test_nodata_spreads_to_neighbours() {
+        let mut elevations = vec![100.0; 16];
+        elevations[0] = f64::NAN;
+        let values = derive(
+            &elevations,
+            2,
+            &[1.0, 1.0],
+            DerivedProduct::Slope,
+            &ReliefConfig::default(),
+        )
+        .unwrap();
+        assert!(values[0].is_nan());
+        assert_eq!(&values[1..], &[0.0, 0.0, 0.0]);
+
+        assert!(derive(
+            &elevations,
+            3,
+            &[1.0; 3],
+            DerivedProduct::Slope,
+            &ReliefConfig::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_mercator_cell_sizes() {
+        // One pixel at the equator keeps its size, at 60 degrees it halves
+        let equator = mercator_cell_sizes(0.5, 1.0, 1);
+        assert!((equator[0] - 1.0).abs() < 1e-9);
+        let y = EARTH_RADIUS * 60.0_f64.to_radians().tan().asinh();
+        let north = mercator_cell_sizes(y + 0.5, 1.0, 1);
+        assert!((north[0] - 0.5).abs() < 1e-6, "{}", north[0]);
+    }
+
+    #[test]
+    fn test_grayscale_png() {
+        let png = grayscale_png(&[255.0, 0.0, 127.6, 300.0], 2, DerivedProduct::Hillshade).unwrap();
+        let img = image::load_from_memory(&png).unwrap();
+        assert_eq!(img.color(), image::ColorType::L8);
+        assert_eq!(img.to_luma8().into_raw(), vec![255, 0, 128, 255]);
+
+        // Flat is white and vertical black; no-data is transparent
+        let png = grayscale_png(&[0.0, 90.0, 45.0, f64::NAN], 2, DerivedProduct::Slope).unwrap();
+        let img = image::load_from_memory(&png).unwrap();
+        assert_eq!(img.color(), image::ColorType::La8);
+        assert_eq!(
+            img.to_luma_alpha8().into_raw(),
+            vec![255, 255, 0, 255, 128, 255, 0, 0]
+        );
+    }
+}
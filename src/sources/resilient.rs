@@ -0,0 +1,402 @@
+//! Timeouts and circuit breaking for slow or failing backends
+//!
+//! A remote backend that hangs would otherwise tie up every request for its
+//! tiles, and with them the connections and workers healthy sources need.
+//! [`ResilientSource`] bounds each tile read with a timeout and opens a
+//! circuit breaker after `failure_threshold` consecutive failures. While
+//! open, reads fail immediately with a 503 `SOURCE_UNAVAILABLE` error and a
+//! `Retry-After` header. Once the cooldown has passed, a single probe read
+//! is let through: it closes the breaker if it succeeds and reopens it if
+//! it fails.
+
+use async_trait::async_trait;
+use opentelemetry::metrics::Counter;
+use opentelemetry::KeyValue;
+use serde::Serialize;
+use std::future::Future;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::config::ResilienceConfig;
+use crate::error::{Result, TileServerError};
+use crate::integrity::IntegrityIssue;
+use crate::sources::{TileData, TileInfo, TileMetadata, TileRange, TileSource};
+
+static BREAKER_TRANSITIONS: OnceLock<Counter<u64>> = OnceLock::new();
+
+fn breaker_transitions() -> &'static Counter<u64> {
+    BREAKER_TRANSITIONS.get_or_init(|| {
+        opentelemetry::global::meter("tileserver-rs")
+            .u64_counter("tileserver_circuit_breaker_transitions_total")
+            .with_description("Circuit breaker state changes, by source and new state")
+            .with_unit("transitions")
+            .build()
+    })
+}
+
+/// State of a source's circuit breaker, reported by `/health/ready`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    /// Requests go through
+    Closed,
+    /// Requests fail immediately until the cooldown has passed
+    Open,
+    /// A probe request is deciding whether to close the breaker again
+    HalfOpen,
+}
+
+impl BreakerState {
+    fn as_str(self) -> &'static str {
+        match self {
+            BreakerState::Closed => "closed",
+            BreakerState::Open => "open",
+            BreakerState::HalfOpen => "half_open",
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Breaker {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    HalfOpen,
+}
+
+/// Circuit breaker shared by a source and the readiness report
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    source_id: String,
+    failure_threshold: u32,
+    cooldown: Duration,
+    state: Mutex<Breaker>,
+}
+
+impl CircuitBreaker {
+    pub fn new(source_id: &str, failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            source_id: source_id.to_string(),
+            failure_threshold,
+            cooldown,
+            state: Mutex::new(Breaker::Closed { failures: 0 }),
+        }
+    }
+
+    /// Current state, without letting a probe through
+    pub fn state(&self) -> BreakerState {
+        match *self.state.lock().unwrap_or_else(PoisonError::into_inner) {
+            Breaker::Closed { .. } => BreakerState::Closed,
+            Breaker::Open { until } if Instant::now() < until => BreakerState::Open,
+            Breaker::Open { .. } | Breaker::HalfOpen => BreakerState::HalfOpen,
+        }
+    }
+
+    /// Check that a request may go through, returning the permit to report
+    /// its outcome with
+    ///
+    /// Past the cooldown, the first caller becomes the half-open probe and
+    /// the others keep failing until it finishes.
+    pub fn acquire(&self) -> Result<Permit<'_>> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let permit = |probe| Permit {
+            breaker: self,
+            probe,
+            finished: false,
+        };
+        let retry_after = match *state {
+            Breaker::Closed { .. } => return Ok(permit(false)),
+            Breaker::Open { until } => {
+                let now = Instant::now();
+                if now >= until {
+                    self.transition(&mut state, Breaker::HalfOpen);
+                    return Ok(permit(true));
+                }
+                until - now
+            }
+            Breaker::HalfOpen => Duration::from_secs(1),
+        };
+        Err(TileServerError::SourceUnavailable {
+            source_id: self.source_id.clone(),
+            retry_after_secs: retry_after.as_secs_f64().ceil().max(1.0) as u64,
+        })
+    }
+
+    /// Record a request that went through successfully
+    fn record_success(&self) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        match *state {
+            Breaker::Closed { ref mut failures } => *failures = 0,
+            _ => self.transition(&mut state, Breaker::Closed { failures: 0 }),
+        }
+    }
+
+    /// Record a failed or timed out request
+    fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let failures = match *state {
+            Breaker::Closed { failures } => failures + 1,
+            // A failed probe reopens the breaker straight away
+            Breaker::HalfOpen => self.failure_threshold,
+            Breaker::Open { .. } => return,
+        };
+        if failures < self.failure_threshold {
+            *state = Breaker::Closed { failures };
+            return;
+        }
+        let until = Instant::now() + self.cooldown;
+        self.transition(&mut state, Breaker::Open { until });
+    }
+
+    fn transition(&self, state: &mut Breaker, next: Breaker) {
+        *state = next;
+        let new_state = match state {
+            Breaker::Closed { .. } => BreakerState::Closed,
+            Breaker::Open { .. } => BreakerState::Open,
+            Breaker::HalfOpen => BreakerState::HalfOpen,
+        };
+        match new_state {
+            BreakerState::Open => tracing::warn!(
+                "Circuit breaker for source '{}' opened for {:?}",
+                self.source_id,
+                self.cooldown
+            ),
+            _ => tracing::info!(
+                "Circuit breaker for source '{}' is {}",
+                self.source_id,
+                new_state.as_str()
+            ),
+        }
+        breaker_transitions().add(
+            1,
+            &[
+                KeyValue::new("source", self.source_id.clone()),
+                KeyValue::new("state", new_state.as_str()),
+            ],
+        );
+    }
+}
+
+/// Permission for one request to go through a [`CircuitBreaker`]
+///
+/// A permit dropped before [`Permit::finish`], when its request was
+/// cancelled, tells nothing about the backend; if it was the half-open
+/// probe, the next request probes instead.
+pub struct Permit<'a> {
+    breaker: &'a CircuitBreaker,
+    probe: bool,
+    finished: bool,
+}
+
+impl Permit<'_> {
+    /// Report whether the request succeeded
+    pub fn finish(mut self, success: bool) {
+        self.finished = true;
+        if success {
+            self.breaker.record_success();
+        } else {
+            self.breaker.record_failure();
+        }
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if self.finished || !self.probe {
+            return;
+        }
+        let mut state = self
+            .breaker
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Breaker::HalfOpen = *state {
+            *state = Breaker::Open {
+                until: Instant::now(),
+            };
+        }
+    }
+}
+
+/// A source whose tile reads are bounded by a timeout and a circuit breaker
+pub struct ResilientSource {
+    inner: Arc<dyn TileSource>,
+    timeout: Option<Duration>,
+    breaker: Option<Arc<CircuitBreaker>>,
+}
+
+impl ResilientSource {
+    pub fn new(inner: Arc<dyn TileSource>, config: &ResilienceConfig) -> Self {
+        let breaker = (config.failure_threshold > 0).then(|| {
+            Arc::new(CircuitBreaker::new(
+                &inner.metadata().id,
+                config.failure_threshold,
+                Duration::from_millis(config.cooldown_ms),
+            ))
+        });
+        Self {
+            inner,
+            timeout: (config.timeout_ms > 0).then(|| Duration::from_millis(config.timeout_ms)),
+            breaker,
+        }
+    }
+
+    /// The source's circuit breaker, if enabled
+    pub fn breaker(&self) -> Option<&Arc<CircuitBreaker>> {
+        self.breaker.as_ref()
+    }
+
+    /// Run a tile read through the breaker and timeout
+    ///
+    /// Backend-specific reads of the wrapped source reached through
+    /// `as_any` go through here too.
+    pub async fn guard<T>(&self, read: impl Future<Output = Result<T>>) -> Result<T> {
+        let permit = match &self.breaker {
+            Some(breaker) => Some(breaker.acquire()?),
+            None => None,
+        };
+
+        let result = match self.timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, read).await {
+                Ok(result) => result,
+                Err(_) => Err(TileServerError::Timeout(format!(
+                    "source '{}' took longer than {:?}",
+                    self.inner.metadata().id,
+                    timeout
+                ))),
+            },
+            None => read.await,
+        };
+
+        if let Some(permit) = permit {
+            permit.finish(result.is_ok());
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl TileSource for ResilientSource {
+    async fn get_xyz_tile(&self, z: u8, x: u32, y: u32) -> Result<Option<TileData>> {
+        self.guard(self.inner.get_xyz_tile(z, x, y)).await
+    }
+
+    fn metadata(&self) -> &TileMetadata {
+        self.inner.metadata()
+    }
+
+    fn backend(&self) -> &'static str {
+        self.inner.backend()
+    }
+
+    /// Fails while the breaker is open, without probing the backend
+    async fn health_check(&self) -> Result<()> {
+        if let Some(breaker) = &self.breaker {
+            if breaker.state() == BreakerState::Open {
+                breaker.acquire().map(drop)?;
+            }
+        }
+        self.inner.health_check().await
+    }
+
+    async fn tile_count(&self) -> Result<Option<u64>> {
+        self.inner.tile_count().await
+    }
+
+    async fn check_integrity(&self) -> Result<Vec<IntegrityIssue>> {
+        self.inner.check_integrity().await
+    }
+
+    async fn list_tiles(&self, range: TileRange, limit: usize) -> Result<Option<Vec<(u32, u32)>>> {
+        self.guard(self.inner.list_tiles(range, limit)).await
+    }
+
+    async fn tile_info(&self, z: u8, x: u32, y: u32) -> Result<Option<TileInfo>> {
+        self.guard(self.inner.tile_info(z, x, y)).await
+    }
+
+    fn last_modified(&self) -> Option<SystemTime> {
+        self.inner.last_modified()
+    }
+
+    async fn get_tile_with_buffer(
+        &self,
+        z: u8,
+        x: u32,
+        y: u32,
+        buffer: Option<u32>,
+    ) -> Result<Option<TileData>> {
+        self.guard(self.inner.get_tile_with_buffer(z, x, y, buffer))
+            .await
+    }
+
+    fn resilient(&self) -> Option<&ResilientSource> {
+        Some(self)
+    }
+
+    /// The wrapped source, so backend-specific features keep working
+    ///
+    /// Reads made through it bypass the breaker and timeout unless run
+    /// through [`ResilientSource::guard`].
+    fn as_any(&self) -> &dyn std::any::Any {
+        self.inner.as_any()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fail(breaker: &CircuitBreaker) {
+        breaker.acquire().unwrap().finish(false);
+    }
+
+    #[test]
+    fn test_breaker_opens_after_threshold() {
+        let breaker = CircuitBreaker::new("remote", 3, Duration::from_secs(30));
+        fail(&breaker);
+        fail(&breaker);
+        assert_eq!(breaker.state(), BreakerState::Closed);
+
+        // A success resets the count
+        breaker.acquire().unwrap().finish(true);
+        fail(&breaker);
+        fail(&breaker);
+        assert_eq!(breaker.state(), BreakerState::Closed);
+
+        fail(&breaker);
+        assert_eq!(breaker.state(), BreakerState::Open);
+        match breaker.acquire().err().unwrap() {
+            TileServerError::SourceUnavailable {
+                source_id,
+                retry_after_secs,
+            } => {
+                assert_eq!(source_id, "remote");
+                assert_eq!(retry_after_secs, 30);
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_single_half_open_probe() {
+        let breaker = CircuitBreaker::new("remote", 1, Duration::ZERO);
+        fail(&breaker);
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+
+        // The first caller probes, the rest keep failing meanwhile
+        let probe = breaker.acquire().unwrap();
+        assert!(breaker.acquire().is_err());
+
+        // A failed probe reopens the breaker
+        probe.finish(false);
+        let probe = breaker.acquire().unwrap();
+        assert!(breaker.acquire().is_err());
+
+        // A cancelled probe lets the next request probe
+        drop(probe);
+        let probe = breaker.acquire().unwrap();
+        probe.finish(true);
+        assert_eq!(breaker.state(), BreakerState::Closed);
+        assert!(breaker.acquire().is_ok());
+    }
+}
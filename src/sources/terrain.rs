@@ -0,0 +1,229 @@
+//! Terrain-RGB and Terrarium encoding of elevation rasters
+//!
+//! Elevations are packed into the red, green and blue channels of a PNG,
+//! which MapLibre GL JS reads for 3D terrain and hillshading. Mapbox
+//! Terrain-RGB (`raster-dem` sources with `"encoding": "mapbox"`) has 0.1 m
+//! steps:
+//!
+//! ```text
+//! elevation = (R * 256 * 256 + G * 256 + B) * 0.1 - 10000
+//! ```
+//!
+//! Mapzen Terrarium (`"encoding": "terrarium"`) has 1/256 m steps over a
+//! narrower range:
+//!
+//! ```text
+//! elevation = (R * 256 + G + B / 256) - 32768
+//! ```
+
+use image::{ImageBuffer, RgbImage};
+use std::io::Cursor;
+
+use crate::config::DemEncoding;
+use crate::error::{Result, TileServerError};
+
+/// Lowest elevation that can be encoded, in meters
+pub const MIN_ELEVATION: f64 = -10000.0;
+
+/// Highest elevation that can be encoded, in meters
+pub const MAX_ELEVATION: f64 = MIN_ELEVATION + MAX_VALUE as f64 * PRECISION;
+
+/// Elevation step of one unit of the encoded value, in meters
+const PRECISION: f64 = 0.1;
+
+/// Largest value of the 24-bit RGB triplet
+const MAX_VALUE: u32 = 0xFF_FFFF;
+
+/// Lowest elevation that can be encoded in Terrarium, in meters
+pub const TERRARIUM_MIN_ELEVATION: f64 = -32768.0;
+
+/// Highest elevation that can be encoded in Terrarium, in meters
+pub const TERRARIUM_MAX_ELEVATION: f64 =
+    TERRARIUM_MIN_ELEVATION + MAX_VALUE as f64 * TERRARIUM_PRECISION;
+
+/// Elevation step of one unit of the Terrarium value, in meters
+const TERRARIUM_PRECISION: f64 = 1.0 / 256.0;
+
+/// Encode an elevation in meters, clamped to the encodable range
+pub fn encode_elevation(elevation: f64) -> [u8; 3] {
+    let value = ((elevation - MIN_ELEVATION) / PRECISION)
+        .round()
+        .clamp(0.0, MAX_VALUE as f64) as u32;
+    [(value >> 16) as u8, (value >> 8) as u8, value as u8]
+}
+
+/// Decode an elevation in meters from a Terrain-RGB pixel
+pub fn decode_elevation([r, g, b]: [u8; 3]) -> f64 {
+    let value = (u32::from(r) << 16) | (u32::from(g) << 8) | u32::from(b);
+    value as f64 * PRECISION + MIN_ELEVATION
+}
+
+/// Encode an elevation in meters as Terrarium, clamped to the encodable
+/// range
+pub fn encode_terrarium(elevation: f64) -> [u8; 3] {
+    let value = ((elevation - TERRARIUM_MIN_ELEVATION) / TERRARIUM_PRECISION)
+        .round()
+        .clamp(0.0, MAX_VALUE as f64) as u32;
+    [(value >> 16) as u8, (value >> 8) as u8, value as u8]
+}
+
+/// Decode an elevation in meters from a Terrarium pixel
+pub fn decode_terrarium([r, g, b]: [u8; 3]) -> f64 {
+    f64::from(r) * 256.0 + f64::from(g) + f64::from(b) / 256.0 + TERRARIUM_MIN_ELEVATION
+}
+
+/// Encode a `size`x`size` grid of elevations, row by row, as a PNG in
+/// `encoding`
+///
+/// NaN values and those equal to `nodata` are written as `nodata_elevation`.
+pub fn elevation_png(
+    elevations: &[f64],
+    size: u32,
+    nodata: Option<f64>,
+    nodata_elevation: f64,
+    encoding: DemEncoding,
+) -> Result<Vec<u8>> {
+    if elevations.len() != (size as usize) * (size as usize) {
+        return Err(TileServerError::RasterError(format!(
+            "Expected {} elevations for a {}px tile, got {}",
+            size * size,
+            size,
+            elevations.len()
+        )));
+    }
+
+    let mut img: RgbImage = ImageBuffer::new(size, size);
+    for (pixel, &elevation) in img.pixels_mut().zip(elevations) {
+        let elevation = if elevation.is_nan() || Some(elevation) == nodata {
+            nodata_elevation
+        } else {
+            elevation
+        };
+        pixel.0 = match encoding {
+            DemEncoding::TerrainRgb => encode_elevation(elevation),
+            DemEncoding::Terrarium => encode_terrarium(elevation),
+        };
+    }
+
+    let mut png_data = Vec::new();
+    img.write_to(&mut Cursor::new(&mut png_data), image::ImageFormat::Png)
+        .map_err(|e| TileServerError::RasterError(format!("Failed to encode PNG: {}", e)))?;
+    Ok(png_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_elevation() {
+        // 0 m is 100000 = 0x0186A0
+        assert_eq!(encode_elevation(0.0), [1, 134, 160]);
+        // Mount Everest: 188480 = 0x02E040
+        assert_eq!(encode_elevation(8848.0), [2, 224, 64]);
+        // Dead Sea shore: 95700 = 0x0175D4
+        assert_eq!(encode_elevation(-430.0), [1, 117, 212]);
+        assert_eq!(encode_elevation(MIN_ELEVATION), [0, 0, 0]);
+    }
+
+    #[test]
+    fn test_encode_elevation_clamps() {
+        assert_eq!(encode_elevation(-20000.0), [0, 0, 0]);
+        assert_eq!(encode_elevation(2_000_000.0), [255, 255, 255]);
+        assert_eq!(decode_elevation([255, 255, 255]), MAX_ELEVATION);
+    }
+
+    #[test]
+    fn test_round_trip_to_precision() {
+        for elevation in [-9999.9, -0.05, 0.04, 123.456, 4807.81, 8848.86] {
+            let decoded = decode_elevation(encode_elevation(elevation));
+            assert!(
+                (decoded - elevation).abs() <= PRECISION / 2.0 + 1e-9,
+                "{} decoded as {}",
+                elevation,
+                decoded
+            );
+        }
+    }
+
+    #[test]
+    fn test_encode_terrarium() {
+        // 0 m is 32768 * 256 = 0x800000
+        assert_eq!(encode_terrarium(0.0), [128, 0, 0]);
+        assert_eq!(encode_terrarium(8848.5), [162, 144, 128]);
+        assert_eq!(encode_terrarium(-430.25), [126, 81, 192]);
+        assert_eq!(decode_terrarium([162, 144, 128]), 8848.5);
+        // Out of range elevations clamp
+        assert_eq!(encode_terrarium(-40000.0), [0, 0, 0]);
+        assert_eq!(encode_terrarium(40000.0), [255, 255, 255]);
+        assert_eq!(decode_terrarium([255, 255, 255]), TERRARIUM_MAX_ELEVATION);
+    }
+
+    /// Elevations from -500 m to 8800 m, in a grid of `size`x`size`
+    fn synthetic_grid(size: u32) -> Vec<f64> {
+        (0..size * size)
+            .map(|i| {
+                let (x, y) = (f64::from(i % size), f64::from(i / size));
+                -500.0 + 9300.0 * (x + y) / f64::from(2 * (size - 1)) + (x * 0.37).sin()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_png_round_trip() {
+        let elevations = synthetic_grid(64);
+        for (encoding, tolerance) in [
+            (DemEncoding::TerrainRgb, 0.1),
+            (DemEncoding::Terrarium, 1.0 / 256.0),
+        ] {
+            let png = elevation_png(&elevations, 64, None, 0.0, encoding).unwrap();
+            let img = image::load_from_memory(&png).unwrap().to_rgb8();
+            let max_error = img
+                .pixels()
+                .zip(&elevations)
+                .map(|(pixel, elevation)| {
+                    let decoded = match encoding {
+                        DemEncoding::TerrainRgb => decode_elevation(pixel.0),
+                        DemEncoding::Terrarium => decode_terrarium(pixel.0),
+                    };
+                    (decoded - elevation).abs()
+                })
+                .fold(0.0, f64::max);
+            assert!(max_error < tolerance, "{:?}: {}", encoding, max_error);
+        }
+    }
+
+    #[test]
+    fn test_terrain_rgb_png_pixels() {
+        let elevations = [0.0, 8848.0, -9999.0, f64::NAN];
+        let png = elevation_png(
+            &elevations,
+            2,
+            Some(-9999.0),
+            -10.0,
+            DemEncoding::TerrainRgb,
+        )
+        .unwrap();
+
+        let img = image::load_from_memory(&png).unwrap().to_rgb8();
+        assert_eq!(img.dimensions(), (2, 2));
+        assert_eq!(img.get_pixel(0, 0).0, [1, 134, 160]);
+        assert_eq!(img.get_pixel(1, 0).0, [2, 224, 64]);
+        // No-data, by value and as NaN, is written as the sentinel elevation
+        assert_eq!(img.get_pixel(0, 1).0, encode_elevation(-10.0));
+        assert_eq!(img.get_pixel(1, 1).0, encode_elevation(-10.0));
+        assert_eq!(decode_elevation(img.get_pixel(1, 1).0), -10.0);
+    }
+
+    #[test]
+    fn test_terrarium_png_nodata() {
+        let png = elevation_png(&[f64::NAN], 1, None, 0.0, DemEncoding::Terrarium).unwrap();
+        let img = image::load_from_memory(&png).unwrap().to_rgb8();
+        assert_eq!(img.get_pixel(0, 0).0, [128, 0, 0]);
+    }
+
+    #[test]
+    fn test_elevation_png_size_mismatch() {
+        assert!(elevation_png(&[0.0; 3], 2, None, 0.0, DemEncoding::TerrainRgb).is_err());
+    }
+}
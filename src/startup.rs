@@ -0,0 +1,329 @@
+//! Startup summary and friendly fatal errors
+//!
+//! Once sources and styles are loaded, the server prints a summary of what it
+//! is about to serve: every source with its backend, format, zooms and size,
+//! every style with the data sources it references (✓ when loaded, ✗ when
+//! not), whether the renderer is available and where it listens. Common
+//! reasons not to start at all are reported as [`StartupError`]s that say how
+//! to fix them.
+
+use std::fmt::{self, Write as _};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::sources::SourceManager;
+use crate::styles::StyleManager;
+
+/// A reason not to start, with a suggested fix
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StartupError {
+    /// Another process is listening on the address
+    PortInUse { addr: String },
+    /// The directory to serve does not exist
+    MissingDataDir(PathBuf),
+    /// No source or style loaded; `failed` sources were configured but did
+    /// not load
+    NothingToServe { failed: usize },
+}
+
+impl fmt::Display for StartupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StartupError::PortInUse { addr } => write!(
+                f,
+                "{} is already in use\n  \
+                 hint: stop the process using it (`lsof -i :{}`), or pick another port \
+                 with --port or `port` in [server]",
+                addr,
+                addr.rsplit_once(':')
+                    .map_or(addr.as_str(), |(_, port)| port)
+            ),
+            StartupError::MissingDataDir(path) => write!(
+                f,
+                "{} does not exist\n  \
+                 hint: pass a directory holding .pmtiles or .mbtiles files, or a config \
+                 file with --config",
+                path.display()
+            ),
+            StartupError::NothingToServe { failed: 0 } => write!(
+                f,
+                "Nothing to serve: no sources or styles are configured\n  \
+                 hint: add [[sources]] or [[styles]] to the config, or run \
+                 `tileserver-rs path/to/tiles` to serve the tile files in a directory"
+            ),
+            StartupError::NothingToServe { failed } => write!(
+                f,
+                "Nothing to serve: all {} configured source(s) failed to load and no \
+                 styles are configured\n  \
+                 hint: fix the errors logged above, or check the paths with \
+                 `tileserver-rs validate`",
+                failed
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StartupError {}
+
+/// Fail fast when `host:port` is taken, before loading any sources
+///
+/// The port is bound and released again; port 0 is always free.
+pub fn check_port(host: &str, port: u16) -> Result<(), StartupError> {
+    if port == 0 {
+        return Ok(());
+    }
+    let addr = format!("{}:{}", host, port);
+    let Ok(socket_addr) = addr.parse::<SocketAddr>() else {
+        // Left for the real bind to report
+        return Ok(());
+    };
+    match std::net::TcpListener::bind(socket_addr) {
+        Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => {
+            Err(StartupError::PortInUse { addr })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Replace a bind error caused by the address being in use with
+/// [`StartupError::PortInUse`]
+pub fn explain_bind_error(err: anyhow::Error, addr: impl fmt::Display) -> anyhow::Error {
+    let in_use = err.chain().any(|cause| {
+        cause
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|e| e.kind() == std::io::ErrorKind::AddrInUse)
+    });
+    if in_use {
+        StartupError::PortInUse {
+            addr: addr.to_string(),
+        }
+        .into()
+    } else {
+        err
+    }
+}
+
+/// Refuse to start without a single source or style
+pub fn check_not_empty(sources: &SourceManager, styles: &StyleManager) -> Result<(), StartupError> {
+    if sources.is_empty() && styles.is_empty() {
+        return Err(StartupError::NothingToServe {
+            failed: sources.failed().len(),
+        });
+    }
+    Ok(())
+}
+
+/// Summary of what the server is about to serve, as plain-text tables
+pub fn summary(
+    config: &Config,
+    sources: &SourceManager,
+    styles: &StyleManager,
+    renderer: bool,
+    listen_url: &str,
+) -> String {
+    let mut out = String::new();
+
+    let mut ids = sources.ids();
+    ids.sort();
+    let mut rows: Vec<[String; 5]> = ids
+        .into_iter()
+        .filter_map(|id| {
+            let source = sources.get(id)?;
+            let metadata = source.metadata();
+            Some([
+                id.clone(),
+                source.backend().to_string(),
+                metadata.format.name().to_string(),
+                format!("{}-{}", metadata.minzoom, metadata.maxzoom),
+                sources
+                    .stats(id)
+                    .size_bytes
+                    .map_or_else(|| "-".to_string(), format_size),
+            ])
+        })
+        .collect();
+    rows.extend(sources.failed().iter().map(|failed| {
+        [
+            failed.id.clone(),
+            "-".to_string(),
+            "failed".to_string(),
+            "-".to_string(),
+            "-".to_string(),
+        ]
+    }));
+    let _ = writeln!(out, "Sources:");
+    write_table(&mut out, ["ID", "TYPE", "FORMAT", "ZOOMS", "SIZE"], &rows);
+
+    let rows: Vec<[String; 2]> = styles
+        .all()
+        .into_iter()
+        .map(|style| {
+            let referenced = style
+                .data_source_ids()
+                .iter()
+                .map(|id| {
+                    let mark = if sources.exists(id) { "✓" } else { "✗" };
+                    format!("{} {}", id, mark)
+                })
+                .collect::<Vec<_>>();
+            let referenced = if referenced.is_empty() {
+                "-".to_string()
+            } else {
+                referenced.join(", ")
+            };
+            [style.id.clone(), referenced]
+        })
+        .collect();
+    let _ = writeln!(out, "Styles:");
+    write_table(&mut out, ["ID", "SOURCES"], &rows);
+
+    let renderer = if renderer {
+        "available"
+    } else if styles.is_empty() {
+        "not started (no styles)"
+    } else {
+        "unavailable, raster tiles and static images are disabled"
+    };
+    let _ = writeln!(out, "Renderer: {}", renderer);
+    let _ = writeln!(out, "Listening on {}", listen_url);
+    for listener in &config.server.listeners {
+        let _ = writeln!(out, "Also listening on {}", listener.base_url());
+    }
+    out
+}
+
+/// Left-aligned columns under a header, or `(none)` without rows
+fn write_table<const N: usize>(out: &mut String, header: [&str; N], rows: &[[String; N]]) {
+    if rows.is_empty() {
+        let _ = writeln!(out, "  (none)");
+        return;
+    }
+    let widths: Vec<usize> = (0..N)
+        .map(|i| {
+            rows.iter()
+                .map(|row| row[i].chars().count())
+                .chain([header[i].len()])
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let mut write_row = |cells: Vec<&str>| {
+        let line: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        let _ = writeln!(out, "  {}", line.join("  ").trim_end());
+    };
+    write_row(header.to_vec());
+    for row in rows {
+        write_row(row.iter().map(String::as_str).collect());
+    }
+}
+
+/// Byte count in binary units, e.g. `1.5 MiB`
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StyleConfig;
+
+    async fn load(styles: &[StyleConfig]) -> (Config, SourceManager, StyleManager) {
+        let config = Config::load(Some(PathBuf::from("tests/config.test.toml"))).unwrap();
+        let sources = SourceManager::from_configs(&config.sources).await.unwrap();
+        let styles = StyleManager::from_configs(styles).unwrap();
+        (config, sources, styles)
+    }
+
+    #[tokio::test]
+    async fn test_summary_lists_sources_and_styles() {
+        let config = Config::load(Some(PathBuf::from("tests/config.test.toml"))).unwrap();
+        let (config, sources, styles) = load(&config.styles).await;
+        let size = std::fs::metadata("data/tiles/zurich_switzerland.mbtiles")
+            .unwrap()
+            .len();
+
+        let text = summary(&config, &sources, &styles, true, "http://127.0.0.1:8080");
+        assert!(text.starts_with("Sources:\n  ID"), "{}", text);
+        let zurich = text
+            .lines()
+            .find(|line| line.trim_start().starts_with("zurich"))
+            .unwrap();
+        let columns: Vec<&str> = zurich.split_whitespace().collect();
+        assert_eq!(columns[..4], ["zurich", "mbtiles", "pbf", "0-14"]);
+        assert_eq!(columns[4..].join(" "), format_size(size));
+        assert!(text.contains("protomaps-light  protomaps ✓"), "{}", text);
+        assert!(text.contains("Renderer: available\n"));
+        assert!(text.ends_with("Listening on http://127.0.0.1:8080\n"));
+    }
+
+    #[tokio::test]
+    async fn test_summary_marks_missing_sources() {
+        let config = Config::load(Some(PathBuf::from("tests/config.test.toml"))).unwrap();
+        let (config, _, styles) = load(&[StyleConfig {
+            id: "light".to_string(),
+            ..config.styles[0].clone()
+        }])
+        .await;
+        let sources = SourceManager::new();
+
+        let text = summary(&config, &sources, &styles, false, "http://[::1]:80");
+        assert!(text.contains("Sources:\n  (none)\n"), "{}", text);
+        assert!(text.contains("light  protomaps ✗"), "{}", text);
+        assert!(text.contains("Renderer: unavailable"));
+    }
+
+    #[tokio::test]
+    async fn test_nothing_to_serve() {
+        let (_, sources, styles) = load(&[]).await;
+        assert!(check_not_empty(&sources, &styles).is_ok());
+
+        let err = check_not_empty(&SourceManager::new(), &styles).unwrap_err();
+        assert_eq!(err, StartupError::NothingToServe { failed: 0 });
+        assert!(err.to_string().contains("hint: add [[sources]]"));
+    }
+
+    #[test]
+    fn test_port_in_use() {
+        let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = taken.local_addr().unwrap().port();
+
+        let err = check_port("127.0.0.1", port).unwrap_err();
+        assert_eq!(
+            err,
+            StartupError::PortInUse {
+                addr: format!("127.0.0.1:{}", port)
+            }
+        );
+        assert!(err.to_string().contains(&format!("lsof -i :{}", port)));
+        assert!(check_port("127.0.0.1", 0).is_ok());
+
+        let bind_error = std::net::TcpListener::bind(("127.0.0.1", port)).unwrap_err();
+        let explained = explain_bind_error(anyhow::Error::new(bind_error), "127.0.0.1:1");
+        assert!(explained.downcast_ref::<StartupError>().is_some());
+
+        let other = explain_bind_error(anyhow::anyhow!("boom"), "127.0.0.1:1");
+        assert_eq!(other.to_string(), "boom");
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(3 * 1024 * 1024), "3.0 MiB");
+    }
+}
@@ -0,0 +1,348 @@
+//! Sprites embedded in styles as `data:` URIs
+//!
+//! Offline styles sometimes give their sprite as a base64 PNG,
+//! `"sprite": "data:image/png;base64,..."`, which some MapLibre Native
+//! versions fail to load. Before rendering, such sprites are decoded into a
+//! temporary directory and the style points at them with a `file://` URL.
+//!
+//! MapLibre reads a sprite as `{url}.png` and `{url}.json`, and an inline
+//! image comes without an index, so one is written with a single icon
+//! covering the whole image, named after the sprite's `id` (`default` for a
+//! plain `sprite` URL). The same image serves `@2x` requests.
+//!
+//! Files are named after a hash of the URI, so a sprite is decoded once
+//! however many renders use it. Each style keeps track of the files it
+//! uses: reloading a style deletes the files no other style uses (they are
+//! decoded again on the next render), and shutting down deletes them all.
+
+use base64::Engine;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use tempfile::TempDir;
+
+use crate::error::{Result, TileServerError};
+
+/// Sprite ID of a `sprite` given as a single URL
+const DEFAULT_SPRITE_ID: &str = "default";
+
+/// Suffixes of the files written for each sprite
+const FILE_SUFFIXES: [&str; 4] = [".png", "@2x.png", ".json", "@2x.json"];
+
+/// Temporary directory of the sprites decoded from `data:` URIs
+#[derive(Debug, Default)]
+pub struct InlineSprites {
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    dir: Option<TempDir>,
+    /// Names of the decoded sprites used by each style
+    styles: HashMap<String, HashSet<String>>,
+}
+
+impl InlineSprites {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Point the `data:` URIs of style `style_id`'s `sprite` at decoded files
+    ///
+    /// Handles a single URL and `{"id", "url"}` arrays. URIs that are not
+    /// base64 PNGs are left as they are, with a warning.
+    pub fn extract(&self, style_id: &str, sprite: &mut serde_json::Value) {
+        let entries: Vec<(String, &mut serde_json::Value)> = match sprite {
+            serde_json::Value::Array(entries) => entries
+                .iter_mut()
+                .filter_map(|entry| {
+                    let entry = entry.as_object_mut()?;
+                    let id = entry.get("id")?.as_str()?.to_string();
+                    Some((id, entry.get_mut("url")?))
+                })
+                .collect(),
+            url => vec![(DEFAULT_SPRITE_ID.to_string(), url)],
+        };
+        for (id, url) in entries {
+            let Some(uri) = url.as_str().filter(|url| url.starts_with("data:")) else {
+                continue;
+            };
+            match self.write(style_id, &id, uri) {
+                Ok(file_url) => *url = serde_json::Value::String(file_url),
+                Err(e) => tracing::warn!("Ignoring inline sprite '{}': {}", id, e),
+            }
+        }
+    }
+
+    /// Decode `uri` into the files of sprite `id` of style `style_id`,
+    /// returning their base URL
+    fn write(&self, style_id: &str, id: &str, uri: &str) -> Result<String> {
+        let name = {
+            let mut hasher = DefaultHasher::new();
+            (id, uri).hash(&mut hasher);
+            format!("{:016x}", hasher.finish())
+        };
+
+        let mut state = self.lock();
+        let dir = match &mut state.dir {
+            Some(dir) => dir,
+            empty => empty.insert(
+                tempfile::Builder::new()
+                    .prefix("tileserver-sprites-")
+                    .tempdir()?,
+            ),
+        };
+        let base = dir.path().join(&name);
+        if !FILE_SUFFIXES
+            .iter()
+            .all(|suffix| with_suffix(&base, suffix).is_file())
+        {
+            let png = decode_png(uri)?;
+            let (width, height) =
+                image::load_from_memory_with_format(&png, image::ImageFormat::Png)
+                    .map(|image| (image.width(), image.height()))
+                    .map_err(|e| {
+                        TileServerError::RenderError(format!("Invalid sprite PNG: {}", e))
+                    })?;
+            let index = serde_json::json!({
+                id: {"x": 0, "y": 0, "width": width, "height": height, "pixelRatio": 1}
+            })
+            .to_string();
+            for suffix in FILE_SUFFIXES {
+                let data = if suffix.ends_with(".png") {
+                    png.as_slice()
+                } else {
+                    index.as_bytes()
+                };
+                std::fs::write(with_suffix(&base, suffix), data)?;
+            }
+        }
+        state
+            .styles
+            .entry(style_id.to_string())
+            .or_default()
+            .insert(name);
+        Ok(format!("file://{}", base.display()))
+    }
+
+    /// Contents of a decoded sprite file at `url`, `None` if the URL is not
+    /// one of them
+    pub fn read(&self, url: &str) -> Option<Result<Vec<u8>>> {
+        let path = Path::new(url.strip_prefix("file://")?);
+        let name = path.file_name()?.to_str()?;
+        let is_hash = |hash: &str| hash.len() == 16 && hash.bytes().all(|b| b.is_ascii_hexdigit());
+        if !FILE_SUFFIXES
+            .iter()
+            .any(|suffix| name.strip_suffix(suffix).is_some_and(is_hash))
+        {
+            return None;
+        }
+
+        let state = self.lock();
+        if path.parent() != Some(state.dir.as_ref()?.path()) {
+            return None;
+        }
+        Some(std::fs::read(path).map_err(TileServerError::from))
+    }
+
+    /// Delete the decoded sprites of style `style_id` that no other style
+    /// uses
+    pub fn clear_style(&self, style_id: &str) {
+        let mut state = self.lock();
+        let Some(names) = state.styles.remove(style_id) else {
+            return;
+        };
+        let Some(dir) = &state.dir else {
+            return;
+        };
+        for name in names {
+            if state.styles.values().any(|used| used.contains(&name)) {
+                continue;
+            }
+            let base = dir.path().join(&name);
+            for suffix in FILE_SUFFIXES {
+                let path = with_suffix(&base, suffix);
+                if let Err(e) = std::fs::remove_file(&path) {
+                    tracing::warn!("Failed to delete inline sprite {}: {}", path.display(), e);
+                }
+            }
+        }
+    }
+
+    /// Delete all decoded sprites
+    pub fn clear(&self) {
+        let mut state = self.lock();
+        state.styles.clear();
+        if let Some(dir) = state.dir.take() {
+            if let Err(e) = dir.close() {
+                tracing::warn!("Failed to delete inline sprites: {}", e);
+            }
+        }
+    }
+}
+
+/// `base` with `suffix` appended to its file name
+fn with_suffix(base: &Path, suffix: &str) -> PathBuf {
+    let mut path = base.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// Bytes of a `data:image/png;base64,` URI
+fn decode_png(uri: &str) -> Result<Vec<u8>> {
+    let (header, data) = uri
+        .strip_prefix("data:")
+        .and_then(|uri| uri.split_once(','))
+        .ok_or_else(|| TileServerError::RenderError("Malformed data URI".to_string()))?;
+    let mut params = header.split(';');
+    let media_type = params.next().unwrap_or_default();
+    if !media_type.eq_ignore_ascii_case("image/png") {
+        return Err(TileServerError::RenderError(format!(
+            "Unsupported sprite type '{}', expected image/png",
+            media_type
+        )));
+    }
+    if !params.any(|param| param.eq_ignore_ascii_case("base64")) {
+        return Err(TileServerError::RenderError(
+            "Only base64 data URIs are supported".to_string(),
+        ));
+    }
+    base64::engine::general_purpose::STANDARD
+        .decode(data.trim())
+        .map_err(|e| TileServerError::RenderError(format!("Invalid base64: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A `width`x`height` PNG as a data URI
+    fn png_uri(width: u32, height: u32) -> String {
+        let mut png = Vec::new();
+        image::RgbaImage::new(width, height)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        format!(
+            "data:image/png;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(png)
+        )
+    }
+
+    #[test]
+    fn test_extract_writes_sprite_files() {
+        let sprites = InlineSprites::new();
+        let mut sprite = json!(png_uri(24, 16));
+        sprites.extract("basic", &mut sprite);
+
+        let url = sprite.as_str().unwrap();
+        let base = url.strip_prefix("file://").unwrap();
+        let png = std::fs::read(format!("{}.png", base)).unwrap();
+        assert_eq!(
+            image::load_from_memory(&png).unwrap().width(),
+            24,
+            "{}",
+            url
+        );
+        assert_eq!(std::fs::read(format!("{}@2x.png", base)).unwrap(), png);
+        let index: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(format!("{}.json", base)).unwrap()).unwrap();
+        assert_eq!(
+            index,
+            json!({"default": {"x": 0, "y": 0, "width": 24, "height": 16, "pixelRatio": 1}})
+        );
+
+        // The same URI maps to the same files
+        let mut again = json!(png_uri(24, 16));
+        sprites.extract("basic", &mut again);
+        assert_eq!(again, sprite);
+    }
+
+    #[test]
+    fn test_extract_sprite_array() {
+        let sprites = InlineSprites::new();
+        let mut sprite = json!([
+            {"id": "icons", "url": png_uri(8, 8)},
+            {"id": "remote", "url": "https://example.com/sprite"},
+            {"id": "text", "url": "data:text/plain;base64,aGk="},
+            {"id": "broken", "url": "data:image/png;base64,aGk="}
+        ]);
+        sprites.extract("basic", &mut sprite);
+
+        let url = sprite[0]["url"].as_str().unwrap();
+        assert!(url.starts_with("file://"), "{}", url);
+        let index = std::fs::read_to_string(format!("{}.json", &url[7..])).unwrap();
+        assert!(index.contains("\"icons\""), "{}", index);
+        assert_eq!(sprite[1]["url"], "https://example.com/sprite");
+        // Unsupported and undecodable URIs stay as they are
+        assert_eq!(sprite[2]["url"], "data:text/plain;base64,aGk=");
+        assert_eq!(sprite[3]["url"], "data:image/png;base64,aGk=");
+    }
+
+    #[test]
+    fn test_read_only_serves_sprite_files() {
+        let sprites = InlineSprites::new();
+        assert!(sprites.read("file:///etc/passwd").is_none());
+
+        let mut sprite = json!(png_uri(4, 4));
+        sprites.extract("basic", &mut sprite);
+        let url = sprite.as_str().unwrap();
+
+        let png = sprites.read(&format!("{}@2x.png", url)).unwrap().unwrap();
+        assert!(image::load_from_memory(&png).is_ok());
+        assert!(sprites.read(&format!("{}.json", url)).is_some());
+        assert!(sprites.read(&format!("{}.webp", url)).is_none());
+        assert!(sprites.read(&format!("{}/../x.png", url)).is_none());
+        assert!(sprites.read("file:///tmp/0123456789abcdef.png").is_none());
+    }
+
+    #[test]
+    fn test_clear_deletes_files() {
+        let sprites = InlineSprites::new();
+        let mut sprite = json!(png_uri(4, 4));
+        sprites.extract("basic", &mut sprite);
+        let url = sprite.as_str().unwrap().to_string();
+        let png = format!("{}.png", &url[7..]);
+        assert!(Path::new(&png).is_file());
+
+        sprites.clear();
+        assert!(!Path::new(&png).exists());
+        assert!(sprites.read(&format!("{}.png", url)).is_none());
+
+        // Rendering again decodes the sprite anew
+        let mut sprite = json!(png_uri(4, 4));
+        sprites.extract("basic", &mut sprite);
+        let url = sprite.as_str().unwrap();
+        assert!(sprites.read(&format!("{}.png", url)).unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_clear_style_keeps_other_styles() {
+        let sprites = InlineSprites::new();
+        let mut own = json!(png_uri(4, 4));
+        sprites.extract("a", &mut own);
+        let mut shared = json!(png_uri(8, 8));
+        sprites.extract("a", &mut shared);
+        let mut other = json!(png_uri(8, 8));
+        sprites.extract("b", &mut other);
+        assert_eq!(shared, other);
+
+        sprites.clear_style("a");
+        let own = format!("{}.png", own.as_str().unwrap());
+        assert!(sprites.read(&own).unwrap().is_err());
+        // Sprites another style uses stay
+        let shared = format!("{}.png", shared.as_str().unwrap());
+        assert!(sprites.read(&shared).unwrap().is_ok());
+
+        sprites.clear_style("b");
+        assert!(sprites.read(&shared).unwrap().is_err());
+        // Unknown styles have nothing to delete
+        sprites.clear_style("unknown");
+    }
+}
@@ -0,0 +1,294 @@
+//! Label localization for rendered images
+//!
+//! `?language=de` on raster tiles and static images makes every label prefer
+//! the feature's `name:de` property, falling back to `name`. Both ways of
+//! writing a `text-field` are handled: legacy `{name}` tokens and
+//! expressions using `["get", "name"]`.
+
+use serde_json::{json, Value};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+
+/// Longest language code kept, e.g. `zh-hant` or `sr-latn`
+const MAX_LANGUAGE_LEN: usize = 16;
+
+/// Localized styles kept before the cache is cleared
+const CACHE_CAPACITY: usize = 256;
+
+/// Language code reduced to lowercase `[a-z-]`, or `None` when nothing is
+/// left of it
+///
+/// `_` becomes `-`, so `de_CH` is `de-ch`.
+pub fn sanitize_language(language: &str) -> Option<String> {
+    let language: String = language
+        .chars()
+        .map(|c| {
+            if c == '_' {
+                '-'
+            } else {
+                c.to_ascii_lowercase()
+            }
+        })
+        .filter(|c| c.is_ascii_lowercase() || *c == '-')
+        .take(MAX_LANGUAGE_LEN)
+        .collect();
+    let language = language.trim_matches('-');
+    (!language.is_empty()).then(|| language.to_string())
+}
+
+/// Rewrite the `text-field` of every layer to prefer `name:{language}` over
+/// `name`
+///
+/// `language` is expected to be sanitized already.
+pub fn localize_style(style_json: &Value, language: &str) -> Value {
+    let mut style = style_json.clone();
+    let layers = style
+        .get_mut("layers")
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten();
+    for layer in layers {
+        if let Some(text_field) = layer
+            .get_mut("layout")
+            .and_then(|layout| layout.get_mut("text-field"))
+        {
+            localize_text_field(text_field, language);
+        }
+    }
+    style
+}
+
+/// Localize a `text-field` value: a token string, an expression, or a legacy
+/// zoom function whose stops hold either
+fn localize_text_field(value: &mut Value, language: &str) {
+    match value {
+        Value::String(text) => {
+            if let Some(localized) = localize_tokens(text, language) {
+                *value = localized;
+            }
+        }
+        Value::Array(_) => localize_expression(value, language),
+        Value::Object(function) => {
+            let stops = function
+                .get_mut("stops")
+                .and_then(Value::as_array_mut)
+                .into_iter()
+                .flatten();
+            for stop in stops {
+                if let Some(output) = stop.get_mut(1) {
+                    localize_text_field(output, language);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Replace every `["get", "name"]` in an expression with a lookup that
+/// prefers the localized name
+fn localize_expression(value: &mut Value, language: &str) {
+    let Value::Array(items) = value else {
+        return;
+    };
+    if is_get_name(items) {
+        *value = localized_name(language);
+        return;
+    }
+    for item in items {
+        localize_expression(item, language);
+    }
+}
+
+fn is_get_name(items: &[Value]) -> bool {
+    matches!(items, [op, name] if op == "get" && name == "name")
+}
+
+/// `["coalesce", ["get", "name:{language}"], ["get", "name"]]`
+fn localized_name(language: &str) -> Value {
+    json!([
+        "coalesce",
+        ["get", format!("name:{}", language)],
+        ["get", "name"]
+    ])
+}
+
+/// Expression for a legacy token string that uses `{name}`, or `None` when
+/// it doesn't
+///
+/// A string that is just `{name}` becomes the localized lookup; one mixing
+/// text and tokens becomes a `concat`, with other tokens as
+/// `["to-string", ["get", token]]` so missing properties stay empty, as
+/// tokens do.
+fn localize_tokens(text: &str, language: &str) -> Option<Value> {
+    if !text.contains("{name}") {
+        return None;
+    }
+    if text == "{name}" {
+        return Some(localized_name(language));
+    }
+
+    let mut parts = vec![json!("concat")];
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        if start > 0 {
+            parts.push(json!(rest[..start]));
+        }
+        let token = &rest[start + 1..start + len];
+        parts.push(if token == "name" {
+            localized_name(language)
+        } else {
+            json!(["to-string", ["get", token]])
+        });
+        rest = &rest[start + len + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(json!(rest));
+    }
+    Some(Value::Array(parts))
+}
+
+/// Localized styles, serialized for the renderer and keyed by style and
+/// language
+///
+/// Each entry remembers a hash of the style it was made from, so it is
+/// redone once the style or its sources change.
+#[derive(Debug, Default)]
+pub struct LocalizedStyleCache {
+    styles: Mutex<HashMap<(String, String), (u64, String)>>,
+}
+
+impl LocalizedStyleCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `style_json` of style `style_id`, localized to `language` and
+    /// serialized
+    pub fn get(&self, style_id: &str, language: &str, style_json: &Value) -> String {
+        let fingerprint = {
+            let mut hasher = DefaultHasher::new();
+            style_json.to_string().hash(&mut hasher);
+            hasher.finish()
+        };
+        let key = (style_id.to_string(), language.to_string());
+        if let Ok(styles) = self.styles.lock() {
+            if let Some((cached, style)) = styles.get(&key) {
+                if *cached == fingerprint {
+                    return style.clone();
+                }
+            }
+        }
+
+        let localized = localize_style(style_json, language).to_string();
+        if let Ok(mut styles) = self.styles.lock() {
+            if styles.len() >= CACHE_CAPACITY {
+                styles.clear();
+            }
+            styles.insert(key, (fingerprint, localized.clone()));
+        }
+        localized
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn style() -> Value {
+        json!({
+            "version": 8,
+            "sources": {},
+            "layers": [
+                { "id": "background", "type": "background" },
+                {
+                    "id": "legacy",
+                    "type": "symbol",
+                    "layout": { "text-field": "{name}" }
+                },
+                {
+                    "id": "legacy-mixed",
+                    "type": "symbol",
+                    "layout": { "text-field": "{name} ({ele} m)" }
+                },
+                {
+                    "id": "expression",
+                    "type": "symbol",
+                    "layout": {
+                        "text-field": ["format", ["upcase", ["get", "name"]], {}, ["get", "ref"], {}]
+                    }
+                },
+                {
+                    "id": "function",
+                    "type": "symbol",
+                    "layout": { "text-field": { "stops": [[4, "{ref}"], [10, "{name}"]] } }
+                },
+                {
+                    "id": "other",
+                    "type": "symbol",
+                    "layout": { "text-field": "{housenumber}" }
+                }
+            ]
+        })
+    }
+
+    fn text_field<'a>(style: &'a Value, id: &str) -> &'a Value {
+        let layers = style["layers"].as_array().unwrap();
+        let layer = layers.iter().find(|l| l["id"] == id).unwrap();
+        &layer["layout"]["text-field"]
+    }
+
+    #[test]
+    fn test_localize_both_syntaxes() {
+        let localized = localize_style(&style(), "de");
+        let name = json!(["coalesce", ["get", "name:de"], ["get", "name"]]);
+
+        assert_eq!(text_field(&localized, "legacy"), &name);
+        assert_eq!(
+            text_field(&localized, "legacy-mixed"),
+            &json!(["concat", name, " (", ["to-string", ["get", "ele"]], " m)"])
+        );
+        assert_eq!(
+            text_field(&localized, "expression"),
+            &json!(["format", ["upcase", name], {}, ["get", "ref"], {}])
+        );
+        assert_eq!(
+            text_field(&localized, "function"),
+            &json!({ "stops": [[4, "{ref}"], [10, name]] })
+        );
+        assert_eq!(text_field(&localized, "other"), "{housenumber}");
+        assert_eq!(localized["layers"][0], style()["layers"][0]);
+    }
+
+    #[test]
+    fn test_sanitize_language() {
+        assert_eq!(sanitize_language("de").as_deref(), Some("de"));
+        assert_eq!(sanitize_language("de_CH").as_deref(), Some("de-ch"));
+        assert_eq!(sanitize_language("zh-Hant").as_deref(), Some("zh-hant"));
+        assert_eq!(sanitize_language("\"]}, {\"x\": 1").as_deref(), Some("x"));
+        assert_eq!(sanitize_language("-fr-").as_deref(), Some("fr"));
+        assert_eq!(sanitize_language("123"), None);
+        assert_eq!(sanitize_language(""), None);
+        assert_eq!(sanitize_language(&"a".repeat(100)).unwrap().len(), 16);
+    }
+
+    #[test]
+    fn test_cache_follows_style_changes() {
+        let cache = LocalizedStyleCache::new();
+        let original = style();
+        let first = cache.get("basic", "de", &original);
+        assert_eq!(first, localize_style(&original, "de").to_string());
+        assert_eq!(cache.get("basic", "de", &original), first);
+        assert_ne!(cache.get("basic", "fr", &original), first);
+
+        let mut changed = original.clone();
+        changed["layers"][1]["layout"]["text-field"] = json!("{name} {ref}");
+        let updated = cache.get("basic", "de", &changed);
+        assert_ne!(updated, first);
+        assert!(updated.contains("\"concat\""));
+    }
+}
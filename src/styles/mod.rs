@@ -1,11 +1,35 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::SystemTime;
 
-use crate::config::StyleConfig;
+use crate::config::{StyleConfig, StyleRewriteConfig, StyleVariantConfig, UrlPattern};
 use crate::error::{Result, TileServerError};
+use crate::render::loader::LOCAL_TILES;
 use crate::sources::SourceManager;
 
+pub mod inline_sprite;
+pub mod localize;
+pub mod patch;
+pub mod raster;
+pub mod sprite;
+pub mod validate;
+
+use inline_sprite::InlineSprites;
+use raster::RasterLimits;
+use validate::{Diagnostic, Severity, StyleChecks};
+
+const MAPBOX_SCHEME: &str = "mapbox://";
+const MAPBOX_API_URL: &str = "https://api.mapbox.com";
+const MAPTILER_SCHEME: &str = "maptiler://";
+const MAPTILER_API_URL: &str = "https://api.maptiler.com";
+const STADIA_SCHEME: &str = "stadia://";
+const STADIA_TILES_URL: &str = "https://tiles.stadiamaps.com";
+/// Scheme of style source URLs naming a local MBTiles file, as in styles
+/// exported from QGIS
+pub const MBTILES_SCHEME: &str = "mbtiles://";
+
 /// Style metadata returned by /styles.json
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StyleInfo {
@@ -13,6 +37,9 @@ pub struct StyleInfo {
     pub name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub url: Option<String>,
+    /// URL of a small preview image of the style
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<String>,
 }
 
 /// A loaded map style
@@ -23,21 +50,36 @@ pub struct Style {
     pub style_json: serde_json::Value,
     /// Path to the style.json file (used to locate sprites)
     pub path: PathBuf,
+    /// Configured zoom range and bounds of the style's raster tiles
+    pub raster: RasterLimits,
 }
 
 impl Style {
-    /// Load a style from a file path
+    /// Load a style from its file, or from the config for inline styles
+    ///
+    /// Inline styles have an empty `path`, so they have no local sprites.
     pub fn from_file(config: &StyleConfig) -> Result<Self> {
-        let path = Path::new(&config.path);
-
-        if !path.exists() {
-            return Err(TileServerError::StyleNotFound(config.id.clone()));
-        }
+        let mut style_json = match &config.inline {
+            Some(_) if !config.path.as_os_str().is_empty() => {
+                return Err(TileServerError::ConfigError(format!(
+                    "Style '{}' sets both path and inline",
+                    config.id
+                )));
+            }
+            Some(serde_json::Value::String(json)) => parse_style_json(json)?,
+            Some(inline) => inline.clone(),
+            None => {
+                let path = Path::new(&config.path);
 
-        let content = std::fs::read_to_string(path).map_err(TileServerError::FileError)?;
+                if !path.exists() {
+                    return Err(TileServerError::StyleNotFound(config.id.clone()));
+                }
 
-        let style_json: serde_json::Value = serde_json::from_str(&content)
-            .map_err(|e| TileServerError::MetadataError(format!("Invalid style JSON: {}", e)))?;
+                let content = std::fs::read_to_string(path).map_err(TileServerError::FileError)?;
+                parse_style_json(&content)?
+            }
+        };
+        rewrite_mbtiles_urls(&mut style_json);
 
         let name = config
             .name
@@ -55,9 +97,64 @@ impl Style {
             name,
             style_json,
             path: config.path.clone(),
+            raster: RasterLimits::from(config),
+        })
+    }
+
+    /// Generate a variant of this style by applying its patch file
+    ///
+    /// The variant shares the parent's directory, and so its sprites. Unless
+    /// configured or changed by the patch, its name is its id.
+    pub fn variant(&self, config: &StyleVariantConfig) -> Result<Self> {
+        let content = std::fs::read_to_string(&config.patch).map_err(TileServerError::FileError)?;
+        let patch: serde_json::Value = serde_json::from_str(&content).map_err(|e| {
+            TileServerError::ConfigError(format!(
+                "Invalid style patch {}: {}",
+                config.patch.display(),
+                e
+            ))
+        })?;
+
+        let mut style_json = self.style_json.clone();
+        patch::apply(&mut style_json, &patch)?;
+
+        let name = config
+            .name
+            .clone()
+            .or_else(|| {
+                style_json
+                    .get("name")
+                    .filter(|name| Some(*name) != self.style_json.get("name"))
+                    .and_then(|v| v.as_str())
+                    .map(|s| s.to_string())
+            })
+            .unwrap_or_else(|| config.id.clone());
+
+        Ok(Self {
+            id: config.id.clone(),
+            name,
+            style_json,
+            path: self.path.clone(),
+            raster: self.raster.clone(),
         })
     }
 
+    /// IDs of the data sources this style references via `/data/{id}.json`
+    pub fn data_source_ids(&self) -> Vec<String> {
+        self.style_json
+            .get("sources")
+            .and_then(|s| s.as_object())
+            .map(|sources| {
+                sources
+                    .values()
+                    .filter_map(|source| source.get("url").and_then(|u| u.as_str()))
+                    .filter_map(data_source_id)
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
     /// Convert to StyleInfo for API response
     pub fn to_info(&self, base_url: &str) -> StyleInfo {
         self.to_info_with_key(base_url, None)
@@ -76,13 +173,77 @@ impl Style {
                 "{}/styles/{}/style.json{}",
                 base_url, self.id, key_query
             )),
+            thumbnail: Some(format!(
+                "{}/styles/{}/thumbnail.png{}",
+                base_url, self.id, key_query
+            )),
         }
     }
 }
 
+fn parse_style_json(content: &str) -> Result<serde_json::Value> {
+    serde_json::from_str(content)
+        .map_err(|e| TileServerError::MetadataError(format!("Invalid style JSON: {}", e)))
+}
+
 /// Manages all map styles
+#[derive(Clone)]
 pub struct StyleManager {
     styles: HashMap<String, Style>,
+    /// When each style was last read from disk
+    loaded_at: HashMap<String, SystemTime>,
+    /// Problems found in each style when it was loaded
+    diagnostics: HashMap<String, Vec<Diagnostic>>,
+    /// Parent style of each variant
+    parents: HashMap<String, String>,
+    checks: StyleChecks,
+}
+
+/// Style manager shared between handlers and replaced wholesale on reload
+///
+/// Like [`crate::sources::SharedSources`], handlers take a snapshot with
+/// [`SharedStyles::load`].
+#[derive(Clone)]
+pub struct SharedStyles {
+    current: Arc<RwLock<Arc<StyleManager>>>,
+    inline_sprites: Arc<InlineSprites>,
+}
+
+impl SharedStyles {
+    pub fn new(manager: StyleManager) -> Self {
+        Self {
+            current: Arc::new(RwLock::new(Arc::new(manager))),
+            inline_sprites: Arc::new(InlineSprites::new()),
+        }
+    }
+
+    /// Sprites decoded from `data:` URIs for the renderer
+    pub fn inline_sprites(&self) -> &InlineSprites {
+        &self.inline_sprites
+    }
+
+    /// Snapshot of the current styles
+    pub fn load(&self) -> Arc<StyleManager> {
+        self.current
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Re-read a single style from disk, regenerating its variants
+    ///
+    /// On failure the previous version (if any) keeps serving.
+    pub fn reload_style(&self, config: &StyleConfig) -> Result<()> {
+        let mut current = self.current.write().unwrap_or_else(PoisonError::into_inner);
+        let mut manager = StyleManager::clone(&current);
+        manager.load_style(config)?;
+        *current = Arc::new(manager);
+        self.inline_sprites.clear_style(&config.id);
+        for variant in &config.variants {
+            self.inline_sprites.clear_style(&variant.id);
+        }
+        Ok(())
+    }
 }
 
 impl StyleManager {
@@ -90,18 +251,37 @@ impl StyleManager {
     pub fn new() -> Self {
         Self {
             styles: HashMap::new(),
+            loaded_at: HashMap::new(),
+            diagnostics: HashMap::new(),
+            parents: HashMap::new(),
+            checks: StyleChecks::default(),
         }
     }
 
+    /// Add or replace a style
+    pub fn insert(&mut self, style: Style) {
+        self.loaded_at.insert(style.id.clone(), SystemTime::now());
+        self.styles.insert(style.id.clone(), style);
+    }
+
     /// Load styles from configuration
     pub fn from_configs(configs: &[StyleConfig]) -> Result<Self> {
+        Self::from_configs_with_checks(configs, StyleChecks::default())
+    }
+
+    /// Load styles from configuration, linting each against `checks`
+    pub fn from_configs_with_checks(configs: &[StyleConfig], checks: StyleChecks) -> Result<Self> {
         let mut manager = Self::new();
+        manager.checks = checks;
 
         for config in configs {
-            match Style::from_file(config) {
-                Ok(style) => {
-                    tracing::info!("Loaded style: {} ({})", config.id, config.path.display());
-                    manager.styles.insert(config.id.clone(), style);
+            match manager.load_style(config) {
+                Ok(()) => {
+                    let origin = match config.inline {
+                        Some(_) => "inline".into(),
+                        None => config.path.display().to_string(),
+                    };
+                    tracing::info!("Loaded style: {} ({})", config.id, origin);
                 }
                 Err(e) => {
                     tracing::error!("Failed to load style {}: {}", config.id, e);
@@ -113,6 +293,77 @@ impl StyleManager {
         Ok(manager)
     }
 
+    /// Read, lint and add (or replace) a style and its variants
+    ///
+    /// Problems found are logged and kept for [`StyleManager::diagnostics`].
+    /// With strict checks, a style with errors is not loaded. A variant that
+    /// fails to load does not keep its parent or the other variants from
+    /// loading, but its error is returned.
+    pub fn load_style(&mut self, config: &StyleConfig) -> Result<()> {
+        let style = Style::from_file(config)?;
+        let variants: Vec<_> = config
+            .variants
+            .iter()
+            .map(|variant| (variant, style.variant(variant)))
+            .collect();
+        self.add_style(style)?;
+
+        let mut result = Ok(());
+        for (variant, loaded) in variants {
+            match loaded.and_then(|style| self.add_style(style)) {
+                Ok(()) => {
+                    self.parents.insert(variant.id.clone(), config.id.clone());
+                    tracing::info!("Loaded style variant: {} (of {})", variant.id, config.id);
+                }
+                Err(e) => {
+                    tracing::error!("Failed to load style variant {}: {}", variant.id, e);
+                    if result.is_ok() {
+                        result = Err(e);
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    /// Lint and add (or replace) a loaded style
+    fn add_style(&mut self, style: Style) -> Result<()> {
+        let diagnostics = validate::lint(&style, self.checks.fonts.as_ref());
+
+        for diagnostic in &diagnostics {
+            let layer = diagnostic.layer.as_deref().unwrap_or("-");
+            match diagnostic.severity {
+                Severity::Error => tracing::error!(
+                    style = %style.id,
+                    layer,
+                    "Invalid style: {}",
+                    diagnostic.message
+                ),
+                Severity::Warning => tracing::warn!(
+                    style = %style.id,
+                    layer,
+                    "Style issue: {}",
+                    diagnostic.message
+                ),
+            }
+        }
+
+        let errors = diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+            .count();
+        if self.checks.strict && errors > 0 {
+            return Err(TileServerError::ConfigError(format!(
+                "Style '{}' has {} error(s) and strict_styles is enabled",
+                style.id, errors
+            )));
+        }
+
+        self.diagnostics.insert(style.id.clone(), diagnostics);
+        self.insert(style);
+        Ok(())
+    }
+
     /// Get a style by ID
     pub fn get(&self, id: &str) -> Option<&Style> {
         self.styles.get(id)
@@ -145,6 +396,24 @@ impl StyleManager {
     pub fn is_empty(&self) -> bool {
         self.styles.is_empty()
     }
+
+    /// When a style was last read from disk
+    pub fn loaded_at(&self, id: &str) -> Option<SystemTime> {
+        self.loaded_at.get(id).copied()
+    }
+
+    /// Problems found in a style when it was last loaded
+    pub fn diagnostics(&self, id: &str) -> &[Diagnostic] {
+        self.diagnostics
+            .get(id)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// ID of the style a variant was generated from
+    pub fn parent(&self, id: &str) -> Option<&str> {
+        self.parents.get(id).map(String::as_str)
+    }
 }
 
 impl Default for StyleManager {
@@ -209,14 +478,36 @@ impl UrlQueryParams {
 /// Similar to tileserver-gl's `fixUrl()` function, this:
 /// - Converts relative URLs to absolute
 /// - Preserves and forwards query parameters (like `?key=...`)
+#[allow(dead_code)]
 pub fn rewrite_style_for_api(
     style_json: &serde_json::Value,
     base_url: &str,
     query_params: &UrlQueryParams,
+) -> serde_json::Value {
+    rewrite_style_for_api_with_config(
+        style_json,
+        base_url,
+        query_params,
+        &StyleRewriteConfig::default(),
+    )
+}
+
+/// Rewrite a style for API clients, also expanding `maptiler://` and
+/// `stadia://` shorthands with the configured keys
+///
+/// `mapbox://` URLs are left alone: Mapbox GL clients resolve them with their
+/// own token, and the server's token is only used for rendering.
+pub fn rewrite_style_for_api_with_config(
+    style_json: &serde_json::Value,
+    base_url: &str,
+    query_params: &UrlQueryParams,
+    rewrite: &StyleRewriteConfig,
 ) -> serde_json::Value {
     let mut style = style_json.clone();
     let query_string = query_params.to_query_string();
 
+    rewrite_provider_urls(&mut style, rewrite);
+
     // Helper to rewrite a relative URL to absolute with query params
     let rewrite_url = |url_str: &str| -> String {
         if url_str.starts_with('/') {
@@ -260,11 +551,9 @@ pub fn rewrite_style_for_api(
         }
     }
 
-    // Rewrite sprite URL if relative
+    // Rewrite sprite URLs if relative
     if let Some(sprite) = style.get_mut("sprite") {
-        if let Some(sprite_str) = sprite.as_str() {
-            *sprite = serde_json::Value::String(rewrite_url(sprite_str));
-        }
+        for_each_sprite_url(sprite, |url| *url = rewrite_url(url));
     }
 
     style
@@ -277,19 +566,30 @@ pub fn rewrite_style_for_api(
 ///
 /// The native renderer cannot fetch TileJSON from our server (same process),
 /// so we need to embed the tile URLs directly in the style.
-/// This also rewrites relative glyphs and sprite URLs to absolute URLs.
+/// This also rewrites relative glyphs and sprite URLs to absolute URLs, and
+/// provider shorthands (`mapbox://`, `maptiler://`, `stadia://`) using the
+/// configured keys. Sprites given as `data:` URIs are decoded into
+/// `inline_sprites` for style `style_id`, unless `extract_data_uris` is off.
 pub fn rewrite_style_for_native(
     style_json: &serde_json::Value,
+    style_id: &str,
     base_url: &str,
     sources: &SourceManager,
+    rewrite: &StyleRewriteConfig,
+    inline_sprites: &InlineSprites,
 ) -> serde_json::Value {
     let mut style = style_json.clone();
 
+    if let Some(token) = rewrite.mapbox_token.as_deref() {
+        rewrite_mapbox_urls(&mut style, token);
+    }
+    rewrite_provider_urls(&mut style, rewrite);
+
     // Rewrite sources - inline tile URLs
     if let Some(style_sources) = style.get_mut("sources") {
         if let Some(sources_obj) = style_sources.as_object_mut() {
             for (source_id, source_config) in sources_obj.iter_mut() {
-                rewrite_source(source_id, source_config, base_url, sources);
+                rewrite_source(source_id, source_config, base_url, sources, rewrite);
             }
         }
     }
@@ -305,26 +605,260 @@ pub fn rewrite_style_for_native(
         }
     }
 
-    // Rewrite sprite URL if it's relative
+    // Rewrite sprite URLs if they're relative
     if let Some(sprite) = style.get_mut("sprite") {
-        if let Some(sprite_str) = sprite.as_str() {
-            if sprite_str.starts_with('/') {
-                let absolute_url = format!("{}{}", base_url, sprite_str);
-                tracing::debug!("Rewriting sprite URL: {} -> {}", sprite_str, absolute_url);
-                *sprite = serde_json::Value::String(absolute_url);
+        for_each_sprite_url(sprite, |url| {
+            if url.starts_with('/') {
+                let absolute_url = format!("{}{}", base_url, url);
+                tracing::debug!("Rewriting sprite URL: {} -> {}", url, absolute_url);
+                *url = absolute_url;
             }
+        });
+        if rewrite.extract_data_uris {
+            inline_sprites.extract(style_id, sprite);
         }
     }
 
     style
 }
 
+/// Apply `f` to the URL of a `sprite` given as a string, or to the URL of
+/// each entry of a sprite array (`{"id", "url"}` objects or plain URLs)
+fn for_each_sprite_url(sprite: &mut serde_json::Value, mut f: impl FnMut(&mut String)) {
+    let urls: Vec<&mut serde_json::Value> = match sprite {
+        serde_json::Value::Array(entries) => entries
+            .iter_mut()
+            .filter_map(|entry| match entry {
+                serde_json::Value::Object(entry) => entry.get_mut("url"),
+                url => Some(url),
+            })
+            .collect(),
+        sprite => vec![sprite],
+    };
+    for url in urls {
+        if let serde_json::Value::String(url) = url {
+            f(url);
+        }
+    }
+}
+
+/// Where in a style a shorthand URL appears
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StyleUrl {
+    /// A source's TileJSON `url`
+    Source,
+    Sprite,
+    Glyphs,
+}
+
+/// Replace URLs starting with `scheme` in source `url`s, `sprite` and `glyphs`
+///
+/// `expand` gets the part after the scheme and returns the replacement, or
+/// `None` to leave the URL unchanged.
+fn rewrite_scheme_urls(
+    style: &mut serde_json::Value,
+    scheme: &str,
+    expand: impl Fn(StyleUrl, &str) -> Option<String>,
+) {
+    let rewrite = |value: &mut serde_json::Value, kind: StyleUrl| {
+        if let Some(expanded) = value
+            .as_str()
+            .and_then(|url| url.strip_prefix(scheme))
+            .and_then(|rest| expand(kind, rest))
+        {
+            *value = serde_json::Value::String(expanded);
+        }
+    };
+
+    if let Some(sources_obj) = style.get_mut("sources").and_then(|s| s.as_object_mut()) {
+        for source in sources_obj.values_mut() {
+            if let Some(url) = source.get_mut("url") {
+                rewrite(url, StyleUrl::Source);
+            }
+        }
+    }
+    if let Some(sprite) = style.get_mut("sprite") {
+        for_each_sprite_url(sprite, |url| {
+            if let Some(expanded) = url
+                .strip_prefix(scheme)
+                .and_then(|rest| expand(StyleUrl::Sprite, rest))
+            {
+                *url = expanded;
+            }
+        });
+    }
+    if let Some(glyphs) = style.get_mut("glyphs") {
+        rewrite(glyphs, StyleUrl::Glyphs);
+    }
+}
+
+/// Rewrite `mapbox://` URLs in a style to Mapbox API URLs using `token`
+///
+/// - `mapbox://{tileset}` source URLs become `https://api.mapbox.com/v4/{tileset}.json`
+/// - `mapbox://sprites/{user}/{style}` becomes `https://api.mapbox.com/styles/v1/{user}/{style}/sprite`
+/// - `mapbox://fonts/{user}/...` becomes `https://api.mapbox.com/fonts/v1/{user}/...`
+pub fn rewrite_mapbox_urls(style: &mut serde_json::Value, token: &str) {
+    let access_token = format!("access_token={}", urlencoding::encode(token));
+
+    rewrite_scheme_urls(style, MAPBOX_SCHEME, |kind, rest| match kind {
+        StyleUrl::Source => Some(format!(
+            "{}/v4/{}.json?{}",
+            MAPBOX_API_URL, rest, access_token
+        )),
+        StyleUrl::Sprite => rest.strip_prefix("sprites/").map(|path| {
+            format!(
+                "{}/styles/v1/{}/sprite?{}",
+                MAPBOX_API_URL, path, access_token
+            )
+        }),
+        StyleUrl::Glyphs => rest
+            .strip_prefix("fonts/")
+            .map(|path| format!("{}/fonts/v1/{}?{}", MAPBOX_API_URL, path, access_token)),
+    });
+}
+
+/// Rewrite `maptiler://` URLs in a style to MapTiler Cloud URLs using `key`
+///
+/// - `maptiler://{tileset}` source URLs become `https://api.maptiler.com/tiles/{tileset}/tiles.json`
+/// - `maptiler://sprites/{map}` becomes `https://api.maptiler.com/maps/{map}/sprite`
+/// - `maptiler://fonts/...` becomes `https://api.maptiler.com/fonts/...`
+pub fn rewrite_maptiler_urls(style: &mut serde_json::Value, key: &str) {
+    let key = format!("key={}", urlencoding::encode(key));
+
+    rewrite_scheme_urls(style, MAPTILER_SCHEME, |kind, rest| match kind {
+        StyleUrl::Source => Some(format!(
+            "{}/tiles/{}/tiles.json?{}",
+            MAPTILER_API_URL, rest, key
+        )),
+        StyleUrl::Sprite => rest
+            .strip_prefix("sprites/")
+            .map(|map| format!("{}/maps/{}/sprite?{}", MAPTILER_API_URL, map, key)),
+        StyleUrl::Glyphs => rest
+            .strip_prefix("fonts/")
+            .map(|path| format!("{}/fonts/{}?{}", MAPTILER_API_URL, path, key)),
+    });
+}
+
+/// Rewrite `stadia://` URLs in a style to Stadia Maps URLs
+///
+/// - `stadia://{tileset}` source URLs become `https://tiles.stadiamaps.com/data/{tileset}.json`
+/// - `stadia://sprites/{style}` becomes `https://tiles.stadiamaps.com/styles/{style}/sprite`
+/// - `stadia://fonts/...` becomes `https://tiles.stadiamaps.com/fonts/...`
+///
+/// Stadia also authenticates by domain, so `api_key` is optional.
+pub fn rewrite_stadia_urls(style: &mut serde_json::Value, api_key: Option<&str>) {
+    let query = api_key
+        .map(|key| format!("?api_key={}", urlencoding::encode(key)))
+        .unwrap_or_default();
+
+    rewrite_scheme_urls(style, STADIA_SCHEME, |kind, rest| match kind {
+        StyleUrl::Source => Some(format!("{}/data/{}.json{}", STADIA_TILES_URL, rest, query)),
+        StyleUrl::Sprite => rest
+            .strip_prefix("sprites/")
+            .map(|name| format!("{}/styles/{}/sprite{}", STADIA_TILES_URL, name, query)),
+        StyleUrl::Glyphs => rest
+            .strip_prefix("fonts/")
+            .map(|path| format!("{}/fonts/{}{}", STADIA_TILES_URL, path, query)),
+    });
+}
+
+/// Apply the configured `url_patterns`, then expand the `maptiler://` and
+/// `stadia://` shorthands
+///
+/// `maptiler://` URLs are left as-is without a key, since MapTiler rejects
+/// unauthenticated requests.
+fn rewrite_provider_urls(style: &mut serde_json::Value, rewrite: &StyleRewriteConfig) {
+    if !rewrite.url_patterns.is_empty() {
+        rewrite_url_patterns(style, &rewrite.url_patterns);
+    }
+    if let Some(key) = rewrite.maptiler_key.as_deref() {
+        rewrite_maptiler_urls(style, key);
+    }
+    rewrite_stadia_urls(style, rewrite.stadia_api_key.as_deref());
+}
+
+/// Rewrite every string value in `value` with the first of `patterns` that
+/// matches it; object keys are left alone
+fn rewrite_url_patterns(value: &mut serde_json::Value, patterns: &[UrlPattern]) {
+    match value {
+        serde_json::Value::String(s) => {
+            if let Some(rewritten) = patterns.iter().find_map(|pattern| pattern.rewrite(s)) {
+                *s = rewritten;
+            }
+        }
+        serde_json::Value::Array(values) => {
+            for value in values {
+                rewrite_url_patterns(value, patterns);
+            }
+        }
+        serde_json::Value::Object(entries) => {
+            for value in entries.values_mut() {
+                rewrite_url_patterns(value, patterns);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// `mbtiles://` URLs of a style's sources, with the scheme stripped
+pub fn mbtiles_paths(style_json: &serde_json::Value) -> Vec<&str> {
+    style_json
+        .get("sources")
+        .and_then(|s| s.as_object())
+        .into_iter()
+        .flat_map(|sources| sources.values())
+        .filter_map(|source| source.get("url")?.as_str()?.strip_prefix(MBTILES_SCHEME))
+        .collect()
+}
+
+/// ID of the source serving the MBTiles file at `path` to styles that
+/// reference it with `mbtiles://`: `mbtiles_` and the file stem
+pub fn mbtiles_source_id(path: &str) -> Option<String> {
+    let stem = Path::new(path).file_stem()?.to_str()?;
+    Some(format!("mbtiles_{}", stem))
+}
+
+/// Point `mbtiles://` source URLs at the sources registered for them
+fn rewrite_mbtiles_urls(style_json: &mut serde_json::Value) {
+    let Some(sources) = style_json
+        .get_mut("sources")
+        .and_then(|s| s.as_object_mut())
+    else {
+        return;
+    };
+    for source in sources.values_mut() {
+        let id = source
+            .get("url")
+            .and_then(|u| u.as_str())
+            .and_then(|url| url.strip_prefix(MBTILES_SCHEME))
+            .and_then(mbtiles_source_id);
+        if let Some(id) = id {
+            source["url"] = serde_json::Value::String(format!("/data/{}.json", id));
+        }
+    }
+}
+
+/// Extract the data source ID from a URL referencing our data endpoint
+/// e.g., "/data/protomaps.json" or "http://localhost:8080/data/protomaps.json"
+pub(crate) fn data_source_id(url: &str) -> Option<&str> {
+    if let Some(rest) = url.strip_prefix("/data/") {
+        rest.strip_suffix(".json")
+    } else if url.contains("/data/") && url.ends_with(".json") {
+        url.rsplit("/data/")
+            .next()
+            .and_then(|s| s.strip_suffix(".json"))
+    } else {
+        None
+    }
+}
+
 /// Rewrite a single source to inline tile URLs
 fn rewrite_source(
     source_id: &str,
     source_config: &mut serde_json::Value,
     base_url: &str,
     sources: &SourceManager,
+    rewrite: &StyleRewriteConfig,
 ) {
     let source_obj = match source_config.as_object_mut() {
         Some(obj) => obj,
@@ -337,19 +871,7 @@ fn rewrite_source(
         _ => return,
     };
 
-    // Check if this is a reference to our data endpoint
-    // e.g., "/data/protomaps.json" or "http://localhost:8080/data/protomaps.json"
-    let data_source_id = if let Some(rest) = url.strip_prefix("/data/") {
-        rest.strip_suffix(".json")
-    } else if url.contains("/data/") && url.ends_with(".json") {
-        url.rsplit("/data/")
-            .next()
-            .and_then(|s| s.strip_suffix(".json"))
-    } else {
-        None
-    };
-
-    let data_source_id = match data_source_id {
+    let data_source_id = match data_source_id(&url) {
         Some(id) => id,
         None => return, // Not a reference to our data endpoint
     };
@@ -370,12 +892,16 @@ fn rewrite_source(
     let metadata = tile_source.metadata();
 
     // Build the tile URL template
-    let tile_url = format!(
-        "{}/data/{}/{{z}}/{{x}}/{{y}}.{}",
-        base_url,
-        data_source_id,
-        metadata.format.extension()
-    );
+    let tile_url = if rewrite.local_tiles {
+        format!("{}{}/{{z}}/{{x}}/{{y}}", LOCAL_TILES, data_source_id)
+    } else {
+        format!(
+            "{}/data/{}/{{z}}/{{x}}/{{y}}.{}",
+            base_url,
+            data_source_id,
+            metadata.format.extension()
+        )
+    };
 
     tracing::debug!(
         "Rewriting source '{}' from URL '{}' to tiles ['{}']",
@@ -442,6 +968,613 @@ mod tests {
         assert_eq!(params.to_query_string(), "?key=abc&foo=bar");
     }
 
+    fn mapbox_style() -> serde_json::Value {
+        json!({
+            "version": 8,
+            "sources": {
+                "terrain": {
+                    "type": "vector",
+                    "url": "mapbox://mapbox.mapbox-terrain-v2"
+                },
+                "streets": {
+                    "type": "vector",
+                    "url": "mapbox://mapbox.mapbox-streets-v8,mapbox.mapbox-terrain-v2"
+                },
+                "local": {
+                    "type": "vector",
+                    "url": "/data/openmaptiles.json"
+                }
+            },
+            "glyphs": "mapbox://fonts/mapbox/{fontstack}/{range}.pbf",
+            "sprite": "mapbox://sprites/mapbox/streets-v12",
+            "layers": []
+        })
+    }
+
+    #[test]
+    fn test_rewrite_mapbox_urls() {
+        let mut style = mapbox_style();
+        rewrite_mapbox_urls(&mut style, "pk.test");
+
+        assert_eq!(
+            style["sources"]["terrain"]["url"],
+            "https://api.mapbox.com/v4/mapbox.mapbox-terrain-v2.json?access_token=pk.test"
+        );
+        assert_eq!(
+            style["sources"]["streets"]["url"],
+            "https://api.mapbox.com/v4/mapbox.mapbox-streets-v8,mapbox.mapbox-terrain-v2.json?access_token=pk.test"
+        );
+        assert_eq!(style["sources"]["local"]["url"], "/data/openmaptiles.json");
+        assert_eq!(
+            style["glyphs"],
+            "https://api.mapbox.com/fonts/v1/mapbox/{fontstack}/{range}.pbf?access_token=pk.test"
+        );
+        assert_eq!(
+            style["sprite"],
+            "https://api.mapbox.com/styles/v1/mapbox/streets-v12/sprite?access_token=pk.test"
+        );
+    }
+
+    #[test]
+    fn test_rewrite_mapbox_urls_encodes_token() {
+        let mut style = mapbox_style();
+        rewrite_mapbox_urls(&mut style, "pk.a+b/c");
+
+        assert!(style["sprite"]
+            .as_str()
+            .unwrap()
+            .ends_with("?access_token=pk.a%2Bb%2Fc"));
+    }
+
+    #[test]
+    fn test_rewrite_style_for_native_mapbox_token() {
+        let sources = SourceManager::new();
+        let style = mapbox_style();
+
+        let rewrite = StyleRewriteConfig {
+            mapbox_token: Some("pk.test".to_string()),
+            ..Default::default()
+        };
+        let rewritten = rewrite_style_for_native(
+            &style,
+            "test",
+            "http://localhost:8080",
+            &sources,
+            &rewrite,
+            &InlineSprites::new(),
+        );
+        assert!(rewritten["sources"]["terrain"]["url"]
+            .as_str()
+            .unwrap()
+            .contains("access_token=pk.test"));
+
+        // Without a token mapbox:// URLs are left as-is
+        let rewritten = rewrite_style_for_native(
+            &style,
+            "test",
+            "http://localhost:8080",
+            &sources,
+            &StyleRewriteConfig::default(),
+            &InlineSprites::new(),
+        );
+        assert_eq!(
+            rewritten["sources"]["terrain"]["url"],
+            "mapbox://mapbox.mapbox-terrain-v2"
+        );
+        assert_eq!(rewritten["sprite"], "mapbox://sprites/mapbox/streets-v12");
+    }
+
+    #[test]
+    fn test_rewrite_url_patterns() {
+        let style = json!({
+            "version": 8,
+            "sources": {
+                "custom": { "type": "vector", "url": "https://tiles.custom.io/planet.json" },
+                "other": { "type": "raster", "tiles": ["https://cdn.custom.io/{z}/{x}/{y}.png"] }
+            },
+            "glyphs": "https://tiles.custom.io/fonts/{fontstack}/{range}.pbf",
+            "layers": [{
+                "id": "icons",
+                "type": "symbol",
+                "source": "custom",
+                "metadata": { "https://tiles.custom.io/": "key is kept" },
+                "layout": { "icon-image": "https://cdn.custom.io/icons/a.png" }
+            }]
+        });
+        let toml = r#"
+            url_patterns = [
+                { match = "^https://tiles\\.custom\\.io/", replace = "/proxy/" },
+                { match = "^https://(\\w+)\\.custom\\.io/", replace = "https://$1.example.com/" },
+                { match = "custom\\.io", replace = "never.applied" },
+            ]
+        "#;
+        let rewrite: StyleRewriteConfig = toml::from_str(toml).unwrap();
+
+        let rewritten = rewrite_style_for_api_with_config(
+            &style,
+            "http://localhost:8080",
+            &UrlQueryParams::default(),
+            &rewrite,
+        );
+        // Known URL fields become absolute after the substitution
+        assert_eq!(
+            rewritten["sources"]["custom"]["url"],
+            "http://localhost:8080/proxy/planet.json"
+        );
+        assert_eq!(
+            rewritten["glyphs"],
+            "http://localhost:8080/proxy/fonts/{fontstack}/{range}.pbf"
+        );
+        // Only the first matching pattern applies
+        assert_eq!(
+            rewritten["sources"]["other"]["tiles"][0],
+            "https://cdn.example.com/{z}/{x}/{y}.png"
+        );
+        // Any string value is rewritten, object keys are not
+        assert_eq!(
+            rewritten["layers"][0]["layout"]["icon-image"],
+            "https://cdn.example.com/icons/a.png"
+        );
+        assert_eq!(
+            rewritten["layers"][0]["metadata"]["https://tiles.custom.io/"],
+            "key is kept"
+        );
+        assert_eq!(rewritten["layers"][0]["source"], "custom");
+
+        // The renderer sees the same substitutions
+        let native = rewrite_style_for_native(
+            &style,
+            "test",
+            "http://localhost:8080",
+            &SourceManager::new(),
+            &rewrite,
+            &InlineSprites::new(),
+        );
+        assert_eq!(
+            native["glyphs"],
+            "http://localhost:8080/proxy/fonts/{fontstack}/{range}.pbf"
+        );
+    }
+
+    #[test]
+    fn test_rewrite_sprite_array() {
+        let style = json!({
+            "version": 8,
+            "sources": {},
+            "sprite": [
+                { "id": "default", "url": "/styles/basic/default/sprite" },
+                { "id": "maki", "url": "maptiler://sprites/maki" },
+                { "id": "remote", "url": "https://example.com/sprite" }
+            ]
+        });
+        let rewrite = StyleRewriteConfig {
+            maptiler_key: Some("mt".to_string()),
+            ..Default::default()
+        };
+
+        let rewritten = rewrite_style_for_api_with_config(
+            &style,
+            "http://localhost:8080",
+            &UrlQueryParams::default(),
+            &rewrite,
+        );
+        assert_eq!(
+            rewritten["sprite"][0],
+            json!({ "id": "default", "url": "http://localhost:8080/styles/basic/default/sprite" })
+        );
+        assert_eq!(
+            rewritten["sprite"][1]["url"],
+            "https://api.maptiler.com/maps/maki/sprite?key=mt"
+        );
+        assert_eq!(rewritten["sprite"][2]["url"], "https://example.com/sprite");
+
+        let rewritten = rewrite_style_for_native(
+            &style,
+            "test",
+            "http://localhost:8080",
+            &SourceManager::new(),
+            &rewrite,
+            &InlineSprites::new(),
+        );
+        assert_eq!(
+            rewritten["sprite"][0]["url"],
+            "http://localhost:8080/styles/basic/default/sprite"
+        );
+    }
+
+    fn shorthand_style(scheme: &str) -> serde_json::Value {
+        json!({
+            "version": 8,
+            "sources": {
+                "base": {
+                    "type": "vector",
+                    "url": format!("{}v3", scheme)
+                },
+                "local": {
+                    "type": "vector",
+                    "url": "/data/local.json"
+                }
+            },
+            "glyphs": format!("{}fonts/{{fontstack}}/{{range}}.pbf", scheme),
+            "sprite": format!("{}sprites/streets", scheme),
+            "layers": []
+        })
+    }
+
+    /// Expected (source url, glyphs, sprite) after rewriting `shorthand_style(scheme)`
+    struct Expected {
+        scheme: &'static str,
+        rewrite: StyleRewriteConfig,
+        api: bool,
+        source: &'static str,
+        glyphs: &'static str,
+        sprite: &'static str,
+    }
+
+    fn shorthand_matrix() -> Vec<Expected> {
+        let keys = StyleRewriteConfig {
+            mapbox_token: Some("pk.test".to_string()),
+            maptiler_key: Some("mt key".to_string()),
+            stadia_api_key: Some("stadia-key".to_string()),
+            ..Default::default()
+        };
+        let none = StyleRewriteConfig::default();
+
+        let mut matrix = Vec::new();
+        for api in [true, false] {
+            matrix.extend([
+                Expected {
+                    scheme: MAPTILER_SCHEME,
+                    rewrite: keys.clone(),
+                    api,
+                    source: "https://api.maptiler.com/tiles/v3/tiles.json?key=mt%20key",
+                    glyphs: "https://api.maptiler.com/fonts/{fontstack}/{range}.pbf?key=mt%20key",
+                    sprite: "https://api.maptiler.com/maps/streets/sprite?key=mt%20key",
+                },
+                Expected {
+                    scheme: MAPTILER_SCHEME,
+                    rewrite: none.clone(),
+                    api,
+                    source: "maptiler://v3",
+                    glyphs: "maptiler://fonts/{fontstack}/{range}.pbf",
+                    sprite: "maptiler://sprites/streets",
+                },
+                Expected {
+                    scheme: STADIA_SCHEME,
+                    rewrite: keys.clone(),
+                    api,
+                    source: "https://tiles.stadiamaps.com/data/v3.json?api_key=stadia-key",
+                    glyphs:
+                        "https://tiles.stadiamaps.com/fonts/{fontstack}/{range}.pbf?api_key=stadia-key",
+                    sprite: "https://tiles.stadiamaps.com/styles/streets/sprite?api_key=stadia-key",
+                },
+                Expected {
+                    scheme: STADIA_SCHEME,
+                    rewrite: none.clone(),
+                    api,
+                    source: "https://tiles.stadiamaps.com/data/v3.json",
+                    glyphs: "https://tiles.stadiamaps.com/fonts/{fontstack}/{range}.pbf",
+                    sprite: "https://tiles.stadiamaps.com/styles/streets/sprite",
+                },
+                Expected {
+                    scheme: MAPBOX_SCHEME,
+                    rewrite: none.clone(),
+                    api,
+                    source: "mapbox://v3",
+                    glyphs: "mapbox://fonts/{fontstack}/{range}.pbf",
+                    sprite: "mapbox://sprites/streets",
+                },
+            ]);
+        }
+
+        // The Mapbox token is only used for rendering, never sent to clients
+        matrix.push(Expected {
+            scheme: MAPBOX_SCHEME,
+            rewrite: keys.clone(),
+            api: true,
+            source: "mapbox://v3",
+            glyphs: "mapbox://fonts/{fontstack}/{range}.pbf",
+            sprite: "mapbox://sprites/streets",
+        });
+        matrix.push(Expected {
+            scheme: MAPBOX_SCHEME,
+            rewrite: keys,
+            api: false,
+            source: "https://api.mapbox.com/v4/v3.json?access_token=pk.test",
+            glyphs: "https://api.mapbox.com/fonts/v1/{fontstack}/{range}.pbf?access_token=pk.test",
+            sprite: "https://api.mapbox.com/styles/v1/streets/sprite?access_token=pk.test",
+        });
+        matrix
+    }
+
+    #[test]
+    fn test_rewrite_shorthand_matrix() {
+        let sources = SourceManager::new();
+        let base_url = "http://localhost:8080";
+
+        for case in shorthand_matrix() {
+            let style = shorthand_style(case.scheme);
+            let rewritten = if case.api {
+                rewrite_style_for_api_with_config(
+                    &style,
+                    base_url,
+                    &UrlQueryParams::default(),
+                    &case.rewrite,
+                )
+            } else {
+                rewrite_style_for_native(
+                    &style,
+                    "test",
+                    base_url,
+                    &sources,
+                    &case.rewrite,
+                    &InlineSprites::new(),
+                )
+            };
+
+            let context = format!(
+                "{} api={} keys={}",
+                case.scheme,
+                case.api,
+                case.rewrite.maptiler_key.is_some()
+            );
+            assert_eq!(
+                rewritten["sources"]["base"]["url"], case.source,
+                "source: {}",
+                context
+            );
+            assert_eq!(rewritten["glyphs"], case.glyphs, "glyphs: {}", context);
+            assert_eq!(rewritten["sprite"], case.sprite, "sprite: {}", context);
+
+            // Local sources are still handled by the regular rewrite
+            if case.api {
+                assert_eq!(
+                    rewritten["sources"]["local"]["url"],
+                    format!("{}/data/local.json", base_url),
+                    "local: {}",
+                    context
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_inline_style() {
+        let mut config = StyleConfig {
+            id: "overlay".to_string(),
+            path: PathBuf::new(),
+            inline: Some(json!({ "version": 8, "name": "Overlay", "sources": {}, "layers": [] })),
+            name: None,
+            variants: Vec::new(),
+            minzoom: None,
+            maxzoom: None,
+            bounds: None,
+            blank_outside: false,
+        };
+        let style = Style::from_file(&config).unwrap();
+        assert_eq!(style.name, "Overlay");
+        assert_eq!(style.style_json["version"], 8);
+        assert!(matches!(
+            style.sprite_dir(None),
+            Err(TileServerError::SpriteNotFound(_))
+        ));
+
+        config.inline = Some(json!(r#"{"version": 8, "layers": []}"#));
+        let style = Style::from_file(&config).unwrap();
+        assert_eq!(style.name, "overlay");
+        assert_eq!(style.style_json["layers"], json!([]));
+
+        config.inline = Some(json!("{"));
+        assert!(Style::from_file(&config).is_err());
+
+        config.inline = Some(json!({ "version": 8 }));
+        config.path = PathBuf::from("style.json");
+        assert!(matches!(
+            Style::from_file(&config),
+            Err(TileServerError::ConfigError(_))
+        ));
+    }
+
+    #[test]
+    fn test_shared_styles_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = StyleConfig {
+            id: "basic".to_string(),
+            path: dir.path().join("style.json"),
+            inline: None,
+            name: None,
+            variants: Vec::new(),
+            minzoom: None,
+            maxzoom: None,
+            bounds: None,
+            blank_outside: false,
+        };
+        std::fs::write(&config.path, r#"{"version": 8, "name": "Before"}"#).unwrap();
+
+        let shared =
+            SharedStyles::new(StyleManager::from_configs(std::slice::from_ref(&config)).unwrap());
+        let before = shared.load();
+        assert_eq!(before.get("basic").unwrap().name, "Before");
+
+        let mut sprite = json!(inline_png());
+        shared.inline_sprites().extract("basic", &mut sprite);
+        let sprite_png = format!("{}.png", sprite.as_str().unwrap());
+        assert!(shared.inline_sprites().read(&sprite_png).is_some());
+
+        std::fs::write(&config.path, r#"{"version": 8, "name": "After"}"#).unwrap();
+        shared.reload_style(&config).unwrap();
+        assert_eq!(shared.load().get("basic").unwrap().name, "After");
+        // Reloading deletes the style's decoded sprites
+        assert!(shared.inline_sprites().read(&sprite_png).unwrap().is_err());
+        // Earlier snapshots are unaffected
+        assert_eq!(before.get("basic").unwrap().name, "Before");
+
+        // A broken edit keeps the last good version
+        std::fs::write(&config.path, "{").unwrap();
+        assert!(shared.reload_style(&config).is_err());
+        assert_eq!(shared.load().get("basic").unwrap().name, "After");
+    }
+
+    #[test]
+    fn test_reload_keeps_sprites_of_other_styles() {
+        let dir = tempfile::tempdir().unwrap();
+        let configs: Vec<_> = ["a", "b"]
+            .into_iter()
+            .map(|id| StyleConfig {
+                id: id.to_string(),
+                path: dir.path().join(format!("{}.json", id)),
+                inline: None,
+                name: None,
+                variants: Vec::new(),
+                minzoom: None,
+                maxzoom: None,
+                bounds: None,
+                blank_outside: false,
+            })
+            .collect();
+        for config in &configs {
+            std::fs::write(&config.path, r#"{"version": 8}"#).unwrap();
+        }
+        let shared = SharedStyles::new(StyleManager::from_configs(&configs).unwrap());
+
+        let uri_a = inline_png();
+        let mut sprite_a = json!(uri_a);
+        shared.inline_sprites().extract("a", &mut sprite_a);
+        let mut sprite_b = json!([{"id": "b", "url": inline_png()}]);
+        shared.inline_sprites().extract("b", &mut sprite_b);
+        let sprite_b = format!("{}.png", sprite_b[0]["url"].as_str().unwrap());
+
+        // Style B renders while style A is reloaded over and over
+        std::thread::scope(|scope| {
+            let reader = scope.spawn(|| {
+                for _ in 0..200 {
+                    let data = shared.inline_sprites().read(&sprite_b).unwrap();
+                    assert!(data.is_ok(), "{:?}", data);
+                }
+            });
+            for _ in 0..200 {
+                shared.inline_sprites().extract("a", &mut json!(uri_a));
+                shared.reload_style(&configs[0]).unwrap();
+            }
+            reader.join().unwrap();
+        });
+
+        let sprite_a = format!("{}.png", sprite_a.as_str().unwrap());
+        assert!(shared.inline_sprites().read(&sprite_a).unwrap().is_err());
+        assert!(shared.inline_sprites().read(&sprite_b).unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_style_variants() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = StyleConfig {
+            id: "light".to_string(),
+            path: dir.path().join("style.json"),
+            inline: None,
+            name: None,
+            variants: vec![
+                StyleVariantConfig {
+                    id: "dark".to_string(),
+                    patch: dir.path().join("dark.patch.json"),
+                    name: None,
+                },
+                StyleVariantConfig {
+                    id: "muted".to_string(),
+                    patch: dir.path().join("muted.patch.json"),
+                    name: Some("Muted".to_string()),
+                },
+            ],
+            minzoom: None,
+            maxzoom: None,
+            bounds: None,
+            blank_outside: false,
+        };
+        std::fs::write(
+            &config.path,
+            r##"{"version": 8, "name": "Light", "sources": {}, "layers": [
+                {"id": "background", "type": "background", "paint": {"background-color": "#ffffff"}}
+            ]}"##,
+        )
+        .unwrap();
+        std::fs::write(
+            &config.variants[0].patch,
+            r##"{"name": "Dark", "layers": [
+                {"id": "background", "type": "background", "paint": {"background-color": "#111111"}}
+            ]}"##,
+        )
+        .unwrap();
+        std::fs::write(
+            &config.variants[1].patch,
+            r##"[{"op": "replace", "path": "/layers/0/paint/background-color", "value": "#cccccc"}]"##,
+        )
+        .unwrap();
+
+        let shared =
+            SharedStyles::new(StyleManager::from_configs(std::slice::from_ref(&config)).unwrap());
+        let styles = shared.load();
+        let color = |id: &str| {
+            styles.get(id).unwrap().style_json["layers"][0]["paint"]["background-color"].clone()
+        };
+        assert_eq!(color("light"), "#ffffff");
+        assert_eq!(color("dark"), "#111111");
+        assert_eq!(color("muted"), "#cccccc");
+        assert_eq!(styles.get("dark").unwrap().name, "Dark");
+        assert_eq!(styles.get("muted").unwrap().name, "Muted");
+        assert_eq!(styles.get("dark").unwrap().path, config.path);
+        assert_eq!(styles.parent("dark"), Some("light"));
+        assert_eq!(styles.parent("light"), None);
+        assert_eq!(styles.len(), 3);
+
+        // Reloading the parent regenerates its variants
+        std::fs::write(
+            &config.path,
+            r##"{"version": 8, "name": "Light", "sources": {}, "layers": [
+                {"id": "background", "type": "background", "paint": {"background-color": "#eeeeee"}}
+            ], "center": [8.5, 47.4]}"##,
+        )
+        .unwrap();
+        shared.reload_style(&config).unwrap();
+        let styles = shared.load();
+        assert_eq!(
+            styles.get("dark").unwrap().style_json["center"],
+            json!([8.5, 47.4])
+        );
+        assert_eq!(
+            styles.get("muted").unwrap().style_json["center"],
+            json!([8.5, 47.4])
+        );
+
+        // A broken patch fails the reload, keeping the last good version
+        std::fs::write(
+            &config.variants[1].patch,
+            r#"[{"op": "remove", "path": "/x"}]"#,
+        )
+        .unwrap();
+        assert!(shared.reload_style(&config).is_err());
+        assert_eq!(
+            shared.load().get("muted").unwrap().style_json["center"],
+            json!([8.5, 47.4])
+        );
+
+        // At startup it only keeps the variant from loading
+        let styles = StyleManager::from_configs(std::slice::from_ref(&config)).unwrap();
+        assert!(styles.get("light").is_some());
+        assert!(styles.get("dark").is_some());
+        assert!(styles.get("muted").is_none());
+    }
+
+    #[test]
+    fn test_rewrite_shorthand_ignores_unknown_paths() {
+        let mut style = json!({
+            "sprite": "maptiler://icons/streets",
+            "glyphs": "stadia://glyphs/{fontstack}/{range}.pbf"
+        });
+        rewrite_maptiler_urls(&mut style, "key");
+        rewrite_stadia_urls(&mut style, None);
+        assert_eq!(style["sprite"], "maptiler://icons/streets");
+        assert_eq!(style["glyphs"], "stadia://glyphs/{fontstack}/{range}.pbf");
+    }
+
     #[test]
     fn test_rewrite_style_for_api_no_params() {
         let style = json!({
@@ -591,6 +1724,118 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_rewrite_style_for_api_sprite_array() {
+        let style = json!({
+            "version": 8,
+            "sources": {},
+            "sprite": [
+                { "id": "default", "url": "/styles/basic/sprite" },
+                { "id": "maki", "url": "/styles/basic/maki/sprite" },
+                { "id": "external", "url": "https://sprites.example.com/icons" }
+            ]
+        });
+
+        let params = UrlQueryParams::with_key(Some("abc".to_string()));
+        let result = rewrite_style_for_api(&style, "http://localhost:8080", &params);
+
+        let sprites = result["sprite"].as_array().unwrap();
+        assert_eq!(sprites.len(), 3);
+        assert_eq!(
+            sprites[0],
+            json!({ "id": "default", "url": "http://localhost:8080/styles/basic/sprite?key=abc" })
+        );
+        assert_eq!(
+            sprites[1]["url"],
+            "http://localhost:8080/styles/basic/maki/sprite?key=abc"
+        );
+        assert_eq!(sprites[2]["url"], "https://sprites.example.com/icons");
+    }
+
+    #[test]
+    fn test_rewrite_style_for_api_sprite_url_array() {
+        let style = json!({
+            "version": 8,
+            "sources": {},
+            "sprite": ["/styles/basic/sprite", "https://sprites.example.com/icons"]
+        });
+
+        let params = UrlQueryParams::default();
+        let result = rewrite_style_for_api(&style, "http://localhost:8080", &params);
+        assert_eq!(
+            result["sprite"],
+            json!([
+                "http://localhost:8080/styles/basic/sprite",
+                "https://sprites.example.com/icons"
+            ])
+        );
+
+        let result = rewrite_style_for_native(
+            &style,
+            "test",
+            "http://localhost:8080",
+            &SourceManager::new(),
+            &StyleRewriteConfig::default(),
+            &InlineSprites::new(),
+        );
+        assert_eq!(
+            result["sprite"][0],
+            "http://localhost:8080/styles/basic/sprite"
+        );
+        assert_eq!(result["sprite"][1], "https://sprites.example.com/icons");
+    }
+
+    /// A 1x1 PNG as a data URI
+    fn inline_png() -> String {
+        use base64::Engine;
+
+        let mut png = Vec::new();
+        image::RgbaImage::new(1, 1)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        format!(
+            "data:image/png;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(png)
+        )
+    }
+
+    #[test]
+    fn test_rewrite_style_for_native_inline_sprite() {
+        let style = json!({"version": 8, "sources": {}, "sprite": inline_png()});
+        let inline_sprites = InlineSprites::new();
+
+        // Clients get the data URI as it is
+        let api =
+            rewrite_style_for_api(&style, "http://localhost:8080", &UrlQueryParams::default());
+        assert_eq!(api["sprite"], style["sprite"]);
+
+        let native = rewrite_style_for_native(
+            &style,
+            "test",
+            "http://localhost:8080",
+            &SourceManager::new(),
+            &StyleRewriteConfig::default(),
+            &inline_sprites,
+        );
+        let url = native["sprite"].as_str().unwrap();
+        assert!(url.starts_with("file://"), "{}", url);
+        assert!(inline_sprites.read(&format!("{}.png", url)).is_some());
+
+        let rewrite = StyleRewriteConfig {
+            extract_data_uris: false,
+            ..Default::default()
+        };
+        let native = rewrite_style_for_native(
+            &style,
+            "test",
+            "http://localhost:8080",
+            &SourceManager::new(),
+            &rewrite,
+            &InlineSprites::new(),
+        );
+        assert_eq!(native["sprite"], style["sprite"]);
+    }
+
     #[test]
     fn test_style_info_to_info() {
         let style = Style {
@@ -598,6 +1843,7 @@ mod tests {
             name: "My Style".to_string(),
             style_json: json!({}),
             path: PathBuf::from("/styles/my-style/style.json"),
+            raster: Default::default(),
         };
 
         let info = style.to_info("http://localhost:8080");
@@ -608,4 +1854,26 @@ mod tests {
             Some("http://localhost:8080/styles/my-style/style.json".to_string())
         );
     }
+
+    #[test]
+    fn test_style_data_source_ids() {
+        let style = Style {
+            id: "my-style".to_string(),
+            name: "My Style".to_string(),
+            style_json: json!({
+                "sources": {
+                    "local": { "type": "vector", "url": "/data/protomaps.json" },
+                    "absolute": { "type": "vector", "url": "http://localhost:8080/data/zurich.json" },
+                    "external": { "type": "vector", "url": "https://example.com/tiles.json" },
+                    "inline": { "type": "geojson", "data": {} }
+                }
+            }),
+            path: PathBuf::from("/styles/my-style/style.json"),
+            raster: Default::default(),
+        };
+
+        let mut ids = style.data_source_ids();
+        ids.sort();
+        assert_eq!(ids, vec!["protomaps".to_string(), "zurich".to_string()]);
+    }
 }
@@ -0,0 +1,253 @@
+//! JSON patches for generating style variants
+//!
+//! A patch is either an RFC 7386 merge patch (an object merged into the
+//! style) or an RFC 6902 JSON Patch (an array of operations).
+
+use serde_json::{Map, Value};
+
+use crate::error::{Result, TileServerError};
+
+/// Apply a merge patch (object) or a JSON Patch (array) to `target`
+///
+/// A JSON Patch either applies as a whole or leaves `target` unchanged.
+pub fn apply(target: &mut Value, patch: &Value) -> Result<()> {
+    match patch {
+        Value::Array(operations) => {
+            let mut patched = target.clone();
+            for (index, operation) in operations.iter().enumerate() {
+                apply_operation(&mut patched, operation).map_err(|message| {
+                    TileServerError::ConfigError(format!(
+                        "Invalid style patch operation {}: {}",
+                        index, message
+                    ))
+                })?;
+            }
+            *target = patched;
+        }
+        patch => merge(target, patch),
+    }
+    Ok(())
+}
+
+/// Apply an RFC 7386 merge patch: objects merge key by key, `null` removes a
+/// key and anything else replaces the target
+pub fn merge(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let Value::Object(target) = target else {
+        return;
+    };
+
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge(target.entry(key.as_str()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+/// Apply one RFC 6902 operation
+fn apply_operation(target: &mut Value, operation: &Value) -> std::result::Result<(), String> {
+    let field = |name: &str| {
+        operation
+            .get(name)
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| format!("missing '{}'", name))
+    };
+    let value = || {
+        operation
+            .get("value")
+            .cloned()
+            .ok_or_else(|| "missing 'value'".to_string())
+    };
+    let path = field("path")?;
+
+    match field("op")? {
+        "add" => add(target, path, value()?),
+        "remove" => remove(target, path).map(|_| ()),
+        "replace" => {
+            let slot = target
+                .pointer_mut(path)
+                .ok_or_else(|| format!("path '{}' does not exist", path))?;
+            *slot = value()?;
+            Ok(())
+        }
+        "move" => {
+            let from = field("from")?;
+            if path.starts_with(from) && path[from.len()..].starts_with('/') {
+                return Err(format!("cannot move '{}' into itself", from));
+            }
+            let moved = remove(target, from)?;
+            add(target, path, moved)
+        }
+        "copy" => {
+            let from = field("from")?;
+            let copied = target
+                .pointer(from)
+                .cloned()
+                .ok_or_else(|| format!("path '{}' does not exist", from))?;
+            add(target, path, copied)
+        }
+        "test" => match target.pointer(path) {
+            Some(actual) if *actual == value()? => Ok(()),
+            _ => Err(format!("test failed at '{}'", path)),
+        },
+        op => Err(format!("unknown op '{}'", op)),
+    }
+}
+
+/// Split a JSON pointer into its parent pointer and unescaped last token
+fn split_pointer(path: &str) -> std::result::Result<(&str, String), String> {
+    let (parent, token) = path
+        .rsplit_once('/')
+        .ok_or_else(|| format!("invalid path '{}'", path))?;
+    Ok((parent, token.replace("~1", "/").replace("~0", "~")))
+}
+
+fn add(target: &mut Value, path: &str, value: Value) -> std::result::Result<(), String> {
+    if path.is_empty() {
+        *target = value;
+        return Ok(());
+    }
+    let (parent, token) = split_pointer(path)?;
+    match target.pointer_mut(parent) {
+        Some(Value::Object(object)) => {
+            object.insert(token, value);
+            Ok(())
+        }
+        Some(Value::Array(array)) if token == "-" => {
+            array.push(value);
+            Ok(())
+        }
+        Some(Value::Array(array)) => match token.parse::<usize>() {
+            Ok(index) if index <= array.len() => {
+                array.insert(index, value);
+                Ok(())
+            }
+            _ => Err(format!("index '{}' out of bounds", token)),
+        },
+        _ => Err(format!("parent of '{}' does not exist", path)),
+    }
+}
+
+fn remove(target: &mut Value, path: &str) -> std::result::Result<Value, String> {
+    let (parent, token) = split_pointer(path)?;
+    let removed = match target.pointer_mut(parent) {
+        Some(Value::Object(object)) => object.remove(&token),
+        Some(Value::Array(array)) => match token.parse::<usize>() {
+            Ok(index) if index < array.len() => Some(array.remove(index)),
+            _ => None,
+        },
+        _ => None,
+    };
+    removed.ok_or_else(|| format!("path '{}' does not exist", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge_patch() {
+        // Example from RFC 7386 section 3
+        let mut target = json!({
+            "title": "Goodbye!",
+            "author": {"givenName": "John", "familyName": "Doe"},
+            "tags": ["example", "sample"],
+            "content": "This will be unchanged"
+        });
+        apply(
+            &mut target,
+            &json!({
+                "title": "Hello!",
+                "phoneNumber": "+01-123-456-7890",
+                "author": {"familyName": null},
+                "tags": ["example"]
+            }),
+        )
+        .unwrap();
+
+        assert_eq!(
+            target,
+            json!({
+                "title": "Hello!",
+                "author": {"givenName": "John"},
+                "tags": ["example"],
+                "content": "This will be unchanged",
+                "phoneNumber": "+01-123-456-7890"
+            })
+        );
+    }
+
+    #[test]
+    fn test_json_patch() {
+        let mut target = json!({
+            "layers": [
+                {"id": "background", "paint": {"background-color": "#fff"}},
+                {"id": "water"}
+            ],
+            "metadata": {"a/b": 1}
+        });
+        apply(
+            &mut target,
+            &json!([
+                {"op": "test", "path": "/layers/0/id", "value": "background"},
+                {"op": "replace", "path": "/layers/0/paint/background-color", "value": "#111"},
+                {"op": "add", "path": "/layers/-", "value": {"id": "labels"}},
+                {"op": "add", "path": "/layers/1", "value": {"id": "land"}},
+                {"op": "remove", "path": "/layers/2"},
+                {"op": "copy", "from": "/layers/0/id", "path": "/name"},
+                {"op": "move", "from": "/metadata/a~1b", "path": "/metadata/c"}
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(
+            target,
+            json!({
+                "layers": [
+                    {"id": "background", "paint": {"background-color": "#111"}},
+                    {"id": "land"},
+                    {"id": "labels"}
+                ],
+                "metadata": {"c": 1},
+                "name": "background"
+            })
+        );
+    }
+
+    #[test]
+    fn test_failed_json_patch_leaves_target_unchanged() {
+        let original = json!({"layers": [{"id": "background"}]});
+        let mut target = original.clone();
+
+        let err = apply(
+            &mut target,
+            &json!([
+                {"op": "add", "path": "/name", "value": "dark"},
+                {"op": "remove", "path": "/layers/3"}
+            ]),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("operation 1"));
+        assert_eq!(target, original);
+
+        for operation in [
+            json!({"op": "test", "path": "/layers/0/id", "value": "water"}),
+            json!({"op": "replace", "path": "/missing", "value": 1}),
+            json!({"op": "move", "from": "/layers", "path": "/layers/0/x"}),
+            json!({"op": "frobnicate", "path": "/name"}),
+            json!({"op": "add", "path": "/name"}),
+        ] {
+            assert!(apply(&mut target, &json!([operation])).is_err());
+        }
+        assert_eq!(target, original);
+    }
+}
@@ -0,0 +1,401 @@
+//! Zoom range and bounds of a style's raster tiles
+//!
+//! Styles may set `minzoom`, `maxzoom` and `bounds` in the config. Unset
+//! values are derived from the data sources the style references, so a style
+//! over a z14 dataset is not rendered at z22. Requests outside the extent
+//! get a 404, or with `blank_outside = true` a tile filled with the style's
+//! background color, which needs no rendering.
+
+use serde::Serialize;
+
+use super::{data_source_id, Style};
+use crate::config::StyleConfig;
+use crate::ogcapi::{tile_matrix_limits, MAX_LATITUDE};
+use crate::render::overlay::parse_hex_color;
+use crate::render::solid::TRANSPARENT;
+use crate::sources::{SourceManager, TileMetadata};
+
+/// Highest zoom raster tiles are rendered at
+pub const MAX_RASTER_ZOOM: u8 = 22;
+
+/// The whole Web Mercator world as [west, south, east, north]
+const WORLD_BOUNDS: [f64; 4] = [-180.0, -MAX_LATITUDE, 180.0, MAX_LATITUDE];
+
+/// Raster tile limits configured for a style
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RasterLimits {
+    pub minzoom: Option<u8>,
+    pub maxzoom: Option<u8>,
+    /// Bounds as [west, south, east, north]
+    pub bounds: Option<[f64; 4]>,
+    /// Serve transparent tiles outside the extent instead of 404
+    pub blank_outside: bool,
+}
+
+impl From<&StyleConfig> for RasterLimits {
+    fn from(config: &StyleConfig) -> Self {
+        Self {
+            minzoom: config.minzoom,
+            maxzoom: config.maxzoom,
+            bounds: config.bounds,
+            blank_outside: config.blank_outside,
+        }
+    }
+}
+
+/// Zoom range and bounds a style's raster tiles are served for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RasterExtent {
+    pub minzoom: u8,
+    pub maxzoom: u8,
+    /// Bounds as [west, south, east, north]
+    pub bounds: [f64; 4],
+}
+
+impl Default for RasterExtent {
+    fn default() -> Self {
+        Self {
+            minzoom: 0,
+            maxzoom: MAX_RASTER_ZOOM,
+            bounds: WORLD_BOUNDS,
+        }
+    }
+}
+
+impl RasterExtent {
+    /// Whether tile `z/x/y` (XYZ row) overlaps the extent
+    pub fn contains(&self, z: u8, x: u32, y: u32) -> bool {
+        if z < self.minzoom || z > self.maxzoom {
+            return false;
+        }
+        let limits = tile_matrix_limits(z, &self.bounds);
+        (limits.min_tile_row..=limits.max_tile_row).contains(&y)
+            && (limits.min_tile_col..=limits.max_tile_col).contains(&x)
+    }
+
+    /// Smallest extent covering both
+    fn union(self, other: Self) -> Self {
+        let [w1, s1, e1, n1] = self.bounds;
+        let [w2, s2, e2, n2] = other.bounds;
+        Self {
+            minzoom: self.minzoom.min(other.minzoom),
+            maxzoom: self.maxzoom.max(other.maxzoom),
+            bounds: [w1.min(w2), s1.min(s2), e1.max(e2), n1.max(n2)],
+        }
+    }
+
+    fn from_source(metadata: &TileMetadata) -> Self {
+        Self {
+            minzoom: metadata.minzoom,
+            maxzoom: if metadata.overzoom {
+                MAX_RASTER_ZOOM
+            } else {
+                metadata.maxzoom.min(MAX_RASTER_ZOOM)
+            },
+            bounds: metadata.bounds.unwrap_or(WORLD_BOUNDS),
+        }
+    }
+}
+
+/// TileJSON for the raster tiles of a style
+#[derive(Debug, Clone, Serialize)]
+pub struct RasterTileJson {
+    pub tilejson: &'static str,
+    pub name: String,
+    pub tiles: Vec<String>,
+    pub minzoom: u8,
+    pub maxzoom: u8,
+    pub bounds: [f64; 4],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attribution: Option<String>,
+    /// Small static render of the style's extent; only filled in by
+    /// `/index.json`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<String>,
+    /// HTML preview page; only filled in by `/index.json`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preview: Option<String>,
+}
+
+impl Style {
+    /// Extent of the style's raster tiles
+    ///
+    /// Configured values win. The rest are the union of the extents of the
+    /// style's sources, or the whole world at zooms 0 to 22 when a source is
+    /// not served from `/data` (such as a remote or GeoJSON source).
+    pub fn raster_extent(&self, sources: &SourceManager) -> RasterExtent {
+        let derived = self
+            .style_json
+            .get("sources")
+            .and_then(|s| s.as_object())
+            .filter(|style_sources| !style_sources.is_empty())
+            .and_then(|style_sources| {
+                style_sources
+                    .values()
+                    .map(|source| {
+                        let id = source
+                            .get("url")
+                            .and_then(|u| u.as_str())
+                            .and_then(data_source_id)?;
+                        let source = sources.get(id)?;
+                        Some(RasterExtent::from_source(source.metadata()))
+                    })
+                    .collect::<Option<Vec<_>>>()
+            })
+            .and_then(|extents| extents.into_iter().reduce(RasterExtent::union))
+            .unwrap_or_default();
+
+        let limits = &self.raster;
+        RasterExtent {
+            minzoom: limits.minzoom.unwrap_or(derived.minzoom),
+            maxzoom: limits
+                .maxzoom
+                .unwrap_or(derived.maxzoom)
+                .min(MAX_RASTER_ZOOM),
+            bounds: limits.bounds.unwrap_or(derived.bounds),
+        }
+    }
+
+    /// Color of the style's background, as RGBA
+    ///
+    /// This is the color of the topmost visible background layer, or
+    /// transparent without one. `None` when it can't be known without
+    /// rendering: a pattern, an expression or an unsupported color syntax.
+    pub fn background_color(&self) -> Option<[u8; 4]> {
+        let layers = self.style_json.get("layers").and_then(|l| l.as_array());
+        let Some(background) = layers.into_iter().flatten().rev().find(|layer| {
+            layer.get("type").and_then(|t| t.as_str()) == Some("background")
+                && layer.pointer("/layout/visibility").and_then(|v| v.as_str()) != Some("none")
+        }) else {
+            return Some(TRANSPARENT);
+        };
+
+        let paint = background.get("paint");
+        let property = |name: &str| paint.and_then(|p| p.get(name));
+        if property("background-pattern").is_some() {
+            return None;
+        }
+        // Style spec defaults: black, fully opaque
+        let [r, g, b, a] = match property("background-color") {
+            Some(color) => parse_css_color(color.as_str()?)?,
+            None => [0, 0, 0, 255],
+        };
+        let opacity = match property("background-opacity") {
+            Some(opacity) => opacity.as_f64()?.clamp(0.0, 1.0),
+            None => 1.0,
+        };
+        Some([r, g, b, (a as f64 * opacity).round() as u8])
+    }
+
+    /// TileJSON for the style's raster tiles, with `key` appended to the
+    /// tile URL
+    pub fn raster_tilejson(
+        &self,
+        base_url: &str,
+        key: Option<&str>,
+        sources: &SourceManager,
+    ) -> RasterTileJson {
+        let key_query = key
+            .map(|k| format!("?key={}", urlencoding::encode(k)))
+            .unwrap_or_default();
+        let extent = self.raster_extent(sources);
+
+        RasterTileJson {
+            tilejson: "3.0.0",
+            name: self.name.clone(),
+            tiles: vec![format!(
+                "{}/styles/{}/{{z}}/{{x}}/{{y}}.png{}",
+                base_url, self.id, key_query
+            )],
+            minzoom: extent.minzoom,
+            maxzoom: extent.maxzoom,
+            bounds: extent.bounds,
+            attribution: None,
+            thumbnail: None,
+            preview: None,
+        }
+    }
+}
+
+impl RasterTileJson {
+    /// URL of a 256x160 static render fitted to the tileset's bounds
+    pub fn thumbnail_url(&self, base_url: &str, id: &str, key: Option<&str>) -> String {
+        let key_query = key
+            .map(|k| format!("?key={}", urlencoding::encode(k)))
+            .unwrap_or_default();
+        let [west, south, east, north] = self.bounds;
+        format!(
+            "{}/styles/{}/static/{},{},{},{}/256x160.png{}",
+            base_url, id, west, south, east, north, key_query
+        )
+    }
+}
+
+/// Parse a constant CSS color: hex, `rgb()`, `rgba()`, `hsl()`, `hsla()`,
+/// `black`, `white` or `transparent`
+fn parse_css_color(color: &str) -> Option<[u8; 4]> {
+    let color = color.trim().to_ascii_lowercase();
+    match color.as_str() {
+        "transparent" => return Some(TRANSPARENT),
+        "black" => return Some([0, 0, 0, 255]),
+        "white" => return Some([255, 255, 255, 255]),
+        _ => {}
+    }
+    if color.starts_with('#') {
+        return parse_hex_color(&color).map(|c| c.0);
+    }
+
+    let (function, args) = color.strip_suffix(')')?.split_once('(')?;
+    let args: Vec<&str> = args.split(',').map(str::trim).collect();
+    let alpha = match args.get(3) {
+        Some(alpha) => alpha.parse::<f64>().ok()?.clamp(0.0, 1.0),
+        None => 1.0,
+    };
+    let alpha = (alpha * 255.0).round() as u8;
+
+    match (function, &args[..]) {
+        ("rgb" | "rgba", [r, g, b, ..]) if args.len() <= 4 => {
+            let channel = |c: &str| c.parse::<f64>().ok().map(|c| c.clamp(0.0, 255.0) as u8);
+            Some([channel(r)?, channel(g)?, channel(b)?, alpha])
+        }
+        ("hsl" | "hsla", [h, s, l, ..]) if args.len() <= 4 => {
+            let percent = |p: &str| p.strip_suffix('%')?.parse::<f64>().ok();
+            let (h, s, l) = (
+                h.parse::<f64>().ok()?.rem_euclid(360.0),
+                percent(s)?.clamp(0.0, 100.0) / 100.0,
+                percent(l)?.clamp(0.0, 100.0) / 100.0,
+            );
+            let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+            let x = c * (1.0 - ((h / 60.0) % 2.0 - 1.0).abs());
+            let (r, g, b) = match (h / 60.0) as u8 {
+                0 => (c, x, 0.0),
+                1 => (x, c, 0.0),
+                2 => (0.0, c, x),
+                3 => (0.0, x, c),
+                4 => (x, 0.0, c),
+                _ => (c, 0.0, x),
+            };
+            let channel = |v: f64| ((v + l - c / 2.0) * 255.0).round() as u8;
+            Some([channel(r), channel(g), channel(b), alpha])
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::path::PathBuf;
+
+    fn style(style_json: serde_json::Value, raster: RasterLimits) -> Style {
+        Style {
+            id: "test".to_string(),
+            name: "Test".to_string(),
+            style_json,
+            path: PathBuf::new(),
+            raster,
+        }
+    }
+
+    #[test]
+    fn test_extent_contains() {
+        let extent = RasterExtent {
+            minzoom: 2,
+            maxzoom: 14,
+            bounds: [11.22, 43.74, 11.29, 43.79],
+        };
+
+        // Florence
+        assert!(extent.contains(14, 8704, 5972));
+        assert!(extent.contains(2, 2, 1));
+        assert!(!extent.contains(15, 17408, 11944));
+        assert!(!extent.contains(1, 1, 0));
+        assert!(!extent.contains(14, 0, 0));
+    }
+
+    #[test]
+    fn test_background_color() {
+        let background = |paint: serde_json::Value| {
+            style(
+                json!({"layers": [
+                    {"id": "bg", "type": "background", "paint": paint},
+                    {"id": "water", "type": "fill", "source": "osm"}
+                ]}),
+                RasterLimits::default(),
+            )
+            .background_color()
+        };
+
+        assert_eq!(
+            background(json!({"background-color": "#aad3df"})),
+            Some([170, 211, 223, 255])
+        );
+        assert_eq!(
+            background(json!({"background-color": "rgba(255, 0, 0, 0.5)"})),
+            Some([255, 0, 0, 128])
+        );
+        assert_eq!(
+            background(
+                json!({"background-color": "hsl(120, 100%, 25%)", "background-opacity": 0.5})
+            ),
+            Some([0, 128, 0, 128])
+        );
+        assert_eq!(background(json!({})), Some([0, 0, 0, 255]));
+
+        // Only known by rendering
+        assert_eq!(
+            background(
+                json!({"background-color": ["interpolate", ["linear"], ["zoom"], 0, "#fff", 10, "#000"]})
+            ),
+            None
+        );
+        assert_eq!(background(json!({"background-pattern": "dots"})), None);
+        assert_eq!(
+            background(json!({"background-color": "cornflowerblue"})),
+            None
+        );
+
+        // Hidden or missing background layers leave the tile transparent
+        let hidden = style(
+            json!({"layers": [{"id": "bg", "type": "background", "layout": {"visibility": "none"}}]}),
+            RasterLimits::default(),
+        );
+        assert_eq!(hidden.background_color(), Some(TRANSPARENT));
+        assert_eq!(
+            style(json!({}), RasterLimits::default()).background_color(),
+            Some(TRANSPARENT)
+        );
+    }
+
+    #[test]
+    fn test_extent_without_local_sources() {
+        let sources = SourceManager::new();
+
+        // No sources, remote sources and missing local sources render everywhere
+        for style_json in [
+            json!({"sources": {}}),
+            json!({"sources": {"osm": {"type": "vector", "url": "https://example.com/tiles.json"}}}),
+            json!({"sources": {"osm": {"type": "vector", "url": "/data/missing.json"}}}),
+        ] {
+            let extent = style(style_json, RasterLimits::default()).raster_extent(&sources);
+            assert_eq!(extent, RasterExtent::default());
+        }
+
+        let limits = RasterLimits {
+            minzoom: Some(3),
+            maxzoom: Some(30),
+            bounds: Some([5.9, 45.8, 10.5, 47.8]),
+            blank_outside: false,
+        };
+        let extent = style(json!({}), limits).raster_extent(&sources);
+        assert_eq!(
+            extent,
+            RasterExtent {
+                minzoom: 3,
+                maxzoom: MAX_RASTER_ZOOM,
+                bounds: [5.9, 45.8, 10.5, 47.8],
+            }
+        );
+    }
+}
@@ -0,0 +1,516 @@
+//! Sprite sheets served from a style's directory
+//!
+//! `/styles/{style}/sprite[@2x].{png,json}` serves the `sprite*` files next
+//! to style.json. Styles with several sprites (`"sprite": [{"id", "url"}]`)
+//! give each local one the URL `/styles/{style}/{id}/sprite`, with its files
+//! in an `{id}` subdirectory. Scales missing on disk, such as `@3x`, are
+//! scaled from the nearest scale that is there.
+//!
+//! `sprite[@{n}x].webp` serves the atlas as lossless WebP, from a `.webp`
+//! file on disk or transcoded from the PNG. Icons keep their sharp edges and
+//! exact alpha, at the cost of larger files than lossy WebP would give.
+
+use std::cmp::Reverse;
+use std::path::{Path, PathBuf};
+
+use super::Style;
+use crate::error::{Result, TileServerError};
+
+/// Highest `@{n}x` scale served
+pub const MAX_SCALE: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpriteFormat {
+    Json,
+    Png,
+    /// Lossless WebP
+    Webp,
+}
+
+impl SpriteFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            SpriteFormat::Json => "application/json",
+            SpriteFormat::Png => "image/png",
+            SpriteFormat::Webp => "image/webp",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            SpriteFormat::Json => "json",
+            SpriteFormat::Png => "png",
+            SpriteFormat::Webp => "webp",
+        }
+    }
+}
+
+/// A requested sprite file, such as `sprite@2x.png`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpriteFile {
+    pub scale: u32,
+    pub format: SpriteFormat,
+}
+
+impl SpriteFile {
+    /// Parse `sprite.{png,webp,json}` or `sprite@{n}x.{png,webp,json}` with `n` from 2
+    /// to [`MAX_SCALE`]; anything else, including paths, is `None`
+    pub fn parse(name: &str) -> Option<Self> {
+        let rest = name.strip_prefix("sprite")?;
+        let (scale, extension) = match rest.strip_prefix('@') {
+            Some(rest) => {
+                let (digits, extension) = rest.split_once("x.")?;
+                let scale: u32 = digits.parse().ok()?;
+                // Rejects "@1x", "@02x" and "@+2x"
+                if !(2..=MAX_SCALE).contains(&scale) || digits != scale.to_string() {
+                    return None;
+                }
+                (scale, extension)
+            }
+            None => (1, rest.strip_prefix('.')?),
+        };
+        let format = match extension {
+            "json" => SpriteFormat::Json,
+            "png" => SpriteFormat::Png,
+            "webp" => SpriteFormat::Webp,
+            _ => return None,
+        };
+        Some(Self { scale, format })
+    }
+
+    pub fn name(self) -> String {
+        let extension = self.format.extension();
+        match self.scale {
+            1 => format!("sprite.{}", extension),
+            scale => format!("sprite@{}x.{}", scale, extension),
+        }
+    }
+}
+
+impl Style {
+    /// Directory holding the files of sprite `id`, or of the style's
+    /// `sprite*` files without an id
+    ///
+    /// `id` must name an entry of the style's sprite array whose URL is
+    /// `/styles/{style}/{id}/sprite`.
+    pub fn sprite_dir(&self, id: Option<&str>) -> Result<PathBuf> {
+        let not_found = || {
+            let name = id.map_or("sprite".to_string(), |id| format!("{}/sprite", id));
+            TileServerError::SpriteNotFound(name)
+        };
+        // Inline styles have no directory to serve sprites from
+        let style_dir = self.path.parent().ok_or_else(not_found)?;
+        let Some(id) = id else {
+            return Ok(style_dir.to_path_buf());
+        };
+
+        if !is_valid_sprite_id(id) {
+            return Err(not_found());
+        }
+        let local_url = format!("/styles/{}/{}/sprite", self.id, id);
+        let listed = self
+            .style_json
+            .get("sprite")
+            .and_then(|sprite| sprite.as_array())
+            .into_iter()
+            .flatten()
+            .any(|entry| {
+                entry.get("id").and_then(|v| v.as_str()) == Some(id)
+                    && entry
+                        .get("url")
+                        .and_then(|v| v.as_str())
+                        .is_some_and(|url| url.split('?').next() == Some(local_url.as_str()))
+            });
+        if !listed {
+            return Err(not_found());
+        }
+        Ok(style_dir.join(id))
+    }
+}
+
+/// Sprite ids become directory names, so only plain names are accepted
+fn is_valid_sprite_id(id: &str) -> bool {
+    !id.is_empty()
+        && !id.starts_with('.')
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Whether `dir` has both files of a sprite at some scale
+pub fn has_sprite(dir: &Path) -> bool {
+    (1..=MAX_SCALE).any(|scale| has_scale(dir, scale))
+}
+
+/// Both files must be there so that the atlas and its index match
+fn has_scale(dir: &Path, scale: u32) -> bool {
+    [SpriteFormat::Json, SpriteFormat::Png]
+        .into_iter()
+        .all(|format| dir.join(SpriteFile { scale, format }.name()).is_file())
+}
+
+/// Icons of the sprite in `dir`, from the index at the lowest scale on disk
+///
+/// `None` if there is no index or it can't be parsed.
+pub fn read_index(dir: &Path) -> Option<serde_json::Map<String, serde_json::Value>> {
+    let path = (1..=MAX_SCALE)
+        .map(|scale| {
+            dir.join(
+                SpriteFile {
+                    scale,
+                    format: SpriteFormat::Json,
+                }
+                .name(),
+            )
+        })
+        .find(|path| path.is_file())?;
+    let data = std::fs::read(path).ok()?;
+    serde_json::from_slice(&data).ok()
+}
+
+/// Read `file` from `dir`, scaling it from the nearest scale on disk if
+/// that scale isn't there
+///
+/// A WebP atlas missing on disk is transcoded from the PNG one.
+pub async fn read_sprite(dir: &Path, file: SpriteFile) -> Result<Vec<u8>> {
+    if file.format != SpriteFormat::Webp {
+        return read_stored(dir, file).await;
+    }
+    if let Ok(data) = tokio::fs::read(dir.join(file.name())).await {
+        return Ok(data);
+    }
+
+    let png = SpriteFile {
+        format: SpriteFormat::Png,
+        ..file
+    };
+    let data = read_stored(dir, png).await?;
+    tokio::task::spawn_blocking(move || encode_webp_lossless(&data))
+        .await
+        .map_err(|e| TileServerError::RenderError(format!("Task failed: {}", e)))?
+}
+
+/// Read a JSON or PNG sprite file, scaling it if needed
+async fn read_stored(dir: &Path, file: SpriteFile) -> Result<Vec<u8>> {
+    let path = dir.join(file.name());
+    match tokio::fs::read(&path).await {
+        Ok(data) => return Ok(data),
+        Err(e) => tracing::debug!("Sprite file not found: {} ({})", path.display(), e),
+    }
+
+    let not_found = || TileServerError::SpriteNotFound(file.name());
+    // Nearest first; on a tie, scaling down from the larger one looks better
+    let mut scales: Vec<u32> = (1..=MAX_SCALE).filter(|&s| s != file.scale).collect();
+    scales.sort_by_key(|&s| (s.abs_diff(file.scale), Reverse(s)));
+    let from = scales
+        .into_iter()
+        .find(|&scale| has_scale(dir, scale))
+        .ok_or_else(not_found)?;
+
+    let source = SpriteFile {
+        scale: from,
+        format: file.format,
+    };
+    let data = tokio::fs::read(dir.join(source.name()))
+        .await
+        .map_err(|_| not_found())?;
+    tracing::debug!(
+        "Scaling sprite {} from {} in {}",
+        file.name(),
+        source.name(),
+        dir.display()
+    );
+
+    match file.format {
+        SpriteFormat::Json => scale_index(&data, from, file.scale),
+        SpriteFormat::Png | SpriteFormat::Webp => {
+            let to = file.scale;
+            tokio::task::spawn_blocking(move || scale_atlas(&data, from, to))
+                .await
+                .map_err(|e| TileServerError::RenderError(format!("Task failed: {}", e)))?
+        }
+    }
+}
+
+fn scaled(value: u32, from: u32, to: u32) -> u32 {
+    (f64::from(value) * f64::from(to) / f64::from(from)).round() as u32
+}
+
+/// Resize a sprite image from `@{from}x` to `@{to}x`
+pub fn scale_atlas(data: &[u8], from: u32, to: u32) -> Result<Vec<u8>> {
+    use image::{imageops::FilterType, ImageFormat};
+
+    let image = image::load_from_memory_with_format(data, ImageFormat::Png)
+        .map_err(|e| TileServerError::RenderError(format!("Failed to decode sprite: {}", e)))?;
+    let width = scaled(image.width(), from, to).max(1);
+    let height = scaled(image.height(), from, to).max(1);
+
+    let mut buffer = std::io::Cursor::new(Vec::new());
+    image
+        .resize_exact(width, height, FilterType::CatmullRom)
+        .write_to(&mut buffer, ImageFormat::Png)
+        .map_err(|e| TileServerError::RenderError(format!("Failed to encode sprite: {}", e)))?;
+    Ok(buffer.into_inner())
+}
+
+/// Re-encode a PNG sprite image as lossless WebP, keeping every RGBA value
+pub fn encode_webp_lossless(png: &[u8]) -> Result<Vec<u8>> {
+    use image::{codecs::webp::WebPEncoder, ExtendedColorType, ImageFormat};
+
+    let image = image::load_from_memory_with_format(png, ImageFormat::Png)
+        .map_err(|e| TileServerError::RenderError(format!("Failed to decode sprite: {}", e)))?
+        .into_rgba8();
+    let mut buffer = Vec::new();
+    WebPEncoder::new_lossless(&mut buffer)
+        .encode(
+            image.as_raw(),
+            image.width(),
+            image.height(),
+            ExtendedColorType::Rgba8,
+        )
+        .map_err(|e| TileServerError::RenderError(format!("Failed to encode sprite: {}", e)))?;
+    Ok(buffer)
+}
+
+/// Scale the positions, sizes and `pixelRatio` of a sprite index from
+/// `@{from}x` to `@{to}x`, matching [`scale_atlas`]
+pub fn scale_index(data: &[u8], from: u32, to: u32) -> Result<Vec<u8>> {
+    let mut index: serde_json::Value = serde_json::from_slice(data)
+        .map_err(|e| TileServerError::MetadataError(format!("Invalid sprite index: {}", e)))?;
+
+    let scale = |value: &mut serde_json::Value| {
+        if let Some(n) = value.as_f64() {
+            *value = ((n * f64::from(to) / f64::from(from)).round() as i64).into();
+        }
+    };
+    for icon in index
+        .as_object_mut()
+        .into_iter()
+        .flat_map(|icons| icons.values_mut())
+    {
+        let Some(icon) = icon.as_object_mut() else {
+            continue;
+        };
+        for key in ["x", "y", "width", "height"] {
+            if let Some(value) = icon.get_mut(key) {
+                scale(value);
+            }
+        }
+        if let Some(content) = icon.get_mut("content").and_then(|v| v.as_array_mut()) {
+            content.iter_mut().for_each(scale);
+        }
+        for key in ["stretchX", "stretchY"] {
+            let ranges = icon.get_mut(key).and_then(|v| v.as_array_mut());
+            for range in ranges.into_iter().flatten() {
+                range.as_array_mut().into_iter().flatten().for_each(scale);
+            }
+        }
+        icon.insert("pixelRatio".to_string(), to.into());
+    }
+
+    serde_json::to_vec(&index)
+        .map_err(|e| TileServerError::MetadataError(format!("Invalid sprite index: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageFormat, Rgba, RgbaImage};
+
+    #[test]
+    fn test_parse_sprite_file() {
+        assert_eq!(
+            SpriteFile::parse("sprite.png"),
+            Some(SpriteFile {
+                scale: 1,
+                format: SpriteFormat::Png
+            })
+        );
+        assert_eq!(
+            SpriteFile::parse("sprite@3x.json"),
+            Some(SpriteFile {
+                scale: 3,
+                format: SpriteFormat::Json
+            })
+        );
+        for name in [
+            "sprite/../../etc",
+            "sprite@2x.png/../../etc/passwd",
+            "sprite.png.bak",
+            "sprite@1x.png",
+            "sprite@02x.png",
+            "sprite@5x.png",
+            "sprite.svg",
+            "sprite.webp.png",
+            "../sprite.png",
+            "other.png",
+        ] {
+            assert_eq!(SpriteFile::parse(name), None, "{}", name);
+        }
+        assert_eq!(
+            SpriteFile::parse("sprite@2x.png").unwrap().name(),
+            "sprite@2x.png"
+        );
+    }
+
+    fn style(dir: &Path, sprite: serde_json::Value) -> Style {
+        Style {
+            id: "basic".to_string(),
+            name: "Basic".to_string(),
+            style_json: serde_json::json!({ "version": 8, "sprite": sprite }),
+            path: dir.join("style.json"),
+            raster: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_sprite_dir_by_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let style = style(
+            dir.path(),
+            serde_json::json!([
+                { "id": "default", "url": "/styles/basic/default/sprite" },
+                { "id": "maki", "url": "/styles/basic/maki/sprite?v=2" },
+                { "id": "remote", "url": "https://example.com/sprite" },
+            ]),
+        );
+
+        assert_eq!(style.sprite_dir(None).unwrap(), dir.path());
+        assert_eq!(
+            style.sprite_dir(Some("maki")).unwrap(),
+            dir.path().join("maki")
+        );
+        for id in [
+            "remote",
+            "missing",
+            "..",
+            "../maki",
+            "maki/../..",
+            "sprite/../../etc",
+        ] {
+            assert!(
+                matches!(
+                    style.sprite_dir(Some(id)),
+                    Err(TileServerError::SpriteNotFound(_))
+                ),
+                "{}",
+                id
+            );
+        }
+    }
+
+    #[test]
+    fn test_traversal_id_rejected_even_if_listed() {
+        let dir = tempfile::tempdir().unwrap();
+        let style = style(
+            dir.path(),
+            serde_json::json!([{ "id": "..", "url": "/styles/basic/../sprite" }]),
+        );
+        assert!(style.sprite_dir(Some("..")).is_err());
+    }
+
+    /// A 2-icon sprite at `@2x`: a 40x20 atlas
+    fn write_2x_sprite(dir: &Path) {
+        let atlas = RgbaImage::from_pixel(40, 20, Rgba([255, 0, 0, 255]));
+        atlas
+            .save_with_format(dir.join("sprite@2x.png"), ImageFormat::Png)
+            .unwrap();
+        let index = serde_json::json!({
+            "marker": { "x": 0, "y": 0, "width": 20, "height": 20, "pixelRatio": 2 },
+            "label": {
+                "x": 20, "y": 0, "width": 20, "height": 20, "pixelRatio": 2,
+                "content": [4, 4, 16, 16],
+                "stretchX": [[4, 16]]
+            }
+        });
+        std::fs::write(dir.join("sprite@2x.json"), index.to_string()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_synthesizes_3x() {
+        let dir = tempfile::tempdir().unwrap();
+        write_2x_sprite(dir.path());
+
+        let png = read_sprite(dir.path(), SpriteFile::parse("sprite@3x.png").unwrap())
+            .await
+            .unwrap();
+        let image = image::load_from_memory(&png).unwrap();
+        assert_eq!((image.width(), image.height()), (60, 30));
+
+        let json = read_sprite(dir.path(), SpriteFile::parse("sprite@3x.json").unwrap())
+            .await
+            .unwrap();
+        let index: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(
+            index["label"],
+            serde_json::json!({
+                "x": 30, "y": 0, "width": 30, "height": 30, "pixelRatio": 3,
+                "content": [6, 6, 24, 24],
+                "stretchX": [[6, 24]]
+            })
+        );
+
+        // Downscaled to @1x too
+        let png = read_sprite(dir.path(), SpriteFile::parse("sprite.png").unwrap())
+            .await
+            .unwrap();
+        let image = image::load_from_memory(&png).unwrap();
+        assert_eq!((image.width(), image.height()), (20, 10));
+    }
+
+    #[tokio::test]
+    async fn test_existing_scale_served_as_is() {
+        let dir = tempfile::tempdir().unwrap();
+        write_2x_sprite(dir.path());
+        let json = read_sprite(dir.path(), SpriteFile::parse("sprite@2x.json").unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            json,
+            std::fs::read(dir.path().join("sprite@2x.json")).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_webp_sprite_is_lossless() {
+        let dir = tempfile::tempdir().unwrap();
+        // Sharp edges, partial transparency and a fully transparent pixel
+        // with color, which lossy encoders are free to change
+        let icon = RgbaImage::from_fn(8, 8, |x, y| match (x + y) % 4 {
+            0 => Rgba([255, 0, 0, 255]),
+            1 => Rgba([0, 128, 255, 128]),
+            2 => Rgba([17, 34, 51, 1]),
+            _ => Rgba([250, 250, 250, 0]),
+        });
+        icon.save_with_format(dir.path().join("sprite.png"), ImageFormat::Png)
+            .unwrap();
+        std::fs::write(dir.path().join("sprite.json"), "{}").unwrap();
+
+        let webp = read_sprite(dir.path(), SpriteFile::parse("sprite.webp").unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            image::guess_format(&webp).unwrap(),
+            ImageFormat::WebP,
+            "Should be WebP"
+        );
+        let decoded = image::load_from_memory_with_format(&webp, ImageFormat::WebP)
+            .unwrap()
+            .into_rgba8();
+        assert_eq!(decoded, icon);
+
+        // Scaled atlases are transcoded too
+        let webp = read_sprite(dir.path(), SpriteFile::parse("sprite@2x.webp").unwrap())
+            .await
+            .unwrap();
+        let decoded = image::load_from_memory(&webp).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (16, 16));
+    }
+
+    #[tokio::test]
+    async fn test_missing_sprite() {
+        let dir = tempfile::tempdir().unwrap();
+        let result = read_sprite(dir.path(), SpriteFile::parse("sprite@3x.png").unwrap()).await;
+        assert!(matches!(result, Err(TileServerError::SpriteNotFound(_))));
+    }
+}
@@ -0,0 +1,446 @@
+//! Style linting at load time
+//!
+//! Catches mistakes that would otherwise only show up as blank or partial
+//! raster tiles: a wrong spec version, unknown layer types, layers pointing
+//! at sources the style doesn't define or missing their `source-layer`, and
+//! fonts or icons that can't be found. Errors make a style unusable and are
+//! rejected with `strict_styles = true`; warnings only lose labels or icons.
+
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::sprite;
+use super::Style;
+use crate::fonts::FontCatalog;
+
+/// Layer types of the MapLibre style spec
+const LAYER_TYPES: &[&str] = &[
+    "background",
+    "fill",
+    "line",
+    "symbol",
+    "circle",
+    "heatmap",
+    "fill-extrusion",
+    "raster",
+    "hillshade",
+    "color-relief",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// One problem found in a style
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Id of the layer at fault, if the problem is with a layer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layer: Option<String>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn error(layer: Option<&str>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            layer: layer.map(str::to_string),
+            message: message.into(),
+        }
+    }
+
+    fn warning(layer: Option<&str>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            layer: layer.map(str::to_string),
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.layer {
+            Some(layer) => write!(f, "layer '{}': {}", layer, self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+/// Fonts served from the fonts directory, as PBF directories or font files
+#[derive(Debug, Clone)]
+pub struct AvailableFonts {
+    dir: PathBuf,
+    files: Arc<FontCatalog>,
+}
+
+impl AvailableFonts {
+    pub fn new(dir: PathBuf, files: Arc<FontCatalog>) -> Self {
+        Self { dir, files }
+    }
+
+    pub fn contains(&self, font: &str) -> bool {
+        let is_name = !font.contains(['/', '\\']) && font != "..";
+        is_name && (self.dir.join(font).is_dir() || self.files.contains(font))
+    }
+}
+
+/// What styles are checked against when loaded
+#[derive(Debug, Clone, Default)]
+pub struct StyleChecks {
+    /// Fonts for styles with local (`/fonts/`) glyphs; not checked if `None`
+    pub fonts: Option<AvailableFonts>,
+    /// Refuse to load styles with errors
+    pub strict: bool,
+}
+
+/// Check `style` for problems, in layer order
+pub fn lint(style: &Style, fonts: Option<&AvailableFonts>) -> Vec<Diagnostic> {
+    let json = &style.style_json;
+    let mut diagnostics = Vec::new();
+
+    match json.get("version").and_then(|v| v.as_u64()) {
+        Some(8) => {}
+        Some(version) => diagnostics.push(Diagnostic::error(
+            None,
+            format!("Style version is {}, only version 8 is supported", version),
+        )),
+        None => diagnostics.push(Diagnostic::error(None, "Style has no version, expected 8")),
+    }
+
+    let sources = json.get("sources").and_then(|s| s.as_object());
+    let Some(layers) = json.get("layers").and_then(|l| l.as_array()) else {
+        diagnostics.push(Diagnostic::error(None, "Style has no layers array"));
+        return diagnostics;
+    };
+
+    let local_glyphs = json
+        .get("glyphs")
+        .and_then(|g| g.as_str())
+        .is_some_and(|g| g.starts_with("/fonts/"));
+    let fonts = fonts.filter(|_| local_glyphs);
+    let icons = sprite_icons(style);
+
+    for (index, layer) in layers.iter().enumerate() {
+        let Some(id) = layer.get("id").and_then(|v| v.as_str()) else {
+            diagnostics.push(Diagnostic::error(
+                None,
+                format!("Layer {} has no id", index),
+            ));
+            continue;
+        };
+        let layer_type = layer.get("type").and_then(|v| v.as_str()).unwrap_or("");
+        if !LAYER_TYPES.contains(&layer_type) {
+            diagnostics.push(Diagnostic::error(
+                Some(id),
+                format!("Unknown layer type '{}'", layer_type),
+            ));
+            continue;
+        }
+
+        if layer_type != "background" {
+            match layer.get("source").and_then(|v| v.as_str()) {
+                None => diagnostics.push(Diagnostic::error(Some(id), "Layer has no source")),
+                Some(source) => match sources.and_then(|s| s.get(source)) {
+                    None => diagnostics.push(Diagnostic::error(
+                        Some(id),
+                        format!("Source '{}' is not defined in the style", source),
+                    )),
+                    Some(definition) => {
+                        let is_vector =
+                            definition.get("type").and_then(|t| t.as_str()) == Some("vector");
+                        if is_vector && layer.get("source-layer").is_none() {
+                            diagnostics.push(Diagnostic::error(
+                                Some(id),
+                                format!(
+                                    "Layer uses vector source '{}' but has no source-layer",
+                                    source
+                                ),
+                            ));
+                        }
+                    }
+                },
+            }
+        }
+
+        if let Some(fonts) = fonts {
+            for font in literal_strings(layer.pointer("/layout/text-font")) {
+                if !fonts.contains(font) {
+                    diagnostics.push(Diagnostic::warning(
+                        Some(id),
+                        format!("Font '{}' not found in the fonts directory", font),
+                    ));
+                }
+            }
+        }
+
+        if let Some(icons) = &icons {
+            let icon = layer.pointer("/layout/icon-image").and_then(|v| v.as_str());
+            // Icons named after feature properties ("{class}") vary per feature
+            if let Some(icon) = icon.filter(|icon| !icon.is_empty() && !icon.contains('{')) {
+                if !icons.contains(icon) {
+                    diagnostics.push(Diagnostic::warning(
+                        Some(id),
+                        format!("Icon '{}' not found in the sprite", icon),
+                    ));
+                }
+            }
+        }
+    }
+
+    diagnostics
+}
+
+/// Strings of an array, or of an array wrapped in a `literal` expression
+fn literal_strings(value: Option<&serde_json::Value>) -> Vec<&str> {
+    let mut array = value.and_then(|v| v.as_array());
+    if let Some(items) = array {
+        if items.first().and_then(|v| v.as_str()) == Some("literal") {
+            array = items.get(1).and_then(|v| v.as_array());
+        }
+    }
+    array
+        .into_iter()
+        .flatten()
+        .filter_map(|v| v.as_str())
+        .collect()
+}
+
+/// Icon names of the style's sprites, prefixed with `{id}:` for sprites of
+/// an array other than `default`
+///
+/// `None` if any sprite isn't served from the style's directory or its index
+/// can't be read, since icons can't be checked then.
+fn sprite_icons(style: &Style) -> Option<HashSet<String>> {
+    let sprite = style.style_json.get("sprite")?;
+    let mut icons = HashSet::new();
+
+    let mut add = |dir: &Path, prefix: Option<&str>| -> Option<()> {
+        let index = sprite::read_index(dir)?;
+        icons.extend(index.keys().map(|name| match prefix {
+            Some(prefix) => format!("{}:{}", prefix, name),
+            None => name.clone(),
+        }));
+        Some(())
+    };
+
+    // Only sprites served from the style's directory can be read
+    match sprite {
+        serde_json::Value::String(url) => {
+            let local_url = format!("/styles/{}/sprite", style.id);
+            if url.split('?').next() != Some(local_url.as_str()) {
+                return None;
+            }
+            add(&style.sprite_dir(None).ok()?, None)?;
+        }
+        serde_json::Value::Array(entries) => {
+            for entry in entries {
+                let id = entry.get("id")?.as_str()?;
+                let prefix = (id != "default").then_some(id);
+                add(&style.sprite_dir(Some(id)).ok()?, prefix)?;
+            }
+        }
+        _ => return None,
+    }
+    Some(icons)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn style(dir: &Path, json: serde_json::Value) -> Style {
+        Style {
+            id: "test".to_string(),
+            name: "Test".to_string(),
+            style_json: json,
+            path: dir.join("style.json"),
+            raster: Default::default(),
+        }
+    }
+
+    fn messages(diagnostics: &[Diagnostic]) -> Vec<String> {
+        diagnostics.iter().map(|d| d.to_string()).collect()
+    }
+
+    #[test]
+    fn test_valid_style() {
+        let dir = tempfile::tempdir().unwrap();
+        let style = style(
+            dir.path(),
+            json!({
+                "version": 8,
+                "sources": {
+                    "osm": { "type": "vector", "url": "/data/osm.json" },
+                    "dem": { "type": "raster-dem", "url": "/data/dem.json" }
+                },
+                "layers": [
+                    { "id": "bg", "type": "background" },
+                    { "id": "water", "type": "fill", "source": "osm", "source-layer": "water" },
+                    { "id": "hills", "type": "hillshade", "source": "dem" }
+                ]
+            }),
+        );
+        assert_eq!(lint(&style, None), vec![]);
+    }
+
+    #[test]
+    fn test_broken_style() {
+        let dir = tempfile::tempdir().unwrap();
+        let style = style(
+            dir.path(),
+            json!({
+                "version": 7,
+                "sources": { "osm": { "type": "vector", "url": "/data/osm.json" } },
+                "layers": [
+                    { "id": "roads", "type": "line", "source": "osm" },
+                    { "id": "rivers", "type": "line", "source": "water", "source-layer": "rivers" },
+                    { "id": "labels", "type": "text", "source": "osm" },
+                    { "id": "fill", "type": "fill" },
+                    { "type": "fill", "source": "osm" }
+                ]
+            }),
+        );
+
+        let diagnostics = lint(&style, None);
+        assert!(diagnostics.iter().all(|d| d.severity == Severity::Error));
+        assert_eq!(
+            messages(&diagnostics),
+            [
+                "Style version is 7, only version 8 is supported",
+                "layer 'roads': Layer uses vector source 'osm' but has no source-layer",
+                "layer 'rivers': Source 'water' is not defined in the style",
+                "layer 'labels': Unknown layer type 'text'",
+                "layer 'fill': Layer has no source",
+                "Layer 4 has no id",
+            ]
+        );
+        assert_eq!(diagnostics[1].layer.as_deref(), Some("roads"));
+    }
+
+    #[test]
+    fn test_missing_layers() {
+        let dir = tempfile::tempdir().unwrap();
+        let style = style(dir.path(), json!({ "sources": {} }));
+        assert_eq!(
+            messages(&lint(&style, None)),
+            [
+                "Style has no version, expected 8",
+                "Style has no layers array"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_missing_fonts() {
+        let dir = tempfile::tempdir().unwrap();
+        let fonts_dir = dir.path().join("fonts");
+        std::fs::create_dir_all(fonts_dir.join("Noto Sans Regular")).unwrap();
+        let fonts = AvailableFonts::new(fonts_dir, Arc::new(FontCatalog::default()));
+
+        let layers = json!([
+            {
+                "id": "places", "type": "symbol", "source": "osm", "source-layer": "places",
+                "layout": { "text-font": ["Noto Sans Regular", "Noto Sans Bold"] }
+            },
+            {
+                "id": "roads", "type": "symbol", "source": "osm", "source-layer": "roads",
+                "layout": { "text-font": ["literal", ["../fonts"]] }
+            }
+        ]);
+        let local = style(
+            dir.path(),
+            json!({
+                "version": 8,
+                "sources": { "osm": { "type": "vector", "url": "/data/osm.json" } },
+                "glyphs": "/fonts/{fontstack}/{range}.pbf",
+                "layers": layers
+            }),
+        );
+        let diagnostics = lint(&local, Some(&fonts));
+        assert!(diagnostics.iter().all(|d| d.severity == Severity::Warning));
+        assert_eq!(
+            messages(&diagnostics),
+            [
+                "layer 'places': Font 'Noto Sans Bold' not found in the fonts directory",
+                "layer 'roads': Font '../fonts' not found in the fonts directory",
+            ]
+        );
+
+        // Remote glyphs aren't checked
+        let mut remote = local.clone();
+        remote.style_json["glyphs"] = json!("https://example.com/{fontstack}/{range}.pbf");
+        assert_eq!(lint(&remote, Some(&fonts)), vec![]);
+    }
+
+    #[test]
+    fn test_missing_icons() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("sprite.json"),
+            json!({ "bus": { "x": 0, "y": 0, "width": 8, "height": 8, "pixelRatio": 1 } })
+                .to_string(),
+        )
+        .unwrap();
+        std::fs::create_dir(dir.path().join("maki")).unwrap();
+        std::fs::write(
+            dir.path().join("maki/sprite@2x.json"),
+            json!({ "park": { "x": 0, "y": 0, "width": 16, "height": 16, "pixelRatio": 2 } })
+                .to_string(),
+        )
+        .unwrap();
+
+        let symbol = |id: &str, icon: &str| {
+            json!({
+                "id": id, "type": "symbol", "source": "osm", "source-layer": "poi",
+                "layout": { "icon-image": icon }
+            })
+        };
+        let single = style(
+            dir.path(),
+            json!({
+                "version": 8,
+                "sources": { "osm": { "type": "vector", "url": "/data/osm.json" } },
+                "sprite": "/styles/test/sprite",
+                "layers": [symbol("bus", "bus"), symbol("tram", "tram"), symbol("poi", "{class}")]
+            }),
+        );
+        assert_eq!(
+            messages(&lint(&single, None)),
+            ["layer 'tram': Icon 'tram' not found in the sprite"]
+        );
+
+        let multiple = style(
+            dir.path(),
+            json!({
+                "version": 8,
+                "sources": { "osm": { "type": "vector", "url": "/data/osm.json" } },
+                "sprite": [
+                    { "id": "default", "url": "/styles/test/default/sprite" },
+                    { "id": "maki", "url": "/styles/test/maki/sprite" }
+                ],
+                "layers": [symbol("park", "maki:park"), symbol("bus", "maki:bus")]
+            }),
+        );
+        // The default sprite has no files, so icons can't be checked
+        assert_eq!(lint(&multiple, None), vec![]);
+
+        std::fs::create_dir(dir.path().join("default")).unwrap();
+        std::fs::copy(
+            dir.path().join("sprite.json"),
+            dir.path().join("default/sprite.json"),
+        )
+        .unwrap();
+        assert_eq!(
+            messages(&lint(&multiple, None)),
+            ["layer 'bus': Icon 'maki:bus' not found in the sprite"]
+        );
+    }
+}
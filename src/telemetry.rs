@@ -96,6 +96,19 @@ where
     Some(Box::new(OpenTelemetryLayer::new(tracer)))
 }
 
+/// Span covering one HTTP request
+///
+/// Its fields, including `request_id`, are exported as attributes of the
+/// request's OpenTelemetry span.
+pub fn request_span<B>(request: &axum::http::Request<B>, request_id: &str) -> tracing::Span {
+    tracing::info_span!(
+        "request",
+        http.request.method = %request.method(),
+        url.path = %request.uri().path(),
+        request_id = %request_id,
+    )
+}
+
 pub fn shutdown_telemetry() {
     if let Some(meter_provider) = METER_PROVIDER.get() {
         if let Err(e) = meter_provider.shutdown() {
@@ -0,0 +1,218 @@
+//! Style thumbnails (`GET /styles/{style}/thumbnail.png`)
+//!
+//! A small PNG of a style at its default view: the `center`, `zoom`,
+//! `bearing` and `pitch` of its style.json. Without a renderer, a gray
+//! placeholder carrying the style's name is served instead, its label drawn
+//! from the glyphs of the style's first `text-font`.
+
+use image::{Rgba, RgbaImage};
+use prost::Message;
+use serde_json::Value;
+
+use crate::error::{Result, TileServerError};
+use crate::fonts::{Glyph, Glyphs};
+
+/// Size of thumbnails requested without `?width=` and `?height=`
+pub const DEFAULT_WIDTH: u32 = 300;
+pub const DEFAULT_HEIGHT: u32 = 200;
+
+/// Font used for placeholder labels when the style names none
+pub const DEFAULT_FONT: &str = "Noto Sans Regular";
+
+const BACKGROUND: Rgba<u8> = Rgba([224, 224, 224, 255]);
+const TEXT_COLOR: [u8; 3] = [96, 96, 96];
+
+/// Size glyph PBFs are drawn at, and the buffer around each bitmap
+const GLYPH_SIZE: f32 = 24.0;
+const GLYPH_BUFFER: i32 = 3;
+
+/// SDF value of a glyph's outline (node-fontnik's cutoff of 0.25)
+const SDF_EDGE: f32 = 192.0;
+
+/// Query parameters of a thumbnail
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct ThumbnailQuery {
+    /// Width in pixels (default 300)
+    pub width: Option<u32>,
+    /// Height in pixels (default 200)
+    pub height: Option<u32>,
+}
+
+impl ThumbnailQuery {
+    /// Requested size, with each side between 1 and `max_size`
+    ///
+    /// The defaults shrink to `max_size` when it is smaller.
+    pub fn size(&self, max_size: u32) -> Result<(u32, u32)> {
+        let width = self.width.unwrap_or(DEFAULT_WIDTH.min(max_size));
+        let height = self.height.unwrap_or(DEFAULT_HEIGHT.min(max_size));
+        if width == 0 || height == 0 || width > max_size || height > max_size {
+            return Err(TileServerError::BadRequest(format!(
+                "Invalid thumbnail size: {}x{}. Width and height must be between 1 and {}",
+                width, height, max_size
+            )));
+        }
+        Ok((width, height))
+    }
+}
+
+/// `[lon, lat, zoom]`, bearing and pitch of a style's default view
+///
+/// Styles without a `center` are shown whole, at zoom 0.
+pub fn default_view(style_json: &Value) -> ([f64; 3], f64, f64) {
+    let number = |key: &str| style_json.get(key).and_then(Value::as_f64);
+    let center = style_json
+        .get("center")
+        .and_then(Value::as_array)
+        .and_then(|center| Some((center.first()?.as_f64()?, center.get(1)?.as_f64()?)));
+    let (lon, lat, zoom) = match center {
+        Some((lon, lat)) => (lon, lat, number("zoom").unwrap_or(0.0)),
+        None => (0.0, 0.0, 0.0),
+    };
+    (
+        [lon, lat, zoom],
+        number("bearing").unwrap_or(0.0),
+        number("pitch").unwrap_or(0.0),
+    )
+}
+
+/// First font of the first `text-font` in the style's layers
+pub fn label_font(style_json: &Value) -> Option<String> {
+    style_json
+        .get("layers")?
+        .as_array()?
+        .iter()
+        .filter_map(|layer| layer.get("layout")?.get("text-font")?.as_array())
+        .find_map(|fonts| fonts.first()?.as_str().map(String::from))
+}
+
+/// Gray PNG of `width`x`height` pixels with `name` centered on it
+///
+/// `glyphs` is the `0-255` range of a font; without it, or for characters
+/// outside the range, the label is left out.
+pub fn placeholder(width: u32, height: u32, name: &str, glyphs: Option<&[u8]>) -> Result<Vec<u8>> {
+    let mut image = RgbaImage::from_pixel(width, height, BACKGROUND);
+    if let Some(glyphs) = glyphs.and_then(|pbf| Glyphs::decode(pbf).ok()) {
+        let glyphs: Vec<&Glyph> = glyphs
+            .stacks
+            .iter()
+            .flat_map(|stack| &stack.glyphs)
+            .collect();
+        let label: Vec<&Glyph> = name
+            .chars()
+            .filter_map(|c| glyphs.iter().find(|glyph| glyph.id == c as u32).copied())
+            .collect();
+        draw_label(&mut image, &label);
+    }
+
+    let mut png = std::io::Cursor::new(Vec::new());
+    image
+        .write_to(&mut png, image::ImageFormat::Png)
+        .map_err(|e| TileServerError::RenderError(format!("PNG encoding failed: {}", e)))?;
+    Ok(png.into_inner())
+}
+
+/// Draw glyphs as one centered line, shrunk to fit within the margins
+fn draw_label(image: &mut RgbaImage, label: &[&Glyph]) {
+    let advance: u32 = label.iter().map(|glyph| glyph.advance).sum();
+    if advance == 0 {
+        return;
+    }
+    let margin = 16.0;
+    let scale = ((image.width() as f32 - 2.0 * margin) / advance as f32)
+        .min((image.height() as f32 - 2.0 * margin) / GLYPH_SIZE)
+        .min(1.0);
+    if scale <= 0.0 {
+        return;
+    }
+
+    let mut pen = (image.width() as f32 - advance as f32 * scale) / 2.0;
+    // Capitals are about 0.7 of the size tall; glyph tops are measured from
+    // a line the whole size above the baseline
+    let baseline = (image.height() as f32 + GLYPH_SIZE * 0.7 * scale) / 2.0;
+    let ascender = baseline - GLYPH_SIZE * scale;
+    for glyph in label {
+        if let Some(bitmap) = &glyph.bitmap {
+            let left = pen + (glyph.left - GLYPH_BUFFER) as f32 * scale;
+            let top = ascender - (glyph.top + GLYPH_BUFFER) as f32 * scale;
+            draw_glyph(image, glyph, bitmap, left, top, scale);
+        }
+        pen += glyph.advance as f32 * scale;
+    }
+}
+
+/// Blend the SDF `bitmap` of `glyph` into the image at `left`, `top`,
+/// sampled at `scale`
+fn draw_glyph(
+    image: &mut RgbaImage,
+    glyph: &Glyph,
+    bitmap: &[u8],
+    left: f32,
+    top: f32,
+    scale: f32,
+) {
+    let bitmap_width = glyph.width as i32 + 2 * GLYPH_BUFFER;
+    let bitmap_height = glyph.height as i32 + 2 * GLYPH_BUFFER;
+    if bitmap.len() < (bitmap_width * bitmap_height) as usize {
+        return;
+    }
+    // Edge softness grows as glyphs shrink, keeping about one pixel of
+    // antialiasing
+    let softness = 16.0 / scale;
+
+    let x_end = (left + bitmap_width as f32 * scale).ceil() as i32;
+    let y_end = (top + bitmap_height as f32 * scale).ceil() as i32;
+    for y in (top.floor() as i32).max(0)..y_end.min(image.height() as i32) {
+        for x in (left.floor() as i32).max(0)..x_end.min(image.width() as i32) {
+            let bx = ((x as f32 + 0.5 - left) / scale) as i32;
+            let by = ((y as f32 + 0.5 - top) / scale) as i32;
+            if bx < 0 || by < 0 || bx >= bitmap_width || by >= bitmap_height {
+                continue;
+            }
+            let distance = bitmap[(by * bitmap_width + bx) as usize] as f32;
+            let alpha = ((distance - SDF_EDGE) / softness + 0.5).clamp(0.0, 1.0);
+            if alpha == 0.0 {
+                continue;
+            }
+            let pixel = image.get_pixel_mut(x as u32, y as u32);
+            for (channel, text) in pixel.0.iter_mut().zip(TEXT_COLOR) {
+                *channel = (*channel as f32 * (1.0 - alpha) + text as f32 * alpha).round() as u8;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_size() {
+        let query = |width, height| ThumbnailQuery { width, height };
+        assert_eq!(query(None, None).size(512).unwrap(), (300, 200));
+        assert_eq!(query(Some(512), Some(64)).size(512).unwrap(), (512, 64));
+        assert_eq!(query(None, None).size(128).unwrap(), (128, 128));
+        assert!(query(Some(513), None).size(512).is_err());
+        assert!(query(None, Some(0)).size(512).is_err());
+    }
+
+    #[test]
+    fn test_default_view() {
+        let style = json!({ "center": [8.5, 47.4], "zoom": 11, "bearing": 30, "pitch": 45 });
+        assert_eq!(default_view(&style), ([8.5, 47.4, 11.0], 30.0, 45.0));
+        assert_eq!(
+            default_view(&json!({ "zoom": 5 })),
+            ([0.0, 0.0, 0.0], 0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn test_label_font() {
+        let style = json!({ "layers": [
+            { "id": "water", "type": "fill" },
+            { "id": "places", "type": "symbol", "layout": { "text-font": ["Noto Sans Medium", "Arial"] } }
+        ] });
+        assert_eq!(label_font(&style).as_deref(), Some("Noto Sans Medium"));
+        assert_eq!(label_font(&json!({ "layers": [] })), None);
+    }
+}
@@ -0,0 +1,45 @@
+//! Request timeout middleware
+//!
+//! Bounds how long any request may take so slow renders or database queries
+//! cannot hold connections indefinitely. Requests that run out of time get a
+//! 503 `TIMEOUT` error with `Retry-After: 1`.
+
+use std::time::Duration;
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Router,
+};
+
+use crate::error::TileServerError;
+
+/// Wrap `router` in a timeout of `timeout_ms` milliseconds, 0 to disable
+pub fn apply(router: Router, timeout_ms: u64) -> Router {
+    if timeout_ms == 0 {
+        return router;
+    }
+    router.layer(middleware::from_fn_with_state(
+        Duration::from_millis(timeout_ms),
+        request_timeout,
+    ))
+}
+
+/// Middleware that abandons requests running longer than the given duration
+pub async fn request_timeout(
+    State(timeout): State<Duration>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let path = request.uri().path().to_string();
+    match tokio::time::timeout(timeout, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => {
+            tracing::warn!("Request to {} timed out after {:?}", path, timeout);
+            TileServerError::Timeout(format!("request took longer than {:?}", timeout))
+                .into_response()
+        }
+    }
+}
@@ -0,0 +1,197 @@
+//! HTTPS serving with rustls
+//!
+//! The certificate is swapped in place on SIGHUP or when the files change, so
+//! renewals (e.g. by certbot) take effect without a restart. An optional plain
+//! HTTP listener answers every request with a redirect to HTTPS.
+
+use axum::{
+    http::{header::HOST, header::LOCATION, HeaderMap, HeaderValue, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Router,
+};
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime};
+
+use axum_server::tls_rustls::RustlsAcceptor;
+pub use axum_server::tls_rustls::RustlsConfig;
+
+use crate::config::TlsConfig;
+use crate::connections::{ConnectionLimits, LimitedAcceptor};
+use crate::error::{Result, TileServerError};
+
+/// Shutdown handle for an HTTPS server
+pub type Handle = axum_server::Handle<SocketAddr>;
+
+/// Load the certificate chain and private key
+pub async fn load(config: &TlsConfig) -> Result<RustlsConfig> {
+    RustlsConfig::from_pem_file(&config.cert, &config.key)
+        .await
+        .map_err(|e| {
+            TileServerError::ConfigError(format!(
+                "Failed to load TLS certificate {} / key {}: {}",
+                config.cert.display(),
+                config.key.display(),
+                e
+            ))
+        })
+}
+
+/// Serve `router` over HTTPS until `handle` shuts it down, with the same
+/// connection limits as plain HTTP listeners
+pub async fn serve(
+    listener: std::net::TcpListener,
+    router: Router,
+    rustls: RustlsConfig,
+    limits: ConnectionLimits,
+    handle: Handle,
+) -> std::io::Result<()> {
+    listener.set_nonblocking(true)?;
+    let acceptor = RustlsAcceptor::new(rustls).acceptor(LimitedAcceptor::new(limits));
+    axum_server::from_tcp(listener)?
+        .acceptor(acceptor)
+        .handle(handle)
+        .serve(router.into_make_service())
+        .await
+}
+
+/// Reload the certificate on SIGHUP and whenever the files change
+///
+/// A certificate that fails to load is logged and the previous one stays in use.
+pub fn spawn_reloader(rustls: RustlsConfig, config: TlsConfig) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        #[cfg(unix)]
+        let mut hangup =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()).ok();
+
+        let mut interval = (config.watch_interval_secs > 0).then(|| {
+            let period = Duration::from_secs(config.watch_interval_secs);
+            tokio::time::interval_at(tokio::time::Instant::now() + period, period)
+        });
+        let mut last_modified = modified(&config);
+
+        loop {
+            let on_hangup = async {
+                #[cfg(unix)]
+                if let Some(signal) = hangup.as_mut() {
+                    signal.recv().await;
+                    return;
+                }
+                std::future::pending::<()>().await
+            };
+            let on_tick = async {
+                match interval.as_mut() {
+                    Some(interval) => {
+                        interval.tick().await;
+                    }
+                    None => std::future::pending::<()>().await,
+                }
+            };
+
+            let reason = tokio::select! {
+                _ = on_hangup => "SIGHUP",
+                _ = on_tick => {
+                    let current = modified(&config);
+                    if current == last_modified {
+                        continue;
+                    }
+                    last_modified = current;
+                    "certificate files changed"
+                }
+            };
+
+            match rustls.reload_from_pem_file(&config.cert, &config.key).await {
+                Ok(()) => tracing::info!("Reloaded TLS certificate ({})", reason),
+                Err(e) => tracing::warn!(
+                    "Failed to reload TLS certificate ({}), keeping the current one: {}",
+                    reason,
+                    e
+                ),
+            }
+        }
+    })
+}
+
+/// Modification times of the certificate and key files
+fn modified(config: &TlsConfig) -> Option<(SystemTime, SystemTime)> {
+    let mtime = |path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    Some((mtime(&config.cert)?, mtime(&config.key)?))
+}
+
+/// Router redirecting every request to the same URL on the HTTPS port
+pub fn redirect_router(https_port: u16) -> Router {
+    Router::new().fallback(move |headers: HeaderMap, uri: Uri| async move {
+        redirect_to_https(&headers, &uri, https_port)
+    })
+}
+
+fn redirect_to_https(headers: &HeaderMap, uri: &Uri, https_port: u16) -> Response {
+    let location = headers
+        .get(HOST)
+        .and_then(|host| host.to_str().ok())
+        .map(|host| https_location(host, uri, https_port))
+        .and_then(|location| HeaderValue::from_str(&location).ok());
+
+    match location {
+        Some(location) => (StatusCode::MOVED_PERMANENTLY, [(LOCATION, location)]).into_response(),
+        None => (StatusCode::BAD_REQUEST, "Missing Host header").into_response(),
+    }
+}
+
+/// HTTPS URL for a request, keeping its host, path and query
+pub fn https_location(host: &str, uri: &Uri, https_port: u16) -> String {
+    // Drop the plain HTTP port, keeping IPv6 brackets intact
+    let hostname = match host.rsplit_once(':') {
+        Some((name, port)) if !port.contains(']') && port.parse::<u16>().is_ok() => name,
+        _ => host,
+    };
+    let port = if https_port == 443 {
+        String::new()
+    } else {
+        format!(":{}", https_port)
+    };
+    let path = uri.path_and_query().map_or("/", |p| p.as_str());
+
+    format!("https://{}{}{}", hostname, port, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_https_location() {
+        let uri: Uri = "/data/osm/1/2/3.pbf?key=abc".parse().unwrap();
+        assert_eq!(
+            https_location("tiles.example.com", &uri, 443),
+            "https://tiles.example.com/data/osm/1/2/3.pbf?key=abc"
+        );
+        assert_eq!(
+            https_location("tiles.example.com:80", &uri, 8443),
+            "https://tiles.example.com:8443/data/osm/1/2/3.pbf?key=abc"
+        );
+        assert_eq!(
+            https_location("[::1]:8080", &"/".parse().unwrap(), 443),
+            "https://[::1]/"
+        );
+        assert_eq!(
+            https_location("[::1]", &"/health".parse().unwrap(), 443),
+            "https://[::1]/health"
+        );
+    }
+
+    #[test]
+    fn test_redirect_requires_host() {
+        let uri: Uri = "/health".parse().unwrap();
+        let response = redirect_to_https(&HeaderMap::new(), &uri, 443);
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(HOST, HeaderValue::from_static("localhost:8080"));
+        let response = redirect_to_https(&headers, &uri, 8443);
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(
+            response.headers()[LOCATION],
+            "https://localhost:8443/health"
+        );
+    }
+}
@@ -0,0 +1,897 @@
+//! Offline configuration validation
+//!
+//! Backs `tileserver-rs validate` (alias `check`): loads the config without
+//! binding a port or starting the renderer, opens every source, checks that
+//! styles only reference configured sources, sprites and fonts, and pings
+//! PostgreSQL. Every problem is collected so a single run reports them all.
+
+use rusqlite::{Connection, OpenFlags};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::config::{Config, SourceConfig, SourceType, StyleConfig};
+use crate::cors::Cors;
+use crate::fonts::FontCatalog;
+use crate::sources::pmtiles::archive_version;
+use crate::sources::SourceManager;
+use crate::styles::{self, sprite, Style};
+
+/// Tables an MBTiles file must have (as tables or views)
+const MBTILES_REQUIRED_TABLES: [&str; 2] = ["metadata", "tiles"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Severity {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+/// A single validation problem
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidationIssue {
+    pub severity: Severity,
+    /// What the issue is about, e.g. `config`, `source:osm` or `style:basic`
+    pub scope: String,
+    pub message: String,
+}
+
+/// Result of validating a configuration file
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ValidationReport {
+    /// The configuration file that was validated, if one was found
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config: Option<PathBuf>,
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    fn push(&mut self, severity: Severity, scope: impl Into<String>, message: impl Into<String>) {
+        self.issues.push(ValidationIssue {
+            severity,
+            scope: scope.into(),
+            message: message.into(),
+        });
+    }
+
+    fn error(&mut self, scope: impl Into<String>, message: impl Into<String>) {
+        self.push(Severity::Error, scope, message);
+    }
+
+    fn warning(&mut self, scope: impl Into<String>, message: impl Into<String>) {
+        self.push(Severity::Warning, scope, message);
+    }
+
+    pub fn errors(&self) -> usize {
+        self.count(Severity::Error)
+    }
+
+    pub fn warnings(&self) -> usize {
+        self.count(Severity::Warning)
+    }
+
+    fn count(&self, severity: Severity) -> usize {
+        self.issues
+            .iter()
+            .filter(|i| i.severity == severity)
+            .count()
+    }
+
+    /// Process exit code: 0 when valid, 1 with warnings only, 2 with errors
+    pub fn exit_code(&self) -> i32 {
+        match self.issues.iter().map(|i| i.severity).max() {
+            None => 0,
+            Some(Severity::Warning) => 1,
+            Some(Severity::Error) => 2,
+        }
+    }
+
+    /// Render the report as a JSON document
+    pub fn to_json(&self) -> String {
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        value["valid"] = serde_json::Value::Bool(self.errors() == 0);
+        value["errors"] = self.errors().into();
+        value["warnings"] = self.warnings().into();
+        serde_json::to_string_pretty(&value).unwrap_or_default()
+    }
+
+    /// Render the report as a plain-text table
+    pub fn to_table(&self) -> String {
+        let mut out = String::new();
+        if !self.issues.is_empty() {
+            let scope_width = self
+                .issues
+                .iter()
+                .map(|i| i.scope.len())
+                .max()
+                .unwrap_or(0)
+                .max("SCOPE".len());
+
+            let _ = writeln!(out, "{:<8} {:<scope_width$} MESSAGE", "SEVERITY", "SCOPE");
+            for issue in &self.issues {
+                let _ = writeln!(
+                    out,
+                    "{:<8} {:<scope_width$} {}",
+                    issue.severity.as_str(),
+                    issue.scope,
+                    issue.message
+                );
+            }
+        }
+
+        let config = self
+            .config
+            .as_ref()
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|| "configuration".to_string());
+        let _ = writeln!(
+            out,
+            "{}: {} error(s), {} warning(s)",
+            config,
+            self.errors(),
+            self.warnings()
+        );
+        out
+    }
+}
+
+/// Validate a configuration file
+///
+/// Without a path, the default locations are used, as when starting the server.
+pub fn validate_config(path: Option<&Path>) -> ValidationReport {
+    let mut report = ValidationReport::default();
+
+    let Some(path) = path.map(Path::to_path_buf).or_else(Config::default_path) else {
+        report.error("config", "No configuration file found");
+        return report;
+    };
+    report.config = Some(path.clone());
+
+    if !path.is_file() {
+        report.error(
+            "config",
+            format!("Configuration file not found: {}", path.display()),
+        );
+        return report;
+    }
+
+    let mut config = match Config::from_file(&path) {
+        Ok(config) => config,
+        Err(e) => {
+            report.error("config", format!("Invalid configuration: {:#}", e));
+            return report;
+        }
+    };
+
+    config.register_style_mbtiles();
+    check_config(&config, &mut report);
+    report
+}
+
+/// Check a parsed configuration, appending issues to `report`
+///
+/// Sources are opened on a private runtime, so this must not be called from
+/// within an async context.
+pub fn check_config(config: &Config, report: &mut ValidationReport) {
+    let runtime = match tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            report.error("config", format!("Failed to start runtime: {}", e));
+            return;
+        }
+    };
+
+    check_server(config, report);
+
+    let mut ids = HashSet::new();
+    for source in &config.sources {
+        if !ids.insert(source.id.as_str()) {
+            report.error(
+                format!("source:{}", source.id),
+                "Duplicate source id".to_string(),
+            );
+        }
+        check_source(&runtime, source, report);
+    }
+
+    #[cfg(feature = "postgres")]
+    let mut postgres_ids: Vec<&str> = Vec::new();
+    #[cfg(feature = "postgres")]
+    let mut discovered_ids: Vec<String> = Vec::new();
+    #[cfg(feature = "postgres")]
+    if let Some(postgres) = &config.postgres {
+        postgres_ids.extend(postgres.functions.iter().map(|f| f.id.as_str()));
+        postgres_ids.extend(postgres.tables.iter().map(|t| t.id.as_str()));
+        #[cfg(feature = "raster")]
+        postgres_ids.extend(postgres.outdb_rasters.iter().map(|r| r.id.as_str()));
+
+        match ping_postgres(&runtime, postgres) {
+            Ok(ids) => discovered_ids = ids,
+            Err(e) => report.error("postgres", e),
+        }
+    }
+    #[cfg(feature = "postgres")]
+    ids.extend(postgres_ids);
+    #[cfg(feature = "postgres")]
+    ids.extend(discovered_ids.iter().map(String::as_str));
+
+    let font_files = match &config.fonts {
+        Some(dir) if !config.styles.is_empty() => FontCatalog::scan(dir),
+        _ => FontCatalog::default(),
+    };
+    for style in &config.styles {
+        check_style(style, &ids, config.fonts.as_deref(), &font_files, report);
+    }
+
+    for (scope, dir) in [("fonts", &config.fonts), ("files", &config.files)] {
+        if let Some(dir) = dir {
+            if !dir.is_dir() {
+                report.warning(scope, format!("Directory not found: {}", dir.display()));
+            }
+        }
+    }
+
+    #[cfg(feature = "postgres")]
+    let has_postgres = config.postgres.is_some();
+    #[cfg(not(feature = "postgres"))]
+    let has_postgres = false;
+
+    if config.sources.is_empty() && config.styles.is_empty() && !has_postgres {
+        report.warning("config", "No sources or styles configured");
+    }
+}
+
+/// Check the listener settings in `[server]`
+fn check_server(config: &Config, report: &mut ValidationReport) {
+    let server = &config.server;
+    if let Err(e) = Cors::from_config(server) {
+        report.error("server", e.to_string());
+    }
+    if let Err(e) = server.socket_mode() {
+        report.error("server", e.to_string());
+    }
+    match server.unix_socket_path() {
+        Ok(Some(_)) => {
+            if server.tls.is_some() {
+                report.error("server", "[server.tls] is not supported on Unix sockets");
+            }
+            if server.public_url.is_none() {
+                report.warning(
+                    "server",
+                    "public_url is not set; URLs in responses will use http://localhost",
+                );
+            }
+        }
+        Ok(None) => {}
+        Err(e) => report.error("server", e.to_string()),
+    }
+}
+
+/// Paths GDAL or the HTTP clients read, which are not checked on disk
+fn is_remote(path: &str) -> bool {
+    path.contains("://") || path.starts_with("/vsi")
+}
+
+fn check_source(
+    runtime: &tokio::runtime::Runtime,
+    source: &SourceConfig,
+    report: &mut ValidationReport,
+) {
+    let scope = format!("source:{}", source.id);
+
+    if let (Some(minzoom), Some(maxzoom)) = (source.proxy.minzoom, source.proxy.maxzoom) {
+        if minzoom > maxzoom {
+            report.error(
+                scope.clone(),
+                format!("minzoom {} is greater than maxzoom {}", minzoom, maxzoom),
+            );
+        }
+    }
+
+    // Remote sources are not fetched
+    if source.source_type == SourceType::Proxy || is_remote(&source.path) {
+        return;
+    }
+
+    let path = Path::new(&source.path);
+    let result = match source.source_type {
+        SourceType::PMTiles
+        | SourceType::MBTiles
+        | SourceType::GeoPackage
+        | SourceType::GeoJson
+            if !path.is_file() =>
+        {
+            Err(format!("File not found: {}", source.path))
+        }
+        SourceType::PMTiles => check_pmtiles(path),
+        SourceType::MBTiles => check_mbtiles(path),
+        _ => Ok(()),
+    };
+    if let Err(e) = result {
+        report.error(scope, e);
+        return;
+    }
+
+    // Open the source with the server's loader to check its metadata
+    let mut sources = SourceManager::new();
+    if let Err(e) = runtime.block_on(sources.load_source(source)) {
+        report.error(scope, e.to_string());
+    }
+}
+
+fn check_pmtiles(path: &Path) -> std::result::Result<(), String> {
+    let mut magic = [0u8; 7];
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .map_err(|e| format!("Cannot read PMTiles header: {}", e))?;
+
+    match archive_version(&magic) {
+        Some(_) => Ok(()),
+        None => Err(format!("Not a PMTiles archive: {}", path.display())),
+    }
+}
+
+fn check_mbtiles(path: &Path) -> std::result::Result<(), String> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Cannot open MBTiles file: {}", e))?;
+
+    let mut stmt = conn
+        .prepare("SELECT name FROM sqlite_master WHERE type IN ('table', 'view')")
+        .map_err(|e| format!("Not a valid SQLite database: {}", e))?;
+    let tables = stmt
+        .query_map([], |row| row.get::<_, String>(0))
+        .and_then(|rows| rows.collect::<rusqlite::Result<HashSet<String>>>())
+        .map_err(|e| format!("Not a valid SQLite database: {}", e))?;
+
+    let missing: Vec<&str> = MBTILES_REQUIRED_TABLES
+        .into_iter()
+        .filter(|t| !tables.contains(*t))
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!(
+            "MBTiles file is missing table(s): {}",
+            missing.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+/// Check a style parses and that the sources, sprite and fonts it uses exist
+fn check_style(
+    config: &StyleConfig,
+    source_ids: &HashSet<&str>,
+    fonts_dir: Option<&Path>,
+    font_files: &FontCatalog,
+    report: &mut ValidationReport,
+) {
+    let scope = format!("style:{}", config.id);
+    let style = match Style::from_file(config) {
+        Ok(style) => style,
+        Err(e) => {
+            report.error(
+                scope,
+                format!("Cannot load style {}: {}", config.path.display(), e),
+            );
+            return;
+        }
+    };
+
+    for id in style.data_source_ids() {
+        if !source_ids.contains(id.as_str()) {
+            report.error(scope.clone(), format!("References unknown source '{}'", id));
+        }
+    }
+
+    // Fonts are checked below against the directory as configured
+    lint_style(&style, &scope, report);
+    for variant in &config.variants {
+        let scope = format!("style:{}", variant.id);
+        match style.variant(variant) {
+            Ok(variant) => lint_style(&variant, &scope, report),
+            Err(e) => report.error(
+                scope,
+                format!("Cannot apply patch {}: {}", variant.patch.display(), e),
+            ),
+        }
+    }
+
+    // Sprites are served from the style's directory
+    if let Some(sprite) = style.style_json.get("sprite").and_then(|s| s.as_str()) {
+        if sprite.starts_with('/') && config.inline.is_some() {
+            report.error(
+                scope.clone(),
+                "Inline styles have no directory to serve local sprites from",
+            );
+        } else if sprite.starts_with('/') {
+            let name = sprite.rsplit('/').next().unwrap_or(sprite);
+            let dir = config.path.parent().unwrap_or(Path::new("."));
+            for ext in ["json", "png"] {
+                let file = dir.join(format!("{}.{}", name, ext));
+                if !file.is_file() {
+                    report.error(
+                        scope.clone(),
+                        format!("Sprite file not found: {}", file.display()),
+                    );
+                }
+            }
+        }
+    }
+
+    // Local entries of a sprite array are served from a subdirectory named
+    // after their id, at whichever scales are there
+    let entries = style.style_json.get("sprite").and_then(|s| s.as_array());
+    for entry in entries.into_iter().flatten() {
+        let id = entry.get("id").and_then(|v| v.as_str());
+        let url = entry.get("url").and_then(|v| v.as_str());
+        let (Some(id), Some(url)) = (id, url) else {
+            report.error(scope.clone(), "Sprite array entries need an id and a url");
+            continue;
+        };
+        if !url.starts_with('/') {
+            continue;
+        }
+        if config.inline.is_some() {
+            report.error(
+                scope.clone(),
+                "Inline styles have no directory to serve local sprites from",
+            );
+            continue;
+        }
+        match style.sprite_dir(Some(id)) {
+            Ok(dir) if sprite::has_sprite(&dir) => {}
+            Ok(dir) => report.error(
+                scope.clone(),
+                format!("Sprite files not found in {}", dir.display()),
+            ),
+            Err(_) => report.error(
+                scope.clone(),
+                format!(
+                    "Sprite '{}' must have the URL /styles/{}/{}/sprite",
+                    id, config.id, id
+                ),
+            ),
+        }
+    }
+
+    // Local glyphs need the fonts directory and each font used by a layer
+    let local_glyphs = style
+        .style_json
+        .get("glyphs")
+        .and_then(|g| g.as_str())
+        .is_some_and(|g| g.starts_with("/fonts/"));
+    if !local_glyphs {
+        return;
+    }
+    let Some(fonts_dir) = fonts_dir else {
+        report.error(
+            scope,
+            "Style uses /fonts/ glyphs but no fonts directory is configured",
+        );
+        return;
+    };
+
+    let mut missing = std::collections::BTreeSet::new();
+    for font in style_fonts(&style.style_json) {
+        if !fonts_dir.join(font).is_dir() && !font_files.contains(font) {
+            missing.insert(font);
+        }
+    }
+    for font in missing {
+        report.warning(scope.clone(), format!("Font not found: {}", font));
+    }
+}
+
+/// Report the problems `styles::validate::lint` finds in a style
+fn lint_style(style: &Style, scope: &str, report: &mut ValidationReport) {
+    for diagnostic in styles::validate::lint(style, None) {
+        match diagnostic.severity {
+            styles::validate::Severity::Error => report.error(scope, diagnostic.to_string()),
+            styles::validate::Severity::Warning => report.warning(scope, diagnostic.to_string()),
+        }
+    }
+}
+
+/// Font names listed as literal `text-font` arrays in a style's layers
+fn style_fonts(style: &serde_json::Value) -> Vec<&str> {
+    style
+        .get("layers")
+        .and_then(|l| l.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|layer| layer.pointer("/layout/text-font")?.as_array())
+        .flatten()
+        .filter_map(|font| font.as_str())
+        .collect()
+}
+
+/// Connect to PostgreSQL, returning the IDs of the tables `auto_publish` finds
+#[cfg(feature = "postgres")]
+fn ping_postgres(
+    runtime: &tokio::runtime::Runtime,
+    config: &crate::config::PostgresConfig,
+) -> std::result::Result<Vec<String>, String> {
+    use crate::sources::postgres::{auto_publish, PoolSettings, PostgresPool};
+
+    let settings = PoolSettings {
+        max_size: 1,
+        wait_timeout_ms: config.pool_wait_timeout_ms,
+        create_timeout_ms: config.pool_create_timeout_ms,
+        recycle_timeout_ms: config.pool_recycle_timeout_ms,
+        statement_timeout_ms: config.statement_timeout_ms.unwrap_or(0),
+        pre_warm: false,
+    };
+
+    runtime
+        .block_on(async {
+            let pool = PostgresPool::new(
+                &config.connection_string,
+                settings,
+                config.ssl_cert.as_ref(),
+                config.ssl_key.as_ref(),
+                config.ssl_root_cert.as_ref(),
+            )
+            .await?;
+            let Some(auto) = &config.auto_publish else {
+                return Ok(Vec::new());
+            };
+            let conn = pool.get().await?;
+            let tables = auto_publish::discover_tables(&conn, auto, &config.tables).await?;
+            Ok(tables.into_iter().map(|t| t.id).collect())
+        })
+        .map_err(|e: crate::error::TileServerError| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn write_config(dir: &Path, content: &str) -> PathBuf {
+        let path = dir.join("config.toml");
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn fixture(path: &str) -> String {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join(path)
+            .display()
+            .to_string()
+    }
+
+    #[test]
+    fn test_valid_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_config(
+            dir.path(),
+            &format!(
+                r#"
+                fonts = "{}"
+
+                [[sources]]
+                id = "protomaps"
+                type = "pmtiles"
+                path = "{}"
+
+                [[sources]]
+                id = "zurich"
+                type = "mbtiles"
+                path = "{}"
+
+                [[styles]]
+                id = "protomaps-light"
+                path = "{}"
+                "#,
+                fixture("data/fonts"),
+                fixture("data/tiles/protomaps-sample.pmtiles"),
+                fixture("data/tiles/zurich_switzerland.mbtiles"),
+                fixture("data/styles/protomaps-light/style.json"),
+            ),
+        );
+
+        let report = validate_config(Some(&path));
+        assert!(report.issues.is_empty(), "{:?}", report.issues);
+        assert_eq!(report.exit_code(), 0);
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_config(
+            dir.path(),
+            r#"
+            [[sources]]
+            id = "missing"
+            type = "mbtiles"
+            path = "/nonexistent/tiles.mbtiles"
+            "#,
+        );
+
+        let report = validate_config(Some(&path));
+        assert_eq!(report.errors(), 1);
+        assert_eq!(report.issues[0].scope, "source:missing");
+        assert_eq!(report.exit_code(), 2);
+
+        let report = validate_config(Some(&dir.path().join("nope.toml")));
+        assert_eq!(report.exit_code(), 2);
+    }
+
+    #[test]
+    fn test_invalid_toml() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_config(dir.path(), "[[sources]\nid = ");
+
+        let report = validate_config(Some(&path));
+        assert_eq!(report.errors(), 1);
+        assert_eq!(report.issues[0].scope, "config");
+        assert!(report.issues[0]
+            .message
+            .starts_with("Invalid configuration"));
+    }
+
+    #[test]
+    fn test_invalid_sqlite() {
+        let dir = tempfile::tempdir().unwrap();
+        let not_sqlite = dir.path().join("bad.mbtiles");
+        std::fs::File::create(&not_sqlite)
+            .unwrap()
+            .write_all(b"definitely not a database, just some bytes to read")
+            .unwrap();
+
+        let empty_db = dir.path().join("empty.mbtiles");
+        Connection::open(&empty_db)
+            .unwrap()
+            .execute_batch("CREATE TABLE metadata (name TEXT, value TEXT);")
+            .unwrap();
+
+        let path = write_config(
+            dir.path(),
+            &format!(
+                r#"
+                [[sources]]
+                id = "bad"
+                type = "mbtiles"
+                path = "{}"
+
+                [[sources]]
+                id = "empty"
+                type = "mbtiles"
+                path = "{}"
+                "#,
+                not_sqlite.display(),
+                empty_db.display(),
+            ),
+        );
+
+        let report = validate_config(Some(&path));
+        assert_eq!(report.errors(), 2, "{:?}", report.issues);
+        assert!(report.issues[0].message.contains("SQLite"));
+        assert!(report.issues[1].message.contains("missing table(s): tiles"));
+    }
+
+    #[test]
+    fn test_style_references_missing_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let style = dir.path().join("style.json");
+        std::fs::write(
+            &style,
+            r#"{
+                "version": 8,
+                "sources": {
+                    "osm": { "type": "vector", "url": "/data/osm.json" },
+                    "zurich": { "type": "vector", "url": "/data/zurich.json" }
+                },
+                "sprite": "/styles/test/sprite",
+                "layers": []
+            }"#,
+        )
+        .unwrap();
+
+        let path = write_config(
+            dir.path(),
+            &format!(
+                r#"
+                [[sources]]
+                id = "zurich"
+                type = "mbtiles"
+                path = "{}"
+
+                [[styles]]
+                id = "test"
+                path = "{}"
+                "#,
+                fixture("data/tiles/zurich_switzerland.mbtiles"),
+                style.display(),
+            ),
+        );
+
+        let report = validate_config(Some(&path));
+        let messages: Vec<&str> = report.issues.iter().map(|i| i.message.as_str()).collect();
+        assert_eq!(report.errors(), 3, "{:?}", messages);
+        assert!(report.issues.iter().all(|i| i.scope == "style:test"));
+        assert_eq!(messages[0], "References unknown source 'osm'");
+        assert!(messages[1].starts_with("Sprite file not found"));
+    }
+
+    #[test]
+    fn test_style_sprite_array() {
+        let dir = tempfile::tempdir().unwrap();
+        let style = dir.path().join("style.json");
+        std::fs::write(
+            &style,
+            r#"{
+                "version": 8,
+                "sources": {},
+                "sprite": [
+                    { "id": "maki", "url": "/styles/test/maki/sprite" },
+                    { "id": "missing", "url": "/styles/test/missing/sprite" },
+                    { "id": "elsewhere", "url": "/sprites/elsewhere" },
+                    { "id": "remote", "url": "https://example.com/sprite" }
+                ],
+                "layers": []
+            }"#,
+        )
+        .unwrap();
+        // Only @2x is needed, other scales are derived from it
+        std::fs::create_dir(dir.path().join("maki")).unwrap();
+        std::fs::write(dir.path().join("maki/sprite@2x.json"), "{}").unwrap();
+        std::fs::write(dir.path().join("maki/sprite@2x.png"), "").unwrap();
+
+        let path = write_config(
+            dir.path(),
+            &format!(
+                r#"
+                [[styles]]
+                id = "test"
+                path = "{}"
+                "#,
+                style.display(),
+            ),
+        );
+
+        let report = validate_config(Some(&path));
+        let messages: Vec<&str> = report.issues.iter().map(|i| i.message.as_str()).collect();
+        assert_eq!(report.errors(), 2, "{:?}", messages);
+        assert!(messages[0].starts_with("Sprite files not found in"));
+        assert!(messages[0].ends_with("missing"));
+        assert_eq!(
+            messages[1],
+            "Sprite 'elsewhere' must have the URL /styles/test/elsewhere/sprite"
+        );
+    }
+
+    #[test]
+    fn test_style_lint() {
+        let dir = tempfile::tempdir().unwrap();
+        let style = dir.path().join("style.json");
+        std::fs::write(
+            &style,
+            r#"{
+                "version": 8,
+                "sources": { "osm": { "type": "vector", "url": "https://example.com/osm.json" } },
+                "layers": [
+                    { "id": "water", "type": "fill", "source": "osm", "source-layer": "water" },
+                    { "id": "roads", "type": "line", "source": "osm" }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let path = write_config(
+            dir.path(),
+            &format!(
+                "[[styles]]\nid = \"test\"\npath = \"{}\"\n",
+                style.display()
+            ),
+        );
+        let report = validate_config(Some(&path));
+        assert_eq!(report.errors(), 1, "{:?}", report.issues);
+        assert_eq!(report.issues[0].scope, "style:test");
+        assert_eq!(
+            report.issues[0].message,
+            "layer 'roads': Layer uses vector source 'osm' but has no source-layer"
+        );
+    }
+
+    #[test]
+    fn test_style_fonts() {
+        let dir = tempfile::tempdir().unwrap();
+        let fonts = dir.path().join("fonts");
+        std::fs::create_dir_all(fonts.join("Noto Sans Regular")).unwrap();
+        let style = dir.path().join("style.json");
+        std::fs::write(
+            &style,
+            r#"{
+                "version": 8,
+                "sources": { "osm": { "type": "geojson", "data": "https://example.com/places.geojson" } },
+                "glyphs": "/fonts/{fontstack}/{range}.pbf",
+                "layers": [
+                    { "id": "a", "type": "symbol", "source": "osm", "layout": { "text-font": ["Noto Sans Regular"] } },
+                    { "id": "b", "type": "symbol", "source": "osm", "layout": { "text-font": ["Noto Sans Bold"] } }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let config = |fonts: Option<&Path>| {
+            let fonts = fonts
+                .map(|f| format!("fonts = \"{}\"\n", f.display()))
+                .unwrap_or_default();
+            format!(
+                "{}[[styles]]\nid = \"test\"\npath = \"{}\"\n",
+                fonts,
+                style.display()
+            )
+        };
+
+        let path = write_config(dir.path(), &config(Some(&fonts)));
+        let report = validate_config(Some(&path));
+        assert_eq!(report.errors(), 0);
+        assert_eq!(report.warnings(), 1);
+        assert_eq!(report.issues[0].message, "Font not found: Noto Sans Bold");
+
+        let path = write_config(dir.path(), &config(None));
+        let report = validate_config(Some(&path));
+        assert_eq!(report.errors(), 1);
+    }
+
+    #[test]
+    fn test_warnings_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_config(dir.path(), "fonts = \"/nonexistent/fonts\"\n");
+
+        let report = validate_config(Some(&path));
+        assert_eq!(report.errors(), 0);
+        assert_eq!(report.warnings(), 2);
+        assert_eq!(report.exit_code(), 1);
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["valid"], true);
+        assert_eq!(json["issues"][0]["severity"], "warning");
+        assert!(report.to_table().contains("0 error(s), 2 warning(s)"));
+    }
+
+    #[test]
+    fn test_unix_listen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_config(
+            dir.path(),
+            r#"
+            [server]
+            listen = "unix:/run/tileserver.sock"
+            socket_mode = "0999"
+
+            [server.tls]
+            cert = "cert.pem"
+            key = "key.pem"
+            "#,
+        );
+
+        let report = validate_config(Some(&path));
+        let server: Vec<_> = report
+            .issues
+            .iter()
+            .filter(|issue| issue.scope == "server")
+            .map(|issue| (issue.severity, issue.message.as_str()))
+            .collect();
+        assert_eq!(server.len(), 3, "{:?}", server);
+        assert!(server[0].1.contains("socket_mode"));
+        assert!(server[1].1.contains("not supported on Unix sockets"));
+        assert_eq!(server[2].0, Severity::Warning);
+        assert!(server[2].1.contains("public_url"));
+    }
+}
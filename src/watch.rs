@@ -0,0 +1,287 @@
+//! Reload styles and sources when their files change (`--watch`)
+//!
+//! Meant for local development: editing a `style.json` or replacing a
+//! `.pmtiles` file takes effect without restarting the server. The parent
+//! directory of each file is watched rather than the file itself, so files
+//! replaced by a rename (as editors and `cp` do) are still picked up.
+
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::config::{SourceConfig, SourceType, StyleConfig};
+use crate::error::{Result, TileServerError};
+use crate::events::{Event, EventBus};
+use crate::sources::SharedSources;
+use crate::styles::SharedStyles;
+
+/// How long events must stop arriving before a change is handled, so a file
+/// being copied is reloaded once rather than on every write
+pub const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Watches a set of files and yields them in debounced batches
+pub struct FileWatcher {
+    /// Kept alive to keep receiving events
+    _watcher: notify::RecommendedWatcher,
+    changes: mpsc::UnboundedReceiver<PathBuf>,
+    debounce: Duration,
+}
+
+impl FileWatcher {
+    /// Watch `files`, which need not exist yet as long as their directory does
+    pub fn new(files: impl IntoIterator<Item = PathBuf>, debounce: Duration) -> Result<Self> {
+        // Event paths are absolute, so match on the resolved path but report
+        // the path as configured
+        let mut watched: HashMap<PathBuf, PathBuf> = HashMap::new();
+        for file in files {
+            watched.insert(resolve(&file)?, file);
+        }
+
+        let (tx, changes) = mpsc::unbounded_channel();
+        let targets = watched.clone();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(e) => {
+                        tracing::warn!("File watcher error: {}", e);
+                        return;
+                    }
+                };
+                if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                    return;
+                }
+                for path in &event.paths {
+                    if let Some(file) = targets.get(path) {
+                        let _ = tx.send(file.clone());
+                    }
+                }
+            })
+            .map_err(watch_error)?;
+
+        let dirs: BTreeSet<&Path> = watched.keys().filter_map(|path| path.parent()).collect();
+        for dir in dirs {
+            watcher
+                .watch(dir, RecursiveMode::NonRecursive)
+                .map_err(watch_error)?;
+        }
+
+        Ok(Self {
+            _watcher: watcher,
+            changes,
+            debounce,
+        })
+    }
+
+    /// Wait for the next batch of changed files
+    ///
+    /// Returns once no further events arrived for the debounce delay.
+    pub async fn next(&mut self) -> Option<Vec<PathBuf>> {
+        let mut batch = BTreeSet::new();
+        batch.insert(self.changes.recv().await?);
+
+        while let Ok(Some(path)) = tokio::time::timeout(self.debounce, self.changes.recv()).await {
+            batch.insert(path);
+        }
+
+        Some(batch.into_iter().collect())
+    }
+}
+
+/// Absolute path of `file`, resolving symlinks in its directory
+fn resolve(file: &Path) -> Result<PathBuf> {
+    let name = file
+        .file_name()
+        .ok_or_else(|| TileServerError::ConfigError(format!("Cannot watch {}", file.display())))?;
+    let dir = match file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    Ok(std::fs::canonicalize(dir)?.join(name))
+}
+
+fn watch_error(e: notify::Error) -> TileServerError {
+    TileServerError::ConfigError(format!("Failed to watch files: {}", e))
+}
+
+/// Local files backing a source, if it has any
+fn source_file(config: &SourceConfig) -> Option<PathBuf> {
+    let local = !config.path.contains("://") && !config.path.starts_with("/vsi");
+    match config.source_type {
+        SourceType::PMTiles
+        | SourceType::MBTiles
+        | SourceType::GeoPackage
+        | SourceType::GeoJson
+            if local =>
+        {
+            Some(PathBuf::from(&config.path))
+        }
+        #[cfg(feature = "raster")]
+        SourceType::Cog | SourceType::Vrt | SourceType::TerrainRgb if local => {
+            Some(PathBuf::from(&config.path))
+        }
+        _ => None,
+    }
+}
+
+/// What a watched file belongs to
+enum Target {
+    Source(Box<SourceConfig>),
+    Style(StyleConfig),
+}
+
+/// Watch the configured style and source files and reload them on change
+pub fn spawn(
+    source_configs: &[SourceConfig],
+    style_configs: &[StyleConfig],
+    sources: SharedSources,
+    styles: SharedStyles,
+    events: EventBus,
+) -> Result<tokio::task::JoinHandle<()>> {
+    let mut targets: HashMap<PathBuf, Vec<Target>> = HashMap::new();
+    for config in source_configs {
+        if let Some(file) = source_file(config) {
+            targets
+                .entry(file)
+                .or_default()
+                .push(Target::Source(Box::new(config.clone())));
+        }
+    }
+    // Editing a variant's patch regenerates it along with its parent
+    for config in style_configs {
+        let style_file = config.inline.is_none().then(|| config.path.clone());
+        let patches = config.variants.iter().map(|v| v.patch.clone());
+        for file in style_file.into_iter().chain(patches) {
+            targets
+                .entry(file)
+                .or_default()
+                .push(Target::Style(config.clone()));
+        }
+    }
+
+    let mut watcher = FileWatcher::new(targets.keys().cloned(), DEBOUNCE)?;
+    tracing::info!("Watching {} file(s) for changes", targets.len());
+
+    Ok(tokio::spawn(async move {
+        while let Some(changed) = watcher.next().await {
+            for target in changed.iter().flat_map(|file| &targets[file]) {
+                match target {
+                    Target::Source(config) => match sources.reload_source(config).await {
+                        Ok(()) => {
+                            tracing::info!("Reloaded source: {} ({})", config.id, config.path);
+                            events.send(Event::SourceReloaded {
+                                source: config.id.clone(),
+                            });
+                        }
+                        Err(e) => tracing::warn!("Failed to reload source {}: {}", config.id, e),
+                    },
+                    Target::Style(config) => match styles.reload_style(config) {
+                        Ok(()) => {
+                            tracing::info!(
+                                "Reloaded style: {} ({})",
+                                config.id,
+                                config.path.display()
+                            );
+                            let variants = config.variants.iter().map(|v| &v.id);
+                            for style in std::iter::once(&config.id).chain(variants) {
+                                events.send(Event::StyleReloaded {
+                                    style: style.clone(),
+                                });
+                            }
+                        }
+                        Err(e) => tracing::warn!("Failed to reload style {}: {}", config.id, e),
+                    },
+                }
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_watcher_reports_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("style.json");
+        std::fs::write(&file, "{}").unwrap();
+        let other = dir.path().join("other.json");
+
+        let mut watcher = FileWatcher::new([file.clone()], Duration::from_millis(100)).unwrap();
+
+        std::fs::write(&other, "{}").unwrap();
+        std::fs::write(&file, r#"{"version": 8}"#).unwrap();
+
+        let changed = tokio::time::timeout(Duration::from_secs(2), watcher.next())
+            .await
+            .expect("Watcher should trigger within 2 seconds")
+            .unwrap();
+        assert_eq!(changed, vec![file]);
+    }
+
+    #[tokio::test]
+    async fn test_watcher_debounces_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("tiles.pmtiles");
+
+        let mut watcher = FileWatcher::new([file.clone()], Duration::from_millis(200)).unwrap();
+
+        // A file copied in several writes, then replaced by a rename
+        for chunk in 0..5 {
+            std::fs::write(&file, vec![0u8; chunk * 1024]).unwrap();
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let tmp = dir.path().join("tiles.pmtiles.tmp");
+        std::fs::write(&tmp, b"new").unwrap();
+        std::fs::rename(&tmp, &file).unwrap();
+
+        let changed = tokio::time::timeout(Duration::from_secs(2), watcher.next())
+            .await
+            .expect("Watcher should trigger within 2 seconds")
+            .unwrap();
+        assert_eq!(changed, vec![file]);
+
+        // Everything was folded into the one batch
+        assert!(
+            tokio::time::timeout(Duration::from_millis(400), watcher.next())
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_source_file() {
+        let config = |source_type, path: &str| SourceConfig {
+            source_type,
+            path: path.to_string(),
+            ..serde_json::from_value(serde_json::json!({
+                "id": "test",
+                "type": "pmtiles",
+                "path": ""
+            }))
+            .unwrap()
+        };
+
+        assert_eq!(
+            source_file(&config(SourceType::PMTiles, "data/tiles.pmtiles")),
+            Some(PathBuf::from("data/tiles.pmtiles"))
+        );
+        assert_eq!(
+            source_file(&config(
+                SourceType::PMTiles,
+                "https://example.com/t.pmtiles"
+            )),
+            None
+        );
+        assert_eq!(
+            source_file(&config(
+                SourceType::Proxy,
+                "https://tile.example.com/{z}/{x}/{y}.png"
+            )),
+            None
+        );
+    }
+}
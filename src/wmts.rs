@@ -1,10 +1,23 @@
-//! WMTS (Web Map Tile Service) Capabilities document generation
+//! WMTS (Web Map Tile Service) support
 //!
 //! Generates OGC WMTS 1.0.0 compliant GetCapabilities XML responses
-//! for use with GIS software like QGIS and ArcGIS.
+//! for use with GIS software like QGIS and ArcGIS, and parses KVP
+//! GetCapabilities/GetTile/GetFeatureInfo requests.
 
+use axum::{
+    http::{header::CONTENT_TYPE, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use std::collections::HashMap;
 use std::fmt::Write;
 
+use crate::error::TileServerError;
+use crate::ogcapi::tile_matrix_limits;
+use crate::render::ImageFormat;
+use crate::sources::{mvt_layer_features, TileFormat, TileSource};
+use crate::styles::raster::RasterExtent;
+use crate::styles::{Style, StyleManager};
+
 /// Scale denominators for each zoom level in Web Mercator (EPSG:3857)
 /// These are standard values for 256px tiles at 0.28mm/pixel (OGC standard)
 const SCALE_DENOMINATORS_256: [f64; 19] = [
@@ -29,29 +42,105 @@ const SCALE_DENOMINATORS_256: [f64; 19] = [
     2132.7295838498,
 ];
 
+/// Highest TileMatrix advertised in capabilities documents
+pub const MAX_TILE_MATRIX: u8 = (SCALE_DENOMINATORS_256.len() - 1) as u8;
+
+/// Half the width of the Web Mercator (EPSG:3857) world in meters
+pub const WEB_MERCATOR_EXTENT: f64 = 20037508.3427892;
+
+/// Standardized rendering pixel size in meters (OGC 0.28mm/pixel)
+pub const PIXEL_SIZE_METERS: f64 = 0.00028;
+
+/// Scale denominator of a Web Mercator zoom level for 256px tiles
+pub fn scale_denominator(z: u8) -> f64 {
+    SCALE_DENOMINATORS_256
+        .get(z as usize)
+        .copied()
+        .unwrap_or_else(|| SCALE_DENOMINATORS_256[0] / 2f64.powi(z as i32))
+}
+
+/// Pixel tolerance when hit-testing features for GetFeatureInfo
+const FEATURE_INFO_TOLERANCE_PX: f64 = 3.0;
+
 /// Generate WMTS GetCapabilities XML for a style
 ///
 /// # Arguments
 /// * `base_url` - Base URL of the server (e.g., "http://localhost:8080")
 /// * `style_id` - Style identifier
 /// * `style_name` - Human-readable style name
-/// * `min_zoom` - Minimum zoom level
-/// * `max_zoom` - Maximum zoom level
+/// * `extent` - Zoom range and bounds of the style's raster tiles
 /// * `key` - Optional API key to append to all URLs as `?key=...`
 pub fn generate_wmts_capabilities(
     base_url: &str,
     style_id: &str,
     style_name: &str,
-    min_zoom: u8,
-    max_zoom: u8,
+    extent: &RasterExtent,
+    key: Option<&str>,
+) -> String {
+    let wmts_url = format!(
+        "{}/styles/{}/wmts.xml{}",
+        base_url,
+        style_id,
+        key_query(key)
+    );
+
+    write_capabilities(base_url, &wmts_url, &[(style_id, style_name, *extent)], key)
+}
+
+/// Generate WMTS GetCapabilities XML listing every style, as served by the KVP `/wmts` endpoint
+///
+/// # Arguments
+/// * `base_url` - Base URL of the server (e.g., "http://localhost:8080")
+/// * `styles` - `(style_id, style_name, extent)` of each style
+/// * `key` - Optional API key to append to all URLs
+pub fn generate_wmts_service_capabilities(
+    base_url: &str,
+    styles: &[(&str, &str, RasterExtent)],
+    key: Option<&str>,
+) -> String {
+    let wmts_url = format!(
+        "{}SERVICE=WMTS&amp;REQUEST=GetCapabilities",
+        kvp_url(base_url, key)
+    );
+
+    write_capabilities(base_url, &wmts_url, styles, key)
+}
+
+/// Build query string for key parameter
+fn key_query(key: Option<&str>) -> String {
+    key.map(|k| format!("?key={}", urlencoding::encode(k)))
+        .unwrap_or_default()
+}
+
+/// KVP endpoint URL, ready for clients to append `KEY=VALUE` pairs
+fn kvp_url(base_url: &str, key: Option<&str>) -> String {
+    match key {
+        Some(k) => format!("{}/wmts?key={}&amp;", base_url, urlencoding::encode(k)),
+        None => format!("{}/wmts?", base_url),
+    }
+}
+
+/// Write a full Capabilities document
+///
+/// The TileMatrixSets cover the zooms of every style; layers with fewer
+/// zooms list theirs in TileMatrixSetLimits.
+fn write_capabilities(
+    base_url: &str,
+    wmts_url: &str,
+    styles: &[(&str, &str, RasterExtent)],
     key: Option<&str>,
 ) -> String {
     let mut xml = String::with_capacity(32768);
 
-    // Build query string for key parameter
-    let key_query = key
-        .map(|k| format!("?key={}", urlencoding::encode(k)))
-        .unwrap_or_default();
+    let key_query = key_query(key);
+    let kvp_url = kvp_url(base_url, key);
+    let min_zoom = styles.iter().map(|(_, _, e)| e.minzoom).min().unwrap_or(0);
+    let max_zoom = styles
+        .iter()
+        .map(|(_, _, e)| e.maxzoom)
+        .max()
+        .unwrap_or(MAX_TILE_MATRIX)
+        .min(MAX_TILE_MATRIX);
 
     // XML declaration and root element
     xml.push_str(r#"<?xml version="1.0" encoding="UTF-8"?>
@@ -68,51 +157,41 @@ pub fn generate_wmts_capabilities(
 "#,
     );
 
-    // Operations Metadata - include key in WMTS URL
-    let wmts_url = format!("{}/styles/{}/wmts.xml{}", base_url, style_id, key_query);
-    write!(
-        xml,
-        r#"  <ows:OperationsMetadata>
-    <ows:Operation name="GetCapabilities">
-      <ows:DCP>
-        <ows:HTTP>
-          <ows:Get xlink:href="{}">
-            <ows:Constraint name="GetEncoding">
-              <ows:AllowedValues>
-                <ows:Value>RESTful</ows:Value>
-              </ows:AllowedValues>
-            </ows:Constraint>
-          </ows:Get>
-        </ows:HTTP>
-      </ows:DCP>
-    </ows:Operation>
-    <ows:Operation name="GetTile">
-      <ows:DCP>
-        <ows:HTTP>
-          <ows:Get xlink:href="{}">
-            <ows:Constraint name="GetEncoding">
-              <ows:AllowedValues>
-                <ows:Value>RESTful</ows:Value>
-              </ows:AllowedValues>
-            </ows:Constraint>
-          </ows:Get>
-        </ows:HTTP>
-      </ows:DCP>
-    </ows:Operation>
-  </ows:OperationsMetadata>
-"#,
-        wmts_url, wmts_url
-    )
-    .unwrap();
+    // Operations Metadata - include key in WMTS URLs
+    xml.push_str("  <ows:OperationsMetadata>\n");
+    write_operation(
+        &mut xml,
+        "GetCapabilities",
+        &[(wmts_url, "RESTful"), (&kvp_url, "KVP")],
+    );
+    write_operation(
+        &mut xml,
+        "GetTile",
+        &[(wmts_url, "RESTful"), (&kvp_url, "KVP")],
+    );
+    write_operation(&mut xml, "GetFeatureInfo", &[(&kvp_url, "KVP")]);
+    xml.push_str("  </ows:OperationsMetadata>\n");
 
     // Contents section
     xml.push_str("  <Contents>\n");
 
-    // Layer for 256px tiles
-    write_layer(&mut xml, base_url, style_id, style_name, 256, &key_query);
+    for (style_id, style_name, extent) in styles {
+        // Only list the style's zooms when the TileMatrixSets have more
+        let limits = (extent.minzoom > min_zoom || extent.maxzoom < max_zoom)
+            .then(|| write_tile_matrix_set_limits(extent, max_zoom));
+        let layer = LayerInfo {
+            style_id,
+            style_name,
+            bounds: &extent.bounds,
+            limits: limits.as_deref(),
+        };
+
+        // Layer for 256px tiles
+        write_layer(&mut xml, base_url, &layer, 256, &key_query);
 
-    // Layer for 512px tiles (using @2x)
-    write_layer(&mut xml, base_url, style_id, style_name, 512, &key_query);
+        // Layer for 512px tiles (using @2x)
+        write_layer(&mut xml, base_url, &layer, 512, &key_query);
+    }
 
     // TileMatrixSets
     write_tile_matrix_set_google_maps(&mut xml, 256, min_zoom, max_zoom);
@@ -128,34 +207,101 @@ pub fn generate_wmts_capabilities(
     xml
 }
 
+/// Write an Operation element with one Get entry per encoding
+fn write_operation(xml: &mut String, name: &str, gets: &[(&str, &str)]) {
+    write!(
+        xml,
+        r#"    <ows:Operation name="{}">
+      <ows:DCP>
+        <ows:HTTP>
+"#,
+        name
+    )
+    .unwrap();
+
+    for (href, encoding) in gets {
+        write!(
+            xml,
+            r#"          <ows:Get xlink:href="{}">
+            <ows:Constraint name="GetEncoding">
+              <ows:AllowedValues>
+                <ows:Value>{}</ows:Value>
+              </ows:AllowedValues>
+            </ows:Constraint>
+          </ows:Get>
+"#,
+            href, encoding
+        )
+        .unwrap();
+    }
+
+    xml.push_str(
+        r#"        </ows:HTTP>
+      </ows:DCP>
+    </ows:Operation>
+"#,
+    );
+}
+
+/// What a Layer element describes, shared by its 256px and 512px variants
+struct LayerInfo<'a> {
+    style_id: &'a str,
+    style_name: &'a str,
+    /// [west, south, east, north]
+    bounds: &'a [f64; 4],
+    /// TileMatrixSetLimits children, if the layer has fewer zooms than the set
+    limits: Option<&'a str>,
+}
+
+/// TileMatrixSetLimits element for a style's zooms up to `max_zoom`
+fn write_tile_matrix_set_limits(extent: &RasterExtent, max_zoom: u8) -> String {
+    let mut xml = String::from("        <TileMatrixSetLimits>\n");
+    for z in extent.minzoom..=extent.maxzoom.min(max_zoom) {
+        let limits = tile_matrix_limits(z, &extent.bounds);
+        write!(
+            xml,
+            r#"          <TileMatrixLimits>
+            <TileMatrix>{}</TileMatrix>
+            <MinTileRow>{}</MinTileRow>
+            <MaxTileRow>{}</MaxTileRow>
+            <MinTileCol>{}</MinTileCol>
+            <MaxTileCol>{}</MaxTileCol>
+          </TileMatrixLimits>
+"#,
+            z, limits.min_tile_row, limits.max_tile_row, limits.min_tile_col, limits.max_tile_col
+        )
+        .unwrap();
+    }
+    xml.push_str("        </TileMatrixSetLimits>\n");
+    xml
+}
+
 /// Write a Layer element for a specific tile size
 fn write_layer(
     xml: &mut String,
     base_url: &str,
-    style_id: &str,
-    style_name: &str,
+    layer: &LayerInfo,
     tile_size: u16,
     key_query: &str,
 ) {
+    let LayerInfo {
+        style_id,
+        style_name,
+        bounds: [west, south, east, north],
+        limits,
+    } = layer;
     let layer_id = format!("{}-{}", style_id, tile_size);
     let layer_title = format!("{}-{}", style_name, tile_size);
     let matrix_set = format!("GoogleMapsCompatible_{}", tile_size);
 
     // Build tile URL template with optional key query parameter
-    // For 256px: /styles/{id}/{z}/{x}/{y}.png?key=...
-    // For 512px: /styles/{id}/{z}/{x}/{y}@2x.png?key=... (which renders at 512px)
-    let tile_template = if tile_size == 256 {
-        format!(
-            "{}/styles/{}/{{TileMatrix}}/{{TileCol}}/{{TileRow}}.png{}",
-            base_url, style_id, key_query
-        )
-    } else {
-        // 512px tiles use @2x scale factor
-        format!(
-            "{}/styles/{}/{{TileMatrix}}/{{TileCol}}/{{TileRow}}@2x.png{}",
-            base_url, style_id, key_query
-        )
-    };
+    // For 256px: /styles/{id}/256/{z}/{x}/{y}.png?key=...
+    // For 512px: /styles/{id}/256/{z}/{x}/{y}@2x.png?key=... (which renders at 512px)
+    let scale_suffix = if tile_size == 256 { "" } else { "@2x" };
+    let tile_template = format!(
+        "{}/styles/{}/256/{{TileMatrix}}/{{TileCol}}/{{TileRow}}{}.png{}",
+        base_url, style_id, scale_suffix, key_query
+    );
 
     write!(
         xml,
@@ -163,20 +309,29 @@ fn write_layer(
       <ows:Title>{}</ows:Title>
       <ows:Identifier>{}</ows:Identifier>
       <ows:WGS84BoundingBox crs="urn:ogc:def:crs:OGC:2:84">
-        <ows:LowerCorner>-180 -85.051128779807</ows:LowerCorner>
-        <ows:UpperCorner>180 85.051128779807</ows:UpperCorner>
+        <ows:LowerCorner>{} {}</ows:LowerCorner>
+        <ows:UpperCorner>{} {}</ows:UpperCorner>
       </ows:WGS84BoundingBox>
       <Style isDefault="true">
         <ows:Identifier>default</ows:Identifier>
       </Style>
       <Format>image/png</Format>
+      <InfoFormat>application/json</InfoFormat>
       <TileMatrixSetLink>
         <TileMatrixSet>{}</TileMatrixSet>
-      </TileMatrixSetLink>
+{}      </TileMatrixSetLink>
       <ResourceURL format="image/png" resourceType="tile" template="{}"/>
     </Layer>
 "#,
-        layer_title, layer_id, matrix_set, tile_template
+        layer_title,
+        layer_id,
+        west,
+        south,
+        east,
+        north,
+        matrix_set,
+        limits.unwrap_or_default(),
+        tile_template
     )
     .unwrap();
 }
@@ -198,14 +353,9 @@ fn write_tile_matrix_set_google_maps(xml: &mut String, tile_size: u16, min_zoom:
     .unwrap();
 
     // Write TileMatrix for each zoom level
-    let max_z = (max_zoom as usize).min(SCALE_DENOMINATORS_256.len() - 1);
-    for (z, &base_scale) in SCALE_DENOMINATORS_256
-        .iter()
-        .enumerate()
-        .take(max_z + 1)
-        .skip(min_zoom as usize)
-    {
+    for z in min_zoom..=max_zoom.min(MAX_TILE_MATRIX) {
         // For 512px tiles, scale denominator is halved (same geographic extent, double pixels)
+        let base_scale = scale_denominator(z);
         let scale = if tile_size == 512 {
             base_scale / 2.0
         } else {
@@ -234,6 +384,383 @@ fn write_tile_matrix_set_google_maps(xml: &mut String, tile_size: u16, min_zoom:
     xml.push_str("    </TileMatrixSet>\n");
 }
 
+/// Tile addressed by a KVP GetTile or GetFeatureInfo request
+#[derive(Debug, Clone, PartialEq)]
+pub struct WmtsTileRequest {
+    /// Layer identifier (`{style}-256`, `{style}-512` or a bare style ID)
+    pub layer: String,
+    /// Requested TileMatrixSet, if any
+    pub tile_matrix_set: Option<String>,
+    /// Zoom level (TILEMATRIX)
+    pub z: u8,
+    /// Column (TILECOL)
+    pub x: u32,
+    /// Row (TILEROW)
+    pub y: u32,
+    /// Output image format
+    pub format: ImageFormat,
+}
+
+/// A parsed WMTS KVP request (variants mirror the OGC operation names)
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::enum_variant_names)]
+pub enum WmtsRequest {
+    GetCapabilities,
+    GetTile(WmtsTileRequest),
+    GetFeatureInfo {
+        tile: WmtsTileRequest,
+        /// Pixel column within the tile
+        i: u32,
+        /// Pixel row within the tile
+        j: u32,
+    },
+}
+
+impl WmtsRequest {
+    /// Parse a KVP request; parameter names are matched case-insensitively
+    pub fn from_kvp(params: &HashMap<String, String>) -> Result<Self, WmtsException> {
+        let params: HashMap<String, &str> = params
+            .iter()
+            .map(|(k, v)| (k.to_ascii_uppercase(), v.as_str()))
+            .collect();
+        let get = |name: &str| params.get(name).copied();
+        let require = |name: &str| get(name).ok_or_else(|| WmtsException::missing(name));
+
+        let service = require("SERVICE")?;
+        if !service.eq_ignore_ascii_case("WMTS") {
+            return Err(WmtsException::invalid(
+                "SERVICE",
+                format!("Unsupported service: {}", service),
+            ));
+        }
+
+        let request = require("REQUEST")?;
+        let parse_tile = || -> Result<WmtsTileRequest, WmtsException> {
+            let layer = require("LAYER")?.to_string();
+            let tile_matrix_set = get("TILEMATRIXSET").map(String::from);
+            let format = match get("FORMAT") {
+                Some(format) => parse_image_format(format)?,
+                None => ImageFormat::Png,
+            };
+
+            let z: u8 = parse_number("TILEMATRIX", require("TILEMATRIX")?)?;
+            let y: u32 = parse_number("TILEROW", require("TILEROW")?)?;
+            let x: u32 = parse_number("TILECOL", require("TILECOL")?)?;
+
+            if z > MAX_TILE_MATRIX {
+                return Err(WmtsException::out_of_range(
+                    "TILEMATRIX",
+                    format!("TileMatrix {} is out of range 0-{}", z, MAX_TILE_MATRIX),
+                ));
+            }
+            let matrix_size = 1u32 << z;
+            if y >= matrix_size {
+                return Err(WmtsException::out_of_range(
+                    "TILEROW",
+                    format!("TileRow {} is out of range for TileMatrix {}", y, z),
+                ));
+            }
+            if x >= matrix_size {
+                return Err(WmtsException::out_of_range(
+                    "TILECOL",
+                    format!("TileCol {} is out of range for TileMatrix {}", x, z),
+                ));
+            }
+
+            Ok(WmtsTileRequest {
+                layer,
+                tile_matrix_set,
+                z,
+                x,
+                y,
+                format,
+            })
+        };
+
+        match request {
+            "GetCapabilities" => Ok(Self::GetCapabilities),
+            "GetTile" => Ok(Self::GetTile(parse_tile()?)),
+            "GetFeatureInfo" => {
+                let tile = parse_tile()?;
+                let i = parse_number("I", require("I")?)?;
+                let j = parse_number("J", require("J")?)?;
+                if let Some(info_format) = get("INFOFORMAT") {
+                    if !matches!(info_format, "application/json" | "application/geo+json") {
+                        return Err(WmtsException::invalid(
+                            "INFOFORMAT",
+                            format!("Unsupported InfoFormat: {}", info_format),
+                        ));
+                    }
+                }
+                Ok(Self::GetFeatureInfo { tile, i, j })
+            }
+            other => Err(WmtsException::new(
+                "OperationNotSupported",
+                Some("REQUEST"),
+                format!("Unsupported request: {}", other),
+            )),
+        }
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, WmtsException> {
+    value
+        .parse()
+        .map_err(|_| WmtsException::invalid(name, format!("Invalid {} value: {}", name, value)))
+}
+
+/// Parse a FORMAT value such as `image/png`
+fn parse_image_format(format: &str) -> Result<ImageFormat, WmtsException> {
+    format
+        .strip_prefix("image/")
+        .unwrap_or(format)
+        .parse()
+        .map_err(|_| WmtsException::invalid("FORMAT", format!("Unsupported format: {}", format)))
+}
+
+/// Resolve a WMTS layer identifier to its style and tile size
+///
+/// Layers are advertised as `{style}-256` and `{style}-512`; a bare style ID
+/// is accepted as the 256px layer.
+pub fn resolve_layer<'a>(
+    styles: &'a StyleManager,
+    layer: &str,
+    tile_matrix_set: Option<&str>,
+) -> Result<(&'a Style, u16), WmtsException> {
+    let resolved = styles.get(layer).map(|style| (style, 256)).or_else(|| {
+        [256u16, 512].into_iter().find_map(|size| {
+            layer
+                .strip_suffix(&format!("-{}", size))
+                .and_then(|style_id| styles.get(style_id))
+                .map(|style| (style, size))
+        })
+    });
+
+    let (style, tile_size) = resolved
+        .ok_or_else(|| WmtsException::invalid("LAYER", format!("Unknown layer: {}", layer)))?;
+
+    if let Some(matrix_set) = tile_matrix_set {
+        if matrix_set != format!("GoogleMapsCompatible_{}", tile_size) {
+            return Err(WmtsException::invalid(
+                "TILEMATRIXSET",
+                format!(
+                    "TileMatrixSet {} is not available for layer {}",
+                    matrix_set, layer
+                ),
+            ));
+        }
+    }
+
+    Ok((style, tile_size))
+}
+
+/// Validate GetFeatureInfo pixel coordinates against the tile size
+pub fn check_pixel(i: u32, j: u32, tile_size: u16) -> Result<(), WmtsException> {
+    for (name, value) in [("I", i), ("J", j)] {
+        if value >= tile_size as u32 {
+            return Err(WmtsException::new(
+                "PointIJOutOfRange",
+                Some(name),
+                format!("{} {} is out of range 0-{}", name, value, tile_size - 1),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Query the features of a vector source under a pixel of a WMTS tile
+///
+/// Tiles beyond the source's maxzoom are answered from the ancestor tile at
+/// maxzoom. A feature matches when the pixel falls within its bounding box,
+/// expanded by a few pixels so points and lines can be hit.
+pub async fn query_point(
+    source: &dyn TileSource,
+    tile: &WmtsTileRequest,
+    i: u32,
+    j: u32,
+    tile_size: u16,
+) -> crate::error::Result<Vec<serde_json::Value>> {
+    let metadata = source.metadata();
+    if metadata.format != TileFormat::Pbf || tile.z < metadata.minzoom {
+        return Ok(Vec::new());
+    }
+
+    // Map the pixel into the ancestor tile when overzooming
+    let dz = tile.z.saturating_sub(metadata.maxzoom);
+    let factor = (1u32 << dz) as f64;
+    let offset_mask = (1u32 << dz) - 1;
+    let px = ((tile.x & offset_mask) as f64 * tile_size as f64 + i as f64 + 0.5) / factor;
+    let py = ((tile.y & offset_mask) as f64 * tile_size as f64 + j as f64 + 0.5) / factor;
+
+    let Some(data) = source
+        .get_tile(tile.z - dz, tile.x >> dz, tile.y >> dz)
+        .await?
+    else {
+        return Ok(Vec::new());
+    };
+
+    let mut mvt_tile = data.decode_mvt()?;
+    let mut features = Vec::new();
+
+    for layer in mvt_tile.layers.iter_mut() {
+        let scale = layer.extent.unwrap_or(4096) as f64 / tile_size as f64;
+        let (x, y) = (px * scale, py * scale);
+        let tolerance = FEATURE_INFO_TOLERANCE_PX * scale;
+
+        layer.features.retain(|feature| {
+            geometry_bbox(&feature.geometry).is_some_and(|[min_x, min_y, max_x, max_y]| {
+                x >= min_x - tolerance
+                    && x <= max_x + tolerance
+                    && y >= min_y - tolerance
+                    && y <= max_y + tolerance
+            })
+        });
+
+        if !layer.features.is_empty() {
+            features.extend(mvt_layer_features(layer));
+        }
+    }
+
+    Ok(features)
+}
+
+/// Bounding box `[min_x, min_y, max_x, max_y]` of an MVT geometry in tile coordinates
+fn geometry_bbox(geometry: &[u32]) -> Option<[f64; 4]> {
+    let zigzag = |v: u32| ((v >> 1) as i64) ^ -((v & 1) as i64);
+
+    let (mut cx, mut cy) = (0i64, 0i64);
+    let mut bbox: Option<[f64; 4]> = None;
+    let mut idx = 0;
+
+    while idx < geometry.len() {
+        let command = geometry[idx];
+        idx += 1;
+
+        // ClosePath has no parameters
+        if command & 0x7 == 7 {
+            continue;
+        }
+
+        for _ in 0..(command >> 3) {
+            let (Some(&dx), Some(&dy)) = (geometry.get(idx), geometry.get(idx + 1)) else {
+                return bbox;
+            };
+            idx += 2;
+            cx += zigzag(dx);
+            cy += zigzag(dy);
+
+            let (x, y) = (cx as f64, cy as f64);
+            bbox = Some(match bbox {
+                Some([min_x, min_y, max_x, max_y]) => {
+                    [min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y)]
+                }
+                None => [x, y, x, y],
+            });
+        }
+    }
+
+    bbox
+}
+
+/// An OGC exception, rendered as an `ows:ExceptionReport`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WmtsException {
+    /// OGC exception code (e.g. `InvalidParameterValue`)
+    pub code: &'static str,
+    /// Name of the offending parameter
+    pub locator: Option<String>,
+    /// Human-readable description
+    pub text: String,
+}
+
+impl WmtsException {
+    pub fn new(code: &'static str, locator: Option<&str>, text: impl Into<String>) -> Self {
+        Self {
+            code,
+            locator: locator.map(String::from),
+            text: text.into(),
+        }
+    }
+
+    fn missing(param: &str) -> Self {
+        Self::new(
+            "MissingParameterValue",
+            Some(param),
+            format!("Missing parameter: {}", param),
+        )
+    }
+
+    fn invalid(param: &str, text: String) -> Self {
+        Self::new("InvalidParameterValue", Some(param), text)
+    }
+
+    fn out_of_range(param: &str, text: String) -> Self {
+        Self::new("TileOutOfRange", Some(param), text)
+    }
+
+    /// HTTP status code for this exception, per OGC WMTS 1.0.0 (Table 28)
+    pub fn status(&self) -> StatusCode {
+        match self.code {
+            "OperationNotSupported" => StatusCode::NOT_IMPLEMENTED,
+            "NoApplicableCode" => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    /// Render as an `ows:ExceptionReport` XML document
+    pub fn to_xml(&self) -> String {
+        let locator = self
+            .locator
+            .as_deref()
+            .map(|l| format!(r#" locator="{}""#, escape_xml(l)))
+            .unwrap_or_default();
+
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<ows:ExceptionReport xmlns:ows="http://www.opengis.net/ows/1.1" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:schemaLocation="http://www.opengis.net/ows/1.1 http://schemas.opengis.net/ows/1.1.0/owsExceptionReport.xsd" version="1.0.0" xml:lang="en">
+  <ows:Exception exceptionCode="{}"{}>
+    <ows:ExceptionText>{}</ows:ExceptionText>
+  </ows:Exception>
+</ows:ExceptionReport>
+"#,
+            self.code,
+            locator,
+            escape_xml(&self.text)
+        )
+    }
+}
+
+impl From<TileServerError> for WmtsException {
+    fn from(err: TileServerError) -> Self {
+        match err {
+            // Outside the layer's zoom range or bounds
+            TileServerError::TileNotFound { .. } => {
+                Self::out_of_range("TILEMATRIX", err.to_string())
+            }
+            err => Self::new("NoApplicableCode", None, err.to_string()),
+        }
+    }
+}
+
+impl IntoResponse for WmtsException {
+    fn into_response(self) -> Response {
+        (
+            self.status(),
+            [(CONTENT_TYPE, HeaderValue::from_static("application/xml"))],
+            self.to_xml(),
+        )
+            .into_response()
+    }
+}
+
+/// Escape text for use in XML content and attributes
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -244,8 +771,7 @@ mod tests {
             "http://localhost:8080",
             "osm-bright",
             "OSM Bright",
-            0,
-            18,
+            &RasterExtent::default(),
             None,
         );
 
@@ -266,8 +792,7 @@ mod tests {
             "http://localhost:8080",
             "osm-bright",
             "OSM Bright",
-            0,
-            18,
+            &RasterExtent::default(),
             Some("my_api_key_123"),
         );
 
@@ -281,8 +806,8 @@ mod tests {
         assert!(xml.contains("http://localhost:8080/styles/osm-bright/wmts.xml?key=my_api_key_123"));
 
         // Tile URLs should include key
-        assert!(xml.contains("{TileRow}.png?key=my_api_key_123"));
-        assert!(xml.contains("{TileRow}@2x.png?key=my_api_key_123"));
+        assert!(xml.contains("/256/{TileMatrix}/{TileCol}/{TileRow}.png?key=my_api_key_123"));
+        assert!(xml.contains("/256/{TileMatrix}/{TileCol}/{TileRow}@2x.png?key=my_api_key_123"));
     }
 
     #[test]
@@ -291,12 +816,230 @@ mod tests {
             "http://localhost:8080",
             "osm-bright",
             "OSM Bright",
-            0,
-            18,
+            &RasterExtent::default(),
             Some("key with spaces & symbols="),
         );
 
         // Key should be URL-encoded
         assert!(xml.contains("?key=key%20with%20spaces%20%26%20symbols%3D"));
     }
+
+    fn kvp(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_generate_wmts_capabilities_advertises_kvp() {
+        let xml = generate_wmts_capabilities(
+            "http://localhost:8080",
+            "osm-bright",
+            "OSM Bright",
+            &RasterExtent::default(),
+            None,
+        );
+
+        assert!(xml.contains("<ows:Value>KVP</ows:Value>"));
+        assert!(xml.contains(r#"<ows:Operation name="GetFeatureInfo">"#));
+        assert!(xml.contains(r#"xlink:href="http://localhost:8080/wmts?""#));
+        assert!(xml.contains("<InfoFormat>application/json</InfoFormat>"));
+    }
+
+    #[test]
+    fn test_generate_wmts_service_capabilities() {
+        let xml = generate_wmts_service_capabilities(
+            "http://localhost:8080",
+            &[
+                ("osm-bright", "OSM Bright", RasterExtent::default()),
+                (
+                    "dark",
+                    "Dark",
+                    RasterExtent {
+                        minzoom: 2,
+                        maxzoom: 14,
+                        bounds: [11.22, 43.74, 11.29, 43.79],
+                    },
+                ),
+            ],
+            Some("abc"),
+        );
+
+        assert!(xml.contains("<ows:Identifier>osm-bright-256</ows:Identifier>"));
+        assert!(xml.contains("<ows:Identifier>dark-512</ows:Identifier>"));
+        assert!(xml.contains(
+            "http://localhost:8080/wmts?key=abc&amp;SERVICE=WMTS&amp;REQUEST=GetCapabilities"
+        ));
+        // TileMatrixSets are shared by all layers
+        assert_eq!(xml.matches("<TileMatrixSet>\n").count(), 2);
+        assert!(xml.contains("<ows:Identifier>18</ows:Identifier>"));
+
+        // The dark layers list their zooms 2 to 14 and bounds
+        assert_eq!(xml.matches("<TileMatrixSetLimits>").count(), 2);
+        assert_eq!(xml.matches("<TileMatrixLimits>").count(), 2 * 13);
+        assert!(
+            xml.contains("<TileMatrix>14</TileMatrix>\n            <MinTileRow>5970</MinTileRow>")
+        );
+        assert!(xml.contains("<ows:LowerCorner>11.22 43.74</ows:LowerCorner>"));
+        assert!(xml.contains("<ows:LowerCorner>-180 -85.051128779807</ows:LowerCorner>"));
+    }
+
+    #[test]
+    fn test_parse_kvp_get_tile() {
+        let request = WmtsRequest::from_kvp(&kvp(&[
+            ("service", "WMTS"),
+            ("Request", "GetTile"),
+            ("VERSION", "1.0.0"),
+            ("LAYER", "osm-bright-256"),
+            ("STYLE", "default"),
+            ("TILEMATRIXSET", "GoogleMapsCompatible_256"),
+            ("TILEMATRIX", "12"),
+            ("TILEROW", "1583"),
+            ("TILECOL", "655"),
+            ("FORMAT", "image/png"),
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            request,
+            WmtsRequest::GetTile(WmtsTileRequest {
+                layer: "osm-bright-256".to_string(),
+                tile_matrix_set: Some("GoogleMapsCompatible_256".to_string()),
+                z: 12,
+                x: 655,
+                y: 1583,
+                format: ImageFormat::Png,
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_kvp_get_capabilities() {
+        let request =
+            WmtsRequest::from_kvp(&kvp(&[("SERVICE", "wmts"), ("REQUEST", "GetCapabilities")]));
+        assert_eq!(request, Ok(WmtsRequest::GetCapabilities));
+    }
+
+    #[test]
+    fn test_parse_kvp_get_feature_info() {
+        let request = WmtsRequest::from_kvp(&kvp(&[
+            ("SERVICE", "WMTS"),
+            ("REQUEST", "GetFeatureInfo"),
+            ("LAYER", "osm-bright"),
+            ("TILEMATRIX", "2"),
+            ("TILEROW", "1"),
+            ("TILECOL", "2"),
+            ("I", "10"),
+            ("J", "20"),
+            ("INFOFORMAT", "application/json"),
+        ]))
+        .unwrap();
+
+        match request {
+            WmtsRequest::GetFeatureInfo { tile, i, j } => {
+                assert_eq!((tile.z, tile.x, tile.y), (2, 2, 1));
+                assert_eq!((i, j), (10, 20));
+            }
+            other => panic!("Expected GetFeatureInfo, got {:?}", other),
+        }
+
+        let err = WmtsRequest::from_kvp(&kvp(&[
+            ("SERVICE", "WMTS"),
+            ("REQUEST", "GetFeatureInfo"),
+            ("LAYER", "osm-bright"),
+            ("TILEMATRIX", "2"),
+            ("TILEROW", "1"),
+            ("TILECOL", "2"),
+            ("I", "10"),
+            ("J", "20"),
+            ("INFOFORMAT", "text/html"),
+        ]))
+        .unwrap_err();
+        assert_eq!(err.code, "InvalidParameterValue");
+        assert_eq!(err.locator.as_deref(), Some("INFOFORMAT"));
+    }
+
+    #[test]
+    fn test_parse_kvp_errors() {
+        let err = WmtsRequest::from_kvp(&kvp(&[("SERVICE", "WMTS")])).unwrap_err();
+        assert_eq!(err.code, "MissingParameterValue");
+        assert_eq!(err.locator.as_deref(), Some("REQUEST"));
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+
+        let err =
+            WmtsRequest::from_kvp(&kvp(&[("SERVICE", "WMS"), ("REQUEST", "GetMap")])).unwrap_err();
+        assert_eq!(err.code, "InvalidParameterValue");
+        assert_eq!(err.locator.as_deref(), Some("SERVICE"));
+
+        let err = WmtsRequest::from_kvp(&kvp(&[("SERVICE", "WMTS"), ("REQUEST", "GetLegend")]))
+            .unwrap_err();
+        assert_eq!(err.code, "OperationNotSupported");
+        assert_eq!(err.status(), StatusCode::NOT_IMPLEMENTED);
+
+        let tile_params = |matrix: &'static str, row: &'static str, format: &'static str| {
+            kvp(&[
+                ("SERVICE", "WMTS"),
+                ("REQUEST", "GetTile"),
+                ("LAYER", "osm-bright"),
+                ("TILEMATRIX", matrix),
+                ("TILEROW", row),
+                ("TILECOL", "0"),
+                ("FORMAT", format),
+            ])
+        };
+
+        let err = WmtsRequest::from_kvp(&tile_params("abc", "0", "image/png")).unwrap_err();
+        assert_eq!(err.code, "InvalidParameterValue");
+        assert_eq!(err.locator.as_deref(), Some("TILEMATRIX"));
+
+        let err = WmtsRequest::from_kvp(&tile_params("19", "0", "image/png")).unwrap_err();
+        assert_eq!(err.code, "TileOutOfRange");
+        assert_eq!(err.locator.as_deref(), Some("TILEMATRIX"));
+
+        let err = WmtsRequest::from_kvp(&tile_params("2", "4", "image/png")).unwrap_err();
+        assert_eq!(err.code, "TileOutOfRange");
+        assert_eq!(err.locator.as_deref(), Some("TILEROW"));
+
+        let err = WmtsRequest::from_kvp(&tile_params("2", "0", "image/gif")).unwrap_err();
+        assert_eq!(err.locator.as_deref(), Some("FORMAT"));
+    }
+
+    #[test]
+    fn test_check_pixel() {
+        assert!(check_pixel(0, 255, 256).is_ok());
+        assert!(check_pixel(511, 511, 512).is_ok());
+
+        let err = check_pixel(256, 0, 256).unwrap_err();
+        assert_eq!(err.code, "PointIJOutOfRange");
+        assert_eq!(err.locator.as_deref(), Some("I"));
+    }
+
+    #[test]
+    fn test_exception_report_xml() {
+        let err = WmtsException::new(
+            "InvalidParameterValue",
+            Some("LAYER"),
+            "Unknown layer: <a&b>",
+        );
+        let xml = err.to_xml();
+
+        assert!(xml.contains("<ows:ExceptionReport"));
+        assert!(xml.contains(r#"exceptionCode="InvalidParameterValue" locator="LAYER""#));
+        assert!(xml.contains("Unknown layer: &lt;a&amp;b&gt;"));
+    }
+
+    #[test]
+    fn test_geometry_bbox() {
+        // MoveTo(1) (25,17)
+        assert_eq!(geometry_bbox(&[9, 50, 34]), Some([25.0, 17.0, 25.0, 17.0]));
+
+        // MoveTo(1) (3,6), LineTo(2) (+5,+2) (+0,-10), ClosePath
+        assert_eq!(
+            geometry_bbox(&[9, 6, 12, 18, 10, 4, 0, 19, 15]),
+            Some([3.0, -2.0, 8.0, 8.0])
+        );
+
+        assert_eq!(geometry_bbox(&[]), None);
+    }
 }
@@ -8,6 +8,33 @@ use std::path::PathBuf;
 /// Test configuration path
 const TEST_CONFIG: &str = "tests/config.test.toml";
 
+/// Config of source `id` of type `kind` reading `path`, followed by the
+/// TOML in `extra`
+fn source_config(
+    id: &str,
+    kind: &str,
+    path: &str,
+    extra: &str,
+) -> tileserver_rs::config::SourceConfig {
+    toml::from_str(&format!(
+        "id = \"{}\"\ntype = \"{}\"\npath = \"{}\"\n{}",
+        id, kind, path, extra
+    ))
+    .unwrap()
+}
+
+/// Layers of the vector tile `z/x/y` of `source`, which must exist
+async fn decode_layers(
+    source: &dyn tileserver_rs::sources::TileSource,
+    z: u8,
+    x: u32,
+    y: u32,
+) -> Vec<geozero::mvt::tile::Layer> {
+    let tile = source.get_tile(z, x, y).await.unwrap().expect("a tile");
+    assert_eq!(tile.format, tileserver_rs::sources::TileFormat::Pbf);
+    tile.decode_mvt().unwrap().layers
+}
+
 // ============================================================
 // Health Endpoint Tests
 // ============================================================
@@ -29,6 +56,81 @@ mod health_tests {
         assert_eq!(expected_response, "OK");
         assert_eq!(expected_response.len(), 2);
     }
+
+    /// Test config plus a source pointing at a file that does not exist
+    fn config_with_broken_source(optional: bool) -> tileserver_rs::Config {
+        let mut config = tileserver_rs::Config::load(Some(PathBuf::from(TEST_CONFIG)))
+            .expect("Should load test config");
+        let broken = tileserver_rs::config::SourceConfig {
+            id: "broken".to_string(),
+            path: "tests/fixtures/missing.pmtiles".to_string(),
+            optional,
+            ..config.sources[0].clone()
+        };
+        config.sources.push(broken);
+        config
+    }
+
+    #[tokio::test]
+    async fn test_ready_fails_on_broken_source() {
+        use axum::{http::StatusCode, response::IntoResponse};
+        use tileserver_rs::health::{self, HealthStatus, ReadinessChecker};
+        use tileserver_rs::SourceManager;
+
+        let config = config_with_broken_source(false);
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
+
+        let report = ReadinessChecker::default().check(&sources).await;
+        assert!(!report.is_ready());
+
+        let broken = report
+            .sources
+            .iter()
+            .find(|s| s.id == "broken")
+            .expect("Report should name the failing source");
+        assert_eq!(broken.status, HealthStatus::Error);
+        assert!(broken.error.is_some());
+        let protomaps = report.sources.iter().find(|s| s.id == "protomaps").unwrap();
+        assert_eq!(protomaps.status, HealthStatus::Ok);
+
+        let response = report.into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["status"], "error");
+        assert!(json["sources"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|s| s["id"] == "broken" && s["status"] == "error"));
+
+        // Liveness does not depend on sources
+        let (status, body) = health::liveness().await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "OK");
+    }
+
+    #[tokio::test]
+    async fn test_ready_ignores_optional_source() {
+        use tileserver_rs::health::ReadinessChecker;
+        use tileserver_rs::SourceManager;
+
+        let config = config_with_broken_source(true);
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
+
+        let report = ReadinessChecker::default().check(&sources).await;
+        assert!(report.is_ready());
+        assert!(report
+            .sources
+            .iter()
+            .any(|s| s.id == "broken" && s.optional));
+    }
 }
 
 // ============================================================
@@ -69,21 +171,34 @@ mod openapi_tests {
         // All endpoints that should be documented
         let required_paths = [
             "/health",
+            "/health/live",
+            "/health/ready",
             "/index.json",
             "/data.json",
             "/data/{source}",
             "/data/{source}/{z}/{x}/{y}.{format}",
+            "/data/{source}/{z}/{x}/{y}",
             "/styles.json",
             "/styles/{style}.json",
             "/styles/{style}/style.json",
             "/styles/{style}/{z}/{x}/{y}.{format}",
             "/styles/{style}/{tileSize}/{z}/{x}/{y}.{format}",
             "/styles/{style}/static/{center}/{size}.{format}",
+            "/styles/{style}/render",
             "/styles/{style}/sprite.{ext}",
             "/styles/{style}/wmts.xml",
+            "/styles/{style}/validate",
+            "/wmts",
             "/fonts.json",
             "/fonts/{fontstack}/{range}",
+            "/fonts/subset",
             "/files/{filepath}",
+            "/events",
+            "/diff/{source_a}/{source_b}/{z}/{x}/{y}",
+            "/conformance",
+            "/tiles",
+            "/tiles/{tilesetId}",
+            "/tiles/{tilesetId}/{tileMatrix}/{tileRow}/{tileCol}",
         ];
 
         for path in required_paths {
@@ -104,7 +219,7 @@ mod openapi_tests {
         assert!(spec.tags.is_some());
 
         let tags = spec.tags.as_ref().unwrap();
-        assert_eq!(tags.len(), 5, "Should have 5 tags");
+        assert_eq!(tags.len(), 7, "Should have 7 tags");
     }
 
     #[test]
@@ -124,6 +239,11 @@ mod openapi_tests {
             "VectorLayer",
             "StyleInfo",
             "GeoJSON",
+            "StaticRenderRequest",
+            "MarkerOverlay",
+            "PathOverlay",
+            "TileDiff",
+            "StyleValidation",
             "ApiError",
         ];
 
@@ -148,13 +268,195 @@ mod openapi_tests {
     }
 }
 
+// ============================================================
+// OGC API - Tiles Tests
+// ============================================================
+
+mod ogcapi_tests {
+    use super::*;
+    use serde_json::Value;
+    use tileserver_rs::ogcapi;
+    use tileserver_rs::{Config, SourceManager, StyleManager};
+
+    const BASE_URL: &str = "http://localhost:8080";
+
+    async fn load() -> (SourceManager, StyleManager) {
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
+        let styles = StyleManager::from_configs(&config.styles).expect("Should load styles");
+        (sources, styles)
+    }
+
+    /// link.yaml: every link requires `href` and `rel`
+    fn assert_links_valid(doc: &Value) {
+        let links = doc["links"].as_array().expect("links must be an array");
+        assert!(!links.is_empty());
+        for link in links {
+            assert!(link["href"].is_string(), "link.href is required: {}", link);
+            assert!(link["rel"].is_string(), "link.rel is required: {}", link);
+        }
+    }
+
+    fn has_rel(doc: &Value, rel: &str) -> bool {
+        doc["links"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|link| link["rel"] == rel)
+    }
+
+    #[test]
+    fn test_landing_page_schema() {
+        let doc = serde_json::to_value(ogcapi::landing_page(BASE_URL)).unwrap();
+
+        // landingPage.yaml requires links; core requires service-desc and conformance
+        assert_links_valid(&doc);
+        assert!(has_rel(&doc, "self"));
+        assert!(has_rel(&doc, "service-desc"));
+        assert!(has_rel(
+            &doc,
+            "http://www.opengis.net/def/rel/ogc/1.0/conformance"
+        ));
+        assert!(has_rel(
+            &doc,
+            "http://www.opengis.net/def/rel/ogc/1.0/tilesets-vector"
+        ));
+    }
+
+    #[test]
+    fn test_conformance_schema() {
+        let doc = serde_json::to_value(ogcapi::conformance()).unwrap();
+
+        // confClasses.yaml requires a conformsTo array of URIs
+        let classes: Vec<&str> = doc["conformsTo"]
+            .as_array()
+            .expect("conformsTo must be an array")
+            .iter()
+            .map(|c| c.as_str().unwrap())
+            .collect();
+        for class in ["core", "tileset", "tilesets-list"] {
+            let uri = format!(
+                "http://www.opengis.net/spec/ogcapi-tiles-1/1.0/conf/{}",
+                class
+            );
+            assert!(classes.contains(&uri.as_str()), "Missing {}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tileset_list_schema() {
+        let (sources, styles) = load().await;
+        let doc = serde_json::to_value(ogcapi::tileset_list(BASE_URL, &sources, &styles)).unwrap();
+
+        // tileSet-item.yaml requires dataType, crs and links (with a self link)
+        let tilesets = doc["tilesets"]
+            .as_array()
+            .expect("tilesets must be an array");
+        assert_eq!(tilesets.len(), sources.len() + styles.len());
+        for tileset in tilesets {
+            assert!(["map", "vector"].contains(&tileset["dataType"].as_str().unwrap()));
+            assert!(tileset["crs"].is_string());
+            assert_links_valid(tileset);
+            assert!(has_rel(tileset, "self"));
+        }
+
+        let hrefs: Vec<&str> = tilesets
+            .iter()
+            .map(|t| t["links"][0]["href"].as_str().unwrap())
+            .collect();
+        assert!(hrefs.contains(&"http://localhost:8080/tiles/protomaps"));
+        assert!(hrefs.contains(&"http://localhost:8080/tiles/protomaps-light"));
+    }
+
+    #[tokio::test]
+    async fn test_source_tileset_metadata() {
+        let (sources, styles) = load().await;
+        let tileset = ogcapi::find_tileset("protomaps", &sources, &styles).unwrap();
+        let metadata = sources.get("protomaps").unwrap().metadata();
+        let doc = serde_json::to_value(tileset.to_tileset(BASE_URL)).unwrap();
+
+        // tileSet.yaml requires dataType, crs and links
+        assert_eq!(doc["dataType"], "vector");
+        assert_eq!(doc["crs"], "http://www.opengis.net/def/crs/EPSG/0/3857");
+        assert_links_valid(&doc);
+        assert!(has_rel(
+            &doc,
+            "http://www.opengis.net/def/rel/ogc/1.0/tiling-scheme"
+        ));
+
+        // Zoom range and bounds come from the source metadata
+        let limits = doc["tileMatrixSetLimits"].as_array().unwrap();
+        assert_eq!(
+            limits.len(),
+            (metadata.maxzoom - metadata.minzoom) as usize + 1
+        );
+        assert_eq!(limits[0]["tileMatrix"], metadata.minzoom.to_string());
+        let bounds = metadata.bounds.unwrap();
+        assert_eq!(doc["boundingBox"]["lowerLeft"][0], bounds[0]);
+        assert_eq!(doc["boundingBox"]["upperRight"][1], bounds[3]);
+    }
+
+    #[tokio::test]
+    async fn test_style_tileset_metadata() {
+        let (sources, styles) = load().await;
+        let tileset = ogcapi::find_tileset("protomaps-light", &sources, &styles).unwrap();
+        assert_eq!(tileset.kind, ogcapi::TilesetKind::Style);
+
+        let doc = serde_json::to_value(tileset.to_tileset(BASE_URL)).unwrap();
+        assert_eq!(doc["dataType"], "map");
+        let item = doc["links"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|l| l["rel"] == "item")
+            .unwrap();
+        assert_eq!(item["type"], "image/png");
+
+        assert!(ogcapi::find_tileset("nonexistent", &sources, &styles).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_tile_within_limits_is_served() {
+        let (sources, styles) = load().await;
+        let tileset = ogcapi::find_tileset("protomaps", &sources, &styles).unwrap();
+
+        // Florence at z10: row 373, col 544
+        assert!(tileset.contains_tile(10, 373, 544));
+        assert!(!tileset.contains_tile(10, 0, 0));
+
+        let source = sources.get("protomaps").unwrap();
+        let tile = source.get_tile(10, 544, 373).await.unwrap();
+        assert!(tile.is_some(), "Tile inside the limits should exist");
+    }
+
+    #[test]
+    fn test_tile_matrix_set_definition() {
+        let doc = serde_json::to_value(ogcapi::web_mercator_quad()).unwrap();
+
+        // tileMatrixSet.yaml requires crs and tileMatrices
+        assert_eq!(doc["id"], "WebMercatorQuad");
+        assert!(doc["crs"].is_string());
+        let matrices = doc["tileMatrices"].as_array().unwrap();
+        for (z, matrix) in matrices.iter().enumerate() {
+            assert_eq!(matrix["id"], z.to_string());
+            assert_eq!(matrix["matrixWidth"], 1u64 << z);
+            assert_eq!(matrix["tileWidth"], 256);
+            assert!(matrix["scaleDenominator"].as_f64().unwrap() > 0.0);
+            assert_eq!(matrix["pointOfOrigin"].as_array().unwrap().len(), 2);
+        }
+    }
+}
+
 // ============================================================
 // Data Endpoint Tests
 // ============================================================
 
 mod data_tests {
     use super::*;
-    use tileserver_rs::{Config, SourceManager};
+    use tileserver_rs::{Config, SourceManager, TileJsonVersion};
 
     #[tokio::test]
     async fn test_data_json_returns_all_sources() {
@@ -185,7 +487,7 @@ mod data_tests {
         let tilejson = source
             .unwrap()
             .metadata()
-            .to_tilejson("http://localhost:8080");
+            .to_tilejson("http://localhost:8080", TileJsonVersion::default());
 
         // Verify TileJSON structure
         assert_eq!(tilejson.tilejson, "3.0.0");
@@ -193,6 +495,410 @@ mod data_tests {
         assert!(tilejson.minzoom <= tilejson.maxzoom);
     }
 
+    #[tokio::test]
+    async fn test_data_source_tilejson_extended() {
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
+
+        for source_config in &config.sources {
+            let id = source_config.id.as_str();
+            let mut tilejson = sources
+                .metadata(id)
+                .unwrap()
+                .to_tilejson("http://localhost:8080", TileJsonVersion::default());
+            let plain = serde_json::to_value(&tilejson).unwrap();
+            assert!(plain.get("x-tile-count").is_none());
+
+            tilejson.extensions = Some(sources.tilejson_extensions(id));
+            let json = serde_json::to_value(&tilejson).unwrap();
+            let tile_count = sources.get(id).unwrap().tile_count().await.unwrap();
+            assert!(tile_count.is_some(), "{} should count its tiles", id);
+            assert_eq!(json["x-tile-count"].as_u64(), tile_count);
+            let size = std::fs::metadata(&source_config.path).unwrap().len();
+            assert_eq!(json["x-storage-bytes"], size);
+
+            let modified = json["x-last-modified"].as_str().unwrap();
+            assert_eq!(modified.len(), "2024-02-29T12:34:56Z".len());
+            assert!(modified.ends_with('Z') && modified.as_bytes()[10] == b'T');
+            assert_eq!(json["tilejson"], "3.0.0");
+        }
+    }
+
+    /// MBTiles whose metadata claims PNG tiles, storing a WebP tile at 0/0/0
+    /// and a gzip vector tile at 1/0/0
+    fn mislabeled_mbtiles(path: &std::path::Path) {
+        use std::io::Write;
+
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(&[0x1a, 0x02, 0x78, 0x02]).unwrap();
+        let vector_tile = gzip.finish().unwrap();
+
+        let conn = rusqlite::Connection::open(path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE metadata (name TEXT, value TEXT);
+             CREATE TABLE tiles (zoom_level INTEGER, tile_column INTEGER,
+                                 tile_row INTEGER, tile_data BLOB);
+             INSERT INTO metadata VALUES ('name', 'mislabeled'), ('format', 'png'),
+                                         ('minzoom', '0'), ('maxzoom', '1');",
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO tiles VALUES (0, 0, 0, ?1), (1, 0, 1, ?2)",
+            rusqlite::params![&b"RIFF\x24\0\0\0WEBPVP8 "[..], vector_tile],
+        )
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_mbtiles_mislabeled_format() {
+        use tileserver_rs::config::SourceConfig;
+        use tileserver_rs::sources::{TileCompression, TileFormat};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mislabeled.mbtiles");
+        mislabeled_mbtiles(&path);
+
+        let config = |options: &str| -> SourceConfig {
+            toml::from_str(&format!(
+                "id = \"mislabeled\"\ntype = \"mbtiles\"\npath = \"{}\"\n{}",
+                path.display(),
+                options
+            ))
+            .unwrap()
+        };
+
+        // Verified by default: the sniffed format is served from then on
+        let sources = SourceManager::from_configs(&[config("")])
+            .await
+            .expect("Should load sources");
+        let source = sources.get("mislabeled").unwrap();
+        let tile = source.get_tile(0, 0, 0).await.unwrap().unwrap();
+        assert_eq!(tile.format, TileFormat::Webp);
+        assert_eq!(tile.compression, TileCompression::None);
+        let info = source.tile_info(0, 0, 0).await.unwrap().unwrap();
+        assert_eq!(info.format, TileFormat::Webp);
+
+        // Compression always follows the magic bytes
+        let tile = source.get_tile(1, 0, 0).await.unwrap().unwrap();
+        assert_eq!(tile.compression, TileCompression::Gzip);
+
+        // Without verification, the metadata is trusted
+        let sources = SourceManager::from_configs(&[config("[options]\nverify_format = false")])
+            .await
+            .expect("Should load sources");
+        let tile = sources.get("mislabeled").unwrap().get_tile(0, 0, 0).await;
+        assert_eq!(tile.unwrap().unwrap().format, TileFormat::Png);
+    }
+
+    #[tokio::test]
+    async fn test_pmtiles_checksum_failures() {
+        use tileserver_rs::config::SourceConfig;
+        use tileserver_rs::sources::TileCompression;
+
+        let fixture = "data/tiles/protomaps-sample.pmtiles";
+        let config = |path: &std::path::Path, options: &str| -> SourceConfig {
+            toml::from_str(&format!(
+                "id = \"checked\"\ntype = \"pmtiles\"\npath = \"{}\"\n{}",
+                path.display(),
+                options
+            ))
+            .unwrap()
+        };
+        let verified = "[options]\nverify_checksums = true";
+
+        // Intact tiles pass
+        let sources = SourceManager::from_configs(&[config(fixture.as_ref(), verified)])
+            .await
+            .expect("Should load sources");
+        let tile = sources
+            .get("checked")
+            .unwrap()
+            .get_tile(0, 0, 0)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(tile.compression, TileCompression::Gzip);
+
+        // Flip a bit of the tile's CRC-32 in a copy of the archive
+        let mut archive = std::fs::read(fixture).unwrap();
+        let start = archive
+            .windows(tile.data.len())
+            .position(|window| window == &tile.data[..])
+            .expect("tile data in the archive");
+        archive[start + tile.data.len() - 8] ^= 0x01;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("corrupt.pmtiles");
+        std::fs::write(&path, archive).unwrap();
+
+        let sources = SourceManager::from_configs(&[config(&path, verified)])
+            .await
+            .expect("Should load sources");
+        let err = sources
+            .get("checked")
+            .unwrap()
+            .get_tile(0, 0, 0)
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), axum::http::StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(err.to_string().contains("checksum"), "{}", err);
+
+        // Unverified, the corrupt tile is served as stored
+        let sources = SourceManager::from_configs(&[config(&path, "")])
+            .await
+            .expect("Should load sources");
+        let corrupt = sources.get("checked").unwrap().get_tile(0, 0, 0).await;
+        assert_ne!(corrupt.unwrap().unwrap().data, tile.data);
+    }
+
+    /// Copy of the sample archive with its metadata JSON replaced
+    fn pmtiles_with_metadata(dir: &std::path::Path, metadata: serde_json::Value) -> PathBuf {
+        use std::io::Write;
+
+        let mut archive = std::fs::read("data/tiles/protomaps-sample.pmtiles").unwrap();
+        assert_eq!(archive[97], 2, "gzip internal compression");
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(metadata.to_string().as_bytes()).unwrap();
+        let metadata = gzip.finish().unwrap();
+
+        // Append the new metadata and point the header at it
+        let offset = archive.len() as u64;
+        archive[24..32].copy_from_slice(&offset.to_le_bytes());
+        archive[32..40].copy_from_slice(&(metadata.len() as u64).to_le_bytes());
+        archive.extend_from_slice(&metadata);
+
+        let path = dir.join("layers.pmtiles");
+        std::fs::write(&path, archive).unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn test_pmtiles_expand_layers() {
+        use tileserver_rs::config::SourceConfig;
+        use tileserver_rs::sources::TileCompression;
+
+        let dir = tempfile::tempdir().unwrap();
+        let multi = pmtiles_with_metadata(
+            dir.path(),
+            serde_json::json!({
+                "vector_layers": [
+                    { "id": "earth", "minzoom": 0, "maxzoom": 6, "bounds": [-10.0, 35.0, 30.0, 60.0] },
+                    { "id": "water", "minzoom": 0, "maxzoom": 15 },
+                ]
+            }),
+        );
+        let config = |path: &std::path::Path, expand: bool| -> SourceConfig {
+            toml::from_str(&format!(
+                "id = \"sample\"\ntype = \"pmtiles\"\npath = \"{}\"\n[options]\nexpand_layers = {}",
+                path.display(),
+                expand
+            ))
+            .unwrap()
+        };
+
+        let sources = SourceManager::from_configs(&[config(&multi, true)])
+            .await
+            .expect("Should load sources");
+        let mut ids: Vec<&String> = sources.ids();
+        ids.sort();
+        assert_eq!(ids, ["sample", "sample.earth", "sample.water"]);
+
+        let earth = sources.metadata("sample.earth").unwrap();
+        assert_eq!((earth.minzoom, earth.maxzoom), (0, 6));
+        assert_eq!(earth.bounds, Some([-10.0, 35.0, 30.0, 60.0]));
+        assert_eq!(earth.vector_layers.as_ref().unwrap()[0]["id"], "earth");
+        let water = sources.metadata("sample.water").unwrap();
+        assert_eq!(water.maxzoom, 15);
+        assert_eq!(water.bounds, sources.metadata("sample").unwrap().bounds);
+
+        // Tiles keep only the source's layer
+        let tile = sources.get_tile("sample.earth", 0, 0, 0).await.unwrap();
+        let tile = tile.expect("earth tile");
+        assert_eq!(tile.compression, TileCompression::None);
+        let layers: Vec<String> = tile
+            .decode_mvt()
+            .unwrap()
+            .layers
+            .into_iter()
+            .map(|layer| layer.name)
+            .collect();
+        assert_eq!(layers, ["earth"]);
+
+        // ...within the layer's zoom range
+        assert!(sources
+            .get_tile("sample.earth", 7, 0, 0)
+            .await
+            .unwrap()
+            .is_none());
+
+        // The archive itself is still served whole
+        let whole = sources.get_tile("sample", 0, 0, 0).await.unwrap().unwrap();
+        assert!(whole.decode_mvt().unwrap().layers.len() > 1);
+
+        // Opt-in only
+        let sources = SourceManager::from_configs(&[config(&multi, false)])
+            .await
+            .expect("Should load sources");
+        assert_eq!(sources.ids(), ["sample"]);
+
+        // A single dataset is served unchanged
+        let single = pmtiles_with_metadata(
+            dir.path(),
+            serde_json::json!({ "vector_layers": [{ "id": "earth", "minzoom": 0, "maxzoom": 6 }] }),
+        );
+        let sources = SourceManager::from_configs(&[config(&single, true)])
+            .await
+            .expect("Should load sources");
+        assert_eq!(sources.ids(), ["sample"]);
+    }
+
+    #[tokio::test]
+    async fn test_accept_negotiation_against_fixtures() {
+        use tileserver_rs::sources::TileRepresentation::{GeoJson, Native};
+        use tileserver_rs::sources::{mvt_layer_features, TileFormat};
+
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
+
+        for id in ["protomaps", "zurich"] {
+            let source = sources.get(id).unwrap();
+            let format = source.format();
+            assert_eq!(format, TileFormat::Pbf);
+            assert_eq!(format.negotiate(None), Some(Native));
+            assert_eq!(
+                format.negotiate(Some("application/vnd.mapbox-vector-tile")),
+                Some(Native)
+            );
+            assert_eq!(
+                format.negotiate(Some("application/x-protobuf")),
+                Some(Native)
+            );
+            assert_eq!(format.negotiate(Some("*/*")), Some(Native));
+            assert_eq!(format.negotiate(Some("image/png")), None);
+            assert_eq!(format.negotiate(Some("image/*")), None);
+            assert_eq!(
+                format.negotiate(Some("application/x-protobuf;q=0.2, application/geo+json")),
+                Some(GeoJson)
+            );
+
+            // The GeoJSON representation converts the fixture tile
+            assert_eq!(
+                format.negotiate(Some("application/geo+json")),
+                Some(GeoJson)
+            );
+            let tile = source.get_tile(0, 0, 0).await.unwrap().unwrap();
+            let features: Vec<_> = tile
+                .decode_mvt()
+                .unwrap()
+                .layers
+                .iter_mut()
+                .flat_map(mvt_layer_features)
+                .collect();
+            assert!(!features.is_empty(), "{} tile should have features", id);
+        }
+
+        // Image types are matched against a raster source's format
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("raster.mbtiles");
+        mislabeled_mbtiles(&path);
+        let config: tileserver_rs::config::SourceConfig = toml::from_str(&format!(
+            "id = \"raster\"\ntype = \"mbtiles\"\npath = \"{}\"",
+            path.display()
+        ))
+        .unwrap();
+        let sources = SourceManager::from_configs(&[config]).await.unwrap();
+        let format = sources.get("raster").unwrap().format();
+        assert_eq!(format, TileFormat::Png);
+        assert_eq!(format.negotiate(Some("image/png")), Some(Native));
+        assert_eq!(format.negotiate(Some("image/*")), Some(Native));
+        assert_eq!(format.negotiate(Some("image/webp")), None);
+        assert_eq!(format.negotiate(Some("application/geo+json")), None);
+
+        let err = tileserver_rs::TileServerError::NotAcceptable("raster".to_string());
+        assert_eq!(err.status(), axum::http::StatusCode::NOT_ACCEPTABLE);
+    }
+
+    #[tokio::test]
+    async fn test_mbtiles_export() {
+        use tileserver_rs::config::ExportConfig;
+        use tileserver_rs::error::TileServerError;
+        use tileserver_rs::export::{self, ExportQuery};
+
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
+        let source = sources.get("zurich").expect("Should have zurich");
+
+        let query = ExportQuery {
+            min_zoom: Some(10),
+            max_zoom: Some(12),
+            bounds: None,
+        };
+        let plan = export::plan(source.metadata(), &query, &ExportConfig::default()).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("zurich.mbtiles");
+        let written = export::write_mbtiles(source.as_ref(), &plan, &path)
+            .await
+            .expect("Should export tiles");
+
+        let stored = rusqlite::Connection::open("data/tiles/zurich_switzerland.mbtiles").unwrap();
+        let expected: i64 = stored
+            .query_row(
+                "SELECT count(*) FROM tiles WHERE zoom_level BETWEEN 10 AND 12",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(written, expected as u64);
+
+        // A self-contained SQLite file, without a WAL next to it
+        assert!(std::fs::read(&path)
+            .unwrap()
+            .starts_with(b"SQLite format 3\0"));
+        assert!(!dir.path().join("zurich.mbtiles-wal").exists());
+
+        let exported = rusqlite::Connection::open(&path).unwrap();
+        let count: i64 = exported
+            .query_row("SELECT count(*) FROM tiles", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, expected);
+        let metadata = |name: &str| -> String {
+            exported
+                .query_row(
+                    "SELECT value FROM metadata WHERE name = ?1",
+                    [name],
+                    |row| row.get(0),
+                )
+                .unwrap()
+        };
+        assert_eq!(metadata("format"), "pbf");
+        assert_eq!(metadata("minzoom"), "10");
+        assert_eq!(metadata("maxzoom"), "12");
+
+        // Tiles are copied byte for byte, in the same rows
+        let tile = "SELECT tile_data FROM tiles WHERE zoom_level = 12 \
+                    ORDER BY tile_column, tile_row LIMIT 1";
+        let original: Vec<u8> = stored.query_row(tile, [], |row| row.get(0)).unwrap();
+        let copy: Vec<u8> = exported.query_row(tile, [], |row| row.get(0)).unwrap();
+        assert_eq!(original, copy);
+
+        // The limit applies to the estimated number of tiles
+        let limited = ExportConfig { max_tiles: 10 };
+        let result = export::plan(source.metadata(), &query, &limited);
+        assert!(
+            matches!(&result, Err(TileServerError::BadRequest(message)) if message.contains("limit of 10")),
+            "{:?}",
+            result
+        );
+    }
+
     #[tokio::test]
     async fn test_data_source_not_found() {
         let config =
@@ -235,1152 +941,6694 @@ mod data_tests {
         // Should either return Ok(None) or Ok(Some(empty)) for out of bounds
         assert!(tile.is_ok());
     }
-}
 
-// ============================================================
-// Style Endpoint Tests
-// ============================================================
-
-mod style_tests {
-    use super::*;
-    use tileserver_rs::{Config, StyleManager};
+    #[tokio::test]
+    async fn test_tms_request_returns_same_tile() {
+        use tileserver_rs::sources::TileScheme;
 
-    #[test]
-    fn test_styles_json_returns_all_styles() {
         let config =
             Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
-        let styles = StyleManager::from_configs(&config.styles).expect("Should load styles");
-
-        let all_infos = styles.all_infos("http://localhost:8080");
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
 
-        // Should have styles from our test config
-        assert!(!all_infos.is_empty(), "Should have at least one style");
-    }
+        let source = sources.get("zurich").expect("Should have zurich");
 
-    #[test]
-    fn test_style_info_structure() {
-        let config =
-            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
-        let styles = StyleManager::from_configs(&config.styles).expect("Should load styles");
+        // /data/zurich/2/2/1.pbf
+        let xyz_tile = source
+            .get_tile(2, 2, 1)
+            .await
+            .unwrap()
+            .expect("Tile should exist");
 
-        let all_infos = styles.all_infos("http://localhost:8080");
-        let style_info = &all_infos[0];
+        // /data/zurich/2/2/2.pbf?scheme=tms
+        let y = source
+            .metadata()
+            .request_row(2, 2, Some(TileScheme::Tms))
+            .expect("Row should be in range");
+        let tms_tile = source
+            .get_tile(2, 2, y)
+            .await
+            .unwrap()
+            .expect("Tile should exist");
 
-        // Verify StyleInfo structure
-        assert!(!style_info.id.is_empty());
-        assert!(!style_info.name.is_empty());
-        assert!(style_info
-            .url
-            .as_ref()
-            .is_some_and(|u| u.contains("style.json")));
+        assert_eq!(xyz_tile.data, tms_tile.data);
     }
 
-    #[test]
-    fn test_style_json_valid() {
-        let config =
+    #[tokio::test]
+    async fn test_tms_source_flips_once() {
+        use tileserver_rs::config::SourceConfig;
+        use tileserver_rs::sources::TileScheme;
+
+        let mut config =
             Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
-        let styles = StyleManager::from_configs(&config.styles).expect("Should load styles");
+        let xyz_config = config
+            .sources
+            .iter()
+            .find(|s| s.id == "zurich")
+            .expect("Should have zurich config")
+            .clone();
+        let tms_config = SourceConfig {
+            id: "zurich-tms".to_string(),
+            scheme: TileScheme::Tms,
+            ..xyz_config
+        };
+        config.sources.push(tms_config);
 
-        // Get a specific style
-        let style = styles.get("protomaps-light");
-        assert!(style.is_some(), "Should have protomaps-light style");
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
+        let xyz = sources.get("zurich").expect("Should have zurich");
+        let tms = sources.get("zurich-tms").expect("Should have zurich-tms");
 
-        let style = style.unwrap();
-        let style_json = &style.style_json;
+        let tilejson = tms
+            .metadata()
+            .to_tilejson("http://localhost:8080", TileJsonVersion::default());
+        assert_eq!(tilejson.scheme, Some(TileScheme::Tms));
 
-        // Verify style JSON has required fields
-        assert!(style_json.get("version").is_some());
-        assert!(style_json.get("sources").is_some());
-        assert!(style_json.get("layers").is_some());
-    }
+        let expected = xyz.get_tile(2, 2, 1).await.unwrap().expect("Tile exists");
 
-    #[test]
-    fn test_style_not_found() {
-        let config =
-            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
-        let styles = StyleManager::from_configs(&config.styles).expect("Should load styles");
+        // Both the source and the request use TMS: the row is flipped exactly once
+        let y = tms
+            .metadata()
+            .request_row(2, 2, Some(TileScheme::Tms))
+            .expect("Row should be in range");
+        let actual = tms.get_tile(2, 2, y).await.unwrap().expect("Tile exists");
+        assert_eq!(expected.data, actual.data);
 
-        let style = styles.get("nonexistent-style");
-        assert!(style.is_none(), "Should not find nonexistent style");
+        // /data/zurich-tms/2/2/1.pbf?scheme=xyz
+        let y = tms
+            .metadata()
+            .request_row(2, 1, Some(TileScheme::Xyz))
+            .expect("Row should be in range");
+        let actual = tms.get_tile(2, 2, y).await.unwrap().expect("Tile exists");
+        assert_eq!(expected.data, actual.data);
     }
 
-    #[test]
-    fn test_style_info_to_tilejson_url() {
-        let config =
+    #[tokio::test]
+    async fn test_source_aliases() {
+        let mut config =
             Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
-        let styles = StyleManager::from_configs(&config.styles).expect("Should load styles");
+        let zurich = config
+            .sources
+            .iter_mut()
+            .find(|s| s.id == "zurich")
+            .expect("Should have zurich config");
+        zurich.aliases = vec!["zh".to_string(), "basemap".to_string()];
 
-        let style = styles.get("protomaps-light").expect("Should have style");
-        let style_info = style.to_info("http://localhost:8080");
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
 
-        // StyleInfo should have URL to style.json
-        assert!(style_info.url.is_some());
-        let url = style_info.url.unwrap();
-        assert!(url.contains("/styles/"));
-        assert!(url.contains("style.json"));
+        // The same source answers under its ID and every alias
+        let canonical = sources.get("zurich").expect("Should have zurich");
+        for alias in ["zh", "basemap"] {
+            let aliased = sources.get(alias).expect("Should resolve alias");
+            assert!(std::sync::Arc::ptr_eq(canonical, aliased));
+            assert_eq!(sources.canonical_id(alias), "zurich");
+
+            let expected = sources.get_tile("zurich", 2, 2, 1).await.unwrap().unwrap();
+            let actual = sources.get_tile(alias, 2, 2, 1).await.unwrap().unwrap();
+            assert_eq!(expected.data, actual.data);
+        }
+
+        // Each alias gets its own TileJSON, with tile URLs under the alias
+        let tilejsons: Vec<_> = sources
+            .all_metadata()
+            .iter()
+            .map(|m| m.to_tilejson("http://localhost:8080", TileJsonVersion::default()))
+            .collect();
+        assert_eq!(tilejsons.len(), config.sources.len() + 2);
+        let zh = tilejsons
+            .iter()
+            .find(|t| t.id == "zh")
+            .expect("Should list zh");
+        assert_eq!(
+            zh.tiles,
+            vec!["http://localhost:8080/data/zh/{z}/{x}/{y}.pbf"]
+        );
+        assert_eq!(sources.metadata("zh").unwrap().id, "zh");
+
+        // Aliases are not sources of their own
+        assert_eq!(sources.len(), config.sources.len());
+        assert!(sources.exists("basemap"));
+    }
+
+    #[tokio::test]
+    async fn test_conflicting_aliases_are_rejected() {
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+
+        // An alias naming another source, as in a cycle of two sources
+        let mut cycle = config.sources.clone();
+        cycle[0].aliases = vec![cycle[1].id.clone()];
+        cycle[1].aliases = vec![cycle[0].id.clone()];
+        let err = SourceManager::from_configs(&cycle).await.err().unwrap();
+        assert!(
+            err.to_string().contains("is already a source ID"),
+            "{}",
+            err
+        );
+
+        // The same alias on two sources
+        let mut shared = config.sources.clone();
+        shared[0].aliases = vec!["basemap".to_string()];
+        shared[1].aliases = vec!["basemap".to_string()];
+        let err = SourceManager::from_configs(&shared).await.err().unwrap();
+        assert!(err.to_string().contains("'basemap'"), "{}", err);
+    }
+
+    /// Zoom levels 0-6 of `zurich` in a PMTiles v2 archive, with leaf
+    /// directories at zoom 5
+    const PMTILES_V2_FIXTURE: &str = "tests/fixtures/pmtiles/zurich-v2.pmtiles";
+
+    #[tokio::test]
+    async fn test_pmtiles_v2_archive() {
+        use tileserver_rs::config::{SourceConfig, SourceType};
+        use tileserver_rs::inspect;
+        use tileserver_rs::sources::TileCompression;
+
+        let mut config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        let mbtiles_config = config
+            .sources
+            .iter()
+            .find(|s| s.id == "zurich")
+            .expect("Should have zurich config")
+            .clone();
+        config.sources.push(SourceConfig {
+            id: "zurich-v2".to_string(),
+            source_type: SourceType::PMTiles,
+            path: PMTILES_V2_FIXTURE.to_string(),
+            ..mbtiles_config
+        });
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
+        let mbtiles = sources.get("zurich").expect("Should have zurich");
+        let v2 = sources.get("zurich-v2").expect("Should have zurich-v2");
+        assert_eq!(v2.backend(), "pmtiles");
+        v2.health_check()
+            .await
+            .expect("v2 archive should be healthy");
+
+        // Metadata is built like that of v3 archives, named from the config
+        let metadata = v2.metadata();
+        let expected = mbtiles.metadata();
+        assert_eq!(metadata.name, "Zurich Switzerland");
+        assert_eq!(metadata.description, None);
+        assert_eq!(metadata.format, expected.format);
+        assert_eq!((metadata.minzoom, metadata.maxzoom), (0, 6));
+        assert_eq!(metadata.bounds, expected.bounds);
+        assert_eq!(metadata.center, expected.center);
+        assert_eq!(metadata.vector_layers, expected.vector_layers);
+
+        // Every tile, including those in leaf directories, matches the MBTiles
+        let mut count = 0;
+        for z in 0..=6 {
+            let stored = inspect::list(mbtiles.as_ref(), z).await.unwrap();
+            for (x, y) in stored.tiles {
+                let tile = v2
+                    .get_tile(z, x, y)
+                    .await
+                    .unwrap()
+                    .expect("Tile should exist");
+                let expected = mbtiles.get_tile(z, x, y).await.unwrap().unwrap();
+                assert_eq!(tile.data, expected.data, "tile {}/{}/{}", z, x, y);
+                assert_eq!(tile.compression, TileCompression::Gzip);
+                count += 1;
+            }
+        }
+        assert_eq!(count, 7);
+
+        assert!(v2.get_tile(6, 0, 0).await.unwrap().is_none());
+        assert!(v2.get_tile(7, 67, 45).await.unwrap().is_none());
+        assert!(v2.get_tile(2, 4, 0).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_overzoom_beyond_maxzoom() {
+        let mut config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        for source in &mut config.sources {
+            source.overzoom = source.id == "protomaps";
+        }
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
+        let source = sources.get("protomaps").expect("Should have protomaps");
+        let maxzoom = source.metadata().maxzoom;
+
+        // Florence, at the source's maxzoom
+        let (x, y) = (8704u32 << (maxzoom - 14), 5972u32 << (maxzoom - 14));
+        assert!(source.get_tile(maxzoom, x, y).await.unwrap().is_some());
+
+        for dz in 1..=3u8 {
+            let z = maxzoom + dz;
+            let half = 1u32 << (dz - 1);
+            let (cx, cy) = ((x << dz) + half, (y << dz) + half);
+
+            // Plain get_tile has nothing beyond maxzoom
+            assert!(source.get_tile(z, cx, cy).await.unwrap().is_none());
+
+            let tile = source
+                .get_tile_with_overzoom(z, cx, cy)
+                .await
+                .unwrap()
+                .unwrap_or_else(|| panic!("Should overzoom z{}", z));
+            let mvt = tile.decode_mvt().expect("Should decode overzoomed tile");
+            assert!(!mvt.layers.is_empty());
+
+            for layer in &mvt.layers {
+                let extent = layer.extent.unwrap_or(4096) as i64;
+                let buffer = extent / 64;
+                for feature in &layer.features {
+                    // The first MoveTo lands inside the buffered tile
+                    let (x0, y0) = (feature.geometry[1], feature.geometry[2]);
+                    let unzigzag = |v: u32| ((v >> 1) as i64) ^ -((v & 1) as i64);
+                    for v in [unzigzag(x0), unzigzag(y0)] {
+                        assert!(
+                            (-buffer..=extent + buffer).contains(&v),
+                            "z{} coordinate {} outside the tile",
+                            z,
+                            v
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_overzoom_with_buffer() {
+        let mut config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        for source in &mut config.sources {
+            source.overzoom = source.id == "protomaps";
+        }
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
+        let maxzoom = sources.get("protomaps").unwrap().metadata().maxzoom;
+        let z = maxzoom + 1;
+        let (x, y) = ((8704u32 << (z - 14)) + 1, (5972u32 << (z - 14)) + 1);
+
+        // First MoveTo of each feature
+        let starts = |tile: tileserver_rs::sources::TileData| -> Vec<(i64, i64, i64)> {
+            let unzigzag = |v: u32| ((v >> 1) as i64) ^ -((v & 1) as i64);
+            let mvt = tile.decode_mvt().expect("Should decode tile");
+            mvt.layers
+                .iter()
+                .flat_map(|layer| {
+                    let extent = layer.extent.unwrap_or(4096) as i64;
+                    layer
+                        .features
+                        .iter()
+                        .map(move |f| (extent, unzigzag(f.geometry[1]), unzigzag(f.geometry[2])))
+                })
+                .collect()
+        };
+
+        let buffered = sources
+            .get_tile_with_buffer("protomaps", z, x, y, Some(128))
+            .await
+            .unwrap()
+            .expect("Should overzoom");
+        let buffered = starts(buffered);
+        // 128 of 512 pixels is a quarter of the extent
+        assert!(buffered
+            .iter()
+            .all(|&(e, x, y)| [x, y].iter().all(|v| (-e / 4..=e + e / 4).contains(v))));
+        assert!(
+            buffered
+                .iter()
+                .any(|&(e, x, y)| [x, y].iter().any(|&v| v < -e / 64 || v > e + e / 64)),
+            "Some features should start beyond the default margin"
+        );
+
+        // Stored tiles have their buffer baked in
+        let stored = sources.get_tile("protomaps", maxzoom, x >> 1, y >> 1).await;
+        let with_buffer = sources
+            .get_tile_with_buffer("protomaps", maxzoom, x >> 1, y >> 1, Some(128))
+            .await;
+        assert_eq!(
+            stored.unwrap().unwrap().data,
+            with_buffer.unwrap().unwrap().data
+        );
+    }
+
+    #[tokio::test]
+    async fn test_overzoom_disabled_by_default() {
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
+        let source = sources.get("protomaps").expect("Should have protomaps");
+        let z = source.metadata().maxzoom + 1;
+
+        let tile = source
+            .get_tile_with_overzoom(z, 8704 << (z - 14), 5972 << (z - 14))
+            .await
+            .unwrap();
+        assert!(tile.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_signed_tile_url_selects_tile_in_archive() {
+        use std::time::{Duration, UNIX_EPOCH};
+        use tileserver_rs::config::SourceConfig;
+        use tileserver_rs::sources::gcs::{BlobSigner, GcsObject};
+        use tileserver_rs::sources::pmtiles::http::HttpPmTilesSource;
+        use tileserver_rs::{TileServerError, TileSource};
+
+        struct MockSigner;
+
+        impl BlobSigner for MockSigner {
+            fn email(&self) -> &str {
+                "tiles@project.iam.gserviceaccount.com"
+            }
+
+            fn sign(&self, _data: &[u8]) -> tileserver_rs::Result<Vec<u8>> {
+                Ok(vec![0xab, 0xcd])
+            }
+        }
+
+        // Serve the sample archive with range support, standing in for GCS
+        let archive = "data/tiles/protomaps-sample.pmtiles";
+        let app = axum::Router::new().route_service(
+            "/tiles/sample.pmtiles",
+            tower_http::services::ServeFile::new(archive),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let config: SourceConfig = serde_json::from_value(serde_json::json!({
+            "id": "remote",
+            "type": "pmtiles",
+            "path": format!("http://{}/tiles/sample.pmtiles", addr),
+        }))
+        .unwrap();
+        let source = HttpPmTilesSource::from_url(&config, reqwest::Client::new())
+            .await
+            .expect("Should open remote archive");
+        let now = UNIX_EPOCH + Duration::from_secs(1_709_210_096);
+        let ttl = Duration::from_secs(60);
+
+        // Signing is off unless configured
+        let result = source.signed_tile_url(14, 8704, 5972, ttl, now).await;
+        assert!(matches!(result, Err(TileServerError::NotFound(_))));
+
+        let source = source.with_url_signer(
+            GcsObject::from_url("gs://tiles/sample.pmtiles").unwrap(),
+            Box::new(MockSigner),
+        );
+        let signed = source
+            .signed_tile_url(14, 8704, 5972, ttl, now)
+            .await
+            .unwrap();
+        assert!(signed
+            .url
+            .starts_with("https://storage.googleapis.com/tiles/sample.pmtiles?"));
+        assert!(signed.url.ends_with("&X-Goog-Signature=abcd"));
+        assert_eq!(signed.expires_at, "2024-02-29T12:35:56Z");
+
+        // The range holds exactly the tile
+        let (start, end) = signed
+            .range
+            .strip_prefix("bytes=")
+            .and_then(|range| range.split_once('-'))
+            .unwrap();
+        let (start, end): (usize, usize) = (start.parse().unwrap(), end.parse().unwrap());
+        let file = std::fs::read(archive).unwrap();
+        let tile = source.get_tile(14, 8704, 5972).await.unwrap().unwrap();
+        assert_eq!(&file[start..=end], &tile.data[..]);
+
+        let result = source.signed_tile_url(14, 0, 0, ttl, now).await;
+        assert!(matches!(result, Err(TileServerError::TileNotFound { .. })));
+        let result = source
+            .signed_tile_url(14, 8704, 5972, Duration::from_secs(8 * 86400), now)
+            .await;
+        assert!(matches!(result, Err(TileServerError::BadRequest(_))));
     }
 }
 
 // ============================================================
-// Font Endpoint Tests
+// Inspect Endpoint Tests
 // ============================================================
 
-mod font_tests {
+mod inspect_tests {
     use super::*;
-    use std::fs;
-    use tileserver_rs::Config;
+    use futures::StreamExt;
+    use std::collections::HashMap;
+    use tileserver_rs::inspect;
+    use tileserver_rs::{Config, SourceManager};
 
-    #[test]
-    fn test_fonts_directory_exists() {
+    async fn load() -> SourceManager {
         let config =
             Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources")
+    }
 
-        if let Some(ref fonts_path) = config.fonts {
-            assert!(
-                fonts_path.exists(),
-                "Fonts directory should exist: {:?}",
-                fonts_path
+    /// Collect the NDJSON lines into a map of `x_y` to stats
+    async fn inspect(
+        sources: &SourceManager,
+        id: &str,
+        z: u8,
+        layers: &[&str],
+    ) -> HashMap<String, serde_json::Value> {
+        let source = sources.get(id).expect("Should have source").clone();
+        let inspection = inspect::list(source.as_ref(), z).await.unwrap();
+        let layers = layers.iter().map(|l| l.to_string()).collect();
+
+        let lines: Vec<_> = inspect::stream(source, inspection, layers).collect().await;
+        let mut tiles = HashMap::new();
+        for line in lines {
+            assert_eq!(line.last(), Some(&b'\n'));
+            let entry: HashMap<String, serde_json::Value> = serde_json::from_slice(&line).unwrap();
+            assert_eq!(entry.len(), 1);
+            tiles.extend(entry);
+        }
+        tiles
+    }
+
+    #[tokio::test]
+    async fn test_query_tile_across_sources() {
+        let sources = load().await;
+
+        let query = inspect::query::query_tile(&sources, 0, 0, 0, &[])
+            .await
+            .unwrap();
+        assert_eq!(
+            query.sources.keys().collect::<Vec<_>>(),
+            ["protomaps", "zurich"]
+        );
+        for (id, tile) in &query.sources {
+            let stored = sources.get_tile(id, 0, 0, 0).await.unwrap().unwrap();
+            assert_eq!(tile.bytes, stored.data.len());
+
+            let decoded = stored.decode_mvt().unwrap();
+            let layers = tile.layers.as_ref().expect("Should decode vector tiles");
+            assert_eq!(layers.len(), decoded.layers.len());
+            for layer in &decoded.layers {
+                let sample = &layers[&layer.name];
+                assert_eq!(sample.features, layer.features.len());
+                assert_eq!(
+                    sample.sample.len(),
+                    layer.features.len().min(inspect::query::SAMPLE_FEATURES)
+                );
+            }
+        }
+
+        let json = serde_json::to_value(&query).unwrap();
+        let water = &json["sources"]["protomaps"]["layers"]["water"];
+        assert!(water["features"].as_u64().unwrap() > 0);
+        assert_eq!(water["sample"][0]["type"], "Feature");
+    }
+
+    #[tokio::test]
+    async fn test_query_tile_source_filter() {
+        let sources = load().await;
+
+        let query = inspect::query::query_tile(&sources, 0, 0, 0, &["zurich".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(query.sources.keys().collect::<Vec<_>>(), ["zurich"]);
+
+        let err = inspect::query::query_tile(&sources, 0, 0, 0, &["missing".to_string()])
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            tileserver_rs::TileServerError::SourceNotFound(_)
+        ));
+
+        // No source has tiles far outside its bounds at its max zoom
+        let query = inspect::query::query_tile(&sources, 14, 0, 0, &[])
+            .await
+            .unwrap();
+        assert!(query.sources.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_inspect_mbtiles_lists_stored_tiles() {
+        let sources = load().await;
+        let source = sources.get("zurich").expect("Should have zurich");
+        let z = source.metadata().minzoom;
+
+        let tiles = inspect(&sources, "zurich", z, &[]).await;
+        assert!(!tiles.is_empty());
+
+        for (key, stats) in &tiles {
+            let (x, y) = key.split_once('_').unwrap();
+            let tile = source
+                .get_tile(z, x.parse().unwrap(), y.parse().unwrap())
+                .await
+                .unwrap()
+                .expect("Listed tiles should exist");
+            assert_eq!(stats["bytes"], tile.data.len());
+            assert!(stats["feature_count"].as_u64().unwrap() > 0);
+            assert!(!stats["layers"].as_array().unwrap().is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_inspect_pmtiles_matches_tiles() {
+        let sources = load().await;
+        let source = sources.get("protomaps").expect("Should have protomaps");
+        let z = source.metadata().minzoom;
+
+        let tiles = inspect(&sources, "protomaps", z, &[]).await;
+        assert!(!tiles.is_empty());
+
+        let (key, stats) = tiles.iter().next().unwrap();
+        let (x, y) = key.split_once('_').unwrap();
+        let tile = source
+            .get_tile(z, x.parse().unwrap(), y.parse().unwrap())
+            .await
+            .unwrap()
+            .expect("Listed tiles should exist");
+        let expected = inspect::tile_stats(&tile, &[]).unwrap();
+        assert_eq!(*stats, serde_json::to_value(expected).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_inspect_layer_filter() {
+        let sources = load().await;
+        let z = sources.get("zurich").unwrap().metadata().minzoom;
+
+        let all = inspect(&sources, "zurich", z, &[]).await;
+        let (_, stats) = all.iter().next().unwrap();
+        let layer = stats["layers"][0]["name"].as_str().unwrap().to_string();
+
+        let filtered = inspect(&sources, "zurich", z, &[&layer]).await;
+        assert!(!filtered.is_empty());
+        for stats in filtered.values() {
+            let layers = stats["layers"].as_array().unwrap();
+            assert_eq!(layers.len(), 1);
+            assert_eq!(layers[0]["name"], layer.as_str());
+            assert_eq!(stats["feature_count"], layers[0]["feature_count"]);
+        }
+
+        assert!(inspect(&sources, "zurich", z, &["no-such-layer"])
+            .await
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_inspect_outside_zoom_range() {
+        let sources = load().await;
+        let source = sources.get("zurich").unwrap();
+        let z = source.metadata().maxzoom + 1;
+
+        let inspection = inspect::list(source.as_ref(), z).await.unwrap();
+        assert!(inspection.tiles.is_empty());
+        assert!(!inspection.truncated);
+        assert!(inspect::list(source.as_ref(), 31).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tile_report_matches_fixture_tiles() {
+        use inspect::tile::{report, Checks};
+
+        let sources = load().await;
+        let zurich = sources.get("zurich").unwrap();
+        let z = zurich.metadata().minzoom;
+        let (x, y) = inspect::list(zurich.as_ref(), z).await.unwrap().tiles[0];
+        let protomaps = sources.get("protomaps").unwrap();
+
+        for (source, (z, x, y)) in [(zurich, (z, x, y)), (protomaps, (14, 8704, 5972))] {
+            let tile = source.get_tile(z, x, y).await.unwrap().unwrap();
+            let decoded = tile.decode_mvt().unwrap();
+
+            let report = report(&tile, Checks::default());
+            assert!(report.valid, "{:?}", report.errors);
+            assert_eq!(report.bytes, tile.data.len());
+            assert_eq!(
+                report.decompressed_bytes,
+                Some(tile.decompress().unwrap().len())
             );
-            assert!(fonts_path.is_dir(), "Fonts path should be a directory");
+            assert_eq!(report.layers.len(), decoded.layers.len());
+            for (layer, decoded) in report.layers.iter().zip(&decoded.layers) {
+                assert_eq!(layer.name, decoded.name);
+                assert_eq!(layer.feature_count, decoded.features.len());
+                let types = &layer.geometry_types;
+                assert_eq!(
+                    types.point + types.linestring + types.polygon + types.unknown,
+                    layer.feature_count
+                );
+            }
+            assert_eq!(
+                report.feature_count,
+                decoded
+                    .layers
+                    .iter()
+                    .map(|l| l.features.len())
+                    .sum::<usize>()
+            );
+
+            let json = serde_json::to_value(&report).unwrap();
+            assert_eq!(json["format"], "pbf");
+            assert!(json["layers"][0]["keys"].is_object());
         }
     }
 
+    #[tokio::test]
+    async fn test_truncated_tile_yields_error_report() {
+        use bytes::Bytes;
+        use inspect::tile::{report, Checks};
+        use tileserver_rs::sources::TileCompression;
+
+        let sources = load().await;
+        let tile = sources
+            .get("protomaps")
+            .unwrap()
+            .get_tile(14, 8704, 5972)
+            .await
+            .unwrap()
+            .unwrap();
+        let raw = tile.decompress().unwrap();
+
+        let mut truncated = tile.clone();
+        truncated.data = Bytes::copy_from_slice(&raw[..raw.len() / 2]);
+        truncated.compression = TileCompression::None;
+        let report = report(
+            &truncated,
+            Checks {
+                strict: true,
+                buffer: None,
+            },
+        );
+        assert!(!report.valid);
+        assert_eq!(report.decompressed_bytes, Some(raw.len() / 2));
+        assert!(report.layers.is_empty());
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["error_count"], 1);
+        let error = &json["errors"][0];
+        assert!(error["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("Failed to decode MVT tile"));
+        assert!(error.get("layer").is_none());
+
+        // Broken compression is reported the same way
+        let mut corrupt = tile.clone();
+        corrupt.data = Bytes::from_static(b"\x1f\x8b\x08\x00garbage");
+        corrupt.compression = TileCompression::Gzip;
+        let report = inspect::tile::report(&corrupt, Checks::default());
+        assert!(!report.valid);
+        assert_eq!(report.compression, "gzip");
+        assert_eq!(report.decompressed_bytes, None);
+    }
+
     #[test]
-    fn test_fonts_list() {
-        let config =
-            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+    fn test_debug_endpoints_config() {
+        let config: Config = toml::from_str("[server]\ndebug_endpoints = true").unwrap();
+        assert!(config.server.debug_endpoints);
+        let config: Config = toml::from_str("[server]").unwrap();
+        assert!(!config.server.debug_endpoints);
+    }
+}
 
-        if let Some(ref fonts_path) = config.fonts {
-            let entries: Vec<_> = fs::read_dir(fonts_path)
-                .expect("Should read fonts dir")
-                .filter_map(|e| e.ok())
-                .filter(|e| e.path().is_dir())
-                .collect();
+// ============================================================
+// Quality Analysis Tests
+// ============================================================
 
-            assert!(!entries.is_empty(), "Should have at least one font family");
+mod quality_tests {
+    use super::*;
+    use geozero::mvt::tile::{Feature, GeomType, Layer, Value};
+    use geozero::mvt::{Message, Tile};
+    use tileserver_rs::config::SourceConfig;
+    use tileserver_rs::quality::{self, IssueKind, QualityQuery};
+    use tileserver_rs::{Config, SourceManager, TileServerError};
+
+    fn query(z: u8, sample: usize) -> QualityQuery {
+        QualityQuery {
+            z,
+            sample,
+            seed: Some(42),
         }
     }
 
-    #[test]
-    fn test_font_pbf_files_format() {
+    /// Command integer followed by zigzag-encoded deltas
+    fn command(id: u32, count: u32, deltas: &[i32]) -> Vec<u32> {
+        let mut encoded = vec![(count << 3) | id];
+        encoded.extend(deltas.iter().map(|&d| ((d << 1) ^ (d >> 31)) as u32));
+        encoded
+    }
+
+    fn feature(geom_type: GeomType, geometry: Vec<u32>) -> Feature {
+        Feature {
+            id: None,
+            tags: vec![],
+            r#type: Some(geom_type as i32),
+            geometry,
+        }
+    }
+
+    fn encode(features: Vec<Feature>, values: Vec<Value>) -> Vec<u8> {
+        Tile {
+            layers: vec![Layer {
+                version: 2,
+                name: "broken".to_string(),
+                features,
+                keys: vec![],
+                values,
+                extent: Some(4096),
+            }],
+        }
+        .encode_to_vec()
+    }
+
+    /// MBTiles with one tile per kind of problem at zoom 2, in TMS rows 0 and 1
+    fn broken_mbtiles(path: &std::path::Path) {
+        let point = || feature(GeomType::Point, command(1, 1, &[10, 10]));
+        let mut bowtie = command(1, 1, &[0, 0]);
+        bowtie.extend(command(2, 3, &[100, 100, 0, -100, -100, 100]));
+        bowtie.extend(command(7, 1, &[]));
+        let padding = Value {
+            string_value: Some("x".repeat(1_100_000)),
+            ..Default::default()
+        };
+
+        let tiles = [
+            encode(vec![], vec![]),
+            encode(vec![point()], vec![padding]),
+            encode(vec![point(), feature(GeomType::Polygon, bowtie)], vec![]),
+            encode(vec![point(), feature(GeomType::Point, vec![])], vec![]),
+            vec![0x1a, 0xff, 0xff],
+        ];
+
+        let conn = rusqlite::Connection::open(path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE metadata (name TEXT, value TEXT);
+             CREATE TABLE tiles (zoom_level INTEGER, tile_column INTEGER,
+                                 tile_row INTEGER, tile_data BLOB);
+             INSERT INTO metadata VALUES ('name', 'broken'), ('format', 'pbf'),
+                                         ('minzoom', '2'), ('maxzoom', '2');",
+        )
+        .unwrap();
+        for (i, data) in tiles.iter().enumerate() {
+            conn.execute(
+                "INSERT INTO tiles VALUES (2, ?1, ?2, ?3)",
+                rusqlite::params![i as u32 % 4, i as u32 / 4, data],
+            )
+            .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_quality_known_good_sources() {
         let config =
             Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
 
-        if let Some(ref fonts_path) = config.fonts {
-            // Check first font family
-            if let Some(font_dir) = fs::read_dir(fonts_path)
-                .ok()
-                .and_then(|mut r| r.next())
-                .and_then(|e| e.ok())
-            {
-                let font_path = font_dir.path();
-                if font_path.is_dir() {
-                    // Should have PBF files with range names like "0-255.pbf"
-                    let pbf_files: Vec<_> = fs::read_dir(&font_path)
-                        .expect("Should read font dir")
-                        .filter_map(|e| e.ok())
-                        .filter(|e| e.path().extension().is_some_and(|ext| ext == "pbf"))
-                        .collect();
+        // A clustered PMTiles archive and an MBTiles file
+        for id in ["protomaps", "zurich"] {
+            let source = sources.get(id).unwrap();
+            let report = quality::analyze(id, source.as_ref(), &query(14, 50))
+                .await
+                .unwrap();
+            assert!(report.sampled_tiles > 0, "{} has tiles at zoom 14", id);
+            assert_eq!(report.sampled_tiles, report.candidates.min(50));
+            assert_eq!(report.quality_score, 100, "{:?}", report.issues);
+            assert!(report.issues.is_empty());
+            let histogram: usize = report.size_histogram.iter().map(|b| b.tiles).sum();
+            assert_eq!(histogram, report.sampled_tiles);
+        }
 
-                    assert!(!pbf_files.is_empty(), "Font should have PBF files");
+        // The same seed draws the same sample
+        let zurich = sources.get("zurich").unwrap();
+        let first = quality::analyze("zurich", zurich.as_ref(), &query(14, 5))
+            .await
+            .unwrap();
+        let second = quality::analyze("zurich", zurich.as_ref(), &query(14, 5))
+            .await
+            .unwrap();
+        assert_eq!(first, second);
+    }
 
-                    // Verify range format
-                    for pbf in pbf_files {
-                        let name = pbf.file_name();
-                        let name_str = name.to_string_lossy();
-                        // Should match pattern like "0-255.pbf"
-                        assert!(
-                            name_str.contains('-') && name_str.ends_with(".pbf"),
-                            "PBF file should have range format: {}",
-                            name_str
-                        );
-                    }
-                }
-            }
+    #[tokio::test]
+    async fn test_quality_reports_each_issue() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("broken.mbtiles");
+        broken_mbtiles(&path);
+        let config: SourceConfig = toml::from_str(&format!(
+            "id = \"broken\"\ntype = \"mbtiles\"\npath = \"{}\"",
+            path.display()
+        ))
+        .unwrap();
+        let sources = SourceManager::from_configs(&[config])
+            .await
+            .expect("Should load sources");
+        let source = sources.get("broken").unwrap();
+
+        let report = quality::analyze("broken", source.as_ref(), &query(2, 10))
+            .await
+            .unwrap();
+        assert_eq!(report.candidates, 5);
+        assert_eq!(report.sampled_tiles, 5);
+        assert_eq!(report.quality_score, 0);
+        assert_eq!(report.counts.empty_tiles, 1);
+        assert_eq!(report.counts.oversized_tiles, 1);
+        assert_eq!(report.counts.invalid_geometries, 1);
+        assert_eq!(report.counts.null_geometries, 1);
+        assert_eq!(report.counts.decode_errors, 1);
+        assert_eq!(report.issue_count, 5);
+
+        let mut kinds: Vec<_> = report.issues.iter().map(|issue| issue.kind).collect();
+        kinds.sort_by_key(|kind| format!("{:?}", kind));
+        assert_eq!(
+            kinds,
+            [
+                IssueKind::DecodeError,
+                IssueKind::EmptyTile,
+                IssueKind::InvalidGeometry,
+                IssueKind::NullGeometry,
+                IssueKind::OversizedTile,
+            ]
+        );
+        let invalid = report
+            .issues
+            .iter()
+            .find(|issue| issue.kind == IssueKind::InvalidGeometry)
+            .unwrap();
+        assert_eq!(invalid.layer.as_deref(), Some("broken"));
+        assert_eq!(invalid.tile, "2/2/3");
+
+        let oversized = report.size_histogram.last().unwrap();
+        assert_eq!((oversized.max_bytes, oversized.tiles), (None, 1));
+
+        let json = serde_json::to_value(&report).unwrap();
+        let empty = json["issues"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|issue| issue["kind"] == "empty_tile")
+            .expect("Should report the empty tile");
+        assert_eq!(empty["tile"], "2/0/3");
+
+        let err = quality::analyze("broken", source.as_ref(), &query(2, 0))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, TileServerError::BadRequest(_)));
+    }
+}
+
+// ============================================================
+// Style Endpoint Tests
+// ============================================================
+
+mod diff_tests {
+    use super::*;
+    use tileserver_rs::error::TileServerError;
+    use tileserver_rs::{diff, inspect};
+    use tileserver_rs::{Config, SourceManager};
+
+    async fn load() -> SourceManager {
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources")
+    }
+
+    /// A stored tile of the zurich source
+    async fn zurich_tile(sources: &SourceManager) -> (u8, u32, u32) {
+        let source = sources.get("zurich").expect("Should have zurich");
+        let z = source.metadata().minzoom;
+        let inspection = inspect::list(source.as_ref(), z).await.unwrap();
+        let (x, y) = inspection.tiles[0];
+        (z, x, y)
+    }
+
+    #[tokio::test]
+    async fn test_diff_same_source_is_identical() {
+        let sources = load().await;
+        let (z, x, y) = zurich_tile(&sources).await;
+
+        let comparison = diff::diff_sources(&sources, "zurich", "zurich", z, x, y)
+            .await
+            .unwrap();
+        let summary = comparison.summary();
+        assert!(summary.identical);
+        assert!(!summary.layers.is_empty());
+        for layer in &summary.layers {
+            assert_eq!((layer.added, layer.removed), (0, 0));
+            assert!(layer.changed.is_empty());
+            assert!(layer.unchanged > 0);
+        }
+
+        let geojson = comparison.to_geojson();
+        let features = geojson["features"].as_array().unwrap();
+        let unchanged: usize = summary.layers.iter().map(|layer| layer.unchanged).sum();
+        assert_eq!(features.len(), unchanged);
+        assert!(features
+            .iter()
+            .all(|f| f["properties"]["_diff_action"] == "unchanged"));
+    }
+
+    #[tokio::test]
+    async fn test_diff_different_sources() {
+        let sources = load().await;
+        let (z, x, y) = zurich_tile(&sources).await;
+
+        let summary = diff::diff_sources(&sources, "zurich", "protomaps", z, x, y)
+            .await
+            .unwrap()
+            .summary();
+        assert!(!summary.identical);
+
+        // Layers of only one source count entirely as removed or added
+        for layer in &summary.layers {
+            if summary.layers_only_in_a.contains(&layer.name) {
+                assert_eq!((layer.added, layer.unchanged), (0, 0));
+                assert!(layer.removed > 0);
+            }
+            if summary.layers_only_in_b.contains(&layer.name) {
+                assert_eq!((layer.removed, layer.unchanged), (0, 0));
+                assert!(layer.added > 0);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_diff_unknown_source() {
+        let sources = load().await;
+        let result = diff::diff_sources(&sources, "zurich", "nope", 0, 0, 0).await;
+        assert!(matches!(result, Err(TileServerError::SourceNotFound(id)) if id == "nope"));
+    }
+
+    #[tokio::test]
+    async fn test_diff_invalid_coordinates() {
+        let sources = load().await;
+        let result = diff::diff_sources(&sources, "zurich", "protomaps", 2, 4, 0).await;
+        assert!(matches!(
+            result,
+            Err(TileServerError::InvalidCoordinates { .. })
+        ));
+    }
+}
+
+mod style_tests {
+    use super::*;
+    use tileserver_rs::{Config, SourceManager, StyleManager};
+
+    const QGIS_STYLE: &str = "tests/fixtures/styles/qgis/style.json";
+
+    fn qgis_config(sources: &str) -> Config {
+        toml::from_str(&format!(
+            r#"
+            {}
+
+            [[styles]]
+            id = "qgis"
+            path = "{}"
+            "#,
+            sources, QGIS_STYLE
+        ))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_style_mbtiles_urls_register_sources() {
+        let mut config = qgis_config("");
+        config.register_style_mbtiles();
+
+        // The missing overlay.mbtiles is not registered
+        assert_eq!(config.sources.len(), 1);
+        let source = &config.sources[0];
+        assert_eq!(source.id, "mbtiles_zurich_switzerland");
+        assert!(source
+            .path
+            .ends_with("data/tiles/zurich_switzerland.mbtiles"));
+
+        let styles = StyleManager::from_configs(&config.styles).expect("Should load styles");
+        let style = styles.get("qgis").unwrap();
+        assert_eq!(
+            style.style_json["sources"]["zurich"]["url"],
+            "/data/mbtiles_zurich_switzerland.json"
+        );
+        assert_eq!(
+            style.style_json["sources"]["overlay"]["url"],
+            "/data/mbtiles_overlay.json"
+        );
+
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
+        assert!(sources.exists("mbtiles_zurich_switzerland"));
+
+        // Registering again changes nothing
+        config.register_style_mbtiles();
+        assert_eq!(config.sources.len(), 1);
+    }
+
+    #[test]
+    fn test_style_mbtiles_url_aliases_existing_source() {
+        let mut config = qgis_config(
+            r#"
+            [[sources]]
+            id = "zurich"
+            type = "mbtiles"
+            path = "data/tiles/zurich_switzerland.mbtiles"
+            "#,
+        );
+        config.register_style_mbtiles();
+
+        assert_eq!(config.sources.len(), 1);
+        assert_eq!(config.sources[0].id, "zurich");
+        assert_eq!(config.sources[0].aliases, ["mbtiles_zurich_switzerland"]);
+    }
+
+    #[test]
+    fn test_styles_json_returns_all_styles() {
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        let styles = StyleManager::from_configs(&config.styles).expect("Should load styles");
+
+        let all_infos = styles.all_infos("http://localhost:8080");
+
+        // Should have styles from our test config
+        assert!(!all_infos.is_empty(), "Should have at least one style");
+    }
+
+    #[test]
+    fn test_style_info_structure() {
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        let styles = StyleManager::from_configs(&config.styles).expect("Should load styles");
+
+        let all_infos = styles.all_infos("http://localhost:8080");
+        let style_info = &all_infos[0];
+
+        // Verify StyleInfo structure
+        assert!(!style_info.id.is_empty());
+        assert!(!style_info.name.is_empty());
+        assert!(style_info
+            .url
+            .as_ref()
+            .is_some_and(|u| u.contains("style.json")));
+    }
+
+    #[test]
+    fn test_style_json_valid() {
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        let styles = StyleManager::from_configs(&config.styles).expect("Should load styles");
+
+        // Get a specific style
+        let style = styles.get("protomaps-light");
+        assert!(style.is_some(), "Should have protomaps-light style");
+
+        let style = style.unwrap();
+        let style_json = &style.style_json;
+
+        // Verify style JSON has required fields
+        assert!(style_json.get("version").is_some());
+        assert!(style_json.get("sources").is_some());
+        assert!(style_json.get("layers").is_some());
+    }
+
+    #[test]
+    fn test_style_not_found() {
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        let styles = StyleManager::from_configs(&config.styles).expect("Should load styles");
+
+        let style = styles.get("nonexistent-style");
+        assert!(style.is_none(), "Should not find nonexistent style");
+    }
+
+    #[test]
+    fn test_style_info_to_tilejson_url() {
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        let styles = StyleManager::from_configs(&config.styles).expect("Should load styles");
+
+        let style = styles.get("protomaps-light").expect("Should have style");
+        let style_info = style.to_info("http://localhost:8080");
+
+        // StyleInfo should have URL to style.json
+        assert!(style_info.url.is_some());
+        let url = style_info.url.unwrap();
+        assert!(url.contains("/styles/"));
+        assert!(url.contains("style.json"));
+    }
+
+    #[test]
+    fn test_style_info_thumbnail_url() {
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        let styles = StyleManager::from_configs(&config.styles).expect("Should load styles");
+        let style = styles.get("protomaps-light").expect("Should have style");
+
+        let info = style.to_info_with_key("http://localhost:8080", Some("abc"));
+        assert_eq!(
+            info.thumbnail.as_deref(),
+            Some("http://localhost:8080/styles/protomaps-light/thumbnail.png?key=abc")
+        );
+    }
+
+    #[test]
+    fn test_thumbnail_placeholder_png() {
+        use tileserver_rs::thumbnail::{self, ThumbnailQuery};
+
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        let glyphs = std::fs::read("data/fonts/Noto Sans Regular/0-255.pbf").unwrap();
+        let query: ThumbnailQuery = serde_json::from_value(serde_json::json!({})).unwrap();
+        let (width, height) = query.size(config.thumbnail.max_size).unwrap();
+        assert_eq!((width, height), (300, 200));
+
+        let png = thumbnail::placeholder(width, height, "Protomaps Light", Some(&glyphs)).unwrap();
+        assert_eq!(image::guess_format(&png).unwrap(), image::ImageFormat::Png);
+        let image = image::load_from_memory(&png).unwrap().to_rgba8();
+        assert_eq!(image.dimensions(), (300, 200));
+        // Gray corners, with the label drawn darker in the middle
+        let corner = *image.get_pixel(0, 0);
+        assert!(image.pixels().any(|pixel| pixel[0] < corner[0]));
+
+        // Requested sizes, and a label too wide to fit at full size
+        let png = thumbnail::placeholder(512, 64, &"Wide ".repeat(20), Some(&glyphs)).unwrap();
+        let image = image::load_from_memory(&png).unwrap();
+        assert_eq!((image.width(), image.height()), (512, 64));
+
+        // No glyphs: just the background
+        let png = thumbnail::placeholder(40, 30, "Plain", None).unwrap();
+        let image = image::load_from_memory(&png).unwrap().to_rgba8();
+        assert_eq!(image.dimensions(), (40, 30));
+        assert!(image.pixels().all(|pixel| *pixel == corner));
+
+        let config: Config = toml::from_str("[thumbnail]\nmax_size = 256").unwrap();
+        let too_big = ThumbnailQuery {
+            width: Some(300),
+            height: None,
+        };
+        let err = too_big.size(config.thumbnail.max_size).unwrap_err();
+        assert_eq!(err.status(), axum::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_test_styles_have_no_errors() {
+        use tileserver_rs::styles::validate::Severity;
+
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        let styles = StyleManager::from_configs(&config.styles).expect("Should load styles");
+
+        let diagnostics = styles.diagnostics("protomaps-light");
+        assert!(
+            diagnostics.iter().all(|d| d.severity != Severity::Error),
+            "Unexpected errors: {:?}",
+            diagnostics
+        );
+    }
+
+    #[test]
+    fn test_inline_style() {
+        use tileserver_rs::styles::rewrite_style_for_api;
+        use tileserver_rs::UrlQueryParams;
+
+        let config: Config = toml::from_str(
+            r#"
+            [[styles]]
+            id = "overlay"
+            name = "Overlay"
+
+            [styles.inline]
+            version = 8
+            glyphs = "/fonts/{fontstack}/{range}.pbf"
+
+            [styles.inline.sources.zurich]
+            type = "vector"
+            url = "/data/zurich.json"
+
+            [[styles.inline.layers]]
+            id = "background"
+            type = "background"
+            "#,
+        )
+        .expect("Should parse config");
+        let styles = StyleManager::from_configs(&config.styles).expect("Should load styles");
+
+        let style = styles.get("overlay").expect("Should load inline style");
+        let info = style.to_info("http://localhost:8080");
+        assert_eq!(info.name, "Overlay");
+        assert_eq!(
+            info.url.as_deref(),
+            Some("http://localhost:8080/styles/overlay/style.json")
+        );
+
+        let rewritten = rewrite_style_for_api(
+            &style.style_json,
+            "http://localhost:8080",
+            &UrlQueryParams::with_key(Some("abc".to_string())),
+        );
+        assert_eq!(
+            rewritten["sources"]["zurich"]["url"],
+            "http://localhost:8080/data/zurich.json?key=abc"
+        );
+        assert_eq!(
+            rewritten["glyphs"],
+            "http://localhost:8080/fonts/{fontstack}/{range}.pbf?key=abc"
+        );
+        assert_eq!(rewritten["layers"][0]["id"], "background");
+    }
+
+    #[test]
+    fn test_strict_styles_rejects_broken_style() {
+        use tileserver_rs::config::StyleConfig;
+        use tileserver_rs::styles::validate::StyleChecks;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("style.json");
+        std::fs::write(
+            &path,
+            r#"{"version": 8, "sources": {}, "layers": [{"id": "roads", "type": "line", "source": "osm"}]}"#,
+        )
+        .unwrap();
+        let configs = [StyleConfig {
+            id: "broken".to_string(),
+            path,
+            inline: None,
+            name: None,
+            variants: Vec::new(),
+            minzoom: None,
+            maxzoom: None,
+            bounds: None,
+            blank_outside: false,
+        }];
+
+        let lenient = StyleManager::from_configs(&configs).expect("Should load styles");
+        assert!(lenient.get("broken").is_some());
+        let diagnostics = lenient.diagnostics("broken");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].layer.as_deref(), Some("roads"));
+        assert_eq!(
+            diagnostics[0].message,
+            "Source 'osm' is not defined in the style"
+        );
+
+        let checks = StyleChecks {
+            fonts: None,
+            strict: true,
+        };
+        let strict =
+            StyleManager::from_configs_with_checks(&configs, checks).expect("Should load styles");
+        assert!(strict.get("broken").is_none());
+    }
+
+    #[test]
+    fn test_raster_tile_sizes() {
+        use axum::http::StatusCode;
+
+        let config: Config = toml::from_str("").unwrap();
+        assert!(config.render.check_tile_size(256).is_ok());
+        assert!(config.render.check_tile_size(512).is_ok());
+        let err = config.render.check_tile_size(384).unwrap_err();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+        assert!(err.to_string().contains("256, 512"), "{}", err);
+
+        let config: Config =
+            toml::from_str("[render]\nallowed_tile_sizes = [256, 384, 512, 640]").unwrap();
+        config.render.validate().unwrap();
+        for size in [256, 384, 512, 640] {
+            assert!(config.render.check_tile_size(size).is_ok(), "{}", size);
+        }
+        for size in [128, 1024] {
+            let err = config.render.check_tile_size(size).unwrap_err();
+            assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+        }
+
+        let config: Config = toml::from_str("[render]\nallowed_tile_sizes = [256, 4096]").unwrap();
+        assert!(config.render.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_style_raster_zoom_limits() {
+        use tileserver_rs::config::StyleConfig;
+        use tileserver_rs::wmts::generate_wmts_capabilities;
+
+        let config = Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load config");
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
+        let protomaps = sources.get("protomaps").unwrap().metadata().clone();
+
+        // Unset limits come from the style's sources
+        let styles = StyleManager::from_configs(&config.styles).expect("Should load styles");
+        let extent = styles
+            .get("protomaps-light")
+            .unwrap()
+            .raster_extent(&sources);
+        assert_eq!(extent.minzoom, protomaps.minzoom);
+        assert_eq!(extent.maxzoom, protomaps.maxzoom);
+        assert_eq!(Some(extent.bounds), protomaps.bounds);
+
+        let configs = [StyleConfig {
+            maxzoom: Some(15),
+            bounds: None,
+            ..config.styles[0].clone()
+        }];
+        let styles = StyleManager::from_configs(&configs).expect("Should load styles");
+        let style = styles.get("protomaps-light").unwrap();
+        let extent = style.raster_extent(&sources);
+
+        // Florence
+        assert!(extent.contains(15, 8704 * 2, 5972 * 2));
+        assert!(!extent.contains(16, 8704 * 4, 5972 * 4));
+
+        let tilejson = style.raster_tilejson("http://localhost:8080", None, &sources);
+        assert_eq!(tilejson.maxzoom, 15);
+        let xml = generate_wmts_capabilities(
+            "http://localhost:8080",
+            &style.id,
+            &style.name,
+            &extent,
+            None,
+        );
+        assert!(xml.contains("<ows:Identifier>15</ows:Identifier>"));
+        assert!(!xml.contains("<ows:Identifier>16</ows:Identifier>"));
+    }
+
+    #[tokio::test]
+    async fn test_style_pixel_query() {
+        use tileserver_rs::query::{query_pixel, PixelQuery};
+
+        let config = Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load config");
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
+        let styles = StyleManager::from_configs(&config.styles).expect("Should load styles");
+        let style = styles.get("protomaps-light").unwrap();
+
+        // A building in the center of a Florence tile, drawn above land use and earth
+        let pixel = PixelQuery::new(14, 8704, 5972, (256.0, 256.0), None, None).unwrap();
+        let (lon, lat) = pixel.lon_lat();
+        let geojson = query_pixel(style, &sources, &pixel)
+            .await
+            .expect("Should query pixel");
+        let features = geojson["features"].as_array().unwrap();
+        assert!(!features.is_empty(), "Should find features");
+
+        let top = &features[0]["properties"];
+        assert_eq!(top["_source"], "protomaps");
+        assert_eq!(top["_layer"], "buildings");
+        assert_eq!(top["_style_layer"], "buildings");
+        assert_eq!(
+            features.last().unwrap()["properties"]["_layer"],
+            "earth",
+            "Bottom layer should come last"
+        );
+
+        // Geometries are in longitude and latitude around the pixel
+        let mut first = &features[0]["geometry"]["coordinates"];
+        while first[0].is_array() {
+            first = &first[0];
+        }
+        assert!((first[0].as_f64().unwrap() - lon).abs() < 0.05);
+        assert!((first[1].as_f64().unwrap() - lat).abs() < 0.05);
+
+        // Nothing is drawn far from the data
+        let pixel = PixelQuery::new(14, 0, 0, (1.0, 1.0), None, None).unwrap();
+        let geojson = query_pixel(style, &sources, &pixel).await.unwrap();
+        assert_eq!(geojson["features"], serde_json::json!([]));
+
+        // Pixels outside the tile
+        assert!(PixelQuery::new(14, 8704, 5972, (512.0, 0.0), None, None).is_err());
+        assert!(PixelQuery::new(14, 8704, 5972, (0.0, -0.5), None, None).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pixel_query_source_kinds() {
+        use tileserver_rs::query::{query_pixel, PixelQuery};
+
+        let config = Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load config");
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
+        let config: Config = toml::from_str(
+            r#"
+            [[styles]]
+            id = "imagery"
+            [styles.inline]
+            version = 8
+            [styles.inline.sources.satellite]
+            type = "raster"
+            tiles = ["https://example.com/{z}/{x}/{y}.jpg"]
+            [[styles.inline.layers]]
+            id = "satellite"
+            type = "raster"
+            source = "satellite"
+
+            [[styles]]
+            id = "combined"
+            [styles.inline]
+            version = 8
+            [styles.inline.sources.florence]
+            type = "vector"
+            url = "/data/protomaps.json"
+            [styles.inline.sources.zurich]
+            type = "vector"
+            url = "/data/zurich.json"
+            [[styles.inline.layers]]
+            id = "earth"
+            type = "fill"
+            source = "florence"
+            source-layer = "earth"
+            [[styles.inline.layers]]
+            id = "hidden-buildings"
+            type = "fill"
+            source = "florence"
+            source-layer = "buildings"
+            layout = { visibility = "none" }
+            "#,
+        )
+        .expect("Should parse config");
+        let styles = StyleManager::from_configs(&config.styles).expect("Should load styles");
+        let pixel = PixelQuery::new(14, 8704, 5972, (256.0, 256.0), None, None).unwrap();
+
+        // Raster-only styles have no features to report
+        let geojson = query_pixel(styles.get("imagery").unwrap(), &sources, &pixel)
+            .await
+            .unwrap();
+        assert_eq!(geojson["features"], serde_json::json!([]));
+
+        // Only the layers the style draws, from the source covering the pixel
+        let geojson = query_pixel(styles.get("combined").unwrap(), &sources, &pixel)
+            .await
+            .unwrap();
+        let features = geojson["features"].as_array().unwrap();
+        assert_eq!(features.len(), 1);
+        assert_eq!(features[0]["properties"]["_layer"], "earth");
+        assert_eq!(features[0]["properties"]["_source"], "protomaps");
+    }
+
+    #[tokio::test]
+    async fn test_blank_outside_tiles_share_background_tile() {
+        use tileserver_rs::config::StyleConfig;
+        use tileserver_rs::render::SolidTileCache;
+        use tileserver_rs::ImageFormat;
+
+        let config = Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load config");
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
+        let configs = [StyleConfig {
+            blank_outside: true,
+            ..config.styles[0].clone()
+        }];
+        let styles = StyleManager::from_configs(&configs).expect("Should load styles");
+        let style = styles.get("protomaps-light").unwrap();
+        let extent = style.raster_extent(&sources);
+
+        // Two ocean tiles far from Florence, served without a renderer
+        let ocean = [(14, 8000, 6000), (14, 8100, 6100)];
+        assert!(ocean.iter().all(|&(z, x, y)| !extent.contains(z, x, y)));
+
+        let color = style
+            .background_color()
+            .expect("Should have a constant background");
+        assert_eq!(color, [0xf8, 0xf4, 0xf0, 255]);
+
+        let cache = SolidTileCache::new();
+        let tiles: Vec<Vec<u8>> = ocean
+            .iter()
+            .map(|_| cache.tile(color, 256, ImageFormat::Png, "outside").unwrap())
+            .collect();
+        assert_eq!(tiles[0], tiles[1], "Should serve byte-identical tiles");
+        assert_eq!(cache.hits(), 1, "Should encode the tile once");
+
+        let image = image::load_from_memory(&tiles[0]).unwrap().to_rgba8();
+        assert_eq!(image.dimensions(), (256, 256));
+        assert!(image.pixels().all(|p| p.0 == color));
+    }
+}
+
+// ============================================================
+// Font Endpoint Tests
+// ============================================================
+
+mod font_tests {
+    use super::*;
+    use std::fs;
+    use tileserver_rs::{fonts, Config};
+
+    #[test]
+    fn test_fonts_directory_exists() {
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+
+        if let Some(ref fonts_path) = config.fonts {
+            assert!(
+                fonts_path.exists(),
+                "Fonts directory should exist: {:?}",
+                fonts_path
+            );
+            assert!(fonts_path.is_dir(), "Fonts path should be a directory");
+        }
+    }
+
+    #[test]
+    fn test_fonts_list() {
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+
+        if let Some(ref fonts_path) = config.fonts {
+            let entries: Vec<_> = fs::read_dir(fonts_path)
+                .expect("Should read fonts dir")
+                .filter_map(|e| e.ok())
+                .filter(|e| e.path().is_dir())
+                .collect();
+
+            assert!(!entries.is_empty(), "Should have at least one font family");
+        }
+    }
+
+    #[test]
+    fn test_font_pbf_files_format() {
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+
+        if let Some(ref fonts_path) = config.fonts {
+            // Check first font family
+            if let Some(font_dir) = fs::read_dir(fonts_path)
+                .ok()
+                .and_then(|mut r| r.next())
+                .and_then(|e| e.ok())
+            {
+                let font_path = font_dir.path();
+                if font_path.is_dir() {
+                    // Should have PBF files with range names like "0-255.pbf"
+                    let pbf_files: Vec<_> = fs::read_dir(&font_path)
+                        .expect("Should read font dir")
+                        .filter_map(|e| e.ok())
+                        .filter(|e| e.path().extension().is_some_and(|ext| ext == "pbf"))
+                        .collect();
+
+                    assert!(!pbf_files.is_empty(), "Font should have PBF files");
+
+                    // Verify range format
+                    for pbf in pbf_files {
+                        let name = pbf.file_name();
+                        let name_str = name.to_string_lossy();
+                        // Should match pattern like "0-255.pbf"
+                        assert!(
+                            name_str.contains('-') && name_str.ends_with(".pbf"),
+                            "PBF file should have range format: {}",
+                            name_str
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn glyph_pbf(name: &str, ids: &[u32]) -> Vec<u8> {
+        font_pbf(name, ids, 8)
+    }
+
+    /// A glyph PBF whose glyphs all have the given advance
+    fn font_pbf(name: &str, ids: &[u32], advance: u32) -> Vec<u8> {
+        use prost::Message;
+
+        let glyphs = ids
+            .iter()
+            .map(|&id| fonts::Glyph {
+                id,
+                bitmap: Some(vec![id as u8; 4]),
+                width: 1,
+                height: 1,
+                left: 0,
+                top: -10,
+                advance,
+            })
+            .collect();
+        fonts::Glyphs {
+            stacks: vec![fonts::Fontstack {
+                name: name.to_string(),
+                range: "0-255".to_string(),
+                glyphs,
+            }],
+        }
+        .encode_to_vec()
+    }
+
+    fn glyph_ids(data: &[u8]) -> Vec<u32> {
+        use prost::Message;
+
+        let glyphs = fonts::Glyphs::decode(data).expect("Should decode glyphs");
+        glyphs
+            .stacks
+            .iter()
+            .flat_map(|stack| stack.glyphs.iter().map(|g| g.id))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_fontstack_glyphs_are_merged() {
+        let dir = tempfile::tempdir().unwrap();
+        for (font, ids) in [("A", &[65u32, 66][..]), ("B", &[66, 67][..])] {
+            fs::create_dir(dir.path().join(font)).unwrap();
+            fs::write(
+                dir.path().join(font).join("0-255.pbf"),
+                glyph_pbf(font, ids),
+            )
+            .unwrap();
+        }
+        let cache = fonts::GlyphCache::new();
+        let files = fonts::FontCatalog::default();
+
+        let merged = fonts::load_glyphs(dir.path(), &files, "A,B", "0-255.pbf", &cache)
+            .await
+            .unwrap();
+        assert_eq!(glyph_ids(&merged), vec![65, 66, 67]);
+
+        // Merged ranges are cached
+        fs::remove_dir_all(dir.path().join("B")).unwrap();
+        let cached = fonts::load_glyphs(dir.path(), &files, "A, B", "0-255.pbf", &cache)
+            .await
+            .unwrap();
+        assert_eq!(cached, merged);
+
+        // Single fonts are served as stored, and missing fonts are skipped
+        let single = fonts::load_glyphs(dir.path(), &files, "A", "0-255.pbf", &cache)
+            .await
+            .unwrap();
+        assert_eq!(single, glyph_pbf("A", &[65, 66]));
+        let fallback = fonts::load_glyphs(dir.path(), &files, "Missing,A", "0-255.pbf", &cache)
+            .await
+            .unwrap();
+        assert_eq!(glyph_ids(&fallback), vec![65, 66]);
+
+        assert!(
+            fonts::load_glyphs(dir.path(), &files, "Missing", "0-255.pbf", &cache)
+                .await
+                .is_err()
+        );
+        assert!(
+            fonts::load_glyphs(dir.path(), &files, "../A", "0-255.pbf", &cache)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fontstack_fills_missing_codepoints_in_order() {
+        use prost::Message;
+
+        let dir = tempfile::tempdir().unwrap();
+        let a: Vec<u32> = (0..128).collect();
+        let b: Vec<u32> = (0..256).collect();
+        for (font, ids, advance) in [("A", &a, 10), ("B", &b, 20)] {
+            fs::create_dir(dir.path().join(font)).unwrap();
+            fs::write(
+                dir.path().join(font).join("0-255.pbf"),
+                font_pbf(font, ids, advance),
+            )
+            .unwrap();
+        }
+        let cache = fonts::GlyphCache::new();
+        let files = fonts::FontCatalog::default();
+
+        let merged = fonts::load_glyphs(dir.path(), &files, "A,B", "0-255.pbf", &cache)
+            .await
+            .unwrap();
+        let glyphs = fonts::Glyphs::decode(merged).unwrap();
+        assert_eq!(glyphs.stacks.len(), 1);
+        let glyphs = &glyphs.stacks[0].glyphs;
+        assert_eq!(glyphs.len(), 256);
+        for glyph in glyphs {
+            let expected = if glyph.id < 128 { 10 } else { 20 };
+            assert_eq!(glyph.advance, expected, "glyph {}", glyph.id);
+        }
+
+        // Reversing the stack takes every glyph from B
+        let reversed = fonts::load_glyphs(dir.path(), &files, "B,A", "0-255.pbf", &cache)
+            .await
+            .unwrap();
+        let glyphs = fonts::Glyphs::decode(reversed).unwrap();
+        assert!(glyphs.stacks[0].glyphs.iter().all(|g| g.advance == 20));
+    }
+
+    /// Advance of the first glyph served for `Latin,CJK JP` to a reader
+    /// whose `Accept-Language` is `header`
+    async fn language_advance(
+        dir: &std::path::Path,
+        map: &std::collections::HashMap<String, String>,
+        header: &str,
+    ) -> u32 {
+        use prost::Message;
+
+        let fontstack = "Latin,CJK JP";
+        let languages = fonts::language::preferred_languages(header);
+        let stack = fonts::language::language_font(&languages, map, |font| dir.join(font).is_dir())
+            .and_then(|font| fonts::language::prioritize(fontstack, font))
+            .unwrap_or_else(|| fontstack.to_string());
+
+        let cache = fonts::GlyphCache::new();
+        let files = fonts::FontCatalog::default();
+        let data = fonts::load_glyphs(dir, &files, &stack, "0-255.pbf", &cache)
+            .await
+            .unwrap();
+        fonts::Glyphs::decode(data).unwrap().stacks[0].glyphs[0].advance
+    }
+
+    #[tokio::test]
+    async fn test_accept_language_prioritizes_font() {
+        let dir = tempfile::tempdir().unwrap();
+        let ids: Vec<u32> = (0..256).collect();
+        for (font, advance) in [("Latin", 10), ("CJK JP", 20), ("CJK KR", 30)] {
+            fs::create_dir(dir.path().join(font)).unwrap();
+            fs::write(
+                dir.path().join(font).join("0-255.pbf"),
+                font_pbf(font, &ids, advance),
+            )
+            .unwrap();
+        }
+        let map = std::collections::HashMap::from([
+            ("ja".to_string(), "CJK JP".to_string()),
+            ("ko".to_string(), "CJK KR".to_string()),
+            ("th".to_string(), "Missing".to_string()),
+        ]);
+
+        assert_eq!(
+            language_advance(dir.path(), &map, "en-US,en;q=0.9").await,
+            10
+        );
+        assert_eq!(
+            language_advance(dir.path(), &map, "ja-JP,en;q=0.8").await,
+            20
+        );
+        assert_eq!(language_advance(dir.path(), &map, "en, ja;q=0.9").await, 20);
+        assert_eq!(language_advance(dir.path(), &map, "ja;q=0, en").await, 10);
+        // A mapped font outside the stack is added from the fonts directory
+        assert_eq!(
+            language_advance(dir.path(), &map, "ko-KR, ja;q=0.5").await,
+            30
+        );
+        // Fonts that aren't installed are skipped
+        assert_eq!(language_advance(dir.path(), &map, "th, ko;q=0.5").await, 30);
+    }
+
+    #[tokio::test]
+    async fn test_fixture_fontstack_merges() {
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        let fonts_dir = config.fonts.expect("Test config should have fonts");
+        let cache = fonts::GlyphCache::new();
+        let files = fonts::FontCatalog::default();
+
+        let regular =
+            fonts::load_glyphs(&fonts_dir, &files, "Noto Sans Regular", "0-255.pbf", &cache)
+                .await
+                .unwrap();
+        let merged = fonts::load_glyphs(
+            &fonts_dir,
+            &files,
+            "Noto Sans Regular,Noto Sans Medium",
+            "0-255.pbf",
+            &cache,
+        )
+        .await
+        .unwrap();
+        assert_eq!(glyph_ids(&merged), glyph_ids(&regular));
+    }
+
+    #[tokio::test]
+    async fn test_glyphs_generated_from_font_file() {
+        use prost::Message;
+
+        let fonts_dir = PathBuf::from("tests/fixtures/fonts");
+        let files = fonts::FontCatalog::scan(&fonts_dir);
+        assert_eq!(files.names().collect::<Vec<_>>(), vec!["Hack Regular"]);
+        let cache = fonts::GlyphCache::new();
+
+        let data = fonts::load_glyphs(&fonts_dir, &files, "Hack Regular", "0-255.pbf", &cache)
+            .await
+            .unwrap();
+        let glyphs = fonts::Glyphs::decode(data.clone()).expect("Should be a valid glyphs PBF");
+        assert_eq!(glyphs.stacks.len(), 1);
+        assert_eq!(glyphs.stacks[0].name, "Hack Regular");
+        assert_eq!(glyphs.stacks[0].range, "0-255");
+
+        let glyph = |id: u32| {
+            glyphs.stacks[0]
+                .glyphs
+                .iter()
+                .find(|g| g.id == id)
+                .unwrap_or_else(|| panic!("Missing glyph {}", id))
+        };
+        // Hack is monospaced: 1233 of 2048 units at 24px
+        let a = glyph('A' as u32);
+        assert_eq!(a.advance, 14);
+        assert_eq!((a.width, a.height), (14, 17));
+        assert_eq!(a.left, 0);
+        // Cap height (17px) below the ascender (22px)
+        assert_eq!(a.top, -5);
+        let bitmap = a.bitmap.as_ref().expect("'A' should have a bitmap");
+        assert_eq!(bitmap.len(), (14 + 6) * (17 + 6));
+        // Buffer pixels are outside the glyph, the strokes inside
+        assert!(bitmap[0] < 192);
+        assert!(bitmap.iter().any(|&d| d > 192));
+
+        // Descenders extend below the baseline
+        let g = glyph('g' as u32);
+        assert!(g.top - (g.height as i32) < a.top - (a.height as i32));
+
+        // Whitespace has metrics but no bitmap
+        let space = glyph(' ' as u32);
+        assert_eq!(space.advance, 14);
+        assert!(space.bitmap.is_none());
+
+        // Generated ranges are cached; unaligned ranges aren't generated
+        assert_eq!(files.glyphs("Hack Regular", "0-255"), Some(data));
+        assert!(
+            fonts::load_glyphs(&fonts_dir, &files, "Hack Regular", "1-256.pbf", &cache)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_style_glyph_subset() {
+        use prost::Message;
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("A")).unwrap();
+        fs::write(dir.path().join("A/0-255.pbf"), glyph_pbf("A", &[65, 66])).unwrap();
+        fs::write(dir.path().join("A/256-511.pbf"), glyph_pbf("A", &[256])).unwrap();
+        let glyph_cache = fonts::GlyphCache::new();
+        let subsets = fonts::subset::SubsetCache::new();
+        let files = fonts::FontCatalog::default();
+
+        let style = |text_field: serde_json::Value| {
+            serde_json::json!({
+                "version": 8,
+                "sources": {},
+                "layers": [{
+                    "id": "labels",
+                    "type": "symbol",
+                    "layout": { "text-field": text_field, "text-font": ["A"] }
+                }]
+            })
+        };
+        let ranges = |data: &[u8]| -> Vec<(String, String)> {
+            fonts::Glyphs::decode(data)
+                .unwrap()
+                .stacks
+                .into_iter()
+                .map(|stack| (stack.name, stack.range))
+                .collect()
+        };
+
+        // Literal text gets only the range of its characters
+        let literal = style(serde_json::json!("AB"));
+        let data = fonts::subset::load_subset(dir.path(), &files, &literal, &glyph_cache, &subsets)
+            .await
+            .unwrap();
+        assert_eq!(ranges(&data), vec![("A".into(), "0-255".into())]);
+        assert_eq!(glyph_ids(&data), vec![65, 66]);
+
+        // Feature names could be anything: every range the font has
+        let names = style(serde_json::json!(["get", "name"]));
+        let data = fonts::subset::load_subset(dir.path(), &files, &names, &glyph_cache, &subsets)
+            .await
+            .unwrap();
+        assert_eq!(
+            ranges(&data),
+            vec![("A".into(), "0-255".into()), ("A".into(), "256-511".into())]
+        );
+
+        // Subsets are cached by style, so a changed font isn't seen again
+        fs::write(dir.path().join("A/0-255.pbf"), glyph_pbf("A", &[67])).unwrap();
+        let cached =
+            fonts::subset::load_subset(dir.path(), &files, &literal, &glyph_cache, &subsets)
+                .await
+                .unwrap();
+        assert_eq!(glyph_ids(&cached), vec![65, 66]);
+
+        let err = fonts::subset::load_subset(
+            dir.path(),
+            &files,
+            &serde_json::json!({ "version": 8 }),
+            &glyph_cache,
+            &subsets,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.status(), axum::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_font_range_parsing() {
+        // Test that font ranges are parsed correctly
+        let valid_ranges = ["0-255.pbf", "256-511.pbf", "65024-65279.pbf"];
+
+        for range in valid_ranges {
+            let parts: Vec<&str> = range.trim_end_matches(".pbf").split('-').collect();
+            assert_eq!(parts.len(), 2, "Range should have start and end: {}", range);
+
+            let start: u32 = parts[0].parse().expect("Start should be a number");
+            let end: u32 = parts[1].parse().expect("End should be a number");
+            assert!(start < end, "Start should be less than end");
+            assert_eq!((end - start + 1), 256, "Range should span 256 characters");
+        }
+    }
+}
+
+// ============================================================
+// Static Files Endpoint Tests
+// ============================================================
+
+mod files_tests {
+    use super::*;
+    use axum::http::{
+        header::{
+            ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE,
+        },
+        HeaderMap, HeaderValue, StatusCode,
+    };
+    use tileserver_rs::files;
+
+    #[test]
+    fn test_files_path_security() {
+        // Test patterns that should be detected as dangerous
+        let dangerous_patterns = [
+            ("../../../etc/passwd", ".."),
+            ("..\\..\\windows\\system32", ".."),
+            ("/etc/passwd", "absolute"),
+            ("C:\\Windows\\System32", "windows_drive"),
+            ("....//....//etc/passwd", ".."),
+        ];
+
+        for (path, reason) in dangerous_patterns {
+            let is_dangerous = path.contains("..")
+                || path.starts_with('/')
+                || (path.len() >= 2 && path.chars().nth(1) == Some(':'));
+            assert!(
+                is_dangerous,
+                "Path should be detected as dangerous ({}): {}",
+                reason, path
+            );
+        }
+    }
+
+    #[test]
+    fn test_url_encoded_path_traversal() {
+        // URL-encoded traversal should be decoded and detected by the server
+        let encoded_traversals = [
+            "%2e%2e%2f", // ../
+            "%2e%2e/",   // ../
+            "..%2f",     // ../
+        ];
+
+        for encoded in encoded_traversals {
+            // After URL decoding, these should contain ".."
+            let decoded = urlencoding::decode(encoded).unwrap_or_default();
+            assert!(
+                decoded.contains("..") || encoded.contains("%2e"),
+                "Encoded traversal should be detectable: {}",
+                encoded
+            );
+        }
+    }
+
+    const FIXTURE_DIR: &str = "tests/fixtures";
+    const FIXTURE_FILE: &str = "tls/cert.pem";
+
+    async fn get_file(range: Option<&str>) -> axum::response::Response {
+        let mut headers = HeaderMap::new();
+        if let Some(range) = range {
+            headers.insert(RANGE, HeaderValue::from_str(range).unwrap());
+        }
+        files::serve(std::path::Path::new(FIXTURE_DIR), FIXTURE_FILE, &headers)
+            .await
+            .expect("Should serve fixture file")
+    }
+
+    async fn body(response: axum::response::Response) -> Vec<u8> {
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap()
+            .to_vec()
+    }
+
+    #[tokio::test]
+    async fn test_file_served_in_full() {
+        let expected = std::fs::read(PathBuf::from(FIXTURE_DIR).join(FIXTURE_FILE)).unwrap();
+
+        let response = get_file(None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[ACCEPT_RANGES], "bytes");
+        assert_eq!(
+            response.headers()[CONTENT_LENGTH],
+            expected.len().to_string().as_str()
+        );
+        assert!(!response.headers().contains_key(CONTENT_RANGE));
+        assert_eq!(body(response).await, expected);
+    }
+
+    #[tokio::test]
+    async fn test_file_range_request() {
+        let expected = std::fs::read(PathBuf::from(FIXTURE_DIR).join(FIXTURE_FILE)).unwrap();
+
+        let response = get_file(Some("bytes=10-19")).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            response.headers()[CONTENT_RANGE],
+            format!("bytes 10-19/{}", expected.len()).as_str()
+        );
+        assert_eq!(response.headers()[CONTENT_LENGTH], "10");
+        assert_eq!(body(response).await, &expected[10..20]);
+
+        let response = get_file(Some(&format!("bytes={}-", expected.len()))).await;
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(
+            response.headers()[CONTENT_RANGE],
+            format!("bytes */{}", expected.len()).as_str()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_file_if_range() {
+        let full = get_file(None).await;
+        let etag = full.headers()[ETAG].clone();
+        let last_modified = full.headers()[LAST_MODIFIED].clone();
+
+        let get = |if_range: HeaderValue| async move {
+            let mut headers = HeaderMap::new();
+            headers.insert(RANGE, HeaderValue::from_static("bytes=0-9"));
+            headers.insert(IF_RANGE, if_range);
+            files::serve(std::path::Path::new(FIXTURE_DIR), FIXTURE_FILE, &headers)
+                .await
+                .expect("Should serve fixture file")
+                .status()
+        };
+
+        // Matching validators honour the range, stale ones return the whole file
+        assert_eq!(get(etag).await, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(get(last_modified).await, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            get(HeaderValue::from_static("\"stale\"")).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            get(HeaderValue::from_static("Thu, 01 Jan 1970 00:00:00 GMT")).await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn test_file_traversal_rejected() {
+        let headers = HeaderMap::new();
+        let dir = std::path::Path::new(FIXTURE_DIR).join("tls");
+        for path in ["../config.test.toml", "/etc/passwd", "missing.pem", ""] {
+            assert!(
+                files::serve(&dir, path, &headers).await.is_err(),
+                "Should reject {:?}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn test_valid_file_paths() {
+        let valid_paths = [
+            "image.png",
+            "data/file.json",
+            "assets/icons/marker.svg",
+            "some-file_name.txt",
+        ];
+
+        for path in valid_paths {
+            assert!(
+                !path.contains(".."),
+                "Valid path should not have ..: {}",
+                path
+            );
+            assert!(
+                !path.starts_with('/'),
+                "Valid path should not start with /: {}",
+                path
+            );
+        }
+    }
+}
+
+// ============================================================
+// Byte Range Tests
+// ============================================================
+
+mod range_tests {
+    use axum::{
+        body::Body,
+        http::{
+            header::{ACCEPT_RANGES, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE},
+            Request, StatusCode,
+        },
+        middleware,
+        routing::get,
+        Router,
+    };
+    use tileserver_rs::range;
+    use tower::ServiceExt;
+
+    const TILE: &[u8] = b"0123456789abcdefghij";
+    const LAST_MODIFIED_DATE: &str = "Wed, 21 Oct 2015 07:28:00 GMT";
+
+    fn router() -> Router {
+        let tile = || async {
+            (
+                [(ETAG, "\"tile\""), (LAST_MODIFIED, LAST_MODIFIED_DATE)],
+                TILE,
+            )
+        };
+        Router::new().route(
+            "/tile",
+            get(tile).layer(middleware::from_fn(range::byte_ranges)),
+        )
+    }
+
+    async fn request(headers: &[(&str, &str)]) -> axum::response::Response {
+        let mut request = Request::get("/tile");
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        router()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    async fn body(response: axum::response::Response) -> Vec<u8> {
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap()
+            .to_vec()
+    }
+
+    #[tokio::test]
+    async fn test_full_response_advertises_ranges() {
+        let response = request(&[]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[ACCEPT_RANGES], "bytes");
+        assert_eq!(body(response).await, TILE);
+    }
+
+    #[tokio::test]
+    async fn test_single_range() {
+        let response = request(&[(RANGE.as_str(), "bytes=5-9")]).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()[CONTENT_RANGE], "bytes 5-9/20");
+        assert_eq!(body(response).await, b"56789");
+
+        let response = request(&[(RANGE.as_str(), "bytes=-4")]).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(body(response).await, b"ghij");
+    }
+
+    #[tokio::test]
+    async fn test_multiple_ranges_return_whole_body() {
+        let response = request(&[(RANGE.as_str(), "bytes=0-1,5-6")]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!response.headers().contains_key(CONTENT_RANGE));
+        assert_eq!(body(response).await, TILE);
+    }
+
+    #[tokio::test]
+    async fn test_unsatisfiable_range() {
+        let response = request(&[(RANGE.as_str(), "bytes=20-")]).await;
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.headers()[CONTENT_RANGE], "bytes */20");
+        assert!(body(response).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_if_range() {
+        let status = |if_range: &'static str| async move {
+            request(&[(RANGE.as_str(), "bytes=0-3"), (IF_RANGE.as_str(), if_range)])
+                .await
+                .status()
+        };
+
+        assert_eq!(status("\"tile\"").await, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            status(LAST_MODIFIED_DATE).await,
+            StatusCode::PARTIAL_CONTENT
+        );
+        // Weak and changed validators fall back to the full response
+        assert_eq!(status("W/\"tile\"").await, StatusCode::OK);
+        assert_eq!(status("\"other\"").await, StatusCode::OK);
+        assert_eq!(
+            status("Thu, 22 Oct 2015 07:28:00 GMT").await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn test_head_is_not_sliced() {
+        let response = router()
+            .oneshot(
+                Request::head("/tile")
+                    .header(RANGE, "bytes=0-3")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[ACCEPT_RANGES], "bytes");
+    }
+}
+
+// ============================================================
+// Content-Type Tests
+// ============================================================
+
+mod content_type_tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_tile_content_types() {
+        let content_types = [
+            ("pbf", "application/x-protobuf"),
+            ("mvt", "application/x-protobuf"),
+            ("png", "image/png"),
+            ("jpg", "image/jpeg"),
+            ("jpeg", "image/jpeg"),
+            ("webp", "image/webp"),
+        ];
+
+        for (ext, expected_type) in content_types {
+            // This would be tested through the TileFormat enum
+            use std::str::FromStr;
+            use tileserver_rs::sources::TileFormat;
+
+            let format = TileFormat::from_str(ext).unwrap();
+            assert_eq!(
+                format.content_type(),
+                expected_type,
+                "Wrong content type for {}",
+                ext
+            );
+        }
+    }
+
+    #[test]
+    fn test_image_content_types() {
+        use std::str::FromStr;
+        use tileserver_rs::render::ImageFormat;
+
+        let formats = [
+            ("png", "image/png"),
+            ("jpg", "image/jpeg"),
+            ("jpeg", "image/jpeg"),
+            ("webp", "image/webp"),
+        ];
+
+        for (ext, expected_type) in formats {
+            let format = ImageFormat::from_str(ext).unwrap();
+            assert_eq!(
+                format.content_type(),
+                expected_type,
+                "Wrong content type for {}",
+                ext
+            );
+        }
+    }
+
+    #[test]
+    fn test_json_endpoints_content_type() {
+        // JSON endpoints should return application/json
+        let json_endpoints = [
+            "/health", // Returns text/plain
+            "/index.json",
+            "/data.json",
+            "/styles.json",
+            "/fonts.json",
+            "/openapi.json",
+        ];
+
+        // Verify endpoints that should return JSON
+        for endpoint in &json_endpoints[1..] {
+            assert!(
+                endpoint.ends_with(".json"),
+                "JSON endpoint should end with .json: {}",
+                endpoint
+            );
+        }
+    }
+}
+
+// ============================================================
+// Cache Control Tests
+// ============================================================
+
+mod cache_control_tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_tile_cache_values() {
+        // Tiles should have long cache times
+        let tile_cache = "public, max-age=86400"; // 1 day
+        assert!(tile_cache.contains("public"));
+        assert!(tile_cache.contains("max-age"));
+    }
+
+    #[test]
+    fn test_metadata_cache_values() {
+        // Metadata (TileJSON, style.json) should have shorter cache
+        let metadata_cache = "public, max-age=300"; // 5 minutes
+        assert!(metadata_cache.contains("max-age=300"));
+    }
+
+    #[test]
+    fn test_static_assets_cache() {
+        // Static assets (fonts, sprites) should have long cache
+        let static_cache = "public, max-age=604800"; // 1 week
+        assert!(static_cache.contains("max-age=604800"));
+    }
+
+    #[tokio::test]
+    async fn test_compressed_tile_varies_on_accept_encoding() {
+        use tileserver_rs::{cache_control, Config, SourceManager, TileCompression};
+
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
+        // Florence at z14, inside the protomaps extract
+        let tile = sources
+            .get("protomaps")
+            .expect("Should have protomaps")
+            .get_tile(14, 8704, 5972)
+            .await
+            .unwrap()
+            .expect("Tile should exist");
+        assert_eq!(tile.compression, TileCompression::Gzip);
+
+        let gzip = tile.clone().negotiate_encoding(Some("gzip, br")).unwrap();
+        let headers = cache_control::tile_response_headers(&gzip);
+        assert_eq!(headers["vary"], "Accept-Encoding");
+        assert_eq!(headers["content-encoding"], "gzip");
+
+        // Clients without gzip support get the decompressed tile, still keyed on the header
+        let identity = tile.negotiate_encoding(Some("identity")).unwrap();
+        let headers = cache_control::tile_response_headers(&identity);
+        assert_eq!(headers["vary"], "Accept-Encoding");
+        assert!(headers.get("content-encoding").is_none());
+        assert!(identity.decode_mvt().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_raster_etag_follows_style_and_data() {
+        use axum::http::{HeaderMap, HeaderValue};
+        use tileserver_rs::cache_control::RenderValidators;
+        use tileserver_rs::config::{SourceConfig, StyleConfig, StyleRewriteConfig};
+        use tileserver_rs::styles::{self, SharedStyles, StyleManager};
+        use tileserver_rs::SourceManager;
+
+        let dir = tempfile::tempdir().unwrap();
+        let style_path = dir.path().join("style.json");
+        let write_style = |color: &str| {
+            let style = serde_json::json!({
+                "version": 8,
+                "sources": { "zurich": { "type": "vector", "url": "/data/zurich.json" } },
+                "layers": [{ "id": "background", "type": "background",
+                             "paint": { "background-color": color } }]
+            });
+            std::fs::write(&style_path, style.to_string()).unwrap();
+        };
+        write_style("#ffffff");
+        let style_config: StyleConfig = toml::from_str(&format!(
+            "id = \"test\"\npath = \"{}\"",
+            style_path.display()
+        ))
+        .unwrap();
+        let styles = SharedStyles::new(
+            StyleManager::from_configs(std::slice::from_ref(&style_config)).unwrap(),
+        );
+
+        let source_config = |version: &str| -> SourceConfig {
+            toml::from_str(&format!(
+                "id = \"zurich\"\ntype = \"mbtiles\"\n\
+                 path = \"data/tiles/zurich_switzerland.mbtiles\"\n\
+                 [options]\nversion = \"{}\"",
+                version
+            ))
+            .unwrap()
+        };
+        let sources = SourceManager::from_configs(&[source_config("1")])
+            .await
+            .unwrap();
+
+        // What the raster handlers compute before rendering
+        let validators = |shared: &SharedStyles, sources: &SourceManager| {
+            let styles = shared.load();
+            let style = styles.get("test").unwrap();
+            let rewritten = styles::rewrite_style_for_native(
+                &style.style_json,
+                &style.id,
+                "http://localhost:8080",
+                sources,
+                &StyleRewriteConfig::default(),
+                shared.inline_sprites(),
+            );
+            RenderValidators::new(&rewritten.to_string(), style, sources)
+        };
+        let if_none_match = |etag: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert("if-none-match", HeaderValue::from_str(etag).unwrap());
+            headers
+        };
+
+        let first = validators(&styles, &sources);
+        let first_etag = first.etag.clone().expect("Should have an ETag");
+        assert!(first_etag.starts_with("W/\""));
+        assert_eq!(first, validators(&styles, &sources), "Should be stable");
+        // Unchanged tiles revalidate with 304
+        assert!(first.matches(&if_none_match(&first_etag)));
+
+        // Hot-reloading an edited style changes the style version and ETag
+        write_style("#000000");
+        styles.reload_style(&style_config).unwrap();
+        let restyled = validators(&styles, &sources);
+        assert_ne!(restyled.style_version, first.style_version);
+        assert_ne!(restyled.etag, first.etag);
+        // The CDN's old ETag no longer matches, so the tile is rendered (200)
+        assert!(!restyled.matches(&if_none_match(&first_etag)));
+        let restyled_etag = restyled.etag.clone().expect("Should have an ETag");
+        assert!(restyled.matches(&if_none_match(&restyled_etag)));
+
+        // So does a new version of the data, with the same style
+        let updated = SourceManager::from_configs(&[source_config("2")])
+            .await
+            .unwrap();
+        let redata = validators(&styles, &updated);
+        assert_eq!(redata.style_version, restyled.style_version);
+        assert_ne!(redata.etag, restyled.etag);
+        assert!(!redata.matches(&if_none_match(&restyled_etag)));
+    }
+
+    #[tokio::test]
+    async fn test_raster_etag_needs_every_data_version() {
+        use axum::http::{HeaderMap, HeaderValue};
+        use tileserver_rs::cache_control::RenderValidators;
+        use tileserver_rs::config::{SourceConfig, StyleConfig, StyleRewriteConfig};
+        use tileserver_rs::styles::{self, SharedStyles, StyleManager};
+        use tileserver_rs::SourceManager;
+
+        let dir = tempfile::tempdir().unwrap();
+        let style_path = dir.path().join("style.json");
+        let style = serde_json::json!({
+            "version": 8,
+            "sources": {
+                "zurich": { "type": "vector", "url": "/data/zurich.json" },
+                "remote": { "type": "vector", "url": "/data/remote.json" }
+            },
+            "layers": [{ "id": "background", "type": "background" }]
+        });
+        std::fs::write(&style_path, style.to_string()).unwrap();
+        let style_config: StyleConfig = toml::from_str(&format!(
+            "id = \"test\"\npath = \"{}\"",
+            style_path.display()
+        ))
+        .unwrap();
+        let shared = SharedStyles::new(
+            StyleManager::from_configs(std::slice::from_ref(&style_config)).unwrap(),
+        );
+        let styles = shared.load();
+        let style = styles.get("test").unwrap();
+
+        let zurich: SourceConfig = toml::from_str(
+            "id = \"zurich\"\ntype = \"mbtiles\"\n\
+             path = \"data/tiles/zurich_switzerland.mbtiles\"",
+        )
+        .unwrap();
+        // A proxied source has no file whose mtime could version it
+        let remote = |options: &str| -> SourceConfig {
+            toml::from_str(&format!(
+                "id = \"remote\"\ntype = \"proxy\"\n\
+                 path = \"https://tiles.example.com/{{z}}/{{x}}/{{y}}.pbf\"\n{}",
+                options
+            ))
+            .unwrap()
+        };
+        let validators = |sources: &SourceManager| {
+            let rewritten = styles::rewrite_style_for_native(
+                &style.style_json,
+                &style.id,
+                "http://localhost:8080",
+                sources,
+                &StyleRewriteConfig::default(),
+                shared.inline_sprites(),
+            );
+            RenderValidators::new(&rewritten.to_string(), style, sources)
+        };
+
+        let sources = SourceManager::from_configs(&[zurich.clone(), remote("")])
+            .await
+            .unwrap();
+        assert!(sources.data_version("remote").is_none());
+        let unversioned = validators(&sources);
+        assert_eq!(unversioned.etag, None);
+
+        // Nothing would change the ETag when the remote data does, so the
+        // tile is neither tagged nor revalidated
+        let mut request = HeaderMap::new();
+        request.insert("if-none-match", HeaderValue::from_static("*"));
+        assert!(!unversioned.matches(&request));
+        let mut headers = HeaderMap::new();
+        unversioned.insert(&mut headers);
+        assert!(headers.get("etag").is_none());
+        assert_eq!(headers["x-style-version"], unversioned.style_version);
+
+        // A configured version brings the ETag back
+        let sources = SourceManager::from_configs(&[zurich, remote("[options]\nversion = \"1\"")])
+            .await
+            .unwrap();
+        assert!(validators(&sources).etag.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_head_tile_headers_match_get() {
+        use tileserver_rs::error::TileServerError;
+        use tileserver_rs::{cache_control, Config, SourceManager};
+
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
+
+        // MBTiles sizes tiles in SQLite; PMTiles measures the mapped tile
+        for (id, z, x, y) in [("zurich", 2, 2, 1), ("protomaps", 14, 8704, 5972)] {
+            let source = sources.get(id).expect("Should have source");
+            let tile = sources
+                .get_tile(id, z, x, y)
+                .await
+                .unwrap()
+                .expect("Tile should exist");
+            let info = sources
+                .tile_info(id, z, x, y)
+                .await
+                .unwrap()
+                .expect("Tile info should exist");
+            assert_eq!(info, tile.info());
+
+            let mut get = cache_control::tile_response_headers(&tile);
+            cache_control::insert_validators(&mut get, &tile.info(), source.last_modified());
+            let mut head = cache_control::tile_info_headers(&info);
+            cache_control::insert_validators(&mut head, &info, source.last_modified());
+            assert_eq!(head, get);
+
+            assert_eq!(head["content-length"], tile.data.len().to_string());
+            assert!(head["etag"].to_str().unwrap().starts_with("W/\""));
+            assert!(head.get("last-modified").is_some());
+        }
+
+        // Zurich covers a single tile per zoom
+        assert!(sources
+            .tile_info("zurich", 2, 0, 0)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(sources.tile_info("missing", 0, 0, 0).await.is_err());
+
+        // Out-of-range tiles fail as they do for GET, even past the zooms
+        // a tile column can be shifted to, and before the source is looked up
+        for (id, z, x, y) in [
+            ("zurich", 31, 0, 0),
+            ("zurich", 40, 0, 0),
+            ("zurich", 2, 4, 0),
+            ("protomaps", 3, 0, 8),
+            ("missing", 40, 0, 0),
+        ] {
+            let err = sources.tile_info(id, z, x, y).await.unwrap_err();
+            assert!(
+                matches!(err, TileServerError::InvalidCoordinates { .. }),
+                "{}/{}/{}/{}: {}",
+                id,
+                z,
+                x,
+                y,
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn test_validators_need_modification_time() {
+        use std::time::{Duration, UNIX_EPOCH};
+        use tileserver_rs::{cache_control, TileCompression, TileFormat, TileInfo};
+
+        let info = TileInfo {
+            size: 255,
+            format: TileFormat::Png,
+            compression: TileCompression::None,
+        };
+        let mut headers = cache_control::tile_info_headers(&info);
+        cache_control::insert_validators(&mut headers, &info, None);
+        assert!(headers.get("etag").is_none());
+        assert!(headers.get("last-modified").is_none());
+
+        let modified = UNIX_EPOCH + Duration::from_secs(0x10000);
+        cache_control::insert_validators(&mut headers, &info, Some(modified));
+        assert_eq!(headers["etag"], "W/\"10000-ff\"");
+        assert_eq!(headers["last-modified"], "Thu, 01 Jan 1970 18:12:16 GMT");
+        assert_eq!(headers["content-length"], "255");
+    }
+
+    #[test]
+    fn test_debug_headers_config() {
+        use tileserver_rs::Config;
+
+        let config: Config = toml::from_str("[server]\ndebug_headers = true").unwrap();
+        assert!(config.server.debug_headers);
+        let config: Config = toml::from_str("[server]\nport = 8080").unwrap();
+        assert!(!config.server.debug_headers);
+    }
+}
+
+// ============================================================
+// Error Response Tests
+// ============================================================
+
+mod error_tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    async fn error_json(
+        err: tileserver_rs::error::TileServerError,
+    ) -> (axum::http::StatusCode, serde_json::Value) {
+        use axum::response::IntoResponse;
+
+        let response = err.into_response();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_error_response_format() {
+        use tileserver_rs::error::TileServerError;
+
+        let (status, json) =
+            error_json(TileServerError::SourceNotFound("invalid-source".into())).await;
+        assert_eq!(status, axum::http::StatusCode::NOT_FOUND);
+        assert_eq!(json["error"]["code"], "SOURCE_NOT_FOUND");
+        assert_eq!(json["error"]["message"], "Source not found: invalid-source");
+        assert_eq!(json["error"]["details"]["source"], "invalid-source");
+    }
+
+    #[tokio::test]
+    async fn test_error_codes() {
+        use axum::http::StatusCode;
+        use tileserver_rs::error::TileServerError;
+
+        let (status, json) = error_json(TileServerError::InvalidTileRequest).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(json["error"]["code"], "INVALID_TILE_REQUEST");
+        assert!(json["error"].get("details").is_none());
+
+        let (status, json) = error_json(TileServerError::RenderUnavailable).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(json["error"]["code"], "RENDER_UNAVAILABLE");
+        assert_eq!(json["error"]["message"], "Rendering not available");
+
+        let (status, json) = error_json(TileServerError::StyleNotFound("dark".into())).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(json["error"]["code"], "STYLE_NOT_FOUND");
+        assert_eq!(json["error"]["details"]["style"], "dark");
+    }
+
+    #[test]
+    fn test_404_scenarios() {
+        // Scenarios that should return 404
+        let not_found_cases = [
+            "Source not found",
+            "Style not found",
+            "Font not found",
+            "File not found",
+            "Tile not found",
+        ];
+
+        for case in not_found_cases {
+            assert!(case.contains("not found"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_out_of_range_tile_body() {
+        use axum::response::IntoResponse;
+        use tileserver_rs::sources::validate_tile_coords;
+
+        for ((z, x, y), coordinate, value, max) in [
+            ((3, 999, 999), "x", 999, 7),
+            ((3, 7, 8), "y", 8, 7),
+            ((45, 0, 0), "z", 45, 30),
+        ] {
+            let err = validate_tile_coords(z, x, y).unwrap_err();
+            let response = err.into_response();
+            assert_eq!(response.status(), axum::http::StatusCode::BAD_REQUEST);
+
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(json["error"]["code"], "INVALID_COORDINATES");
+            assert_eq!(
+                json["error"]["message"],
+                format!("Invalid tile coordinates: z={}, x={}, y={}", z, x, y)
+            );
+            let details = &json["error"]["details"];
+            assert_eq!(details["coordinate"], coordinate);
+            assert_eq!(details["value"], value);
+            assert_eq!(details["max"], max);
+        }
+    }
+
+    #[test]
+    fn test_400_scenarios() {
+        // Scenarios that should return 400
+        let bad_request_cases = [
+            "Invalid tile coordinates",
+            "Invalid zoom level",
+            "Invalid format",
+            "Invalid size",
+        ];
+
+        for case in bad_request_cases {
+            assert!(case.starts_with("Invalid"));
+        }
+    }
+}
+
+// ============================================================
+// Running Server Tests
+// ============================================================
+
+mod server_tests {
+    use super::*;
+    use std::process::{Child, Command, Stdio};
+    use std::time::Duration;
+
+    /// The server binary serving the test configuration, killed on drop
+    struct Server {
+        child: Child,
+        url: String,
+    }
+
+    impl Server {
+        async fn start() -> Self {
+            Self::start_with(TEST_CONFIG, &[]).await
+        }
+
+        async fn start_with(config: &str, args: &[&str]) -> Self {
+            let port = std::net::TcpListener::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap()
+                .port();
+            let child = Command::new(env!("CARGO_BIN_EXE_tileserver-rs"))
+                .args(["--config", config, "--no-ui", "--port"])
+                .arg(port.to_string())
+                .args(args)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .expect("Should start the server");
+            let server = Self {
+                child,
+                url: format!("http://127.0.0.1:{}", port),
+            };
+
+            let client = reqwest::Client::new();
+            for _ in 0..100 {
+                let ready = client
+                    .get(format!("{}/health", server.url))
+                    .send()
+                    .await
+                    .is_ok_and(|response| response.status().is_success());
+                if ready {
+                    return server;
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            panic!("The server did not start");
+        }
+    }
+
+    impl Drop for Server {
+        fn drop(&mut self) {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_head_rejects_out_of_range_tiles_like_get() {
+        let server = Server::start().await;
+        let client = reqwest::Client::new();
+
+        for path in [
+            "/data/zurich/31/0/0.pbf",
+            "/data/zurich/40/0/0.pbf",
+            "/data/zurich/2/4/0.pbf",
+            "/data/protomaps/3/0/8.mvt",
+        ] {
+            let url = format!("{}{}", server.url, path);
+            let get = client.get(&url).send().await.unwrap();
+            assert_eq!(get.status(), reqwest::StatusCode::BAD_REQUEST, "{}", path);
+            let get_length = get.headers()[reqwest::header::CONTENT_LENGTH].clone();
+            let body: serde_json::Value =
+                serde_json::from_slice(&get.bytes().await.unwrap()).unwrap();
+            assert_eq!(body["error"]["code"], "INVALID_COORDINATES", "{}", path);
+
+            // The same error, whose body HEAD leaves out
+            let head = client.head(&url).send().await.unwrap();
+            assert_eq!(head.status(), reqwest::StatusCode::BAD_REQUEST, "{}", path);
+            assert_eq!(
+                head.headers()[reqwest::header::CONTENT_TYPE],
+                "application/json"
+            );
+            assert_eq!(head.headers()[reqwest::header::CONTENT_LENGTH], get_length);
+        }
+
+        // The server is still up after them
+        let tile = client
+            .head(format!("{}/data/zurich/2/2/1.pbf", server.url))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(tile.status(), reqwest::StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_only_tile_responses_vary_on_accept_encoding() {
+        let server = Server::start().await;
+        let client = reqwest::Client::new();
+        let varies = |response: &reqwest::Response| {
+            response
+                .headers()
+                .get_all(reqwest::header::VARY)
+                .iter()
+                .any(|vary| {
+                    vary.to_str()
+                        .unwrap()
+                        .to_lowercase()
+                        .contains("accept-encoding")
+                })
+        };
+
+        for path in ["/data/zurich", "/data.json", "/styles.json", "/index.json"] {
+            let response = client
+                .get(format!("{}{}", server.url, path))
+                .header(reqwest::header::ACCEPT_ENCODING, "gzip, br")
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), reqwest::StatusCode::OK, "{}", path);
+            assert!(!varies(&response), "{}", path);
+        }
+
+        let tile = client
+            .get(format!("{}/data/zurich/2/2/1.pbf", server.url))
+            .header(reqwest::header::ACCEPT_ENCODING, "gzip, br")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(tile.status(), reqwest::StatusCode::OK);
+        assert!(varies(&tile));
+    }
+
+    /// Debug headers of a source tile and a rendered tile
+    async fn debug_headers(server: &Server) -> Vec<Option<String>> {
+        let client = reqwest::Client::new();
+        let mut found = Vec::new();
+        for path in [
+            "/data/zurich/2/2/1.pbf",
+            "/styles/protomaps-light/0/0/0.png",
+        ] {
+            let response = client
+                .get(format!("{}{}", server.url, path))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), reqwest::StatusCode::OK, "{}", path);
+            for name in ["x-tile-source", "x-tile-format", "x-render-time-ms"] {
+                found.push(
+                    response
+                        .headers()
+                        .get(name)
+                        .map(|value| value.to_str().unwrap().to_string()),
+                );
+            }
+        }
+        found
+    }
+
+    #[tokio::test]
+    async fn test_debug_headers_follow_config_flag() {
+        // Off by default
+        let server = Server::start().await;
+        assert!(debug_headers(&server).await.iter().all(Option::is_none));
+        drop(server);
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("config.toml");
+        let contents = std::fs::read_to_string(TEST_CONFIG).unwrap();
+        std::fs::write(
+            &config,
+            contents.replace("[server]\n", "[server]\ndebug_headers = true\n"),
+        )
+        .unwrap();
+
+        for server in [
+            Server::start_with(config.to_str().unwrap(), &[]).await,
+            Server::start_with(TEST_CONFIG, &["--debug-headers"]).await,
+        ] {
+            let headers = debug_headers(&server).await;
+            assert_eq!(headers[0].as_deref(), Some("mbtiles"));
+            assert_eq!(headers[1].as_deref(), Some("pbf"));
+            // Only rendered tiles report the render time
+            assert_eq!(headers[2], None);
+            assert_eq!(headers[3].as_deref(), Some("render"));
+            assert_eq!(headers[4].as_deref(), Some("png"));
+            let render_time = headers[5]
+                .as_deref()
+                .expect("Should report the render time");
+            assert!(render_time.parse::<u64>().is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validate_is_served_without_debug_endpoints() {
+        let server = Server::start().await;
+
+        let response = reqwest::Client::new()
+            .post(format!("{}/data/zurich/validate", server.url))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let report: serde_json::Value =
+            serde_json::from_slice(&response.bytes().await.unwrap()).unwrap();
+        assert_eq!(report["complete"], true);
+    }
+}
+
+// ============================================================
+// CORS Tests
+// ============================================================
+
+mod cors_tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{header, Method, Request};
+    use axum::routing::get;
+    use std::collections::HashMap;
+    use tileserver_rs::config::{CorsRouteConfig, ServerConfig};
+    use tileserver_rs::cors::{Cors, ADMIN_GROUP};
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_cors_origins() {
+        // Test CORS origin patterns
+        let allowed_origins = ["*", "http://localhost:3000", "https://example.com"];
+
+        for origin in allowed_origins {
+            assert!(!origin.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_cors_methods() {
+        // API should allow these methods
+        let allowed_methods = ["GET", "POST", "OPTIONS", "HEAD"];
+
+        for method in allowed_methods {
+            assert!(["GET", "OPTIONS", "HEAD", "POST", "PUT", "DELETE"].contains(&method));
+        }
+    }
+
+    #[test]
+    fn test_cors_headers() {
+        // API should allow these headers
+        let allowed_headers = ["Accept", "Content-Type"];
+
+        for header in allowed_headers {
+            assert!(!header.is_empty());
+        }
+    }
+
+    /// Router with one route per group: `/data`, `/styles`, `/admin` and `/health`
+    fn cors_router(server: &ServerConfig) -> axum::Router {
+        let cors = Cors::from_config(server).expect("Should build CORS policies");
+        let route = |path: &str| axum::Router::new().route(path, get(|| async { "OK" }));
+
+        axum::Router::new()
+            .merge(cors.apply(Some("data"), route("/data")))
+            .merge(cors.apply(Some("styles"), route("/styles")))
+            .merge(cors.apply(Some(ADMIN_GROUP), route("/admin")))
+            .merge(cors.apply(None, route("/health")))
+    }
+
+    fn server(origins: &[&str]) -> ServerConfig {
+        ServerConfig {
+            cors_origins: origins.iter().map(|o| o.to_string()).collect(),
+            ..ServerConfig::default()
+        }
+    }
+
+    /// Send a preflight request and return the Access-Control-Allow-Origin header
+    async fn preflight(router: &axum::Router, path: &str, origin: &str) -> Option<String> {
+        let response = router
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method(Method::OPTIONS)
+                    .uri(path)
+                    .header(header::ORIGIN, origin)
+                    .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        response
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .map(|v| v.to_str().unwrap().to_string())
+    }
+
+    #[tokio::test]
+    async fn test_preflight_multiple_origins() {
+        let router = cors_router(&server(&[
+            "https://app.example.com",
+            "https://staging.example.com",
+        ]));
+
+        for origin in ["https://app.example.com", "https://staging.example.com"] {
+            assert_eq!(
+                preflight(&router, "/data", origin).await.as_deref(),
+                Some(origin)
+            );
+        }
+        assert_eq!(preflight(&router, "/data", "https://evil.com").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_preflight_wildcard_subdomain() {
+        let router = cors_router(&server(&["https://*.example.com"]));
+
+        assert_eq!(
+            preflight(&router, "/styles", "https://maps.example.com")
+                .await
+                .as_deref(),
+            Some("https://maps.example.com")
+        );
+        assert_eq!(
+            preflight(&router, "/styles", "https://example.com.evil.com").await,
+            None
+        );
+        assert_eq!(
+            preflight(&router, "/styles", "http://maps.example.com").await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_preflight_any_origin() {
+        let router = cors_router(&server(&["*"]));
+        assert_eq!(
+            preflight(&router, "/health", "https://anywhere.test")
+                .await
+                .as_deref(),
+            Some("*")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_admin_cors_disabled_by_default() {
+        let router = cors_router(&server(&["*"]));
+        assert_eq!(
+            preflight(&router, "/admin", "https://anywhere.test").await,
+            None
+        );
+
+        let mut config = server(&["*"]);
+        config.cors_routes.insert(
+            ADMIN_GROUP.to_string(),
+            CorsRouteConfig {
+                origins: Some(vec!["https://ops.example.com".to_string()]),
+                ..CorsRouteConfig::default()
+            },
+        );
+        let router = cors_router(&config);
+        assert_eq!(
+            preflight(&router, "/admin", "https://ops.example.com")
+                .await
+                .as_deref(),
+            Some("https://ops.example.com")
+        );
+        assert_eq!(
+            preflight(&router, "/admin", "https://anywhere.test").await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_route_group_overrides() {
+        let config = ServerConfig {
+            cors_allow_credentials: true,
+            cors_expose_headers: vec!["X-Tile-Source".to_string()],
+            cors_routes: HashMap::from([(
+                "styles".to_string(),
+                CorsRouteConfig {
+                    enabled: Some(false),
+                    ..CorsRouteConfig::default()
+                },
+            )]),
+            ..server(&["https://*.example.com"])
+        };
+        let router = cors_router(&config);
+
+        assert_eq!(
+            preflight(&router, "/styles", "https://app.example.com").await,
+            None
+        );
+
+        // With credentials the allowed origin is echoed
+        let response = router
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/data")
+                    .header(header::ORIGIN, "https://app.example.com")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let headers = response.headers();
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example.com"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_EXPOSE_HEADERS],
+            "x-tile-source"
+        );
+    }
+
+    /// Serve `router` on a local port and return its base URL
+    async fn spawn_server(router: axum::Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    async fn http_preflight(url: &str, origin: &str, headers: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .request(reqwest::Method::OPTIONS, url)
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+            .header(header::ACCESS_CONTROL_REQUEST_HEADERS, headers)
+            .send()
+            .await
+            .expect("Preflight request should succeed")
+    }
+
+    #[tokio::test]
+    async fn test_http_preflight_headers() {
+        let base = spawn_server(cors_router(&server(&["https://app.example.com"]))).await;
+
+        for path in ["/data", "/styles", "/health"] {
+            let response = http_preflight(
+                &format!("{}{}", base, path),
+                "https://app.example.com",
+                "authorization,x-request-id",
+            )
+            .await;
+            assert_eq!(response.status(), 200, "{}", path);
+
+            let headers = response.headers();
+            assert_eq!(
+                headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+                "https://app.example.com"
+            );
+            assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "86400");
+            assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "false");
+            assert_eq!(
+                headers[header::ACCESS_CONTROL_ALLOW_METHODS],
+                "GET,POST,OPTIONS,HEAD"
+            );
+            assert_eq!(
+                headers[header::ACCESS_CONTROL_ALLOW_HEADERS],
+                "accept,authorization,content-type,if-range,range,x-request-id"
+            );
+        }
+
+        // Simple requests carry the credentials header too
+        let response = reqwest::Client::new()
+            .get(format!("{}/data", base))
+            .header(header::ORIGIN, "https://app.example.com")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_CREDENTIALS],
+            "false"
+        );
+
+        // Disallowed origins get no CORS headers at all
+        let response = http_preflight(&format!("{}/data", base), "https://evil.com", "range").await;
+        let headers = response.headers();
+        assert!(!headers.contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+        assert!(!headers.contains_key(header::ACCESS_CONTROL_ALLOW_CREDENTIALS));
+    }
+
+    #[tokio::test]
+    async fn test_http_preflight_admin_and_credentials() {
+        let mut config = ServerConfig {
+            cors_allow_credentials: true,
+            ..server(&["https://ops.example.com"])
+        };
+        config.cors_routes.insert(
+            ADMIN_GROUP.to_string(),
+            CorsRouteConfig {
+                allow_credentials: Some(false),
+                ..CorsRouteConfig::default()
+            },
+        );
+        let base = spawn_server(cors_router(&config)).await;
+
+        let response = http_preflight(
+            &format!("{}/admin", base),
+            "https://ops.example.com",
+            "x-admin-token",
+        )
+        .await;
+        let headers = response.headers();
+        assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "86400");
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "false");
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_METHODS],
+            "GET,POST,OPTIONS,HEAD,DELETE"
+        );
+        assert!(headers[header::ACCESS_CONTROL_ALLOW_HEADERS]
+            .to_str()
+            .unwrap()
+            .split(',')
+            .any(|h| h == "x-admin-token"));
+
+        // Credentialed policies send `true`
+        let response = http_preflight(
+            &format!("{}/data", base),
+            "https://ops.example.com",
+            "authorization",
+        )
+        .await;
+        let headers = response.headers();
+        assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "86400");
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+    }
+
+    #[test]
+    fn test_invalid_cors_config() {
+        assert!(Cors::from_config(&server(&["app.example.com"])).is_err());
+
+        let mut config = server(&[]);
+        config
+            .cors_routes
+            .insert("unknown".to_string(), CorsRouteConfig::default());
+        assert!(Cors::from_config(&config).is_err());
+
+        // Credentials need an explicit origin list
+        for origins in [&[][..], &["*"], &["https://app.example.com", "*"]] {
+            let config = ServerConfig {
+                cors_allow_credentials: true,
+                ..server(origins)
+            };
+            assert!(Cors::from_config(&config).is_err(), "{:?}", origins);
+        }
+        let mut config = ServerConfig {
+            cors_allow_credentials: true,
+            ..server(&["https://app.example.com"])
+        };
+        assert!(Cors::from_config(&config).is_ok());
+        config.cors_routes.insert(
+            "tiles".to_string(),
+            CorsRouteConfig {
+                origins: Some(vec!["*".to_string()]),
+                ..CorsRouteConfig::default()
+            },
+        );
+        assert!(Cors::from_config(&config).is_err());
+    }
+}
+
+// ============================================================
+// URL Parameter Tests
+// ============================================================
+
+mod url_params_tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_tile_z_x_y_parsing() {
+        // Valid tile coordinates
+        let valid_coords = [(0, 0, 0), (1, 0, 0), (1, 1, 1), (14, 8192, 8192)];
+
+        for (z, x, y) in valid_coords {
+            assert!(z <= 22, "Zoom should be <= 22");
+            let max_coord = 1u32 << z;
+            assert!(x < max_coord, "X should be < 2^z");
+            assert!(y < max_coord, "Y should be < 2^z");
+        }
+    }
+
+    #[test]
+    fn test_scale_factor_parsing() {
+        // Valid scale factors
+        let valid_scales = ["@1x", "@2x", "@3x", "@4x"];
+
+        for scale in valid_scales {
+            let num: u8 = scale
+                .trim_start_matches('@')
+                .trim_end_matches('x')
+                .parse()
+                .unwrap();
+            assert!((1..=4).contains(&num));
+        }
+    }
+
+    #[test]
+    fn test_static_image_size_parsing() {
+        // Valid size formats
+        let valid_sizes = ["800x600", "1920x1080", "256x256", "512x512@2x"];
+
+        for size in valid_sizes {
+            let size_part = size.split('@').next().unwrap();
+            let parts: Vec<&str> = size_part.split('x').collect();
+            assert_eq!(parts.len(), 2);
+
+            let width: u32 = parts[0].parse().unwrap();
+            let height: u32 = parts[1].parse().unwrap();
+            assert!(width > 0 && width <= 4096);
+            assert!(height > 0 && height <= 4096);
+        }
+    }
+
+    #[test]
+    fn test_static_type_parsing() {
+        use tileserver_rs::render::StaticType;
+
+        // Center format
+        let center = StaticType::from_str("-122.4,37.8,12").unwrap();
+        match center {
+            StaticType::Center { lon, lat, zoom, .. } => {
+                assert!((lon - (-122.4)).abs() < 0.01);
+                assert!((lat - 37.8).abs() < 0.01);
+                assert!((zoom - 12.0).abs() < 0.01);
+            }
+            _ => panic!("Expected Center"),
+        }
+
+        // Auto format
+        let auto = StaticType::from_str("auto").unwrap();
+        assert!(matches!(auto, StaticType::Auto));
+
+        // Bounding box format
+        let bbox = StaticType::from_str("-123,37,-122,38").unwrap();
+        match bbox {
+            StaticType::BoundingBox {
+                min_lon,
+                min_lat,
+                max_lon,
+                max_lat,
+            } => {
+                assert!((min_lon - (-123.0)).abs() < 0.01);
+                assert!((min_lat - 37.0).abs() < 0.01);
+                assert!((max_lon - (-122.0)).abs() < 0.01);
+                assert!((max_lat - 38.0).abs() < 0.01);
+            }
+            _ => panic!("Expected BoundingBox"),
+        }
+    }
+
+    #[test]
+    fn test_format_extensions() {
+        use tileserver_rs::render::ImageFormat;
+        use tileserver_rs::sources::TileFormat;
+
+        // Image formats
+        assert!(ImageFormat::from_str("png").is_ok());
+        assert!(ImageFormat::from_str("jpg").is_ok());
+        assert!(ImageFormat::from_str("jpeg").is_ok());
+        assert!(ImageFormat::from_str("webp").is_ok());
+        assert!(ImageFormat::from_str("gif").is_err()); // Not supported
+
+        // Tile formats
+        assert!(TileFormat::from_str("pbf").is_ok());
+        assert!(TileFormat::from_str("mvt").is_ok());
+        assert!(TileFormat::from_str("png").is_ok());
+    }
+}
+
+// ============================================================
+// Marker/Path Query Parameter Tests
+// ============================================================
+
+mod overlay_params_tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use tileserver_rs::render::overlay::{parse_marker, parse_path};
+
+    #[test]
+    fn test_marker_param_formats() {
+        // Valid marker formats
+        let valid_markers = [
+            "pin-s+f00(0,0)",
+            "pin-m+00ff00(-122.4,37.8)",
+            "pin-l-a+0000ff(10.5,20.5)",
+            "pin-s+ff0000(0,0)",
+        ];
+
+        for marker in valid_markers {
+            assert!(
+                parse_marker(marker).is_some(),
+                "Should parse marker: {}",
+                marker
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_marker_formats() {
+        // Invalid marker formats
+        let invalid_markers = [
+            "",
+            "pin",
+            "pin-x+f00(0,0)", // Invalid size
+            "marker(0,0)",    // Wrong prefix
+        ];
+
+        for marker in invalid_markers {
+            if !marker.is_empty() {
+                // Empty string might have different handling
+                let _result = parse_marker(marker);
+                // Invalid formats should return None or parse to defaults
+                // The actual behavior depends on implementation
+            }
+        }
+    }
+
+    #[test]
+    fn test_path_param_formats() {
+        // Valid path formats
+        let valid_paths = [
+            "path-5+f00(0,0|1,1)",
+            "path-3+00ff00(-122.4,37.8|-122.5,37.9)",
+            "path-2+0000ff-80ffffff(0,0|10,0|10,10|0,10|0,0)", // With fill
+        ];
+
+        for path in valid_paths {
+            assert!(parse_path(path).is_some(), "Should parse path: {}", path);
+        }
+    }
+
+    #[test]
+    fn test_encoded_polyline_format() {
+        // Test encoded polyline detection
+        let encoded = "_p~iF~ps|U_ulLnnqC_mqNvxq`@";
+
+        // This should be detected as a polyline (no commas, valid chars)
+        assert!(!encoded.contains(','));
+        assert!(encoded
+            .chars()
+            .all(|c| (c as u32) >= 63 && (c as u32) <= 126));
+    }
+}
+
+// ============================================================
+// Debug Render Option Tests
+// ============================================================
+
+mod debug_render_tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use axum::http::StatusCode;
+    use maplibre_native_sys::MLNDebugOptions;
+    use tileserver_rs::error::{ErrorCode, TileServerError};
+    use tileserver_rs::render::DebugOptions;
+
+    #[test]
+    fn test_debug_names_map_to_native_flags() {
+        let debug = DebugOptions::from_query(Some("tile-borders,collision"), true).unwrap();
+        assert_eq!(debug.bits(), 0b1001);
+        assert_eq!(
+            MLNDebugOptions::from(debug),
+            MLNDebugOptions::MLN_DEBUG_TILE_BORDERS | MLNDebugOptions::MLN_DEBUG_COLLISION
+        );
+
+        let all = "tile-borders,parse-status,timestamps,collision,overdraw";
+        let debug = DebugOptions::from_query(Some(all), true).unwrap();
+        assert_eq!(debug, DebugOptions::all());
+        assert_eq!(MLNDebugOptions::from(debug), MLNDebugOptions::all());
+
+        assert_eq!(
+            DebugOptions::from_query(None, false).unwrap(),
+            DebugOptions::empty()
+        );
+    }
+
+    #[test]
+    fn test_debug_requires_debug_endpoints() {
+        let err = DebugOptions::from_query(Some("tile-borders"), false).unwrap_err();
+        assert!(matches!(err, TileServerError::Forbidden(_)));
+        assert_eq!(err.code(), ErrorCode::Forbidden);
+        assert_eq!(err.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn test_unknown_debug_option_is_bad_request() {
+        let err = DebugOptions::from_query(Some("tile-borders,wireframe"), true).unwrap_err();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+        assert!(err.to_string().contains("wireframe"), "{}", err);
+    }
+}
+
+// ============================================================
+// Rotated and Tilted Tile Tests
+// ============================================================
+
+mod tile_view_tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use axum::http::StatusCode;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+    use tileserver_rs::render::{DebugOptions, ImageFormat, Renderer, TileView};
+
+    const STYLE: &str = r#"{"version":8,"sources":{},"layers":[]}"#;
+
+    async fn tile_hash(renderer: &Renderer, view: TileView) -> u64 {
+        let png = renderer
+            .render_tile(
+                STYLE,
+                3,
+                4,
+                2,
+                1,
+                ImageFormat::Png,
+                DebugOptions::empty(),
+                view,
+            )
+            .await
+            .expect("Should render the tile");
+        let pixels = image::load_from_memory(&png).unwrap().to_rgba8();
+        let mut hasher = DefaultHasher::new();
+        pixels.as_raw().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[tokio::test]
+    async fn test_rotated_tile_differs() {
+        let renderer = Renderer::new().expect("Should create renderer");
+        let north_up = tile_hash(&renderer, TileView::default()).await;
+        let rotated = TileView::from_query(Some(90.0), None).unwrap();
+
+        assert_ne!(tile_hash(&renderer, rotated).await, north_up);
+        // Unrotated requests still get the north-up tile
+        let flat = TileView::from_query(Some(0.0), Some(0.0)).unwrap();
+        assert_eq!(tile_hash(&renderer, flat).await, north_up);
+    }
+
+    #[test]
+    fn test_invalid_view_is_bad_request() {
+        let err = TileView::from_query(None, Some(89.0)).unwrap_err();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+        assert!(err.to_string().contains("pitch 89"), "{}", err);
+
+        let err = TileView::from_query(Some(400.0), None).unwrap_err();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+    }
+}
+
+// ============================================================
+// Static Image Pixel Ratio Tests
+// ============================================================
+
+mod pixel_ratio_tests {
+    #[allow(unused_imports)]
+    use super::*;
+    use axum::extract::Query;
+    use axum::http::{StatusCode, Uri};
+    use tileserver_rs::config::RenderConfig;
+    use tileserver_rs::render::{
+        output_size, parse_static_size, ImageFormat, RenderOptions, Renderer, StaticQueryParams,
+        StaticType,
+    };
+
+    const STYLE: &str = r#"{"version":8,"sources":{},"layers":[]}"#;
+
+    #[test]
+    fn test_fractional_pixel_ratio_parsing() {
+        assert_eq!(
+            parse_static_size("800x600@3.5x.png"),
+            Some((800, 600, 3.5, ImageFormat::Png))
+        );
+        assert_eq!(
+            parse_static_size("800x600@4x.webp"),
+            Some((800, 600, 4.0, ImageFormat::Webp))
+        );
+        assert_eq!(
+            parse_static_size("800x600.jpg"),
+            Some((800, 600, 1.0, ImageFormat::Jpeg))
+        );
+        assert_eq!(parse_static_size("800x600@x.png"), None);
+        assert_eq!(parse_static_size("800x600@2.png"), None);
+
+        let uri: Uri = "/static?pixelRatio=2.5&language=de".parse().unwrap();
+        let Query(query) = Query::<StaticQueryParams>::try_from_uri(&uri).unwrap();
+        assert_eq!(query.pixel_ratio, Some(2.5));
+
+        // Out of range ratios are refused along with the size
+        let static_type: StaticType = "0,0,1".parse().unwrap();
+        for ratio in [0.25, 8.5] {
+            let err = RenderOptions::for_static(
+                "test".into(),
+                STYLE.into(),
+                static_type.clone(),
+                800,
+                600,
+                ratio,
+                ImageFormat::Png,
+                StaticQueryParams::default(),
+            )
+            .unwrap_err();
+            assert!(err.contains("between 0.5 and 8"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_output_pixel_cap() {
+        let render = RenderConfig::default();
+        assert!(render.check_image_size(4096, 4096, 4.0).is_ok());
+
+        let err = render.check_image_size(4096, 3000, 6.5).unwrap_err();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+        assert!(
+            err.to_string()
+                .contains("26624x19500 pixels (519168000 pixels)"),
+            "{}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_image_size_is_rounded() {
+        let renderer = Renderer::new().expect("Should create renderer");
+        for (width, height, ratio) in [(101, 75, 3.5), (64, 48, 0.5), (100, 50, 2.0)] {
+            let options = RenderOptions::for_static(
+                "test".into(),
+                STYLE.into(),
+                "8.5,47.4,10".parse().unwrap(),
+                width,
+                height,
+                ratio,
+                ImageFormat::Png,
+                StaticQueryParams::default(),
+            )
+            .unwrap();
+            let png = renderer.render_static(options).await.unwrap();
+            let image = image::load_from_memory(&png).unwrap();
+
+            let expected = output_size(width, height, ratio);
+            assert_eq!((image.width(), image.height()), expected);
+            assert_eq!(
+                expected,
+                (
+                    (width as f32 * ratio).round() as u32,
+                    (height as f32 * ratio).round() as u32
+                )
+            );
+        }
+    }
+}
+
+// ============================================================
+// Mapbox Static Images Compatibility Tests
+// ============================================================
+
+mod mapbox_static_tests {
+    use super::*;
+    use tileserver_rs::mapbox_static::MapboxStaticRequest;
+    use tileserver_rs::{Config, ImageFormat, StyleManager};
+
+    /// Split a Mapbox Static Images URL into the route's path segments
+    fn route_segments(url: &str) -> (String, String, Option<String>, String, String) {
+        let path = url.split_once("/styles/v1/").unwrap().1;
+        let path = path.split_once('?').map_or(path, |(path, _)| path);
+        let segments: Vec<String> = path
+            .split('/')
+            .map(|s| urlencoding::decode(s).unwrap().into_owned())
+            .collect();
+        match &segments[..] {
+            [username, style, static_, position, size] if static_ == "static" => (
+                username.clone(),
+                style.clone(),
+                None,
+                position.clone(),
+                size.clone(),
+            ),
+            [username, style, static_, overlay, position, size] if static_ == "static" => (
+                username.clone(),
+                style.clone(),
+                Some(overlay.clone()),
+                position.clone(),
+                size.clone(),
+            ),
+            _ => panic!("Not a Mapbox Static Images URL: {}", url),
+        }
+    }
+
+    #[test]
+    fn test_compat_config() {
+        let config: Config = toml::from_str(
+            r#"
+            [compat]
+            mapbox_static = true
+            mapbox_username = "acme"
+            "#,
+        )
+        .expect("Should parse config");
+        assert!(config.compat.mapbox_static);
+        assert_eq!(config.compat.mapbox_username.as_deref(), Some("acme"));
+
+        let config = Config::load(Some(PathBuf::from(TEST_CONFIG))).unwrap();
+        assert!(!config.compat.mapbox_static, "Compat routes are opt-in");
+    }
+
+    #[test]
+    fn test_mapbox_urls_render_options() {
+        let config = Config::load(Some(PathBuf::from(TEST_CONFIG))).unwrap();
+        let styles = StyleManager::from_configs(&config.styles).expect("Should load styles");
+
+        // URLs from the Mapbox Static Images API documentation, pointed at a local style
+        let urls = [
+            "https://api.mapbox.com/styles/v1/mapbox/protomaps-light/static/-122.4241,37.78,14.25,0,60/600x600?access_token=pk",
+            "https://api.mapbox.com/styles/v1/mapbox/protomaps-light/static/[-77.043686,38.892035,-77.028923,38.904192]/400x400",
+            "https://api.mapbox.com/styles/v1/mapbox/protomaps-light/static/pin-s-l+000(-87.0186,32.4055)/-87.0186,32.4055,14/500x300@2x",
+            "https://api.mapbox.com/styles/v1/mapbox/protomaps-light/static/pin-s-a+9ed4bd(-122.46589,37.77343),pin-s-b+000(-122.42816,37.75965),path-5+f44-0.5(%7DrpeFxbnjVsFwdAvr@cHgFor@jEmAlFmEMwM_FuItCkOi@wc@bg@wBSgM)/auto/500x300?padding=50",
+            "https://api.mapbox.com/styles/v1/mapbox/protomaps-light/static/geojson(%7B%22type%22%3A%22Point%22%2C%22coordinates%22%3A%5B-73.99%2C40.7%5D%7D)/-73.99,40.70,12/500x300",
+        ];
+
+        for url in urls {
+            let (username, style_id, overlay, position, size) = route_segments(url);
+            assert_eq!(username, "mapbox");
+            let style = styles.get(&style_id).expect("Should find style");
+
+            let request = MapboxStaticRequest::parse(overlay.as_deref(), &position, &size)
+                .unwrap_or_else(|e| panic!("Should parse {}: {}", url, e));
+            let padding = url.split_once("padding=").map(|(_, p)| p);
+            let options = request
+                .render_options(style.id.clone(), style.style_json.to_string(), padding)
+                .unwrap_or_else(|e| panic!("Should build options for {}: {}", url, e));
+
+            assert_eq!(options.style_id, "protomaps-light");
+            assert_eq!(options.format, ImageFormat::Png);
+        }
+
+        // Center with bearing and pitch
+        let (_, _, overlay, position, size) = route_segments(urls[0]);
+        let options = MapboxStaticRequest::parse(overlay.as_deref(), &position, &size)
+            .unwrap()
+            .render_options("protomaps-light".to_string(), "{}".to_string(), None)
+            .unwrap();
+        assert_eq!(
+            (options.lon, options.lat, options.zoom),
+            (-122.4241, 37.78, 14.25)
+        );
+        assert_eq!((options.bearing, options.pitch), (0.0, 60.0));
+        assert_eq!(
+            (options.width, options.height, options.scale),
+            (600, 600, 1.0)
+        );
+
+        // Retina marker image
+        let (_, _, overlay, position, size) = route_segments(urls[2]);
+        let options = MapboxStaticRequest::parse(overlay.as_deref(), &position, &size)
+            .unwrap()
+            .render_options("protomaps-light".to_string(), "{}".to_string(), None)
+            .unwrap();
+        assert_eq!(options.scale, 2.0);
+        assert_eq!(options.markers.len(), 1);
+        assert_eq!(options.markers[0].label.as_deref(), Some("l"));
+
+        // Auto-fitted markers and path
+        let (_, _, overlay, position, size) = route_segments(urls[3]);
+        let options = MapboxStaticRequest::parse(overlay.as_deref(), &position, &size)
+            .unwrap()
+            .render_options("protomaps-light".to_string(), "{}".to_string(), Some("50"))
+            .unwrap();
+        assert_eq!(options.markers.len(), 2);
+        assert_eq!(options.paths.len(), 1);
+        assert!(options.lon > -122.5 && options.lon < -122.4);
+        assert!(options.lat > 37.7 && options.lat < 37.8);
+
+        // GeoJSON point becomes a marker
+        let (_, _, overlay, position, size) = route_segments(urls[4]);
+        let options = MapboxStaticRequest::parse(overlay.as_deref(), &position, &size)
+            .unwrap()
+            .render_options("protomaps-light".to_string(), "{}".to_string(), None)
+            .unwrap();
+        assert_eq!(options.markers[0].position.lon, -73.99);
+    }
+
+    #[test]
+    fn test_invalid_mapbox_urls() {
+        for (overlay, position, size) in [
+            (None, "-122.4,37.8,12", "600"),
+            (None, "-122.4,37.8", "600x400"),
+            (None, "auto", "600x400"),
+            (Some("pin-s(oops)"), "-122.4,37.8,12", "600x400"),
+            (Some("geojson({)"), "-122.4,37.8,12", "600x400"),
+        ] {
+            assert!(
+                MapboxStaticRequest::parse(overlay, position, size).is_err(),
+                "Should reject {:?} {} {}",
+                overlay,
+                position,
+                size
+            );
+        }
+
+        // Too large to render
+        let request = MapboxStaticRequest::parse(None, "0,0,1", "20000x20000").unwrap();
+        assert!(request
+            .render_options("protomaps-light".to_string(), "{}".to_string(), None)
+            .is_err());
+    }
+}
+
+// ============================================================
+// TileJSON Validation Tests
+// ============================================================
+
+mod tilejson_tests {
+    use super::*;
+    use tileserver_rs::{Config, SourceManager, TileJsonVersion};
+
+    #[tokio::test]
+    async fn test_tilejson_spec_compliance() {
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
+
+        for metadata in sources.all_metadata() {
+            let tilejson =
+                metadata.to_tilejson("http://localhost:8080", TileJsonVersion::default());
+
+            // TileJSON 3.0 required fields
+            assert_eq!(tilejson.tilejson, "3.0.0", "Should be TileJSON 3.0.0");
+            assert!(!tilejson.tiles.is_empty(), "tiles array required");
+
+            // Validate zoom levels
+            assert!(tilejson.minzoom <= 22, "minzoom should be <= 22");
+            assert!(tilejson.maxzoom <= 22, "maxzoom should be <= 22");
+            assert!(
+                tilejson.minzoom <= tilejson.maxzoom,
+                "minzoom should be <= maxzoom"
+            );
+
+            // Validate bounds if present
+            if let Some(bounds) = &tilejson.bounds {
+                assert_eq!(bounds.len(), 4, "bounds should have 4 values");
+                assert!(bounds[0] >= -180.0 && bounds[0] <= 180.0, "west bound");
+                assert!(bounds[1] >= -90.0 && bounds[1] <= 90.0, "south bound");
+                assert!(bounds[2] >= -180.0 && bounds[2] <= 180.0, "east bound");
+                assert!(bounds[3] >= -90.0 && bounds[3] <= 90.0, "north bound");
+            }
+
+            // Validate center if present
+            if let Some(center) = &tilejson.center {
+                assert!(center.len() >= 2, "center should have at least lon,lat");
+                assert!(
+                    center[0] >= -180.0 && center[0] <= 180.0,
+                    "center longitude"
+                );
+                assert!(center[1] >= -90.0 && center[1] <= 90.0, "center latitude");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_index_source_stats() {
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
+
+        for id in ["protomaps", "zurich"] {
+            let stats = sources.stats(id);
+            assert!(stats.tile_count.unwrap() > 0, "{} should count tiles", id);
+            let path = &config.sources.iter().find(|s| s.id == id).unwrap().path;
+            assert_eq!(
+                stats.size_bytes,
+                Some(std::fs::metadata(path).unwrap().len())
+            );
+        }
+        assert_eq!(sources.stats("missing"), Default::default());
+
+        // Plain TileJSON leaves the index-only fields out
+        let metadata = sources.get("zurich").unwrap().metadata();
+        let json = serde_json::to_value(
+            metadata.to_tilejson("http://localhost:8080", TileJsonVersion::default()),
+        )
+        .unwrap();
+        assert!(json.get("tile_count").is_none());
+        assert!(json.get("size_bytes").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_index_style_thumbnail() {
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
+        let styles =
+            tileserver_rs::StyleManager::from_configs(&config.styles).expect("Should load styles");
+        let style = styles.get("protomaps-light").unwrap();
+
+        let tilejson = style.raster_tilejson("http://localhost:8080", None, &sources);
+        assert!(serde_json::to_value(&tilejson)
+            .unwrap()
+            .get("thumbnail")
+            .is_none());
+
+        let [west, south, east, north] =
+            sources.get("protomaps").unwrap().metadata().bounds.unwrap();
+        assert_eq!(
+            tilejson.thumbnail_url("http://localhost:8080", &style.id, Some("abc")),
+            format!(
+                "http://localhost:8080/styles/protomaps-light/static/{},{},{},{}/256x160.png?key=abc",
+                west, south, east, north
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tilejson_v2_2() {
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
+        let metadata = sources
+            .get("zurich")
+            .expect("Should have zurich")
+            .metadata();
+        assert!(metadata.vector_layers.is_some());
+
+        let v3 = serde_json::to_value(
+            metadata.to_tilejson("http://localhost:8080", TileJsonVersion::V3_0),
+        )
+        .unwrap();
+        assert_eq!(v3["tilejson"], "3.0.0");
+        assert!(v3.get("vector_layers").is_some());
+        assert!(v3.get("scheme").is_none());
+
+        let v2 = serde_json::to_value(
+            metadata.to_tilejson("http://localhost:8080", TileJsonVersion::V2_2),
+        )
+        .unwrap();
+        assert_eq!(v2["tilejson"], "2.2.0");
+        assert_eq!(v2["scheme"], "xyz");
+        assert!(v2.get("vector_layers").is_none());
+        assert_eq!(v2["tiles"], v3["tiles"]);
+        assert_eq!(v2["minzoom"], v3["minzoom"]);
+        assert_eq!(v2["bounds"], v3["bounds"]);
+    }
+
+    #[test]
+    fn test_tilejson_version_query_values() {
+        let parse = |value: &str| serde_json::from_value::<TileJsonVersion>(value.into());
+
+        assert_eq!(parse("2.2.0").unwrap(), TileJsonVersion::V2_2);
+        assert_eq!(parse("2.2").unwrap(), TileJsonVersion::V2_2);
+        assert_eq!(parse("3.0.0").unwrap(), TileJsonVersion::V3_0);
+        assert_eq!(TileJsonVersion::default(), TileJsonVersion::V3_0);
+        assert!(parse("1.0.0").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tilejson_tiles_url_format() {
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
+
+        for metadata in sources.all_metadata() {
+            let tilejson =
+                metadata.to_tilejson("http://localhost:8080", TileJsonVersion::default());
+
+            for tile_url in &tilejson.tiles {
+                // Should contain placeholders
+                assert!(tile_url.contains("{z}"), "Should have {{z}} placeholder");
+                assert!(tile_url.contains("{x}"), "Should have {{x}} placeholder");
+                assert!(tile_url.contains("{y}"), "Should have {{y}} placeholder");
+
+                // Should be a valid URL
+                assert!(
+                    tile_url.starts_with("http://") || tile_url.starts_with("https://"),
+                    "Should be absolute URL"
+                );
+            }
+        }
+    }
+}
+
+// ============================================================
+// WMTS Tests
+// ============================================================
+
+mod wmts_tests {
+    use super::*;
+    use std::collections::HashMap;
+    use tileserver_rs::wmts::{self, WmtsRequest};
+    use tileserver_rs::{Config, SourceManager, StyleManager};
+
+    #[test]
+    fn test_wmts_xml_structure() {
+        // WMTS GetCapabilities should return valid XML
+        let expected_elements = [
+            "Capabilities",
+            "ServiceIdentification",
+            "Contents",
+            "Layer",
+            "TileMatrixSet",
+        ];
+
+        for element in expected_elements {
+            assert!(!element.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_wmts_tile_matrix_set() {
+        // WebMercator tile matrix set levels
+        for zoom in 0..=22 {
+            let scale_denominator = 559082264.028717 / (1 << zoom) as f64;
+            let tile_size = 256;
+            let matrix_width = 1 << zoom;
+            let matrix_height = 1 << zoom;
+
+            assert!(scale_denominator > 0.0);
+            assert_eq!(tile_size, 256);
+            assert!(matrix_width > 0);
+            assert_eq!(matrix_width, matrix_height);
+        }
+    }
+
+    #[test]
+    fn test_wmts_key_parameter_format() {
+        // Test that key parameter is properly formatted in WMTS URLs
+        let key = "my_api_key_123";
+        let expected_query = format!("?key={}", key);
+
+        assert!(expected_query.starts_with("?key="));
+        assert!(expected_query.contains(key));
+    }
+
+    #[test]
+    fn test_wmts_key_parameter_encoding() {
+        // Test URL encoding of special characters in key
+        let special_keys = [
+            ("simple_key", "simple_key"),
+            ("key with spaces", "key%20with%20spaces"),
+            ("key&value=test", "key%26value%3Dtest"),
+        ];
+
+        for (input, expected_encoded) in special_keys {
+            let encoded = urlencoding::encode(input);
+            assert_eq!(
+                encoded, expected_encoded,
+                "Key '{}' not encoded correctly",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_wmts_generates_without_key() {
+        use tileserver_rs::styles::raster::RasterExtent;
+        use tileserver_rs::wmts::generate_wmts_capabilities;
+
+        let xml = generate_wmts_capabilities(
+            "http://localhost:8080",
+            "test-style",
+            "Test Style",
+            &RasterExtent::default(),
+            None,
+        );
+
+        assert!(
+            !xml.contains("?key="),
+            "Should not have key param without key"
+        );
+        assert!(
+            xml.contains("wmts.xml\""),
+            "Should have WMTS URL without query params"
+        );
+    }
+
+    #[test]
+    fn test_wmts_generates_with_key() {
+        use tileserver_rs::styles::raster::RasterExtent;
+        use tileserver_rs::wmts::generate_wmts_capabilities;
+
+        let xml = generate_wmts_capabilities(
+            "http://localhost:8080",
+            "test-style",
+            "Test Style",
+            &RasterExtent::default(),
+            Some("my_secret_key"),
+        );
+
+        assert!(
+            xml.contains("?key=my_secret_key"),
+            "Should have key param in URLs"
+        );
+
+        // Key should appear in:
+        // 1. WMTS capabilities URL
+        assert!(xml.contains("wmts.xml?key=my_secret_key"));
+        // 2. Tile URLs for both 256 and 512 layers
+        assert!(xml.contains(".png?key=my_secret_key"));
+    }
+
+    #[test]
+    fn test_wmts_key_url_encoded() {
+        use tileserver_rs::styles::raster::RasterExtent;
+        use tileserver_rs::wmts::generate_wmts_capabilities;
+
+        let xml = generate_wmts_capabilities(
+            "http://localhost:8080",
+            "test-style",
+            "Test Style",
+            &RasterExtent::default(),
+            Some("key with spaces & symbols="),
+        );
+
+        // Key should be URL-encoded
+        assert!(xml.contains("?key=key%20with%20spaces%20%26%20symbols%3D"));
+    }
+
+    #[test]
+    fn test_wmts_key_in_all_urls() {
+        use tileserver_rs::styles::raster::RasterExtent;
+        use tileserver_rs::wmts::generate_wmts_capabilities;
+
+        let xml = generate_wmts_capabilities(
+            "http://localhost:8080",
+            "osm-bright",
+            "OSM Bright",
+            &RasterExtent::default(),
+            Some("test_key"),
+        );
+
+        // Count occurrences of key parameter
+        let key_count = xml.matches("?key=test_key").count();
+
+        // Should appear in:
+        // - 2x in OperationsMetadata (GetCapabilities + GetTile hrefs)
+        // - 2x in ResourceURL templates (256px + 512px layers)
+        // - 1x in ServiceMetadataURL
+        // Total: 5 occurrences
+        assert!(
+            key_count >= 4,
+            "Key should appear in multiple URLs, found {} occurrences",
+            key_count
+        );
+    }
+
+    /// Parse a URL query string into KVP parameters
+    fn query(url: &str) -> HashMap<String, String> {
+        let (_, query) = url.split_once('?').expect("URL should have a query");
+        query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(k, v)| (k.to_string(), urlencoding::decode(v).unwrap().into_owned()))
+            .collect()
+    }
+
+    fn styles() -> StyleManager {
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        StyleManager::from_configs(&config.styles).expect("Should load styles")
+    }
+
+    #[test]
+    fn test_kvp_get_tile_returns_png() {
+        let styles = styles();
+        let params = query(
+            "http://localhost:8080/wmts?SERVICE=WMTS&REQUEST=GetTile&VERSION=1.0.0\
+             &LAYER=protomaps-light-256&STYLE=default&TILEMATRIXSET=GoogleMapsCompatible_256\
+             &TILEMATRIX=12&TILEROW=1497&TILECOL=2175&FORMAT=image%2Fpng",
+        );
+
+        let WmtsRequest::GetTile(tile) = WmtsRequest::from_kvp(&params).unwrap() else {
+            panic!("Expected a GetTile request");
+        };
+        assert_eq!((tile.z, tile.x, tile.y), (12, 2175, 1497));
+        assert_eq!(tile.format.content_type(), "image/png");
+
+        let (style, tile_size) =
+            wmts::resolve_layer(&styles, &tile.layer, tile.tile_matrix_set.as_deref()).unwrap();
+        assert_eq!(style.id, "protomaps-light");
+        assert_eq!(tile_size, 256);
+
+        // 512px layers resolve to the @2x tile matrix set
+        let (_, tile_size) = wmts::resolve_layer(
+            &styles,
+            "protomaps-light-512",
+            Some("GoogleMapsCompatible_512"),
+        )
+        .unwrap();
+        assert_eq!(tile_size, 512);
+    }
+
+    #[test]
+    fn test_kvp_invalid_layer_returns_exception_report() {
+        let styles = styles();
+        let params = query(
+            "http://localhost:8080/wmts?SERVICE=WMTS&REQUEST=GetTile&LAYER=nonexistent-256\
+             &TILEMATRIXSET=GoogleMapsCompatible_256&TILEMATRIX=0&TILEROW=0&TILECOL=0\
+             &FORMAT=image/png",
+        );
+
+        let WmtsRequest::GetTile(tile) = WmtsRequest::from_kvp(&params).unwrap() else {
+            panic!("Expected a GetTile request");
+        };
+        let err =
+            wmts::resolve_layer(&styles, &tile.layer, tile.tile_matrix_set.as_deref()).unwrap_err();
+
+        assert_eq!(err.status().as_u16(), 400);
+        let xml = err.to_xml();
+        assert!(xml.contains("<ows:ExceptionReport"));
+        assert!(xml.contains(r#"exceptionCode="InvalidParameterValue""#));
+        assert!(xml.contains(r#"locator="LAYER""#));
+
+        // A matrix set that does not belong to the layer is rejected too
+        let err = wmts::resolve_layer(
+            &styles,
+            "protomaps-light-256",
+            Some("GoogleMapsCompatible_512"),
+        )
+        .unwrap_err();
+        assert_eq!(err.locator.as_deref(), Some("TILEMATRIXSET"));
+    }
+
+    #[tokio::test]
+    async fn test_kvp_get_feature_info_queries_vector_source() {
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
+        let styles = styles();
+
+        // Pixel over Florence, which the protomaps sample covers
+        let params = query(
+            "http://localhost:8080/wmts?SERVICE=WMTS&REQUEST=GetFeatureInfo\
+             &LAYER=protomaps-light&TILEMATRIX=0&TILEROW=0&TILECOL=0\
+             &I=136&J=93&INFOFORMAT=application/json",
+        );
+        let WmtsRequest::GetFeatureInfo { tile, i, j } = WmtsRequest::from_kvp(&params).unwrap()
+        else {
+            panic!("Expected a GetFeatureInfo request");
+        };
+
+        let (style, tile_size) = wmts::resolve_layer(&styles, &tile.layer, None).unwrap();
+        assert_eq!(style.data_source_ids(), vec!["protomaps".to_string()]);
+
+        let source = sources.get("protomaps").expect("Should have protomaps");
+        let features = wmts::query_point(source.as_ref(), &tile, i, j, tile_size)
+            .await
+            .unwrap();
+        assert!(!features.is_empty(), "Should find features under the pixel");
+        assert!(features
+            .iter()
+            .all(|f| f["properties"]["_layer"].is_string()));
+
+        // Only features near the pixel are returned, not the whole tile
+        let whole_tile = source.get_tile(0, 0, 0).await.unwrap().unwrap();
+        let total: usize = whole_tile
+            .decode_mvt()
+            .unwrap()
+            .layers
+            .iter()
+            .map(|l| l.features.len())
+            .sum();
+        assert!(features.len() < total);
+    }
+}
+
+// ============================================================
+// API Key Parameter Tests
+// ============================================================
+
+mod key_param_tests {
+    use super::*;
+    use tileserver_rs::{Config, SourceManager, TileJsonVersion};
+
+    #[tokio::test]
+    async fn test_tilejson_with_key() {
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
+
+        let all_metadata = sources.all_metadata();
+        let metadata = all_metadata.first().expect("Should have a source");
+
+        // Without key
+        let tilejson_no_key =
+            metadata.to_tilejson("http://localhost:8080", TileJsonVersion::default());
+        assert!(
+            !tilejson_no_key.tiles[0].contains("?key="),
+            "Should not have key param without key"
+        );
+
+        // With key
+        let tilejson_with_key = metadata.to_tilejson_with_key(
+            "http://localhost:8080",
+            Some("my_api_key"),
+            TileJsonVersion::default(),
+        );
+        assert!(
+            tilejson_with_key.tiles[0].contains("?key=my_api_key"),
+            "Should have key param in tile URL"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tilejson_key_url_encoding() {
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
+
+        let all_metadata = sources.all_metadata();
+        let metadata = all_metadata.first().expect("Should have a source");
+
+        // Key with special characters should be URL-encoded
+        let tilejson = metadata.to_tilejson_with_key(
+            "http://localhost:8080",
+            Some("key with spaces & symbols="),
+            TileJsonVersion::default(),
+        );
+
+        assert!(
+            tilejson.tiles[0].contains("key%20with%20spaces%20%26%20symbols%3D"),
+            "Key should be URL-encoded in tile URL: {}",
+            tilejson.tiles[0]
+        );
+    }
+
+    #[test]
+    fn test_style_info_with_key() {
+        use std::path::PathBuf;
+        use tileserver_rs::styles::Style;
+
+        let style = Style {
+            id: "osm-bright".to_string(),
+            name: "OSM Bright".to_string(),
+            style_json: serde_json::json!({"version": 8}),
+            path: PathBuf::from("data/styles/protomaps-light/style.json"),
+            raster: Default::default(),
+        };
+
+        // Without key
+        let info_no_key = style.to_info("http://localhost:8080");
+        assert!(
+            !info_no_key.url.as_ref().unwrap().contains("?key="),
+            "Should not have key param without key"
+        );
+
+        // With key
+        let info_with_key = style.to_info_with_key("http://localhost:8080", Some("my_api_key"));
+        assert!(
+            info_with_key
+                .url
+                .as_ref()
+                .unwrap()
+                .contains("?key=my_api_key"),
+            "Should have key param in style URL"
+        );
+    }
+
+    #[test]
+    fn test_style_info_key_url_encoding() {
+        use std::path::PathBuf;
+        use tileserver_rs::styles::Style;
+
+        let style = Style {
+            id: "test-style".to_string(),
+            name: "Test Style".to_string(),
+            style_json: serde_json::json!({"version": 8}),
+            path: PathBuf::from("data/styles/test/style.json"),
+            raster: Default::default(),
+        };
+
+        // Key with special characters should be URL-encoded
+        let info =
+            style.to_info_with_key("http://localhost:8080", Some("key with spaces & symbols="));
+
+        assert!(
+            info.url
+                .as_ref()
+                .unwrap()
+                .contains("key%20with%20spaces%20%26%20symbols%3D"),
+            "Key should be URL-encoded in style URL: {}",
+            info.url.as_ref().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_rewrite_style_for_api_with_key() {
+        use tileserver_rs::styles::{rewrite_style_for_api, UrlQueryParams};
+
+        let style = serde_json::json!({
+            "version": 8,
+            "sources": {
+                "openmaptiles": {
+                    "type": "vector",
+                    "url": "/data/openmaptiles.json"
+                }
+            },
+            "glyphs": "/fonts/{fontstack}/{range}.pbf",
+            "sprite": "/styles/basic/sprite"
+        });
+
+        // With key
+        let params = UrlQueryParams::with_key(Some("my_secret_key".to_string()));
+        let result = rewrite_style_for_api(&style, "http://tiles.example.com", &params);
+
+        // All URLs should have key appended
+        assert_eq!(
+            result["sources"]["openmaptiles"]["url"],
+            "http://tiles.example.com/data/openmaptiles.json?key=my_secret_key"
+        );
+        assert_eq!(
+            result["glyphs"],
+            "http://tiles.example.com/fonts/{fontstack}/{range}.pbf?key=my_secret_key"
+        );
+        assert_eq!(
+            result["sprite"],
+            "http://tiles.example.com/styles/basic/sprite?key=my_secret_key"
+        );
+    }
+
+    #[test]
+    fn test_rewrite_style_for_api_preserves_external_urls() {
+        use tileserver_rs::styles::{rewrite_style_for_api, UrlQueryParams};
+
+        let style = serde_json::json!({
+            "version": 8,
+            "sources": {
+                "external": {
+                    "type": "vector",
+                    "url": "https://external-tiles.com/tiles.json"
+                }
+            },
+            "glyphs": "https://fonts.example.com/{fontstack}/{range}.pbf"
+        });
+
+        // With key - external URLs should NOT have key appended
+        let params = UrlQueryParams::with_key(Some("my_key".to_string()));
+        let result = rewrite_style_for_api(&style, "http://localhost", &params);
+
+        // External URLs should remain unchanged
+        assert_eq!(
+            result["sources"]["external"]["url"],
+            "https://external-tiles.com/tiles.json"
+        );
+        assert_eq!(
+            result["glyphs"],
+            "https://fonts.example.com/{fontstack}/{range}.pbf"
+        );
+    }
+
+    #[test]
+    fn test_key_query_string_generation() {
+        use tileserver_rs::styles::UrlQueryParams;
+
+        // Empty params
+        let empty = UrlQueryParams::default();
+        assert_eq!(empty.to_query_string(), "");
+
+        // With key
+        let with_key = UrlQueryParams::with_key(Some("abc123".to_string()));
+        assert_eq!(with_key.to_query_string(), "?key=abc123");
+
+        // With key and extra params
+        let with_extra = UrlQueryParams {
+            key: Some("key1".to_string()),
+            extra: vec![("foo".to_string(), "bar".to_string())],
+        };
+        assert_eq!(with_extra.to_query_string(), "?key=key1&foo=bar");
+    }
+}
+
+// ============================================================
+// Config Validation Tests
+// ============================================================
+
+mod config_tests {
+    use super::*;
+    use tileserver_rs::Config;
+
+    #[test]
+    fn test_config_loads() {
+        let config = Config::load(Some(PathBuf::from(TEST_CONFIG)));
+        assert!(config.is_ok(), "Should load test config");
+    }
+
+    #[test]
+    fn test_config_has_server_section() {
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        // Port defaults to 8080 if not specified
+        // Host defaults to "0.0.0.0" if not specified
+        assert!(!config.server.host.is_empty());
+    }
+
+    #[test]
+    fn test_config_sources_valid() {
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+
+        for source in &config.sources {
+            assert!(!source.id.is_empty(), "Source should have ID");
+            // Path should exist or be a URL
+            if !source.path.starts_with("http") {
+                assert!(
+                    PathBuf::from(&source.path).exists(),
+                    "Source path should exist: {}",
+                    source.path
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_config_styles_valid() {
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+
+        for style in &config.styles {
+            assert!(!style.id.is_empty(), "Style should have ID");
+            assert!(
+                style.path.exists(),
+                "Style path should exist: {}",
+                style.path.display()
+            );
         }
     }
 
     #[test]
-    fn test_font_range_parsing() {
-        // Test that font ranges are parsed correctly
-        let valid_ranges = ["0-255.pbf", "256-511.pbf", "65024-65279.pbf"];
-
-        for range in valid_ranges {
-            let parts: Vec<&str> = range.trim_end_matches(".pbf").split('-').collect();
-            assert_eq!(parts.len(), 2, "Range should have start and end: {}", range);
+    fn test_config_cors_settings() {
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
 
-            let start: u32 = parts[0].parse().expect("Start should be a number");
-            let end: u32 = parts[1].parse().expect("End should be a number");
-            assert!(start < end, "Start should be less than end");
-            assert_eq!((end - start + 1), 256, "Range should span 256 characters");
-        }
+        // CORS origins should be defined
+        assert!(
+            !config.server.cors_origins.is_empty(),
+            "Should have CORS origins"
+        );
     }
 }
 
 // ============================================================
-// Static Files Endpoint Tests
+// Admin API Tests
 // ============================================================
 
-mod files_tests {
-    #[allow(unused_imports)]
+mod admin_tests {
     use super::*;
+    use axum::body::Body;
+    use axum::http::{Method, Request, StatusCode};
+    use std::sync::Arc;
+    use tileserver_rs::admin::{self, AdminState};
+    use tileserver_rs::config::SourceConfig;
+    use tileserver_rs::events::EventBus;
+    use tileserver_rs::{Config, SharedSources, SharedStyles, SourceManager, StyleManager};
+    use tower::ServiceExt;
+
+    const TOKEN: &str = "test-admin-token";
+
+    async fn admin_router(configs: Vec<SourceConfig>) -> axum::Router {
+        let sources = SourceManager::from_configs(&configs)
+            .await
+            .expect("Should load sources");
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        let styles = StyleManager::from_configs(&config.styles).expect("Should load styles");
 
-    #[test]
-    fn test_files_path_security() {
-        // Test patterns that should be detected as dangerous
-        let dangerous_patterns = [
-            ("../../../etc/passwd", ".."),
-            ("..\\..\\windows\\system32", ".."),
-            ("/etc/passwd", "absolute"),
-            ("C:\\Windows\\System32", "windows_drive"),
-            ("....//....//etc/passwd", ".."),
-        ];
+        admin::router(AdminState {
+            sources: SharedSources::new(sources),
+            styles: SharedStyles::new(styles),
+            source_configs: Arc::new(configs),
+            token: TOKEN.into(),
+            events: EventBus::new(),
+        })
+    }
 
-        for (path, reason) in dangerous_patterns {
-            let is_dangerous = path.contains("..")
-                || path.starts_with('/')
-                || (path.len() >= 2 && path.chars().nth(1) == Some(':'));
-            assert!(
-                is_dangerous,
-                "Path should be detected as dangerous ({}): {}",
-                reason, path
-            );
+    async fn call(
+        router: &axum::Router,
+        method: Method,
+        uri: &str,
+        token: Option<&str>,
+    ) -> (StatusCode, serde_json::Value) {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            request = request.header(admin::ADMIN_TOKEN_HEADER, token);
         }
+        let response = router
+            .clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (
+            status,
+            serde_json::from_slice(&body).expect("Should be JSON"),
+        )
     }
 
-    #[test]
-    fn test_url_encoded_path_traversal() {
-        // URL-encoded traversal should be decoded and detected by the server
-        let encoded_traversals = [
-            "%2e%2e%2f", // ../
-            "%2e%2e/",   // ../
-            "..%2f",     // ../
-        ];
+    fn test_sources() -> Vec<SourceConfig> {
+        Config::load(Some(PathBuf::from(TEST_CONFIG)))
+            .expect("Should load test config")
+            .sources
+    }
 
-        for encoded in encoded_traversals {
-            // After URL decoding, these should contain ".."
-            let decoded = urlencoding::decode(encoded).unwrap_or_default();
-            assert!(
-                decoded.contains("..") || encoded.contains("%2e"),
-                "Encoded traversal should be detectable: {}",
-                encoded
-            );
+    #[tokio::test]
+    async fn test_admin_requires_token() {
+        let router = admin_router(test_sources()).await;
+
+        for (method, uri) in [
+            (Method::GET, "/admin/sources"),
+            (Method::GET, "/admin/styles"),
+            (Method::GET, "/admin/cache"),
+            (Method::DELETE, "/admin/cache"),
+            (Method::POST, "/admin/sources/protomaps/reload"),
+        ] {
+            let (status, body) = call(&router, method.clone(), uri, None).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{} {}", method, uri);
+            assert_eq!(body["error"]["code"], "UNAUTHORIZED");
+
+            let (status, body) = call(&router, method.clone(), uri, Some("wrong")).await;
+            assert_eq!(status, StatusCode::FORBIDDEN, "{} {}", method, uri);
+            assert_eq!(body["error"]["code"], "FORBIDDEN");
         }
+
+        let (status, _) = call(&router, Method::GET, "/admin/sources", Some(TOKEN)).await;
+        assert_eq!(status, StatusCode::OK);
     }
 
-    #[test]
-    fn test_valid_file_paths() {
-        let valid_paths = [
-            "image.png",
-            "data/file.json",
-            "assets/icons/marker.svg",
-            "some-file_name.txt",
-        ];
+    #[tokio::test]
+    async fn test_admin_sources_detail() {
+        let router = admin_router(test_sources()).await;
+        let (status, body) = call(&router, Method::GET, "/admin/sources", Some(TOKEN)).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let sources = body.as_array().unwrap();
+        let protomaps = sources.iter().find(|s| s["id"] == "protomaps").unwrap();
+        assert_eq!(protomaps["status"], "ok");
+        assert_eq!(protomaps["backend"], "pmtiles");
+        assert_eq!(protomaps["reloadable"], true);
+        let size = std::fs::metadata("data/tiles/protomaps-sample.pmtiles")
+            .unwrap()
+            .len();
+        assert_eq!(protomaps["file_size_bytes"], size);
+        assert!(protomaps["tile_count"].as_u64().unwrap() > 0);
+
+        let zurich = sources.iter().find(|s| s["id"] == "zurich").unwrap();
+        assert_eq!(zurich["backend"], "mbtiles");
+        assert!(zurich["tile_count"].as_u64().unwrap() > 0);
+
+        let (status, body) = call(&router, Method::GET, "/admin/styles", Some(TOKEN)).await;
+        assert_eq!(status, StatusCode::OK);
+        let style = &body.as_array().unwrap()[0];
+        assert_eq!(style["id"], "protomaps-light");
+        assert!(style["loaded_at"].as_u64().unwrap() > 0);
+
+        // No cache configured
+        let (status, body) = call(&router, Method::GET, "/admin/cache", Some(TOKEN)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body["tile_cache"].is_null());
+        let (status, _) = call(&router, Method::DELETE, "/admin/cache", Some(TOKEN)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
 
-        for path in valid_paths {
-            assert!(
-                !path.contains(".."),
-                "Valid path should not have ..: {}",
-                path
-            );
-            assert!(
-                !path.starts_with('/'),
-                "Valid path should not start with /: {}",
-                path
-            );
-        }
+    #[tokio::test]
+    async fn test_admin_reload_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("florence.pmtiles");
+        let config = SourceConfig {
+            id: "florence".to_string(),
+            path: path.to_string_lossy().to_string(),
+            ..test_sources()[0].clone()
+        };
+        let router = admin_router(vec![config]).await;
+
+        // The file is missing at startup
+        let (_, body) = call(&router, Method::GET, "/admin/sources", Some(TOKEN)).await;
+        assert_eq!(body[0]["id"], "florence");
+        assert_eq!(body[0]["status"], "error");
+
+        // Reloading while it is still missing fails and changes nothing
+        let (status, body) = call(
+            &router,
+            Method::POST,
+            "/admin/sources/florence/reload",
+            Some(TOKEN),
+        )
+        .await;
+        assert!(status.is_server_error());
+        assert!(body["error"]["message"].is_string());
+
+        // Once the file appears, a reload brings the source up
+        std::fs::copy("data/tiles/protomaps-sample.pmtiles", &path).unwrap();
+        let (status, body) = call(
+            &router,
+            Method::POST,
+            "/admin/sources/florence/reload",
+            Some(TOKEN),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "ok");
+        assert_eq!(body["backend"], "pmtiles");
+
+        let (_, body) = call(&router, Method::GET, "/admin/sources", Some(TOKEN)).await;
+        let sources = body.as_array().unwrap();
+        assert_eq!(sources.len(), 1);
+        assert_eq!(sources[0]["status"], "ok");
+        assert!(sources[0].get("error").is_none());
+
+        let (status, body) = call(
+            &router,
+            Method::POST,
+            "/admin/sources/unknown/reload",
+            Some(TOKEN),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"]["code"], "SOURCE_NOT_FOUND");
+        assert_eq!(body["error"]["details"]["source"], "unknown");
     }
 }
 
 // ============================================================
-// Content-Type Tests
+// Server-Sent Events Tests
 // ============================================================
 
-mod content_type_tests {
-    #[allow(unused_imports)]
+mod events_tests {
     use super::*;
+    use axum::body::{Body, BodyDataStream};
+    use axum::http::{header, Method, Request, StatusCode};
+    use futures::StreamExt;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tileserver_rs::admin::{self, AdminState};
+    use tileserver_rs::events::{self, EventBus};
+    use tileserver_rs::{Config, SharedSources, SharedStyles, SourceManager, StyleManager};
+    use tower::ServiceExt;
+
+    const TOKEN: &str = "test-admin-token";
+
+    async fn admin_router(events: EventBus) -> axum::Router {
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
+        let styles = StyleManager::from_configs(&config.styles).expect("Should load styles");
 
-    #[test]
-    fn test_tile_content_types() {
-        let content_types = [
-            ("pbf", "application/x-protobuf"),
-            ("mvt", "application/x-protobuf"),
-            ("png", "image/png"),
-            ("jpg", "image/jpeg"),
-            ("jpeg", "image/jpeg"),
-            ("webp", "image/webp"),
-        ];
+        admin::router(AdminState {
+            sources: SharedSources::new(sources),
+            styles: SharedStyles::new(styles),
+            source_configs: Arc::new(config.sources),
+            token: TOKEN.into(),
+            events,
+        })
+    }
 
-        for (ext, expected_type) in content_types {
-            // This would be tested through the TileFormat enum
-            use std::str::FromStr;
-            use tileserver_rs::sources::TileFormat;
+    async fn admin_call(router: &axum::Router, method: Method, uri: &str) -> StatusCode {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(admin::ADMIN_TOKEN_HEADER, TOKEN)
+            .body(Body::empty())
+            .unwrap();
+        router.clone().oneshot(request).await.unwrap().status()
+    }
 
-            let format = TileFormat::from_str(ext).unwrap();
-            assert_eq!(
-                format.content_type(),
-                expected_type,
-                "Wrong content type for {}",
-                ext
-            );
-        }
+    async fn subscribe(events: &EventBus) -> BodyDataStream {
+        let router: axum::Router = events::router(events.clone());
+        let response = router
+            .oneshot(Request::get("/events").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            "text/event-stream"
+        );
+        response.into_body().into_data_stream()
     }
 
-    #[test]
-    fn test_image_content_types() {
-        use std::str::FromStr;
-        use tileserver_rs::render::ImageFormat;
+    /// Read the stream until an event named `name` arrives, returning its data
+    async fn next_event(stream: &mut BodyDataStream, name: &str) -> serde_json::Value {
+        let mut buffer = String::new();
+        let read = async {
+            while let Some(chunk) = stream.next().await {
+                buffer.push_str(std::str::from_utf8(&chunk.unwrap()).unwrap());
+                while let Some(end) = buffer.find("\n\n") {
+                    let message: String = buffer.drain(..end + 2).collect();
+                    let field = |key: &str| {
+                        message
+                            .lines()
+                            .find_map(|line| line.strip_prefix(key))
+                            .map(str::to_string)
+                    };
+                    if field("event: ").as_deref() == Some(name) {
+                        return serde_json::from_str(&field("data: ").unwrap()).unwrap();
+                    }
+                }
+            }
+            panic!("Event stream ended");
+        };
+        tokio::time::timeout(Duration::from_secs(2), read)
+            .await
+            .unwrap_or_else(|_| panic!("Should receive {} within 2 seconds", name))
+    }
 
-        let formats = [
-            ("png", "image/png"),
-            ("jpg", "image/jpeg"),
-            ("jpeg", "image/jpeg"),
-            ("webp", "image/webp"),
-        ];
+    #[tokio::test]
+    async fn test_source_reload_event() {
+        let events = EventBus::new();
+        let admin = admin_router(events.clone()).await;
+        let mut stream = subscribe(&events).await;
 
-        for (ext, expected_type) in formats {
-            let format = ImageFormat::from_str(ext).unwrap();
-            assert_eq!(
-                format.content_type(),
-                expected_type,
-                "Wrong content type for {}",
-                ext
-            );
-        }
+        let status = admin_call(&admin, Method::POST, "/admin/sources/zurich/reload").await;
+        assert_eq!(status, StatusCode::OK);
+
+        let event = next_event(&mut stream, "source_reloaded").await;
+        assert_eq!(
+            event,
+            serde_json::json!({"type": "source_reloaded", "source": "zurich"})
+        );
     }
 
-    #[test]
-    fn test_json_endpoints_content_type() {
-        // JSON endpoints should return application/json
-        let json_endpoints = [
-            "/health", // Returns text/plain
-            "/index.json",
-            "/data.json",
-            "/styles.json",
-            "/fonts.json",
-            "/openapi.json",
-        ];
+    #[tokio::test]
+    async fn test_tile_invalidated_event() {
+        let events = EventBus::new();
+        let admin = admin_router(events.clone()).await;
+        let mut stream = subscribe(&events).await;
+
+        let status = admin_call(
+            &admin,
+            Method::DELETE,
+            "/admin/sources/protomaps/tiles/4/8/5",
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let event = next_event(&mut stream, "tile_invalidated").await;
+        assert_eq!(
+            event,
+            serde_json::json!({
+                "type": "tile_invalidated",
+                "source": "protomaps",
+                "z": 4,
+                "x": 8,
+                "y": 5
+            })
+        );
 
-        // Verify endpoints that should return JSON
-        for endpoint in &json_endpoints[1..] {
-            assert!(
-                endpoint.ends_with(".json"),
-                "JSON endpoint should end with .json: {}",
-                endpoint
-            );
-        }
+        let status = admin_call(
+            &admin,
+            Method::DELETE,
+            "/admin/sources/protomaps/tiles/4/16/0",
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let status = admin_call(&admin, Method::DELETE, "/admin/sources/unknown/tiles/0/0/0").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
     }
 }
 
 // ============================================================
-// Cache Control Tests
+// TLS Tests
 // ============================================================
 
-mod cache_control_tests {
-    #[allow(unused_imports)]
+mod tls_tests {
     use super::*;
-
-    #[test]
-    fn test_tile_cache_values() {
-        // Tiles should have long cache times
-        let tile_cache = "public, max-age=86400"; // 1 day
-        assert!(tile_cache.contains("public"));
-        assert!(tile_cache.contains("max-age"));
+    use axum::routing::get;
+    use tileserver_rs::config::{ServerConfig, TlsConfig};
+    use tileserver_rs::connections::ConnectionLimits;
+    use tileserver_rs::styles::{rewrite_style_for_api, UrlQueryParams};
+    use tileserver_rs::{health, tls};
+
+    /// Self-signed `localhost` certificate (openssl req -x509, EC P-256, 100 years)
+    fn tls_config() -> TlsConfig {
+        TlsConfig {
+            cert: PathBuf::from("tests/fixtures/tls/cert.pem"),
+            key: PathBuf::from("tests/fixtures/tls/key.pem"),
+            redirect_http_port: None,
+            watch_interval_secs: 0,
+        }
     }
 
-    #[test]
-    fn test_metadata_cache_values() {
-        // Metadata (TileJSON, style.json) should have shorter cache
-        let metadata_cache = "public, max-age=300"; // 5 minutes
-        assert!(metadata_cache.contains("max-age=300"));
-    }
+    #[tokio::test]
+    async fn test_health_over_tls() {
+        let rustls = tls::load(&tls_config()).await.expect("Should load cert");
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let router = axum::Router::new().route("/health", get(health::liveness));
+        let handle = tls::Handle::new();
+        let server = tokio::spawn(tls::serve(
+            listener,
+            router,
+            rustls,
+            ConnectionLimits::default(),
+            handle.clone(),
+        ));
+
+        let cert = std::fs::read("tests/fixtures/tls/cert.pem").unwrap();
+        let client = reqwest::Client::builder()
+            .tls_certs_only([reqwest::Certificate::from_pem(&cert).unwrap()])
+            .build()
+            .unwrap();
+        let response = client
+            .get(format!("https://localhost:{}/health", port))
+            .send()
+            .await
+            .expect("TLS request should succeed");
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.text().await.unwrap(), "OK");
 
-    #[test]
-    fn test_static_assets_cache() {
-        // Static assets (fonts, sprites) should have long cache
-        let static_cache = "public, max-age=604800"; // 1 week
-        assert!(static_cache.contains("max-age=604800"));
+        // Plain HTTP is not accepted on the TLS port
+        assert!(reqwest::get(format!("http://localhost:{}/health", port))
+            .await
+            .map(|r| !r.status().is_success())
+            .unwrap_or(true));
+
+        handle.shutdown();
+        server.await.unwrap().unwrap();
     }
-}
 
-// ============================================================
-// Error Response Tests
-// ============================================================
+    #[tokio::test]
+    async fn test_connection_limits_over_tls() {
+        let rustls = tls::load(&tls_config()).await.expect("Should load cert");
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let router = axum::Router::new().route(
+            "/peer",
+            get(
+                |axum::extract::ConnectInfo(peer): axum::extract::ConnectInfo<
+                    std::net::SocketAddr,
+                >| async move { peer.ip().to_string() },
+            ),
+        );
+        let limits = ConnectionLimits {
+            keepalive_timeout_secs: 5,
+            keepalive_max_requests: 2,
+            max_connections: 1,
+        };
+        let handle = tls::Handle::new();
+        let server = tokio::spawn(tls::serve(listener, router, rustls, limits, handle.clone()));
+
+        let cert = std::fs::read("tests/fixtures/tls/cert.pem").unwrap();
+        let client = || {
+            reqwest::Client::builder()
+                .tls_certs_only([reqwest::Certificate::from_pem(&cert).unwrap()])
+                .http1_only()
+                .build()
+                .unwrap()
+        };
+        let url = format!("https://localhost:{}/peer", port);
+
+        // The first connection is admitted and told its limits
+        let first = client();
+        let response = first.get(&url).send().await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["keep-alive"], "timeout=5, max=2");
+        assert_eq!(response.text().await.unwrap(), "127.0.0.1");
+
+        // A second one, while the first is kept alive, is over the limit
+        let response = client().get(&url).send().await.unwrap();
+        assert_eq!(response.status(), 503);
+        assert_eq!(response.headers()["connection"], "close");
+
+        // The first connection's last request closes it
+        let response = first.get(&url).send().await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["connection"], "close");
+
+        handle.shutdown();
+        server.await.unwrap().unwrap();
+    }
 
-mod error_tests {
-    #[allow(unused_imports)]
-    use super::*;
+    #[tokio::test]
+    async fn test_load_invalid_cert_fails() {
+        let config = TlsConfig {
+            cert: PathBuf::from("tests/fixtures/tls/missing.pem"),
+            ..tls_config()
+        };
+        let err = tls::load(&config).await.unwrap_err();
+        assert!(err.to_string().contains("missing.pem"));
+
+        // Certificate and key swapped
+        let config = TlsConfig {
+            cert: tls_config().key,
+            key: tls_config().cert,
+            ..tls_config()
+        };
+        assert!(tls::load(&config).await.is_err());
+    }
 
     #[test]
-    fn test_error_response_format() {
-        // Error responses should be JSON with "error" field
-        let error_json = r#"{"error": "Source not found: invalid-source"}"#;
-        let parsed: serde_json::Value = serde_json::from_str(error_json).unwrap();
+    fn test_style_urls_use_https() {
+        let server = ServerConfig {
+            host: "tiles.example.com".to_string(),
+            port: 8443,
+            tls: Some(tls_config()),
+            ..ServerConfig::default()
+        };
+        let base_url = server.base_url();
+        assert_eq!(base_url, "https://tiles.example.com:8443");
 
-        assert!(parsed.get("error").is_some());
-        assert!(parsed["error"].is_string());
+        let style = serde_json::json!({
+            "version": 8,
+            "sources": { "osm": { "type": "vector", "url": "/data/osm.json" } },
+            "sprite": "/styles/basic/sprite",
+            "glyphs": "/fonts/{fontstack}/{range}.pbf"
+        });
+        let rewritten = rewrite_style_for_api(&style, &base_url, &UrlQueryParams::default());
+        assert_eq!(
+            rewritten["sources"]["osm"]["url"],
+            "https://tiles.example.com:8443/data/osm.json"
+        );
+        assert!(rewritten["sprite"]
+            .as_str()
+            .unwrap()
+            .starts_with("https://"));
+        assert!(rewritten["glyphs"]
+            .as_str()
+            .unwrap()
+            .starts_with("https://"));
     }
+}
 
-    #[test]
-    fn test_404_scenarios() {
-        // Scenarios that should return 404
-        let not_found_cases = [
-            "Source not found",
-            "Style not found",
-            "Font not found",
-            "File not found",
-            "Tile not found",
-        ];
+// ============================================================
+// Unix Socket Tests
+// ============================================================
 
-        for case in not_found_cases {
-            assert!(case.contains("not found"));
-        }
+#[cfg(unix)]
+mod unix_socket_tests {
+    use axum::routing::get;
+    use tileserver_rs::config::ServerConfig;
+    use tileserver_rs::{health, listener};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Send a plain HTTP/1.1 request over a Unix socket and return the raw response
+    async fn request(path: &std::path::Path, uri: &str) -> String {
+        let mut stream = tokio::net::UnixStream::connect(path)
+            .await
+            .expect("Should connect to socket");
+        stream
+            .write_all(
+                format!(
+                    "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+                    uri
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
     }
 
-    #[test]
-    fn test_400_scenarios() {
-        // Scenarios that should return 400
-        let bad_request_cases = [
-            "Invalid tile coordinates",
-            "Invalid zoom level",
-            "Invalid format",
-            "Invalid size",
-        ];
+    #[tokio::test]
+    async fn test_health_over_unix_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tileserver.sock");
+        let server_config = ServerConfig {
+            listen: Some(format!("unix:{}", path.display())),
+            socket_mode: Some("0660".to_string()),
+            public_url: Some("https://tiles.example.com".to_string()),
+            ..ServerConfig::default()
+        };
+        assert_eq!(server_config.base_url(), "https://tiles.example.com");
 
-        for case in bad_request_cases {
-            assert!(case.starts_with("Invalid"));
-        }
+        let listener::Listener::Unix(socket) = listener::bind(&server_config).unwrap() else {
+            panic!("Expected a Unix socket listener");
+        };
+        let router = axum::Router::new().route("/health", get(health::liveness));
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(listener::serve_unix(socket, router, async {
+            stopped.await.ok();
+        }));
+
+        let response = request(&path, "/health").await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.ends_with("OK"), "{}", response);
+
+        // Graceful shutdown unlinks the socket
+        stop.send(()).unwrap();
+        server.await.unwrap().unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_invalid_listen_address() {
+        let server_config = ServerConfig {
+            listen: Some("0.0.0.0:8080".to_string()),
+            ..ServerConfig::default()
+        };
+        let err = listener::bind(&server_config).unwrap_err();
+        assert!(err.to_string().contains("unix:"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let server_config = ServerConfig {
+            listen: Some(format!("unix:{}", dir.path().join("t.sock").display())),
+            socket_mode: Some("rw-rw----".to_string()),
+            ..ServerConfig::default()
+        };
+        assert!(listener::bind(&server_config).is_err());
     }
 }
 
 // ============================================================
-// CORS Tests
+// Request Timeout Tests
 // ============================================================
 
-mod cors_tests {
-    #[allow(unused_imports)]
-    use super::*;
-
-    #[test]
-    fn test_cors_origins() {
-        // Test CORS origin patterns
-        let allowed_origins = ["*", "http://localhost:3000", "https://example.com"];
+mod timeout_tests {
+    use axum::body::Body;
+    use axum::http::{header, Request, StatusCode};
+    use axum::routing::get;
+    use std::time::Duration;
+    use tileserver_rs::timeout;
+    use tower::ServiceExt;
+
+    /// Router whose tile handler stands in for a renderer taking `render_ms`
+    fn slow_router(render_ms: u64, timeout_ms: u64) -> axum::Router {
+        let router = axum::Router::new().route(
+            "/styles/slow/{z}/{x}/{tile}",
+            get(move || async move {
+                tokio::time::sleep(Duration::from_millis(render_ms)).await;
+                "tile"
+            }),
+        );
+        timeout::apply(router, timeout_ms)
+    }
 
-        for origin in allowed_origins {
-            assert!(!origin.is_empty());
-        }
+    async fn get_tile(router: axum::Router) -> axum::response::Response {
+        router
+            .oneshot(
+                Request::builder()
+                    .uri("/styles/slow/0/0/0.png")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
     }
 
-    #[test]
-    fn test_cors_methods() {
-        // API should allow these methods
-        let allowed_methods = ["GET", "OPTIONS", "HEAD"];
+    #[tokio::test]
+    async fn test_slow_render_returns_503() {
+        let response = get_tile(slow_router(1000, 50)).await;
 
-        for method in allowed_methods {
-            assert!(["GET", "OPTIONS", "HEAD", "POST", "PUT", "DELETE"].contains(&method));
-        }
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["error"]["code"], "TIMEOUT");
     }
 
-    #[test]
-    fn test_cors_headers() {
-        // API should allow these headers
-        let allowed_headers = ["Accept", "Content-Type"];
+    #[tokio::test]
+    async fn test_fast_render_and_disabled_timeout() {
+        let response = get_tile(slow_router(10, 1000)).await;
+        assert_eq!(response.status(), StatusCode::OK);
 
-        for header in allowed_headers {
-            assert!(!header.is_empty());
-        }
+        // 0 turns the timeout off
+        let response = get_tile(slow_router(100, 0)).await;
+        assert_eq!(response.status(), StatusCode::OK);
     }
 }
 
 // ============================================================
-// URL Parameter Tests
+// Circuit Breaker Tests
 // ============================================================
 
-mod url_params_tests {
-    #[allow(unused_imports)]
-    use super::*;
-    use std::str::FromStr;
-
-    #[test]
-    fn test_tile_z_x_y_parsing() {
-        // Valid tile coordinates
-        let valid_coords = [(0, 0, 0), (1, 0, 0), (1, 1, 1), (14, 8192, 8192)];
+mod resilience_tests {
+    use axum::http::{header, StatusCode};
+    use axum::response::IntoResponse;
+    use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tileserver_rs::config::{ResilienceConfig, SourceConfig};
+    use tileserver_rs::health::{HealthStatus, ReadinessChecker};
+    use tileserver_rs::sources::resilient::{BreakerState, ResilientSource};
+    use tileserver_rs::sources::{TileData, TileFormat, TileMetadata, TileScheme, TileSource};
+    use tileserver_rs::{SourceManager, TileServerError};
+
+    /// Backend that fails or stalls on demand
+    struct FlakySource {
+        metadata: TileMetadata,
+        failing: AtomicBool,
+        delay_ms: AtomicU64,
+        calls: AtomicUsize,
+    }
 
-        for (z, x, y) in valid_coords {
-            assert!(z <= 22, "Zoom should be <= 22");
-            let max_coord = 1u32 << z;
-            assert!(x < max_coord, "X should be < 2^z");
-            assert!(y < max_coord, "Y should be < 2^z");
+    impl FlakySource {
+        fn new() -> Arc<Self> {
+            Arc::new(Self {
+                metadata: TileMetadata {
+                    id: "remote".to_string(),
+                    name: "Remote".to_string(),
+                    description: None,
+                    attribution: None,
+                    format: TileFormat::Pbf,
+                    minzoom: 0,
+                    maxzoom: 14,
+                    bounds: None,
+                    center: None,
+                    vector_layers: None,
+                    scheme: TileScheme::Xyz,
+                    overzoom: false,
+                    encoding: None,
+                },
+                failing: AtomicBool::new(false),
+                delay_ms: AtomicU64::new(0),
+                calls: AtomicUsize::new(0),
+            })
         }
     }
 
-    #[test]
-    fn test_scale_factor_parsing() {
-        // Valid scale factors
-        let valid_scales = ["@1x", "@2x", "@3x", "@4x"];
-
-        for scale in valid_scales {
-            let num: u8 = scale
-                .trim_start_matches('@')
-                .trim_end_matches('x')
-                .parse()
-                .unwrap();
-            assert!((1..=4).contains(&num));
+    #[async_trait::async_trait]
+    impl TileSource for FlakySource {
+        async fn get_xyz_tile(
+            &self,
+            _z: u8,
+            _x: u32,
+            _y: u32,
+        ) -> tileserver_rs::error::Result<Option<TileData>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let delay = self.delay_ms.load(Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            if self.failing.load(Ordering::SeqCst) {
+                return Err(TileServerError::UpstreamError("connection reset".into()));
+            }
+            Ok(None)
         }
-    }
 
-    #[test]
-    fn test_static_image_size_parsing() {
-        // Valid size formats
-        let valid_sizes = ["800x600", "1920x1080", "256x256", "512x512@2x"];
+        fn metadata(&self) -> &TileMetadata {
+            &self.metadata
+        }
 
-        for size in valid_sizes {
-            let size_part = size.split('@').next().unwrap();
-            let parts: Vec<&str> = size_part.split('x').collect();
-            assert_eq!(parts.len(), 2);
+        fn backend(&self) -> &'static str {
+            "flaky"
+        }
 
-            let width: u32 = parts[0].parse().unwrap();
-            let height: u32 = parts[1].parse().unwrap();
-            assert!(width > 0 && width <= 4096);
-            assert!(height > 0 && height <= 4096);
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
         }
     }
 
-    #[test]
-    fn test_static_type_parsing() {
-        use tileserver_rs::render::StaticType;
+    fn resilient(backend: &Arc<FlakySource>, config: ResilienceConfig) -> ResilientSource {
+        ResilientSource::new(Arc::clone(backend) as Arc<dyn TileSource>, &config)
+    }
 
-        // Center format
-        let center = StaticType::from_str("-122.4,37.8,12").unwrap();
-        match center {
-            StaticType::Center { lon, lat, zoom, .. } => {
-                assert!((lon - (-122.4)).abs() < 0.01);
-                assert!((lat - 37.8).abs() < 0.01);
-                assert!((zoom - 12.0).abs() < 0.01);
-            }
-            _ => panic!("Expected Center"),
+    #[tokio::test]
+    async fn test_breaker_opens_fails_fast_and_recovers() {
+        let backend = FlakySource::new();
+        let source = resilient(
+            &backend,
+            ResilienceConfig {
+                timeout_ms: 0,
+                failure_threshold: 3,
+                cooldown_ms: 200,
+            },
+        );
+        let breaker = Arc::clone(source.breaker().unwrap());
+
+        backend.failing.store(true, Ordering::SeqCst);
+        for _ in 0..3 {
+            let err = source.get_tile(0, 0, 0).await.unwrap_err();
+            assert!(matches!(err, TileServerError::UpstreamError(_)), "{}", err);
         }
+        assert_eq!(breaker.state(), BreakerState::Open);
+        assert_eq!(backend.calls.load(Ordering::SeqCst), 3);
+
+        // Open: fail without reaching the backend
+        let err = source.get_tile(0, 0, 0).await.unwrap_err();
+        assert_eq!(backend.calls.load(Ordering::SeqCst), 3);
+        let response = err.into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["error"]["code"], "SOURCE_UNAVAILABLE");
+        assert_eq!(json["error"]["details"]["source"], "remote");
+
+        // Half-open: a failed probe reopens the breaker
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+        assert!(source.get_tile(0, 0, 0).await.is_err());
+        assert_eq!(backend.calls.load(Ordering::SeqCst), 4);
+        assert_eq!(breaker.state(), BreakerState::Open);
+
+        // ...and a successful one closes it
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        backend.failing.store(false, Ordering::SeqCst);
+        assert!(source.get_tile(0, 0, 0).await.unwrap().is_none());
+        assert_eq!(breaker.state(), BreakerState::Closed);
+        assert!(source.get_tile(0, 0, 0).await.is_ok());
+        assert_eq!(backend.calls.load(Ordering::SeqCst), 6);
+    }
 
-        // Auto format
-        let auto = StaticType::from_str("auto").unwrap();
-        assert!(matches!(auto, StaticType::Auto));
+    #[tokio::test]
+    async fn test_slow_reads_time_out_and_open_the_breaker() {
+        let backend = FlakySource::new();
+        backend.delay_ms.store(1000, Ordering::SeqCst);
+        let source = resilient(
+            &backend,
+            ResilienceConfig {
+                timeout_ms: 20,
+                failure_threshold: 2,
+                cooldown_ms: 60_000,
+            },
+        );
 
-        // Bounding box format
-        let bbox = StaticType::from_str("-123,37,-122,38").unwrap();
-        match bbox {
-            StaticType::BoundingBox {
-                min_lon,
-                min_lat,
-                max_lon,
-                max_lat,
-            } => {
-                assert!((min_lon - (-123.0)).abs() < 0.01);
-                assert!((min_lat - 37.0).abs() < 0.01);
-                assert!((max_lon - (-122.0)).abs() < 0.01);
-                assert!((max_lat - 38.0).abs() < 0.01);
-            }
-            _ => panic!("Expected BoundingBox"),
+        let started = std::time::Instant::now();
+        for _ in 0..2 {
+            let err = source.get_tile(0, 0, 0).await.unwrap_err();
+            assert_eq!(err.status(), StatusCode::SERVICE_UNAVAILABLE);
+            assert!(err.to_string().contains("took longer than"), "{}", err);
+        }
+        let err = source.get_tile(0, 0, 0).await.unwrap_err();
+        assert!(started.elapsed() < Duration::from_millis(500));
+        match err {
+            TileServerError::SourceUnavailable {
+                retry_after_secs, ..
+            } => assert_eq!(retry_after_secs, 60),
+            e => panic!("unexpected error: {}", e),
+        }
+        assert_eq!(backend.calls.load(Ordering::SeqCst), 2);
+
+        // Without a threshold only the timeout applies
+        let source = resilient(
+            &backend,
+            ResilienceConfig {
+                timeout_ms: 20,
+                failure_threshold: 0,
+                cooldown_ms: 0,
+            },
+        );
+        assert!(source.breaker().is_none());
+        for _ in 0..3 {
+            let err = source.get_tile(0, 0, 0).await.unwrap_err();
+            assert!(matches!(err, TileServerError::Timeout(_)), "{}", err);
         }
     }
 
-    #[test]
-    fn test_format_extensions() {
-        use tileserver_rs::render::ImageFormat;
-        use tileserver_rs::sources::TileFormat;
-
-        // Image formats
-        assert!(ImageFormat::from_str("png").is_ok());
-        assert!(ImageFormat::from_str("jpg").is_ok());
-        assert!(ImageFormat::from_str("jpeg").is_ok());
-        assert!(ImageFormat::from_str("webp").is_ok());
-        assert!(ImageFormat::from_str("gif").is_err()); // Not supported
+    #[tokio::test]
+    async fn test_breaker_state_in_readiness() {
+        // Nothing listens on port 9 of localhost
+        let config: SourceConfig = toml::from_str(
+            r#"
+            id = "remote"
+            type = "proxy"
+            url = "http://127.0.0.1:9/{z}/{x}/{y}.pbf"
+            retries = 0
+            optional = true
+
+            [resilience]
+            timeout_ms = 2000
+            failure_threshold = 2
+            cooldown_ms = 60000
+            "#,
+        )
+        .unwrap();
+        let sources = SourceManager::from_configs(&[config])
+            .await
+            .expect("Should load sources");
+        assert_eq!(sources.breaker_state("remote"), Some(BreakerState::Closed));
 
-        // Tile formats
-        assert!(TileFormat::from_str("pbf").is_ok());
-        assert!(TileFormat::from_str("mvt").is_ok());
-        assert!(TileFormat::from_str("png").is_ok());
+        for _ in 0..2 {
+            assert!(sources.get_tile("remote", 0, 0, 0).await.is_err());
+        }
+        assert_eq!(sources.breaker_state("remote"), Some(BreakerState::Open));
+        let err = sources.get_tile("remote", 0, 0, 0).await.unwrap_err();
+        assert!(matches!(err, TileServerError::SourceUnavailable { .. }));
+
+        let report = ReadinessChecker::default().check(&sources).await;
+        let remote = &report.sources[0];
+        assert_eq!(remote.status, HealthStatus::Error);
+        assert_eq!(remote.breaker, Some(BreakerState::Open));
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["sources"][0]["breaker"], "open");
+
+        // Sources without a breaker report none
+        let config: SourceConfig = toml::from_str(
+            r#"
+            id = "plain"
+            type = "pmtiles"
+            path = "data/tiles/protomaps-sample.pmtiles"
+            "#,
+        )
+        .unwrap();
+        let sources = SourceManager::from_configs(&[config]).await.unwrap();
+        assert_eq!(sources.breaker_state("plain"), None);
+        let report = ReadinessChecker::default().check(&sources).await;
+        assert!(serde_json::to_value(&report).unwrap()["sources"][0]
+            .get("breaker")
+            .is_none());
     }
 }
 
 // ============================================================
-// Marker/Path Query Parameter Tests
+// Request Limit Tests
 // ============================================================
 
-mod overlay_params_tests {
-    #[allow(unused_imports)]
-    use super::*;
-    use tileserver_rs::render::overlay::{parse_marker, parse_path};
-
-    #[test]
-    fn test_marker_param_formats() {
-        // Valid marker formats
-        let valid_markers = [
-            "pin-s+f00(0,0)",
-            "pin-m+00ff00(-122.4,37.8)",
-            "pin-l-a+0000ff(10.5,20.5)",
-            "pin-s+ff0000(0,0)",
-        ];
-
-        for marker in valid_markers {
-            assert!(
-                parse_marker(marker).is_some(),
-                "Should parse marker: {}",
-                marker
+mod limits_tests {
+    use axum::body::{Body, Bytes};
+    use axum::extract::Query;
+    use axum::http::{header, Request, StatusCode};
+    use axum::routing::{get, post};
+    use std::collections::HashMap;
+    use tileserver_rs::config::ServerConfig;
+    use tileserver_rs::middleware::limits::{self, RequestLimits};
+    use tileserver_rs::render::overlay::decode_polyline;
+    use tower::ServiceExt;
+
+    /// Router with a render endpoint echoing the body size and a static
+    /// image endpoint counting the points of its path
+    fn router(limits: RequestLimits) -> axum::Router {
+        let router = axum::Router::new()
+            .route(
+                "/styles/basic/render",
+                post(|body: Bytes| async move { body.len().to_string() }),
+            )
+            .route(
+                "/styles/basic/static/auto/256x256.png",
+                get(|Query(query): Query<HashMap<String, String>>| async move {
+                    let path = query["path"].trim_start_matches("enc:");
+                    decode_polyline(path).len().to_string()
+                }),
             );
-        }
+        limits::apply(router, limits)
     }
 
-    #[test]
-    fn test_invalid_marker_formats() {
-        // Invalid marker formats
-        let invalid_markers = [
-            "",
-            "pin",
-            "pin-x+f00(0,0)", // Invalid size
-            "marker(0,0)",    // Wrong prefix
-        ];
+    async fn send(
+        limits: RequestLimits,
+        request: Request<Body>,
+    ) -> (StatusCode, serde_json::Value, String) {
+        let response = router(limits).oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json = serde_json::from_slice(&body).unwrap_or_default();
+        (status, json, String::from_utf8_lossy(&body).into_owned())
+    }
 
-        for marker in invalid_markers {
-            if !marker.is_empty() {
-                // Empty string might have different handling
-                let _result = parse_marker(marker);
-                // Invalid formats should return None or parse to defaults
-                // The actual behavior depends on implementation
-            }
+    fn render(body: Vec<u8>, content_length: bool) -> Request<Body> {
+        let mut request = Request::post("/styles/basic/render");
+        if content_length {
+            request = request.header(header::CONTENT_LENGTH, body.len());
         }
+        request.body(Body::from(body)).unwrap()
     }
 
-    #[test]
-    fn test_path_param_formats() {
-        // Valid path formats
-        let valid_paths = [
-            "path-5+f00(0,0|1,1)",
-            "path-3+00ff00(-122.4,37.8|-122.5,37.9)",
-            "path-2+0000ff-80ffffff(0,0|10,0|10,10|0,10|0,0)", // With fill
-        ];
+    #[tokio::test]
+    async fn test_request_body_limit() {
+        let limits = RequestLimits {
+            max_body_bytes: 1024,
+            max_query_bytes: 0,
+        };
 
-        for path in valid_paths {
-            assert!(parse_path(path).is_some(), "Should parse path: {}", path);
-        }
+        // Exactly the limit is accepted
+        let (status, _, body) = send(limits, render(vec![b' '; 1024], true)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "1024");
+        let (status, _, _) = send(limits, render(vec![b' '; 1024], false)).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // One byte over is refused, up front when the length is declared
+        let (status, json, _) = send(limits, render(vec![b' '; 1025], true)).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(json["error"]["code"], "PAYLOAD_TOO_LARGE");
+        let (status, _, _) = send(limits, render(vec![b' '; 1025], false)).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+
+        // 0 turns the limit off
+        let unlimited = RequestLimits::default();
+        let (status, _, _) = send(unlimited, render(vec![b' '; 4 << 20], true)).await;
+        assert_eq!(status, StatusCode::OK);
     }
 
-    #[test]
-    fn test_encoded_polyline_format() {
-        // Test encoded polyline detection
-        let encoded = "_p~iF~ps|U_ulLnnqC_mqNvxq`@";
+    #[tokio::test]
+    async fn test_query_string_limit() {
+        let limits = RequestLimits::from(&ServerConfig::default());
+        assert_eq!(limits.max_body_bytes, 1_048_576);
+        assert_eq!(limits.max_query_bytes, 32_768);
+
+        // A long but valid encoded polyline, one segment repeated
+        let path = |segments: usize| {
+            format!(
+                "/styles/basic/static/auto/256x256.png?path=enc:_p~iF~ps%7CU{}",
+                "_ulLnnqC".repeat(segments)
+            )
+        };
+        let get = |uri: String| Request::get(uri).body(Body::empty()).unwrap();
 
-        // This should be detected as a polyline (no commas, valid chars)
-        assert!(!encoded.contains(','));
-        assert!(encoded
-            .chars()
-            .all(|c| (c as u32) >= 63 && (c as u32) <= 126));
+        let (status, _, body) = send(limits, get(path(4000))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "4001");
+
+        let (status, json, _) = send(limits, get(path(4100))).await;
+        assert_eq!(status, StatusCode::URI_TOO_LONG);
+        assert_eq!(json["error"]["code"], "URI_TOO_LONG");
+        assert!(json["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("32768 byte limit"));
     }
 }
 
 // ============================================================
-// TileJSON Validation Tests
+// Source Validation Tests
 // ============================================================
 
-mod tilejson_tests {
+mod integrity_tests {
     use super::*;
+    use std::time::Duration;
+    use tileserver_rs::integrity;
     use tileserver_rs::{Config, SourceManager};
 
-    #[tokio::test]
-    async fn test_tilejson_spec_compliance() {
-        let config =
-            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
-        let sources = SourceManager::from_configs(&config.sources)
-            .await
-            .expect("Should load sources");
-
-        for metadata in sources.all_metadata() {
-            let tilejson = metadata.to_tilejson("http://localhost:8080");
-
-            // TileJSON 3.0 required fields
-            assert_eq!(tilejson.tilejson, "3.0.0", "Should be TileJSON 3.0.0");
-            assert!(!tilejson.tiles.is_empty(), "tiles array required");
-
-            // Validate zoom levels
-            assert!(tilejson.minzoom <= 22, "minzoom should be <= 22");
-            assert!(tilejson.maxzoom <= 22, "maxzoom should be <= 22");
-            assert!(
-                tilejson.minzoom <= tilejson.maxzoom,
-                "minzoom should be <= maxzoom"
-            );
-
-            // Validate bounds if present
-            if let Some(bounds) = &tilejson.bounds {
-                assert_eq!(bounds.len(), 4, "bounds should have 4 values");
-                assert!(bounds[0] >= -180.0 && bounds[0] <= 180.0, "west bound");
-                assert!(bounds[1] >= -90.0 && bounds[1] <= 90.0, "south bound");
-                assert!(bounds[2] >= -180.0 && bounds[2] <= 180.0, "east bound");
-                assert!(bounds[3] >= -90.0 && bounds[3] <= 90.0, "north bound");
-            }
+    async fn test_sources() -> SourceManager {
+        let config =
+            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+        SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources")
+    }
 
-            // Validate center if present
-            if let Some(center) = &tilejson.center {
-                assert!(center.len() >= 2, "center should have at least lon,lat");
-                assert!(
-                    center[0] >= -180.0 && center[0] <= 180.0,
-                    "center longitude"
-                );
-                assert!(center[1] >= -90.0 && center[1] <= 90.0, "center latitude");
-            }
+    #[tokio::test]
+    async fn test_fixtures_are_valid() {
+        let sources = test_sources().await;
+        for (id, backend) in [("protomaps", "pmtiles"), ("zurich", "mbtiles")] {
+            let source = sources.get(id).unwrap();
+            let report = integrity::validate(id, source.as_ref(), Duration::from_secs(60)).await;
+
+            assert!(report.valid, "{}: {:?}", id, report.issues);
+            assert!(report.complete);
+            assert_eq!(report.backend, backend);
+            assert!(report.sampled_tiles > 0);
+            let checks: Vec<&str> = report.checks.iter().map(|c| c.check).collect();
+            assert_eq!(checks, ["backend", "tiles"]);
         }
     }
 
     #[tokio::test]
-    async fn test_tilejson_tiles_url_format() {
+    async fn test_missing_metadata_is_reported() {
+        use tileserver_rs::config::SourceConfig;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("zurich.mbtiles");
+        std::fs::copy("data/tiles/zurich_switzerland.mbtiles", &path).unwrap();
+        rusqlite::Connection::open(&path)
+            .unwrap()
+            .execute("DELETE FROM metadata WHERE name = 'name'", [])
+            .unwrap();
+
         let config =
             Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
-        let sources = SourceManager::from_configs(&config.sources)
-            .await
-            .expect("Should load sources");
-
-        for metadata in sources.all_metadata() {
-            let tilejson = metadata.to_tilejson("http://localhost:8080");
+        let zurich = config.sources.iter().find(|s| s.id == "zurich").unwrap();
+        let sources = SourceManager::from_configs(&[SourceConfig {
+            path: path.to_string_lossy().into_owned(),
+            ..zurich.clone()
+        }])
+        .await
+        .unwrap();
+
+        let source = sources.get("zurich").unwrap();
+        let report = integrity::validate("zurich", source.as_ref(), Duration::from_secs(60)).await;
+        assert!(!report.valid);
+        assert!(report.complete);
+        assert_eq!(report.issues.len(), 1);
+        assert_eq!(report.issues[0].check, "metadata");
+        assert_eq!(report.issues[0].message, "Metadata table has no 'name'");
+    }
 
-            for tile_url in &tilejson.tiles {
-                // Should contain placeholders
-                assert!(tile_url.contains("{z}"), "Should have {{z}} placeholder");
-                assert!(tile_url.contains("{x}"), "Should have {{x}} placeholder");
-                assert!(tile_url.contains("{y}"), "Should have {{y}} placeholder");
+    #[tokio::test]
+    async fn test_validation_stops_after_max_duration() {
+        let sources = test_sources().await;
+        let source = sources.get("zurich").unwrap();
+        let report = integrity::validate("zurich", source.as_ref(), Duration::ZERO).await;
+
+        assert!(!report.valid);
+        assert!(!report.complete);
+        assert_eq!(report.issues.last().unwrap().check, "timeout");
+    }
 
-                // Should be a valid URL
-                assert!(
-                    tile_url.starts_with("http://") || tile_url.starts_with("https://"),
-                    "Should be absolute URL"
-                );
-            }
-        }
+    #[tokio::test]
+    async fn test_streamed_validation() {
+        use futures::StreamExt;
+
+        let sources = test_sources().await;
+        let source = sources.get("protomaps").unwrap().clone();
+        let lines: Vec<serde_json::Value> =
+            integrity::validate_stream("protomaps".to_string(), source, Duration::from_secs(60))
+                .map(|line| serde_json::from_slice(&line).unwrap())
+                .collect()
+                .await;
+
+        let types: Vec<&str> = lines.iter().map(|l| l["type"].as_str().unwrap()).collect();
+        assert_eq!(types, ["progress", "progress", "report"]);
+        assert_eq!(lines[0]["check"], "backend");
+        assert_eq!(lines[2]["valid"], true);
+        assert_eq!(lines[2]["source"], "protomaps");
     }
 }
 
 // ============================================================
-// WMTS Tests
+// Request ID Tests
 // ============================================================
 
-mod wmts_tests {
-    #[test]
-    fn test_wmts_xml_structure() {
-        // WMTS GetCapabilities should return valid XML
-        let expected_elements = [
-            "Capabilities",
-            "ServiceIdentification",
-            "Contents",
-            "Layer",
-            "TileMatrixSet",
-        ];
+mod request_id_tests {
+    use axum::{
+        body::Body,
+        extract::Path,
+        http::{Request, StatusCode},
+        routing::get,
+        Router,
+    };
+    use tileserver_rs::error::TileServerError;
+    use tileserver_rs::request_id;
+    use tower::ServiceExt;
+
+    fn router(trust_incoming: bool) -> Router {
+        let router = Router::new().route(
+            "/data/{source}",
+            get(|Path(source): Path<String>| async move {
+                Err::<(), _>(TileServerError::SourceNotFound(source))
+            }),
+        );
+        request_id::apply(router, trust_incoming)
+    }
 
-        for element in expected_elements {
-            assert!(!element.is_empty());
+    async fn get_missing(router: Router, id: Option<&str>) -> (String, serde_json::Value) {
+        let mut request = Request::builder().uri("/data/missing");
+        if let Some(id) = id {
+            request = request.header("x-request-id", id);
         }
+        let response = router
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let header = response.headers()["x-request-id"]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (header, serde_json::from_slice(&body).unwrap())
     }
 
-    #[test]
-    fn test_wmts_tile_matrix_set() {
-        // WebMercator tile matrix set levels
-        for zoom in 0..=22 {
-            let scale_denominator = 559082264.028717 / (1 << zoom) as f64;
-            let tile_size = 256;
-            let matrix_width = 1 << zoom;
-            let matrix_height = 1 << zoom;
+    #[tokio::test]
+    async fn test_incoming_request_id_is_echoed() {
+        let (header, json) = get_missing(router(true), Some("abc")).await;
+        assert_eq!(header, "abc");
+        assert_eq!(json["error"]["code"], "SOURCE_NOT_FOUND");
+        assert_eq!(json["error"]["request_id"], "abc");
+    }
 
-            assert!(scale_denominator > 0.0);
-            assert_eq!(tile_size, 256);
-            assert!(matrix_width > 0);
-            assert_eq!(matrix_width, matrix_height);
+    #[tokio::test]
+    async fn test_request_id_is_generated() {
+        let (header, json) = get_missing(router(true), None).await;
+        assert_eq!(uuid::Uuid::parse_str(&header).unwrap().get_version_num(), 7);
+        assert_eq!(json["error"]["request_id"], header.as_str());
+
+        // Untrusted incoming IDs are replaced
+        let (header, json) = get_missing(router(false), Some("abc")).await;
+        assert_ne!(header, "abc");
+        assert_eq!(json["error"]["request_id"], header.as_str());
+    }
+}
+
+// ============================================================================
+// Client IP Tests
+// ============================================================================
+
+mod real_ip_tests {
+    use axum::{body::Body, extract::ConnectInfo, http::Request, routing::get, Extension, Router};
+    use std::net::SocketAddr;
+    use tileserver_rs::middleware::real_ip::{self, ClientIp, RealIpSettings};
+    use tower::ServiceExt;
+
+    async fn client_ip(settings: RealIpSettings, forwarded_for: Option<&str>) -> String {
+        let router = Router::new().route(
+            "/",
+            get(|Extension(ClientIp(ip)): Extension<ClientIp>| async move { ip.to_string() }),
+        );
+        // What `into_make_service_with_connect_info` adds for each connection
+        let peer = ConnectInfo(SocketAddr::from(([10, 0, 0, 2], 51234)));
+        let router = real_ip::apply(router, settings).layer(Extension(peer));
+
+        let mut request = Request::builder().uri("/");
+        if let Some(value) = forwarded_for {
+            request = request.header("x-forwarded-for", value);
         }
+        let response = router
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
     }
 
-    #[test]
-    fn test_wmts_key_parameter_format() {
-        // Test that key parameter is properly formatted in WMTS URLs
-        let key = "my_api_key_123";
-        let expected_query = format!("?key={}", key);
+    #[tokio::test]
+    async fn test_proxy_headers_are_ignored_by_default() {
+        let ip = client_ip(RealIpSettings::default(), Some("203.0.113.7")).await;
+        assert_eq!(ip, "10.0.0.2");
+    }
 
-        assert!(expected_query.starts_with("?key="));
-        assert!(expected_query.contains(key));
+    #[tokio::test]
+    async fn test_trusted_proxy_headers() {
+        let settings = RealIpSettings {
+            trust_proxy_headers: true,
+            trusted_proxy_depth: None,
+        };
+        assert_eq!(
+            client_ip(settings, Some("203.0.113.7")).await,
+            "203.0.113.7"
+        );
+        assert_eq!(client_ip(settings, None).await, "10.0.0.2");
+
+        let settings = RealIpSettings {
+            trusted_proxy_depth: Some(1),
+            ..settings
+        };
+        let spoofed = Some("1.2.3.4, 203.0.113.7");
+        assert_eq!(client_ip(settings, spoofed).await, "203.0.113.7");
     }
+}
 
-    #[test]
-    fn test_wmts_key_parameter_encoding() {
-        // Test URL encoding of special characters in key
-        let special_keys = [
-            ("simple_key", "simple_key"),
-            ("key with spaces", "key%20with%20spaces"),
-            ("key&value=test", "key%26value%3Dtest"),
-        ];
+// ============================================================================
+// Additional Listener Tests
+// ============================================================================
+
+mod listener_tests {
+    use axum::{routing::get, Router};
+    use tileserver_rs::config::{ListenerConfig, ServerConfig};
+
+    /// Router with one route per group, keeping the groups `listener` serves
+    fn filtered_router(listener: &ListenerConfig) -> Router {
+        let mut router = Router::new();
+        for (group, path) in [
+            ("health", "/health"),
+            ("data", "/data.json"),
+            ("styles", "/styles.json"),
+            ("files", "/files/{*filepath}"),
+        ] {
+            if listener.serves(group) {
+                router = router.route(path, get(|| async { "OK" }));
+            }
+        }
+        router
+    }
 
-        for (input, expected_encoded) in special_keys {
-            let encoded = urlencoding::encode(input);
+    async fn spawn_listener(listener: &ListenerConfig) -> String {
+        let socket = tokio::net::TcpListener::bind((listener.host.as_str(), listener.port))
+            .await
+            .unwrap();
+        let addr = socket.local_addr().unwrap();
+        let router = filtered_router(listener);
+        tokio::spawn(async move { axum::serve(socket, router).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_listeners_serve_their_route_groups() {
+        let server: ServerConfig = toml::from_str(
+            r#"
+            [[listeners]]
+            host = "127.0.0.1"
+            port = 0
+
+            [[listeners]]
+            host = "127.0.0.1"
+            port = 0
+            routes = ["data", "styles", "health"]
+            "#,
+        )
+        .unwrap();
+        let full = spawn_listener(&server.listeners[0]).await;
+        let filtered = spawn_listener(&server.listeners[1]).await;
+
+        let status = |url: String| async move { reqwest::get(url).await.unwrap().status() };
+        for path in ["/health", "/data.json", "/styles.json", "/files/a.geojson"] {
+            assert_eq!(status(format!("{}{}", full, path)).await, 200, "{}", path);
+        }
+        for path in ["/health", "/data.json", "/styles.json"] {
             assert_eq!(
-                encoded, expected_encoded,
-                "Key '{}' not encoded correctly",
-                input
+                status(format!("{}{}", filtered, path)).await,
+                200,
+                "{}",
+                path
             );
         }
+        assert_eq!(status(format!("{}/files/a.geojson", filtered)).await, 404);
     }
 
     #[test]
-    fn test_wmts_generates_without_key() {
-        use tileserver_rs::wmts::generate_wmts_capabilities;
+    fn test_listener_config() {
+        let server: ServerConfig = toml::from_str(
+            r#"
+            [[listeners]]
+            port = 8081
+            routes = ["data"]
+
+            [[listeners]]
+            host = "10.0.0.5"
+            port = 8082
+            public_url = "https://tiles.example.com/"
+            "#,
+        )
+        .unwrap();
+        let (tiles, internal) = (&server.listeners[0], &server.listeners[1]);
+
+        assert_eq!(tiles.base_url(), "http://localhost:8081");
+        assert_eq!(internal.base_url(), "https://tiles.example.com");
+        assert!(tiles.serves("data") && !tiles.serves("files"));
+        assert!(internal.serves("files") && internal.serves("ui"));
+        assert!(tiles.validate().is_ok());
+
+        let unknown = ListenerConfig {
+            routes: Some(vec!["admin".to_string()]),
+            ..tiles.clone()
+        };
+        assert!(unknown.validate().is_err());
+    }
+}
 
-        let xml = generate_wmts_capabilities(
-            "http://localhost:8080",
-            "test-style",
-            "Test Style",
-            0,
-            18,
-            None,
-        );
+// ============================================================
+// Preview Page Tests
+// ============================================================
 
-        assert!(
-            !xml.contains("?key="),
-            "Should not have key param without key"
-        );
-        assert!(
-            xml.contains("wmts.xml\""),
-            "Should have WMTS URL without query params"
-        );
+mod preview_tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{header, Request, StatusCode};
+    use tileserver_rs::config::ServerConfig;
+    use tileserver_rs::preview::{self, PreviewState};
+    use tileserver_rs::{Config, SharedSources, SharedStyles, SourceManager, StyleManager};
+    use tower::ServiceExt;
+
+    async fn router() -> axum::Router {
+        let config = Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load config");
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
+        let styles = StyleManager::from_configs(&config.styles).expect("Should load styles");
+        let state = PreviewState {
+            sources: SharedSources::new(sources),
+            styles: SharedStyles::new(styles),
+            base_url: "http://localhost:8080".to_string(),
+        };
+        preview::data_router(state.clone()).merge(preview::styles_router(state))
     }
 
-    #[test]
-    fn test_wmts_generates_with_key() {
-        use tileserver_rs::wmts::generate_wmts_capabilities;
+    async fn get(router: &axum::Router, uri: &str) -> (StatusCode, String) {
+        let response = router
+            .clone()
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        if status == StatusCode::OK {
+            assert!(response.headers()[header::CONTENT_TYPE]
+                .to_str()
+                .unwrap()
+                .starts_with("text/html"));
+        }
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
 
-        let xml = generate_wmts_capabilities(
-            "http://localhost:8080",
-            "test-style",
-            "Test Style",
-            0,
-            18,
-            Some("my_secret_key"),
-        );
+    #[tokio::test]
+    async fn test_data_preview_loads_tilejson() {
+        let router = router().await;
+
+        let (status, html) = get(&router, "/data/zurich/").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(html.contains(r#"const tilejsonUrl = "http://localhost:8080/data/zurich.json";"#));
+        assert!(html.contains("const vector = true;"));
+        assert!(html.contains("<title>OpenMapTiles - tileserver-rs</title>"));
+
+        let (_, html) = get(&router, "/data/protomaps/?key=a%20b").await;
+        assert!(html.contains(r#""http://localhost:8080/data/protomaps.json?key=a%20b""#));
+
+        let (status, _) = get(&router, "/data/missing/").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
 
+    #[tokio::test]
+    async fn test_style_preview_loads_style() {
+        let router = router().await;
+
+        let (status, html) = get(&router, "/styles/protomaps-light/").await;
+        assert_eq!(status, StatusCode::OK);
         assert!(
-            xml.contains("?key=my_secret_key"),
-            "Should have key param in URLs"
+            html.contains(r#"style: "http://localhost:8080/styles/protomaps-light/style.json""#)
         );
 
-        // Key should appear in:
-        // 1. WMTS capabilities URL
-        assert!(xml.contains("wmts.xml?key=my_secret_key"));
-        // 2. Tile URLs for both 256 and 512 layers
-        assert!(xml.contains(".png?key=my_secret_key"));
+        let (status, _) = get(&router, "/styles/missing/").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
     }
 
     #[test]
-    fn test_wmts_key_url_encoded() {
-        use tileserver_rs::wmts::generate_wmts_capabilities;
+    fn test_preview_pages_disabled_by_default() {
+        assert!(!ServerConfig::default().preview_pages);
+        let server: ServerConfig = toml::from_str("preview_pages = true").unwrap();
+        assert!(server.preview_pages);
+    }
+}
 
-        let xml = generate_wmts_capabilities(
-            "http://localhost:8080",
-            "test-style",
-            "Test Style",
-            0,
-            18,
-            Some("key with spaces & symbols="),
-        );
+// ============================================================
+// Render Coalescing Tests
+// ============================================================
 
-        // Key should be URL-encoded
-        assert!(xml.contains("?key=key%20with%20spaces%20%26%20symbols%3D"));
+mod coalesce_tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tileserver_rs::render::coalesce::SingleFlight;
+
+    /// Stand-in for a renderer taking 100ms, counting its renders
+    fn slow_render(
+        renders: &Arc<AtomicUsize>,
+    ) -> impl std::future::Future<Output = tileserver_rs::error::Result<Vec<u8>>> + Send + 'static
+    {
+        let renders = Arc::clone(renders);
+        async move {
+            renders.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            Ok(b"tile".to_vec())
+        }
     }
 
-    #[test]
-    fn test_wmts_key_in_all_urls() {
-        use tileserver_rs::wmts::generate_wmts_capabilities;
+    #[tokio::test]
+    async fn test_identical_requests_share_one_render() {
+        let flights = Arc::new(SingleFlight::new());
+        let renders = Arc::new(AtomicUsize::new(0));
+        let key = ("style", 14u8, 8192u32, 5461u32, 1u8, "png");
+
+        let requests: Vec<_> = (0..10)
+            .map(|_| {
+                let flights = Arc::clone(&flights);
+                let render = slow_render(&renders);
+                tokio::spawn(async move { flights.run(key, render).await })
+            })
+            .collect();
+        for request in requests {
+            assert_eq!(request.await.unwrap().unwrap(), b"tile");
+        }
 
-        let xml = generate_wmts_capabilities(
-            "http://localhost:8080",
-            "osm-bright",
-            "OSM Bright",
-            0,
-            18,
-            Some("test_key"),
-        );
+        assert_eq!(renders.load(Ordering::SeqCst), 1);
+        assert_eq!(flights.coalesced(), 9);
+    }
 
-        // Count occurrences of key parameter
-        let key_count = xml.matches("?key=test_key").count();
+    #[tokio::test]
+    async fn test_different_tiles_render_separately() {
+        let flights = SingleFlight::new();
+        let renders = Arc::new(AtomicUsize::new(0));
 
-        // Should appear in:
-        // - 2x in OperationsMetadata (GetCapabilities + GetTile hrefs)
-        // - 2x in ResourceURL templates (256px + 512px layers)
-        // - 1x in ServiceMetadataURL
-        // Total: 5 occurrences
-        assert!(
-            key_count >= 4,
-            "Key should appear in multiple URLs, found {} occurrences",
-            key_count
+        let (png, webp) = tokio::join!(
+            flights.run((14u8, 8192u32, 5461u32, "png"), slow_render(&renders)),
+            flights.run((14u8, 8192u32, 5461u32, "webp"), slow_render(&renders))
         );
+        assert!(png.is_ok() && webp.is_ok());
+        assert_eq!(renders.load(Ordering::SeqCst), 2);
+        assert_eq!(flights.coalesced(), 0);
+
+        // Finished renders are not reused
+        flights
+            .run((14u8, 8192u32, 5461u32, "png"), slow_render(&renders))
+            .await
+            .unwrap();
+        assert_eq!(renders.load(Ordering::SeqCst), 3);
     }
 }
 
 // ============================================================
-// API Key Parameter Tests
+// GeoPackage Source Tests
 // ============================================================
 
-mod key_param_tests {
-    use super::*;
-    use tileserver_rs::{Config, SourceManager};
+mod geopackage_tests {
+    use super::{decode_layers, source_config};
+    use geozero::mvt::tile::Layer;
+    use serde_json::{json, Value};
+    use tileserver_rs::config::SourceConfig;
+    use tileserver_rs::sources::geopackage::GeoPackageSource;
+    use tileserver_rs::sources::{mvt_layer_features, TileSource};
+    use tileserver_rs::SourceManager;
+
+    /// Places and roads in EPSG:4326 with RTree indexes, and a park with a
+    /// hole in EPSG:3857 without one
+    const FIXTURE: &str = "tests/fixtures/geopackage/zurich.gpkg";
+
+    fn config(options: &str) -> SourceConfig {
+        source_config("zurich-gpkg", "geopackage", FIXTURE, options)
+    }
+
+    fn names(layer: &mut Layer) -> Vec<Value> {
+        mvt_layer_features(layer)
+            .into_iter()
+            .map(|feature| feature["properties"]["name"].clone())
+            .collect()
+    }
 
     #[tokio::test]
-    async fn test_tilejson_with_key() {
-        let config =
-            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
-        let sources = SourceManager::from_configs(&config.sources)
+    async fn test_geopackage_serves_every_table_as_a_layer() {
+        let sources = SourceManager::from_configs(&[config("")])
             .await
             .expect("Should load sources");
+        let source = sources.get("zurich-gpkg").unwrap();
+
+        let metadata = source.metadata();
+        assert_eq!(metadata.name, "zurich-gpkg");
+        assert_eq!(source.backend(), "geopackage");
+        let vector_layers = metadata.vector_layers.as_ref().unwrap();
+        let ids: Vec<&str> = vector_layers
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|layer| layer["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids, ["parks", "places", "roads"]);
+        assert_eq!(
+            vector_layers[1]["fields"],
+            json!({ "name": "String", "population": "Number", "station": "Boolean" })
+        );
+        let bounds = metadata.bounds.unwrap();
+        assert_eq!((bounds[0], bounds[1]), (7.4474, 46.948));
+        assert_eq!((bounds[2], bounds[3]), (8.5651, 47.45));
+
+        // Zurich's center at zoom 12: three places, both roads and the park
+        let mut layers = decode_layers(source.as_ref(), 12, 2145, 1434).await;
+        let layer_names: Vec<&str> = layers.iter().map(|layer| layer.name.as_str()).collect();
+        assert_eq!(layer_names, ["parks", "places", "roads"]);
+        assert_eq!(names(&mut layers[0]), ["Platzspitz"]);
+        assert_eq!(
+            names(&mut layers[1]),
+            ["Zürich HB", "Bellevue", "Hardbrücke"]
+        );
+        assert_eq!(names(&mut layers[2]), ["Bahnhofstrasse", "Hardturmstrasse"]);
 
-        let all_metadata = sources.all_metadata();
-        let metadata = all_metadata.first().expect("Should have a source");
+        let station = &mvt_layer_features(&mut layers[1])[0];
+        assert_eq!(station["properties"]["station"], true);
+        assert!(station["properties"].get("population").is_none());
+        assert_eq!(layers[1].features[0].id, Some(1));
+
+        // Nothing in the Atlantic
+        assert!(source.get_tile(12, 1800, 1500).await.unwrap().is_none());
+    }
+
+    /// Tile coordinates of a feature's first point
+    fn first_point(feature: &Value) -> (f64, f64) {
+        let mut coords = &feature["geometry"]["coordinates"];
+        while coords[0].is_array() {
+            coords = &coords[0];
+        }
+        (coords[0].as_f64().unwrap(), coords[1].as_f64().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_geopackage_world_tile_has_every_feature() {
+        let source = GeoPackageSource::from_file(&config("")).await.unwrap();
+
+        let counts = |tile: &[Layer]| {
+            tile.iter()
+                .map(|layer| (layer.name.clone(), layer.features.len()))
+                .collect::<Vec<_>>()
+        };
+
+        // Every point and line; the park is under a tile unit across at
+        // zoom 0, so it collapses like any other ring that small
+        let mut world = decode_layers(&source, 0, 0, 0).await;
+        assert_eq!(
+            counts(&world),
+            [("places".to_string(), 5), ("roads".to_string(), 2)]
+        );
+        assert_eq!(
+            names(&mut world[0]),
+            ["Zürich HB", "Bellevue", "Hardbrücke", "Kloten", "Bern"]
+        );
 
-        // Without key
-        let tilejson_no_key = metadata.to_tilejson("http://localhost:8080");
-        assert!(
-            !tilejson_no_key.tiles[0].contains("?key="),
-            "Should not have key param without key"
+        let switzerland = decode_layers(&source, 4, 8, 5).await;
+        assert_eq!(
+            counts(&switzerland),
+            [
+                ("parks".to_string(), 1),
+                ("places".to_string(), 5),
+                ("roads".to_string(), 2)
+            ]
         );
+    }
 
-        // With key
-        let tilejson_with_key =
-            metadata.to_tilejson_with_key("http://localhost:8080", Some("my_api_key"));
+    #[tokio::test]
+    async fn test_geopackage_reprojects_to_web_mercator() {
+        let source = GeoPackageSource::from_file(&config("")).await.unwrap();
+        let mut zurich = decode_layers(&source, 12, 2145, 1434).await;
+
+        // Zürich HB, stored in EPSG:4326 at 8.5417, 47.3769
+        let station = &mvt_layer_features(&mut zurich[1])[0];
+        assert_eq!(first_point(station), (760.0, 1476.0));
+
+        // The park's corner, stored in EPSG:3857 at 950111.85, 6002020.55
+        // (8.535, 47.366), lands where that longitude and latitude do
+        let park = &mvt_layer_features(&mut zurich[0])[0];
+        let ring = park["geometry"]["coordinates"][0].as_array().unwrap();
         assert!(
-            tilejson_with_key.tiles[0].contains("?key=my_api_key"),
-            "Should have key param in tile URL"
+            ring.iter()
+                .any(|point| (point[0].as_f64(), point[1].as_f64()) == (Some(448.0), Some(2227.0))),
+            "{:?}",
+            ring
         );
     }
 
     #[tokio::test]
-    async fn test_tilejson_key_url_encoding() {
-        let config =
-            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
-        let sources = SourceManager::from_configs(&config.sources)
-            .await
-            .expect("Should load sources");
+    async fn test_geopackage_simplifies_by_zoom() {
+        let vertices = |source: GeoPackageSource| async move {
+            let mut tile = decode_layers(&source, 12, 2145, 1434).await;
+            let roads = tile.iter_mut().find(|layer| layer.name == "roads").unwrap();
+            mvt_layer_features(roads)[0]["geometry"]["coordinates"]
+                .as_array()
+                .unwrap()
+                .len()
+        };
 
-        let all_metadata = sources.all_metadata();
-        let metadata = all_metadata.first().expect("Should have a source");
+        // Bahnhofstrasse has four vertices, nearly in a line at zoom 12
+        let plain = GeoPackageSource::from_file(&config("")).await.unwrap();
+        assert_eq!(vertices(plain).await, 4);
 
-        // Key with special characters should be URL-encoded
-        let tilejson = metadata
-            .to_tilejson_with_key("http://localhost:8080", Some("key with spaces & symbols="));
+        let coarse = config("[simplify]\nroads = { 0 = 256.0, 14 = 0 }");
+        let coarse = GeoPackageSource::from_file(&coarse).await.unwrap();
+        assert_eq!(vertices(coarse).await, 2);
+
+        // Past the last step's zoom, no simplification
+        let fine = config("[simplify]\nroads = { 0 = 256.0, 12 = 0 }");
+        let fine = GeoPackageSource::from_file(&fine).await.unwrap();
+        assert_eq!(vertices(fine).await, 4);
 
+        let Err(e) = GeoPackageSource::from_file(&config("[simplify]\nrivers = 4.0")).await else {
+            panic!("rivers is not a table of the fixture");
+        };
         assert!(
-            tilejson.tiles[0].contains("key%20with%20spaces%20%26%20symbols%3D"),
-            "Key should be URL-encoded in tile URL: {}",
-            tilejson.tiles[0]
+            e.to_string()
+                .contains("no feature table 'rivers' to simplify"),
+            "{}",
+            e
         );
     }
 
-    #[test]
-    fn test_style_info_with_key() {
-        use std::path::PathBuf;
-        use tileserver_rs::styles::Style;
-
-        let style = Style {
-            id: "osm-bright".to_string(),
-            name: "OSM Bright".to_string(),
-            style_json: serde_json::json!({"version": 8}),
-            path: PathBuf::from("data/styles/protomaps-light/style.json"),
-        };
+    #[tokio::test]
+    async fn test_geopackage_clips_to_the_tile() {
+        let source = GeoPackageSource::from_file(&config("table = \"parks\""))
+            .await
+            .unwrap();
+
+        // The park reaches past the bottom of this tile
+        let mut tile = decode_layers(&source, 14, 8580, 5737).await;
+        let park = &mvt_layer_features(&mut tile[0])[0];
+        assert_eq!(park["geometry"]["type"], "Polygon");
+        let rings = park["geometry"]["coordinates"].as_array().unwrap();
+        for ring in rings {
+            for point in ring.as_array().unwrap() {
+                let y = point[1].as_f64().unwrap();
+                assert!((-64.0..=4160.0).contains(&y), "{} is outside the buffer", y);
+            }
+        }
+        let bottom = rings[0]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|point| point[1].as_f64().unwrap())
+            .fold(f64::MIN, f64::max);
+        assert_eq!(bottom, 4160.0);
+    }
 
-        // Without key
-        let info_no_key = style.to_info("http://localhost:8080");
-        assert!(
-            !info_no_key.url.as_ref().unwrap().contains("?key="),
-            "Should not have key param without key"
+    #[tokio::test]
+    async fn test_geopackage_single_table() {
+        let source = GeoPackageSource::from_file(&config("table = \"places\""))
+            .await
+            .unwrap();
+        let metadata = source.metadata();
+        assert_eq!(metadata.name, "Places");
+        assert_eq!(metadata.description.as_deref(), Some("Stations and towns"));
+        assert_eq!(metadata.vector_layers.as_ref().unwrap()[0]["id"], "places");
+
+        let mut zurich = decode_layers(&source, 12, 2145, 1434).await;
+        assert_eq!(zurich.len(), 1);
+        assert_eq!(names(&mut zurich[0]).len(), 3);
+
+        // Bern, on its own at zoom 10
+        let mut bern = decode_layers(&source, 10, 533, 360).await;
+        assert_eq!(names(&mut bern[0]), ["Bern"]);
+        assert_eq!(
+            mvt_layer_features(&mut bern[0])[0]["properties"]["population"],
+            134794
         );
+    }
 
-        // With key
-        let info_with_key = style.to_info_with_key("http://localhost:8080", Some("my_api_key"));
+    #[tokio::test]
+    async fn test_geopackage_config_errors() {
+        let error = |options: &'static str| async move {
+            match GeoPackageSource::from_file(&config(options)).await {
+                Ok(_) => panic!("{} should not load", options),
+                Err(e) => e.to_string(),
+            }
+        };
+
+        let missing = error("table = \"buildings\"").await;
         assert!(
-            info_with_key
-                .url
-                .as_ref()
-                .unwrap()
-                .contains("?key=my_api_key"),
-            "Should have key param in style URL"
+            missing.contains("no feature table 'buildings'"),
+            "{}",
+            missing
         );
-    }
+        assert!(missing.contains("parks, places, roads"), "{}", missing);
 
-    #[test]
-    fn test_style_info_key_url_encoding() {
-        use std::path::PathBuf;
-        use tileserver_rs::styles::Style;
+        let column = error("geometry_column = \"shape\"").await;
+        assert!(column.contains("no column 'shape'"), "{}", column);
 
-        let style = Style {
-            id: "test-style".to_string(),
-            name: "Test Style".to_string(),
-            style_json: serde_json::json!({"version": 8}),
-            path: PathBuf::from("data/styles/test/style.json"),
+        let not_gpkg = source_config(
+            "zurich",
+            "geopackage",
+            "data/tiles/zurich_switzerland.mbtiles",
+            "",
+        );
+        let Err(e) = GeoPackageSource::from_file(&not_gpkg).await else {
+            panic!("an MBTiles file is not a GeoPackage");
         };
+        assert!(e.to_string().contains("is not a GeoPackage"), "{}", e);
+    }
+}
 
-        // Key with special characters should be URL-encoded
-        let info =
-            style.to_info_with_key("http://localhost:8080", Some("key with spaces & symbols="));
+// ============================================================
+// GeoJSON Source Tests
+// ============================================================
 
-        assert!(
-            info.url
-                .as_ref()
-                .unwrap()
-                .contains("key%20with%20spaces%20%26%20symbols%3D"),
-            "Key should be URL-encoded in style URL: {}",
-            info.url.as_ref().unwrap()
-        );
+mod geojson_tests {
+    use super::{decode_layers, source_config};
+    use geozero::mvt::tile::Layer;
+    use serde_json::json;
+    use tileserver_rs::config::SourceConfig;
+    use tileserver_rs::sources::geojson::GeoJsonSource;
+    use tileserver_rs::sources::{mvt_layer_features, TileSource};
+    use tileserver_rs::SourceManager;
+
+    /// An island with a coastline of 4000 vertices, wiggling by a few
+    /// hundredths of a degree, and a lighthouse on it
+    const FIXTURE: &str = "tests/fixtures/geojson/coastline.geojson";
+
+    fn config(options: &str) -> SourceConfig {
+        source_config(
+            "coast",
+            "geojson",
+            FIXTURE,
+            &format!("[options]\n{}", options),
+        )
     }
 
-    #[test]
-    fn test_rewrite_style_for_api_with_key() {
-        use tileserver_rs::styles::{rewrite_style_for_api, UrlQueryParams};
+    async fn layer(source: &dyn TileSource, z: u8, x: u32, y: u32) -> Layer {
+        let mut layers = decode_layers(source, z, x, y).await;
+        assert_eq!(layers.len(), 1);
+        layers.remove(0)
+    }
 
-        let style = serde_json::json!({
-            "version": 8,
-            "sources": {
-                "openmaptiles": {
-                    "type": "vector",
-                    "url": "/data/openmaptiles.json"
+    /// Vertices of the features of a layer, counted from the MoveTo and
+    /// LineTo commands of their geometry
+    fn vertices(layer: &Layer) -> usize {
+        let mut count = 0;
+        for feature in &layer.features {
+            let mut i = 0;
+            while i < feature.geometry.len() {
+                let (command, repeat) = (feature.geometry[i] & 7, feature.geometry[i] >> 3);
+                i += 1;
+                if command != 7 {
+                    count += repeat as usize;
+                    i += 2 * repeat as usize;
                 }
-            },
-            "glyphs": "/fonts/{fontstack}/{range}.pbf",
-            "sprite": "/styles/basic/sprite"
-        });
+            }
+        }
+        count
+    }
 
-        // With key
-        let params = UrlQueryParams::with_key(Some("my_secret_key".to_string()));
-        let result = rewrite_style_for_api(&style, "http://tiles.example.com", &params);
+    #[tokio::test]
+    async fn test_geojson_serves_features_as_one_layer() {
+        let sources = SourceManager::from_configs(&[config("")])
+            .await
+            .expect("Should load sources");
+        let source = sources.get("coast").unwrap();
+        assert_eq!(source.backend(), "geojson");
 
-        // All URLs should have key appended
-        assert_eq!(
-            result["sources"]["openmaptiles"]["url"],
-            "http://tiles.example.com/data/openmaptiles.json?key=my_secret_key"
-        );
-        assert_eq!(
-            result["glyphs"],
-            "http://tiles.example.com/fonts/{fontstack}/{range}.pbf?key=my_secret_key"
-        );
+        let metadata = source.metadata();
+        let vector_layers = metadata.vector_layers.as_ref().unwrap();
+        assert_eq!(vector_layers[0]["id"], "coast");
         assert_eq!(
-            result["sprite"],
-            "http://tiles.example.com/styles/basic/sprite?key=my_secret_key"
+            vector_layers[0]["fields"],
+            json!({ "height": "Number", "kind": "String", "lit": "Boolean", "name": "String" })
         );
+        let bounds = metadata.bounds.unwrap();
+        assert!(bounds[0] > 3.0 && bounds[2] < 19.0, "{:?}", bounds);
+        assert!(bounds[1] > 42.0 && bounds[3] < 54.0, "{:?}", bounds);
+
+        let mut tile = layer(source.as_ref(), 4, 8, 5).await;
+        assert_eq!(tile.name, "coast");
+        let features = mvt_layer_features(&mut tile);
+        assert_eq!(features.len(), 2);
+        assert_eq!(features[0]["geometry"]["type"], "Polygon");
+        assert_eq!(features[1]["properties"]["name"], "Lighthouse");
+        assert_eq!(features[1]["properties"]["lit"], true);
+        assert_eq!(tile.features[1].id, Some(2));
+
+        // Nothing in the Atlantic
+        assert!(source.get_tile(4, 5, 5).await.unwrap().is_none());
     }
 
-    #[test]
-    fn test_rewrite_style_for_api_preserves_external_urls() {
-        use tileserver_rs::styles::{rewrite_style_for_api, UrlQueryParams};
-
-        let style = serde_json::json!({
-            "version": 8,
-            "sources": {
-                "external": {
-                    "type": "vector",
-                    "url": "https://external-tiles.com/tiles.json"
-                }
-            },
-            "glyphs": "https://fonts.example.com/{fontstack}/{range}.pbf"
-        });
-
-        // With key - external URLs should NOT have key appended
-        let params = UrlQueryParams::with_key(Some("my_key".to_string()));
-        let result = rewrite_style_for_api(&style, "http://localhost", &params);
+    #[tokio::test]
+    async fn test_geojson_simplifies_low_zooms() {
+        let tile = |options: &'static str, z: u8, x: u32, y: u32| async move {
+            let source = GeoJsonSource::from_file(&config(options)).await.unwrap();
+            layer(&source, z, x, y).await
+        };
 
-        // External URLs should remain unchanged
-        assert_eq!(
-            result["sources"]["external"]["url"],
-            "https://external-tiles.com/tiles.json"
-        );
-        assert_eq!(
-            result["glyphs"],
-            "https://fonts.example.com/{fontstack}/{range}.pbf"
+        // The coastline at zoom 4 keeps only what a pixel shows
+        let plain = vertices(&tile("simplify_tolerance_factor = 0", 4, 8, 5).await);
+        let simplified = vertices(&tile("", 4, 8, 5).await);
+        assert!(plain > 2000, "{} vertices unsimplified", plain);
+        assert!(
+            simplified * 4 < plain,
+            "{} vertices simplified, {} unsimplified",
+            simplified,
+            plain
         );
-    }
+        let coarse = vertices(&tile("simplify_tolerance_factor = 4.0", 4, 8, 5).await);
+        assert!(coarse < simplified, "{} vertices at 4 pixels", coarse);
 
-    #[test]
-    fn test_key_query_string_generation() {
-        use tileserver_rs::styles::UrlQueryParams;
+        // Past simplify_max_zoom, no simplification
+        let unsimplified = vertices(&tile("simplify_max_zoom = 3", 4, 8, 5).await);
+        assert_eq!(unsimplified, plain);
+    }
 
-        // Empty params
-        let empty = UrlQueryParams::default();
-        assert_eq!(empty.to_query_string(), "");
+    #[tokio::test]
+    async fn test_geojson_clips_to_the_tile() {
+        let source = GeoJsonSource::from_file(&config("")).await.unwrap();
+
+        // The island's west coast, around 4°E 48°N, and the lighthouse
+        // outside of it
+        let mut tile = layer(&source, 7, 65, 44).await;
+        let features = mvt_layer_features(&mut tile);
+        assert_eq!(features.len(), 1);
+        for ring in features[0]["geometry"]["coordinates"].as_array().unwrap() {
+            for point in ring.as_array().unwrap() {
+                for v in [point[0].as_f64().unwrap(), point[1].as_f64().unwrap()] {
+                    assert!((-64.0..=4160.0).contains(&v), "{} is outside the buffer", v);
+                }
+            }
+        }
+    }
 
-        // With key
-        let with_key = UrlQueryParams::with_key(Some("abc123".to_string()));
-        assert_eq!(with_key.to_query_string(), "?key=abc123");
+    #[tokio::test]
+    async fn test_geojson_config_errors() {
+        let Err(e) = GeoJsonSource::from_file(&config("simplify_tolerance_factor = -1.0")).await
+        else {
+            panic!("a negative tolerance is invalid");
+        };
+        assert!(e.to_string().contains("simplify_tolerance_factor"), "{}", e);
 
-        // With key and extra params
-        let with_extra = UrlQueryParams {
-            key: Some("key1".to_string()),
-            extra: vec![("foo".to_string(), "bar".to_string())],
+        let not_json = source_config(
+            "zurich",
+            "geojson",
+            "tests/fixtures/pmtiles/zurich-v2.pmtiles",
+            "",
+        );
+        let Err(e) = GeoJsonSource::from_file(&not_json).await else {
+            panic!("a PMTiles archive is not GeoJSON");
         };
-        assert_eq!(with_extra.to_query_string(), "?key=key1&foo=bar");
+        assert!(e.to_string().contains("is not valid JSON"), "{}", e);
     }
 }
 
-// ============================================================
-// Config Validation Tests
-// ============================================================
-
-mod config_tests {
-    use super::*;
-    use tileserver_rs::Config;
-
-    #[test]
-    fn test_config_loads() {
-        let config = Config::load(Some(PathBuf::from(TEST_CONFIG)));
-        assert!(config.is_ok(), "Should load test config");
+// ============================================================================
+// Connection Limit Tests
+// ============================================================================
+
+mod connection_tests {
+    use axum::{extract::ConnectInfo, routing::get, Router};
+    use std::net::SocketAddr;
+    use std::time::Duration;
+    use tileserver_rs::connections::{self, ConnectionLimits, LimitedListener};
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpStream;
+
+    /// Serve a router echoing the peer address with `limits` on a local port
+    async fn spawn_server(limits: ConnectionLimits) -> SocketAddr {
+        let router = Router::new().route(
+            "/peer",
+            get(|ConnectInfo(peer): ConnectInfo<SocketAddr>| async move {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                peer.to_string()
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let listener = LimitedListener::new(listener, limits);
+        let service = connections::make_service(router, limits);
+        tokio::spawn(async move { axum::serve(listener, service).await.unwrap() });
+        addr
     }
 
-    #[test]
-    fn test_config_has_server_section() {
-        let config =
-            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
-        // Port defaults to 8080 if not specified
-        // Host defaults to "0.0.0.0" if not specified
-        assert!(!config.server.host.is_empty());
+    struct RawResponse {
+        status: u16,
+        headers: Vec<(String, String)>,
+        body: String,
     }
 
-    #[test]
-    fn test_config_sources_valid() {
-        let config =
-            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+    impl RawResponse {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+        }
+    }
 
-        for source in &config.sources {
-            assert!(!source.id.is_empty(), "Source should have ID");
-            // Path should exist or be a URL
-            if !source.path.starts_with("http") {
-                assert!(
-                    PathBuf::from(&source.path).exists(),
-                    "Source path should exist: {}",
-                    source.path
-                );
-            }
+    /// Send `GET /peer` on a persistent connection and read the response
+    async fn get_peer(stream: &mut BufReader<TcpStream>) -> RawResponse {
+        stream
+            .get_mut()
+            .write_all(b"GET /peer HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut line = String::new();
+        stream.read_line(&mut line).await.unwrap();
+        let status = line.split(' ').nth(1).unwrap().parse().unwrap();
+
+        let mut headers = Vec::new();
+        loop {
+            line.clear();
+            stream.read_line(&mut line).await.unwrap();
+            let Some((key, value)) = line.trim_end().split_once(':') else {
+                break;
+            };
+            headers.push((key.to_string(), value.trim().to_string()));
         }
+        let mut response = RawResponse {
+            status,
+            headers,
+            body: String::new(),
+        };
+
+        let length: usize = response.header("content-length").unwrap().parse().unwrap();
+        let mut body = vec![0; length];
+        stream.read_exact(&mut body).await.unwrap();
+        response.body = String::from_utf8(body).unwrap();
+        response
     }
 
-    #[test]
-    fn test_config_styles_valid() {
-        let config =
-            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+    async fn connect(addr: SocketAddr) -> BufReader<TcpStream> {
+        BufReader::new(TcpStream::connect(addr).await.unwrap())
+    }
 
-        for style in &config.styles {
-            assert!(!style.id.is_empty(), "Style should have ID");
-            assert!(
-                style.path.exists(),
-                "Style path should exist: {}",
-                style.path.display()
-            );
+    /// Whether the server closes the connection within `within`
+    async fn closed_within(stream: &mut BufReader<TcpStream>, within: Duration) -> bool {
+        let mut buf = [0; 1];
+        matches!(
+            tokio::time::timeout(within, stream.read(&mut buf)).await,
+            Ok(Ok(0) | Err(_))
+        )
+    }
+
+    #[tokio::test]
+    async fn test_persistent_connections_under_load() {
+        let limits = ConnectionLimits {
+            keepalive_timeout_secs: 5,
+            keepalive_max_requests: 1000,
+            max_connections: 10_000,
+        };
+        let addr = spawn_server(limits).await;
+
+        let clients = (0..100).map(|_| {
+            tokio::spawn(async move {
+                let mut stream = connect(addr).await;
+                let local = stream.get_ref().local_addr().unwrap().to_string();
+                for served in 0..3 {
+                    let response =
+                        tokio::time::timeout(Duration::from_secs(5), get_peer(&mut stream))
+                            .await
+                            .expect("Every connection should be answered within the timeout");
+                    assert_eq!(response.status, 200);
+                    assert_eq!(response.body, local);
+                    assert_eq!(
+                        response.header("keep-alive"),
+                        Some(format!("timeout=5, max={}", 1000 - served).as_str())
+                    );
+                    assert_eq!(response.header("connection"), None);
+                }
+            })
+        });
+        for client in futures::future::join_all(clients).await {
+            client.unwrap();
         }
     }
 
-    #[test]
-    fn test_config_cors_settings() {
-        let config =
-            Config::load(Some(PathBuf::from(TEST_CONFIG))).expect("Should load test config");
+    #[tokio::test]
+    async fn test_idle_connections_are_closed() {
+        let limits = ConnectionLimits {
+            keepalive_timeout_secs: 1,
+            ..Default::default()
+        };
+        let addr = spawn_server(limits).await;
+
+        let mut stream = connect(addr).await;
+        let response = get_peer(&mut stream).await;
+        assert_eq!(response.header("keep-alive"), Some("timeout=1"));
+        assert!(closed_within(&mut stream, Duration::from_secs(3)).await);
+
+        // Without a timeout, idle connections stay open
+        let addr = spawn_server(ConnectionLimits::default()).await;
+        let mut stream = connect(addr).await;
+        let response = get_peer(&mut stream).await;
+        assert_eq!(response.header("keep-alive"), None);
+        assert!(!closed_within(&mut stream, Duration::from_millis(1500)).await);
+    }
 
-        // CORS origins should be defined
-        assert!(
-            !config.server.cors_origins.is_empty(),
-            "Should have CORS origins"
-        );
+    #[tokio::test]
+    async fn test_max_requests_closes_connection() {
+        let limits = ConnectionLimits {
+            keepalive_max_requests: 2,
+            ..Default::default()
+        };
+        let addr = spawn_server(limits).await;
+
+        let mut stream = connect(addr).await;
+        let first = get_peer(&mut stream).await;
+        assert_eq!(first.header("keep-alive"), Some("max=2"));
+        let last = get_peer(&mut stream).await;
+        assert_eq!(last.status, 200);
+        assert_eq!(last.header("connection"), Some("close"));
+        assert!(closed_within(&mut stream, Duration::from_secs(1)).await);
+    }
+
+    #[tokio::test]
+    async fn test_connections_over_limit_get_503() {
+        let limits = ConnectionLimits {
+            max_connections: 1,
+            ..Default::default()
+        };
+        let addr = spawn_server(limits).await;
+
+        let mut first = connect(addr).await;
+        assert_eq!(get_peer(&mut first).await.status, 200);
+
+        let mut second = connect(addr).await;
+        let refused = get_peer(&mut second).await;
+        assert_eq!(refused.status, 503);
+        assert_eq!(refused.header("connection"), Some("close"));
+        assert_eq!(refused.header("retry-after"), Some("1"));
+        let json: serde_json::Value = serde_json::from_str(&refused.body).unwrap();
+        assert_eq!(json["error"]["code"], "TOO_MANY_CONNECTIONS");
+        assert!(closed_within(&mut second, Duration::from_secs(1)).await);
+
+        // Closing the first connection makes room for another
+        drop(first);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let mut third = connect(addr).await;
+        assert_eq!(get_peer(&mut third).await.status, 200);
     }
 }
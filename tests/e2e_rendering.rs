@@ -297,7 +297,7 @@ mod polyline_rendering {
 
 mod json_snapshots {
     use std::path::PathBuf;
-    use tileserver_rs::{Config, SourceManager, StyleManager};
+    use tileserver_rs::{Config, SourceManager, StyleManager, TileJsonVersion};
 
     #[tokio::test]
     async fn test_tilejson_snapshot() {
@@ -308,7 +308,9 @@ mod json_snapshots {
             .expect("Should load sources");
 
         let protomaps = sources.get("protomaps").expect("Should have protomaps");
-        let tilejson = protomaps.metadata().to_tilejson("http://localhost:8080");
+        let tilejson = protomaps
+            .metadata()
+            .to_tilejson("http://localhost:8080", TileJsonVersion::default());
 
         // Convert to JSON for snapshot
         let json = serde_json::to_value(&tilejson).expect("Should serialize");
@@ -340,7 +342,7 @@ mod json_snapshots {
         let mut all_metadata: Vec<_> = sources
             .all_metadata()
             .iter()
-            .map(|m| m.to_tilejson("http://localhost:8080"))
+            .map(|m| m.to_tilejson("http://localhost:8080", TileJsonVersion::default()))
             .collect();
 
         // Sort by id for consistent ordering in snapshots
@@ -548,7 +550,7 @@ mod raster_snapshots {
 #[cfg(feature = "raster")]
 mod raster_json_snapshots {
     use std::path::PathBuf;
-    use tileserver_rs::{Config, SourceManager};
+    use tileserver_rs::{Config, SourceManager, TileJsonVersion};
 
     const RASTER_TEST_CONFIG: &str = "tests/config.raster.toml";
 
@@ -561,7 +563,9 @@ mod raster_json_snapshots {
             .expect("Should load sources");
 
         let rgb_source = sources.get("test-rgb").expect("Should have test-rgb");
-        let tilejson = rgb_source.metadata().to_tilejson("http://localhost:8080");
+        let tilejson = rgb_source
+            .metadata()
+            .to_tilejson("http://localhost:8080", TileJsonVersion::default());
 
         let json = serde_json::to_value(&tilejson).expect("Should serialize");
 
@@ -579,7 +583,7 @@ mod raster_json_snapshots {
         let mut all_metadata: Vec<_> = sources
             .all_metadata()
             .iter()
-            .map(|m| m.to_tilejson("http://localhost:8080"))
+            .map(|m| m.to_tilejson("http://localhost:8080", TileJsonVersion::default()))
             .collect();
 
         all_metadata.sort_by(|a, b| a.id.cmp(&b.id));
@@ -248,6 +248,8 @@ mod cache_control {
 // ============================================================
 
 mod sprite_security {
+    use tileserver_rs::styles::sprite::SpriteFile;
+
     #[test]
     fn test_sprite_filename_validation() {
         let valid_sprites = [
@@ -255,26 +257,31 @@ mod sprite_security {
             "sprite.json",
             "sprite@2x.png",
             "sprite@2x.json",
+            "sprite@3x.png",
         ];
 
         let invalid_sprites = [
             "../sprite.png",
             "sprite/../secret.png",
+            "sprite/../../etc",
             "/etc/passwd",
             "sprite.exe",
         ];
 
         for sprite in valid_sprites {
-            assert!(sprite.starts_with("sprite"));
-            assert!(sprite.ends_with(".png") || sprite.ends_with(".json"));
-            assert!(!sprite.contains(".."));
+            assert!(
+                SpriteFile::parse(sprite).is_some(),
+                "Sprite '{}' should be valid",
+                sprite
+            );
         }
 
         for sprite in invalid_sprites {
-            let is_invalid = sprite.contains("..")
-                || sprite.contains('/')
-                || (!sprite.ends_with(".png") && !sprite.ends_with(".json"));
-            assert!(is_invalid, "Sprite '{}' should be invalid", sprite);
+            assert!(
+                SpriteFile::parse(sprite).is_none(),
+                "Sprite '{}' should be invalid",
+                sprite
+            );
         }
     }
 }
@@ -717,7 +724,7 @@ mod overlay_integration {
 
 mod async_source_tests {
     use std::path::PathBuf;
-    use tileserver_rs::{Config, SourceManager};
+    use tileserver_rs::{Config, SourceManager, TileJsonVersion};
 
     #[tokio::test]
     async fn test_load_sources_from_config() {
@@ -813,7 +820,9 @@ mod async_source_tests {
             .expect("Should have protomaps source");
         let base_url = "http://localhost:8080";
 
-        let tilejson = protomaps.metadata().to_tilejson(base_url);
+        let tilejson = protomaps
+            .metadata()
+            .to_tilejson(base_url, TileJsonVersion::default());
 
         assert_eq!(tilejson.tilejson, "3.0.0");
         assert!(!tilejson.tiles.is_empty());
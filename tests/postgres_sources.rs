@@ -158,6 +158,8 @@ mod validation_tests {
             minzoom: 5,
             maxzoom: 15,
             bounds: None,
+            allowed_params: None,
+            resilience: None,
         };
 
         assert_eq!(func.minzoom, 5);
@@ -399,6 +401,7 @@ mod integration_tests {
             wait_timeout_ms: 5000,
             create_timeout_ms: 5000,
             recycle_timeout_ms: 5000,
+            statement_timeout_ms: 0,
             pre_warm: false,
         }
     }
@@ -447,6 +450,8 @@ mod integration_tests {
             minzoom: 0,
             maxzoom: 14,
             bounds: Some([8.45, 47.32, 8.63, 47.44]),
+            allowed_params: None,
+            resilience: None,
         };
 
         let source = PostgresFunctionSource::new(pool, &config, None).await;
@@ -486,6 +491,8 @@ mod integration_tests {
             minzoom: 0,
             maxzoom: 14,
             bounds: Some([8.45, 47.32, 8.63, 47.44]),
+            allowed_params: None,
+            resilience: None,
         };
 
         let source = match PostgresFunctionSource::new(pool, &config, None).await {
@@ -538,6 +545,8 @@ mod integration_tests {
             minzoom: 5,
             maxzoom: 10,
             bounds: Some([8.45, 47.32, 8.63, 47.44]),
+            allowed_params: None,
+            resilience: None,
         };
 
         let source = match PostgresFunctionSource::new(pool, &config, None).await {
@@ -583,6 +592,8 @@ mod integration_tests {
             minzoom: 0,
             maxzoom: 14,
             bounds: Some([8.45, 47.32, 8.63, 47.44]),
+            allowed_params: Some(vec!["category".to_string()]),
+            resilience: None,
         };
 
         let source = PostgresFunctionSource::new(pool, &config, None).await;
@@ -593,6 +604,15 @@ mod integration_tests {
                     source.supports_query_params(),
                     "Function should support query params"
                 );
+
+                let all = serde_json::json!({});
+                let parks = serde_json::json!({ "category": "parks", "debug": "1" });
+                for params in [&all, &parks] {
+                    let result = source
+                        .get_tile_with_query_params(12, 2145, 1434, params)
+                        .await;
+                    assert!(result.is_ok(), "Should fetch tile with {}", params);
+                }
             }
             Err(e) => {
                 eprintln!("Skipping test - function not available: {}", e);
@@ -622,6 +642,8 @@ mod integration_tests {
             minzoom: 0,
             maxzoom: 14,
             bounds: None,
+            allowed_params: None,
+            resilience: None,
         };
 
         let source = match PostgresFunctionSource::new(pool, &config, None).await {
@@ -658,6 +680,8 @@ mod integration_tests {
             minzoom: 0,
             maxzoom: 14,
             bounds: Some([8.45, 47.32, 8.63, 47.44]),
+            allowed_params: None,
+            resilience: None,
         };
 
         let source = match PostgresFunctionSource::new(pool, &config, None).await {
@@ -681,4 +705,129 @@ mod integration_tests {
             );
         }
     }
+
+    #[tokio::test]
+    async fn test_postgres_auto_publish() {
+        let config: tileserver_rs::PostgresConfig = toml::from_str(&format!(
+            r#"
+                connection_string = "{}"
+                pool_size = 5
+                pool_pre_warm = false
+
+                [auto_publish]
+                schemas = ["public"]
+                exclude = ["tmp_*"]
+            "#,
+            get_connection_string()
+        ))
+        .unwrap();
+
+        let manager = match tileserver_rs::SourceManager::from_configs_with_postgres(
+            &[],
+            tileserver_rs::sources::manager::DEFAULT_SOURCE_LOAD_CONCURRENCY,
+            Some(&config),
+        )
+        .await
+        {
+            Ok(manager) => manager,
+            Err(e) => {
+                eprintln!("Skipping test - database not available: {}", e);
+                return;
+            }
+        };
+
+        let Some(source) = manager.get("public.benchmark_points") else {
+            eprintln!("Skipping test - benchmark_points table not available");
+            return;
+        };
+        let metadata = source.metadata();
+        assert!(metadata.bounds.is_some());
+        let fields = &metadata.vector_layers.as_ref().unwrap()[0]["fields"];
+        assert_eq!(fields["id"], "Number");
+        assert_eq!(fields["name"], "String");
+        assert_eq!(fields["category"], "String");
+    }
+
+    #[tokio::test]
+    async fn test_postgres_cache_invalidation_listener() {
+        let conn_str = get_connection_string();
+        let config: tileserver_rs::PostgresConfig = toml::from_str(&format!(
+            r#"
+                connection_string = "{}"
+                pool_size = 5
+                pool_pre_warm = false
+                cache_invalidation_channel = "tiles_changed_test"
+
+                [cache]
+                size_mb = 16
+
+                [[tables]]
+                id = "cached_points"
+                table = "benchmark_points"
+            "#,
+            conn_str
+        ))
+        .unwrap();
+
+        let manager = match tileserver_rs::SourceManager::from_configs_with_postgres(
+            &[],
+            tileserver_rs::sources::manager::DEFAULT_SOURCE_LOAD_CONCURRENCY,
+            Some(&config),
+        )
+        .await
+        {
+            Ok(manager) => manager,
+            Err(e) => {
+                eprintln!("Skipping test - database not available: {}", e);
+                return;
+            }
+        };
+        let Some(source) = manager.get("cached_points") else {
+            eprintln!("Skipping test - benchmark_points table not available");
+            return;
+        };
+
+        let (client, connection) = tokio_postgres::connect(&conn_str, tokio_postgres::NoTls)
+            .await
+            .unwrap();
+        tokio::spawn(connection);
+
+        let entries = || async { manager.cache_stats().await.unwrap().entries };
+        source.get_tile(0, 0, 0).await.unwrap();
+        source.get_tile(1, 0, 0).await.unwrap();
+        if entries().await == 0 {
+            eprintln!("Skipping test - benchmark_points has no tiles to cache");
+            return;
+        }
+
+        // The listener connects in the background, so notify until it evicts
+        let notify = |payload: &'static str| {
+            let client = &client;
+            async move {
+                for _ in 0..50 {
+                    let before = entries().await;
+                    client
+                        .execute("SELECT pg_notify('tiles_changed_test', $1)", &[&payload])
+                        .await
+                        .unwrap();
+                    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                    if entries().await < before {
+                        return;
+                    }
+                }
+                panic!("Notification {:?} didn't evict any tiles", payload);
+            }
+        };
+
+        // The world tile's eviction takes the zoom 1 tile below it along
+        notify("cached_points:0/0/0").await;
+        assert_eq!(entries().await, 0);
+
+        // The next fetch misses the cache and refills it
+        source.get_tile(0, 0, 0).await.unwrap();
+        assert_eq!(entries().await, 1);
+
+        notify("cached_points").await;
+        assert_eq!(entries().await, 0);
+    }
 }
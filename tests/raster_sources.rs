@@ -59,7 +59,7 @@ mod fixture_validation {
 
 mod cog_source_loading {
     use super::*;
-    use tileserver_rs::{Config, SourceManager};
+    use tileserver_rs::{Config, SourceManager, TileJsonVersion};
 
     #[tokio::test]
     async fn test_load_cog_sources_from_config() {
@@ -138,7 +138,9 @@ mod cog_source_loading {
             .expect("Should have test-rgb source");
         let base_url = "http://localhost:8080";
 
-        let tilejson = rgb_source.metadata().to_tilejson(base_url);
+        let tilejson = rgb_source
+            .metadata()
+            .to_tilejson(base_url, TileJsonVersion::default());
 
         assert_eq!(tilejson.tilejson, "3.0.0");
         assert!(!tilejson.tiles.is_empty());
@@ -471,6 +473,179 @@ mod resampling_tests {
     }
 }
 
+mod overview_tests {
+    use super::*;
+    use gdal::raster::Buffer;
+    use gdal::spatial_ref::SpatialRef;
+    use gdal::DriverManager;
+    use std::collections::HashSet;
+    use std::hash::{Hash, Hasher};
+    use tileserver_rs::config::{ResamplingMethod, SourceConfig};
+    use tileserver_rs::sources::cog::CogSource;
+    use tileserver_rs::{Config, SourceManager};
+
+    const CLASSES: [u8; 4] = [10, 20, 30, 40];
+
+    /// A 512px GeoTIFF over the extent of the other fixtures, holding the
+    /// classes in 8px squares that differ from each of their neighbours,
+    /// with overviews at 1/2, 1/4 and 1/8
+    fn categorical_tiff(dir: &Path) -> PathBuf {
+        let path = dir.join("classes.tif");
+        let driver = DriverManager::get_driver_by_name("GTiff").unwrap();
+        let mut dataset = driver
+            .create_with_band_type::<u8, _>(&path, 512, 512, 1)
+            .unwrap();
+        dataset
+            .set_geo_transform(&[-122.5, 0.000390625, 0.0, 37.9, 0.0, -0.000390625])
+            .unwrap();
+        dataset
+            .set_spatial_ref(&SpatialRef::from_epsg(4326).unwrap())
+            .unwrap();
+        let classes: Vec<u8> = (0..512 * 512)
+            .map(|i| {
+                let (row, col) = (i / 512, i % 512);
+                CLASSES[(col / 8 + 2 * (row / 8)) % 4]
+            })
+            .collect();
+        dataset
+            .rasterband(1)
+            .unwrap()
+            .write((0, 0), (512, 512), &mut Buffer::new((512, 512), classes))
+            .unwrap();
+        dataset.build_overviews("NEAREST", &[2, 4, 8], &[]).unwrap();
+        path
+    }
+
+    async fn categorical_source(path: &Path, options: &str) -> CogSource {
+        let config: SourceConfig = toml::from_str(&format!(
+            r##"
+                id = "classes"
+                type = "cog"
+                path = "{}"
+                {}
+
+                [colormap]
+                map_type = "discrete"
+                nodata_color = "#00000000"
+                entries = [
+                    {{ value = 10.0, color = "#ff0000" }},
+                    {{ value = 20.0, color = "#00ff00" }},
+                    {{ value = 30.0, color = "#0000ff" }},
+                    {{ value = 40.0, color = "#ffff00" }},
+                ]
+            "##,
+            path.display(),
+            options
+        ))
+        .unwrap();
+        CogSource::from_file(&config).await.unwrap()
+    }
+
+    async fn rgba_tile(
+        cog: &CogSource,
+        (z, x, y): (u8, u32, u32),
+        resampling: ResamplingMethod,
+    ) -> image::RgbaImage {
+        let tile = cog
+            .get_tile_with_resampling(z, x, y, 256, resampling)
+            .await
+            .expect("Should get tile")
+            .expect("Should have tile data");
+        image::load_from_memory(&tile.data)
+            .expect("Should decode PNG")
+            .to_rgba8()
+    }
+
+    /// A z14 tile inside the fixtures, where their pixels are magnified
+    const MAGNIFIED: (u8, u32, u32) = (14, 2621, 6331);
+
+    #[tokio::test]
+    async fn test_resampling_methods_differ() {
+        let config = Config::load(Some(PathBuf::from(RASTER_TEST_CONFIG)))
+            .expect("Should load raster test config");
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
+        let cog = sources
+            .get("test-dem")
+            .and_then(|s| s.as_any().downcast_ref::<CogSource>())
+            .expect("test-dem should be a CogSource");
+
+        let mut hashes = HashSet::new();
+        for resampling in [
+            ResamplingMethod::Nearest,
+            ResamplingMethod::Bilinear,
+            ResamplingMethod::Cubic,
+            ResamplingMethod::Lanczos,
+        ] {
+            let tile = rgba_tile(cog, MAGNIFIED, resampling).await;
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            tile.as_raw().hash(&mut hasher);
+            assert!(
+                hashes.insert(hasher.finish()),
+                "{} renders the same tile as another method",
+                resampling
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_nearest_keeps_categories() {
+        let dir = tempfile::tempdir().unwrap();
+        let cog = categorical_source(&categorical_tiff(dir.path()), "").await;
+        let palette = [
+            [255, 0, 0, 255],
+            [0, 255, 0, 255],
+            [0, 0, 255, 255],
+            [255, 255, 0, 255],
+        ];
+
+        // Every pixel keeps one of the classes
+        let nearest = rgba_tile(&cog, MAGNIFIED, ResamplingMethod::Nearest).await;
+        let colors: HashSet<[u8; 4]> = nearest.pixels().map(|p| p.0).collect();
+        assert!(colors.len() > 1, "{:?}", colors);
+        assert!(colors.iter().all(|c| palette.contains(c)), "{:?}", colors);
+
+        // Blending neighbouring classes makes values that are none of them
+        let bilinear = rgba_tile(&cog, MAGNIFIED, ResamplingMethod::Bilinear).await;
+        assert!(bilinear.pixels().any(|p| !palette.contains(&p.0)));
+    }
+
+    #[tokio::test]
+    async fn test_overview_selection() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = categorical_tiff(dir.path());
+
+        // Pixels are 43.5 m at full resolution, then 87 m, 174 m and 348 m
+        let cog = categorical_source(&path, "").await;
+        assert_eq!(cog.overview_for(12, 256), None);
+        assert_eq!(cog.overview_for(11, 256), None);
+        assert_eq!(cog.overview_for(10, 256), Some(0));
+        assert_eq!(cog.overview_for(9, 256), Some(1));
+        assert_eq!(cog.overview_for(8, 256), Some(2));
+        assert_eq!(cog.overview_for(0, 256), Some(2));
+
+        // @2x tiles have pixels half as large, so they read finer overviews
+        assert_eq!(cog.overview_for(10, 512), None);
+        assert_eq!(cog.overview_for(9, 512), Some(0));
+        assert_eq!(cog.overview_for(8, 512), Some(1));
+
+        let coarse = categorical_source(&path, "overview_bias = 1.0").await;
+        assert_eq!(coarse.overview_for(11, 256), Some(0));
+        assert_eq!(coarse.overview_for(10, 256), Some(1));
+        let fine = categorical_source(&path, "overview_bias = -1.0").await;
+        assert_eq!(fine.overview_for(10, 256), None);
+        assert_eq!(fine.overview_for(9, 256), Some(0));
+
+        // Tiles read from an overview keep the classes too
+        let tile = rgba_tile(&cog, (8, 40, 98), ResamplingMethod::Nearest).await;
+        assert!(tile.pixels().any(|p| p.0 == [255, 0, 0, 255]));
+
+        let fixture = categorical_source(Path::new("data/raster/test-dem.cog.tif"), "").await;
+        assert_eq!(fixture.overview_for(0, 256), None, "no overviews");
+    }
+}
+
 mod reprojection_tests {
     use super::*;
     use tileserver_rs::{Config, SourceManager};
@@ -613,12 +788,25 @@ mod error_handling {
     async fn test_invalid_cog_path() {
         let config = SourceConfig {
             id: "invalid".to_string(),
+            aliases: Vec::new(),
             source_type: SourceType::Cog,
             path: "nonexistent/path/to/file.tif".to_string(),
             name: None,
             attribution: None,
+            scheme: Default::default(),
+            optional: false,
+            overzoom: false,
             resampling: None,
             colormap: None,
+            nodata_elevation: None,
+            encoding: None,
+            overview_bias: None,
+            relief: Default::default(),
+            proxy: Default::default(),
+            geopackage: Default::default(),
+            tile_limits: Default::default(),
+            options: Default::default(),
+            resilience: None,
         };
 
         let result = CogSource::from_file(&config).await;
@@ -645,6 +833,326 @@ mod error_handling {
     }
 }
 
+mod terrain_rgb_tests {
+    use super::*;
+    use tileserver_rs::sources::cog::CogSource;
+    use tileserver_rs::sources::terrain::{decode_elevation, decode_terrarium};
+    use tileserver_rs::{Config, SourceManager};
+
+    async fn load_sources() -> SourceManager {
+        let config = Config::load(Some(PathBuf::from(RASTER_TEST_CONFIG)))
+            .expect("Should load raster test config");
+        SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources")
+    }
+
+    /// Elevations of every pixel of a Terrain-RGB tile
+    fn decode_tile(png: &[u8]) -> Vec<f64> {
+        let img = image::load_from_memory(png)
+            .expect("Should decode PNG")
+            .to_rgb8();
+        assert_eq!(img.dimensions(), (256, 256));
+        img.pixels().map(|p| decode_elevation(p.0)).collect()
+    }
+
+    #[tokio::test]
+    async fn test_terrain_rgb_source_encodes_dem() {
+        let sources = load_sources().await;
+        let terrain = sources
+            .get("test-terrain")
+            .expect("Should have test-terrain source");
+
+        let tile = terrain
+            .get_tile(0, 0, 0)
+            .await
+            .expect("Should get tile")
+            .expect("Should have tile data");
+        let elevations = decode_tile(&tile.data);
+
+        // The fixture covers a small area; the rest of the world is no-data
+        let nodata = elevations.iter().filter(|&&e| e == -10.0).count();
+        assert!(nodata > 0, "Pixels outside the DEM should be no-data");
+        assert!(nodata < elevations.len(), "Pixels inside should be encoded");
+        for elevation in elevations.iter().filter(|&&e| e != -10.0) {
+            assert!(
+                (-500.0..=9000.0).contains(elevation),
+                "Unexpected elevation {}",
+                elevation
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_terrain_rgb_route_matches_source() {
+        let sources = load_sources().await;
+
+        // Any COG can be read as elevation, matching a terrain-rgb source
+        let from_cog = sources
+            .get_terrain_rgb_tile("test-dem", 0, 0, 0, 256)
+            .await
+            .expect("Should get tile")
+            .expect("Should have tile data");
+        let from_terrain = sources
+            .get_terrain_rgb_tile("test-terrain", 0, 0, 0, 256)
+            .await
+            .expect("Should get tile")
+            .expect("Should have tile data");
+
+        let cog = decode_tile(&from_cog.data);
+        let terrain = decode_tile(&from_terrain.data);
+        for (a, b) in cog.iter().zip(&terrain) {
+            // Only the no-data sentinel differs: 0 by default, -10 configured
+            if *b != -10.0 {
+                assert_eq!(a, b);
+            } else {
+                assert_eq!(*a, 0.0);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_terrarium_matches_terrain_rgb() {
+        let sources = load_sources().await;
+        let tile = |id: &'static str| {
+            let sources = &sources;
+            async move {
+                sources
+                    .get(id)
+                    .expect("Should have source")
+                    .get_tile(0, 0, 0)
+                    .await
+                    .expect("Should get tile")
+                    .expect("Should have tile data")
+            }
+        };
+
+        let terrain = decode_tile(&tile("test-terrain").await.data);
+        let terrarium = image::load_from_memory(&tile("test-terrarium").await.data)
+            .expect("Should decode PNG")
+            .to_rgb8();
+        assert_eq!(terrarium.dimensions(), (256, 256));
+        for (pixel, expected) in terrarium.pixels().zip(&terrain) {
+            let elevation = decode_terrarium(pixel.0);
+            // Both are within half a step of the DEM, no-data is -10 in both
+            assert!(
+                (elevation - expected).abs() <= 0.05 + 1.0 / 512.0 + 1e-9,
+                "{} vs {}",
+                elevation,
+                expected
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tilejson_advertises_encoding() {
+        let sources = load_sources().await;
+        let encoding = |id: &str| {
+            sources
+                .get(id)
+                .expect("Should have source")
+                .metadata()
+                .to_tilejson("http://localhost:8080", Default::default())
+                .encoding
+        };
+
+        assert_eq!(encoding("test-terrain").as_deref(), Some("mapbox"));
+        assert_eq!(encoding("test-terrarium").as_deref(), Some("terrarium"));
+        assert_eq!(encoding("test-dem"), None);
+    }
+
+    #[tokio::test]
+    async fn test_terrarium_config_errors() {
+        let error = |options: &'static str| async move {
+            let config: tileserver_rs::config::SourceConfig = toml::from_str(&format!(
+                "id = \"dem\"\npath = \"data/raster/test-dem.cog.tif\"\n{}",
+                options
+            ))
+            .unwrap();
+            match CogSource::from_file(&config).await {
+                Ok(_) => panic!("{} should not load", options),
+                Err(e) => e.to_string(),
+            }
+        };
+
+        let terrain = error("type = \"terrain-rgb\"\nencoding = \"terrarium\"").await;
+        assert!(terrain.contains("always Terrain-RGB"), "{}", terrain);
+        let derived = error("type = \"cog\"\nencoding = \"terrarium\"\nderived = \"slope\"").await;
+        assert!(
+            derived.contains("`derived` is not supported"),
+            "{}",
+            derived
+        );
+    }
+
+    #[tokio::test]
+    async fn test_terrain_rgb_rejects_other_sources() {
+        let config = Config::load(Some(PathBuf::from("tests/config.test.toml")))
+            .expect("Should load test config");
+        let sources = SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources");
+
+        let err = sources
+            .get_terrain_rgb_tile("protomaps", 0, 0, 0, 256)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            tileserver_rs::error::TileServerError::BadRequest(_)
+        ));
+    }
+}
+
+mod relief_tests {
+    use super::*;
+    use tileserver_rs::config::SourceConfig;
+    use tileserver_rs::sources::cog::CogSource;
+    use tileserver_rs::{Config, SourceManager};
+
+    /// Row of zoom 13 tiles over San Francisco, inside the DEM
+    const Y: u32 = 3165;
+
+    async fn load_sources() -> SourceManager {
+        let config = Config::load(Some(PathBuf::from(RASTER_TEST_CONFIG)))
+            .expect("Should load raster test config");
+        SourceManager::from_configs(&config.sources)
+            .await
+            .expect("Should load sources")
+    }
+
+    /// Gray levels of a zoom 13 tile, which covers no no-data
+    async fn gray_tile(sources: &SourceManager, id: &str, x: u32, y: u32) -> image::GrayImage {
+        let tile = sources
+            .get(id)
+            .expect("Should have source")
+            .get_tile(13, x, y)
+            .await
+            .expect("Should get tile")
+            .expect("Should have tile data");
+        let img = image::load_from_memory(&tile.data).expect("Should decode PNG");
+        assert_eq!(img.color(), image::ColorType::L8, "{} is not grayscale", id);
+        img.to_luma8()
+    }
+
+    fn mean(values: impl Iterator<Item = f64>) -> f64 {
+        let (sum, count) = values.fold((0.0, 0), |(sum, count), v| (sum + v, count + 1));
+        sum / count as f64
+    }
+
+    /// Mean difference between two columns of gray levels
+    fn column_difference(
+        a: &image::GrayImage,
+        col_a: u32,
+        b: &image::GrayImage,
+        col_b: u32,
+    ) -> f64 {
+        mean((0..a.height()).map(|row| {
+            (a.get_pixel(col_a, row).0[0] as f64 - b.get_pixel(col_b, row).0[0] as f64).abs()
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_hillshade_is_grayscale_relief() {
+        let sources = load_sources().await;
+        let tile = gray_tile(&sources, "test-hillshade", 1309, Y).await;
+        assert_eq!(tile.dimensions(), (256, 256));
+
+        // Hills, not a flat or saturated tile: flat ground under the
+        // default 45 degree light is 180
+        let levels: Vec<f64> = tile.pixels().map(|p| p.0[0] as f64).collect();
+        let average = mean(levels.iter().copied());
+        let spread = mean(levels.iter().map(|l| (l - average).abs()));
+        assert!((100.0..=240.0).contains(&average), "mean {}", average);
+        assert!(spread > 2.0, "spread {}", spread);
+        assert!(levels.iter().any(|&l| l > 180.0) && levels.iter().any(|&l| l < 180.0));
+    }
+
+    #[tokio::test]
+    async fn test_slope_is_grayscale_relief() {
+        let sources = load_sources().await;
+        let tile = gray_tile(&sources, "test-slope", 1309, Y).await;
+
+        // White is flat and black vertical; these hills are gentle
+        let average = mean(tile.pixels().map(|p| p.0[0] as f64));
+        assert!((128.0..255.0).contains(&average), "mean {}", average);
+    }
+
+    #[tokio::test]
+    async fn test_hillshade_tiles_meet_without_seams() {
+        let sources = load_sources().await;
+        let left = gray_tile(&sources, "test-hillshade", 1309, Y).await;
+        let right = gray_tile(&sources, "test-hillshade", 1310, Y).await;
+        let below = gray_tile(&sources, "test-hillshade", 1309, Y + 1).await;
+
+        // Across the seam, neighbouring pixels differ no more than they do
+        // within a tile
+        let within = column_difference(&left, 254, &left, 255);
+        let across = column_difference(&left, 255, &right, 0);
+        assert!(
+            across <= within * 2.0 + 2.0,
+            "{} across, {} within",
+            across,
+            within
+        );
+
+        let rotate = |img: &image::GrayImage| image::imageops::rotate90(img);
+        let (left, below) = (rotate(&left), rotate(&below));
+        // Rotated clockwise, the bottom row of a tile is its first column
+        let within = column_difference(&left, 0, &left, 1);
+        let across = column_difference(&left, 0, &below, 255);
+        assert!(
+            across <= within * 2.0 + 2.0,
+            "{} across, {} within",
+            across,
+            within
+        );
+    }
+
+    #[tokio::test]
+    async fn test_relief_outside_the_dem_is_transparent() {
+        let sources = load_sources().await;
+        let tile = sources
+            .get("test-hillshade")
+            .unwrap()
+            .get_tile(0, 0, 0)
+            .await
+            .expect("Should get tile")
+            .expect("Should have tile data");
+
+        let img = image::load_from_memory(&tile.data).expect("Should decode PNG");
+        assert_eq!(img.color(), image::ColorType::La8);
+        let alpha: Vec<u8> = img.to_luma_alpha8().pixels().map(|p| p.0[1]).collect();
+        assert!(alpha.contains(&0), "The rest of the world is no-data");
+    }
+
+    #[tokio::test]
+    async fn test_relief_config_errors() {
+        let error = |options: &'static str| async move {
+            let config: SourceConfig = toml::from_str(&format!(
+                "id = \"relief\"\npath = \"data/raster/test-dem.cog.tif\"\n{}",
+                options
+            ))
+            .unwrap();
+            match CogSource::from_file(&config).await {
+                Ok(_) => panic!("{} should not load", options),
+                Err(e) => e.to_string(),
+            }
+        };
+
+        let terrain = error("type = \"terrain-rgb\"\nderived = \"slope\"").await;
+        assert!(
+            terrain.contains("not supported by terrain-rgb"),
+            "{}",
+            terrain
+        );
+        let altitude = error("type = \"cog\"\nderived = \"hillshade\"\naltitude = 120").await;
+        assert!(altitude.contains("altitude"), "{}", altitude);
+        let z_factor = error("type = \"cog\"\nderived = \"slope\"\nz_factor = 0").await;
+        assert!(z_factor.contains("z_factor"), "{}", z_factor);
+    }
+}
+
 mod source_manager_integration {
     use super::*;
     use tileserver_rs::config::ResamplingMethod;